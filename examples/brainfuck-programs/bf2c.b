@@ -0,0 +1,129 @@
+#!/usr/bin/env brainfuck-rs
+
+[bf2c.b -- Brainfuck to C translator
+Reads a Brainfuck program from input until EOF, and prints an equivalent
+C program. Needs EOF to read as zero, so run it with `--quit-on-eof false`.]
+
+>+++++++++++++++++++++++++++++++++++.+++++++++++++++++++++++++++++++++++
++++++++++++++++++++++++++++++++++++.+++++.-----------.+++++++++.++++++++
++.-----------------.+.--------------------------------------------------
+-------------------.++++++++++++++++++++++++++++.+++++++++++++++++++++++
+++++++++++++++++++++++++++++++++.+.----------------.+++++.++++++.-------
+----------------------------------------------------------.+++++++++++++
++++++++++++++++++++++++++++++++++++++++++++++.--------------------------
+----------------.----------------------------------------------------..+
+++++++++++++++++++++++++++++++++++++++++++++++++++++++++++++++++++++++++
+++++++++++++++++++++++++++++++++++.-------.+++++.----------.--.+++++++.-
+--------.-.-------------------------------------------------------------
+-------.++++++++++++++++++++++++++++++++++++++++++++++++++++++++++++++++
++++.+++++.-------.+++++++++++++++++.------------------------------------
+----------------------------------------------.+++++++++++++++++++++++++
++++++++++++++++++++++++++++++++++++++++++++++++++++++++++++.------------
+-------.+++++++++++++++.-----------.----------.-------------------------
+---------------.---....+++++++++++++++++++++++++++++++++++++++++++++.---
+-------------------------------.----------------------------------------
+---------..+++++++++++++++++++++++++++++++++++++++++++++++++++++++++++++
+++++++++++++++++++++++++++++++++++.+++++.++++++.------------------------
+------------------------------------------------------------.+++++++++++
+++++++++++++++++++++++++++++++++++++++++++++++++++++++++++++++++++.-----
+-------.++++++++.+++++.-------------------------------------------------
+---------------------.++++++++++++++++++++++++++++++++++++++++++++++++++
+++++++++++++++++++++++++++++.-------.------.-----.----------------------
+-------------------------------------.---------.++++++++++++++++++++++++
++++++++++++++++++++++++++++++++++++++++++++++++++++++++++++++++++++.----
+------------------------------------------------------------------------
+-------------------------------------.-.++++++++++++++++++++++++++++++++
+++++++++++++++++++++++++++++++++++++++++++++++++++++++++++++++++++++++++
+++++.-------.+++++.----------.--.+++++++.---------.-.-------------------
+-------------------------------------------------.++++++++++++++++++++++
++++++++++++++++++++++++++++++++++++++++++++++.+++++.-------.++++++++++++
++++++.------------------------------------------------------------------
+----------------.++++++++++.++++++++++++++++++++++++++++++++++++++++++++
+++++++++++++++++++++++++++.---------------------------------------------
+-----------------------------------.+++++++++++++++++++++++++++++.------
+-----------------------.++++++++++++++++++++++++++++++++++++++++++++++++
+++++++++++++++++++++++++++++++++++++.-------------------.+++++++++++++++
+.-----------.------------------------------------------.----------------
+---------------------------------.-.++++++++++++++++++++++++++++++++++++
+++++++++++++++++++++++++++++++++++++++++++++++++++++++++++++.+++++.+++++
++.----------------------------------------------------------------------
+--------------.+++++++++++++++++++++++++++++++++++++++++++++++++++++++++
+++++++++++.----------------------------------------.--------------------
+-----------------------------..[-]>+<<,[[->>>+>+<<<<]>>>>[-<<<<+>>>>]<--
+----------------------------------------->+<[>[-]<[-]]>[<<[->>>+>+<<<<]>
+>>>[-<<<<+>>>>]<[>+++++++++.[-]<-]>+++++++++++++++++++++++++++++++++++++
+++++++..-.++++++++++++++++++++++++++++++++++++++++++++++++++++++++++++++
+++++++++.-----------------------------------------------------.---------
+----------------------------------------.[-]<<[-]]<<<<[->>>+>+<<<<]>>>>[
+-<<<<+>>>>]<--------------------------------------------->+<[>[-]<[-]]>[
+<<[->>>+>+<<<<]>>>>[-<<<<+>>>>]<[>+++++++++.[-]<-]>+++++++++++++++++++++
+++++++++++++++++++++++++..---.++++++++++++++++++++++++++++++++++++++++++
+++++++++++++++++++++++++++++.-------------------------------------------
+----------.-------------------------------------------------.[-]<<[-]]<<
+<<[->>>+>+<<<<]>>>>[-<<<<+>>>>]<----------------------------------------
+---------------------->+<[>[-]<[-]]>[<<[->>>+>+<<<<]>>>>[-<<<<+>>>>]<[>+
+++++++++.[-]<-]>+++++++++++++++++++++++++++++++++++++++++++..+++++++++++
+++++++++++++++++++++++++++++++++++++++++++++++++++++++++++.-------------
+----------------------------------------.-------------------------------
+------------------.[-]<<[-]]<<<<[->>>+>+<<<<]>>>>[-<<<<+>>>>]<----------
+-------------------------------------------------->+<[>[-]<[-]]>[<<[->>>
++>+<<<<]>>>>[-<<<<+>>>>]<[>+++++++++.[-]<-]>++++++++++++++++++++++++++++
++++++++++++++++++..+++++++++++++++++++++++++++++++++++++++++++++++++++++
+++++++++++++++.-----------------------------------------------------.---
+----------------------------------------------.[-]<<[-]]<<<<[->>>+>+<<<<
+]>>>>[-<<<<+>>>>]<---------------------------------------------->+<[>[-]
+<[-]]>[<<[->>>+>+<<<<]>>>>[-<<<<+>>>>]<[>+++++++++.[-]<-]>++++++++++++++
+++++++++++++++++++++++++++++++++++++++++++++++++++++++++++++++++++++++++
+++++++++++++++++++++++++++.+++++.-.-----------------.+++++.-------.+++++
+++++++++++++.-----------------------------------------------------------
+---------------.++.+++++++++++++++++++++++++++++++++++++++++++++++++++++
++++++++++++++++++.------------------------------------------------------
+-----------------.++++++++++++++++++.-----------------------------------
+--------------.[-]<<[-]]<<<<[->>>+>+<<<<]>>>>[-<<<<+>>>>]<--------------
+------------------------------>+<[>[-]<[-]]>[<<[->>>+>+<<<<]>>>>[-<<<<+>
+>>>]<[>+++++++++.[-]<-]>++++++++++++++++++++++++++++++++++++++++++++++++
++++++++++++++++++++++++++++++++++++++++++++++++++++.--------------------
+-----------------------------------------------.++++++++++++++++++++++++
++++++.-----------------------------.++++++++++++++++++++++++++++++++++++
++++++++++++++++++++++++++++++++++++.--.+++++++++++++++.-----------------
+.+++++.-------.+++++++++++++++++.---------------------------------------
+-----------------------------------.+.++++++++++++++++++.---------------
+----------------------------------.[-]<<<<[->>>+>+<<<<]>>>>[-<<<<+>>>>]<
+[>+++++++++.[-]<-]>++++++++++++++++++++++++++++++++++++++++++.++++++++++
+++++++++++++++++++++++++++++++++++++++++++++++++++++++++++++.-----------
+---------------------------------------------------------------------.++
++++++++++++++++++++++++++++.-----------------------------.++++++++++++++
++++++++++++++++++++++++++++++++++++++++++++++++++++++.------------------
+-------------------------------------------------.++++++++++++++++++++++
++++++++..-----------------------------.+++++++++++++++++++++++++++++++++
+++++.++++++++++.---------.--------------------------------------.+++++++
+++++++++++++++++++++++++.-------------------------------.+++++++++++++++
++.----------------.++++++++++++++++++++++++++.--------------------------
+.+++++++++++++++++++++++++++++++++++++++++++++++++++++++++++++++++++.---
+-------------------------------------.----------------------------------
+---------------.[-]<<[-]]<<<<[->>>+>+<<<<]>>>>[-<<<<+>>>>]<-------------
+------------------------------------------------------------------------
+------>+<[>[-]<[-]]>[<<[->>>+>+<<<<]>>>>[-<<<<+>>>>]<[>+++++++++.[-]<-]>
+++++++++++++++++++++++++++++++++++++++++++++++++++++++++++++++++++++++++
++++++++++++++++++++++++++++++++++++++++++++++++.---------------.+.+++.--
+-----.------------------------------------------------------------------
+---.++++++++.++.++++++++++++++++++++++++++++++++++++++++++++++++++++++++
+++++++++++++++.---------------------------------------------------------
+--------------.---------.+++++++++++++++++++++++++++++++++++++++++++++++
+++++++++++++++++++++++++++++++++++++++++++++.---------------------------
+------------------------------------------------------------------------
+--------------.[-]<<<<+>>[-]]<<<<[->>>+>+<<<<]>>>>[-<<<<+>>>>]<---------
+------------------------------------------------------------------------
+------------>+<[>[-]<[-]]>[<<-[->>>+>+<<<<]>>>>[-<<<<+>>>>]<[>+++++++++.
+[-]<-]>+++++++++++++++++++++++++++++++++++++++++++++++++++++++++++++++++
+++++++++++++++++++++++++++++++++++++++++++++++++++++++++++++.-----------
+------------------------------------------------------------------------
+--------------------------------.[-]<<[-]]<<<<,]>>[-]<++++++++++.-.+++++
+++++++++++++++++++++++++++++++++++++++++++++++++++++++++++++++++++++++++
+++++++++++++++++++++++++++++.-------------.+++++++++++++++.+.---.----.--
+------------------------------------------------------------------------
+----.++++++++++++++++.+++++++++++.--------------------------------------
+-----------.++++++++++++++++++++++++++++++++++++++++++++++++++++++++++++
++++++++++++++++++++++++++++++++++++++++++++++++++++++++.----------------
+------------------------------------------------------------------------
+---------------------------.[-]<
@@ -0,0 +1,12 @@
+#!/usr/bin/env brainfuck-rs
+
+[fibonacci.b -- Fibonacci numbers
+Prints every Fibonacci number that fits into a cell, one per line.]
+
+++++++++++++++>>+<<[->[->>+>+<<<]>>>[-<<<+>>>]<[->+>+<<]>>[-<<+>>]++++++
+++++<[->->+>>+<<<[->>>>+>+<<<<<]>>>>>[-<<<<<+>>>>>]<[<->[-]]<[<<[-<+>]>+
+>-]<<<<]>[-]>[-<+>]>[-<<<+>>>]<<<[->>+<<]>>>++++++++++<[->->+>>+<<<[->>>
+>+>+<<<<<]>>>>>[-<<<<<+>>>>>]<[<->[-]]<[<<[-<+>]>+>-]<<<<]>[-]>[-<+>]>[-
+<<<+>>>]<<<[>>+>++++++[-<<<++++++++>>>]<<<.[-]]>[->>+>+<<<]>>>[-<<<+>>>]
+<<[->+<]>[>++++++[-<<<++++++++>>>]<<<.[-]>>[-]]<<<++++++[-<++++++++>]<.[
+-]<<[-]++++++++++.[-]<<[->>+<<]>[-<+>>+<]>[-<+>]<<<]>[-]>[-]<<
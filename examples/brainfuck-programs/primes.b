@@ -0,0 +1,17 @@
+#!/usr/bin/env brainfuck-rs
+
+[primes.b -- prime numbers
+Prints the primes below 50, one per line, by trial division.]
+
+++++++++++++++++++++++++++++++++++++++++++++++++>++<[->[->+>>>+<<<<]>>>>
+[-<<<<+>>>>]<<<-->++<[-<[->>>>+>>+<<<<<<]>>>>>>[-<<<<<<+>>>>>>]<<<<[->>>
++>+<<<<]>>>>[-<<<<+>>>>]<<[->->+>>+<<<[->>>>+>+<<<<<]>>>>>[-<<<<<+>>>>>]
+<[<->[-]]<[<<[-<+>]>+>-]<<<<]>[-]>[-<+>]>[-<<<+>>>]<<<[-]>>+<[>[-]<[-]]>
+[<<<+>>>[-]]<<<<+<]>[-]>>+<[>[-]<[-]]>[<<<<[->>>>>+>+<<<<<<]>>>>>>[-<<<<
+<<+>>>>>>]<[->+>+<<]>>[-<<+>>]++++++++++<[->->+>>+<<<[->>>>+>+<<<<<]>>>>
+>[-<<<<<+>>>>>]<[<->[-]]<[<<[-<+>]>+>-]<<<<]>[-]>[-<+>]>[-<<<+>>>]<<<[->
+>+<<]>>>++++++++++<[->->+>>+<<<[->>>>+>+<<<<<]>>>>>[-<<<<<+>>>>>]<[<->[-
+]]<[<<[-<+>]>+>-]<<<<]>[-]>[-<+>]>[-<<<+>>>]<<<[>>+>++++++[-<<<++++++++>
+>>]<<<.[-]]>[->>+>+<<<]>>>[-<<<+>>>]<<[->+<]>[>++++++[-<<<++++++++>>>]<<
+<.[-]>>[-]]<<<++++++[-<++++++++>]<.[-]<<[-]++++++++++.[-]<[-]]<<<<+<]>[-
+]<
@@ -21,6 +21,7 @@ fn main() {
 		// NOTE: since rot13.b doesn't terminate on EOF, we should terminate when the input buffer
 		// is emptied. This option is *specifically* made for this purpose.
 		quit_on_eof: true,
+		..Default::default()
 	};
 
 	let instructions = Instruction::parse(Token::tokenize(ROT13.strip_shebang())).unwrap();
@@ -0,0 +1,108 @@
+//! Runs the cookbook programs through the optimizer and the profiler, showing how much each pass
+//! shrinks them, which superinstructions pay off for them, and how long they take to run.
+
+use std::{
+	fmt::Debug,
+	io,
+	time::{Duration, Instant},
+};
+
+use brainfuck_rs::{
+	bytecode::{Bytecode, Profile},
+	engine::{Engine, RunSummary, RuntimeSettings},
+	host::StdHost,
+	program::Program,
+	utils::StripShebang,
+};
+
+/// Name, source and input of every program.
+const PROGRAMS: &[(&str, &str, &[u8])] = &[
+	(
+		"fibonacci",
+		include_str!("brainfuck-programs/fibonacci.b"),
+		b"",
+	),
+	("primes", include_str!("brainfuck-programs/primes.b"), b""),
+	(
+		"qsort",
+		include_str!("brainfuck-programs/qsort.b"),
+		b"the quick brown fox jumps over the lazy dog",
+	),
+	(
+		"bf2c",
+		include_str!("brainfuck-programs/bf2c.b"),
+		include_bytes!("brainfuck-programs/rot13.b"),
+	),
+	("quine", include_str!("brainfuck-programs/quine.b"), b""),
+];
+
+/// Share of executed ops a superinstruction must save to be used.
+const MIN_FUSION_SHARE: f64 = 0.01;
+
+fn main() {
+	for &(name, source, input) in PROGRAMS {
+		let program = Program::parse(source.strip_shebang()).unwrap();
+
+		let compiled = Bytecode::compile(&program.instructions);
+		let collapsed = compiled.collapse();
+		let counted = collapsed.count_loops();
+
+		// NOTE: the profile tells which superinstructions are worth it for this particular run
+		let mut profile = Profile::new();
+		Engine::default()
+			.run_bytecode_with(
+				&counted,
+				&mut &input[..],
+				&mut io::sink(),
+				RuntimeSettings::default(),
+				&mut StdHost::default(),
+				&mut profile,
+			)
+			.unwrap();
+
+		let hot_fusions = profile.hot_fusions(&counted, MIN_FUSION_SHARE);
+		let fused = counted.fuse(&hot_fusions);
+
+		println!("{name}:");
+		println!(
+			"  ops: {} compiled, {} collapsed, {} with counted loops, {} fused",
+			compiled.ops().len(),
+			collapsed.ops().len(),
+			counted.ops().len(),
+			fused.ops().len(),
+		);
+		println!("  hot superinstructions: {hot_fusions:?}");
+
+		let tree = time(|bf| {
+			bf.run(
+				&program.instructions,
+				&mut &input[..],
+				&mut io::sink(),
+				RuntimeSettings::default(),
+			)
+		});
+		let optimized = time(|bf| {
+			bf.run_bytecode(
+				&fused,
+				&mut &input[..],
+				&mut io::sink(),
+				RuntimeSettings {
+					specialize_after: Some(16),
+					..Default::default()
+				},
+			)
+		});
+
+		println!("  time: {tree:?} interpreted, {optimized:?} optimized");
+	}
+}
+
+/// How long a run on a fresh engine takes.
+fn time<E: Debug>(run: impl FnOnce(&mut Engine) -> Result<RunSummary, E>) -> Duration {
+	let mut bf = Engine::default();
+
+	let start = Instant::now();
+	run(&mut bf).unwrap();
+
+	start.elapsed()
+}
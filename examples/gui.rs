@@ -0,0 +1,392 @@
+//! A playground for editing programs and running them step by step while watching the tape, run
+//! with `cargo run --example gui --features gui`.
+//!
+//! The program runs on a thread of its own, with [`Controller`] as its hooks, which pause it
+//! between instructions and share the state of the engine with the UI. Until it's started, the
+//! output it would print for the given input is previewed with [`Engine::peek`].
+
+use std::{
+	collections::VecDeque,
+	io::{self, Read, Write},
+	num::{NonZeroU64, Wrapping},
+	panic,
+	sync::{
+		atomic::{AtomicBool, Ordering},
+		Arc, Condvar, Mutex, MutexGuard, PoisonError,
+	},
+	thread,
+};
+
+use brainfuck_rs::{
+	engine::{Engine, RunError, RuntimeSettings},
+	hooks::Hooks,
+	host::StdHost,
+	instruction::Instruction,
+	token::Token,
+	view::EngineView,
+};
+use eframe::egui;
+
+const HELLO_WORLD: &str = include_str!("brainfuck-programs/hello-world.b");
+
+/// Length of the tape programs run on.
+const TAPE_LENGTH: usize = 30_000;
+
+/// Instructions a running program executes between updates of the tape view.
+const SNAPSHOT_EVERY: u64 = 10_000;
+
+/// Maximum number of instructions the preview runs.
+const PREVIEW_STEPS: u64 = 1_000_000;
+
+/// Cells shown per row of the tape view.
+const ROW_LENGTH: usize = 16;
+
+fn main() -> eframe::Result {
+	eframe::run_native(
+		"brainfuck-rs playground",
+		eframe::NativeOptions::default(),
+		Box::new(|_| Ok(Box::new(Playground::default()))),
+	)
+}
+
+/// What the UI and the thread running a program share.
+#[derive(Default)]
+struct Shared {
+	/// Copy of the tape, as of `step`.
+	tape: Vec<Wrapping<u8>>,
+	pointer: usize,
+	step: u64,
+	output: Vec<u8>,
+	/// Input the program hasn't read yet.
+	input: VecDeque<u8>,
+	/// Instructions to run before pausing again.
+	steps_left: u64,
+	waiting_for_input: bool,
+	/// How the run ended, if it did.
+	result: Option<String>,
+	stopped: bool,
+}
+
+/// A program running on a thread of its own.
+struct Link {
+	shared: Mutex<Shared>,
+	changed: Condvar,
+	/// Checked before every instruction, so it's kept outside the lock.
+	paused: AtomicBool,
+}
+
+impl Link {
+	fn lock(&self) -> MutexGuard<'_, Shared> {
+		self.shared.lock().unwrap_or_else(PoisonError::into_inner)
+	}
+
+	/// Wait until the shared state changes.
+	fn wait<'a>(&self, shared: MutexGuard<'a, Shared>) -> MutexGuard<'a, Shared> {
+		self.changed
+			.wait(shared)
+			.unwrap_or_else(PoisonError::into_inner)
+	}
+}
+
+/// Payload of the unwind that ends a stopped program.
+struct Stopped;
+
+/// Hooks of a running program, which share the engine's state with the UI every so often, and
+/// on every instruction while it's paused.
+struct Controller(Arc<Link>);
+
+impl Controller {
+	fn snapshot(shared: &mut Shared, step: u64, bf: EngineView<'_>) {
+		shared.tape.clear();
+		shared.tape.extend_from_slice(bf.tape());
+		shared.pointer = bf.pointer();
+		shared.step = step;
+	}
+}
+
+impl Hooks for Controller {
+	fn before_instruction(&mut self, step: u64, _instruction: &Instruction, bf: EngineView<'_>) {
+		if !self.0.paused.load(Ordering::Relaxed) {
+			return;
+		}
+
+		let mut shared = self.0.lock();
+		Self::snapshot(&mut shared, step, bf);
+		self.0.changed.notify_all();
+
+		while shared.steps_left == 0 && !shared.stopped && self.0.paused.load(Ordering::Relaxed) {
+			shared = self.0.wait(shared);
+		}
+
+		if shared.stopped {
+			drop(shared);
+			// NOTE: hooks can't end a run, so the program unwinds out of it, which
+			// `contain_panics` turns into an error. Unlike `panic!`, this doesn't report a crash
+			panic::resume_unwind(Box::new(Stopped));
+		}
+		shared.steps_left = shared.steps_left.saturating_sub(1);
+	}
+
+	fn on_yield(&mut self, step: u64, bf: EngineView<'_>) {
+		let mut shared = self.0.lock();
+		Self::snapshot(&mut shared, step, bf);
+		self.0.changed.notify_all();
+	}
+}
+
+/// Input of a running program, typed into the UI. Reading waits until there's some.
+struct Input(Arc<Link>);
+
+impl Read for Input {
+	fn read(&mut self, buffer: &mut [u8]) -> io::Result<usize> {
+		let mut shared = self.0.lock();
+
+		while shared.input.is_empty() && !shared.stopped {
+			shared.waiting_for_input = true;
+			shared = self.0.wait(shared);
+		}
+		shared.waiting_for_input = false;
+
+		// NOTE: a stopped program reads EOF, and is ended before its next instruction
+		let length = buffer.len().min(shared.input.len());
+		for (byte, input) in buffer.iter_mut().zip(shared.input.drain(..length)) {
+			*byte = input;
+		}
+
+		Ok(length)
+	}
+}
+
+/// Output of a running program, shown in the UI.
+struct Output(Arc<Link>);
+
+impl Write for Output {
+	fn write(&mut self, buffer: &[u8]) -> io::Result<usize> {
+		self.0.lock().output.extend_from_slice(buffer);
+		Ok(buffer.len())
+	}
+
+	fn flush(&mut self) -> io::Result<()> {
+		Ok(())
+	}
+}
+
+/// Start running `instructions`, paused before the first one.
+fn start(instructions: Vec<Instruction>) -> Arc<Link> {
+	let link = Arc::new(Link {
+		shared: Mutex::new(Shared {
+			tape: vec![Wrapping(0); TAPE_LENGTH],
+			..Default::default()
+		}),
+		changed: Condvar::new(),
+		paused: AtomicBool::new(true),
+	});
+
+	let program_link = Arc::clone(&link);
+	thread::spawn(move || {
+		let mut bf = Engine::new(TAPE_LENGTH);
+		let result = bf.run_with(
+			&instructions,
+			&mut Input(Arc::clone(&program_link)),
+			&mut Output(Arc::clone(&program_link)),
+			RuntimeSettings {
+				yield_every: NonZeroU64::new(SNAPSHOT_EVERY),
+				..Default::default()
+			},
+			&mut StdHost::default(),
+			&mut Controller(Arc::clone(&program_link)),
+		);
+
+		let mut shared = program_link.lock();
+		shared.tape.clone_from(&bf.tape);
+		shared.pointer = bf.pointer;
+		shared.result = Some(match result {
+			Ok(summary) => format!("finished after {} steps", summary.steps),
+			Err(RunError::InternalError { .. }) if shared.stopped => "stopped".to_owned(),
+			Err(e) => format!("failed: {e}"),
+		});
+		program_link.changed.notify_all();
+	});
+
+	link
+}
+
+struct Playground {
+	source: String,
+	input: String,
+	running: Option<Arc<Link>>,
+	/// Output the program would print with `input`, or why it can't run.
+	preview: String,
+}
+
+impl Default for Playground {
+	fn default() -> Self {
+		let mut playground = Self {
+			source: HELLO_WORLD.to_owned(),
+			input: String::new(),
+			running: None,
+			preview: String::new(),
+		};
+		playground.update_preview();
+
+		playground
+	}
+}
+
+impl Playground {
+	fn update_preview(&mut self) {
+		self.preview = match Instruction::parse(Token::tokenize(&self.source)) {
+			Ok(instructions) => match Engine::new(TAPE_LENGTH).peek(
+				&instructions,
+				self.input.as_bytes(),
+				RuntimeSettings::default(),
+				PREVIEW_STEPS,
+			) {
+				Ok(preview) if preview.finished => {
+					String::from_utf8_lossy(&preview.output).into_owned()
+				}
+				Ok(preview) => format!(
+					"{}\n(still running after {} steps)",
+					String::from_utf8_lossy(&preview.output),
+					preview.steps
+				),
+				Err(e) => format!("fails: {e}"),
+			},
+			Err(e) => format!("can't be parsed: {e}"),
+		};
+	}
+
+	fn stop(&mut self) {
+		if let Some(link) = self.running.take() {
+			// NOTE: a running program only checks whether it was stopped while it's paused
+			link.paused.store(true, Ordering::Relaxed);
+			link.lock().stopped = true;
+			link.changed.notify_all();
+		}
+	}
+
+	fn controls(&mut self, ui: &mut egui::Ui) {
+		ui.horizontal(|ui| {
+			let Some(link) = self.running.clone() else {
+				if ui.button("Start").clicked() {
+					if let Ok(instructions) = Instruction::parse(Token::tokenize(&self.source)) {
+						let link = start(instructions);
+						link.lock().input.extend(self.input.bytes());
+						self.input.clear();
+						self.running = Some(link);
+					}
+				}
+				return;
+			};
+
+			let paused = link.paused.load(Ordering::Relaxed);
+			let finished = link.lock().result.is_some();
+
+			if ui.button("Stop").clicked() {
+				self.stop();
+				return;
+			}
+			ui.add_enabled_ui(!finished, |ui| {
+				if ui.button(if paused { "Play" } else { "Pause" }).clicked() {
+					link.paused.store(!paused, Ordering::Relaxed);
+					link.changed.notify_all();
+				}
+				if ui.add_enabled(paused, egui::Button::new("Step")).clicked() {
+					link.lock().steps_left += 1;
+					link.changed.notify_all();
+				}
+			});
+
+			let shared = link.lock();
+			ui.label(match &shared.result {
+				Some(result) => result.clone(),
+				None if shared.waiting_for_input => {
+					format!("step {}, waiting for input", shared.step)
+				}
+				None => format!("step {}", shared.step),
+			});
+		});
+	}
+
+	fn tape(ui: &mut egui::Ui, shared: &Shared) {
+		// NOTE: only the rows around the pointer, the tape is far too long to show whole
+		let row = shared.pointer / ROW_LENGTH;
+		let rows = row.saturating_sub(4)..(row + 4).min(shared.tape.len().div_ceil(ROW_LENGTH));
+
+		egui::Grid::new("tape").striped(true).show(ui, |ui| {
+			for row in rows {
+				ui.monospace(format!("{:>5}", row * ROW_LENGTH));
+				for index in row * ROW_LENGTH..((row + 1) * ROW_LENGTH).min(shared.tape.len()) {
+					let cell =
+						egui::RichText::new(format!("{:>3}", shared.tape[index])).monospace();
+					if index == shared.pointer {
+						ui.label(
+							cell.strong()
+								.background_color(ui.visuals().selection.bg_fill),
+						);
+					} else {
+						ui.label(cell);
+					}
+				}
+				ui.end_row();
+			}
+		});
+	}
+}
+
+impl eframe::App for Playground {
+	fn update(&mut self, ctx: &egui::Context, _frame: &mut eframe::Frame) {
+		egui::TopBottomPanel::top("controls").show(ctx, |ui| self.controls(ui));
+
+		egui::SidePanel::left("source").show(ctx, |ui| {
+			ui.heading("Program");
+			let editable = self.running.is_none();
+			let source = ui.add_enabled(
+				editable,
+				egui::TextEdit::multiline(&mut self.source)
+					.code_editor()
+					.desired_rows(24),
+			);
+
+			ui.heading("Input");
+			let input = ui.text_edit_singleline(&mut self.input);
+			if input.changed() || source.changed() {
+				self.update_preview();
+			}
+
+			// NOTE: a running program is fed a line at a time, like on a terminal
+			if let Some(link) = &self.running {
+				if ui.button("Send line").clicked() {
+					let mut shared = link.lock();
+					shared.input.extend(self.input.bytes().chain([b'\n']));
+					self.input.clear();
+					link.changed.notify_all();
+				}
+			}
+		});
+
+		egui::CentralPanel::default().show(ctx, |ui| {
+			let Some(link) = &self.running else {
+				ui.heading("Preview");
+				ui.monospace(&self.preview);
+				return;
+			};
+
+			let shared = link.lock();
+			ui.heading("Tape");
+			Self::tape(ui, &shared);
+			ui.heading("Output");
+			ui.monospace(String::from_utf8_lossy(&shared.output));
+
+			if shared.result.is_none() && !link.paused.load(Ordering::Relaxed) {
+				ctx.request_repaint();
+			}
+		});
+	}
+}
+
+impl Drop for Playground {
+	fn drop(&mut self) {
+		self.stop();
+	}
+}
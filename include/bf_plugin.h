@@ -0,0 +1,56 @@
+/*
+ * The C ABI of brainfuck-rs plugins, which handle custom instructions of the programs they're
+ * loaded with `run --plugin`. See the docs of `brainfuck_rs::plugin::Plugin` for the details.
+ */
+#ifndef BF_PLUGIN_H
+#define BF_PLUGIN_H
+
+#include <stddef.h>
+#include <stdint.h>
+
+#define BF_PLUGIN_ABI_VERSION 1
+
+/*
+ * Access to the engine and the host, valid for the duration of a call to
+ * `bf_plugin_handle_instruction`. Every function takes `context` first.
+ */
+struct bf_api {
+	uint32_t version;
+	void *context;
+
+	/* Index of the current cell. */
+	size_t (*pointer)(void *context);
+	/* Number of cells. */
+	size_t (*tape_length)(void *context);
+	/* Value of a cell, or -1 past the end of the tape. */
+	int32_t (*get)(void *context, size_t index);
+	/* Set a cell, returning 0, or -1 past the end of the tape. */
+	int32_t (*set)(void *context, size_t index, uint8_t value);
+	/* Move the pointer, returning 0, or -1 past the end of the tape. */
+	int32_t (*set_pointer)(void *context, size_t index);
+
+	/* A byte of the program's input, -1 at its end, or -2 on failure. */
+	int32_t (*read)(void *context);
+	/* Print a byte, returning 0, or -1 on failure. */
+	int32_t (*write)(void *context, uint8_t byte);
+
+	/* Milliseconds since a fixed point in time, returning 0, or -1 if the host refuses. */
+	int32_t (*clock_millis)(void *context, uint64_t *millis);
+	/* A random byte, returning 0, or -1 if the host refuses. */
+	int32_t (*random_byte)(void *context, uint8_t *byte);
+};
+
+/*
+ * Called once the library is loaded. Returns 0 if `abi_version` is supported, after setting
+ * `state` to whatever the other functions get back, and `symbols` to a NUL-terminated string
+ * of the instructions the plugin handles. Anything else refuses to load.
+ */
+int32_t bf_plugin_init(uint32_t abi_version, void **state, const char **symbols);
+
+/* Run the instruction `symbol`, returning 0, or anything else to end the program with an error. */
+int32_t bf_plugin_handle_instruction(void *state, char symbol, const struct bf_api *api);
+
+/* Called once the plugin isn't needed anymore, as the last call made with `state`. */
+void bf_plugin_shutdown(void *state);
+
+#endif
@@ -0,0 +1,379 @@
+use brainfuck_rs::{
+	bytecode::Fusion,
+	engine::{Engine, RuntimeSettings},
+	program::Program,
+};
+
+use super::run::Backend;
+use color_eyre::eyre::{bail, Result};
+use serde::{Deserialize, Serialize};
+use std::{
+	fmt,
+	io::{self, BufReader},
+	time::{Duration, Instant},
+};
+
+/// Version of the JSON benchmark results, bumped whenever their shape changes.
+const REPORT_VERSION: u32 = 1;
+
+/// Timings of repeated runs of a program.
+pub struct Measurement {
+	pub label: String,
+	pub samples: Vec<Duration>,
+	pub steps: u64,
+}
+
+impl Measurement {
+	pub fn mean(&self) -> Duration {
+		self.samples.iter().sum::<Duration>() / self.samples.len() as u32
+	}
+
+	pub fn median(&self) -> Duration {
+		let mut sorted = self.samples.clone();
+		sorted.sort_unstable();
+
+		let middle = sorted.len() / 2;
+		if sorted.len() % 2 == 0 {
+			(sorted[middle - 1] + sorted[middle]) / 2
+		} else {
+			sorted[middle]
+		}
+	}
+
+	/// Sample standard deviation.
+	pub fn stddev(&self) -> Duration {
+		if self.samples.len() < 2 {
+			return Duration::ZERO;
+		}
+
+		let mean = self.mean().as_secs_f64();
+		let variance = self
+			.samples
+			.iter()
+			.map(|sample| (sample.as_secs_f64() - mean).powi(2))
+			.sum::<f64>()
+			/ (self.samples.len() - 1) as f64;
+
+		Duration::from_secs_f64(variance.sqrt())
+	}
+
+	pub fn instructions_per_second(&self) -> f64 {
+		self.steps as f64 / self.mean().as_secs_f64()
+	}
+}
+
+/// Run the program `warmup + runs` times, feeding it the same input every time and discarding the
+/// output, and measure the last `runs` of them.
+#[allow(clippy::too_many_arguments)]
+pub fn measure(
+	backend: Backend,
+	program: &Program,
+	settings: &RuntimeSettings,
+	tape_length: usize,
+	fusions: &[Fusion],
+	input: &[u8],
+	runs: usize,
+	warmup: usize,
+) -> Result<Measurement> {
+	let mut samples = Vec::with_capacity(runs);
+	let mut steps = 0;
+
+	for run in 0..warmup + runs {
+		let mut bf = Engine::new(tape_length);
+
+		let mut stdin = BufReader::new(input);
+		let mut stdout = io::sink();
+
+		let start = Instant::now();
+		let summary = backend.run_with(
+			&mut bf,
+			program,
+			fusions,
+			&mut stdin,
+			&mut stdout,
+			settings.clone(),
+			&mut (),
+		)?;
+		let elapsed = start.elapsed();
+
+		if run >= warmup {
+			samples.push(elapsed);
+			steps = summary.steps;
+		}
+	}
+
+	Ok(Measurement {
+		label: backend.name().to_owned(),
+		samples,
+		steps,
+	})
+}
+
+/// Measurements as JSON, for comparing them with [`compare`] later.
+pub fn to_json(measurements: &[Measurement]) -> String {
+	let report = Report {
+		version: REPORT_VERSION,
+		measurements: measurements
+			.iter()
+			.map(|measurement| RawMeasurement {
+				label: measurement.label.clone(),
+				steps: measurement.steps,
+				samples_ns: measurement
+					.samples
+					.iter()
+					.map(|sample| sample.as_nanos().try_into().unwrap_or(u64::MAX))
+					.collect(),
+			})
+			.collect(),
+	};
+
+	serde_json::to_string_pretty(&report).expect("benchmark serialization never fails")
+}
+
+/// Read measurements written by [`to_json`].
+pub fn from_json(json: &str) -> Result<Vec<Measurement>> {
+	let report: Report = serde_json::from_str(json)?;
+	if report.version != REPORT_VERSION {
+		bail!(
+			"unsupported benchmark results version {}, expected {REPORT_VERSION}",
+			report.version
+		);
+	}
+
+	Ok(report
+		.measurements
+		.into_iter()
+		.map(|raw| Measurement {
+			label: raw.label,
+			samples: raw
+				.samples_ns
+				.into_iter()
+				.map(Duration::from_nanos)
+				.collect(),
+			steps: raw.steps,
+		})
+		.collect())
+}
+
+#[derive(Serialize, Deserialize)]
+struct Report {
+	version: u32,
+	measurements: Vec<RawMeasurement>,
+}
+
+#[derive(Serialize, Deserialize)]
+struct RawMeasurement {
+	label: String,
+	steps: u64,
+	samples_ns: Vec<u64>,
+}
+
+/// How the mean time of a benchmark changed between two sets of measurements.
+pub struct Comparison {
+	pub label: String,
+	pub before: Duration,
+	pub after: Duration,
+	/// 95% confidence interval of the change of the mean, relative to the mean before, or
+	/// [`None`] if either side has fewer than two runs.
+	pub interval: Option<(f64, f64)>,
+}
+
+impl Comparison {
+	/// Change of the mean, relative to the mean before.
+	pub fn change(&self) -> f64 {
+		(self.after.as_secs_f64() - self.before.as_secs_f64()) / self.before.as_secs_f64()
+	}
+
+	pub fn verdict(&self) -> Verdict {
+		match self.interval {
+			Some((low, _)) if low > 0.0 => Verdict::Regression,
+			Some((_, high)) if high < 0.0 => Verdict::Improvement,
+			Some(_) => Verdict::NoChange,
+			None => Verdict::Unknown,
+		}
+	}
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Verdict {
+	Regression,
+	Improvement,
+	NoChange,
+	Unknown,
+}
+
+impl fmt::Display for Verdict {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		f.write_str(match self {
+			Self::Regression => "REGRESSION",
+			Self::Improvement => "improvement",
+			Self::NoChange => "no change",
+			Self::Unknown => "too few runs",
+		})
+	}
+}
+
+/// Compare the measurements with the same labels, using Welch's t-test, which doesn't assume
+/// both sides are equally noisy. A change only counts if its whole confidence interval is on one
+/// side of zero.
+pub fn compare(before: &[Measurement], after: &[Measurement]) -> Vec<Comparison> {
+	before
+		.iter()
+		.filter_map(|before| {
+			let after = after.iter().find(|after| after.label == before.label)?;
+
+			Some(Comparison {
+				label: before.label.clone(),
+				before: before.mean(),
+				after: after.mean(),
+				interval: confidence_interval(before, after),
+			})
+		})
+		.collect()
+}
+
+/// 95% confidence interval of the difference of the means, relative to the mean of `before`.
+fn confidence_interval(before: &Measurement, after: &Measurement) -> Option<(f64, f64)> {
+	if before.samples.len() < 2 || after.samples.len() < 2 {
+		return None;
+	}
+
+	let spread = |measurement: &Measurement| {
+		measurement.stddev().as_secs_f64().powi(2) / measurement.samples.len() as f64
+	};
+	let (before_spread, after_spread) = (spread(before), spread(after));
+	let error = (before_spread + after_spread).sqrt();
+
+	// NOTE: Welch–Satterthwaite approximation of the degrees of freedom
+	let freedom = if error == 0.0 {
+		f64::INFINITY
+	} else {
+		error.powi(4)
+			/ (before_spread.powi(2) / (before.samples.len() - 1) as f64
+				+ after_spread.powi(2) / (after.samples.len() - 1) as f64)
+	};
+
+	let mean = before.mean().as_secs_f64();
+	let difference = after.mean().as_secs_f64() - mean;
+	let margin = critical_t(freedom) * error;
+
+	Some(((difference - margin) / mean, (difference + margin) / mean))
+}
+
+/// Two-sided 95% critical value of Student's t-distribution, rounding the degrees of freedom down
+/// to stay on the safe side.
+fn critical_t(freedom: f64) -> f64 {
+	const TABLE: [f64; 30] = [
+		12.706, 4.303, 3.182, 2.776, 2.571, 2.447, 2.365, 2.306, 2.262, 2.228, 2.201, 2.179, 2.160,
+		2.145, 2.131, 2.120, 2.110, 2.101, 2.093, 2.086, 2.080, 2.074, 2.069, 2.064, 2.060, 2.056,
+		2.052, 2.048, 2.045, 2.042,
+	];
+
+	match freedom.floor() {
+		freedom if freedom < 1.0 => TABLE[0],
+		freedom if freedom <= 30.0 => TABLE[freedom as usize - 1],
+		freedom if freedom <= 60.0 => 2.000,
+		freedom if freedom <= 120.0 => 1.980,
+		_ => 1.960,
+	}
+}
+
+/// Print comparisons side by side, with the changes in percent.
+pub fn print_comparison(comparisons: &[Comparison]) {
+	println!(
+		"{:<12} {:>12} {:>12} {:>9} {:>20}  verdict",
+		"backend", "before", "after", "change", "95% interval"
+	);
+
+	for comparison in comparisons {
+		let interval = comparison.interval.map_or_else(
+			|| "?".to_owned(),
+			|(low, high)| format!("[{:+.1}%, {:+.1}%]", low * 100.0, high * 100.0),
+		);
+
+		println!(
+			"{:<12} {:>12} {:>12} {:>9} {:>20}  {}",
+			comparison.label,
+			format!("{:.3?}", comparison.before),
+			format!("{:.3?}", comparison.after),
+			format!("{:+.1}%", comparison.change() * 100.0),
+			interval,
+			comparison.verdict(),
+		);
+	}
+}
+
+/// Print measurements side by side.
+pub fn print_table(measurements: &[Measurement]) {
+	println!(
+		"{:<12} {:>6} {:>12} {:>12} {:>12} {:>16}",
+		"backend", "runs", "mean", "median", "stddev", "instructions/s"
+	);
+
+	for measurement in measurements {
+		println!(
+			"{:<12} {:>6} {:>12} {:>12} {:>12} {:>16.0}",
+			measurement.label,
+			measurement.samples.len(),
+			format!("{:.3?}", measurement.mean()),
+			format!("{:.3?}", measurement.median()),
+			format!("{:.3?}", measurement.stddev()),
+			measurement.instructions_per_second(),
+		);
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn statistics() {
+		let measurement = Measurement {
+			label: "tree".to_owned(),
+			samples: [1, 2, 3, 10].map(Duration::from_secs).to_vec(),
+			steps: 8,
+		};
+
+		assert_eq!(Duration::from_secs(4), measurement.mean());
+		assert_eq!(Duration::from_millis(2500), measurement.median());
+		assert_eq!(2.0, measurement.instructions_per_second());
+		assert_eq!(
+			4.082,
+			(measurement.stddev().as_secs_f64() * 1000.0).round() / 1000.0
+		);
+	}
+
+	#[test]
+	fn comparison() {
+		let measurement = |label: &str, millis: &[u64]| Measurement {
+			label: label.to_owned(),
+			samples: millis.iter().copied().map(Duration::from_millis).collect(),
+			steps: 1,
+		};
+
+		let before = [
+			measurement("tree", &[100, 102, 98, 101, 99]),
+			measurement("bytecode", &[50, 52, 48, 51, 49]),
+			measurement("fused", &[20, 40, 30, 25, 35]),
+		];
+		let after = from_json(&to_json(&[
+			measurement("tree", &[110, 112, 108, 111, 109]),
+			measurement("bytecode", &[40, 42, 38, 41, 39]),
+			measurement("fused", &[22, 38, 31, 24, 36]),
+		]))
+		.unwrap();
+
+		let verdicts: Vec<_> = compare(&before, &after)
+			.iter()
+			.map(Comparison::verdict)
+			.collect();
+		assert_eq!(
+			vec![Verdict::Regression, Verdict::Improvement, Verdict::NoChange],
+			verdicts
+		);
+
+		let single = compare(&[measurement("tree", &[1])], &[measurement("tree", &[2])]);
+		assert_eq!(Verdict::Unknown, single[0].verdict());
+	}
+}
@@ -0,0 +1,47 @@
+use super::bench::{self, Verdict};
+use clap::{value_parser, Arg, ArgMatches};
+use color_eyre::eyre::{bail, Result};
+use fs_err as fs;
+use std::{path::PathBuf, process::ExitCode};
+
+pub fn args() -> Vec<Arg> {
+	vec![
+		Arg::new("before")
+			.required(true)
+			.value_name("BEFORE")
+			.help("Benchmark results to compare against, from `run --bench RUNS --json`")
+			.value_parser(value_parser!(PathBuf)),
+		Arg::new("after")
+			.required(true)
+			.value_name("AFTER")
+			.help("Benchmark results to compare, from `run --bench RUNS --json`")
+			.value_parser(value_parser!(PathBuf)),
+	]
+}
+
+/// Compare two benchmark results, failing if anything got slower.
+pub fn execute(matches: &ArgMatches) -> Result<ExitCode> {
+	let before = bench::from_json(&fs::read_to_string(
+		matches.get_one::<PathBuf>("before").unwrap(),
+	)?)?;
+	let after = bench::from_json(&fs::read_to_string(
+		matches.get_one::<PathBuf>("after").unwrap(),
+	)?)?;
+
+	let comparisons = bench::compare(&before, &after);
+	if comparisons.is_empty() {
+		bail!("the results have no engines in common");
+	}
+
+	bench::print_comparison(&comparisons);
+
+	let regressed = comparisons
+		.iter()
+		.any(|comparison| comparison.verdict() == Verdict::Regression);
+
+	Ok(if regressed {
+		ExitCode::FAILURE
+	} else {
+		ExitCode::SUCCESS
+	})
+}
@@ -0,0 +1,169 @@
+use brainfuck_rs::bytecode::{Bytecode, Fusion};
+use clap::{ArgMatches, Command};
+use color_eyre::eyre::{eyre, Result};
+use fs_err as fs;
+use std::{
+	cmp::Reverse,
+	collections::hash_map::DefaultHasher,
+	hash::{Hash, Hasher},
+	io::ErrorKind,
+	path::{Path, PathBuf},
+	time::SystemTime,
+};
+
+/// Extension of cached superinstruction picks, which list the picked fusions one per line.
+const EXTENSION: &str = "fusions";
+/// Extension of cached compiled ops, which are `.bfc` files.
+const OPS_EXTENSION: &str = "bfc";
+
+pub fn subcommands() -> Vec<Command> {
+	vec![
+		Command::new("ls").about("List cached entries, most recent first"),
+		Command::new("clear").about("Delete every cached entry"),
+	]
+}
+
+pub fn execute(matches: &ArgMatches) -> Result<()> {
+	let directory = directory()?;
+
+	match matches.subcommand() {
+		Some(("clear", _)) => match fs::remove_dir_all(&directory) {
+			Err(e) if e.kind() != ErrorKind::NotFound => return Err(e.into()),
+			_ => println!("cleared {}", directory.display()),
+		},
+		_ => {
+			let mut entries = vec![];
+			if directory.exists() {
+				for entry in fs::read_dir(&directory)? {
+					let entry = entry?;
+					let metadata = entry.metadata()?;
+					entries.push((entry.file_name(), metadata.len(), metadata.modified()?));
+				}
+			}
+			entries.sort_by_key(|&(_, _, modified)| Reverse(modified));
+
+			println!("{}", directory.display());
+			for (name, size, modified) in &entries {
+				let age = SystemTime::now()
+					.duration_since(*modified)
+					.unwrap_or_default()
+					.as_secs();
+				println!(
+					"{:>10} bytes {:>8} ago  {}",
+					size,
+					format_age(age),
+					name.to_string_lossy()
+				);
+			}
+			let total: u64 = entries.iter().map(|(_, size, _)| size).sum();
+			println!("{} entries, {total} bytes", entries.len());
+		}
+	}
+
+	Ok(())
+}
+
+/// Superinstructions picked for a program by `compute`, or by an earlier invocation with the
+/// same `key`, which must cover everything the pick depends on. `name` tells cached picks apart
+/// in `cache ls`.
+///
+/// The cache is only an optimization: if it can't be read or written, the superinstructions are
+/// picked as usual.
+pub fn fusions(
+	name: &str,
+	key: impl Hash,
+	compute: impl FnOnce() -> Result<Vec<Fusion>>,
+) -> Result<Vec<Fusion>> {
+	let Ok(path) = entry(name, key, EXTENSION) else {
+		return compute();
+	};
+
+	if let Ok(cached) = fs::read_to_string(&path) {
+		let fusions: Option<Vec<Fusion>> = cached
+			.lines()
+			.map(|line| {
+				Fusion::ALL
+					.iter()
+					.copied()
+					.find(|fusion| format!("{fusion:?}") == line)
+			})
+			.collect();
+
+		if let Some(fusions) = fusions {
+			return Ok(fusions);
+		}
+	}
+
+	let fusions = compute()?;
+	let cached: String = fusions
+		.iter()
+		.map(|fusion| format!("{fusion:?}\n"))
+		.collect();
+	store(&path, cached.as_bytes());
+
+	Ok(fusions)
+}
+
+/// Ops a program was compiled into by `compute`, or by an earlier invocation with the same
+/// `key`, which must cover the source code and everything that changes how it's compiled. `name`
+/// tells cached ops apart in `cache ls`.
+///
+/// Like [`fusions`], it falls back to `compute` if the cache can't be used.
+pub fn bytecode(
+	name: &str,
+	key: impl Hash,
+	compute: impl FnOnce() -> Result<Bytecode>,
+) -> Result<Bytecode> {
+	let Ok(path) = entry(name, key, OPS_EXTENSION) else {
+		return compute();
+	};
+
+	if let Some(bytecode) = fs::read(&path)
+		.ok()
+		.and_then(|cached| Bytecode::from_bfc(&cached).ok())
+	{
+		return Ok(bytecode);
+	}
+
+	let bytecode = compute()?;
+	store(&path, &bytecode.to_bfc());
+
+	Ok(bytecode)
+}
+
+/// Path of the cached entry called `name` with the given `key` and `extension`.
+fn entry(name: &str, key: impl Hash, extension: &str) -> Result<PathBuf> {
+	// NOTE: the hash isn't stable across Rust versions, which only costs a cache miss, and the
+	// crate version is part of it since the way programs are optimized may change
+	let mut hasher = DefaultHasher::new();
+	env!("CARGO_PKG_VERSION").hash(&mut hasher);
+	key.hash(&mut hasher);
+
+	Ok(directory()?.join(format!("{name}-{:016x}.{extension}", hasher.finish())))
+}
+
+/// Write a cached entry to `path`.
+fn store(path: &Path, contents: &[u8]) {
+	// NOTE: failing to cache isn't worth failing the run over
+	let _ = path
+		.parent()
+		.map_or(Ok(()), fs::create_dir_all)
+		.and_then(|()| fs::write(path, contents));
+}
+
+/// Directory cached programs are stored in.
+fn directory() -> Result<PathBuf> {
+	dirs::cache_dir()
+		.map(|directory| directory.join(env!("CARGO_PKG_NAME")))
+		.ok_or_else(|| eyre!("couldn't find the cache directory of this system"))
+}
+
+/// Roughly how long ago something happened, `age` seconds ago.
+pub fn format_age(age: u64) -> String {
+	match age {
+		0..=59 => format!("{age}s"),
+		60..=3599 => format!("{}m", age / 60),
+		3600..=86_399 => format!("{}h", age / 3600),
+		_ => format!("{}d", age / 86_400),
+	}
+}
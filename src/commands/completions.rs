@@ -0,0 +1,27 @@
+use std::io::{self, Write};
+
+use clap::{value_parser, Arg, ArgMatches};
+use clap_complete::Shell;
+use color_eyre::eyre::Result;
+
+pub fn args() -> Vec<Arg> {
+	vec![Arg::new("shell")
+		.required(true)
+		.value_name("SHELL")
+		.help("Shell to complete the command line of")
+		.value_parser(value_parser!(Shell))]
+}
+
+/// Print the completion script for a shell.
+pub fn execute(matches: &ArgMatches) -> Result<()> {
+	let shell = *matches.get_one::<Shell>("shell").unwrap();
+	let mut command = super::command();
+	let name = command.get_name().to_owned();
+
+	// NOTE: `generate` panics if writing fails, e.g. when piped into `head`
+	let mut script = vec![];
+	clap_complete::generate(shell, &mut command, name, &mut script);
+	io::stdout().write_all(&script)?;
+
+	Ok(())
+}
@@ -0,0 +1,512 @@
+//! A background process that keeps programs loaded and compiled between runs, for `daemon`.
+//!
+//! `run` hands plain runs to the daemon, if one is listening, instead of loading the program
+//! itself. It connects to a Unix socket and sends a line of JSON with its arguments, and the
+//! daemon answers with a line of JSON telling its version. If the versions differ, the run
+//! happens locally, like it does without a daemon. Otherwise, the client sends its input as it
+//! reads it, and the daemon sends back [frames](Frames) of output, and then the exit code or an
+//! error.
+//!
+//! Programs are kept as long as their file doesn't change, and compiled once per engine. The
+//! daemon exits once no run was started for a while.
+
+use clap::{parser::ValueSource, value_parser, Arg, ArgMatches};
+use color_eyre::eyre::{eyre, Result};
+use serde::{Deserialize, Serialize};
+use std::path::PathBuf;
+
+use super::run;
+
+/// Whether the daemon is supported on this platform.
+pub const SUPPORTED: bool = cfg!(unix);
+
+/// Name of the socket in the runtime directory.
+const SOCKET_NAME: &str = "daemon.sock";
+
+/// Arguments of `run` the daemon handles, besides ones that only matter to the client, like
+/// encodings. Runs with any other argument given happen locally.
+const FORWARDED: &[&str] = &[
+	"input",
+	"tape-length",
+	"quit-on-eof",
+	"eof",
+	"emulate",
+	"should-flush",
+	"input-raw",
+	"output-raw",
+	"input-encoding",
+	"output-encoding",
+	"newlines",
+	"from",
+	"time-source",
+	"allow",
+	"budget",
+	"engine",
+	"extensions",
+	"opt-level",
+];
+
+/// What a client sends first.
+#[derive(Debug, Serialize, Deserialize)]
+struct Request {
+	version: String,
+	/// Directory the client runs in, which relative paths are relative to.
+	directory: PathBuf,
+	/// Arguments of the client, without the executable.
+	args: Vec<String>,
+}
+
+/// What the daemon answers a [`Request`] with.
+#[derive(Debug, Serialize, Deserialize)]
+struct Handshake {
+	version: String,
+}
+
+pub fn args() -> Vec<Arg> {
+	vec![Arg::new("idle-timeout")
+		.long("idle-timeout")
+		.value_name("SECONDS")
+		.help("Exit once no run was started for this long")
+		.value_parser(value_parser!(u64).range(1..))
+		.default_value("600")]
+}
+
+/// Run the daemon until it's idle for long enough.
+pub fn execute(matches: &ArgMatches) -> Result<()> {
+	let idle_seconds = *matches.get_one::<u64>("idle-timeout").unwrap();
+
+	unix::serve(&socket_path()?, idle_seconds)
+}
+
+/// Run the program of `run` in the daemon, if one is listening and every argument given is one it
+/// handles, and return the exit code. If it returns `None`, the run should happen locally.
+///
+/// What the daemon reads is taken from `stdin`, and what it prints is written to `stdout`.
+///
+/// # Errors
+///
+/// Fails like running the program would, or if the connection to the daemon breaks.
+pub fn forward(
+	matches: &ArgMatches,
+	stdin: &mut Box<dyn std::io::Read + Send>,
+	stdout: &mut dyn std::io::Write,
+) -> Result<Option<std::process::ExitCode>> {
+	if !SUPPORTED || matches.get_flag("no-daemon") || !forwardable(matches) {
+		return Ok(None);
+	}
+	let Ok(path) = socket_path() else {
+		return Ok(None);
+	};
+
+	unix::forward(&path, stdin, stdout)
+}
+
+/// Whether every argument of `run` that was given is one the daemon handles, and the program
+/// isn't compiled ops, which are quick enough to load.
+fn forwardable(matches: &ArgMatches) -> bool {
+	let input = matches.get_one::<PathBuf>("input").unwrap();
+
+	run::format(matches, input) != "bfc"
+		&& matches.ids().all(|id| {
+			FORWARDED.contains(&id.as_str())
+				|| matches.value_source(id.as_str()) != Some(ValueSource::CommandLine)
+		})
+}
+
+/// Socket the daemon listens on.
+fn socket_path() -> Result<PathBuf> {
+	// NOTE: not every system has a runtime directory, macOS doesn't
+	dirs::runtime_dir()
+		.or_else(dirs::cache_dir)
+		.map(|directory| directory.join(env!("CARGO_PKG_NAME")).join(SOCKET_NAME))
+		.ok_or_else(|| eyre!("couldn't find the runtime directory of this system"))
+}
+
+#[cfg(unix)]
+mod unix {
+	use brainfuck_rs::{
+		bytecode::{Bytecode, Fusion},
+		engine::{Engine, RunError},
+		program::Program,
+	};
+	use clap::ArgMatches;
+	use color_eyre::eyre::{bail, Result, WrapErr};
+	use fs_err as fs;
+	use std::{
+		collections::HashMap,
+		ffi::OsString,
+		io::{self, BufRead, BufReader, BufWriter, ErrorKind, Read, Write},
+		iter,
+		net::Shutdown,
+		os::unix::net::{UnixListener, UnixStream},
+		path::{Path, PathBuf},
+		process::ExitCode,
+		sync::{
+			atomic::{AtomicUsize, Ordering},
+			mpsc::{self, RecvTimeoutError},
+			Arc, Mutex,
+		},
+		thread,
+		time::{Duration, SystemTime},
+	};
+
+	use super::{Handshake, Request};
+	use crate::commands::{
+		self,
+		run::{self, Backend},
+	};
+
+	/// Version a client and the daemon need to share, since arguments and their meaning may change
+	/// between versions.
+	const VERSION: &str = env!("CARGO_PKG_VERSION");
+
+	/// How long a client waits for the daemon to answer before running the program itself.
+	const HANDSHAKE_TIMEOUT: Duration = Duration::from_secs(1);
+
+	/// Kinds of [frames](Frames) the daemon sends.
+	const OUTPUT: u8 = b'o';
+	const ERROR: u8 = b'e';
+	const EXIT: u8 = b'x';
+
+	/// Where a program was loaded from: its file, and the [loading](run::loading) arguments it
+	/// was loaded with.
+	type Key = (PathBuf, Vec<Vec<OsString>>);
+
+	/// A program the daemon loaded, and what it was compiled into so far.
+	struct Loaded {
+		/// Modification time and length of the file, for telling whether it changed since.
+		stamp: (SystemTime, u64),
+		program: Program,
+		compiled: Mutex<HashMap<Backend, Arc<Option<Bytecode>>>>,
+	}
+
+	/// Programs the daemon loaded.
+	#[derive(Default)]
+	struct Cache(Mutex<HashMap<Key, Arc<Loaded>>>);
+
+	impl Cache {
+		/// The program at `path`, loaded as chosen in `matches`, and loaded again if its file changed.
+		fn load(&self, matches: &ArgMatches, path: &Path) -> Result<Arc<Loaded>> {
+			let metadata = fs::metadata(path)?;
+			let stamp = (metadata.modified()?, metadata.len());
+			let key = (path.to_owned(), run::loading(matches));
+
+			if let Some(loaded) = self.0.lock().unwrap().get(&key) {
+				if loaded.stamp == stamp {
+					return Ok(Arc::clone(loaded));
+				}
+			}
+
+			// NOTE: loaded without holding the lock, so other runs aren't held up meanwhile
+			let loaded = Arc::new(Loaded {
+				stamp,
+				program: run::load(matches, path)?,
+				compiled: Mutex::default(),
+			});
+			self.0.lock().unwrap().insert(key, Arc::clone(&loaded));
+
+			Ok(loaded)
+		}
+	}
+
+	impl Loaded {
+		/// The program compiled for `backend`.
+		fn compiled(&self, backend: Backend) -> Arc<Option<Bytecode>> {
+			Arc::clone(
+				self.compiled
+					.lock()
+					.unwrap()
+					.entry(backend)
+					.or_insert_with(|| Arc::new(backend.compile(&self.program, Fusion::ALL))),
+			)
+		}
+	}
+
+	/// Messages from the daemon to a client: a byte telling the kind, the length of the payload as
+	/// 4 big-endian bytes, and the payload.
+	///
+	/// Writing to it sends output.
+	struct Frames(UnixStream);
+
+	impl Frames {
+		fn send(&mut self, kind: u8, payload: &[u8]) -> io::Result<()> {
+			let length = u32::try_from(payload.len()).map_err(io::Error::other)?;
+			self.0.write_all(&[kind])?;
+			self.0.write_all(&length.to_be_bytes())?;
+			self.0.write_all(payload)
+		}
+
+		/// Receive the next frame, or `None` if the daemon closed the connection.
+		fn receive(reader: &mut impl Read) -> io::Result<Option<(u8, Vec<u8>)>> {
+			let mut header = [0; 5];
+			match reader.read_exact(&mut header) {
+				Err(e) if e.kind() == ErrorKind::UnexpectedEof => return Ok(None),
+				result => result?,
+			}
+			let [kind, length @ ..] = header;
+
+			let mut payload = vec![0; u32::from_be_bytes(length) as usize];
+			reader.read_exact(&mut payload)?;
+
+			Ok(Some((kind, payload)))
+		}
+	}
+
+	impl Write for Frames {
+		fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+			self.send(OUTPUT, buf)?;
+			Ok(buf.len())
+		}
+
+		fn flush(&mut self) -> io::Result<()> {
+			self.0.flush()
+		}
+	}
+
+	/// Listen on `path`, running programs for clients, until no run was started for `idle_seconds`.
+	pub fn serve(path: &Path, idle_seconds: u64) -> Result<()> {
+		fs::create_dir_all(path.parent().expect("the socket is in a directory"))?;
+		if UnixStream::connect(path).is_ok() {
+			bail!("a daemon is already listening on {}", path.display());
+		}
+		// NOTE: a daemon that was killed leaves its socket behind
+		match fs::remove_file(path) {
+			Err(e) if e.kind() != ErrorKind::NotFound => return Err(e.into()),
+			_ => {}
+		}
+		let listener = UnixListener::bind(path)
+			.wrap_err_with(|| format!("can't listen on {}", path.display()))?;
+		eprintln!("listening on {}", path.display());
+
+		let cache = Arc::new(Cache::default());
+		let running = Arc::new(AtomicUsize::new(0));
+		let (connections, incoming) = mpsc::channel();
+		thread::spawn(move || {
+			for stream in listener.incoming() {
+				if connections.send(stream).is_err() {
+					return;
+				}
+			}
+		});
+
+		loop {
+			match incoming.recv_timeout(Duration::from_secs(idle_seconds)) {
+				Ok(Ok(stream)) => {
+					let cache = Arc::clone(&cache);
+					let running = Arc::clone(&running);
+					running.fetch_add(1, Ordering::SeqCst);
+					thread::spawn(move || {
+						if let Err(e) = respond(stream, &cache) {
+							eprintln!("run failed: {e:#}");
+						}
+						running.fetch_sub(1, Ordering::SeqCst);
+					});
+				}
+				Ok(Err(e)) => eprintln!("can't accept a connection: {e}"),
+				// NOTE: a long run keeps the daemon busy, not idle
+				Err(RecvTimeoutError::Timeout) if running.load(Ordering::SeqCst) > 0 => {}
+				Err(_) => break,
+			}
+		}
+
+		eprintln!("no run for {idle_seconds} seconds, exiting");
+		let _ = fs::remove_file(path);
+		Ok(())
+	}
+
+	/// Run the program a client asks for on `stream`.
+	fn respond(stream: UnixStream, cache: &Cache) -> Result<()> {
+		let mut reader = BufReader::new(stream.try_clone()?);
+		let mut line = String::new();
+		reader.read_line(&mut line)?;
+		let request: Request = serde_json::from_str(&line).wrap_err("malformed request")?;
+
+		let mut frames = Frames(stream);
+		let handshake = serde_json::to_string(&Handshake {
+			version: VERSION.to_owned(),
+		})?;
+		writeln!(frames.0, "{handshake}")?;
+		if request.version != VERSION {
+			return Ok(());
+		}
+
+		// NOTE: output is sent whenever the program flushes it, not byte by byte
+		let mut stdout = BufWriter::new(&mut frames);
+		let result = execute(&request, cache, &mut reader, &mut stdout);
+		let result = stdout.flush().map_err(Into::into).and(result);
+		drop(stdout);
+
+		match result {
+			Ok(exit_code) => frames.send(EXIT, &[exit_code])?,
+			Err(e) => frames.send(ERROR, format!("{e:#}").as_bytes())?,
+		}
+		frames.0.shutdown(Shutdown::Both)?;
+
+		Ok(())
+	}
+
+	/// Run the program of `request` like `run` would, and return its exit code.
+	fn execute(
+		request: &Request,
+		cache: &Cache,
+		stdin: &mut impl Read,
+		stdout: &mut impl Write,
+	) -> Result<u8> {
+		let matches = commands::command().try_get_matches_from(
+			iter::once(OsString::from(env!("CARGO_PKG_NAME")))
+				.chain(request.args.iter().map(OsString::from)),
+		)?;
+		let matches = matches.subcommand_matches("run").unwrap_or(&matches);
+
+		let path = request
+			.directory
+			.join(matches.get_one::<PathBuf>("input").unwrap());
+		let loaded = cache.load(matches, &path)?;
+		let backend: Backend = matches
+			.get_one::<String>("engine")
+			.map_or(Backend::Tree, |name| {
+				name.parse().expect("clap only accepts known engines")
+			});
+		let compiled = loaded.compiled(backend);
+
+		let (tape_length, grow_tape) = run::tape_length(matches, &loaded.program);
+		let result = backend.run_compiled(
+			&mut Engine::new(tape_length),
+			&loaded.program,
+			compiled.as_ref().as_ref(),
+			stdin,
+			stdout,
+			run::settings(matches, grow_tape),
+			&mut (),
+		);
+
+		match result {
+			Ok(summary) => Ok(summary.exit_code.unwrap_or(0)),
+			// NOTE: the client went away, like `run` does when its output is closed
+			Err(RunError::Io { .. }) => Ok(0),
+			Err(e) => Err(e.into()),
+		}
+	}
+
+	/// Run the program in the daemon listening on `path`, see [`super::forward`].
+	pub fn forward(
+		path: &Path,
+		stdin: &mut Box<dyn Read + Send>,
+		stdout: &mut dyn Write,
+	) -> Result<Option<ExitCode>> {
+		let Some(args) = std::env::args_os()
+			.skip(1)
+			.map(|arg| arg.into_string().ok())
+			.collect::<Option<Vec<String>>>()
+		else {
+			return Ok(None);
+		};
+		let Ok(stream) = UnixStream::connect(path) else {
+			return Ok(None);
+		};
+		let Some(mut reader) = handshake(&stream, args) else {
+			return Ok(None);
+		};
+
+		// NOTE: not joined, since reading our input may never end
+		let mut input = std::mem::replace(stdin, Box::new(io::empty()));
+		let mut to_daemon = stream.try_clone()?;
+		thread::spawn(move || {
+			let _ = io::copy(&mut input, &mut to_daemon);
+			let _ = to_daemon.shutdown(Shutdown::Write);
+		});
+
+		while let Some((kind, payload)) = Frames::receive(&mut reader)? {
+			match kind {
+				OUTPUT => {
+					if stdout.write_all(&payload).is_err() || stdout.flush().is_err() {
+						// NOTE: like `run`, a closed output isn't an error
+						return Ok(Some(ExitCode::SUCCESS));
+					}
+				}
+				EXIT => return Ok(Some(ExitCode::from(payload.first().copied().unwrap_or(0)))),
+				ERROR => bail!("{}", String::from_utf8_lossy(&payload)),
+				_ => bail!("the daemon sent something unexpected"),
+			}
+		}
+
+		bail!("the daemon stopped before the program ended")
+	}
+
+	/// Send the request for running with `args` and read the answer, returning the rest of what
+	/// the daemon sends, or `None` if the program should run locally instead.
+	fn handshake(stream: &UnixStream, args: Vec<String>) -> Option<BufReader<UnixStream>> {
+		let request = Request {
+			version: VERSION.to_owned(),
+			directory: std::env::current_dir().ok()?,
+			args,
+		};
+		let mut writer = stream.try_clone().ok()?;
+		writeln!(writer, "{}", serde_json::to_string(&request).ok()?).ok()?;
+
+		stream.set_read_timeout(Some(HANDSHAKE_TIMEOUT)).ok()?;
+		let mut reader = BufReader::new(stream.try_clone().ok()?);
+		let mut line = String::new();
+		reader.read_line(&mut line).ok()?;
+		let handshake: Handshake = serde_json::from_str(&line).ok()?;
+		stream.set_read_timeout(None).ok()?;
+
+		(handshake.version == VERSION).then_some(reader)
+	}
+}
+
+#[cfg(not(unix))]
+mod unix {
+	use color_eyre::eyre::{bail, Result};
+	use std::{
+		io::{Read, Write},
+		path::Path,
+		process::ExitCode,
+	};
+
+	pub fn serve(_path: &Path, _idle_seconds: u64) -> Result<()> {
+		bail!("the daemon is only supported on Unix")
+	}
+
+	pub fn forward(
+		_path: &Path,
+		_stdin: &mut Box<dyn Read + Send>,
+		_stdout: &mut dyn Write,
+	) -> Result<Option<ExitCode>> {
+		Ok(None)
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::commands::command;
+
+	#[test]
+	fn only_handled_arguments_are_forwarded() {
+		let forwarded = |args: &[&str]| {
+			let matches = command().get_matches_from(args);
+			forwardable(matches.subcommand_matches("run").unwrap())
+		};
+
+		assert!(forwarded(&["brainfuck-rs", "run", "hello.b"]));
+		assert!(forwarded(&[
+			"brainfuck-rs",
+			"run",
+			"--engine",
+			"fused",
+			"-t",
+			"auto",
+			"hello.b"
+		]));
+		assert!(!forwarded(&[
+			"brainfuck-rs",
+			"run",
+			"--bench",
+			"5",
+			"hello.b"
+		]));
+		assert!(!forwarded(&["brainfuck-rs", "run", "--isolate", "hello.b"]));
+		assert!(!forwarded(&["brainfuck-rs", "run", "hello.bfc"]));
+	}
+}
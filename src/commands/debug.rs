@@ -0,0 +1,108 @@
+use brainfuck_rs::{
+	crash::CrashDump,
+	debugger::Debugger,
+	engine::{Engine, RuntimeSettings},
+	host::StdHost,
+	program::Program,
+	utils::StripShebang,
+};
+use clap::{value_parser, Arg, ArgMatches};
+use color_eyre::eyre::Result;
+use fs_err as fs;
+use std::{
+	io,
+	net::{Shutdown, SocketAddr, TcpStream},
+	path::PathBuf,
+	thread,
+};
+
+use super::{extensions, extensions_arg};
+
+pub fn args() -> Vec<Arg> {
+	vec![
+		Arg::new("input")
+			.value_name("FILE")
+			.help("Brainfuck program to run under the debugger, which takes commands on stdin, like `until 12:3` to run until the program gets there or `tbreak 100` to stop once it ran 100 more instructions. See `help` once it starts.")
+			.value_parser(value_parser!(PathBuf)),
+		Arg::new("core")
+			.long("core")
+			.value_name("BFCRASH")
+			.help("Crash dump saved by `run --crash-dump` to inspect")
+			.value_parser(value_parser!(PathBuf))
+			.required_unless_present_any(["input", "attach"])
+			.conflicts_with_all(["input", "attach"]),
+		Arg::new("attach")
+			.long("attach")
+			.value_name("ADDRESS")
+			.help("Attach to a program run with `run --debug-listen ADDRESS`, stopping it at the next loop condition or IO, and send it commands from stdin. Ending them, or `detach`, lets the program run on.")
+			.value_parser(value_parser!(SocketAddr))
+			.conflicts_with("input"),
+		Arg::new("stdin")
+			.long("stdin")
+			.value_name("TEXT")
+			.help("Input the program reads, instead of none at all, since stdin is for commands")
+			.requires("input"),
+		extensions_arg(),
+	]
+}
+
+pub fn execute(matches: &ArgMatches) -> Result<()> {
+	if let Some(address) = matches.get_one::<SocketAddr>("attach") {
+		return attach(address);
+	}
+
+	let Some(input_file_path) = matches.get_one::<PathBuf>("input") else {
+		let core_path = matches.get_one::<PathBuf>("core").unwrap();
+
+		let dump = CrashDump::from_json(&fs::read_to_string(core_path)?)?;
+		print!("{dump}");
+
+		return Ok(());
+	};
+
+	let code = fs::read_to_string(input_file_path)?;
+	let program = Program::parse_with_extensions(code.strip_shebang(), &extensions(matches)?)?;
+
+	let stdin = matches
+		.get_one::<String>("stdin")
+		.map(String::as_bytes)
+		.unwrap_or_default();
+	let mut debugger = Debugger::new(&program, io::stdin().lock(), io::stderr());
+	let summary = Engine::default().run_with(
+		&program.instructions,
+		&mut &stdin[..],
+		&mut io::stdout(),
+		RuntimeSettings {
+			should_flush: true,
+			..Default::default()
+		},
+		&mut StdHost::default(),
+		&mut debugger,
+	)?;
+	debugger.finish()?;
+
+	if !summary.breakpoint_hit {
+		eprintln!("the program ended after {} steps", summary.steps);
+	}
+
+	Ok(())
+}
+
+/// Send commands from stdin to the program listening for debuggers at `address`, and its replies
+/// to stderr, until it closes the connection.
+fn attach(address: &SocketAddr) -> Result<()> {
+	let stream = TcpStream::connect(address)?;
+
+	let mut commands = stream.try_clone()?;
+	// NOTE: the program closes the connection once it detaches or ends, even if stdin hasn't
+	// ended, so nothing waits for this thread
+	thread::spawn(move || {
+		let _ = io::copy(&mut io::stdin().lock(), &mut commands);
+		let _ = commands.shutdown(Shutdown::Write);
+	});
+
+	let mut replies = stream;
+	io::copy(&mut replies, &mut io::stderr())?;
+
+	Ok(())
+}
@@ -0,0 +1,73 @@
+use brainfuck_rs::{
+	engine::{Engine, RuntimeSettings},
+	program::Program,
+	utils::StripShebang,
+	walkthrough::Walkthrough,
+};
+use clap::{value_parser, Arg, ArgMatches};
+use color_eyre::eyre::Result;
+use fs_err as fs;
+use std::{io, path::PathBuf};
+
+use super::{extensions, extensions_arg};
+
+pub fn args() -> Vec<Arg> {
+	vec![
+		Arg::new("input")
+			.required(true)
+			.value_name("FILE")
+			.help("Brainfuck program to walk through")
+			.value_parser(value_parser!(PathBuf)),
+		Arg::new("max-steps")
+			.long("max-steps")
+			.value_name("STEPS")
+			.help("Stop recording the run after this many instructions")
+			.default_value("5000")
+			.value_parser(value_parser!(u64).range(1..)),
+		Arg::new("stdin")
+			.long("stdin")
+			.value_name("TEXT")
+			.help("Input the program reads, instead of none at all"),
+		Arg::new("output")
+			.short('o')
+			.long("output")
+			.value_name("FILE")
+			.help("Write the page to this file, instead of printing it")
+			.value_parser(value_parser!(PathBuf)),
+		extensions_arg(),
+	]
+}
+
+pub fn execute(matches: &ArgMatches) -> Result<()> {
+	let input_file_path = matches.get_one::<PathBuf>("input").unwrap();
+
+	let code = fs::read_to_string(input_file_path)?;
+	let source = code.strip_shebang();
+	let program = Program::parse_with_extensions(source, &extensions(matches)?)?;
+
+	let stdin = matches
+		.get_one::<String>("stdin")
+		.map(String::as_bytes)
+		.unwrap_or_default();
+	let walkthrough = Walkthrough::record(
+		&mut Engine::default(),
+		&program,
+		&mut &stdin[..],
+		&mut io::sink(),
+		RuntimeSettings::default(),
+		*matches.get_one::<u64>("max-steps").unwrap(),
+	)?;
+
+	let title = input_file_path.file_name().map_or_else(
+		|| input_file_path.to_string_lossy(),
+		|name| name.to_string_lossy(),
+	);
+	let html = walkthrough.to_html(&title, source);
+
+	match matches.get_one::<PathBuf>("output") {
+		Some(path) => fs::write(path, html)?,
+		None => print!("{html}"),
+	}
+
+	Ok(())
+}
@@ -0,0 +1,147 @@
+use super::{extensions, extensions_arg};
+use brainfuck_rs::{
+	engine::{Engine, RunError, RuntimeSettings},
+	golf::Score,
+	program::Program,
+	utils::StripShebang,
+};
+use clap::{value_parser, Arg, ArgAction, ArgMatches};
+use color_eyre::eyre::Result;
+use fs_err as fs;
+use serde::Serialize;
+use std::{path::PathBuf, process::ExitCode};
+
+/// Everything `golf` reports, see `--json`.
+#[derive(Debug, Serialize)]
+struct Report {
+	#[serde(flatten)]
+	score: Score,
+	minified_bytes: usize,
+	/// How the program did against `--target-output`, if one was given.
+	#[serde(skip_serializing_if = "Option::is_none")]
+	verification: Option<Verification>,
+}
+
+#[derive(Debug, Serialize)]
+struct Verification {
+	passed: bool,
+	/// Whether the program ended within `--max-steps`.
+	finished: bool,
+	steps: u64,
+}
+
+pub fn args() -> Vec<Arg> {
+	vec![
+		Arg::new("input")
+			.required(true)
+			.value_name("FILE")
+			.help("Brainfuck program to score")
+			.value_parser(value_parser!(PathBuf)),
+		Arg::new("target-output")
+			.long("target-output")
+			.value_name("TEXT")
+			.help("Exact output the program should print, checked by running it"),
+		Arg::new("program-input")
+			.long("program-input")
+			.value_name("FILE")
+			.help("File the program reads its input from when it's checked [default: no input]")
+			.value_parser(value_parser!(PathBuf))
+			.requires("target-output"),
+		Arg::new("max-steps")
+			.long("max-steps")
+			.value_name("INSTRUCTIONS")
+			.help("Fail the check if the program executes more instructions than this")
+			.value_parser(value_parser!(u64))
+			.default_value("1000000"),
+		Arg::new("json")
+			.long("json")
+			.help("Print the score as JSON, for contest tooling")
+			.action(ArgAction::SetTrue),
+		extensions_arg(),
+	]
+}
+
+pub fn execute(matches: &ArgMatches) -> Result<ExitCode> {
+	let input_file_path = matches.get_one::<PathBuf>("input").unwrap();
+
+	let code = fs::read_to_string(input_file_path)?;
+	let extensions = extensions(matches)?;
+	let score = Score::of(code.strip_shebang(), &extensions)?;
+
+	let verification = match matches.get_one::<String>("target-output") {
+		Some(target) => {
+			let program = Program::parse_with_extensions(code.strip_shebang(), &extensions)?;
+			let input = match matches.get_one::<PathBuf>("program-input") {
+				Some(path) => fs::read(path)?,
+				None => vec![],
+			};
+
+			let mut output = vec![];
+			let result = Engine::default().run(
+				&program.instructions,
+				&mut input.as_slice(),
+				&mut output,
+				RuntimeSettings {
+					budget: matches.get_one::<u64>("max-steps").copied(),
+					extensions,
+					..Default::default()
+				},
+			);
+			let (finished, steps) = match result {
+				Ok(summary) => (true, summary.steps),
+				Err(RunError::BudgetExhausted { spent, .. }) => (false, spent),
+				Err(e) => return Err(e.into()),
+			};
+
+			Some(Verification {
+				passed: finished && output == target.as_bytes(),
+				finished,
+				steps,
+			})
+		}
+		None => None,
+	};
+
+	let passed = verification
+		.as_ref()
+		.is_none_or(|verification| verification.passed);
+	let report = Report {
+		minified_bytes: score.minified.len(),
+		score,
+		verification,
+	};
+
+	if matches.get_flag("json") {
+		println!("{}", serde_json::to_string_pretty(&report)?);
+	} else {
+		println!("bytes:    {}", report.score.bytes);
+		println!("commands: {}", report.score.commands);
+		println!("minified: {} bytes", report.minified_bytes);
+		println!("{}", report.score.minified);
+
+		match report.verification {
+			Some(Verification {
+				finished: false,
+				steps,
+				..
+			}) => println!("target:   not printed, the program didn't end within {steps} steps"),
+			Some(Verification {
+				passed: true,
+				steps,
+				..
+			}) => println!("target:   printed in {steps} steps"),
+			Some(Verification { steps, .. }) => {
+				println!(
+					"target:   not printed, the program printed something else in {steps} steps"
+				);
+			}
+			None => {}
+		}
+	}
+
+	Ok(if passed {
+		ExitCode::SUCCESS
+	} else {
+		ExitCode::FAILURE
+	})
+}
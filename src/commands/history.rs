@@ -0,0 +1,197 @@
+use clap::{value_parser, Arg, ArgMatches, Command};
+use color_eyre::eyre::{bail, eyre, Result, WrapErr};
+use fs_err::{self as fs, OpenOptions};
+use serde::{Deserialize, Serialize};
+use std::{
+	env,
+	io::{ErrorKind, Write},
+	path::{Path, PathBuf},
+	process::{self, ExitCode},
+	time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use super::cache::format_age;
+
+/// Name of the file runs are recorded in, one JSON object per line.
+const FILE_NAME: &str = "history.jsonl";
+
+/// A recorded invocation of `run`.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct Entry {
+	/// When the run started, in seconds since the Unix epoch.
+	started: u64,
+	/// Hash of the program, as in crash dumps.
+	program_hash: u64,
+	/// The program file, as it was given.
+	path: PathBuf,
+	/// Directory the run was started in.
+	directory: PathBuf,
+	/// Arguments the run was started with, for running it again.
+	args: Vec<String>,
+	engine: String,
+	/// How long the whole invocation took, in seconds.
+	seconds: f64,
+	/// Whether it ended with an error rather than the program's exit code.
+	failed: bool,
+}
+
+pub fn subcommands() -> Vec<Command> {
+	vec![
+		Command::new("enable").about("Start recording runs, which is off by default"),
+		Command::new("disable").about("Stop recording runs, and delete the ones recorded so far"),
+		Command::new("ls")
+			.about("List recorded runs, oldest first")
+			.arg(hash_arg().required(false).help("Only list runs of programs whose hash starts with this")),
+		Command::new("rerun")
+			.about("Run the latest run of a program again, with the same arguments in the same directory")
+			.arg(hash_arg().help("Hash of the program, or the beginning of it")),
+	]
+}
+
+fn hash_arg() -> Arg {
+	Arg::new("hash")
+		.required(true)
+		.value_name("HASH")
+		.value_parser(value_parser!(String))
+}
+
+pub fn execute(matches: &ArgMatches) -> Result<ExitCode> {
+	let path = path()?;
+
+	match matches.subcommand() {
+		Some(("enable", _)) => {
+			if !path.exists() {
+				fs::create_dir_all(path.parent().expect("the file is in a directory"))?;
+				fs::write(&path, "")?;
+			}
+			println!("recording runs in {}", path.display());
+		}
+		Some(("disable", _)) => match fs::remove_file(&path) {
+			Err(e) if e.kind() != ErrorKind::NotFound => return Err(e.into()),
+			_ => println!("stopped recording runs"),
+		},
+		Some(("rerun", matches)) => {
+			let prefix = matches.get_one::<String>("hash").unwrap();
+			let entries = matching(&entries(&path)?, Some(prefix));
+			let Some(latest) = entries.last() else {
+				bail!("no recorded run of a program whose hash starts with `{prefix}`");
+			};
+			if entries
+				.iter()
+				.any(|entry| entry.program_hash != latest.program_hash)
+			{
+				bail!("more than one program's hash starts with `{prefix}`");
+			}
+
+			let status = process::Command::new(env::current_exe()?)
+				.args(&latest.args)
+				.current_dir(&latest.directory)
+				.status()
+				.wrap_err("can't run it again")?;
+			// NOTE: a status without a code means it was killed by a signal
+			return Ok(status.code().map_or(ExitCode::FAILURE, |code| {
+				ExitCode::from(u8::try_from(code).unwrap_or(u8::MAX))
+			}));
+		}
+		_ => {
+			let prefix = matches
+				.subcommand_matches("ls")
+				.and_then(|matches| matches.get_one::<String>("hash"));
+			let now = SystemTime::now()
+				.duration_since(UNIX_EPOCH)
+				.unwrap_or_default()
+				.as_secs();
+
+			for entry in matching(&entries(&path)?, prefix.map(String::as_str)) {
+				println!(
+					"{:>8} ago  {:016x}  {:<8} {:>10.3}s{}  {}",
+					format_age(now.saturating_sub(entry.started)),
+					entry.program_hash,
+					entry.engine,
+					entry.seconds,
+					if entry.failed { " failed" } else { "" },
+					entry.path.display()
+				);
+			}
+		}
+	}
+
+	Ok(ExitCode::SUCCESS)
+}
+
+/// Record a run of the program with the hash `program_hash`, if recording was enabled.
+///
+/// Recording is only a convenience: if the file can't be written, the run is left alone.
+pub fn record(matches: &ArgMatches, program_hash: u64, started: SystemTime, failed: bool) {
+	let Ok(path) = path() else {
+		return;
+	};
+	// NOTE: opting out is the file not being there, so it's never created here
+	let Ok(mut file) = OpenOptions::new().append(true).open(&path) else {
+		return;
+	};
+
+	let entry = Entry {
+		started: started
+			.duration_since(UNIX_EPOCH)
+			.unwrap_or_default()
+			.as_secs(),
+		program_hash,
+		path: matches.get_one::<PathBuf>("input").unwrap().clone(),
+		directory: env::current_dir().unwrap_or_default(),
+		args: env::args_os()
+			.skip(1)
+			.map(|arg| arg.to_string_lossy().into_owned())
+			.collect(),
+		engine: matches
+			.get_one::<String>("engine")
+			.map_or("tree", String::as_str)
+			.to_owned(),
+		seconds: started.elapsed().unwrap_or(Duration::ZERO).as_secs_f64(),
+		failed,
+	};
+	let line = serde_json::to_string(&entry).expect("history entry serialization never fails");
+	let _ = writeln!(file, "{line}");
+}
+
+/// Whether runs are being recorded.
+pub fn enabled() -> bool {
+	path().is_ok_and(|path| path.exists())
+}
+
+/// Recorded runs, oldest first.
+fn entries(path: &Path) -> Result<Vec<Entry>> {
+	let history = match fs::read_to_string(path) {
+		Ok(history) => history,
+		Err(e) if e.kind() == ErrorKind::NotFound => {
+			bail!("runs aren't being recorded, see `history enable`")
+		}
+		Err(e) => return Err(e.into()),
+	};
+
+	history
+		.lines()
+		.filter(|line| !line.trim().is_empty())
+		.map(|line| serde_json::from_str(line).wrap_err("malformed history entry"))
+		.collect()
+}
+
+/// Entries of programs whose hash starts with `prefix`, or every entry if there's none.
+fn matching(entries: &[Entry], prefix: Option<&str>) -> Vec<Entry> {
+	entries
+		.iter()
+		.filter(|entry| {
+			prefix.is_none_or(|prefix| {
+				format!("{:016x}", entry.program_hash).starts_with(&prefix.to_lowercase())
+			})
+		})
+		.cloned()
+		.collect()
+}
+
+/// File runs are recorded in.
+fn path() -> Result<PathBuf> {
+	dirs::data_local_dir()
+		.map(|directory| directory.join(env!("CARGO_PKG_NAME")).join(FILE_NAME))
+		.ok_or_else(|| eyre!("couldn't find the data directory of this system"))
+}
@@ -0,0 +1,11 @@
+use std::io;
+
+use clap_mangen::Man;
+use color_eyre::eyre::Result;
+
+/// Print the man page.
+pub fn execute() -> Result<()> {
+	Man::new(super::command()).render(&mut io::stdout())?;
+
+	Ok(())
+}
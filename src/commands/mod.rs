@@ -0,0 +1,138 @@
+use brainfuck_rs::extension::{Extension, ExtensionSet};
+use clap::{
+	builder::{PossibleValue, PossibleValuesParser},
+	command, Arg, ArgAction, ArgMatches, Command,
+};
+use color_eyre::eyre::Result;
+
+pub mod bench;
+pub mod bench_compare;
+pub mod cache;
+pub mod completions;
+pub mod daemon;
+pub mod debug;
+pub mod export_steps;
+pub mod golf;
+pub mod history;
+pub mod man;
+pub mod parse;
+pub mod pgo;
+pub mod run;
+pub mod solve;
+pub mod stats;
+pub mod version;
+
+/// Build the command line interface.
+pub fn command() -> Command {
+	command!()
+		.args_conflicts_with_subcommands(true)
+		.subcommand_negates_reqs(true)
+		.args(run::args())
+		.subcommand(
+			Command::new("run")
+				.about("Run a Brainfuck program (the default when no subcommand is given)")
+				.args(run::args()),
+		)
+		.subcommand(
+			Command::new("parse")
+				.about("Parse a Brainfuck program and export its instruction tree")
+				.args(parse::args()),
+		)
+		.subcommand(
+			Command::new("pgo")
+				.about("Compile a Brainfuck program into a .bfc file tuned for the input it's profiled on")
+				.args(pgo::args()),
+		)
+		.subcommand(
+			Command::new("stats")
+				.about(
+					"Show static properties of a Brainfuck program, like the tape length it needs",
+				)
+				.args(stats::args()),
+		)
+		.subcommand(
+			Command::new("golf")
+				.about("Score a Brainfuck program for code golf, and optionally check what it prints")
+				.args(golf::args()),
+		)
+		.subcommand(
+			Command::new("bench-compare")
+				.about("Compare two benchmark results, flagging the engines that got slower")
+				.args(bench_compare::args()),
+		)
+		.subcommand(
+			Command::new("solve")
+				.about("Search for input that makes a Brainfuck program print the given output (experimental)")
+				.args(solve::args()),
+		)
+		.subcommand(
+			Command::new("debug")
+				.about("Step through a Brainfuck program interactively, attach to one started with `run --debug-listen`, or inspect a run that was aborted, from the crash dump saved by `run --crash-dump`")
+				.args(debug::args()),
+		)
+		.subcommand(
+			Command::new("export-steps")
+				.about("Export a run as a standalone HTML page that steps through it instruction by instruction")
+				.args(export_steps::args()),
+		)
+		.subcommand(
+			Command::new("cache")
+				.about("Manage what `run --cache` keeps in the cache directory")
+				.subcommand_required(true)
+				.subcommands(cache::subcommands()),
+		)
+		.subcommand(
+			Command::new("history")
+				.about("Review past runs, recorded locally once `history enable` is run, and run them again")
+				.subcommand_required(true)
+				.subcommands(history::subcommands()),
+		)
+		.subcommand(
+			Command::new("daemon")
+				.about("Keep programs loaded and compiled in the background, for `run` to use when it can (Unix only)")
+				.args(daemon::args()),
+		)
+		.subcommand(
+			Command::new("completions")
+				.about("Print a script that completes the command line in a shell")
+				.args(completions::args()),
+		)
+		.subcommand(Command::new("man").about("Print the man page"))
+		.subcommand(
+			Command::new("version")
+				.about("Show what this build supports: features, extensions, formats, engines and defaults")
+				.args(version::args()),
+		)
+}
+
+/// Argument that enables extension instructions.
+pub fn extensions_arg() -> Arg {
+	Arg::new("extensions")
+		.short('x')
+		.long("extensions")
+		.value_name("EXTENSIONS")
+		.help("Comma-separated list of nonstandard instructions to enable")
+		.value_parser(PossibleValuesParser::new(Extension::ALL.iter().map(
+			|extension| {
+				let definition = extension
+					.definition()
+					.expect("only built-in extensions are listed");
+
+				PossibleValue::new(definition.name)
+					.help(format!("`{}` {}", definition.symbol, definition.summary))
+			},
+		)))
+		.value_delimiter(',')
+		.action(ArgAction::Append)
+}
+
+/// Extensions enabled with [`extensions_arg`].
+pub fn extensions(matches: &ArgMatches) -> Result<ExtensionSet> {
+	let extensions: Vec<Extension> = matches
+		.get_many::<String>("extensions")
+		.unwrap_or_default()
+		.map(|name| name.parse().expect("clap only accepts known extensions"))
+		.collect();
+
+	Ok(ExtensionSet::new(&extensions)?)
+}
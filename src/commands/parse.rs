@@ -0,0 +1,124 @@
+use super::{extensions, extensions_arg};
+#[cfg(feature = "jit")]
+use brainfuck_rs::jit::Listing;
+use brainfuck_rs::{
+	analysis,
+	bytecode::{Bytecode, Fusion},
+	optimize::{OptLevel, PassManager},
+	program::Program,
+	utils::StripShebang,
+};
+use clap::{builder::PossibleValuesParser, value_parser, Arg, ArgMatches};
+use color_eyre::eyre::Result;
+use fs_err as fs;
+use std::{
+	io::{self, Write},
+	path::PathBuf,
+};
+
+pub fn args() -> Vec<Arg> {
+	vec![
+		Arg::new("input")
+			.required(true)
+			.value_name("FILE")
+			.help("Brainfuck program to parse")
+			.value_parser(value_parser!(PathBuf)),
+		Arg::new("emit")
+			.short('e')
+			.long("emit")
+			.value_name("FORMAT")
+			.help("What to export: the instruction tree, a listing of the ops the `bytecode` or `fused` engine runs, with the loops the `tiered` engine compiles into closures marked, the ops of the `fused` engine in the binary format `run` loads from .bfc files, the ranges of values cells may hold before every instruction as JSON, or the Cranelift IR and the machine instructions the `jit` engine compiles the program into, on the default tape, which needs the `jit` feature")
+			.value_parser(PossibleValuesParser::new([
+				"ast-json",
+				"ast-sexpr",
+				"bfc",
+				"bfo",
+				"bytecode",
+				"fused",
+				"value-ranges",
+				"clif",
+				"asm",
+			]))
+			.default_value("ast-json"),
+		extensions_arg(),
+	]
+}
+
+pub fn execute(matches: &ArgMatches) -> Result<()> {
+	let input_file_path = matches.get_one::<PathBuf>("input").unwrap();
+	let emit = matches.get_one::<String>("emit").unwrap();
+
+	let code = fs::read_to_string(input_file_path)?;
+
+	let program = Program::parse_with_extensions(code.strip_shebang(), &extensions(matches)?)?;
+
+	let stdout = &mut io::stdout().lock();
+	match emit_to(stdout, emit, &program) {
+		// NOTE: like `run`, stopping early because whatever reads the output went away is fine
+		Err(e) if e.kind() == io::ErrorKind::BrokenPipe => Ok(()),
+		result => Ok(result?),
+	}
+}
+
+/// Write `program` to `out` in the format named by `emit`.
+fn emit_to(out: &mut impl Write, emit: &str, program: &Program) -> io::Result<()> {
+	match emit {
+		"ast-json" => writeln!(out, "{}", program.to_json()),
+		"ast-sexpr" => writeln!(out, "{}", program.to_sexpr()),
+		"bfc" => {
+			// NOTE: dead loops are kept, since the tape the program will run on isn't known
+			let program = PassManager::preset(OptLevel::Basic, 0).run(program);
+			let bytecode = Bytecode::compile(&program.instructions)
+				.collapse()
+				.multiply_loops()
+				.count_loops()
+				.address_offsets()
+				.fuse(Fusion::ALL);
+			out.write_all(&bytecode.to_bfc())
+		}
+		"bfo" => out.write_all(&program.to_bfo()),
+		"bytecode" => write!(out, "{}", Bytecode::compile(&program.instructions)),
+		"fused" => write!(
+			out,
+			"{}",
+			Bytecode::compile(&program.instructions)
+				.collapse()
+				.multiply_loops()
+				.count_loops()
+				.address_offsets()
+				.fuse(Fusion::ALL)
+		),
+		"value-ranges" => writeln!(out, "{}", analysis::value_ranges(program).to_json(program)),
+		"clif" | "asm" => write!(out, "{}", native_listing(program, emit)?),
+		_ => unreachable!("clap only accepts known formats"),
+	}?;
+
+	out.flush()
+}
+
+/// The code the `jit` engine of `run` compiles `program` into on the default tape, as Cranelift IR
+/// for `clif`, or as machine instructions for `asm`.
+#[cfg(feature = "jit")]
+fn native_listing(program: &Program, emit: &str) -> io::Result<String> {
+	// NOTE: the same ops `run --engine jit` compiles
+	let bytecode = Bytecode::compile(&program.instructions)
+		.collapse()
+		.multiply_loops()
+		.address_offsets();
+	let listing =
+		Listing::of(&bytecode, super::run::DEFAULT_TAPE_LENGTH).map_err(io::Error::other)?;
+
+	Ok(if emit == "clif" {
+		listing.clif
+	} else {
+		listing.asm
+	})
+}
+
+#[cfg(not(feature = "jit"))]
+fn native_listing(_program: &Program, emit: &str) -> io::Result<String> {
+	Err(io::Error::new(
+		io::ErrorKind::Unsupported,
+		format!("`--emit {emit}` needs brainfuck-rs built with the `jit` feature"),
+	))
+}
@@ -0,0 +1,106 @@
+use super::{extensions, extensions_arg, run};
+use brainfuck_rs::{
+	engine::RuntimeSettings,
+	optimize::{OptLevel, PassManager},
+	program::Program,
+	utils::StripShebang,
+};
+use clap::{value_parser, Arg, ArgMatches};
+use color_eyre::eyre::Result;
+use fs_err as fs;
+use std::path::PathBuf;
+
+pub fn args() -> Vec<Arg> {
+	vec![
+		Arg::new("input")
+			.required(true)
+			.value_name("FILE")
+			.help("Brainfuck program to optimize")
+			.value_parser(value_parser!(PathBuf)),
+		Arg::new("train-input")
+			.long("train-input")
+			.required(true)
+			.value_name("FILE")
+			.help("Input of a typical run, which the program is profiled on")
+			.value_parser(value_parser!(PathBuf)),
+		Arg::new("output")
+			.short('o')
+			.long("output")
+			.value_name("FILE")
+			.help("Where to write the compiled program [default: the program's path with the .bfc extension]")
+			.value_parser(value_parser!(PathBuf)),
+		Arg::new("tape-length")
+			.long("tape-length")
+			.value_name("CELLS")
+			.help("Number of cells of the tape the program is profiled on")
+			.value_parser(value_parser!(usize))
+			.default_value("30000"),
+		Arg::new("max-steps")
+			.long("max-steps")
+			.value_name("OPS")
+			.help("Stop profiling once the program executed this many ops, for programs that run for too long or never end")
+			.value_parser(value_parser!(u64))
+			.default_value("100000000"),
+		Arg::new("quit-on-eof")
+			.short('q')
+			.long("quit-on-eof")
+			.value_name("BOOL")
+			.help("Stop profiling when the program reads past the end of the input")
+			.value_parser(value_parser!(bool))
+			.default_value("true"),
+		extensions_arg(),
+	]
+}
+
+pub fn execute(matches: &ArgMatches) -> Result<()> {
+	let input_file_path = matches.get_one::<PathBuf>("input").unwrap();
+	let output_path = matches
+		.get_one::<PathBuf>("output")
+		.cloned()
+		.unwrap_or_else(|| input_file_path.with_extension("bfc"));
+
+	let code = fs::read_to_string(input_file_path)?;
+	let extensions = extensions(matches)?;
+	let program = Program::parse_with_extensions(code.strip_shebang(), &extensions)?;
+	// NOTE: dead loops are kept, since the tape the program will run on isn't known
+	let program = PassManager::preset(OptLevel::Basic, 0).run(&program);
+
+	let input = fs::read(matches.get_one::<PathBuf>("train-input").unwrap())?;
+	let max_steps = *matches.get_one::<u64>("max-steps").unwrap();
+	let settings = RuntimeSettings {
+		budget: Some(max_steps),
+		quit_on_eof: *matches.get_one::<bool>("quit-on-eof").unwrap(),
+		extensions,
+		..Default::default()
+	};
+	let (bytecode, profile) = run::profile(
+		&program,
+		&settings,
+		*matches.get_one::<usize>("tape-length").unwrap(),
+		&input,
+	)?;
+
+	let fusions = profile.hot_fusions(&bytecode, run::MIN_FUSION_SHARE);
+	let tuned = bytecode.fuse(&fusions);
+	fs::write(&output_path, tuned.to_bfc())?;
+
+	let executed: u64 = (0..bytecode.ops().len())
+		.map(|index| profile.count(index))
+		.sum();
+	eprintln!("profiled {executed} ops on {} bytes of input", input.len());
+	if executed >= max_steps {
+		eprintln!("the program didn't end within --max-steps, so only its start was profiled");
+	}
+	if fusions.is_empty() {
+		eprintln!("no superinstruction pays off for this input");
+	} else {
+		eprintln!("superinstructions, the most profitable first: {fusions:?}");
+	}
+	eprintln!(
+		"saved {} ops to {}",
+		tuned.ops().len(),
+		output_path.display()
+	);
+
+	Ok(())
+}
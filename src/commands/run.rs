@@ -0,0 +1,1866 @@
+use super::{extensions, extensions_arg};
+use brainfuck_rs::{
+	analysis,
+	breakpoint::{Breakpoint, Breakpoints},
+	bytecode::{Bytecode, Fusion, Profile},
+	capability::Capability,
+	crash::{self, CrashDump, Event, History},
+	diagnostics::WrapCheck,
+	encoding::{Decoder, Encoder, Encoding},
+	engine::{
+		Engine, Eof, IdleLoops, NewlineMode, RunError, RunSummary, RuntimeSettings, TimeSource,
+	},
+	expect::{ExpectError, ExpectedOutput},
+	explain,
+	extension::ExtensionSet,
+	hooks::Hooks,
+	host::StdHost,
+	instruction::Instruction,
+	labels::CellLabels,
+	optimize::{CollapseRuns, OptLevel, Pass, PassManager, Verdict},
+	pipe::{self, Blocked, Stage},
+	program::Program,
+	remote::{DebugListener, RemoteDebugger},
+	rules::Rules,
+	script::Session,
+	semantics,
+	taint::{self, Dependencies},
+	trace::{Recorder, Redaction, Trace},
+	tracer::{TraceFilter, Tracer},
+	utils::StripShebang,
+	view::EngineView,
+};
+#[cfg(feature = "plugins")]
+use brainfuck_rs::{host::Granted, plugin::Plugin};
+use clap::{
+	builder::{PossibleValue, PossibleValuesParser, TypedValueParser},
+	parser::ValueSource,
+	value_parser, Arg, ArgAction, ArgMatches,
+};
+use color_eyre::{
+	eyre::{bail, Report, Result},
+	Section,
+};
+use fs_err as fs;
+use std::{
+	collections::BTreeSet,
+	ffi::OsString,
+	io::{self, BufReader, BufWriter, IsTerminal, Read, Write},
+	net::{Ipv4Addr, SocketAddr},
+	path::{Path, PathBuf},
+	process::ExitCode,
+	slice,
+	str::FromStr,
+	time::SystemTime,
+};
+
+use super::{bench, cache, daemon, history};
+use crate::{
+	console::{self, KeypressMode},
+	sandbox::{self, Limits},
+};
+
+pub fn args() -> Vec<Arg> {
+	vec![
+		Arg::new("input")
+			.required(true)
+			.value_name("FILE")
+			.help("Brainfuck program to run")
+			.value_parser(value_parser!(PathBuf)),
+		Arg::new("tape-length")
+			.short('t')
+			.long("tape-length")
+			.value_name("BYTES")
+			.help("Tape length, or `auto` to infer it from the program")
+			.long_help("Tape length, or `auto` to infer it from the program. If the program's pointer movement can't be bounded statically, `auto` falls back to a tape that grows as needed.")
+			.value_parser(parse_tape_length)
+			.default_value("30000"),
+		Arg::new("quit-on-eof")
+			.short('q')
+			.long("quit-on-eof")
+			.value_name("BOOL")
+			.help("Quit when EOF is encountered. E.g. after Ctrl-D or after the piped data ends.")
+			.value_parser(value_parser!(bool))
+			.default_value("true"),
+		Arg::new("eof")
+			.long("eof")
+			.value_name("CELL")
+			.help("What reading past the end of the input leaves in the cell, when the program doesn't quit then: 0, -1 (255), or the value it had")
+			.value_parser(["zero", "minus-one", "unchanged"])
+			.default_value("zero"),
+		Arg::new("emulate")
+			.long("emulate")
+			.value_name("INTERPRETER")
+			.help("Run the program like a well-known interpreter would: with its EOF behavior, tape length and wrapping. Other options given override what it does.")
+			.value_parser(PossibleValuesParser::new(semantics::Profile::ALL.iter().map(
+				|profile| PossibleValue::new(profile.name).help(profile.summary),
+			))),
+		Arg::new("should-flush")
+			.short('f')
+			.long("flush")
+			.value_name("BOOL")
+			.help("Flush the buffer on every print")
+			.value_parser(value_parser!(bool))
+			.default_value("true"),
+		Arg::new("contain-panics")
+			.long("contain-panics")
+			.value_name("BOOL")
+			.help("Report a panic of the engine as an error of the run. Turn this off to debug the interpreter itself.")
+			.value_parser(value_parser!(bool))
+			.default_value("true"),
+		Arg::new("input-raw")
+			.long("input-raw")
+			.help("Read input straight from the underlying handle, bypassing the console's text conversion (Windows)")
+			.action(ArgAction::SetTrue),
+		Arg::new("output-raw")
+			.long("output-raw")
+			.help("Write output straight to the underlying handle, so the console gets every byte as is, even if it's not valid UTF-8 (Windows)")
+			.action(ArgAction::SetTrue),
+		Arg::new("input-encoding")
+			.long("input-encoding")
+			.value_name("ENCODING")
+			.help("How input is written: as is, as hexadecimal digits, as base64, or as text with escapes like `\\x00`, for feeding binary data to a program from a terminal")
+			.value_parser(PossibleValuesParser::new(Encoding::ALL.iter().map(|encoding| encoding.name())))
+			.default_value("raw"),
+		Arg::new("output-encoding")
+			.long("output-encoding")
+			.value_name("ENCODING")
+			.help("How output is shown: as is, as hexadecimal digits, as base64, or as text with escapes like `\\x00`, for inspecting binary output on a terminal")
+			.value_parser(PossibleValuesParser::new(Encoding::ALL.iter().map(|encoding| encoding.name())))
+			.default_value("raw"),
+		Arg::new("input-script")
+			.long("input-script")
+			.value_name("SCRIPT")
+			.help("Drive an interactive program with a script of `expect \"TEXT\"`, `send \"TEXT\"` and `waitms N` lines instead of reading input, failing if the program doesn't print what the script expects")
+			.value_parser(value_parser!(PathBuf))
+			.conflicts_with_all(["bench", "input-encoding", "keypress-input"]),
+		Arg::new("newlines")
+			.long("newlines")
+			.value_name("MODE")
+			.help("Translate line endings: `crlf-to-lf` turns Windows line endings (and lone carriage returns) in the input into line feeds, for programs that read lines until they see 10, while `lf-to-crlf` prints every line feed as a carriage return and a line feed")
+			.value_parser(["passthrough", "crlf-to-lf", "lf-to-crlf"])
+			.default_value("passthrough"),
+		Arg::new("keypress-input")
+			.long("keypress-input")
+			.help("Pass every key to the program as soon as it's pressed, without waiting for Enter or echoing it. Ctrl-D (Ctrl-Z on Windows) ends the input and Ctrl-C stops the program.")
+			.action(ArgAction::SetTrue),
+		Arg::new("from")
+			.long("from")
+			.value_name("FORMAT")
+			.help("Format of the input file: Brainfuck source, a pre-parsed program, or ops compiled with `parse --emit bfc`, which only run on their own. Files ending in .bfc are compiled ops unless told otherwise.")
+			.value_parser(PossibleValuesParser::new(FORMATS))
+			.default_value("source"),
+		extensions_arg(),
+		Arg::new("opt-level")
+			.short('O')
+			.value_name("LEVEL")
+			.help("How hard to optimize the program before it runs: 0 runs it as written, 1 removes instructions that undo each other, clears cells in one step rather than counting them down, and runs repeated `+`, `-`, `>` and `<` in one step, 2 also skips loops that are never entered, and 3 also runs the part before the program first reads input right away, replacing it with what it prints")
+			.value_parser(PossibleValuesParser::new(["0", "1", "2", "3"]))
+			.default_value("2"),
+		Arg::new("extra-rules")
+			.long("extra-rules")
+			.value_name("FILE")
+			.help("Rewrite the program with the peephole rules in this file after the passes of -O, one `\"CODE\" => \"CODE\"` or `\"CODE\" => Set(N)` per line. Rules whose sides don't do the same are refused.")
+			.value_parser(value_parser!(PathBuf)),
+		Arg::new("verify-opts")
+			.long("verify-opts")
+			.help("Before running the optimized program, check on stderr that every pass of -O kept what it does, by conditions particular to each pass, then run it and the program as written on the same input, which is read whole first, and refuse to go on if either check fails")
+			.action(ArgAction::SetTrue)
+			.conflicts_with_all(["bench", "pipe", "link", "keypress-input", "input-script"]),
+		Arg::new("time-source")
+			.long("time-source")
+			.value_name("SOURCE")
+			.help(
+				"What the `time` extension reads: executed instruction count or host milliseconds",
+			)
+			.value_parser(PossibleValuesParser::new(["counter", "host"]))
+			.default_value("counter"),
+		Arg::new("allow")
+			.long("allow")
+			.value_name("CAPABILITIES")
+			.help("Comma-separated list of capabilities extensions are allowed to use")
+			.value_parser(PossibleValuesParser::new(
+				Capability::ALL.iter().map(|capability| capability.name()),
+			))
+			.value_delimiter(',')
+			.action(ArgAction::Append),
+		#[cfg(feature = "plugins")]
+		Arg::new("plugin")
+			.long("plugin")
+			.value_name("LIBRARY")
+			.help("Load handlers of custom instructions from a dynamic library that exposes the plugin C ABI, see `include/bf_plugin.h`. Plugins only reach the host through capabilities granted with `--allow`, but loading one runs its code, so only load libraries you trust.")
+			.value_parser(value_parser!(PathBuf))
+			.action(ArgAction::Append),
+		Arg::new("budget")
+			.long("budget")
+			.value_name("INSTRUCTIONS")
+			.help("Stop with an error after executing this many instructions")
+			.value_parser(value_parser!(u64)),
+		Arg::new("break-on-output")
+			.long("break-on-output")
+			.value_name("TEXT")
+			.help("Stop the program as soon as its output contains this text")
+			.conflicts_with_all(["explain", "taint"]),
+		Arg::new("break")
+			.long("break")
+			.value_name("LINE:COLUMN [if CONDITION]")
+			.help("Stop the program before it runs the instruction at this place, if the condition holds then, like `14:3 if cell[ptr] == 65`, and show where it stopped on stderr. Conditions compare numbers, `ptr`, and cells like `cell[3]` or `cell[ptr-1]`, joined by `&&`. Can be given more than once. Only the tree engine stops at breakpoints.")
+			.action(ArgAction::Append)
+			.value_parser(|text: &str| text.parse::<Breakpoint>())
+			.conflicts_with_all(["engine", "bench", "pipe", "link", "explain", "taint", "check-wrapping", "trace", "idle-loops"]),
+		Arg::new("debug-listen")
+			.long("debug-listen")
+			.value_name("[ADDRESS:]PORT")
+			.help("Let debuggers attach to the program while it runs, with `debug --attach ADDRESS`, which stops it at the next loop condition or IO and takes commands like `debug` does. Closing the debugger lets the program run on. Only the tree engine can be debugged. Listens on 127.0.0.1 if only a port is given, and refuses addresses other machines can reach unless --debug-listen-anywhere is given.")
+			.value_parser(|text: &str| {
+				text.parse::<u16>()
+					.map(|port| SocketAddr::from((Ipv4Addr::LOCALHOST, port)))
+					.or_else(|_| text.parse::<SocketAddr>())
+			})
+			.conflicts_with_all(["engine", "bench", "pipe", "link", "explain", "taint", "check-wrapping", "trace", "idle-loops", "break"]),
+		Arg::new("debug-listen-anywhere")
+			.long("debug-listen-anywhere")
+			.help("Let --debug-listen listen on addresses that aren't loopback ones. Debuggers aren't authenticated, so anyone who can connect can read and change the program's memory.")
+			.action(ArgAction::SetTrue)
+			.requires("debug-listen"),
+		Arg::new("record")
+			.long("record")
+			.value_name("TRACE")
+			.help("Record the program's input and output into a trace file")
+			.value_parser(value_parser!(PathBuf)),
+		Arg::new("redact")
+			.long("redact")
+			.value_name("MODE")
+			.help("How payloads recorded with --record or --transcript are stored: `none`, `hash`, or `truncate:N` to keep the first N bytes")
+			.value_parser(parse_redaction)
+			.default_value("none"),
+		Arg::new("crash-dump")
+			.long("crash-dump")
+			.value_name("BFCRASH")
+			.help("If the program is aborted, like when it exceeds the budget or gets stuck in a loop, save the tape, the last things it did and the settings into a file that `debug --core` shows")
+			.value_parser(value_parser!(PathBuf))
+			.conflicts_with_all(["bench", "explain", "taint", "check-wrapping"]),
+		Arg::new("show-recent")
+			.long("show-recent")
+			.help("If the program fails, also show the last instructions it ran before it did, along with the cells around the pointer, which are shown either way. Slows the program down a little.")
+			.action(ArgAction::SetTrue),
+		Arg::new("transcript")
+			.long("transcript")
+			.value_name("HTML")
+			.help("Save the program's input and output as a standalone HTML page once it ends, with input highlighted and the step of every part shown, for sharing a run")
+			.value_parser(value_parser!(PathBuf)),
+		Arg::new("engine")
+			.long("engine")
+			.value_name("ENGINE")
+			.help("How the program is executed: by walking the instruction tree, as compiled bytecode, as bytecode with superinstructions, additionally with hot loops compiled into closures, or compiled to native code as a whole with the `jit` feature. Benchmarks compare all engines unless one is given.")
+			.value_parser(PossibleValuesParser::new(Backend::KNOWN.iter().map(|backend| backend.possible_value())).try_map(|name| name.parse::<Backend>().map(|_| name))),
+		Arg::new("fuse-from")
+			.long("fuse-from")
+			.value_name("TRACE")
+			.help("Pick the superinstructions of the `fused` and `tiered` engines by profiling a run on the input of a trace recorded with --record, instead of using all of them")
+			.value_parser(value_parser!(PathBuf))
+			.requires("engine"),
+		Arg::new("cache")
+			.long("cache")
+			.help("Keep the ops the program is compiled into in the cache directory, and reuse them while its source and the settings it's loaded with stay the same, instead of optimizing it again. With --fuse-from, keep the picked superinstructions instead, while the program, the trace and the settings stay the same. See the `cache` subcommand.")
+			.action(ArgAction::SetTrue)
+			.requires("engine"),
+		Arg::new("explain")
+			.long("explain")
+			.value_name("STEPS")
+			.help("Narrate in plain English what every part of the program does while it runs, stopping after this many instructions. The narration goes to stderr.")
+			.value_parser(value_parser!(u64))
+			.conflicts_with_all(["bench", "record", "transcript", "engine"]),
+		Arg::new("taint")
+			.long("taint")
+			.help("Track which bytes of input every byte of output depends on, and show that as a matrix on stderr when the program ends")
+			.action(ArgAction::SetTrue)
+			.conflicts_with_all(["bench", "record", "transcript", "engine", "explain"]),
+		Arg::new("check-wrapping")
+			.long("check-wrapping")
+			.help("Log every time a cell or the pointer wraps around to stderr, along with where it happened, and summarize that when the program ends, so programs can be checked for relying on wrapping by accident")
+			.action(ArgAction::SetTrue)
+			.conflicts_with_all(["bench", "record", "transcript", "engine", "explain", "taint"]),
+		Arg::new("trace")
+			.long("trace")
+			.value_name("FILTER")
+			.help("Print every instruction the program runs to stderr, with the step, where it is and the current cell, or only the ones a filter keeps: terms separated by spaces, all of which must hold, like `only=.,` for instructions written with these symbols, `span=12:1-20:80` for ones between these places, and comparisons like `loop-depth>=3` or `step<1000`. Only the tree engine traces instructions.")
+			.num_args(0..=1)
+			.require_equals(true)
+			.default_missing_value("")
+			.value_parser(|text: &str| text.parse::<TraceFilter>())
+			.conflicts_with_all(["bench", "record", "transcript", "engine", "explain", "taint", "check-wrapping"]),
+		Arg::new("idle-loops")
+			.long("idle-loops")
+			.value_name("MODE")
+			.help("Look for loops that never end, because the program came back to exactly the same state without any IO in between, and either report where the first one is on stderr and keep running, or abort")
+			.value_parser(["report", "abort"])
+			.conflicts_with_all(["bench", "record", "transcript", "engine", "explain", "taint", "check-wrapping", "trace"]),
+		Arg::new("bench")
+			.long("bench")
+			.value_name("RUNS")
+			.help("Instead of showing the output, run the program several times and report timings. Piped input is fed to every run.")
+			.value_parser(value_parser!(u64).range(1..)),
+		Arg::new("warmup")
+			.long("warmup")
+			.value_name("RUNS")
+			.help("Number of benchmark runs to discard before measuring")
+			.value_parser(value_parser!(usize))
+			.default_value("1")
+			.requires("bench"),
+		Arg::new("pipe")
+			.long("pipe")
+			.value_name("FILE")
+			.help("Run these programs after the first one like a shell pipeline, in a single process, each reading what the previous one prints. Settings apply to every program, and each gets a tape of its own.")
+			.num_args(1..)
+			.value_parser(value_parser!(PathBuf))
+			.conflicts_with_all(["bench", "record", "transcript", "crash-dump", "fuse-from", "explain", "taint", "check-wrapping", "trace", "idle-loops", "input-script"]),
+		Arg::new("link")
+			.long("link")
+			.value_name("FILE")
+			.help("Run this program alongside the first one, each reading what the other prints, until both end or every one waits for the other. Instead of their output, print what each sent the other, in order. If they deadlock, where each was waiting is shown on stderr, and the exit code is 3.")
+			.value_parser(value_parser!(PathBuf))
+			.conflicts_with_all(["pipe", "bench", "record", "transcript", "crash-dump", "fuse-from", "explain", "taint", "check-wrapping", "trace", "idle-loops", "input-script"]),
+		Arg::new("summary")
+			.long("summary")
+			.help("Once a --pipe pipeline ends, show how many instructions every program executed, and how it ended, on stderr")
+			.action(ArgAction::SetTrue)
+			.requires("pipe"),
+		Arg::new("expect-output")
+			.long("expect-output")
+			.value_name("FILE")
+			.help("Instead of showing the output, compare it with this file as it's printed, and if they differ, show where as a unified diff on stderr and exit with 1. Neither is kept in memory whole, so huge outputs are fine.")
+			.value_parser(value_parser!(PathBuf))
+			.conflicts_with_all(["bench", "pipe", "link", "explain", "taint", "input-script", "output-encoding"]),
+		Arg::new("isolate")
+			.long("isolate")
+			.help("Run the program in a child process, which can't use more memory or CPU time than it's given, write files or dump core, and only talks to this one over pipes. For running untrusted programs on shared machines. Only supported on Unix. Since it can't write files, options that save one, like --record, --crash-dump or --cache, are refused along with it.")
+			.action(ArgAction::SetTrue)
+			// NOTE: the child would be killed with SIGXFSZ as soon as it wrote them
+			.conflicts_with_all(["keypress-input", "record", "transcript", "crash-dump", "cache"]),
+		Arg::new("memory-limit")
+			.long("memory-limit")
+			.value_name("MIB")
+			.help(format!("Mebibytes of memory an --isolate'd program may use [default: {DEFAULT_MEMORY_LIMIT}]"))
+			.value_parser(value_parser!(u64).range(1..))
+			.requires("isolate"),
+		Arg::new("cpu-limit")
+			.long("cpu-limit")
+			.value_name("SECONDS")
+			.help("Seconds of CPU time an --isolate'd program may use")
+			.value_parser(value_parser!(u64).range(1..))
+			.requires("isolate"),
+		Arg::new("no-daemon")
+			.long("no-daemon")
+			.help("Run the program in this process, even if a `daemon` is listening")
+			.action(ArgAction::SetTrue),
+		Arg::new("json")
+			.long("json")
+			.help("Print benchmark results as JSON instead of a table, for comparing them with `bench-compare` later")
+			.action(ArgAction::SetTrue)
+			.requires("bench"),
+	]
+}
+
+pub fn execute(matches: &ArgMatches) -> Result<ExitCode> {
+	// NOTE: an isolated child can't write files, so its parent records the run
+	if sandbox::is_child() || !history::enabled() {
+		return execute_unrecorded(matches, None);
+	}
+
+	let started = SystemTime::now();
+	let mut program_hash = None;
+	let result = execute_unrecorded(matches, Some(&mut program_hash));
+	// NOTE: runs that never loaded the program here, like isolated ones, ones handed to the daemon
+	// or ones of cached ops, load it only to hash it
+	let program_hash = program_hash.or_else(|| {
+		load(matches, matches.get_one::<PathBuf>("input").unwrap())
+			.ok()
+			.map(|program| crash::program_hash(&program))
+	});
+	if let Some(program_hash) = program_hash {
+		history::record(matches, program_hash, started, result.is_err());
+	}
+
+	result
+}
+
+/// Run the program as chosen on the command line, without recording it in the history, setting
+/// `program_hash`, if given, to the hash of the program once it's loaded.
+fn execute_unrecorded(
+	matches: &ArgMatches,
+	program_hash: Option<&mut Option<u64>>,
+) -> Result<ExitCode> {
+	// NOTE: the child gets the same arguments, so it's the one to actually run the program
+	if matches.get_flag("isolate") && !sandbox::is_child() {
+		let memory = matches
+			.get_one::<u64>("memory-limit")
+			.copied()
+			.unwrap_or(DEFAULT_MEMORY_LIMIT);
+
+		return sandbox::run(Limits {
+			memory: memory.saturating_mul(1024 * 1024),
+			cpu_seconds: matches.get_one::<u64>("cpu-limit").copied(),
+		});
+	}
+
+	let keypress_mode = if matches.get_flag("keypress-input") {
+		KeypressMode::enable()?
+	} else {
+		None
+	};
+	let keypress = keypress_mode.is_some();
+
+	let mut stdin = console::stdin(matches.get_flag("input-raw"), keypress)?;
+	let mut stdout = console::stdout(matches.get_flag("output-raw"), keypress)?;
+
+	let input_encoding = encoding(matches, "input-encoding");
+	if input_encoding != Encoding::Raw {
+		stdin = Box::new(Decoder::new(stdin, input_encoding));
+	}
+	let output_encoding = encoding(matches, "output-encoding");
+	if output_encoding != Encoding::Raw {
+		stdout = Box::new(Encoder::new(stdout, output_encoding));
+	}
+
+	if let Some(exit_code) = daemon::forward(matches, &mut stdin, &mut stdout)? {
+		return Ok(exit_code);
+	}
+
+	let input_file_path = matches.get_one::<PathBuf>("input").unwrap();
+	if format(matches, input_file_path) == "bfc" {
+		return run_precompiled(matches, input_file_path, &mut stdin, &mut stdout);
+	}
+	if let Some((backend, bytecode)) = cached_ops(matches, input_file_path)? {
+		return run_ops(matches, backend, &bytecode, &mut stdin, &mut stdout);
+	}
+
+	let program = load(matches, input_file_path)?;
+	if let Some(program_hash) = program_hash {
+		*program_hash = Some(crash::program_hash(&program));
+	}
+
+	if matches.contains_id("pipe") {
+		return run_pipeline(matches, program, &mut stdin, &mut stdout);
+	}
+	if matches.contains_id("link") {
+		return run_linked(matches, program, &mut stdout);
+	}
+	// NOTE: only pipelines need to send input to another thread, unlike scripts
+	let mut stdin: Box<dyn Read> = stdin;
+
+	if matches.get_flag("verify-opts") {
+		let mut input = vec![];
+		stdin.read_to_end(&mut input)?;
+		verify_opts(matches, input_file_path, &input)?;
+		stdin = Box::new(io::Cursor::new(input));
+	}
+
+	let (tape_length, grow_tape) = tape_length(matches, &program);
+	let mut bf = Engine::new(tape_length);
+	bf.labels = labels(matches, input_file_path)?;
+	let mut settings = settings(matches, grow_tape);
+	settings.extensions = extension_set(matches)?;
+
+	if let Some(expected_path) = matches.get_one::<PathBuf>("expect-output") {
+		let mut expected = ExpectedOutput::new(BufReader::new(fs::File::open(expected_path)?));
+		let exit_code = run(
+			matches,
+			&program,
+			&mut bf,
+			settings,
+			tape_length,
+			&mut stdin,
+			&mut expected,
+		)?;
+
+		return match expected.finish() {
+			Ok(()) => Ok(exit_code),
+			Err(ExpectError::Mismatch { offset, line, diff }) => {
+				eprintln!(
+					"output differs from {} from byte {offset} on, on line {line}:",
+					expected_path.display()
+				);
+				eprint!("{diff}");
+				Ok(ExitCode::FAILURE)
+			}
+			Err(e) => Err(e.into()),
+		};
+	}
+
+	let session = match matches.get_one::<PathBuf>("input-script") {
+		Some(script_path) => Some(Session::new(fs::read_to_string(script_path)?.parse()?)),
+		None => None,
+	};
+	if let Some(session) = &session {
+		stdin = Box::new(session.input());
+		stdout = Box::new(session.output(stdout));
+	}
+
+	let exit_code = run(
+		matches,
+		&program,
+		&mut bf,
+		settings,
+		tape_length,
+		&mut stdin,
+		&mut stdout,
+	)?;
+
+	if let Some(session) = session {
+		// NOTE: finish writing the output before reporting on the script
+		drop(stdout);
+		session.finish()?;
+	}
+
+	Ok(exit_code)
+}
+
+/// Run the ops compiled into the `.bfc` file at `path`, which skip parsing and optimizing the
+/// program, but only run on their own.
+fn run_precompiled(
+	matches: &ArgMatches,
+	path: &Path,
+	stdin: &mut impl Read,
+	stdout: &mut impl Write,
+) -> Result<ExitCode> {
+	if let Some(id) = NEEDS_PROGRAM
+		.iter()
+		.find(|id| matches.value_source(id) == Some(ValueSource::CommandLine))
+	{
+		bail!(
+			"--{id} can't be used with .bfc files, which only hold the compiled ops of a program"
+		);
+	}
+
+	let bytecode = Bytecode::from_bfc(&fs::read(path)?)?;
+
+	run_ops(matches, Backend::Tiered, &bytecode, stdin, stdout)
+}
+
+/// Ops the program at `path` is compiled into by the engine chosen on the command line, kept in
+/// the cache directory with `--cache`, or `None` if the run needs more than the ops, or the tape
+/// length depends on the program.
+fn cached_ops(matches: &ArgMatches, path: &Path) -> Result<Option<(Backend, Bytecode)>> {
+	if !matches.get_flag("cache") || matches.contains_id("fuse-from") {
+		return Ok(None);
+	}
+
+	let backend: Backend = matches
+		.get_one::<String>("engine")
+		.unwrap()
+		.parse()
+		.expect("clap only accepts known engines");
+	if backend == Backend::Tree {
+		bail!("--cache only applies to the bytecode, fused and tiered engines, unless --fuse-from is given");
+	}
+
+	let auto_tape_length = emulated(matches, "tape-length").is_none()
+		&& matches!(
+			matches.get_one::<TapeLength>("tape-length"),
+			Some(TapeLength::Auto)
+		);
+	let needs_program = NEEDS_PROGRAM
+		.iter()
+		.filter(|&&id| id != "engine")
+		.any(|id| matches.value_source(id) == Some(ValueSource::CommandLine));
+	if auto_tape_length || needs_program {
+		return Ok(None);
+	}
+
+	let name = path
+		.file_stem()
+		.map_or_else(|| "program".into(), |stem| stem.to_string_lossy());
+	let key = (fs::read(path)?, loading(matches), backend.name());
+	let bytecode = cache::bytecode(&name, key, || {
+		Ok(backend
+			.compile(&load(matches, path)?, Fusion::ALL)
+			.expect("only the tree engine doesn't compile programs"))
+	})?;
+
+	Ok(Some((backend, bytecode)))
+}
+
+/// Run compiled ops on their own, like `backend` runs the ops it compiles.
+fn run_ops(
+	matches: &ArgMatches,
+	backend: Backend,
+	bytecode: &Bytecode,
+	stdin: &mut impl Read,
+	stdout: &mut impl Write,
+) -> Result<ExitCode> {
+	// NOTE: the instructions aren't there to tell how far the pointer goes
+	let (tape_length, grow_tape) = match emulated(matches, "tape-length") {
+		Some(profile) => (profile.tape_length, profile.grow_tape),
+		None => match *matches.get_one::<TapeLength>("tape-length").unwrap() {
+			TapeLength::Fixed(length) => (length, false),
+			TapeLength::Auto => (DEFAULT_TAPE_LENGTH, true),
+		},
+	};
+
+	let bf = &mut Engine::new(tape_length);
+	let result = backend.run_compiled(
+		bf,
+		&Program::default(),
+		Some(bytecode),
+		stdin,
+		stdout,
+		settings(matches, grow_tape),
+		&mut (),
+	);
+
+	match result {
+		Ok(summary) => Ok(summary.exit_code.map_or(ExitCode::SUCCESS, ExitCode::from)),
+		Err(RunError::Io { .. }) => Ok(ExitCode::SUCCESS),
+		Err(e) => Err(locate(e, &Program::default(), backend)),
+	}
+}
+
+/// Format of the program at `path`, as chosen on the command line, or told by the extension of
+/// `.bfc` files.
+pub fn format<'a>(matches: &'a ArgMatches, path: &Path) -> &'a str {
+	let from = matches.get_one::<String>("from").unwrap();
+
+	if matches.value_source("from") != Some(ValueSource::CommandLine)
+		&& path.extension().is_some_and(|extension| extension == "bfc")
+	{
+		return "bfc";
+	}
+
+	from
+}
+
+/// Arguments of `run` that change how a program is loaded, as they were given.
+pub fn loading(matches: &ArgMatches) -> Vec<Vec<OsString>> {
+	const LOADING: &[&str] = &["from", "extensions", "opt-level", "tape-length", "emulate"];
+
+	LOADING
+		.iter()
+		.map(|id| {
+			matches
+				.get_raw(id)
+				.unwrap_or_default()
+				.map(ToOwned::to_owned)
+				.collect()
+		})
+		.collect()
+}
+
+/// Load the program at `path`, in the format chosen on the command line, and optimize it as
+/// much as `-O` asks for.
+pub fn load(matches: &ArgMatches, path: &Path) -> Result<Program> {
+	let program = load_unoptimized(matches, path)?;
+	let (tape_length, _) = tape_length(matches, &program);
+
+	Ok(passes(matches, tape_length)?.run(&program))
+}
+
+/// Load the program at `path`, in the format chosen on the command line, as it's written.
+fn load_unoptimized(matches: &ArgMatches, path: &Path) -> Result<Program> {
+	Ok(match format(matches, path) {
+		"ast-json" => Program::from_json(&fs::read_to_string(path)?)?,
+		"bfo" => Program::from_bfo(&fs::read(path)?)?,
+		"bfc" => bail!(
+			"{} holds compiled ops rather than a program, so it can only run on its own",
+			path.display()
+		),
+		_ => Program::parse_with_extensions(
+			fs::read_to_string(path)?.strip_shebang(),
+			&extension_set(matches)?,
+		)?,
+	})
+}
+
+/// The passes `-O` asks for, followed by the `--extra-rules`, for programs that run on a tape of
+/// `tape_length` cells.
+fn passes(matches: &ArgMatches, tape_length: usize) -> Result<PassManager> {
+	let level = match matches.get_one::<String>("opt-level").unwrap().as_str() {
+		"0" => OptLevel::None,
+		"1" => OptLevel::Basic,
+		"3" => OptLevel::Evaluate,
+		_ => OptLevel::Aggressive,
+	};
+
+	let passes = PassManager::preset(level, tape_length);
+
+	Ok(match matches.get_one::<PathBuf>("extra-rules") {
+		// NOTE: rules match runs as they're written, so runs are only collapsed after them
+		Some(path) if level > OptLevel::None => passes
+			.without(CollapseRuns.name())
+			.with_pass(fs::read_to_string(path)?.parse::<Rules>()?)
+			.with_pass(CollapseRuns),
+		Some(path) => passes.with_pass(fs::read_to_string(path)?.parse::<Rules>()?),
+		None => passes,
+	})
+}
+
+/// Validate every pass `-O` runs on the program at `path`, then run the program as written and
+/// as optimized on `input`, failing if a pass is refuted or they print different things.
+fn verify_opts(matches: &ArgMatches, path: &Path, input: &[u8]) -> Result<()> {
+	let program = load_unoptimized(matches, path)?;
+	let (tape_length, grow_tape) = tape_length(matches, &program);
+	let (optimized, validations) = passes(matches, tape_length)?.run_validated(&program);
+
+	for validation in &validations {
+		eprintln!("{validation}");
+	}
+	if let Some(refuted) = validations
+		.iter()
+		.find(|validation| matches!(validation.verdict, Verdict::Refuted(_)))
+	{
+		bail!(
+			"the {} pass may have changed what the program does",
+			refuted.pass
+		);
+	}
+
+	let [written, optimized] = [&program, &optimized].map(|program| {
+		let mut output = vec![];
+		Engine::new(tape_length)
+			.run(
+				&program.instructions,
+				&mut &input[..],
+				&mut output,
+				settings(matches, grow_tape),
+			)
+			.map(|_| output)
+	});
+	match (written, optimized) {
+		(Ok(written), Ok(optimized)) if written == optimized => {
+			eprintln!("differential run: the program as written and optimized print the same");
+		}
+		(Ok(written), Ok(optimized)) => {
+			let offset = written
+				.iter()
+				.zip(&optimized)
+				.position(|(written, optimized)| written != optimized)
+				.unwrap_or(written.len().min(optimized.len()));
+			bail!("the optimized program prints something else than the program as written, from byte {offset} on");
+		}
+		(Err(e), _) | (_, Err(e)) => {
+			eprintln!("differential run: skipped, since a run failed: {e}");
+		}
+	}
+
+	Ok(())
+}
+
+/// Names of cells given by `@cell` directives in the program at `path`, unless it's pre-parsed.
+pub fn labels(matches: &ArgMatches, path: &Path) -> Result<CellLabels> {
+	Ok(match format(matches, path) {
+		"source" => CellLabels::parse(&fs::read_to_string(path)?),
+		_ => CellLabels::default(),
+	})
+}
+
+/// Length of the tape `program` runs on, and whether it grows, as chosen on the command line.
+pub fn tape_length(matches: &ArgMatches, program: &Program) -> (usize, bool) {
+	if let Some(profile) = emulated(matches, "tape-length") {
+		return (profile.tape_length, profile.grow_tape);
+	}
+
+	match *matches.get_one::<TapeLength>("tape-length").unwrap() {
+		TapeLength::Fixed(length) => (length, false),
+		TapeLength::Auto => match analysis::pointer_bounds(&program.instructions) {
+			Some(bounds) => (bounds.tape_length(), false),
+			None => (DEFAULT_TAPE_LENGTH, true),
+		},
+	}
+}
+
+/// Settings chosen on the command line.
+pub fn settings(matches: &ArgMatches, grow_tape: bool) -> RuntimeSettings {
+	RuntimeSettings {
+		should_flush: *matches.get_one::<bool>("should-flush").unwrap(),
+		quit_on_eof: emulated(matches, "quit-on-eof").map_or_else(
+			|| *matches.get_one::<bool>("quit-on-eof").unwrap(),
+			|profile| profile.quit_on_eof,
+		),
+		eof: emulated(matches, "eof").map_or_else(
+			|| match matches.get_one::<String>("eof").unwrap().as_str() {
+				"minus-one" => Eof::MinusOne,
+				"unchanged" => Eof::Unchanged,
+				_ => Eof::Zero,
+			},
+			|profile| profile.eof,
+		),
+		time_source: match matches.get_one::<String>("time-source").unwrap().as_str() {
+			"host" => TimeSource::HostMillis,
+			_ => TimeSource::Counter,
+		},
+		capabilities: capabilities(matches),
+		grow_tape,
+		budget: matches.get_one::<u64>("budget").copied(),
+		read_ahead: true,
+		break_on_output: matches
+			.get_one::<String>("break-on-output")
+			.map(|text| text.clone().into_bytes()),
+		idle_loops: match matches.get_one::<String>("idle-loops").map(String::as_str) {
+			Some("abort") => IdleLoops::Abort,
+			Some(_) => IdleLoops::Report,
+			None => IdleLoops::Ignore,
+		},
+		newlines: match matches.get_one::<String>("newlines").unwrap().as_str() {
+			"crlf-to-lf" => NewlineMode::CrlfToLf,
+			"lf-to-crlf" => NewlineMode::LfToCrlf,
+			_ => NewlineMode::Passthrough,
+		},
+		contain_panics: *matches.get_one::<bool>("contain-panics").unwrap(),
+		remember_recent: matches.get_flag("show-recent"),
+		..Default::default()
+	}
+}
+
+/// Capabilities granted with `--allow`.
+fn capabilities(matches: &ArgMatches) -> BTreeSet<Capability> {
+	matches
+		.get_many::<String>("allow")
+		.unwrap_or_default()
+		.map(|name| name.parse().expect("clap only accepts known capabilities"))
+		.collect()
+}
+
+/// Extensions enabled with `--extensions`, along with the instructions of every `--plugin`.
+fn extension_set(matches: &ArgMatches) -> Result<ExtensionSet> {
+	#[cfg_attr(not(feature = "plugins"), allow(unused_mut))]
+	let mut extensions = extensions(matches)?;
+
+	#[cfg(feature = "plugins")]
+	for path in matches.get_many::<PathBuf>("plugin").unwrap_or_default() {
+		// NOTE: nothing tells what capabilities plugins need before they run, so the host refuses
+		// whatever wasn't granted instead
+		let host = Granted {
+			host: StdHost::default(),
+			capabilities: capabilities(matches),
+		};
+		// SAFETY: `--plugin` says to trust the library, which its help warns about
+		extensions = unsafe { Plugin::load(path, host) }?.register(extensions)?;
+	}
+
+	Ok(extensions)
+}
+
+/// Interpreter chosen with `--emulate`, if there's one and the argument `id` wasn't given on the
+/// command line to override it.
+fn emulated(matches: &ArgMatches, id: &str) -> Option<&'static semantics::Profile> {
+	if matches.value_source(id) == Some(ValueSource::CommandLine) {
+		return None;
+	}
+
+	matches
+		.get_one::<String>("emulate")
+		.map(|name| semantics::Profile::find(name).expect("clap only accepts known interpreters"))
+}
+
+/// Run `first` and the programs given with `--pipe` as a pipeline, each on a tape of its own.
+fn run_pipeline(
+	matches: &ArgMatches,
+	first: Program,
+	stdin: &mut (impl Read + Send),
+	stdout: &mut impl Write,
+) -> Result<ExitCode> {
+	let paths: Vec<&PathBuf> = matches
+		.get_one::<PathBuf>("input")
+		.into_iter()
+		.chain(matches.get_many::<PathBuf>("pipe").unwrap())
+		.collect();
+	let mut programs = vec![first];
+	for path in &paths[1..] {
+		programs.push(load(matches, path)?);
+	}
+	let stages: Vec<Stage<'_>> = programs
+		.iter()
+		.map(|program| stage(matches, program, None))
+		.collect();
+	let results = pipe::run(stdin, stdout, stages);
+
+	if matches.get_flag("summary") {
+		for (path, result) in paths.iter().zip(&results) {
+			match result {
+				Ok(summary) => match summary.exit_code {
+					Some(code) => eprintln!(
+						"{}: {} steps, exited with {code}",
+						path.display(),
+						summary.steps
+					),
+					None => eprintln!("{}: {} steps", path.display(), summary.steps),
+				},
+				Err(e) => eprintln!("{}: {e}", path.display()),
+			}
+		}
+	}
+
+	let mut exit_code = ExitCode::SUCCESS;
+	for (path, result) in paths.iter().zip(results) {
+		match result {
+			Ok(summary) => exit_code = summary.exit_code.map_or(ExitCode::SUCCESS, ExitCode::from),
+			// NOTE: programs whose output is cut off by the next one ending are fine, just like in
+			// a shell, and so is the last one being cut off like a single program is
+			Err(RunError::Io { .. }) => exit_code = ExitCode::SUCCESS,
+			Err(e) => return Err(Report::new(e).wrap_err(format!("{} failed", path.display()))),
+		}
+	}
+
+	Ok(exit_code)
+}
+
+/// Run the program given with `--link` alongside `first`, each reading what the other prints, and
+/// print what they sent each other.
+fn run_linked(matches: &ArgMatches, first: Program, stdout: &mut impl Write) -> Result<ExitCode> {
+	let paths = [
+		matches.get_one::<PathBuf>("input").unwrap(),
+		matches.get_one::<PathBuf>("link").unwrap(),
+	];
+	let second = load(matches, paths[1])?;
+
+	let mut states = [None, None];
+	let [first_state, second_state] = &mut states;
+	let linked = pipe::link(
+		stage(matches, &first, Some(first_state)),
+		stage(matches, &second, Some(second_state)),
+		pipe::DEFAULT_CAPACITY,
+	);
+
+	for (program, chunk) in &linked.transcript {
+		writeln!(
+			stdout,
+			"{}: {}",
+			paths[*program].display(),
+			chunk.escape_ascii()
+		)?;
+	}
+	stdout.flush()?;
+
+	if let Some(blocked) = linked.blocked {
+		eprintln!(
+			"{} and {} deadlocked, each is waiting for the other:",
+			paths[0].display(),
+			paths[1].display()
+		);
+		for ((path, blocked), state) in paths.iter().zip(blocked).zip(&states) {
+			let (Some(blocked), Some(state)) = (blocked, state) else {
+				continue;
+			};
+			eprintln!(
+				"  {}: {} {}",
+				path.display(),
+				match blocked {
+					Blocked::Reading => "waiting to read",
+					Blocked::Writing => "waiting to print",
+				},
+				describe_state(state)
+			);
+		}
+
+		return Ok(ExitCode::from(DEADLOCK_EXIT_CODE));
+	}
+
+	let mut exit_code = ExitCode::SUCCESS;
+	for (path, result) in paths.iter().zip(linked.results) {
+		match result {
+			Ok(summary) => exit_code = summary.exit_code.map_or(ExitCode::SUCCESS, ExitCode::from),
+			// NOTE: a program whose output is cut off by the other one ending is fine, like in
+			// a pipeline
+			Err(RunError::Io { .. }) => exit_code = ExitCode::SUCCESS,
+			Err(e) => return Err(Report::new(e).wrap_err(format!("{} failed", path.display()))),
+		}
+	}
+
+	Ok(exit_code)
+}
+
+/// Where a program left off, as in `at 1:5 (\`,\`), step 3, cell 0 = 1`.
+fn describe_state(state: &CrashDump) -> String {
+	let position = match state.history.last() {
+		Some(Event::Instruction {
+			symbol,
+			location: Some(span),
+			..
+		}) => format!("at {span} (`{symbol}`)"),
+		Some(Event::Instruction { symbol, .. }) => format!("at `{symbol}`"),
+		Some(Event::Op { index, op, .. }) => format!("at op {index} {op}"),
+		Some(Event::Input { .. } | Event::Output { .. }) | None => "at the start".to_owned(),
+	};
+
+	format!(
+		"{position}, step {}, cell {} = {}",
+		state.steps,
+		state.pointer,
+		state.tape.get(state.pointer).copied().unwrap_or_default()
+	)
+}
+
+/// Stage of a pipeline running `program` on a tape of its own, as chosen on the command line.
+///
+/// If it fails, the state the program was left in is kept in `state`, if given.
+fn stage<'a>(
+	matches: &'a ArgMatches,
+	program: &'a Program,
+	state: Option<&'a mut Option<CrashDump>>,
+) -> Stage<'a> {
+	let (tape_length, grow_tape) = tape_length(matches, program);
+	let backend: Backend = matches
+		.get_one::<String>("engine")
+		.map_or(Backend::Tree, |name| {
+			name.parse().expect("clap only accepts known engines")
+		});
+
+	Box::new(move |mut stdin, mut stdout| {
+		let mut bf = Engine::new(tape_length);
+		let settings = settings(matches, grow_tape);
+		let Some(state) = state else {
+			return backend.run_with(
+				&mut bf,
+				program,
+				Fusion::ALL,
+				&mut stdin,
+				&mut stdout,
+				settings,
+				&mut (),
+			);
+		};
+
+		// NOTE: only the last instruction is needed to tell where the program is
+		let mut history = History::new(program, 1);
+		let result = backend.run_with(
+			&mut bf,
+			program,
+			Fusion::ALL,
+			&mut stdin,
+			&mut stdout,
+			settings.clone(),
+			&mut history,
+		);
+		if let Err(e) = &result {
+			*state = Some(history.dump(e, &bf, &settings));
+		}
+
+		result
+	})
+}
+
+/// Run the program in the mode chosen on the command line.
+fn run(
+	matches: &ArgMatches,
+	program: &Program,
+	bf: &mut Engine,
+	settings: RuntimeSettings,
+	tape_length: usize,
+	stdin: &mut impl Read,
+	stdout: &mut impl Write,
+) -> Result<ExitCode> {
+	if let Some(&max_steps) = matches.get_one::<u64>("explain") {
+		let summary = explain::explain(
+			bf,
+			program,
+			stdin,
+			stdout,
+			&mut io::stderr(),
+			settings,
+			max_steps,
+		)?;
+
+		return Ok(summary.exit_code.map_or(ExitCode::SUCCESS, ExitCode::from));
+	}
+
+	if matches.get_flag("taint") {
+		let mut dependencies = Dependencies::default();
+		let result = taint::track(
+			bf,
+			&program.instructions,
+			stdin,
+			stdout,
+			settings,
+			&mut dependencies,
+		);
+
+		eprintln!("input bytes (columns) every output byte (rows) depends on:");
+		eprint!("{dependencies}");
+
+		return Ok(result?.exit_code.map_or(ExitCode::SUCCESS, ExitCode::from));
+	}
+
+	if matches.get_flag("check-wrapping") {
+		let mut check = WrapCheck::new(program, io::stderr());
+		let result = bf.run_with(
+			&program.instructions,
+			stdin,
+			stdout,
+			settings,
+			&mut StdHost::default(),
+			&mut check,
+		);
+
+		eprint!("{}", check.finish());
+
+		return Ok(result?.exit_code.map_or(ExitCode::SUCCESS, ExitCode::from));
+	}
+
+	if let Some(filter) = matches.get_one::<TraceFilter>("trace") {
+		let mut tracer = Tracer::new(program, filter.clone(), BufWriter::new(io::stderr()));
+		let result = bf.run_with(
+			&program.instructions,
+			stdin,
+			stdout,
+			settings,
+			&mut StdHost::default(),
+			&mut tracer,
+		);
+
+		tracer.finish()?;
+
+		return Ok(result?.exit_code.map_or(ExitCode::SUCCESS, ExitCode::from));
+	}
+
+	if let Some(address) = matches.get_one::<SocketAddr>("debug-listen") {
+		let listener = if matches.get_flag("debug-listen-anywhere") {
+			if !address.ip().is_loopback() {
+				eprintln!(
+					"warning: anyone who can reach {address} can take control of the program"
+				);
+			}
+			DebugListener::bind_any(address)?
+		} else {
+			DebugListener::bind(address)
+				.section("Pass --debug-listen-anywhere to listen there anyway.")?
+		};
+		eprintln!("listening for debuggers at {}", listener.local_addr());
+
+		let settings = RuntimeSettings {
+			pause: Some(listener.pause()),
+			..settings
+		};
+		let summary = bf.run_with(
+			&program.instructions,
+			stdin,
+			stdout,
+			settings,
+			&mut StdHost::default(),
+			&mut RemoteDebugger::new(program, listener),
+		)?;
+
+		if summary.breakpoint_hit {
+			eprintln!("a debugger ended the program at step {}", summary.steps);
+		}
+
+		return Ok(summary.exit_code.map_or(ExitCode::SUCCESS, ExitCode::from));
+	}
+
+	if let Some(breakpoints) = matches.get_many::<Breakpoint>("break") {
+		let mut breakpoints = Breakpoints::new(program, breakpoints.cloned().collect())?;
+		let result = bf.run_with(
+			&program.instructions,
+			stdin,
+			stdout,
+			settings,
+			&mut StdHost::default(),
+			&mut breakpoints,
+		);
+
+		if let Some(hit) = breakpoints.hit() {
+			let breakpoint = hit
+				.breakpoint
+				.as_ref()
+				.expect("only breakpoints are set, rather than steps");
+			eprintln!(
+				"stopped at breakpoint {} at step {}, {} = {}",
+				breakpoint,
+				hit.step,
+				bf.labels.name(hit.pointer),
+				hit.cell
+			);
+		}
+
+		return Ok(result?.exit_code.map_or(ExitCode::SUCCESS, ExitCode::from));
+	}
+
+	let crash_path = matches.get_one::<PathBuf>("crash-dump");
+
+	if settings.idle_loops != IdleLoops::Ignore {
+		let mut report = IdleLoopReport {
+			program,
+			history: crash_path.map(|_| History::new(program, HISTORY_LENGTH)),
+		};
+		let result = bf.run_with(
+			&program.instructions,
+			stdin,
+			stdout,
+			settings.clone(),
+			&mut StdHost::default(),
+			&mut report,
+		);
+
+		if let (Err(error), Some(crash_path), Some(history)) = (&result, crash_path, report.history)
+		{
+			save_crash(crash_path, history, error, bf, &settings)?;
+		}
+
+		return Ok(result
+			.map_err(|e| locate(e, program, Backend::Tree))?
+			.exit_code
+			.map_or(ExitCode::SUCCESS, ExitCode::from));
+	}
+
+	let backend: Option<Backend> = matches
+		.get_one::<String>("engine")
+		.map(|name| name.parse().expect("clap only accepts known engines"));
+
+	let fusions = match matches.get_one::<PathBuf>("fuse-from") {
+		Some(_) if !matches!(backend, Some(Backend::Fused | Backend::Tiered)) => {
+			bail!("--fuse-from only applies to the fused and tiered engines")
+		}
+		Some(trace_path) if matches.get_flag("cache") => cache::fusions(
+			&matches
+				.get_one::<PathBuf>("input")
+				.unwrap()
+				.file_stem()
+				.map_or_else(|| "program".into(), |stem| stem.to_string_lossy()),
+			(
+				crash::program_hash(program),
+				fs::read(trace_path)?,
+				tape_length,
+				settings.budget,
+				settings.grow_tape,
+			),
+			|| profile_fusions(program, &settings, tape_length, trace_path),
+		)?,
+		Some(trace_path) => profile_fusions(program, &settings, tape_length, trace_path)?,
+		None => Fusion::ALL.to_vec(),
+	};
+
+	if let Some(&runs) = matches.get_one::<u64>("bench") {
+		let warmup = *matches.get_one::<usize>("warmup").unwrap();
+
+		let mut input = vec![];
+		if !io::stdin().is_terminal() {
+			stdin.read_to_end(&mut input)?;
+		}
+
+		let backends = backend.as_ref().map_or(Backend::ALL, slice::from_ref);
+		let measurements = backends
+			.iter()
+			.map(|&backend| {
+				bench::measure(
+					backend,
+					program,
+					&settings,
+					tape_length,
+					&fusions,
+					&input,
+					usize::try_from(runs)?,
+					warmup,
+				)
+			})
+			.collect::<Result<Vec<_>>>()?;
+		if matches.get_flag("json") {
+			println!("{}", bench::to_json(&measurements));
+		} else {
+			bench::print_table(&measurements);
+		}
+
+		return Ok(ExitCode::SUCCESS);
+	}
+
+	let trace_path = matches.get_one::<PathBuf>("record");
+	let transcript_path = matches.get_one::<PathBuf>("transcript");
+
+	let result = if trace_path.is_some() || transcript_path.is_some() {
+		let mut recorder = Recorder::new(*matches.get_one::<Redaction>("redact").unwrap());
+
+		let result = backend.unwrap_or(Backend::Tree).run_with(
+			bf,
+			program,
+			&fusions,
+			stdin,
+			stdout,
+			settings,
+			&mut recorder,
+		);
+
+		let trace = recorder.finish();
+		if let Some(trace_path) = trace_path {
+			fs::write(trace_path, trace.to_string())?;
+		}
+		if let Some(transcript_path) = transcript_path {
+			let title = format!(
+				"brainfuck-rs run of {}",
+				matches.get_one::<PathBuf>("input").unwrap().display()
+			);
+			fs::write(transcript_path, trace.to_html(&title))?;
+		}
+
+		result
+	} else if let Some(crash_path) = crash_path {
+		let mut history = History::new(program, HISTORY_LENGTH);
+
+		let result = backend.unwrap_or(Backend::Tree).run_with(
+			bf,
+			program,
+			&fusions,
+			stdin,
+			stdout,
+			settings.clone(),
+			&mut history,
+		);
+
+		if let Err(error) = &result {
+			save_crash(crash_path, history, error, bf, &settings)?;
+		}
+
+		result
+	} else {
+		backend.unwrap_or(Backend::Tree).run_with(
+			bf,
+			program,
+			&fusions,
+			stdin,
+			stdout,
+			settings,
+			&mut (),
+		)
+	};
+
+	match result {
+		Ok(summary) => Ok(summary.exit_code.map_or(ExitCode::SUCCESS, ExitCode::from)),
+		// NOTE: It may error if the user piped our output into a program that doesn't read stdin,
+		// but we don't care (like a good programmer). Handlers of plugins fail with other errors.
+		Err(RunError::Io { error: e, .. }) if e.kind() == io::ErrorKind::BrokenPipe => {
+			Ok(ExitCode::SUCCESS)
+		}
+		Err(e) => Err(locate(e, program, backend.unwrap_or(Backend::Tree))),
+	}
+}
+
+/// Ways of executing a program.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Backend {
+	Tree,
+	Bytecode,
+	Fused,
+	Tiered,
+	/// Compiles the whole program to native code, only with the `jit` feature.
+	Jit,
+}
+
+impl Backend {
+	/// The engines this build has.
+	#[cfg(feature = "jit")]
+	pub const ALL: &'static [Self] = &[
+		Self::Tree,
+		Self::Bytecode,
+		Self::Fused,
+		Self::Tiered,
+		Self::Jit,
+	];
+	/// The engines this build has.
+	#[cfg(not(feature = "jit"))]
+	pub const ALL: &'static [Self] = &[Self::Tree, Self::Bytecode, Self::Fused, Self::Tiered];
+
+	/// Every engine there is, including ones this build was compiled without, which `--engine`
+	/// refuses by the feature they need.
+	pub const KNOWN: &'static [Self] = &[
+		Self::Tree,
+		Self::Bytecode,
+		Self::Fused,
+		Self::Tiered,
+		Self::Jit,
+	];
+
+	pub const fn name(self) -> &'static str {
+		match self {
+			Self::Tree => "tree",
+			Self::Bytecode => "bytecode",
+			Self::Fused => "fused",
+			Self::Tiered => "tiered",
+			Self::Jit => "jit",
+		}
+	}
+
+	/// How the engine is given to `--engine`, hidden from the help if this build doesn't have it.
+	pub fn possible_value(self) -> PossibleValue {
+		PossibleValue::new(self.name()).hide(!Self::ALL.contains(&self))
+	}
+
+	/// Run the program, using `fusions` if it's compiled into superinstructions.
+	#[allow(clippy::too_many_arguments)]
+	pub fn run_with(
+		self,
+		bf: &mut Engine,
+		program: &Program,
+		fusions: &[Fusion],
+		stdin: &mut impl Read,
+		stdout: &mut impl Write,
+		settings: RuntimeSettings,
+		hooks: &mut impl Hooks,
+	) -> Result<RunSummary, RunError> {
+		let compiled = self.compile(program, fusions);
+
+		self.run_compiled(
+			bf,
+			program,
+			compiled.as_ref(),
+			stdin,
+			stdout,
+			settings,
+			hooks,
+		)
+	}
+
+	/// Compile the program, using `fusions` if it's compiled into superinstructions, or `None`
+	/// if it's run as it is.
+	pub fn compile(self, program: &Program, fusions: &[Fusion]) -> Option<Bytecode> {
+		match self {
+			Self::Tree => None,
+			Self::Bytecode => Some(Bytecode::compile(&program.instructions)),
+			Self::Fused | Self::Tiered => Some(
+				Bytecode::compile(&program.instructions)
+					.collapse()
+					.multiply_loops()
+					.count_loops()
+					.address_offsets()
+					.fuse(fusions),
+			),
+			// NOTE: native code is compiled from the ops the JIT supports, which leaves out
+			// superinstructions and counted loops
+			Self::Jit => Some(
+				Bytecode::compile(&program.instructions)
+					.collapse()
+					.multiply_loops()
+					.address_offsets(),
+			),
+		}
+	}
+
+	/// Run the program, as compiled by [`Backend::compile`].
+	#[allow(clippy::too_many_arguments)]
+	pub fn run_compiled(
+		self,
+		bf: &mut Engine,
+		program: &Program,
+		compiled: Option<&Bytecode>,
+		stdin: &mut impl Read,
+		stdout: &mut impl Write,
+		settings: RuntimeSettings,
+		hooks: &mut impl Hooks,
+	) -> Result<RunSummary, RunError> {
+		let host = &mut StdHost::default();
+
+		let Some(bytecode) = compiled else {
+			return bf.run_with(&program.instructions, stdin, stdout, settings, host, hooks);
+		};
+		let settings = match self {
+			Self::Fused | Self::Tiered => RuntimeSettings {
+				specialize_after: (self == Self::Tiered).then_some(HOT_LOOP_THRESHOLD),
+				..settings
+			},
+			Self::Tree | Self::Bytecode => settings,
+			Self::Jit => return run_native(bf, bytecode, stdin, stdout, &settings),
+		};
+
+		bf.run_bytecode_with(bytecode, stdin, stdout, settings, host, hooks)
+	}
+}
+
+impl FromStr for Backend {
+	type Err = String;
+
+	fn from_str(name: &str) -> Result<Self, Self::Err> {
+		let backend = Self::KNOWN
+			.iter()
+			.copied()
+			.find(|backend| backend.possible_value().matches(name, false))
+			.ok_or_else(|| format!("unknown engine `{name}`"))?;
+
+		if !Self::ALL.contains(&backend) {
+			return Err(format!(
+				"the `{name}` engine needs brainfuck-rs built with the `jit` feature"
+			));
+		}
+
+		Ok(backend)
+	}
+}
+
+/// Run bytecode compiled to native code for the `jit` engine. Hooks aren't called.
+#[cfg(feature = "jit")]
+fn run_native(
+	bf: &mut Engine,
+	bytecode: &Bytecode,
+	stdin: &mut impl Read,
+	stdout: &mut impl Write,
+	settings: &RuntimeSettings,
+) -> Result<RunSummary, RunError> {
+	let compiled = brainfuck_rs::jit::JitProgram::compile(bytecode, bf.tape.len())
+		.map_err(|e| io::Error::new(io::ErrorKind::Unsupported, e))?;
+
+	compiled.run(bf, stdin, stdout, settings)
+}
+
+/// Fail to run the `jit` engine, which this build doesn't have.
+#[cfg(not(feature = "jit"))]
+fn run_native(
+	_bf: &mut Engine,
+	_bytecode: &Bytecode,
+	_stdin: &mut impl Read,
+	_stdout: &mut impl Write,
+	_settings: &RuntimeSettings,
+) -> Result<RunSummary, RunError> {
+	Err(io::Error::new(
+		io::ErrorKind::Unsupported,
+		"the `jit` engine needs brainfuck-rs built with the `jit` feature",
+	)
+	.into())
+}
+
+/// Formats programs can be loaded from, see `--from`.
+pub const FORMATS: &[&str] = &["source", "ast-json", "bfo", "bfc"];
+
+/// Arguments that need the program itself, so they can't be used with `.bfc` files.
+const NEEDS_PROGRAM: &[&str] = &[
+	"engine",
+	"verify-opts",
+	"extra-rules",
+	"fuse-from",
+	"bench",
+	"record",
+	"transcript",
+	"crash-dump",
+	"explain",
+	"taint",
+	"check-wrapping",
+	"idle-loops",
+	"pipe",
+	"link",
+	"expect-output",
+	"input-script",
+	"break",
+];
+
+/// Number of times the `tiered` engine lets a loop run interpreted before compiling it.
+const HOT_LOOP_THRESHOLD: u64 = 16;
+
+/// Share of executed ops a superinstruction must save to be picked by `--fuse-from` and `pgo`.
+pub const MIN_FUSION_SHARE: f64 = 0.01;
+
+/// Mebibytes of memory an `--isolate`d program may use, unless told otherwise.
+const DEFAULT_MEMORY_LIMIT: u64 = 1024;
+
+/// Exit code of `--link` when the programs deadlock.
+const DEADLOCK_EXIT_CODE: u8 = 3;
+
+/// Run the program on the input recorded in a trace and pick the superinstructions that pay off.
+/// Number of events kept for `--crash-dump`.
+const HISTORY_LENGTH: usize = 64;
+
+/// Save a dump of a run that was aborted with `error` for `--crash-dump`, unless it merely failed
+/// to read or write.
+fn save_crash(
+	path: &Path,
+	history: History<'_>,
+	error: &RunError,
+	bf: &Engine,
+	settings: &RuntimeSettings,
+) -> Result<()> {
+	if matches!(error, RunError::Io { .. }) {
+		return Ok(());
+	}
+
+	fs::write(path, history.dump(error, bf, settings).to_json())?;
+	eprintln!("crash dump saved to {}", path.display());
+
+	Ok(())
+}
+
+/// Add where the program stopped, as `error` tells, in the source code if it ran on the tree
+/// engine. The cells around the pointer and what ran right before are added as sections.
+fn locate(error: RunError, program: &Program, backend: Backend) -> Report {
+	let Some(location) = error.location().cloned() else {
+		return Report::new(error);
+	};
+
+	let recent: Vec<String> = if backend == Backend::Tree {
+		location
+			.recent_instructions(program)
+			.into_iter()
+			.map(|(span, symbol)| match span {
+				Some(span) => format!("`{symbol}` at {span}"),
+				None => format!("`{symbol}`"),
+			})
+			.collect()
+	} else {
+		location
+			.recent
+			.iter()
+			.map(|op| format!("op {op}"))
+			.collect()
+	};
+	let mut report = Report::new(error).section(location.cells.to_string());
+	if !recent.is_empty() {
+		report = report.section(format!("ran right before: {}", recent.join(", ")));
+	}
+
+	if backend != Backend::Tree {
+		return report.wrap_err(format!("stopped at op {}", location.index));
+	}
+	let Some(span) = location.span(program) else {
+		return report;
+	};
+
+	let loops: Vec<String> = location
+		.loop_spans(program)
+		.iter()
+		.map(ToString::to_string)
+		.collect();
+	if loops.is_empty() {
+		report.wrap_err(format!("stopped at {span}"))
+	} else {
+		report.wrap_err(format!(
+			"stopped at {span}, in the loops at {}",
+			loops.join(" > ")
+		))
+	}
+}
+
+/// Hooks that tell where the program got stuck, see [`IdleLoops`], while keeping a history for
+/// `--crash-dump` if one was asked for.
+struct IdleLoopReport<'a> {
+	program: &'a Program,
+	history: Option<History<'a>>,
+}
+
+impl Hooks for IdleLoopReport<'_> {
+	const WATCHES_WRAPS: bool = false;
+
+	fn before_instruction(&mut self, step: u64, instruction: &Instruction, bf: EngineView<'_>) {
+		if let Some(history) = &mut self.history {
+			history.before_instruction(step, instruction, bf);
+		}
+	}
+
+	fn on_input(&mut self, step: u64, byte: u8) {
+		if let Some(history) = &mut self.history {
+			history.on_input(step, byte);
+		}
+	}
+
+	fn on_output(&mut self, step: u64, byte: u8) {
+		if let Some(history) = &mut self.history {
+			history.on_output(step, byte);
+		}
+	}
+
+	fn on_idle_loop(&mut self, step: u64, loop_instruction: &Instruction) {
+		let location = self
+			.program
+			.index_of(loop_instruction)
+			.and_then(|index| self.program.span(index))
+			.map_or_else(|| "?".to_owned(), |span| span.to_string());
+
+		eprintln!("step {step}: the loop at {location} never ends");
+	}
+}
+
+/// Encoding chosen with the given argument.
+fn encoding(matches: &ArgMatches, id: &str) -> Encoding {
+	matches
+		.get_one::<String>(id)
+		.unwrap()
+		.parse()
+		.expect("clap only accepts known encodings")
+}
+
+fn profile_fusions(
+	program: &Program,
+	settings: &RuntimeSettings,
+	tape_length: usize,
+	trace_path: &Path,
+) -> Result<Vec<Fusion>> {
+	let trace: Trace = fs::read_to_string(trace_path)?.parse()?;
+	let Some(input) = trace.input() else {
+		bail!("can't profile with a trace whose input is redacted");
+	};
+
+	let (bytecode, profile) = profile(program, settings, tape_length, &input)?;
+
+	Ok(profile.hot_fusions(&bytecode, MIN_FUSION_SHARE))
+}
+
+/// Run the program on `input`, counting how often every op of its bytecode runs before
+/// superinstructions are fused, until it ends or exhausts the budget of `settings`.
+pub fn profile(
+	program: &Program,
+	settings: &RuntimeSettings,
+	tape_length: usize,
+	mut input: &[u8],
+) -> Result<(Bytecode, Profile)> {
+	let bytecode = Bytecode::compile(&program.instructions)
+		.collapse()
+		.multiply_loops()
+		.count_loops()
+		.address_offsets();
+	let mut profile = Profile::new();
+
+	let mut bf = Engine::new(tape_length);
+
+	match bf.run_bytecode_with(
+		&bytecode,
+		&mut input,
+		&mut io::sink(),
+		settings.clone(),
+		&mut StdHost::default(),
+		&mut profile,
+	) {
+		Ok(_) | Err(RunError::BudgetExhausted { .. }) => {}
+		Err(e) => return Err(e.into()),
+	}
+
+	Ok((bytecode, profile))
+}
+
+pub const DEFAULT_TAPE_LENGTH: usize = 30_000;
+
+#[derive(Debug, Clone, Copy)]
+enum TapeLength {
+	Fixed(usize),
+	Auto,
+}
+
+fn parse_tape_length(length: &str) -> Result<TapeLength, String> {
+	if length == "auto" {
+		return Ok(TapeLength::Auto);
+	}
+
+	match length.parse() {
+		Ok(0) => Err("tape must have at least one cell".to_owned()),
+		Ok(length) => Ok(TapeLength::Fixed(length)),
+		Err(e) => Err(format!("expected a number or `auto`: {e}")),
+	}
+}
+
+fn parse_redaction(mode: &str) -> Result<Redaction, String> {
+	match mode.split_once(':') {
+		None if mode == "none" => Ok(Redaction::None),
+		None if mode == "hash" => Ok(Redaction::Hash),
+		Some(("truncate", keep)) => keep
+			.parse()
+			.map(Redaction::Truncate)
+			.map_err(|e| format!("invalid number of bytes to keep: {e}")),
+		_ => Err("expected `none`, `hash` or `truncate:N`".to_owned()),
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::commands::command;
+	use clap::error::ErrorKind;
+
+	#[test]
+	fn jit_engine_needs_the_feature() {
+		let parsed = "jit".parse::<Backend>();
+
+		if cfg!(feature = "jit") {
+			assert_eq!(Ok(Backend::Jit), parsed);
+		} else {
+			assert!(parsed.unwrap_err().contains("`jit` feature"));
+		}
+		assert!("turbo".parse::<Backend>().is_err());
+	}
+
+	#[test]
+	fn release_builds_let_panics_unwind() {
+		// NOTE: tests always unwind, whatever the release profile says, so it's read instead
+		let manifest = include_str!(concat!(env!("CARGO_MANIFEST_DIR"), "/Cargo.toml"));
+		let (_, release) = manifest.split_once("[profile.release]").unwrap();
+		let strategy = release
+			.lines()
+			.take_while(|line| !line.starts_with('['))
+			.find_map(|line| line.strip_prefix("panic"))
+			.map(|value| value.trim_start_matches([' ', '=']).trim());
+
+		assert!(
+			matches!(strategy, None | Some("\"unwind\"")),
+			"--contain-panics can't catch panics that abort"
+		);
+	}
+
+	#[test]
+	fn isolated_runs_refuse_to_write_files() {
+		let parse = |args: &[&str]| {
+			command()
+				.try_get_matches_from([&["brainfuck-rs", "run", "--isolate"], args].concat())
+				.map_err(|e| e.kind())
+		};
+
+		assert!(parse(&["hello.b"]).is_ok());
+		for args in [
+			&["--record", "run.trace", "hello.b"][..],
+			&["--transcript", "run.html", "hello.b"],
+			&["--crash-dump", "run.dump", "hello.b"],
+			&["--engine", "fused", "--cache", "hello.b"],
+		] {
+			assert_eq!(Err(ErrorKind::ArgumentConflict), parse(args), "{args:?}");
+		}
+	}
+
+	#[test]
+	fn deeply_nested_programs_run_on_every_engine() {
+		const DEPTH: usize = 20_000;
+
+		let path =
+			std::env::temp_dir().join(format!("brainfuck-rs-nested-{}.b", std::process::id()));
+		fs::write(
+			&path,
+			format!("+{}-{}", "[".repeat(DEPTH), "]".repeat(DEPTH)),
+		)
+		.unwrap();
+
+		// NOTE: the jit engine runs as deeply nested programs too, but compiling them takes minutes
+		// in a debug build, since it takes time quadratic in how deeply loops are nested
+		for &backend in Backend::ALL
+			.iter()
+			.filter(|&&backend| backend != Backend::Jit)
+		{
+			let path = path.clone();
+			let run_nested = move || {
+				let matches = command()
+					.try_get_matches_from([
+						"brainfuck-rs",
+						"run",
+						"--engine",
+						backend.name(),
+						path.to_str().unwrap(),
+					])
+					.unwrap();
+				let matches = matches.subcommand_matches("run").unwrap();
+
+				let program = load(matches, &path).unwrap();
+				let (tape_length, grow_tape) = tape_length(matches, &program);
+				let mut bf = Engine::new(tape_length);
+				run(
+					matches,
+					&program,
+					&mut bf,
+					settings(matches, grow_tape),
+					tape_length,
+					&mut [].as_slice(),
+					&mut vec![],
+				)
+				.unwrap();
+
+				bf.tape[0].0
+			};
+
+			// NOTE: programs run on the main thread, which has more stack than test threads do
+			let cell = std::thread::Builder::new()
+				.stack_size(8 * 1024 * 1024)
+				.spawn(run_nested)
+				.unwrap()
+				.join()
+				.unwrap();
+			assert_eq!(0, cell, "{}", backend.name());
+		}
+
+		fs::remove_file(path).unwrap();
+	}
+}
@@ -0,0 +1,103 @@
+use super::{extensions, extensions_arg};
+use brainfuck_rs::{
+	engine::{Engine, RuntimeSettings},
+	program::Program,
+	search::{self, SearchSettings},
+	utils::StripShebang,
+};
+use clap::{value_parser, Arg, ArgMatches};
+use color_eyre::eyre::{bail, Result};
+use fs_err as fs;
+use std::path::PathBuf;
+
+pub fn args() -> Vec<Arg> {
+	vec![
+		Arg::new("input")
+			.required(true)
+			.value_name("FILE")
+			.help("Brainfuck program to find input for")
+			.value_parser(value_parser!(PathBuf)),
+		Arg::new("target-output")
+			.long("target-output")
+			.required(true)
+			.value_name("TEXT")
+			.help("Exact output the program should print"),
+		Arg::new("alphabet")
+			.long("alphabet")
+			.value_name("CHARS")
+			.help("Characters the input is made of [default: printable ASCII and line feed]"),
+		Arg::new("max-length")
+			.long("max-length")
+			.value_name("BYTES")
+			.help("Length of the longest input that is tried")
+			.value_parser(value_parser!(usize))
+			.default_value("8"),
+		Arg::new("max-steps")
+			.long("max-steps")
+			.value_name("INSTRUCTIONS")
+			.help("Give up on an input after the program executes this many instructions with it")
+			.value_parser(value_parser!(u64))
+			.default_value("1000000"),
+		Arg::new("max-runs")
+			.long("max-runs")
+			.value_name("RUNS")
+			.help("Give up on the search after running the program this many times")
+			.value_parser(value_parser!(u64))
+			.default_value("100000"),
+		Arg::new("quit-on-eof")
+			.short('q')
+			.long("quit-on-eof")
+			.value_name("BOOL")
+			.help("Quit when the program reads past the end of the input")
+			.value_parser(value_parser!(bool))
+			.default_value("true"),
+		extensions_arg(),
+	]
+}
+
+pub fn execute(matches: &ArgMatches) -> Result<()> {
+	let input_file_path = matches.get_one::<PathBuf>("input").unwrap();
+	let target = matches.get_one::<String>("target-output").unwrap();
+
+	let code = fs::read_to_string(input_file_path)?;
+	let program = Program::parse_with_extensions(code.strip_shebang(), &extensions(matches)?)?;
+
+	let search_settings = SearchSettings {
+		alphabet: matches.get_one::<String>("alphabet").map_or_else(
+			|| SearchSettings::default().alphabet,
+			|chars| chars.clone().into_bytes(),
+		),
+		max_length: *matches.get_one("max-length").unwrap(),
+		max_steps: *matches.get_one("max-steps").unwrap(),
+		max_runs: *matches.get_one("max-runs").unwrap(),
+	};
+	let settings = RuntimeSettings {
+		quit_on_eof: *matches.get_one("quit-on-eof").unwrap(),
+		..Default::default()
+	};
+
+	let search = search::solve(
+		&Engine::default(),
+		&program.instructions,
+		target.as_bytes(),
+		settings,
+		&search_settings,
+	)?;
+
+	match search.found {
+		Some(input) => {
+			eprintln!("found after {} runs:", search.runs);
+			println!("{}", input.escape_ascii());
+		}
+		None if search.complete => bail!(
+			"no input up to {} bytes makes the program print the target",
+			search_settings.max_length
+		),
+		None => bail!(
+			"gave up after {} runs without finding the input, try raising --max-runs",
+			search.runs
+		),
+	}
+
+	Ok(())
+}
@@ -0,0 +1,71 @@
+use brainfuck_rs::{analysis, program::Program, utils::StripShebang};
+use clap::{value_parser, Arg, ArgMatches};
+use color_eyre::eyre::Result;
+use fs_err as fs;
+use std::path::PathBuf;
+
+use super::{extensions, extensions_arg};
+
+pub fn args() -> Vec<Arg> {
+	vec![
+		Arg::new("input")
+			.required(true)
+			.value_name("FILE")
+			.help("Brainfuck program to analyze")
+			.value_parser(value_parser!(PathBuf)),
+		extensions_arg(),
+	]
+}
+
+pub fn execute(matches: &ArgMatches) -> Result<()> {
+	let input_file_path = matches.get_one::<PathBuf>("input").unwrap();
+
+	let code = fs::read_to_string(input_file_path)?;
+	let program = Program::parse_with_extensions(code.strip_shebang(), &extensions(matches)?)?;
+
+	let stats = analysis::stats(&program.instructions);
+	let capabilities = program.required_capabilities();
+
+	println!("instructions:   {}", stats.instructions);
+	println!("loops:          {}", stats.loops);
+	println!("max loop depth: {}", stats.max_depth);
+
+	match stats.pointer_bounds {
+		Some(bounds) => println!(
+			"tape length:    {} cells (pointer stays within {}..={} of the start)",
+			bounds.tape_length(),
+			bounds.min,
+			bounds.max
+		),
+		None => println!("tape length:    unbounded (pointer movement depends on data)"),
+	}
+
+	// NOTE: programs whose cells never wrap around run the same with cells of any size
+	let may_wrap = analysis::value_ranges(&program).may_wrap;
+	match may_wrap.first() {
+		None => println!("cell size:      any (no cell ever wraps around)"),
+		Some(&index) => {
+			let location = program
+				.span(index)
+				.map_or_else(String::new, |span| format!(" at {span}"));
+			let others = match may_wrap.len() - 1 {
+				0 => String::new(),
+				1 => " and 1 other".to_owned(),
+				count => format!(" and {count} others"),
+			};
+			println!("cell size:      8 bits (may wrap around{location}{others})");
+		}
+	}
+
+	if capabilities.is_empty() {
+		println!("capabilities:   none");
+	} else {
+		let names: Vec<&str> = capabilities
+			.iter()
+			.map(|capability| capability.name())
+			.collect();
+		println!("capabilities:   {}", names.join(", "));
+	}
+
+	Ok(())
+}
@@ -0,0 +1,174 @@
+use brainfuck_rs::{extension::Extension, semantics::Profile};
+use clap::{Arg, ArgAction, ArgMatches};
+use color_eyre::eyre::Result;
+use serde::Serialize;
+use std::collections::BTreeMap;
+
+use super::{
+	daemon,
+	run::{self, Backend, FORMATS},
+};
+use crate::sandbox;
+
+/// Cargo features of the crate, and whether this build has them enabled.
+const FEATURES: &[(&str, bool)] = &[
+	("monitoring", cfg!(feature = "monitoring")),
+	("gui", cfg!(feature = "gui")),
+	("jit", cfg!(feature = "jit")),
+	("aot", cfg!(feature = "aot")),
+	("plugins", cfg!(feature = "plugins")),
+];
+
+/// Everything tools may want to know about this build before running it, see [`capabilities`].
+#[derive(Debug, Serialize)]
+struct Capabilities {
+	name: &'static str,
+	version: &'static str,
+	features: BTreeMap<&'static str, bool>,
+	/// Nonstandard instructions programs can opt into.
+	extensions: Vec<ExtensionInfo>,
+	/// Formats programs can be loaded from, see `run --from`.
+	formats: Vec<&'static str>,
+	/// Engines that can run programs, see `run --engine`.
+	engines: Vec<&'static str>,
+	/// Interpreters whose semantics can be emulated, see `run --emulate`.
+	emulations: Vec<&'static str>,
+	/// Whether `run --isolate` works on this platform.
+	isolation: bool,
+	/// Whether `daemon` works on this platform.
+	daemon: bool,
+	semantics: Semantics,
+}
+
+#[derive(Debug, Serialize)]
+struct ExtensionInfo {
+	name: &'static str,
+	symbol: char,
+	capability: Option<&'static str>,
+	summary: &'static str,
+}
+
+/// How programs run unless told otherwise.
+#[derive(Debug, Serialize)]
+struct Semantics {
+	cell_bits: u32,
+	/// Whether cells wrap around between 0 and 255, rather than failing.
+	cell_wrapping: bool,
+	/// Whether the pointer wraps around the ends of the tape, rather than failing.
+	pointer_wrapping: bool,
+	/// What a read leaves in the cell at the end of the input.
+	eof_cell: u8,
+	tape_length: String,
+	quit_on_eof: bool,
+	newlines: String,
+}
+
+pub fn args() -> Vec<Arg> {
+	vec![Arg::new("json")
+		.long("json")
+		.help("Print everything this build supports as JSON, for editors and judges to detect it")
+		.action(ArgAction::SetTrue)]
+}
+
+pub fn execute(matches: &ArgMatches) -> Result<()> {
+	let capabilities = capabilities();
+
+	if matches.get_flag("json") {
+		println!("{}", serde_json::to_string_pretty(&capabilities)?);
+		return Ok(());
+	}
+
+	println!("{} {}", capabilities.name, capabilities.version);
+	let features: Vec<&str> = capabilities
+		.features
+		.iter()
+		.filter(|(_, &enabled)| enabled)
+		.map(|(&name, _)| name)
+		.collect();
+	println!("features:   {}", features.join(", "));
+	let extensions: Vec<String> = capabilities
+		.extensions
+		.iter()
+		.map(|extension| format!("{} (`{}`)", extension.name, extension.symbol))
+		.collect();
+	println!("extensions: {}", extensions.join(", "));
+	println!("formats:    {}", capabilities.formats.join(", "));
+	println!("engines:    {}", capabilities.engines.join(", "));
+	println!("emulations: {}", capabilities.emulations.join(", "));
+	println!(
+		"isolation:  {}",
+		if capabilities.isolation {
+			"supported"
+		} else {
+			"unsupported"
+		}
+	);
+	println!(
+		"daemon:     {}",
+		if capabilities.daemon {
+			"supported"
+		} else {
+			"unsupported"
+		}
+	);
+
+	Ok(())
+}
+
+/// What this build supports, gathered from where each part of it is defined, so the report can't
+/// fall behind.
+fn capabilities() -> Capabilities {
+	Capabilities {
+		name: env!("CARGO_PKG_NAME"),
+		version: env!("CARGO_PKG_VERSION"),
+		features: FEATURES.iter().copied().collect(),
+		extensions: Extension::ALL
+			.iter()
+			.filter_map(|extension| extension.definition())
+			.map(|definition| ExtensionInfo {
+				name: definition.name,
+				symbol: definition.symbol,
+				capability: definition.capability.map(|capability| capability.name()),
+				summary: definition.summary,
+			})
+			.collect(),
+		formats: FORMATS.to_vec(),
+		engines: Backend::ALL.iter().map(|backend| backend.name()).collect(),
+		emulations: Profile::ALL.iter().map(|profile| profile.name).collect(),
+		isolation: sandbox::SUPPORTED,
+		daemon: daemon::SUPPORTED,
+		semantics: Semantics {
+			cell_bits: u8::BITS,
+			cell_wrapping: true,
+			pointer_wrapping: true,
+			eof_cell: 0,
+			tape_length: run_default("tape-length"),
+			quit_on_eof: run_default("quit-on-eof") == "true",
+			newlines: run_default("newlines"),
+		},
+	}
+}
+
+/// Default value of an argument of `run`.
+fn run_default(id: &str) -> String {
+	run::args()
+		.into_iter()
+		.find(|arg| arg.get_id() == id)
+		.and_then(|arg| arg.get_default_values().first().cloned())
+		.map(|value| value.to_string_lossy().into_owned())
+		.expect("the argument exists and has a default")
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn defaults_come_from_run() {
+		let capabilities = capabilities();
+
+		assert_eq!("30000", capabilities.semantics.tape_length);
+		assert!(capabilities.semantics.quit_on_eof);
+		assert_eq!("passthrough", capabilities.semantics.newlines);
+	}
+}
@@ -0,0 +1,214 @@
+//! Standard streams of the process, and the platform specifics of consoles.
+//!
+//! Brainfuck programs deal in bytes, not text. The standard library's streams pass bytes through
+//! untouched when they're pipes or files, but on Windows a console only accepts valid UTF-8 and
+//! hands input over after converting it from UTF-16. Raw streams talk to the underlying handle
+//! directly, so every byte reaches the console as is, and input arrives in the console's code
+//! page.
+
+use std::{
+	fs::File,
+	io::{self, IsTerminal, Read, Write},
+};
+
+use color_eyre::{
+	config::{HookBuilder, Theme},
+	eyre::Result,
+};
+use crossterm::terminal;
+
+/// Key that ends console input when it isn't handled by the platform: Ctrl-Z on Windows, Ctrl-D
+/// elsewhere.
+const EOF_KEY: u8 = if cfg!(windows) { 0x1A } else { 0x04 };
+
+/// Ctrl-C, which doesn't interrupt the process in keypress mode, but arrives as input.
+const INTERRUPT_KEY: u8 = 0x03;
+
+/// Install the error report handler, with colors only if the console can show them.
+pub fn install_error_reporting() -> Result<()> {
+	if supports_color() {
+		color_eyre::install()
+	} else {
+		HookBuilder::new().theme(Theme::new()).install()
+	}
+}
+
+#[cfg(windows)]
+fn supports_color() -> bool {
+	// NOTE: this also turns on ANSI escape sequences in consoles that support them, but don't
+	// have them enabled by default
+	crossterm::ansi_support::supports_ansi()
+}
+
+#[cfg(not(windows))]
+fn supports_color() -> bool {
+	true
+}
+
+/// Standard input, raw if requested.
+///
+/// In keypress mode, or when a raw Windows console is read, [`EOF_KEY`] ends the input, since the
+/// platform doesn't do it for us.
+pub fn stdin(raw: bool, keypress: bool) -> io::Result<Box<dyn Read + Send>> {
+	let console = io::stdin().is_terminal();
+
+	let stdin: Box<dyn Read + Send> = if raw {
+		Box::new(raw_handle(&io::stdin())?)
+	} else {
+		Box::new(io::stdin())
+	};
+
+	if console && (keypress || (raw && cfg!(windows))) {
+		Ok(Box::new(ConsoleInput {
+			inner: stdin,
+			keypress,
+		}))
+	} else {
+		Ok(stdin)
+	}
+}
+
+/// Standard output, raw if requested. Raw output isn't buffered by the standard library, which
+/// is fine, since the engine writes unflushed output in chunks anyway.
+pub fn stdout(raw: bool, keypress: bool) -> io::Result<Box<dyn Write>> {
+	let console = io::stdout().is_terminal();
+
+	let stdout: Box<dyn Write> = if raw {
+		Box::new(raw_handle(&io::stdout())?)
+	} else {
+		Box::new(io::stdout())
+	};
+
+	// NOTE: the terminal no longer turns line feeds into new lines in keypress mode
+	if console && keypress {
+		Ok(Box::new(CrLf(stdout)))
+	} else {
+		Ok(stdout)
+	}
+}
+
+#[cfg(unix)]
+fn raw_handle(stream: &impl std::os::fd::AsFd) -> io::Result<File> {
+	Ok(File::from(stream.as_fd().try_clone_to_owned()?))
+}
+
+#[cfg(windows)]
+fn raw_handle(stream: &impl std::os::windows::io::AsHandle) -> io::Result<File> {
+	Ok(File::from(stream.as_handle().try_clone_to_owned()?))
+}
+
+/// Console switched into a mode where every key press reaches the program right away, without
+/// waiting for Enter or being echoed. The console is switched back when this is dropped.
+pub struct KeypressMode(());
+
+impl KeypressMode {
+	/// Switch the console into keypress mode, if the input comes from one.
+	pub fn enable() -> io::Result<Option<Self>> {
+		if !io::stdin().is_terminal() {
+			return Ok(None);
+		}
+
+		terminal::enable_raw_mode()?;
+
+		Ok(Some(Self(())))
+	}
+}
+
+impl Drop for KeypressMode {
+	fn drop(&mut self) {
+		let _ = terminal::disable_raw_mode();
+	}
+}
+
+/// Console input that ends at [`EOF_KEY`]. In keypress mode, Enter is read as a line feed and
+/// Ctrl-C stops the program.
+struct ConsoleInput<R> {
+	inner: R,
+	keypress: bool,
+}
+
+impl<R: Read> Read for ConsoleInput<R> {
+	fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+		let read = self.inner.read(buf)?;
+		let input = &mut buf[..read];
+
+		if self.keypress && input.contains(&INTERRUPT_KEY) {
+			return Err(io::Error::other("interrupted by Ctrl-C"));
+		}
+
+		let end = input
+			.iter()
+			.position(|&byte| byte == EOF_KEY)
+			.unwrap_or(read);
+
+		if self.keypress {
+			for byte in &mut input[..end] {
+				if *byte == b'\r' {
+					*byte = b'\n';
+				}
+			}
+		}
+
+		Ok(end)
+	}
+}
+
+/// Output that turns every line feed into a carriage return and a line feed.
+struct CrLf<W>(W);
+
+impl<W: Write> Write for CrLf<W> {
+	fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+		for (index, line) in buf.split(|&byte| byte == b'\n').enumerate() {
+			if index > 0 {
+				self.0.write_all(b"\r\n")?;
+			}
+
+			self.0.write_all(line)?;
+		}
+
+		Ok(buf.len())
+	}
+
+	fn flush(&mut self) -> io::Result<()> {
+		self.0.flush()
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn console_input() {
+		let mut input = ConsoleInput {
+			inner: [b'a', b'\r', EOF_KEY, b'b'].as_slice(),
+			keypress: true,
+		};
+
+		let mut buffer = [0; 8];
+		assert_eq!(2, input.read(&mut buffer).unwrap());
+		assert_eq!(b"a\n", &buffer[..2]);
+
+		let mut input = ConsoleInput {
+			inner: [b'a', INTERRUPT_KEY].as_slice(),
+			keypress: true,
+		};
+
+		assert!(input.read(&mut buffer).is_err());
+
+		let mut input = ConsoleInput {
+			inner: [EOF_KEY, b'\r', b'\n'].as_slice(),
+			keypress: false,
+		};
+
+		assert_eq!(0, input.read(&mut buffer).unwrap());
+	}
+
+	#[test]
+	fn crlf() {
+		let mut output = CrLf(vec![]);
+		output.write_all(b"a\nb\n\nc").unwrap();
+
+		assert_eq!(b"a\r\nb\r\n\r\nc", output.0.as_slice());
+	}
+}
@@ -0,0 +1,964 @@
+use std::{
+	collections::{BTreeMap, BTreeSet, HashMap},
+	slice,
+};
+
+use serde::Serialize;
+
+use crate::{
+	extension::Extension,
+	instruction::Instruction,
+	program::{count_instructions, Program},
+	token::Span,
+};
+
+/// Version of the document exported by [`ValueRanges::to_json`].
+pub const VALUE_RANGES_VERSION: u32 = 1;
+
+/// Number of times the condition of a loop is reached before [`value_ranges`] starts widening
+/// what it knows there.
+const WIDEN_AFTER: usize = 3;
+
+/// Range of cells the pointer can visit, relative to the cell it starts at.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PointerBounds {
+	/// Leftmost reachable offset, zero or negative.
+	pub min: isize,
+	/// Rightmost reachable offset, zero or positive.
+	pub max: isize,
+}
+
+impl PointerBounds {
+	/// Shortest tape that fits every reachable cell without them overlapping. Offsets to the left
+	/// of the starting cell wrap around to the end of the tape.
+	pub const fn tape_length(self) -> usize {
+		self.max.abs_diff(self.min) + 1
+	}
+}
+
+/// Statically bound the pointer's movement.
+///
+/// Returns [`None`] if there is a loop whose body doesn't return the pointer to where it started,
+/// because then the movement depends on the data (e.g. `[>]`), which may come from input.
+///
+/// # Usage
+///
+/// ```
+/// # use brainfuck_rs::{analysis::{self, PointerBounds}, instruction::Instruction, token::Token};
+/// let balanced = Instruction::parse(Token::tokenize("<+>>[->>+<<]")).unwrap();
+/// let unbalanced = Instruction::parse(Token::tokenize("+[>+]")).unwrap();
+///
+/// assert_eq!(Some(PointerBounds { min: -1, max: 3 }), analysis::pointer_bounds(&balanced));
+/// assert_eq!(None, analysis::pointer_bounds(&unbalanced));
+/// ```
+pub fn pointer_bounds(instructions: &[Instruction]) -> Option<PointerBounds> {
+	fn walk(
+		instructions: &[Instruction],
+		offset: &mut isize,
+		bounds: &mut PointerBounds,
+	) -> Option<()> {
+		for instruction in instructions {
+			match instruction {
+				Instruction::Next => {
+					*offset += 1;
+					bounds.max = bounds.max.max(*offset);
+				}
+				Instruction::Prev => {
+					*offset -= 1;
+					bounds.min = bounds.min.min(*offset);
+				}
+				Instruction::Move(distance) => {
+					*offset += *distance as isize;
+					bounds.min = bounds.min.min(*offset);
+					bounds.max = bounds.max.max(*offset);
+				}
+				Instruction::Loop(inner) => {
+					let entry = *offset;
+
+					walk(inner, offset, bounds)?;
+
+					// NOTE: a balanced body ends where it started, so every iteration visits the
+					// same cells
+					if *offset != entry {
+						return None;
+					}
+				}
+				_ => {}
+			}
+		}
+
+		Some(())
+	}
+
+	let mut bounds = PointerBounds { min: 0, max: 0 };
+	walk(instructions, &mut 0, &mut bounds)?;
+
+	Some(bounds)
+}
+
+/// Bounds of the pointer within the loops of a program, and the whole program, where they're
+/// known, see [`pointer_ranges`].
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct PointerRanges {
+	/// Range of cells the whole program visits, like [`pointer_bounds`], if it's bounded.
+	pub program: Option<PointerBounds>,
+	/// Range of cells every iteration of a loop visits, relative to the cell its condition
+	/// checks, keyed by the address of the loop.
+	loops: HashMap<*const Instruction, PointerBounds>,
+}
+
+impl PointerRanges {
+	/// Range of cells an iteration of `loop_instruction` visits, relative to the cell its
+	/// condition checks, if its body returns the pointer there.
+	pub fn of_loop(&self, loop_instruction: &Instruction) -> Option<PointerBounds> {
+		self.loops
+			.get(&std::ptr::from_ref(loop_instruction))
+			.copied()
+	}
+}
+
+/// Statically bound the pointer within every loop whose body returns it to where it started,
+/// and within the whole program, so the engine can move it without checking whether it wraps
+/// around, and access cells without checking that they're on the tape, while the bounds fit on
+/// the tape.
+///
+/// Unlike [`pointer_bounds`], a loop that doesn't return the pointer only leaves out the loops
+/// around it. Custom extensions are taken to move the pointer anywhere, since their handlers may.
+///
+/// # Usage
+///
+/// ```
+/// # use brainfuck_rs::{analysis::{self, PointerBounds}, instruction::Instruction, token::Token};
+/// let instructions = Instruction::parse(Token::tokenize("+[<[>]>[->>+<<]<]")).unwrap();
+/// let ranges = analysis::pointer_ranges(&instructions);
+///
+/// let Instruction::Loop(body) = &instructions[1] else { unreachable!() };
+/// assert_eq!(None, ranges.program);
+/// assert_eq!(None, ranges.of_loop(&instructions[1]));
+/// assert_eq!(None, ranges.of_loop(&body[1]));
+/// assert_eq!(Some(PointerBounds { min: 0, max: 2 }), ranges.of_loop(&body[3]));
+/// ```
+pub fn pointer_ranges(instructions: &[Instruction]) -> PointerRanges {
+	pointer_ranges_of(instructions)
+}
+
+/// [`pointer_ranges`] of the instructions at the top level of a program.
+pub(crate) fn pointer_ranges_of<'a>(
+	instructions: impl IntoIterator<Item = &'a Instruction>,
+) -> PointerRanges {
+	/// Where the pointer ends up after the instructions of a sequence walked so far, relative to
+	/// where it started, and the cells it visits on the way, if they're known.
+	struct Walk {
+		offset: isize,
+		bounds: PointerBounds,
+		bounded: bool,
+	}
+
+	impl Walk {
+		const fn new() -> Self {
+			Self {
+				offset: 0,
+				bounds: PointerBounds { min: 0, max: 0 },
+				bounded: true,
+			}
+		}
+
+		fn step(&mut self, instruction: &Instruction) {
+			match instruction {
+				Instruction::Next => self.offset += 1,
+				Instruction::Prev => self.offset -= 1,
+				Instruction::Move(distance) => self.offset += *distance as isize,
+				Instruction::Extension(Extension::Custom(_)) => self.bounded = false,
+				_ => {}
+			}
+
+			self.visit();
+		}
+
+		/// Step over `loop_instruction`, whose body was walked as `body`.
+		fn step_over_loop(
+			&mut self,
+			loop_instruction: &Instruction,
+			body: Self,
+			loops: &mut HashMap<*const Instruction, PointerBounds>,
+		) {
+			match body.finish() {
+				Some((0, inner)) => {
+					loops.insert(loop_instruction, inner);
+					self.bounds.min = self.bounds.min.min(self.offset + inner.min);
+					self.bounds.max = self.bounds.max.max(self.offset + inner.max);
+				}
+				// NOTE: the loops inside are still walked, even once this is unbounded
+				_ => self.bounded = false,
+			}
+
+			self.visit();
+		}
+
+		fn visit(&mut self) {
+			self.bounds.min = self.bounds.min.min(self.offset);
+			self.bounds.max = self.bounds.max.max(self.offset);
+		}
+
+		fn finish(self) -> Option<(isize, PointerBounds)> {
+			self.bounded.then_some((self.offset, self.bounds))
+		}
+	}
+
+	let mut loops = HashMap::new();
+	let mut program = Walk::new();
+	// NOTE: the loops whose bodies are being walked, innermost last, so that deeply nested loops
+	// can't overflow the stack
+	let mut open: Vec<(&Instruction, slice::Iter<'_, Instruction>, Walk)> = vec![];
+
+	for instruction in instructions {
+		let Instruction::Loop(body) = instruction else {
+			program.step(instruction);
+			continue;
+		};
+
+		open.push((instruction, body.iter(), Walk::new()));
+		while let Some((loop_instruction, mut body, mut walk)) = open.pop() {
+			match body.next() {
+				Some(inner @ Instruction::Loop(inner_body)) => {
+					open.push((loop_instruction, body, walk));
+					open.push((inner, inner_body.iter(), Walk::new()));
+				}
+				Some(inner) => {
+					walk.step(inner);
+					open.push((loop_instruction, body, walk));
+				}
+				None => open
+					.last_mut()
+					.map_or(&mut program, |(_, _, outer)| outer)
+					.step_over_loop(loop_instruction, walk, &mut loops),
+			}
+		}
+	}
+
+	PointerRanges {
+		program: program.finish().map(|(_, bounds)| bounds),
+		loops,
+	}
+}
+
+/// Net change of every cell after running the instructions once, keyed by offset from the cell
+/// the pointer starts at. Cells that don't change are left out.
+///
+/// Returns [`None`] unless the instructions do nothing but arithmetic and pointer movement that
+/// ends where it started, like the body of `[->+<]`.
+///
+/// # Usage
+///
+/// ```
+/// # use std::collections::BTreeMap;
+/// # use brainfuck_rs::{analysis, instruction::Instruction, token::Token};
+/// let body = Instruction::parse(Token::tokenize("->++<")).unwrap();
+///
+/// assert_eq!(Some(BTreeMap::from([(0, 255), (1, 2)])), analysis::cell_deltas(&body));
+/// ```
+pub fn cell_deltas(instructions: &[Instruction]) -> Option<BTreeMap<isize, u8>> {
+	let mut deltas: BTreeMap<isize, u8> = BTreeMap::new();
+	let mut offset = 0;
+
+	for instruction in instructions {
+		match instruction {
+			Instruction::Inc => {
+				let delta = deltas.entry(offset).or_default();
+				*delta = delta.wrapping_add(1);
+			}
+			Instruction::Dec => {
+				let delta = deltas.entry(offset).or_default();
+				*delta = delta.wrapping_sub(1);
+			}
+			Instruction::Add(amount) => {
+				let delta = deltas.entry(offset).or_default();
+				*delta = delta.wrapping_add_signed(*amount);
+			}
+			Instruction::Next => offset += 1,
+			Instruction::Prev => offset -= 1,
+			Instruction::Move(distance) => offset += *distance as isize,
+			Instruction::Print
+			| Instruction::Read
+			| Instruction::Loop(_)
+			| Instruction::Clear
+			| Instruction::Extension(_) => return None,
+		}
+	}
+
+	deltas.retain(|_, delta| *delta != 0);
+
+	(offset == 0).then_some(deltas)
+}
+
+/// Static properties of a program.
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct Stats {
+	/// Number of instructions, including the nested ones.
+	pub instructions: usize,
+	/// Number of loops.
+	pub loops: usize,
+	/// Deepest loop nesting, 0 if there are no loops.
+	pub max_depth: usize,
+	/// See [`pointer_bounds`].
+	pub pointer_bounds: Option<PointerBounds>,
+}
+
+/// Gather static properties of a program.
+pub fn stats(instructions: &[Instruction]) -> Stats {
+	fn walk(instructions: &[Instruction], depth: usize, stats: &mut Stats) {
+		stats.max_depth = stats.max_depth.max(depth);
+
+		for instruction in instructions {
+			stats.instructions += 1;
+
+			if let Instruction::Loop(inner) = instruction {
+				stats.loops += 1;
+				walk(inner, depth + 1, stats);
+			}
+		}
+	}
+
+	let mut stats = Stats {
+		pointer_bounds: pointer_bounds(instructions),
+		..Default::default()
+	};
+	walk(instructions, 0, &mut stats);
+
+	stats
+}
+
+/// Values a cell may hold, from `min` to `max`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+pub struct Range {
+	/// Smallest value.
+	pub min: u8,
+	/// Largest value.
+	pub max: u8,
+}
+
+impl Range {
+	/// Every value, when nothing is known about a cell.
+	pub const FULL: Self = Self {
+		min: 0,
+		max: u8::MAX,
+	};
+	/// Only zero, the value of cells that weren't touched.
+	pub const ZERO: Self = Self { min: 0, max: 0 };
+
+	/// Whether the cell is zero for sure.
+	pub const fn is_zero(self) -> bool {
+		self.max == 0
+	}
+
+	/// Whether the cell is anything but zero for sure.
+	pub const fn is_nonzero(self) -> bool {
+		self.min > 0
+	}
+
+	/// Smallest range with both ranges' values.
+	const fn join(self, other: Self) -> Self {
+		Self {
+			min: if self.min < other.min {
+				self.min
+			} else {
+				other.min
+			},
+			max: if self.max > other.max {
+				self.max
+			} else {
+				other.max
+			},
+		}
+	}
+
+	/// Values after adding `amount`, which wrap around like as many `+` or `-` would.
+	const fn add(self, amount: i8) -> Self {
+		let (min, min_wrapped) = self.min.overflowing_add_signed(amount);
+		let (max, max_wrapped) = self.max.overflowing_add_signed(amount);
+		if min_wrapped == max_wrapped {
+			Self { min, max }
+		} else {
+			Self::FULL
+		}
+	}
+
+	/// Values after `+`, which wrap around past 255.
+	const fn inc(self) -> Self {
+		if self.max < u8::MAX {
+			Self {
+				min: self.min + 1,
+				max: self.max + 1,
+			}
+		} else if self.min == u8::MAX {
+			Self::ZERO
+		} else {
+			Self::FULL
+		}
+	}
+
+	/// Values after `-`, which wrap around past 0.
+	const fn dec(self) -> Self {
+		if self.min > 0 {
+			Self {
+				min: self.min - 1,
+				max: self.max - 1,
+			}
+		} else if self.max == 0 {
+			Self {
+				min: u8::MAX,
+				max: u8::MAX,
+			}
+		} else {
+			Self::FULL
+		}
+	}
+}
+
+/// What's known about the tape at some point of a program, see [`value_ranges`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Cells {
+	/// Offset of the pointer from the cell it starts at, unless it depends on the data.
+	pub pointer: Option<isize>,
+	/// Ranges of the cells known better than [`Cells::rest`], keyed by offset from the pointer.
+	pub known: BTreeMap<isize, Range>,
+	/// Range of every other cell.
+	pub rest: Range,
+}
+
+impl Cells {
+	/// The tape before the program starts, with every cell zero.
+	const START: Self = Self {
+		pointer: Some(0),
+		known: BTreeMap::new(),
+		rest: Range::ZERO,
+	};
+
+	/// Range of the cell at `offset` from the pointer.
+	pub fn get(&self, offset: isize) -> Range {
+		self.known.get(&offset).copied().unwrap_or(self.rest)
+	}
+
+	/// Range of the current cell.
+	pub fn current(&self) -> Range {
+		self.get(0)
+	}
+
+	fn set(&mut self, offset: isize, range: Range) {
+		if range == self.rest {
+			self.known.remove(&offset);
+		} else {
+			self.known.insert(offset, range);
+		}
+	}
+
+	/// Move the pointer by `offset`.
+	fn shift(&mut self, offset: isize) {
+		self.pointer = self.pointer.map(|pointer| pointer + offset);
+		self.known = std::mem::take(&mut self.known)
+			.into_iter()
+			.map(|(key, range)| (key - offset, range))
+			.collect();
+	}
+
+	/// The tape if the current cell is zero for sure, or [`None`] if it can't be.
+	fn zero(&self) -> Option<Self> {
+		let current = self.current();
+		if current.is_nonzero() {
+			return None;
+		}
+
+		let mut cells = self.clone();
+		cells.set(0, Range::ZERO);
+		Some(cells)
+	}
+
+	/// The tape if the current cell is anything but zero for sure, or [`None`] if it can't be.
+	fn nonzero(&self) -> Option<Self> {
+		let current = self.current();
+		if current.is_zero() {
+			return None;
+		}
+
+		let mut cells = self.clone();
+		cells.set(
+			0,
+			Range {
+				min: current.min.max(1),
+				..current
+			},
+		);
+		Some(cells)
+	}
+
+	/// Smallest description of both tapes.
+	fn join(&self, other: &Self) -> Self {
+		let mut cells = Self {
+			pointer: self
+				.pointer
+				.filter(|&pointer| other.pointer == Some(pointer)),
+			known: BTreeMap::new(),
+			rest: self.rest.join(other.rest),
+		};
+		for &offset in self.known.keys().chain(other.known.keys()) {
+			cells.set(offset, self.get(offset).join(other.get(offset)));
+		}
+
+		cells
+	}
+
+	/// Give up on what keeps changing between `self` and `next`, a later description of the same
+	/// point, so loops settle after a few iterations.
+	fn widen(&self, next: &Self) -> Self {
+		let grown = |previous: Range, range: Range| Range {
+			min: if range.min < previous.min {
+				0
+			} else {
+				previous.min
+			},
+			max: if range.max > previous.max {
+				u8::MAX
+			} else {
+				previous.max
+			},
+		};
+		// NOTE: cells only the later description knows about are given up on as well, otherwise
+		// loops that move the pointer would find new ones forever
+		let rest = if next
+			.known
+			.keys()
+			.all(|offset| self.known.contains_key(offset))
+		{
+			grown(self.rest, next.rest)
+		} else {
+			Range::FULL
+		};
+
+		let mut cells = Self {
+			pointer: next.pointer,
+			known: BTreeMap::new(),
+			rest,
+		};
+		for (&offset, &range) in &self.known {
+			cells.set(offset, grown(range, next.get(offset)));
+		}
+
+		cells
+	}
+
+	/// Largest distance between two cells told apart, the pointer's included.
+	fn spread(&self) -> usize {
+		let min = self.known.keys().next().map_or(0, |&offset| offset.min(0));
+		let max = self
+			.known
+			.keys()
+			.next_back()
+			.map_or(0, |&offset| offset.max(0));
+
+		max.abs_diff(min)
+	}
+}
+
+/// Ranges of values cells may hold at every point of a program, see [`value_ranges`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ValueRanges {
+	/// The tape right before every instruction, by pre-order index, or [`None`] if the
+	/// instruction is never reached. For loops that's whenever their condition is checked.
+	pub before: Vec<Option<Cells>>,
+	/// The tape once the program ends, or [`None`] if it never does.
+	pub end: Option<Cells>,
+	/// Largest distance between two cells the analysis tells apart. The ranges only hold on tapes
+	/// longer than that, since the pointer wraps around the ends of the tape.
+	pub spread: usize,
+	/// Pre-order indices of the `+` and `-` that may wrap the cell around.
+	pub may_wrap: Vec<usize>,
+	/// Pre-order indices of the loops that are never entered.
+	pub dead_loops: Vec<usize>,
+}
+
+#[derive(Serialize)]
+struct RangesDocument {
+	version: u32,
+	spread: usize,
+	points: Vec<PointNode>,
+	end: Option<CellsNode>,
+	may_wrap: Vec<usize>,
+	dead_loops: Vec<usize>,
+}
+
+#[derive(Serialize)]
+struct PointNode {
+	index: usize,
+	symbol: char,
+	#[serde(skip_serializing_if = "Option::is_none")]
+	span: Option<Span>,
+	before: Option<CellsNode>,
+}
+
+#[derive(Serialize)]
+struct CellsNode {
+	pointer: Option<isize>,
+	/// Whether the current cell is `zero`, `nonzero` or `unknown`.
+	current: &'static str,
+	cells: Vec<CellNode>,
+	rest: Range,
+}
+
+#[derive(Serialize)]
+struct CellNode {
+	offset: isize,
+	min: u8,
+	max: u8,
+}
+
+impl From<&Cells> for CellsNode {
+	fn from(cells: &Cells) -> Self {
+		let current = cells.current();
+
+		Self {
+			pointer: cells.pointer,
+			current: if current.is_zero() {
+				"zero"
+			} else if current.is_nonzero() {
+				"nonzero"
+			} else {
+				"unknown"
+			},
+			cells: cells
+				.known
+				.iter()
+				.map(|(&offset, range)| CellNode {
+					offset,
+					min: range.min,
+					max: range.max,
+				})
+				.collect(),
+			rest: cells.rest,
+		}
+	}
+}
+
+impl ValueRanges {
+	/// Export the ranges of `program`, the one they were computed for, as JSON, for research
+	/// tools.
+	///
+	/// The document has the following shape, with a point for every instruction in pre-order:
+	///
+	/// ```json
+	/// {
+	///   "version": 1,
+	///   "spread": 1,
+	///   "points": [
+	///     {
+	///       "index": 0,
+	///       "symbol": "+",
+	///       "span": { "start": 0, "end": 1, "line": 1, "column": 1 },
+	///       "before": {
+	///         "pointer": 0,
+	///         "current": "zero",
+	///         "cells": [{ "offset": 1, "min": 1, "max": 255 }],
+	///         "rest": { "min": 0, "max": 0 }
+	///       }
+	///     }
+	///   ],
+	///   "end": { ... },
+	///   "may_wrap": [],
+	///   "dead_loops": []
+	/// }
+	/// ```
+	///
+	/// Spans are omitted if the program doesn't have them, and `before` is `null` for
+	/// instructions that are never reached.
+	pub fn to_json(&self, program: &Program) -> String {
+		fn walk(instructions: &[Instruction], symbols: &mut Vec<char>) {
+			for instruction in instructions {
+				symbols.push(instruction.symbol());
+
+				if let Instruction::Loop(body) = instruction {
+					walk(body, symbols);
+				}
+			}
+		}
+
+		let mut symbols = vec![];
+		walk(&program.instructions, &mut symbols);
+
+		let document = RangesDocument {
+			version: VALUE_RANGES_VERSION,
+			spread: self.spread,
+			points: symbols
+				.into_iter()
+				.zip(&self.before)
+				.enumerate()
+				.map(|(index, (symbol, before))| PointNode {
+					index,
+					symbol,
+					span: program.span(index),
+					before: before.as_ref().map(CellsNode::from),
+				})
+				.collect(),
+			end: self.end.as_ref().map(CellsNode::from),
+			may_wrap: self.may_wrap.clone(),
+			dead_loops: self.dead_loops.clone(),
+		};
+
+		serde_json::to_string_pretty(&document).expect("value range serialization never fails")
+	}
+}
+
+/// Compute the ranges of values cells may hold at every point of a program, by abstract
+/// interpretation.
+///
+/// Every cell is described by a [`Range`], an interval of values, and ranges are joined into the
+/// smallest one covering both where paths meet. Cells are tracked by their offset from the
+/// pointer, so what's known survives loops that move the pointer by an unknown amount, like `[>]`.
+/// Loop conditions refine the current cell: it's nonzero in the body and zero after the loop.
+///
+/// The analysis is sound but not precise:
+///
+/// - a range can't have holes, so a cell that's either 0 or 255 may be anything,
+/// - after its condition was reached a few times, the ranges a loop keeps changing are given up
+///   on, so counters of loops are usually unknown after an iteration or two,
+/// - cells written by custom extension instructions, which may do anything, are all unknown,
+/// - the ranges only hold on tapes longer than [`ValueRanges::spread`], since the pointer wraps
+///   around the ends of the tape, making distant cells one and the same.
+///
+/// # Usage
+///
+/// ```
+/// # use brainfuck_rs::{analysis::{self, Range}, program::Program};
+/// let program = Program::parse("++[-]>[+]").unwrap();
+/// let ranges = analysis::value_ranges(&program);
+///
+/// // NOTE: the first loop clears the cell for sure, and the second is never entered
+/// assert_eq!(Some(Range::ZERO), ranges.before[4].as_ref().map(|cells| cells.current()));
+/// assert_eq!(vec![5], ranges.dead_loops);
+/// ```
+pub fn value_ranges(program: &Program) -> ValueRanges {
+	// NOTE: the tree is flattened into the instructions in pre-order, and what runs after each of
+	// them
+	let mut nodes: Vec<&Instruction> = vec![];
+	let mut next: Vec<usize> = vec![];
+	let end = count_instructions(&program.instructions);
+	// NOTE: the rest of every sequence being flattened, what runs after it, and the last of its
+	// instructions flattened so far, innermost last, so that deeply nested loops can't overflow
+	// the stack
+	let mut open = vec![(program.instructions.iter(), end, None)];
+
+	while let Some((rest, after, previous)) = open.last_mut() {
+		let Some(instruction) = rest.next() else {
+			if let Some(previous) = *previous {
+				next[previous] = *after;
+			}
+			open.pop();
+			continue;
+		};
+
+		let index = nodes.len();
+		if let Some(previous) = previous.replace(index) {
+			next[previous] = index;
+		}
+		nodes.push(instruction);
+		// NOTE: patched once the instruction after it is known
+		next.push(end);
+
+		if let Instruction::Loop(body) = instruction {
+			// NOTE: the body runs its condition again once it ends
+			open.push((body.iter(), index, None));
+		}
+	}
+
+	let mut before: Vec<Option<Cells>> = vec![None; end];
+	let mut visits = vec![0; end];
+	let mut after_end: Option<Cells> = None;
+	let mut pending = BTreeSet::new();
+
+	let mut flow = |target: usize,
+	                cells: Cells,
+	                before: &mut Vec<Option<Cells>>,
+	                pending: &mut BTreeSet<usize>| {
+		if target == end {
+			after_end = Some(match &after_end {
+				Some(previous) => previous.join(&cells),
+				None => cells,
+			});
+			return;
+		}
+
+		let joined = match &before[target] {
+			Some(previous) => {
+				let joined = previous.join(&cells);
+				visits[target] += 1;
+				if visits[target] > WIDEN_AFTER {
+					previous.widen(&joined)
+				} else {
+					joined
+				}
+			}
+			None => cells,
+		};
+		if before[target].as_ref() != Some(&joined) {
+			before[target] = Some(joined);
+			pending.insert(target);
+		}
+	};
+
+	flow(0, Cells::START, &mut before, &mut pending);
+
+	// NOTE: earlier instructions go first, so loops settle before what follows them
+	while let Some(index) = pending.pop_first() {
+		let cells = before[index]
+			.clone()
+			.expect("pending instructions are reached");
+		let mut after = cells.clone();
+
+		match nodes[index] {
+			Instruction::Inc => after.set(0, cells.current().inc()),
+			Instruction::Dec => after.set(0, cells.current().dec()),
+			Instruction::Add(amount) => after.set(0, cells.current().add(*amount)),
+			Instruction::Clear => after.set(0, Range::ZERO),
+			Instruction::Next => after.shift(1),
+			Instruction::Prev => after.shift(-1),
+			Instruction::Move(distance) => after.shift(*distance as isize),
+			Instruction::Print => {}
+			Instruction::Read | Instruction::Extension(Extension::Time) => {
+				after.set(0, Range::FULL);
+			}
+			// NOTE: reads may end the program at the end of the input as well
+			Instruction::Extension(Extension::Exit) => {}
+			Instruction::Extension(Extension::Custom(_)) => {
+				after = Cells {
+					pointer: None,
+					known: BTreeMap::new(),
+					rest: Range::FULL,
+				};
+			}
+			Instruction::Loop(body) => {
+				if let Some(entered) = cells.nonzero() {
+					if !body.is_empty() {
+						flow(index + 1, entered, &mut before, &mut pending);
+					}
+				}
+				if let Some(left) = cells.zero() {
+					flow(next[index], left, &mut before, &mut pending);
+				}
+				continue;
+			}
+		}
+
+		if matches!(
+			nodes[index],
+			Instruction::Read | Instruction::Extension(Extension::Exit)
+		) {
+			flow(end, cells, &mut before, &mut pending);
+		}
+		flow(next[index], after, &mut before, &mut pending);
+	}
+
+	let mut may_wrap = vec![];
+	let mut dead_loops = vec![];
+	for (index, instruction) in nodes.iter().enumerate() {
+		let current = before[index].as_ref().map(Cells::current);
+
+		match instruction {
+			Instruction::Inc if current.is_some_and(|range| range.max == u8::MAX) => {
+				may_wrap.push(index);
+			}
+			Instruction::Dec if current.is_some_and(|range| range.min == 0) => {
+				may_wrap.push(index);
+			}
+			Instruction::Add(amount)
+				if current.is_some_and(|range| {
+					range.min.checked_add_signed(*amount).is_none()
+						|| range.max.checked_add_signed(*amount).is_none()
+				}) =>
+			{
+				may_wrap.push(index);
+			}
+			Instruction::Loop(_) if current.is_none_or(Range::is_zero) => dead_loops.push(index),
+			_ => {}
+		}
+	}
+
+	ValueRanges {
+		spread: before
+			.iter()
+			.chain([&after_end])
+			.flatten()
+			.map(Cells::spread)
+			.max()
+			.unwrap_or(0),
+		before,
+		end: after_end,
+		may_wrap,
+		dead_loops,
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use crate::token::Token;
+
+	use super::*;
+
+	fn parse(code: &str) -> Vec<Instruction> {
+		Instruction::parse(Token::tokenize(code)).unwrap()
+	}
+
+	#[test]
+	fn nested_balanced_loops() {
+		let bounds = pointer_bounds(&parse(">>[<<[->>>+<<<]>>-]>")).unwrap();
+
+		assert_eq!(PointerBounds { min: 0, max: 3 }, bounds);
+		assert_eq!(4, bounds.tape_length());
+	}
+
+	#[test]
+	fn data_dependent_movement() {
+		assert_eq!(None, pointer_bounds(&parse(",[>,]")));
+		assert_eq!(None, pointer_bounds(&parse("+[[<]+]")));
+	}
+
+	#[test]
+	fn loop_body_deltas() {
+		assert_eq!(
+			Some(BTreeMap::from([(-1, 3)])),
+			cell_deltas(&parse("+-<+++>"))
+		);
+		assert_eq!(Some(BTreeMap::new()), cell_deltas(&parse("+->+-<")));
+		assert_eq!(None, cell_deltas(&parse("->+")));
+		assert_eq!(None, cell_deltas(&parse("-.")));
+	}
+
+	#[test]
+	fn value_ranges_of_loops() {
+		let program = Program::parse("+++[>+<-]>[-]<-,[.,]").unwrap();
+		let ranges = value_ranges(&program);
+
+		// NOTE: the cell the first loop counts up is given up on after a few iterations, so its
+		// `+` may wrap, but both cells are known to be zero after the loops clearing them
+		let cleared = ranges.before[11].as_ref().unwrap();
+		assert_eq!(Some(1), cleared.pointer);
+		assert_eq!(Range::ZERO, cleared.current());
+		assert_eq!(Range::ZERO, cleared.get(-1));
+		assert_eq!(vec![5, 12], ranges.may_wrap);
+		assert!(ranges.dead_loops.is_empty());
+
+		assert!(ranges.before[15].as_ref().unwrap().current().is_nonzero());
+		// NOTE: reads may end the program as well
+		assert_eq!(Some(Some(0)), ranges.end.map(|end| end.pointer));
+	}
+
+	#[test]
+	fn program_stats() {
+		assert_eq!(
+			Stats {
+				instructions: 6,
+				loops: 2,
+				max_depth: 2,
+				pointer_bounds: Some(PointerBounds { min: 0, max: 1 }),
+			},
+			stats(&parse("+[>[-]<]"))
+		);
+	}
+}
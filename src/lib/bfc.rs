@@ -0,0 +1,223 @@
+//! The layout of a `.bfc` file is as follows (all integers are little-endian):
+//!
+//! | Size        | Content                                                       |
+//! |-------------|---------------------------------------------------------------|
+//! | 4 bytes     | Magic bytes `BFC\0`                                           |
+//! | 4 bytes     | Format version                                                |
+//! | 8 bytes     | Number of ops                                                 |
+//! | 1-6 bytes   | Ops, each an opcode followed by its operands                  |
+//!
+//! Jump targets are 4 bytes, offsets 4 bytes and signed, and deltas, factors and extensions,
+//! which are stored as their `.bfo` opcode, 1 byte.
+
+use crate::{
+	bfo::Reader,
+	bytecode::{Bytecode, Op},
+	extension::Extension,
+	program::ImportError,
+};
+
+/// Magic bytes every `.bfc` file starts with.
+pub const BFC_MAGIC: &[u8; 4] = b"BFC\0";
+/// Version of the `.bfc` format.
+pub const BFC_VERSION: u32 = 2;
+
+impl Bytecode {
+	/// Serialize the ops into the `.bfc` binary format, so programs that are compiled and
+	/// optimized once can be run many times without parsing them again.
+	pub fn to_bfc(&self) -> Vec<u8> {
+		let mut output = Vec::with_capacity(16 + self.ops().len() * 2);
+		output.extend_from_slice(BFC_MAGIC);
+		output.extend_from_slice(&BFC_VERSION.to_le_bytes());
+		output.extend_from_slice(&(self.ops().len() as u64).to_le_bytes());
+
+		for &op in self.ops() {
+			let (opcode, operands): (u8, &[&[u8]]) = match op {
+				Op::Inc => (0, &[]),
+				Op::Dec => (1, &[]),
+				Op::Next => (2, &[]),
+				Op::Prev => (3, &[]),
+				Op::Print => (4, &[]),
+				Op::Read => (5, &[]),
+				Op::JumpIfZero(target) => (6, &[&target.to_le_bytes()]),
+				Op::JumpIfNonZero(target) => (7, &[&target.to_le_bytes()]),
+				Op::Extension(extension) => (8, &[&[extension.opcode()]]),
+				Op::Add(delta) => (9, &[&[delta]]),
+				Op::Move(offset) => (10, &[&offset.to_le_bytes()]),
+				Op::Clear => (11, &[]),
+				Op::AddMove(delta, offset) => (12, &[&[delta], &offset.to_le_bytes()]),
+				Op::ClearMove(offset) => (13, &[&offset.to_le_bytes()]),
+				Op::MoveAdd(offset, delta) => (14, &[&offset.to_le_bytes(), &[delta]]),
+				Op::JumpIfZeroAdd(target, delta) => (15, &[&target.to_le_bytes(), &[delta]]),
+				Op::RepeatN(target, factor) => (16, &[&target.to_le_bytes(), &[factor]]),
+				Op::RepeatEnd(target) => (17, &[&target.to_le_bytes()]),
+				Op::MulAdd(offset, factor) => (18, &[&offset.to_le_bytes(), &[factor]]),
+				Op::ScanRight => (19, &[]),
+				Op::ScanLeft => (20, &[]),
+				Op::AddAt(offset, delta) => (21, &[&offset.to_le_bytes(), &[delta]]),
+				Op::Set(value) => (22, &[&[value]]),
+				Op::AddPrint(delta) => (23, &[&[delta]]),
+				Op::Fill(offset, value) => (24, &[&offset.to_le_bytes(), &[value]]),
+			};
+
+			output.push(opcode);
+			for operand in operands {
+				output.extend_from_slice(operand);
+			}
+		}
+
+		output
+	}
+
+	/// Deserialize ops produced by [`Bytecode::to_bfc`], skipping parsing, compiling and
+	/// optimizing the program.
+	///
+	/// # Usage
+	///
+	/// ```
+	/// # use brainfuck_rs::{bytecode::{Bytecode, Fusion}, instruction::Instruction, token::Token};
+	/// let instructions = Instruction::parse(Token::tokenize("++[->+++<]>.")).unwrap();
+	/// let bytecode = Bytecode::compile(&instructions).collapse().multiply_loops().fuse(Fusion::ALL);
+	///
+	/// assert_eq!(bytecode, Bytecode::from_bfc(&bytecode.to_bfc()).unwrap());
+	/// ```
+	///
+	/// # Errors
+	///
+	/// It errors if the input isn't a `.bfc` file, uses an unsupported format version, is
+	/// truncated, or jumps past the end of the ops.
+	pub fn from_bfc(bytes: &[u8]) -> Result<Self, ImportError> {
+		let mut reader = Reader { bytes };
+
+		if reader.take(4)? != BFC_MAGIC {
+			return Err(ImportError::Malformed(
+				"missing `BFC` magic bytes".to_owned(),
+			));
+		}
+
+		let version = u32::from_le_bytes(reader.take_array()?);
+		if version != BFC_VERSION {
+			return Err(ImportError::UnsupportedVersion {
+				found: version,
+				supported: BFC_VERSION,
+			});
+		}
+
+		let op_count = reader.take_usize()?;
+		// NOTE: every op takes at least a byte, which keeps bogus counts from allocating
+		let mut ops = Vec::with_capacity(op_count.min(reader.bytes.len()));
+		for _ in 0..op_count {
+			ops.push(read_op(&mut reader)?);
+		}
+
+		if !reader.bytes.is_empty() {
+			return Err(ImportError::Malformed(format!(
+				"{} unexpected trailing bytes",
+				reader.bytes.len()
+			)));
+		}
+
+		for (index, op) in ops.iter().enumerate() {
+			let (Op::JumpIfZero(target)
+			| Op::JumpIfNonZero(target)
+			| Op::JumpIfZeroAdd(target, _)
+			| Op::RepeatN(target, _)
+			| Op::RepeatEnd(target)) = *op
+			else {
+				continue;
+			};
+
+			if target as usize > ops.len() {
+				return Err(ImportError::Malformed(format!(
+					"op {index} jumps to {target}, past the end of the ops"
+				)));
+			}
+		}
+
+		let capabilities = ops
+			.iter()
+			.filter_map(|op| match op {
+				Op::Extension(extension) => extension.capability(),
+				_ => None,
+			})
+			.collect();
+
+		Ok(Self::from_parts(ops, capabilities))
+	}
+}
+
+fn read_op(reader: &mut Reader<'_>) -> Result<Op, ImportError> {
+	let target = |reader: &mut Reader<'_>| reader.take_array().map(u32::from_le_bytes);
+	let offset = |reader: &mut Reader<'_>| reader.take_array().map(i32::from_le_bytes);
+	let byte = |reader: &mut Reader<'_>| reader.take(1).map(|bytes| bytes[0]);
+
+	Ok(match byte(reader)? {
+		0 => Op::Inc,
+		1 => Op::Dec,
+		2 => Op::Next,
+		3 => Op::Prev,
+		4 => Op::Print,
+		5 => Op::Read,
+		6 => Op::JumpIfZero(target(reader)?),
+		7 => Op::JumpIfNonZero(target(reader)?),
+		8 => {
+			let opcode = byte(reader)?;
+			Op::Extension(Extension::from_opcode(opcode).ok_or_else(|| {
+				ImportError::Malformed(format!("unknown extension opcode {opcode}"))
+			})?)
+		}
+		9 => Op::Add(byte(reader)?),
+		10 => Op::Move(offset(reader)?),
+		11 => Op::Clear,
+		12 => Op::AddMove(byte(reader)?, offset(reader)?),
+		13 => Op::ClearMove(offset(reader)?),
+		14 => Op::MoveAdd(offset(reader)?, byte(reader)?),
+		15 => Op::JumpIfZeroAdd(target(reader)?, byte(reader)?),
+		16 => Op::RepeatN(target(reader)?, byte(reader)?),
+		17 => Op::RepeatEnd(target(reader)?),
+		18 => Op::MulAdd(offset(reader)?, byte(reader)?),
+		19 => Op::ScanRight,
+		20 => Op::ScanLeft,
+		21 => Op::AddAt(offset(reader)?, byte(reader)?),
+		22 => Op::Set(byte(reader)?),
+		23 => Op::AddPrint(byte(reader)?),
+		24 => Op::Fill(offset(reader)?, byte(reader)?),
+		opcode => return Err(ImportError::Malformed(format!("unknown opcode {opcode}"))),
+	})
+}
+
+#[cfg(test)]
+mod tests {
+	use std::collections::BTreeSet;
+
+	use crate::{bytecode::Fusion, instruction::Instruction, token::Token};
+
+	use super::*;
+
+	#[test]
+	fn round_trip() {
+		let extensions = [Extension::Time];
+		let instructions = Instruction::parse(Token::tokenize_with_extensions(
+			"+[->+>---<<]>[-<.>]<<[>]~,[+++[-]]",
+			&extensions,
+		))
+		.unwrap();
+		let bytecode = Bytecode::compile(&instructions)
+			.collapse()
+			.multiply_loops()
+			.count_loops()
+			.fuse(Fusion::ALL);
+
+		assert_eq!(bytecode, Bytecode::from_bfc(&bytecode.to_bfc()).unwrap());
+		assert_eq!(
+			ImportError::Malformed("missing `BFC` magic bytes".to_owned()),
+			Bytecode::from_bfc(b"+[-]+[-]").unwrap_err()
+		);
+
+		let jump_past_end = Bytecode::from_parts(vec![Op::JumpIfZero(2)], BTreeSet::new());
+		assert_eq!(
+			ImportError::Malformed("op 0 jumps to 2, past the end of the ops".to_owned()),
+			Bytecode::from_bfc(&jump_past_end.to_bfc()).unwrap_err()
+		);
+	}
+}
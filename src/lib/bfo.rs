@@ -0,0 +1,286 @@
+//! The layout of a `.bfo` file is as follows (all integers are little-endian):
+//!
+//! | Size        | Content                                                       |
+//! |-------------|---------------------------------------------------------------|
+//! | 4 bytes     | Magic bytes `BFO\0`                                           |
+//! | 4 bytes     | Format version                                                |
+//! | 1 byte      | Flags, bit 0 is set if spans are present                      |
+//! | 8 bytes     | Number of bytes of opcodes                                    |
+//! | 1 byte each | Opcodes, one per [`Token`] in the order they appear in source |
+//! |             | and one per [`Instruction::Clear`], [`Instruction::Add`] and  |
+//! |             | [`Instruction::Move`], the last two followed by their amount  |
+//! |             | as an `i8` and an `i32`                                       |
+//! | 32 bytes    | Optionally, one span per instruction in pre-order             |
+
+use std::mem;
+
+use crate::{
+	extension::Extension,
+	instruction::{Instruction, ParseError},
+	program::{count_instructions, ImportError, Program},
+	token::{Span, Token},
+};
+
+/// Magic bytes every `.bfo` file starts with.
+pub const BFO_MAGIC: &[u8; 4] = b"BFO\0";
+/// Version of the `.bfo` format.
+pub const BFO_VERSION: u32 = 1;
+
+const FLAG_SPANS: u8 = 1;
+/// Opcode of [`Instruction::Clear`], which stands for a whole loop rather than a [`Token`].
+const CLEAR: u8 = 0x7f;
+/// Opcode of [`Instruction::Add`], which stands for a run of tokens.
+const ADD: u8 = 0x7e;
+/// Opcode of [`Instruction::Move`], which stands for a run of tokens.
+const MOVE: u8 = 0x7d;
+
+impl Program {
+	/// Serialize the program into the compact `.bfo` binary format.
+	pub fn to_bfo(&self) -> Vec<u8> {
+		fn push_opcodes(output: &mut Vec<u8>, instructions: &[Instruction]) {
+			for instruction in instructions {
+				match instruction {
+					Instruction::Loop(inner) => {
+						output.push(opcode(Token::LoopStart));
+						push_opcodes(output, inner);
+						output.push(opcode(Token::LoopEnd));
+					}
+					Instruction::Inc => output.push(opcode(Token::Inc)),
+					Instruction::Dec => output.push(opcode(Token::Dec)),
+					Instruction::Next => output.push(opcode(Token::Next)),
+					Instruction::Prev => output.push(opcode(Token::Prev)),
+					Instruction::Print => output.push(opcode(Token::Print)),
+					Instruction::Read => output.push(opcode(Token::Read)),
+					Instruction::Clear => output.push(CLEAR),
+					Instruction::Add(amount) => {
+						output.push(ADD);
+						output.extend_from_slice(&amount.to_le_bytes());
+					}
+					Instruction::Move(distance) => {
+						output.push(MOVE);
+						output.extend_from_slice(&distance.to_le_bytes());
+					}
+					Instruction::Extension(extension) => {
+						output.push(opcode(Token::Extension(*extension)));
+					}
+				}
+			}
+		}
+
+		let has_spans = self.spans.len() == count_instructions(&self.instructions);
+
+		let mut opcodes = vec![];
+		push_opcodes(&mut opcodes, &self.instructions);
+
+		let mut output = Vec::with_capacity(17 + opcodes.len() + self.spans.len() * 32);
+		output.extend_from_slice(BFO_MAGIC);
+		output.extend_from_slice(&BFO_VERSION.to_le_bytes());
+		output.push(if has_spans { FLAG_SPANS } else { 0 });
+		output.extend_from_slice(&(opcodes.len() as u64).to_le_bytes());
+		output.extend_from_slice(&opcodes);
+
+		if has_spans {
+			for span in &self.spans {
+				for field in [span.start, span.end, span.line, span.column] {
+					output.extend_from_slice(&(field as u64).to_le_bytes());
+				}
+			}
+		}
+
+		output
+	}
+
+	/// Deserialize a program produced by [`Program::to_bfo`], skipping tokenization.
+	///
+	/// # Errors
+	///
+	/// It errors if the input isn't a `.bfo` file, uses an unsupported format version, is
+	/// truncated, or contains unbalanced loops.
+	pub fn from_bfo(bytes: &[u8]) -> Result<Self, ImportError> {
+		let mut reader = Reader { bytes };
+
+		if reader.take(4)? != BFO_MAGIC {
+			return Err(ImportError::Malformed(
+				"missing `BFO` magic bytes".to_owned(),
+			));
+		}
+
+		let version = u32::from_le_bytes(reader.take_array()?);
+		if version != BFO_VERSION {
+			return Err(ImportError::UnsupportedVersion {
+				found: version,
+				supported: BFO_VERSION,
+			});
+		}
+
+		let flags = reader.take(1)?[0];
+		let opcode_length = reader.take_usize()?;
+
+		let mut instructions: Vec<Instruction> = vec![];
+		// NOTE: like `Instruction::parse`, but clears and runs aren't tokens
+		let mut open: Vec<Vec<Instruction>> = vec![];
+		let mut opcodes = Reader {
+			bytes: reader.take(opcode_length)?,
+		};
+		while !opcodes.bytes.is_empty() {
+			let byte = opcodes.take(1)?[0];
+			match byte {
+				CLEAR => {
+					instructions.push(Instruction::Clear);
+					continue;
+				}
+				ADD => {
+					instructions.push(Instruction::Add(i8::from_le_bytes(opcodes.take_array()?)));
+					continue;
+				}
+				MOVE => {
+					instructions.push(Instruction::Move(i32::from_le_bytes(opcodes.take_array()?)));
+					continue;
+				}
+				_ => {}
+			}
+
+			match token(byte)
+				.ok_or_else(|| ImportError::Malformed(format!("unknown opcode {byte}")))?
+			{
+				Token::LoopStart => open.push(mem::take(&mut instructions)),
+				Token::LoopEnd => {
+					let outer = open.pop().ok_or_else(|| {
+						ImportError::Malformed(ParseError::UnmatchedLoopEnd.to_string())
+					})?;
+					let body = mem::replace(&mut instructions, outer);
+					instructions.push(Instruction::Loop(body));
+				}
+				token => instructions.push(token.into()),
+			}
+		}
+		if !open.is_empty() {
+			return Err(ImportError::Malformed(
+				ParseError::UnmatchedLoopStart.to_string(),
+			));
+		}
+
+		let mut spans = vec![];
+		if flags & FLAG_SPANS != 0 {
+			for _ in 0..count_instructions(&instructions) {
+				spans.push(Span {
+					start: reader.take_usize()?,
+					end: reader.take_usize()?,
+					line: reader.take_usize()?,
+					column: reader.take_usize()?,
+				});
+			}
+		}
+
+		if !reader.bytes.is_empty() {
+			return Err(ImportError::Malformed(format!(
+				"{} unexpected trailing bytes",
+				reader.bytes.len()
+			)));
+		}
+
+		Ok(Self {
+			instructions,
+			spans,
+		})
+	}
+}
+
+const fn opcode(token: Token) -> u8 {
+	match token {
+		Token::Inc => 0,
+		Token::Dec => 1,
+		Token::Next => 2,
+		Token::Prev => 3,
+		Token::Print => 4,
+		Token::Read => 5,
+		Token::LoopStart => 6,
+		Token::LoopEnd => 7,
+		Token::Extension(extension) => extension.opcode(),
+	}
+}
+
+fn token(opcode: u8) -> Option<Token> {
+	match opcode {
+		0 => Some(Token::Inc),
+		1 => Some(Token::Dec),
+		2 => Some(Token::Next),
+		3 => Some(Token::Prev),
+		4 => Some(Token::Print),
+		5 => Some(Token::Read),
+		6 => Some(Token::LoopStart),
+		7 => Some(Token::LoopEnd),
+		_ => Extension::from_opcode(opcode).map(Token::Extension),
+	}
+}
+
+pub(crate) struct Reader<'a> {
+	pub(crate) bytes: &'a [u8],
+}
+
+impl<'a> Reader<'a> {
+	pub(crate) fn take(&mut self, length: usize) -> Result<&'a [u8], ImportError> {
+		if self.bytes.len() < length {
+			return Err(ImportError::Malformed("unexpected end of file".to_owned()));
+		}
+
+		let (taken, rest) = self.bytes.split_at(length);
+		self.bytes = rest;
+
+		Ok(taken)
+	}
+
+	pub(crate) fn take_array<const N: usize>(&mut self) -> Result<[u8; N], ImportError> {
+		Ok(self
+			.take(N)?
+			.try_into()
+			.expect("slice has the right length"))
+	}
+
+	pub(crate) fn take_usize(&mut self) -> Result<usize, ImportError> {
+		usize::try_from(u64::from_le_bytes(self.take_array()?))
+			.map_err(|_| ImportError::Malformed("value doesn't fit into memory".to_owned()))
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn round_trip() {
+		let program = Program::parse("+[->[.]<]\n,").unwrap();
+
+		assert_eq!(program, Program::from_bfo(&program.to_bfo()).unwrap());
+
+		let optimized = Program::parse("+[->[-]<<<]")
+			.unwrap()
+			.clear_loops()
+			.collapse_runs();
+
+		assert_eq!(optimized, Program::from_bfo(&optimized.to_bfo()).unwrap());
+
+		let spanless = Program::from(program.instructions);
+
+		assert_eq!(spanless, Program::from_bfo(&spanless.to_bfo()).unwrap());
+	}
+
+	#[test]
+	fn rejects_foreign_files() {
+		assert_eq!(
+			ImportError::Malformed("missing `BFO` magic bytes".to_owned()),
+			Program::from_bfo(b"+[-]+[-]").unwrap_err()
+		);
+
+		let mut bytes = Program::parse("+").unwrap().to_bfo();
+		bytes[4] = 2;
+
+		assert_eq!(
+			ImportError::UnsupportedVersion {
+				found: 2,
+				supported: BFO_VERSION
+			},
+			Program::from_bfo(&bytes).unwrap_err()
+		);
+	}
+}
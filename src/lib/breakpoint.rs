@@ -0,0 +1,469 @@
+use std::{
+	collections::{BTreeSet, HashMap},
+	fmt,
+	str::FromStr,
+};
+
+use thiserror::Error;
+
+use crate::{hooks::Hooks, instruction::Instruction, program::Program, view::EngineView};
+
+/// A place in the source code to stop a program at, when the instruction there is about to run,
+/// optionally only if a [`Condition`] holds then, like `14:3 if cell[ptr] == 65`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Breakpoint {
+	/// Line of the instruction, starting from 1.
+	pub line: usize,
+	/// Column of the instruction, starting from 1.
+	pub column: usize,
+	/// What must hold for the program to stop, or [`None`] to stop whenever it gets there.
+	pub condition: Option<Condition>,
+}
+
+impl FromStr for Breakpoint {
+	type Err = BreakpointError;
+
+	fn from_str(text: &str) -> Result<Self, Self::Err> {
+		let (location, condition) = match text.trim().split_once(" if ") {
+			Some((location, condition)) => (location, Some(condition.parse()?)),
+			None => (text.trim(), None),
+		};
+
+		let (line, column) = location
+			.trim()
+			.split_once(':')
+			.and_then(|(line, column)| Some((line.parse().ok()?, column.parse().ok()?)))
+			.ok_or_else(|| {
+				BreakpointError::Syntax(format!("expected `LINE:COLUMN`, found `{location}`"))
+			})?;
+
+		Ok(Self {
+			line,
+			column,
+			condition,
+		})
+	}
+}
+
+impl fmt::Display for Breakpoint {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		write!(f, "{}:{}", self.line, self.column)?;
+
+		if let Some(condition) = &self.condition {
+			write!(f, " if {condition}")?;
+		}
+
+		Ok(())
+	}
+}
+
+/// Comparisons of cells, the pointer and numbers, all of which must hold, joined by `&&`, like
+/// `cell[ptr] == 65 && cell[ptr-1] != 0`.
+///
+/// Operands are numbers, `ptr`, the index of the current cell, and cells, either at an index,
+/// like `cell[3]`, or relative to the pointer, like `cell[ptr]`, `cell[ptr+1]` or `cell[ptr-2]`.
+/// Comparisons with cells past either end of the tape never hold.
+///
+/// # Usage
+///
+/// ```
+/// # use brainfuck_rs::breakpoint::Condition;
+/// let condition: Condition = "cell[ ptr-1 ]>2&&ptr==1".parse().unwrap();
+///
+/// assert_eq!("cell[ptr-1] > 2 && ptr == 1", condition.to_string());
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Condition(Vec<Comparison>);
+
+impl Condition {
+	/// Whether every comparison holds for the engine as it is.
+	pub fn holds(&self, bf: EngineView<'_>) -> bool {
+		self.0.iter().all(|comparison| comparison.holds(bf))
+	}
+}
+
+impl FromStr for Condition {
+	type Err = BreakpointError;
+
+	fn from_str(text: &str) -> Result<Self, Self::Err> {
+		text.split("&&")
+			.map(str::parse)
+			.collect::<Result<_, _>>()
+			.map(Self)
+	}
+}
+
+impl fmt::Display for Condition {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		for (index, comparison) in self.0.iter().enumerate() {
+			if index > 0 {
+				write!(f, " && ")?;
+			}
+			write!(f, "{comparison}")?;
+		}
+
+		Ok(())
+	}
+}
+
+/// A comparison of a [`Condition`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct Comparison {
+	left: Operand,
+	operator: &'static str,
+	right: Operand,
+}
+
+impl Comparison {
+	/// Comparison operators, longest first, so `<=` isn't taken for `<`.
+	const OPERATORS: &'static [&'static str] = &["==", "!=", "<=", ">=", "<", ">"];
+
+	fn holds(self, bf: EngineView<'_>) -> bool {
+		let (Some(left), Some(right)) = (self.left.value(bf), self.right.value(bf)) else {
+			return false;
+		};
+
+		match self.operator {
+			"==" => left == right,
+			"!=" => left != right,
+			"<=" => left <= right,
+			">=" => left >= right,
+			"<" => left < right,
+			_ => left > right,
+		}
+	}
+}
+
+impl FromStr for Comparison {
+	type Err = BreakpointError;
+
+	fn from_str(text: &str) -> Result<Self, Self::Err> {
+		let (operator, (left, right)) = Self::OPERATORS
+			.iter()
+			.find_map(|&operator| Some((operator, text.split_once(operator)?)))
+			.ok_or_else(|| {
+				BreakpointError::Syntax(format!("expected a comparison, found `{}`", text.trim()))
+			})?;
+
+		Ok(Self {
+			left: left.parse()?,
+			operator,
+			right: right.parse()?,
+		})
+	}
+}
+
+impl fmt::Display for Comparison {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		write!(f, "{} {} {}", self.left, self.operator, self.right)
+	}
+}
+
+/// An operand of a [`Comparison`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Operand {
+	Number(i64),
+	Pointer,
+	Cell(usize),
+	/// A cell at this offset from the pointer.
+	RelativeCell(i64),
+}
+
+impl Operand {
+	fn value(self, bf: EngineView<'_>) -> Option<i64> {
+		let pointer = i64::try_from(bf.pointer()).ok()?;
+
+		match self {
+			Self::Number(number) => Some(number),
+			Self::Pointer => Some(pointer),
+			Self::Cell(index) => bf.get(index).map(i64::from),
+			Self::RelativeCell(offset) => bf
+				.get(usize::try_from(pointer.checked_add(offset)?).ok()?)
+				.map(i64::from),
+		}
+	}
+}
+
+impl FromStr for Operand {
+	type Err = BreakpointError;
+
+	fn from_str(text: &str) -> Result<Self, Self::Err> {
+		let text: String = text.chars().filter(|ch| !ch.is_whitespace()).collect();
+		let error = || BreakpointError::Syntax(format!("unknown operand `{text}`"));
+
+		if text == "ptr" {
+			return Ok(Self::Pointer);
+		}
+		let Some(index) = text
+			.strip_prefix("cell[")
+			.and_then(|rest| rest.strip_suffix(']'))
+		else {
+			return text.parse().map(Self::Number).map_err(|_| error());
+		};
+
+		let Some(offset) = index.strip_prefix("ptr") else {
+			return index.parse().map(Self::Cell).map_err(|_| error());
+		};
+		match offset.split_at_checked(1) {
+			None => Ok(Self::RelativeCell(0)),
+			Some(("+", offset)) => offset.parse().map(Self::RelativeCell).map_err(|_| error()),
+			Some(("-", offset)) => offset
+				.parse()
+				.map(|offset: i64| Self::RelativeCell(-offset))
+				.map_err(|_| error()),
+			Some(_) => Err(error()),
+		}
+	}
+}
+
+impl fmt::Display for Operand {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		match *self {
+			Self::Number(number) => write!(f, "{number}"),
+			Self::Pointer => write!(f, "ptr"),
+			Self::Cell(index) => write!(f, "cell[{index}]"),
+			Self::RelativeCell(0) => write!(f, "cell[ptr]"),
+			Self::RelativeCell(offset) if offset < 0 => write!(f, "cell[ptr-{}]", -offset),
+			Self::RelativeCell(offset) => write!(f, "cell[ptr+{offset}]"),
+		}
+	}
+}
+
+/// Hooks that stop a program at the first [`Breakpoint`] it gets to whose condition holds.
+///
+/// Conditions are only evaluated when the instruction they're at is about to run, so the rest of
+/// the program only pays for looking its instructions up. Besides breakpoints, which stay until
+/// they're removed, it stops at temporary ones, which are removed once they're hit, and at steps
+/// set with [`Breakpoints::stop_at_step`], which is what debuggers run to a place or for a number
+/// of instructions with.
+///
+/// # Usage
+///
+/// ```
+/// # use brainfuck_rs::{
+/// #   breakpoint::Breakpoints,
+/// #   engine::{Engine, RuntimeSettings},
+/// #   host::StdHost,
+/// #   program::Program,
+/// # };
+/// let program = Program::parse("+++++[>+<-]").unwrap();
+/// let mut breakpoints =
+///     Breakpoints::new(&program, vec!["1:8 if cell[ptr-1] == 2".parse().unwrap()]).unwrap();
+///
+/// let summary = Engine::default()
+///     .run_with(
+///         &program.instructions,
+///         &mut [].as_slice(),
+///         &mut vec![],
+///         RuntimeSettings::default(),
+///         &mut StdHost::default(),
+///         &mut breakpoints,
+///     )
+///     .unwrap();
+///
+/// assert!(summary.breakpoint_hit);
+/// assert_eq!(Some(3), breakpoints.hit().map(|hit| hit.cell));
+/// ```
+#[derive(Debug, Clone, Default)]
+pub struct Breakpoints {
+	/// Breakpoints at every instruction that has some, keyed by its address.
+	at: HashMap<*const Instruction, Vec<Placed>>,
+	/// Steps to stop at, before the instruction with that step runs.
+	steps: BTreeSet<u64>,
+	hit: Option<Hit>,
+}
+
+/// A breakpoint of [`Breakpoints`], and whether it's removed once it's hit.
+#[derive(Debug, Clone)]
+struct Placed {
+	breakpoint: Breakpoint,
+	temporary: bool,
+}
+
+/// Where a program stopped, see [`Breakpoints::hit`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Hit {
+	/// The breakpoint the program stopped at, or [`None`] if it stopped at a step set with
+	/// [`Breakpoints::stop_at_step`].
+	pub breakpoint: Option<Breakpoint>,
+	/// Number of instructions executed before it.
+	pub step: u64,
+	/// Index of the current cell.
+	pub pointer: usize,
+	/// Value of the current cell.
+	pub cell: u8,
+}
+
+impl Breakpoints {
+	/// Breakpoints in `program`, which must have spans, since breakpoints are placed by line and
+	/// column.
+	///
+	/// # Errors
+	///
+	/// Fails if no instruction starts where a breakpoint is.
+	pub fn new(program: &Program, breakpoints: Vec<Breakpoint>) -> Result<Self, BreakpointError> {
+		let mut set = Self::default();
+
+		for breakpoint in breakpoints {
+			set.add(program, breakpoint)?;
+		}
+
+		Ok(set)
+	}
+
+	/// Add a breakpoint to `program`, the one these breakpoints were made for.
+	///
+	/// # Errors
+	///
+	/// Fails if no instruction starts where the breakpoint is.
+	pub fn add(
+		&mut self,
+		program: &Program,
+		breakpoint: Breakpoint,
+	) -> Result<(), BreakpointError> {
+		self.place(program, breakpoint, false)
+	}
+
+	/// Add a breakpoint to `program` that's removed once the program stops at it.
+	///
+	/// # Errors
+	///
+	/// Fails if no instruction starts where the breakpoint is.
+	pub fn add_temporary(
+		&mut self,
+		program: &Program,
+		breakpoint: Breakpoint,
+	) -> Result<(), BreakpointError> {
+		self.place(program, breakpoint, true)
+	}
+
+	fn place(
+		&mut self,
+		program: &Program,
+		breakpoint: Breakpoint,
+		temporary: bool,
+	) -> Result<(), BreakpointError> {
+		let instruction = program
+			.indices()
+			.into_iter()
+			.find(|&(_, instruction)| {
+				program.span(instruction).is_some_and(|span| {
+					(span.line, span.column) == (breakpoint.line, breakpoint.column)
+				})
+			})
+			.ok_or(BreakpointError::NoInstruction {
+				line: breakpoint.line,
+				column: breakpoint.column,
+			})?
+			.0;
+
+		self.at.entry(instruction).or_default().push(Placed {
+			breakpoint,
+			temporary,
+		});
+
+		Ok(())
+	}
+
+	/// Stop right before the instruction that runs after `step` instructions, once.
+	pub fn stop_at_step(&mut self, step: u64) {
+		self.steps.insert(step);
+	}
+
+	/// Where the program stopped, if it got to a breakpoint.
+	pub const fn hit(&self) -> Option<&Hit> {
+		self.hit.as_ref()
+	}
+
+	/// Forget where the program stopped, so it can carry on, for hooks that keep running it
+	/// rather than stopping it, like debuggers.
+	pub fn take_hit(&mut self) -> Option<Hit> {
+		self.hit.take()
+	}
+}
+
+impl Hooks for Breakpoints {
+	fn before_instruction(&mut self, step: u64, instruction: &Instruction, bf: EngineView<'_>) {
+		let hit = |breakpoint| Hit {
+			breakpoint,
+			step,
+			pointer: bf.pointer(),
+			cell: bf.cell(),
+		};
+
+		// NOTE: stopping here for any reason also reaches the steps that are due
+		let step_due = self.steps.first().is_some_and(|&first| first <= step);
+		if step_due {
+			self.steps.retain(|&other| other > step);
+		}
+
+		if let Some(placed) = self.at.get_mut(&std::ptr::from_ref(instruction)) {
+			let found = placed.iter().position(|placed| {
+				placed
+					.breakpoint
+					.condition
+					.as_ref()
+					.is_none_or(|condition| condition.holds(bf))
+			});
+
+			if let Some(index) = found {
+				let breakpoint = if placed[index].temporary {
+					placed.remove(index).breakpoint
+				} else {
+					placed[index].breakpoint.clone()
+				};
+				self.hit = Some(hit(Some(breakpoint)));
+				return;
+			}
+		}
+
+		if step_due {
+			self.hit = Some(hit(None));
+		}
+	}
+
+	fn should_break(&mut self) -> bool {
+		self.hit.is_some()
+	}
+}
+
+/// An error of a [`Breakpoint`].
+#[derive(Debug, Clone, PartialEq, Eq, Error)]
+pub enum BreakpointError {
+	/// The breakpoint couldn't be parsed
+	#[error("{0}")]
+	Syntax(String),
+	/// No instruction starts where the breakpoint is
+	#[error("there's no instruction at {line}:{column}")]
+	NoInstruction {
+		/// Line of the breakpoint.
+		line: usize,
+		/// Column of the breakpoint.
+		column: usize,
+	},
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn conditions_round_trip() {
+		let breakpoint: Breakpoint =
+			"14:3 if cell[ptr]==65 && cell[ ptr - 1 ] != cell[2] && ptr < 10"
+				.parse()
+				.unwrap();
+
+		assert_eq!((14, 3), (breakpoint.line, breakpoint.column));
+		assert_eq!(
+			"14:3 if cell[ptr] == 65 && cell[ptr-1] != cell[2] && ptr < 10",
+			breakpoint.to_string()
+		);
+		assert_eq!(
+			Err(BreakpointError::Syntax(
+				"unknown operand `cell[x]`".to_owned()
+			)),
+			"1:1 if cell[x] == 0".parse::<Breakpoint>()
+		);
+	}
+}
@@ -0,0 +1,1278 @@
+use std::{
+	cmp::Reverse,
+	collections::{BTreeMap, BTreeSet},
+	fmt, mem, slice,
+};
+
+use crate::{
+	capability::{self, Capability},
+	extension::Extension,
+	hooks::Hooks,
+	instruction::{Instruction, ParseError},
+	specialize::SpecializedLoop,
+	token::Token,
+	view::EngineView,
+};
+
+/// A single operation of [`Bytecode`].
+///
+/// Loops are flattened into a pair of jumps, so the whole program is one contiguous slice that
+/// the engine walks with a program counter instead of a stack of tree nodes.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Op {
+	/// `+`
+	Inc,
+	/// `-`
+	Dec,
+	/// `>`
+	Next,
+	/// `<`
+	Prev,
+	/// `.`
+	Print,
+	/// `,`
+	Read,
+	/// `[`, jumps to the given index (right past the matching [`Op::JumpIfNonZero`]) if the
+	/// current cell is zero.
+	JumpIfZero(u32),
+	/// `]`, jumps to the given index (right past the matching [`Op::JumpIfZero`]) if the current
+	/// cell isn't zero.
+	JumpIfNonZero(u32),
+	/// Instruction of an [`Extension`]
+	Extension(Extension),
+	/// A run of `+` and `-`, adding their wrapped sum to the cell.
+	Add(u8),
+	/// A run of `>` and `<`, moving the pointer by their sum.
+	Move(i32),
+	/// `[-]` or any other loop that only adds an odd number to the cell, setting it to zero.
+	Clear,
+	/// Superinstruction for [`Op::Add`] followed by [`Op::Move`].
+	AddMove(u8, i32),
+	/// Superinstruction for [`Op::Clear`] followed by [`Op::Move`].
+	ClearMove(i32),
+	/// Superinstruction for [`Op::Move`] followed by [`Op::Add`].
+	MoveAdd(i32, u8),
+	/// Superinstruction for [`Op::JumpIfZero`] followed by [`Op::Add`] at the start of a loop body.
+	/// The matching [`Op::JumpIfNonZero`] jumps back to it rather than past it, since the add has
+	/// to be repeated.
+	JumpIfZeroAdd(u32, u8),
+	/// A loop whose condition cell is only changed by adding the same odd number on every
+	/// iteration, so the number of iterations is known upfront. If the cell isn't zero, it's
+	/// multiplied by the given factor to get the number of iterations, and the body runs that many
+	/// times without checking the cell. Otherwise, it jumps to the given index (right past the
+	/// matching [`Op::RepeatEnd`]).
+	///
+	/// The body still adds to the condition cell, so it can be run like a plain loop instead,
+	/// which happens on tapes too short to tell every cell the body changes apart from the
+	/// condition cell.
+	RepeatN(u32, u8),
+	/// End of the body of [`Op::RepeatN`], jumps to the given index (the start of the body) until
+	/// the body ran the given number of times.
+	RepeatEnd(u32),
+	/// Part of a multiply loop, like `[->+>++<<]`: adds the current cell, multiplied by the given
+	/// factor, to the cell at the given offset from it. The loop becomes one of these for every
+	/// cell it changes, followed by [`Op::Clear`].
+	MulAdd(i32, u8),
+	/// `[>]`, moves the pointer right to the nearest zero cell, wrapping around the end of the
+	/// tape. Found with `memchr` rather than by stepping cell by cell.
+	ScanRight,
+	/// `[<]`, moves the pointer left to the nearest zero cell, wrapping around the start of the
+	/// tape.
+	ScanLeft,
+	/// Adds the given number to the cell at the given offset from the current one, without moving
+	/// the pointer, for loops that return the pointer to where it started, see
+	/// [`Bytecode::address_offsets`].
+	AddAt(i32, u8),
+	/// Set the cell to a value, a fused [`Op::Clear`] and [`Op::Add`].
+	Set(u8),
+	/// Add to the cell, then print it, a fused [`Op::Add`] and [`Op::Print`].
+	AddPrint(u8),
+	/// Set the current cell and the ones up to the given (nonzero) offset from it to a value,
+	/// moving the pointer to the last one, for runs like `[-]>[-]>[-]` or `[-]+<[-]+`. Filled with
+	/// `fill()`, which is a `memset`, rather than cell by cell.
+	Fill(i32, u8),
+}
+
+/// A pair of ops that [`Bytecode::fuse`] can merge into a superinstruction.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub enum Fusion {
+	/// [`Op::AddMove`]
+	AddMove,
+	/// [`Op::ClearMove`]
+	ClearMove,
+	/// [`Op::MoveAdd`]
+	MoveAdd,
+	/// [`Op::JumpIfZeroAdd`]
+	JumpIfZeroAdd,
+	/// [`Op::Set`]
+	Set,
+	/// [`Op::AddPrint`]
+	AddPrint,
+}
+
+impl Fusion {
+	/// All the known fusions.
+	pub const ALL: &'static [Self] = &[
+		Self::AddMove,
+		Self::ClearMove,
+		Self::MoveAdd,
+		Self::JumpIfZeroAdd,
+		Self::Set,
+		Self::AddPrint,
+	];
+
+	/// The superinstruction replacing `first` followed by `second`, if this fusion applies to
+	/// them.
+	pub const fn fuse(self, first: Op, second: Op) -> Option<Op> {
+		match (self, first, second) {
+			(Self::AddMove, Op::Add(delta), Op::Move(offset)) => Some(Op::AddMove(delta, offset)),
+			(Self::ClearMove, Op::Clear, Op::Move(offset)) => Some(Op::ClearMove(offset)),
+			(Self::MoveAdd, Op::Move(offset), Op::Add(delta)) => Some(Op::MoveAdd(offset, delta)),
+			(Self::JumpIfZeroAdd, Op::JumpIfZero(target), Op::Add(delta)) => {
+				Some(Op::JumpIfZeroAdd(target, delta))
+			}
+			(Self::Set, Op::Clear, Op::Add(value)) => Some(Op::Set(value)),
+			(Self::AddPrint, Op::Add(delta), Op::Print) => Some(Op::AddPrint(delta)),
+			_ => None,
+		}
+	}
+}
+
+impl fmt::Display for Op {
+	/// Short mnemonic of the op and its operands, with deltas shown as signed numbers.
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		match *self {
+			Self::Inc => write!(f, "inc"),
+			Self::Dec => write!(f, "dec"),
+			Self::Next => write!(f, "next"),
+			Self::Prev => write!(f, "prev"),
+			Self::Print => write!(f, "print"),
+			Self::Read => write!(f, "read"),
+			Self::JumpIfZero(target) => write!(f, "jz {target}"),
+			Self::JumpIfNonZero(target) => write!(f, "jnz {target}"),
+			Self::Extension(extension) => write!(f, "ext {}", extension.symbol()),
+			Self::Add(delta) => write!(f, "add {}", delta as i8),
+			Self::Move(offset) => write!(f, "move {offset}"),
+			Self::Clear => write!(f, "clear"),
+			Self::AddMove(delta, offset) => write!(f, "add-move {} {offset}", delta as i8),
+			Self::ClearMove(offset) => write!(f, "clear-move {offset}"),
+			Self::MoveAdd(offset, delta) => write!(f, "move-add {offset} {}", delta as i8),
+			Self::JumpIfZeroAdd(target, delta) => write!(f, "jz-add {target} {}", delta as i8),
+			Self::RepeatN(target, factor) => write!(f, "repeat {target} {factor}"),
+			Self::RepeatEnd(target) => write!(f, "end-repeat {target}"),
+			Self::MulAdd(offset, factor) => write!(f, "mul-add {offset} {}", factor as i8),
+			Self::ScanRight => write!(f, "scan-right"),
+			Self::ScanLeft => write!(f, "scan-left"),
+			Self::AddAt(offset, delta) => write!(f, "add-at {offset} {}", delta as i8),
+			Self::Set(value) => write!(f, "set {value}"),
+			Self::AddPrint(delta) => write!(f, "add-print {}", delta as i8),
+			Self::Fill(offset, value) => write!(f, "fill {offset} {value}"),
+		}
+	}
+}
+
+/// A program compiled into a flat sequence of [`Op`]s, run by
+/// [`Engine::run_bytecode`](`crate::engine::Engine::run_bytecode`).
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct Bytecode {
+	ops: Vec<Op>,
+	capabilities: BTreeSet<Capability>,
+}
+
+impl Bytecode {
+	/// Compile instructions into bytecode.
+	///
+	/// Every instruction becomes exactly one op, except loops, which become two, so the number of
+	/// executed steps is the same as when running the instructions directly.
+	///
+	/// # Usage
+	///
+	/// ```
+	/// # use brainfuck_rs::{bytecode::{Bytecode, Op}, instruction::Instruction, token::Token};
+	/// let instructions = Instruction::parse(Token::tokenize("+[-]")).unwrap();
+	/// let bytecode = Bytecode::compile(&instructions);
+	///
+	/// assert_eq!(
+	///     &[Op::Inc, Op::JumpIfZero(4), Op::Dec, Op::JumpIfNonZero(2)],
+	///     bytecode.ops(),
+	/// );
+	/// ```
+	///
+	/// # Panics
+	///
+	/// It panics if the program has more than [`u32::MAX`] ops.
+	pub fn compile(instructions: &[Instruction]) -> Self {
+		let mut ops = vec![];
+		let mut rest = instructions.iter();
+		// NOTE: the rest of the bodies around the loop being compiled, with the indices of their
+		// `JumpIfZero`s, innermost last, so that deeply nested loops can't overflow the stack
+		let mut open: Vec<(usize, slice::Iter<'_, Instruction>)> = vec![];
+
+		loop {
+			let Some(instruction) = rest.next() else {
+				let Some((start, outer)) = open.pop() else {
+					break;
+				};
+
+				let end = ops.len();
+				ops[start] = Op::JumpIfZero(index_u32(end + 1));
+				ops.push(Op::JumpIfNonZero(index_u32(start + 1)));
+				rest = outer;
+				continue;
+			};
+
+			let op = match instruction {
+				Instruction::Inc => Op::Inc,
+				Instruction::Dec => Op::Dec,
+				Instruction::Next => Op::Next,
+				Instruction::Prev => Op::Prev,
+				Instruction::Print => Op::Print,
+				Instruction::Read => Op::Read,
+				Instruction::Clear => Op::Clear,
+				Instruction::Add(amount) => Op::Add(*amount as u8),
+				Instruction::Move(distance) => Op::Move(*distance),
+				Instruction::Extension(extension) => Op::Extension(*extension),
+				Instruction::Loop(inner) => {
+					open.push((ops.len(), mem::replace(&mut rest, inner.iter())));
+					// NOTE: patched once the end of the loop is known
+					Op::JumpIfZero(0)
+				}
+			};
+
+			ops.push(op);
+		}
+
+		Self {
+			ops,
+			capabilities: capability::required_by(instructions),
+		}
+	}
+
+	/// Parse tokens straight into bytecode, like [`Bytecode::compile`] does with the instructions
+	/// they parse into, but without building the instruction tree, which allocates for every loop.
+	///
+	/// # Usage
+	///
+	/// ```
+	/// # use brainfuck_rs::{bytecode::Bytecode, instruction::Instruction, token::Token};
+	/// let code = "+[>[-]<.]";
+	/// let instructions = Instruction::parse(Token::tokenize(code)).unwrap();
+	///
+	/// assert_eq!(Bytecode::compile(&instructions), Bytecode::parse(Token::tokenize(code)).unwrap());
+	/// ```
+	///
+	/// # Errors
+	///
+	/// Same as [`Instruction::parse`].
+	///
+	/// # Panics
+	///
+	/// It panics if the program has more than [`u32::MAX`] ops.
+	pub fn parse(tokens: impl IntoIterator<Item = Token>) -> Result<Self, ParseError> {
+		let mut ops = vec![];
+		let mut capabilities = BTreeSet::new();
+		// NOTE: indices of the `JumpIfZero`s of the loops that are open
+		let mut open = vec![];
+
+		for token in tokens {
+			let op = match token {
+				Token::Inc => Op::Inc,
+				Token::Dec => Op::Dec,
+				Token::Next => Op::Next,
+				Token::Prev => Op::Prev,
+				Token::Print => Op::Print,
+				Token::Read => Op::Read,
+				Token::Extension(extension) => {
+					capabilities.extend(extension.capability());
+					Op::Extension(extension)
+				}
+				Token::LoopStart => {
+					open.push(ops.len());
+					// NOTE: patched once the end of the loop is known
+					Op::JumpIfZero(0)
+				}
+				Token::LoopEnd => {
+					let start = open.pop().ok_or(ParseError::UnmatchedLoopEnd)?;
+					ops[start] = Op::JumpIfZero(index_u32(ops.len() + 1));
+					Op::JumpIfNonZero(index_u32(start + 1))
+				}
+			};
+
+			ops.push(op);
+		}
+
+		if !open.is_empty() {
+			return Err(ParseError::UnmatchedLoopStart);
+		}
+
+		Ok(Self { ops, capabilities })
+	}
+
+	/// Collapse runs of `+`/`-` and `>`/`<` into [`Op::Add`] and [`Op::Move`], loops that
+	/// clear the cell into [`Op::Clear`], and `[>]` and `[<]` into [`Op::ScanRight`] and
+	/// [`Op::ScanLeft`]. Cells next to each other that are cleared, or set to the same value, one
+	/// after another, like by `[-]>[-]>[-]`, are filled by a single [`Op::Fill`].
+	///
+	/// Every collapsed op counts as a single step, so [`RunSummary::steps`](`crate::engine::RunSummary::steps`),
+	/// budgets and the counter of [`TimeSource`](`crate::engine::TimeSource`) see fewer steps than
+	/// with the original bytecode. Collapsed ops are charged by budgets like the first instruction
+	/// they replace.
+	///
+	/// # Usage
+	///
+	/// ```
+	/// # use brainfuck_rs::{bytecode::{Bytecode, Op}, instruction::Instruction, token::Token};
+	/// let instructions = Instruction::parse(Token::tokenize("+++>>[-]<[<]")).unwrap();
+	/// let bytecode = Bytecode::compile(&instructions).collapse();
+	///
+	/// assert_eq!(
+	///     &[Op::Add(3), Op::Move(2), Op::Clear, Op::Move(-1), Op::ScanLeft],
+	///     bytecode.ops(),
+	/// );
+	/// ```
+	#[must_use]
+	pub fn collapse(&self) -> Self {
+		let mut ops: Vec<Op> = Vec::with_capacity(self.ops.len());
+
+		fn add(ops: &mut Vec<Op>, delta: u8) {
+			match ops.last_mut() {
+				Some(Op::Add(sum)) => *sum = sum.wrapping_add(delta),
+				_ => ops.push(Op::Add(delta)),
+			}
+
+			if ops.last() == Some(&Op::Add(0)) {
+				ops.pop();
+			}
+		}
+
+		fn shift(ops: &mut Vec<Op>, offset: i32) {
+			// NOTE: moves in opposite directions are kept apart, since whether the pointer passed
+			// the end of a growing tape on the way, or wrapped around its start, tells where it
+			// ends up
+			match ops.last_mut() {
+				Some(Op::Move(sum)) if sum.signum() == offset.signum() => *sum += offset,
+				_ => ops.push(Op::Move(offset)),
+			}
+
+			if ops.last() == Some(&Op::Move(0)) {
+				ops.pop();
+			}
+		}
+
+		for &op in &self.ops {
+			match op {
+				Op::Inc => add(&mut ops, 1),
+				Op::Dec => add(&mut ops, u8::MAX),
+				Op::Add(delta) => add(&mut ops, delta),
+				Op::Next => shift(&mut ops, 1),
+				Op::Prev => shift(&mut ops, -1),
+				Op::Move(offset) => shift(&mut ops, offset),
+				Op::JumpIfNonZero(_) => match ops[..] {
+					// NOTE: adding an odd number visits every value of a cell, so it's bound to
+					// reach zero
+					[.., Op::JumpIfZero(_), Op::Add(delta)] if delta % 2 == 1 => {
+						ops.truncate(ops.len() - 2);
+						ops.push(Op::Clear);
+					}
+					[.., Op::JumpIfZero(_), Op::Move(offset @ (1 | -1))] => {
+						ops.truncate(ops.len() - 2);
+						ops.push(if offset == 1 {
+							Op::ScanRight
+						} else {
+							Op::ScanLeft
+						});
+					}
+					_ => ops.push(op),
+				},
+				_ => ops.push(op),
+			}
+		}
+
+		let mut ops = fill_runs(&ops);
+		link(&mut ops);
+
+		Self {
+			ops,
+			capabilities: self.capabilities.clone(),
+		}
+	}
+
+	/// Turn multiply loops into [`Op::MulAdd`]s followed by [`Op::Clear`], so the loop doesn't run
+	/// at all.
+	///
+	/// Meant to be used on [collapsed](`Bytecode::collapse`) bytecode, before
+	/// [counting loops](`Bytecode::count_loops`). A loop qualifies if it does nothing but add to
+	/// cells and move the pointer, returns it to where it started, and adds an odd number to the
+	/// condition cell, which makes the number of iterations a multiple of the cell. Like collapsed
+	/// ops, every op counts as a single step.
+	///
+	/// # Usage
+	///
+	/// ```
+	/// # use brainfuck_rs::{bytecode::{Bytecode, Op}, instruction::Instruction, token::Token};
+	/// let instructions = Instruction::parse(Token::tokenize("[->+>++<<]")).unwrap();
+	/// let bytecode = Bytecode::compile(&instructions).collapse().multiply_loops();
+	///
+	/// assert_eq!(
+	///     &[Op::MulAdd(1, 1), Op::MulAdd(2, 2), Op::Clear],
+	///     bytecode.ops(),
+	/// );
+	/// ```
+	#[must_use]
+	pub fn multiply_loops(&self) -> Self {
+		let mut ops: Vec<Op> = Vec::with_capacity(self.ops.len());
+		let mut index = 0;
+
+		while index < self.ops.len() {
+			let op = self.ops[index];
+			index += 1;
+
+			if let Op::JumpIfZero(end) = op {
+				let end = end as usize;
+
+				if let Some(factors) = multiply_loop(&self.ops[index..end - 1]) {
+					ops.extend(
+						factors
+							.into_iter()
+							.map(|(offset, factor)| Op::MulAdd(offset, factor)),
+					);
+					ops.push(Op::Clear);
+
+					index = end;
+					continue;
+				}
+			}
+
+			ops.push(op);
+		}
+
+		link(&mut ops);
+
+		Self {
+			ops,
+			capabilities: self.capabilities.clone(),
+		}
+	}
+
+	/// Turn innermost loops that only change their condition cell by a constant into
+	/// [`Op::RepeatN`], so the cell is checked once instead of on every iteration.
+	///
+	/// Meant to be used on [collapsed](`Bytecode::collapse`) bytecode, before
+	/// [fusing](`Bytecode::fuse`) it. A loop qualifies if it doesn't contain other loops, returns
+	/// the pointer to where it started, and neither reads nor writes the condition cell other
+	/// than adding an odd number to it (even numbers might never reach zero).
+	///
+	/// # Usage
+	///
+	/// ```
+	/// # use brainfuck_rs::{bytecode::{Bytecode, Op}, instruction::Instruction, token::Token};
+	/// let instructions = Instruction::parse(Token::tokenize("[->+>+++<<]")).unwrap();
+	/// let bytecode = Bytecode::compile(&instructions).collapse().count_loops();
+	///
+	/// assert_eq!(
+	///     &[
+	///         Op::RepeatN(8, 1),
+	///         Op::Add(255),
+	///         Op::Move(1),
+	///         Op::Add(1),
+	///         Op::Move(1),
+	///         Op::Add(3),
+	///         Op::Move(-2),
+	///         Op::RepeatEnd(1),
+	///     ],
+	///     bytecode.ops(),
+	/// );
+	/// ```
+	#[must_use]
+	pub fn count_loops(&self) -> Self {
+		let mut ops: Vec<Op> = Vec::with_capacity(self.ops.len());
+		let mut index = 0;
+
+		while index < self.ops.len() {
+			let op = self.ops[index];
+			index += 1;
+
+			if let Op::JumpIfZero(end) = op {
+				let end = end as usize;
+				let body = &self.ops[index..end - 1];
+
+				if let Some(step) = counted_loop(body) {
+					// NOTE: the number of iterations `n` solves `cell + n * step = 0`, which for
+					// an odd step is `n = cell * (-step)⁻¹` modulo 256
+					ops.push(Op::RepeatN(0, inverse(step.wrapping_neg())));
+					ops.extend_from_slice(body);
+					ops.push(Op::RepeatEnd(0));
+
+					index = end;
+					continue;
+				}
+			}
+
+			ops.push(op);
+		}
+
+		link(&mut ops);
+
+		Self {
+			ops,
+			capabilities: self.capabilities.clone(),
+		}
+	}
+
+	/// Address the cells innermost loops change by their offset from the cell the loop started
+	/// at, with [`Op::AddAt`], if the loop returns the pointer to where it started, so the pointer
+	/// only moves before ops that need it there, like [`Op::Print`], and back at the end.
+	///
+	/// Meant to be used on [counted](`Bytecode::count_loops`) bytecode, before
+	/// [fusing](`Bytecode::fuse`) it. Like collapsing, this reduces the number of executed steps.
+	///
+	/// # Usage
+	///
+	/// ```
+	/// # use brainfuck_rs::{bytecode::{Bytecode, Op}, instruction::Instruction, token::Token};
+	/// let instructions = Instruction::parse(Token::tokenize(",[>+>--<<,]")).unwrap();
+	/// let bytecode = Bytecode::compile(&instructions).collapse().address_offsets();
+	///
+	/// assert_eq!(
+	///     &[Op::Read, Op::JumpIfZero(6), Op::AddAt(1, 1), Op::AddAt(2, 254), Op::Read, Op::JumpIfNonZero(2)],
+	///     bytecode.ops(),
+	/// );
+	/// ```
+	#[must_use]
+	pub fn address_offsets(&self) -> Self {
+		let mut ops: Vec<Op> = Vec::with_capacity(self.ops.len());
+		// NOTE: index of the start of the innermost loop seen so far, as long as no other loop
+		// started or ended since
+		let mut start = None;
+
+		for &op in &self.ops {
+			match op {
+				Op::JumpIfZero(_) | Op::RepeatN(..) => start = Some(ops.len()),
+				Op::JumpIfZeroAdd(..) => start = None,
+				Op::JumpIfNonZero(_) | Op::RepeatEnd(_) => {
+					if let Some(start) = start.take() {
+						if let Some(body) = offset_body(&ops[start + 1..]) {
+							ops.truncate(start + 1);
+							ops.extend(body);
+						}
+					}
+				}
+				_ => {}
+			}
+
+			ops.push(op);
+		}
+
+		link(&mut ops);
+
+		Self {
+			ops,
+			capabilities: self.capabilities.clone(),
+		}
+	}
+
+	/// Merge adjacent pairs of ops into superinstructions, using only the given fusions.
+	///
+	/// Meant to be used on [collapsed](`Bytecode::collapse`) bytecode, which has the ops that can
+	/// be fused. Like collapsing, this reduces the number of executed steps. Pick the fusions
+	/// worth doing for a program with [`Profile::hot_fusions`], or use [`Fusion::ALL`].
+	///
+	/// # Usage
+	///
+	/// ```
+	/// # use brainfuck_rs::{bytecode::{Bytecode, Fusion, Op}, instruction::Instruction, token::Token};
+	/// let instructions = Instruction::parse(Token::tokenize("[->+<]")).unwrap();
+	/// let bytecode = Bytecode::compile(&instructions).collapse().fuse(Fusion::ALL);
+	///
+	/// assert_eq!(
+	///     &[Op::JumpIfZeroAdd(4, 255), Op::MoveAdd(1, 1), Op::Move(-1), Op::JumpIfNonZero(0)],
+	///     bytecode.ops(),
+	/// );
+	/// ```
+	#[must_use]
+	pub fn fuse(&self, fusions: &[Fusion]) -> Self {
+		let mut ops: Vec<Op> = Vec::with_capacity(self.ops.len());
+
+		for &op in &self.ops {
+			let fused = ops
+				.last()
+				.and_then(|&previous| fusions.iter().find_map(|fusion| fusion.fuse(previous, op)));
+
+			match (fused, ops.last_mut()) {
+				(Some(fused), Some(previous)) => *previous = fused,
+				_ => ops.push(op),
+			}
+		}
+
+		link(&mut ops);
+
+		Self {
+			ops,
+			capabilities: self.capabilities.clone(),
+		}
+	}
+
+	/// Bytecode made of ops that are linked already, like the ones of an embedded program.
+	pub(crate) const fn from_parts(ops: Vec<Op>, capabilities: BTreeSet<Capability>) -> Self {
+		Self { ops, capabilities }
+	}
+
+	/// The compiled ops.
+	pub fn ops(&self) -> &[Op] {
+		&self.ops
+	}
+
+	/// Capabilities the program needs, see [`Program::required_capabilities`](`crate::program::Program::required_capabilities`).
+	pub const fn required_capabilities(&self) -> &BTreeSet<Capability> {
+		&self.capabilities
+	}
+}
+
+impl fmt::Display for Bytecode {
+	/// A listing of the ops, one per line along with its index, indented by how deeply it's nested
+	/// in loops. Loops that [`RuntimeSettings::specialize_after`] would compile into closures are
+	/// marked.
+	///
+	/// ```
+	/// # use brainfuck_rs::{bytecode::Bytecode, instruction::Instruction, token::Token};
+	/// let instructions = Instruction::parse(Token::tokenize("+[->++<]")).unwrap();
+	/// let bytecode = Bytecode::compile(&instructions).collapse().count_loops();
+	///
+	/// let listing = bytecode.to_string();
+	/// let lines: Vec<&str> = listing.lines().collect();
+	///
+	/// assert_eq!(
+	///     vec![
+	///         "    0  add 1",
+	///         "    1  repeat 7 1  ; specialized when hot",
+	///         "    2    add -1",
+	///         "    3    move 1",
+	///         "    4    add 2",
+	///         "    5    move -1",
+	///         "    6  end-repeat 2",
+	///     ],
+	///     lines
+	/// );
+	/// ```
+	///
+	/// [`RuntimeSettings::specialize_after`]: crate::engine::RuntimeSettings::specialize_after
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		let width = self.ops.len().saturating_sub(1).to_string().len().max(5);
+		let mut depth = 0;
+
+		for (index, &op) in self.ops.iter().enumerate() {
+			if matches!(op, Op::JumpIfNonZero(_) | Op::RepeatEnd(_)) {
+				depth -= 1;
+			}
+
+			write!(f, "{index:>width$}  {:indent$}{op}", "", indent = depth * 2)?;
+
+			if matches!(
+				op,
+				Op::JumpIfZero(_) | Op::JumpIfZeroAdd(..) | Op::RepeatN(..)
+			) {
+				if SpecializedLoop::specializable(&self.ops, index) {
+					write!(f, "  ; specialized when hot")?;
+				}
+				depth += 1;
+			}
+			writeln!(f)?;
+		}
+
+		Ok(())
+	}
+}
+
+/// Number of times each op of a [`Bytecode`] was executed, recorded by passing it as
+/// [`Hooks`] to [`Engine::run_bytecode_with`](`crate::engine::Engine::run_bytecode_with`).
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct Profile {
+	counts: Vec<u64>,
+}
+
+impl Profile {
+	/// Creates an empty profile.
+	pub fn new() -> Self {
+		Self::default()
+	}
+
+	/// How many times the op at `index` was executed.
+	pub fn count(&self, index: usize) -> u64 {
+		self.counts.get(index).copied().unwrap_or_default()
+	}
+
+	/// Fusions that apply to `bytecode` often enough to save at least `min_share` (between 0 and
+	/// 1) of the executed ops in the recorded run, the most profitable first.
+	///
+	/// `bytecode` must be the same one the profile was recorded with.
+	pub fn hot_fusions(&self, bytecode: &Bytecode, min_share: f64) -> Vec<Fusion> {
+		let total: u64 = self.counts.iter().sum();
+		let mut saved: BTreeMap<Fusion, u64> = BTreeMap::new();
+
+		for (index, pair) in bytecode.ops().windows(2).enumerate() {
+			for &fusion in Fusion::ALL {
+				if fusion.fuse(pair[0], pair[1]).is_some() {
+					// NOTE: every time both ops run one after the other, fusing them saves a
+					// dispatch
+					*saved.entry(fusion).or_default() +=
+						self.count(index).min(self.count(index + 1));
+				}
+			}
+		}
+
+		let mut hot: Vec<(Fusion, u64)> = saved
+			.into_iter()
+			.filter(|&(_, saved)| total > 0 && saved as f64 / total as f64 >= min_share)
+			.collect();
+		hot.sort_by_key(|&(_, saved)| Reverse(saved));
+
+		hot.into_iter().map(|(fusion, _)| fusion).collect()
+	}
+}
+
+impl Hooks for Profile {
+	fn before_op(&mut self, _step: u64, index: usize, _op: Op, _bf: EngineView<'_>) {
+		if index >= self.counts.len() {
+			self.counts.resize(index + 1, 0);
+		}
+
+		self.counts[index] += 1;
+	}
+}
+
+/// Collapsed `ops` with runs of cells set to the same value one after another, each right next to
+/// the previous one, replaced by [`Op::Fill`].
+fn fill_runs(ops: &[Op]) -> Vec<Op> {
+	// NOTE: the value the ops at `index` set the cell to, if they do, and how many ops that takes
+	let set = |index: usize| match ops.get(index..) {
+		Some([Op::Clear, Op::Add(value), ..]) => Some((*value, 2)),
+		Some([Op::Clear, ..]) => Some((0, 1)),
+		_ => None,
+	};
+
+	let mut filled = Vec::with_capacity(ops.len());
+	let mut index = 0;
+
+	while index < ops.len() {
+		let Some((value, length)) = set(index) else {
+			filled.push(ops[index]);
+			index += 1;
+			continue;
+		};
+
+		let mut end = index + length;
+		let mut offset: i32 = 0;
+
+		while let Some(&Op::Move(step @ (1 | -1))) = ops.get(end) {
+			if offset != 0 && offset.signum() != step {
+				break;
+			}
+
+			match set(end + 1) {
+				Some((next, length)) if next == value => {
+					offset += step;
+					end += 1 + length;
+				}
+				_ => break,
+			}
+		}
+
+		if offset == 0 {
+			filled.extend_from_slice(&ops[index..end]);
+		} else {
+			filled.push(Op::Fill(offset, value));
+		}
+		index = end;
+	}
+
+	filled
+}
+
+/// The constant added to the condition cell on every iteration of a loop, if the loop can become
+/// [`Op::RepeatN`].
+fn counted_loop(body: &[Op]) -> Option<u8> {
+	let mut step: u8 = 0;
+	let mut offset: i32 = 0;
+
+	for &op in body {
+		match (op, offset) {
+			(Op::Inc, 0) => step = step.wrapping_add(1),
+			(Op::Dec, 0) => step = step.wrapping_sub(1),
+			(Op::Add(delta), 0) => step = step.wrapping_add(delta),
+			(Op::Next, _) => offset += 1,
+			(Op::Prev, _) => offset -= 1,
+			(Op::Move(by), _) => offset += by,
+			(Op::Inc | Op::Dec | Op::Add(_), _) => {}
+			// NOTE: the condition cell may only be added to, or the number of iterations isn't
+			// known upfront
+			(Op::Clear | Op::Print | Op::Read | Op::Extension(Extension::Time), offset)
+				if offset != 0 => {}
+			(Op::Fill(by, _), _)
+				if !(offset.min(offset + by)..=offset.max(offset + by)).contains(&0) =>
+			{
+				offset += by;
+			}
+			_ => return None,
+		}
+	}
+
+	(offset == 0 && step % 2 == 1).then_some(step)
+}
+
+/// Shortest tape the [`Op::RepeatN`] loops among `ops` can be counted on. On a shorter tape, a
+/// cell the body changes may be the condition cell, once its offset wraps around the tape, so
+/// the loops have to check the cell on every iteration like plain loops.
+pub(crate) fn counting_tape_length(ops: &[Op]) -> usize {
+	// NOTE: the offset from the condition cell, while inside the body of a counted loop
+	let mut offset: Option<i64> = None;
+	let mut reach: u64 = 0;
+
+	for &op in ops {
+		let Some(at) = offset.as_mut() else {
+			if matches!(op, Op::RepeatN(..)) {
+				offset = Some(0);
+			}
+			continue;
+		};
+
+		let (touched, moved) = match op {
+			Op::Next => (1, 1),
+			Op::Prev => (-1, -1),
+			Op::Move(by)
+			| Op::AddMove(_, by)
+			| Op::ClearMove(by)
+			| Op::MoveAdd(by, _)
+			| Op::Fill(by, _) => (by, by),
+			Op::AddAt(by, _) | Op::MulAdd(by, _) => (by, 0),
+			Op::RepeatEnd(_) => {
+				offset = None;
+				continue;
+			}
+			Op::JumpIfZero(_)
+			| Op::JumpIfNonZero(_)
+			| Op::JumpIfZeroAdd(..)
+			| Op::RepeatN(..)
+			| Op::ScanRight
+			| Op::ScanLeft => return usize::MAX,
+			Op::Inc
+			| Op::Dec
+			| Op::Add(_)
+			| Op::Clear
+			| Op::Set(_)
+			| Op::Print
+			| Op::AddPrint(_)
+			| Op::Read
+			| Op::Extension(_) => (0, 0),
+		};
+
+		reach = reach
+			.max(at.unsigned_abs())
+			.max((*at + i64::from(touched)).unsigned_abs());
+		*at += i64::from(moved);
+	}
+
+	usize::try_from(reach).map_or(usize::MAX, |reach| reach.saturating_add(1))
+}
+
+/// The body of an innermost loop with its arithmetic addressed by offset, if the loop returns the
+/// pointer to where it started and there is arithmetic away from that cell.
+fn offset_body(body: &[Op]) -> Option<Vec<Op>> {
+	let mut ops = vec![];
+	// NOTE: the pointer is only moved to `offset` when an op needs it there
+	let mut offset: i32 = 0;
+	let mut pointer: i32 = 0;
+	let mut addressed = false;
+
+	for &op in body {
+		match op {
+			Op::Inc | Op::Dec | Op::Add(_) => {
+				let delta = match op {
+					Op::Inc => 1,
+					Op::Dec => u8::MAX,
+					Op::Add(delta) => delta,
+					_ => unreachable!(),
+				};
+
+				if offset == pointer {
+					ops.push(Op::Add(delta));
+				} else {
+					ops.push(Op::AddAt(offset - pointer, delta));
+					addressed = true;
+				}
+			}
+			Op::Next => offset += 1,
+			Op::Prev => offset -= 1,
+			Op::Move(by) => offset += by,
+			// NOTE: these move the pointer by themselves, or by as much as the tape says
+			Op::AddMove(..)
+			| Op::ClearMove(_)
+			| Op::MoveAdd(..)
+			| Op::Fill(..)
+			| Op::ScanRight
+			| Op::ScanLeft
+			| Op::JumpIfZero(_)
+			| Op::JumpIfNonZero(_)
+			| Op::JumpIfZeroAdd(..)
+			| Op::RepeatN(..)
+			| Op::RepeatEnd(_) => return None,
+			Op::Print
+			| Op::Read
+			| Op::Extension(_)
+			| Op::Clear
+			| Op::MulAdd(..)
+			| Op::AddAt(..)
+			| Op::Set(_)
+			| Op::AddPrint(_) => {
+				if offset != pointer {
+					ops.push(Op::Move(offset - pointer));
+					pointer = offset;
+				}
+				ops.push(op);
+			}
+		}
+	}
+
+	if offset != 0 || !addressed {
+		return None;
+	}
+	if pointer != 0 {
+		ops.push(Op::Move(-pointer));
+	}
+
+	Some(ops)
+}
+
+/// The factor every cell the loop changes is multiplied by, by offset from the condition cell, if
+/// the loop can become [`Op::MulAdd`]s.
+fn multiply_loop(body: &[Op]) -> Option<BTreeMap<i32, u8>> {
+	let mut deltas: BTreeMap<i32, u8> = BTreeMap::new();
+	let mut offset: i32 = 0;
+
+	for &op in body {
+		match op {
+			Op::Inc | Op::Dec | Op::Add(_) => {
+				let delta = match op {
+					Op::Inc => 1,
+					Op::Dec => u8::MAX,
+					Op::Add(delta) => delta,
+					_ => unreachable!(),
+				};
+				let sum = deltas.entry(offset).or_default();
+				*sum = sum.wrapping_add(delta);
+			}
+			Op::Next => offset += 1,
+			Op::Prev => offset -= 1,
+			Op::Move(by) => offset += by,
+			_ => return None,
+		}
+	}
+
+	let step = deltas.remove(&0).unwrap_or_default();
+	if offset != 0 || step % 2 == 0 {
+		return None;
+	}
+
+	// NOTE: the loop runs `cell * (-step)⁻¹` times, like a counted loop, adding its delta to every
+	// other cell each time
+	let iterations = inverse(step.wrapping_neg());
+	Some(
+		deltas
+			.into_iter()
+			.filter(|&(_, delta)| delta != 0)
+			.map(|(offset, delta)| (offset, delta.wrapping_mul(iterations)))
+			.collect(),
+	)
+}
+
+/// Multiplicative inverse of an odd number, modulo 256.
+const fn inverse(odd: u8) -> u8 {
+	// NOTE: every step of Newton's iteration doubles the number of correct low bits, and an odd
+	// number is its own inverse modulo 8
+	let mut inverse = odd;
+	let mut i = 0;
+
+	while i < 2 {
+		inverse = inverse.wrapping_mul(2u8.wrapping_sub(odd.wrapping_mul(inverse)));
+		i += 1;
+	}
+
+	inverse
+}
+
+/// Point every jump at its matching op. Loops must be balanced.
+fn link(ops: &mut [Op]) {
+	let mut starts = vec![];
+
+	for index in 0..ops.len() {
+		match ops[index] {
+			Op::JumpIfZero(_) | Op::JumpIfZeroAdd(..) | Op::RepeatN(..) => starts.push(index),
+			Op::RepeatEnd(_) => {
+				let start = starts.pop().expect("loops are balanced");
+
+				ops[index] = Op::RepeatEnd(index_u32(start + 1));
+				if let Op::RepeatN(_, factor) = ops[start] {
+					ops[start] = Op::RepeatN(index_u32(index + 1), factor);
+				}
+			}
+			Op::JumpIfNonZero(_) => {
+				let start = starts.pop().expect("loops are balanced");
+
+				ops[index] = match ops[start] {
+					Op::JumpIfZeroAdd(..) => Op::JumpIfNonZero(index_u32(start)),
+					_ => Op::JumpIfNonZero(index_u32(start + 1)),
+				};
+				ops[start] = match ops[start] {
+					Op::JumpIfZeroAdd(_, delta) => Op::JumpIfZeroAdd(index_u32(index + 1), delta),
+					_ => Op::JumpIfZero(index_u32(index + 1)),
+				};
+			}
+			_ => {}
+		}
+	}
+}
+
+fn index_u32(index: usize) -> u32 {
+	u32::try_from(index).expect("program is too large to compile into bytecode")
+}
+
+#[cfg(test)]
+mod tests {
+	use crate::{
+		engine::{Engine, RuntimeSettings},
+		host::DenyAll,
+	};
+
+	use super::*;
+
+	#[test]
+	fn nested_loops() {
+		let instructions = Instruction::parse(Token::tokenize("[>[-]<]")).unwrap();
+
+		assert_eq!(
+			&[
+				Op::JumpIfZero(7),
+				Op::Next,
+				Op::JumpIfZero(5),
+				Op::Dec,
+				Op::JumpIfNonZero(3),
+				Op::Prev,
+				Op::JumpIfNonZero(1),
+			][..],
+			Bytecode::compile(&instructions).ops()
+		);
+	}
+
+	#[test]
+	fn parse_like_instructions() {
+		for code in ["[>[-]<]~.", "[[]", "[]]", "]["] {
+			let extensions = [Extension::Time];
+
+			assert_eq!(
+				Instruction::parse(Token::tokenize_with_extensions(code, &extensions))
+					.map(|instructions| Bytecode::compile(&instructions)),
+				Bytecode::parse(Token::tokenize_with_extensions(code, &extensions)),
+				"{code}"
+			);
+		}
+	}
+
+	#[test]
+	fn profile_selects_hot_fusions() {
+		let instructions = Instruction::parse(Token::tokenize("+++[->+<]")).unwrap();
+		let bytecode = Bytecode::compile(&instructions).collapse();
+
+		let mut profile = Profile::new();
+		Engine::default()
+			.run_bytecode_with(
+				&bytecode,
+				&mut <&[u8]>::default(),
+				&mut vec![],
+				RuntimeSettings::default(),
+				&mut DenyAll,
+				&mut profile,
+			)
+			.unwrap();
+
+		assert_eq!(3, profile.count(2));
+		assert_eq!(
+			vec![Fusion::AddMove, Fusion::MoveAdd],
+			profile.hot_fusions(&bytecode, 0.1)
+		);
+	}
+
+	#[test]
+	fn modular_inverse() {
+		for odd in (1..=u8::MAX).step_by(2) {
+			assert_eq!(1, odd.wrapping_mul(inverse(odd)));
+		}
+	}
+
+	#[test]
+	fn multiply_loops_run_like_loops() {
+		// NOTE: the third loop wraps around the start of the tape, the fourth one runs 85 times
+		// due to its step of -3, and the fifth one adds nothing to the cell right of it
+		for code in [
+			"+++[->+>++<<]",
+			"+>++[-<+++>]",
+			"+++[-<<+>>]",
+			"-[--->+<]",
+			"+++[->+>+<-<]",
+			"+++[->-<]",
+		] {
+			let instructions = Instruction::parse(Token::tokenize(code)).unwrap();
+			let bytecode = Bytecode::compile(&instructions).collapse().multiply_loops();
+			assert!(
+				!bytecode
+					.ops()
+					.iter()
+					.any(|op| matches!(op, Op::JumpIfZero(_))),
+				"{code}"
+			);
+
+			let mut tree = Engine::new(8);
+			tree.run(
+				&instructions,
+				&mut <&[u8]>::default(),
+				&mut vec![],
+				RuntimeSettings::default(),
+			)
+			.unwrap();
+			let mut multiplied = Engine::new(8);
+			multiplied
+				.run_bytecode(
+					&bytecode,
+					&mut <&[u8]>::default(),
+					&mut vec![],
+					RuntimeSettings::default(),
+				)
+				.unwrap();
+
+			assert_eq!(tree, multiplied, "{code}");
+		}
+
+		for code in ["[-->+<]", "[->+<<]", "[->.<]", "[->[-]<]", "[>+<]"] {
+			let instructions = Instruction::parse(Token::tokenize(code)).unwrap();
+			let bytecode = Bytecode::compile(&instructions).collapse();
+
+			assert_eq!(bytecode, bytecode.multiply_loops(), "{code}");
+		}
+	}
+
+	#[test]
+	fn fills_run_like_clears() {
+		// NOTE: the tape is short, so the third fill wraps around its start
+		for code in [
+			"+>+>+<<[-]>[-]>[-]>[-]",
+			"+>+>+[-]++<[-]++<[-]++",
+			"+[-]<[-]<[-]<[-]+>[-]>[-]",
+			"[-]+>[-]->[-]>[+]",
+		] {
+			let instructions = Instruction::parse(Token::tokenize(code)).unwrap();
+			let bytecode = Bytecode::compile(&instructions).collapse();
+			assert!(
+				bytecode.ops().iter().any(|op| matches!(op, Op::Fill(..))),
+				"{code}"
+			);
+
+			let mut tree = Engine::new(6);
+			tree.run(
+				&instructions,
+				&mut <&[u8]>::default(),
+				&mut vec![],
+				RuntimeSettings::default(),
+			)
+			.unwrap();
+			let mut filled = Engine::new(6);
+			filled
+				.run_bytecode(
+					&bytecode,
+					&mut <&[u8]>::default(),
+					&mut vec![],
+					RuntimeSettings::default(),
+				)
+				.unwrap();
+
+			assert_eq!(tree, filled, "{code}");
+		}
+
+		let instructions = Instruction::parse(Token::tokenize("[-]>[-]+>[-]<[-]")).unwrap();
+		assert_eq!(
+			&[
+				Op::Clear,
+				Op::Move(1),
+				Op::Clear,
+				Op::Add(1),
+				Op::Move(1),
+				Op::Fill(-1, 0)
+			],
+			Bytecode::compile(&instructions).collapse().ops()
+		);
+	}
+
+	#[test]
+	fn loops_that_cant_be_counted() {
+		for code in [
+			"[-->+<]",
+			"[->+<<]",
+			"[-.]",
+			"[->[>]<]",
+			"[-,]",
+			"[->+<[-]]",
+		] {
+			let instructions = Instruction::parse(Token::tokenize(code)).unwrap();
+			let bytecode = Bytecode::compile(&instructions).collapse();
+
+			assert_eq!(bytecode, bytecode.count_loops(), "{code}");
+		}
+	}
+
+	#[test]
+	fn offset_loops_run_like_loops() {
+		// NOTE: the second loop wraps around the start of the tape, the third one grows it, and
+		// the fourth one moves the pointer for the clear
+		for (code, grow_tape) in [
+			(",[>+>--<<,]", false),
+			("+++[<+<++>>-.]", false),
+			(",[>>>>>>>>>+<<<<<<<<<,]", true),
+			("++[>+++[-]>+<<-]", false),
+		] {
+			let instructions = Instruction::parse(Token::tokenize(code)).unwrap();
+			let bytecode = Bytecode::compile(&instructions)
+				.collapse()
+				.multiply_loops()
+				.count_loops()
+				.address_offsets();
+			assert!(
+				bytecode.ops().iter().any(|op| matches!(op, Op::AddAt(..))),
+				"{code}"
+			);
+
+			let settings = RuntimeSettings {
+				grow_tape,
+				..Default::default()
+			};
+			let mut tree = Engine::new(4);
+			let mut tree_output = vec![];
+			tree.run(
+				&instructions,
+				&mut b"abc".as_slice(),
+				&mut tree_output,
+				settings.clone(),
+			)
+			.unwrap();
+			let mut addressed = Engine::new(4);
+			let mut addressed_output = vec![];
+			addressed
+				.run_bytecode(
+					&bytecode,
+					&mut b"abc".as_slice(),
+					&mut addressed_output,
+					settings,
+				)
+				.unwrap();
+
+			assert_eq!(tree, addressed, "{code}");
+			assert_eq!(tree_output, addressed_output, "{code}");
+		}
+	}
+}
@@ -0,0 +1,77 @@
+use std::{collections::BTreeSet, fmt, str::FromStr};
+
+use thiserror::Error;
+
+use crate::instruction::Instruction;
+
+/// Access to the outside world that extension instructions may need.
+///
+/// Programs only get the capabilities granted in
+/// [`RuntimeSettings::capabilities`](`crate::engine::RuntimeSettings::capabilities`).
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub enum Capability {
+	/// Reading the host clock.
+	Clock,
+	/// Generating random numbers.
+	Random,
+	/// Reading environment variables.
+	Env,
+	/// Reading and writing files.
+	Files,
+}
+
+impl Capability {
+	/// All the known capabilities.
+	pub const ALL: &'static [Self] = &[Self::Clock, Self::Random, Self::Env, Self::Files];
+
+	/// Name of the capability, as used on the command line.
+	pub const fn name(self) -> &'static str {
+		match self {
+			Self::Clock => "clock",
+			Self::Random => "random",
+			Self::Env => "env",
+			Self::Files => "files",
+		}
+	}
+}
+
+impl fmt::Display for Capability {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		f.write_str(self.name())
+	}
+}
+
+impl FromStr for Capability {
+	type Err = UnknownCapability;
+
+	fn from_str(name: &str) -> Result<Self, Self::Err> {
+		Self::ALL
+			.iter()
+			.copied()
+			.find(|capability| capability.name() == name)
+			.ok_or_else(|| UnknownCapability(name.to_owned()))
+	}
+}
+
+/// An error returned when parsing the name of a capability that doesn't exist.
+#[derive(Debug, Clone, PartialEq, Eq, Error)]
+#[error("unknown capability `{0}`")]
+pub struct UnknownCapability(pub String);
+
+/// Collect capabilities needed by the instructions, including the nested ones.
+pub fn required_by<'a>(
+	instructions: impl IntoIterator<Item = &'a Instruction>,
+) -> BTreeSet<Capability> {
+	let mut capabilities = BTreeSet::new();
+	let mut stack: Vec<&Instruction> = instructions.into_iter().collect();
+
+	while let Some(instruction) = stack.pop() {
+		match instruction {
+			Instruction::Loop(inner) => stack.extend(inner),
+			Instruction::Extension(extension) => capabilities.extend(extension.capability()),
+			_ => {}
+		}
+	}
+
+	capabilities
+}
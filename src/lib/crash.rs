@@ -0,0 +1,449 @@
+use std::{
+	collections::{BTreeMap, HashMap, VecDeque},
+	fmt,
+};
+
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+
+use crate::{
+	bytecode::Op,
+	engine::{Engine, RunError, RuntimeSettings},
+	hooks::Hooks,
+	instruction::Instruction,
+	labels::CellLabels,
+	program::Program,
+	token::Span,
+	trace::fnv1a,
+	view::EngineView,
+};
+
+/// Version of the crash dump format.
+pub const CRASH_VERSION: u32 = 1;
+
+/// The state of a run at the moment it was aborted, saved so it can be looked into afterwards,
+/// see [`History`].
+///
+/// Dumps are stored as JSON, in `.bfcrash` files by convention, and shown by the `debug --core`
+/// subcommand.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct CrashDump {
+	/// Version of the format, see [`CRASH_VERSION`].
+	pub version: u32,
+	/// Why the run was aborted.
+	pub reason: String,
+	/// Hash of the program, see [`program_hash`], for telling which program a dump belongs to.
+	pub program_hash: u64,
+	/// Number of instructions (or ops) executed before the run was aborted.
+	pub steps: u64,
+	/// Index of the current cell.
+	pub pointer: usize,
+	/// Number of cells on the tape.
+	pub tape_length: usize,
+	/// Cells up to the last one that isn't zero, since the rest of a tape is usually empty.
+	pub tape: Vec<u8>,
+	/// What the program did last, oldest first.
+	pub history: Vec<Event>,
+	/// Settings of the run, by name.
+	pub settings: BTreeMap<String, String>,
+	/// Names of the cells, see [`Engine::labels`].
+	#[serde(default, skip_serializing_if = "CellLabels::is_empty")]
+	pub labels: CellLabels,
+}
+
+/// Something a program did, as recorded in [`CrashDump::history`].
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum Event {
+	/// The tree engine executed an instruction.
+	Instruction {
+		/// Number of instructions executed before it.
+		step: u64,
+		/// Its symbol, `[` for loops.
+		symbol: char,
+		/// Where it is in the source code, if that's known.
+		#[serde(default, skip_serializing_if = "Option::is_none")]
+		location: Option<Span>,
+		/// Index of the current cell right before it.
+		pointer: usize,
+		/// Value of the current cell right before it.
+		cell: u8,
+	},
+	/// The bytecode engine executed an op.
+	Op {
+		/// Number of ops executed before it.
+		step: u64,
+		/// Its position in [`Bytecode::ops`](`crate::bytecode::Bytecode::ops`).
+		index: usize,
+		/// The op, as it's debug-printed.
+		op: String,
+		/// Index of the current cell right before it.
+		pointer: usize,
+		/// Value of the current cell right before it.
+		cell: u8,
+	},
+	/// The program read a byte.
+	Input {
+		/// Number of instructions executed before the read.
+		step: u64,
+		/// The byte.
+		byte: u8,
+	},
+	/// The program printed a byte.
+	Output {
+		/// Number of instructions executed before the print.
+		step: u64,
+		/// The byte.
+		byte: u8,
+	},
+}
+
+/// An error returned when a [`CrashDump`] can't be loaded.
+#[derive(Debug, Clone, PartialEq, Eq, Error)]
+pub enum CrashDumpError {
+	/// The input isn't a crash dump at all
+	#[error("malformed crash dump: {0}")]
+	Malformed(String),
+	/// The dump was written by an incompatible version of the format
+	#[error("unsupported crash dump version {found}, only version {supported} is supported")]
+	UnsupportedVersion {
+		/// Version found in the dump.
+		found: u32,
+		/// Version this library understands.
+		supported: u32,
+	},
+}
+
+impl CrashDump {
+	/// Serialize the dump as JSON.
+	pub fn to_json(&self) -> String {
+		serde_json::to_string_pretty(self).expect("crash dump serialization never fails")
+	}
+
+	/// Deserialize a dump produced by [`CrashDump::to_json`].
+	///
+	/// # Errors
+	///
+	/// Fails if the input isn't a crash dump, or one of another version.
+	pub fn from_json(json: &str) -> Result<Self, CrashDumpError> {
+		let version = serde_json::from_str::<serde_json::Value>(json)
+			.map_err(|e| CrashDumpError::Malformed(e.to_string()))?["version"]
+			.as_u64();
+		if let Some(found) = version.filter(|&version| version != u64::from(CRASH_VERSION)) {
+			return Err(CrashDumpError::UnsupportedVersion {
+				found: u32::try_from(found).unwrap_or(u32::MAX),
+				supported: CRASH_VERSION,
+			});
+		}
+
+		serde_json::from_str(json).map_err(|e| CrashDumpError::Malformed(e.to_string()))
+	}
+}
+
+impl fmt::Display for CrashDump {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		writeln!(f, "aborted:      {}", self.reason)?;
+		writeln!(f, "program hash: {:016x}", self.program_hash)?;
+		writeln!(f, "steps:        {}", self.steps)?;
+		writeln!(
+			f,
+			"pointer:      {} (cell {})",
+			self.pointer,
+			self.tape.get(self.pointer).copied().unwrap_or_default()
+		)?;
+
+		writeln!(
+			f,
+			"\ntape ({} of {} cells shown, the rest are zero):",
+			self.tape.len(),
+			self.tape_length
+		)?;
+		for (row, cells) in self.tape.chunks(16).enumerate() {
+			let cells: Vec<String> = cells.iter().map(|cell| format!("{cell:02x}")).collect();
+			writeln!(f, "{:>8}: {}", row * 16, cells.join(" "))?;
+		}
+		if !self.labels.is_empty() {
+			writeln!(f, "\nlabels:")?;
+			for (index, label) in self.labels.iter() {
+				let value = self.tape.get(index).copied().unwrap_or_default();
+				writeln!(f, "{index:>8}: {label} = {value}")?;
+			}
+		}
+
+		writeln!(f, "\nsettings:")?;
+		for (name, value) in &self.settings {
+			writeln!(f, "  {name}: {value}")?;
+		}
+
+		writeln!(f, "\nlast {} events, oldest first:", self.history.len())?;
+		for event in &self.history {
+			match event {
+				Event::Instruction {
+					step,
+					symbol,
+					location,
+					pointer,
+					cell,
+				} => {
+					let location = location.map_or_else(|| "?".to_owned(), |span| span.to_string());
+					writeln!(
+						f,
+						"  step {step} at {location}: `{symbol}` on {} = {cell}",
+						self.labels.name(*pointer)
+					)?;
+				}
+				Event::Op {
+					step,
+					index,
+					op,
+					pointer,
+					cell,
+				} => writeln!(
+					f,
+					"  step {step}: op {index} {op} on {} = {cell}",
+					self.labels.name(*pointer)
+				)?,
+				Event::Input { step, byte } => writeln!(f, "  step {step}: read {byte:#04x}")?,
+				Event::Output { step, byte } => writeln!(f, "  step {step}: printed {byte:#04x}")?,
+			}
+		}
+
+		Ok(())
+	}
+}
+
+/// Hooks that remember the last events of a run in a ring buffer, so a [`CrashDump`] can be made
+/// if it's aborted.
+///
+/// # Usage
+///
+/// ```
+/// # use brainfuck_rs::{
+/// #   crash::History,
+/// #   engine::{Engine, RuntimeSettings},
+/// #   host::StdHost,
+/// #   program::Program,
+/// # };
+/// let program = Program::parse("+[+]").unwrap();
+/// let settings = RuntimeSettings {
+///     budget: Some(100),
+///     ..Default::default()
+/// };
+///
+/// let mut bf = Engine::default();
+/// let mut history = History::new(&program, 8);
+/// let error = bf
+///     .run_with(
+///         &program.instructions,
+///         &mut std::io::empty(),
+///         &mut std::io::sink(),
+///         settings.clone(),
+///         &mut StdHost::default(),
+///         &mut history,
+///     )
+///     .unwrap_err();
+/// let dump = history.dump(&error, &bf, &settings);
+///
+/// assert_eq!(8, dump.history.len());
+/// assert_eq!(99, dump.steps);
+/// ```
+pub struct History<'a> {
+	program: &'a Program,
+	/// Pre-order index of every instruction, keyed by its address, since the engine only hands
+	/// out references.
+	indices: HashMap<*const Instruction, usize>,
+	capacity: usize,
+	events: VecDeque<Event>,
+	last_step: u64,
+}
+
+impl<'a> History<'a> {
+	/// Remember the last `capacity` events of a run of `program`.
+	pub fn new(program: &'a Program, capacity: usize) -> Self {
+		Self {
+			program,
+			indices: program.indices(),
+			capacity,
+			events: VecDeque::with_capacity(capacity),
+			last_step: 0,
+		}
+	}
+
+	/// Make a dump of the run that `bf` was aborted from with `error`.
+	pub fn dump(self, error: &RunError, bf: &Engine, settings: &RuntimeSettings) -> CrashDump {
+		let used = bf
+			.tape
+			.iter()
+			.rposition(|cell| cell.0 != 0)
+			.map_or(0, |last| last + 1);
+
+		CrashDump {
+			version: CRASH_VERSION,
+			reason: error.to_string(),
+			program_hash: program_hash(self.program),
+			steps: self.last_step,
+			pointer: bf.pointer,
+			tape_length: bf.tape.len(),
+			tape: bf.tape[..used].iter().map(|cell| cell.0).collect(),
+			history: self.events.into(),
+			settings: describe(settings),
+			labels: bf.labels.clone(),
+		}
+	}
+
+	fn record(&mut self, step: u64, event: Event) {
+		self.last_step = step;
+
+		if self.capacity == 0 {
+			return;
+		}
+		if self.events.len() == self.capacity {
+			self.events.pop_front();
+		}
+		self.events.push_back(event);
+	}
+}
+
+impl Hooks for History<'_> {
+	const WATCHES_WRAPS: bool = false;
+
+	fn before_instruction(&mut self, step: u64, instruction: &Instruction, bf: EngineView<'_>) {
+		let symbol = instruction.symbol();
+		let location = self
+			.indices
+			.get(&std::ptr::from_ref(instruction))
+			.and_then(|&index| self.program.span(index));
+
+		self.record(
+			step,
+			Event::Instruction {
+				step,
+				symbol,
+				location,
+				pointer: bf.pointer(),
+				cell: bf.cell(),
+			},
+		);
+	}
+
+	fn before_op(&mut self, step: u64, index: usize, op: Op, bf: EngineView<'_>) {
+		self.record(
+			step,
+			Event::Op {
+				step,
+				index,
+				op: format!("{op:?}"),
+				pointer: bf.pointer(),
+				cell: bf.cell(),
+			},
+		);
+	}
+
+	fn on_input(&mut self, step: u64, byte: u8) {
+		self.record(step, Event::Input { step, byte });
+	}
+
+	fn on_output(&mut self, step: u64, byte: u8) {
+		self.record(step, Event::Output { step, byte });
+	}
+}
+
+/// FNV-1a hash of a program in the `.bfo` format, which includes the source locations of its
+/// instructions, if it has them.
+pub fn program_hash(program: &Program) -> u64 {
+	fnv1a(&program.to_bfo())
+}
+
+/// Settings that affect how a program runs, by name.
+fn describe(settings: &RuntimeSettings) -> BTreeMap<String, String> {
+	let extensions: Vec<String> = settings
+		.extensions
+		.iter()
+		.map(|extension| extension.symbol().to_string())
+		.collect();
+
+	[
+		("should_flush", settings.should_flush.to_string()),
+		("quit_on_eof", settings.quit_on_eof.to_string()),
+		("time_source", format!("{:?}", settings.time_source)),
+		("capabilities", format!("{:?}", settings.capabilities)),
+		("grow_tape", settings.grow_tape.to_string()),
+		("budget", format!("{:?}", settings.budget)),
+		(
+			"specialize_after",
+			format!("{:?}", settings.specialize_after),
+		),
+		("read_ahead", settings.read_ahead.to_string()),
+		("break_on_output", format!("{:?}", settings.break_on_output)),
+		("idle_loops", format!("{:?}", settings.idle_loops)),
+		("newlines", format!("{:?}", settings.newlines)),
+		("extensions", extensions.concat()),
+		("contain_panics", settings.contain_panics.to_string()),
+	]
+	.into_iter()
+	.map(|(name, value)| (name.to_owned(), value))
+	.collect()
+}
+
+#[cfg(test)]
+mod tests {
+	use crate::{host::StdHost, token::Span};
+
+	use super::*;
+
+	#[test]
+	fn dumps() {
+		let program = Program::parse(",>++\n[+]").unwrap();
+		let settings = RuntimeSettings {
+			budget: Some(10),
+			..Default::default()
+		};
+
+		let mut bf = Engine::default();
+		let mut history = History::new(&program, 3);
+		let error = bf
+			.run_with(
+				&program.instructions,
+				&mut b"a".as_slice(),
+				&mut std::io::sink(),
+				settings.clone(),
+				&mut StdHost::default(),
+				&mut history,
+			)
+			.unwrap_err();
+		let dump = history.dump(&error, &bf, &settings);
+
+		assert_eq!(9, dump.steps);
+		assert_eq!(1, dump.pointer);
+		assert_eq!(vec![b'a', 5], dump.tape);
+		assert_eq!(
+			Event::Instruction {
+				step: 9,
+				symbol: '+',
+				location: Some(Span {
+					start: 6,
+					end: 7,
+					line: 2,
+					column: 2
+				}),
+				pointer: 1,
+				cell: 4,
+			},
+			dump.history[2]
+		);
+		assert_eq!("Some(10)", dump.settings["budget"]);
+
+		assert_eq!(Ok(dump.clone()), CrashDump::from_json(&dump.to_json()));
+		assert_eq!(
+			Err(CrashDumpError::UnsupportedVersion {
+				found: 2,
+				supported: CRASH_VERSION
+			}),
+			CrashDump::from_json(
+				&dump
+					.to_json()
+					.replacen("\"version\": 1", "\"version\": 2", 1)
+			)
+		);
+	}
+}
@@ -0,0 +1,404 @@
+use std::{
+	io::{self, BufRead, Write},
+	ops::ControlFlow,
+};
+
+use crate::{
+	breakpoint::{Breakpoint, Breakpoints, Hit},
+	hooks::Hooks,
+	instruction::Instruction,
+	program::Program,
+	view::EngineView,
+};
+
+/// An interactive debugger, as hooks that stop the program before its first instruction and at
+/// breakpoints, and wait for commands, one per line, read from `commands`, replying to `replies`:
+///
+/// - `break LINE:COLUMN [if CONDITION]`, or `b`, adds a [`Breakpoint`].
+/// - `until LINE:COLUMN`, or `u`, runs the program until it gets to that place, unless it gets to
+///   a breakpoint first, which leaves the place to stop at later.
+/// - `tbreak STEPS` stops the program once it ran that many more instructions, without running it.
+/// - `step`, or `s`, runs a single instruction.
+/// - `continue`, or `c`, runs the program until it gets to a breakpoint.
+/// - `print`, or `p`, shows where the program is and the current cell.
+/// - `frames`, or `bt`, lists the loops the program is in, innermost first, like frames of a call
+///   stack: where each one is, which iteration it's in, and the cell it checks.
+/// - `frame N`, or `f N`, shows the frame with that number, `up` the one around it, and `down` the
+///   one in it, starting from the innermost one every time the program stops.
+/// - `detach`, or `d`, lets the program run on without the debugger.
+/// - `quit`, or `q`, ends the program there, like the end of `commands` does, unless the debugger
+///   was [attached](`Debugger::attach`) to the program.
+/// - `help`, or `h`, lists the commands.
+///
+/// Only the tree engine calls the hooks this needs. Ending the program sets
+/// [`RunSummary::breakpoint_hit`](`crate::engine::RunSummary::breakpoint_hit`). Set
+/// [`RuntimeSettings::should_flush`](`crate::engine::RuntimeSettings::should_flush`) to see what
+/// the program printed before it stopped.
+///
+/// # Usage
+///
+/// ```
+/// # use brainfuck_rs::{
+/// #   debugger::Debugger,
+/// #   engine::{Engine, RuntimeSettings},
+/// #   host::StdHost,
+/// #   program::Program,
+/// # };
+/// let program = Program::parse("++[>+<-]").unwrap();
+/// let mut replies = vec![];
+/// let mut debugger = Debugger::new(&program, "until 1:5\ncontinue\n".as_bytes(), &mut replies);
+///
+/// Engine::default()
+///     .run_with(
+///         &program.instructions,
+///         &mut [].as_slice(),
+///         &mut vec![],
+///         RuntimeSettings::default(),
+///         &mut StdHost::default(),
+///         &mut debugger,
+///     )
+///     .unwrap();
+/// debugger.finish().unwrap();
+///
+/// assert!(String::from_utf8(replies)
+///     .unwrap()
+///     .contains("step 4 at 1:5 `+`, cell 1 = 0"));
+/// ```
+pub struct Debugger<'a, C, R> {
+	program: &'a Program,
+	breakpoints: Breakpoints,
+	commands: C,
+	replies: R,
+	/// Number of the frame `up` and `down` move from, 0 being the innermost loop.
+	frame: usize,
+	/// Whether the end of `commands` detaches the debugger, instead of ending the program.
+	attached: bool,
+	detached: bool,
+	quit: bool,
+	error: Option<io::Error>,
+}
+
+impl<'a, C: BufRead, R: Write> Debugger<'a, C, R> {
+	/// Debug `program`, stopping before its first instruction.
+	pub fn new(program: &'a Program, commands: C, replies: R) -> Self {
+		Self::stopping_at(program, commands, replies, 0, false)
+	}
+
+	/// Debug `program` from the middle of a run, stopping before the instruction it runs after
+	/// `step` instructions. Once `commands` end, the program runs on without the debugger, like
+	/// with `detach`, since whoever was sending them went away.
+	pub fn attach(program: &'a Program, commands: C, replies: R, step: u64) -> Self {
+		Self::stopping_at(program, commands, replies, step, true)
+	}
+
+	fn stopping_at(
+		program: &'a Program,
+		commands: C,
+		replies: R,
+		step: u64,
+		attached: bool,
+	) -> Self {
+		let mut breakpoints = Breakpoints::default();
+		breakpoints.stop_at_step(step);
+
+		Self {
+			program,
+			breakpoints,
+			commands,
+			replies,
+			frame: 0,
+			attached,
+			detached: false,
+			quit: false,
+			error: None,
+		}
+	}
+
+	/// Whether the program runs on without the debugger, because of `detach`, or because the
+	/// commands ran out after [`Debugger::attach`].
+	pub const fn detached(&self) -> bool {
+		self.detached
+	}
+
+	/// Whether the program was ended with `quit`, or because the commands ran out after
+	/// [`Debugger::new`].
+	pub const fn quit(&self) -> bool {
+		self.quit
+	}
+
+	/// Hand back the breakpoints, once the program ends.
+	///
+	/// # Errors
+	///
+	/// Fails if reading commands or writing replies failed at some point, which ends the program.
+	pub fn finish(self) -> io::Result<Breakpoints> {
+		match self.error {
+			Some(error) => Err(error),
+			None => Ok(self.breakpoints),
+		}
+	}
+
+	/// Take commands until one of them resumes or ends the program.
+	fn prompt(
+		&mut self,
+		hit: &Hit,
+		instruction: &Instruction,
+		bf: EngineView<'_>,
+	) -> io::Result<()> {
+		if let Some(breakpoint) = &hit.breakpoint {
+			writeln!(self.replies, "stopped at breakpoint {breakpoint}")?;
+		}
+		self.show(hit.step, instruction, bf)?;
+		self.frame = 0;
+
+		loop {
+			write!(self.replies, "(bf) ")?;
+			self.replies.flush()?;
+
+			let mut line = String::new();
+			if self.commands.read_line(&mut line)? == 0 {
+				if self.attached {
+					self.detached = true;
+				} else {
+					self.quit = true;
+				}
+				return Ok(());
+			}
+
+			if self
+				.command(line.trim(), hit.step, instruction, bf)?
+				.is_break()
+			{
+				return Ok(());
+			}
+		}
+	}
+
+	/// Run `command`, breaking if the program resumes or ends.
+	fn command(
+		&mut self,
+		command: &str,
+		step: u64,
+		instruction: &Instruction,
+		bf: EngineView<'_>,
+	) -> io::Result<ControlFlow<()>> {
+		let (name, argument) = command.split_once(' ').unwrap_or((command, ""));
+
+		match name {
+			"" => {}
+			"break" | "b" | "until" | "u" => {
+				let breakpoint = match argument.parse::<Breakpoint>() {
+					Ok(breakpoint) => breakpoint,
+					Err(e) => {
+						writeln!(self.replies, "error: {e}")?;
+						return Ok(ControlFlow::Continue(()));
+					}
+				};
+
+				let temporary = matches!(name, "until" | "u");
+				let placed = if temporary {
+					self.breakpoints
+						.add_temporary(self.program, breakpoint.clone())
+				} else {
+					self.breakpoints.add(self.program, breakpoint.clone())
+				};
+
+				match placed {
+					Ok(()) if temporary => return Ok(ControlFlow::Break(())),
+					Ok(()) => writeln!(self.replies, "breakpoint at {breakpoint}")?,
+					Err(e) => writeln!(self.replies, "error: {e}")?,
+				}
+			}
+			"tbreak" => match argument.trim().parse::<u64>() {
+				Ok(steps) if steps > 0 => {
+					self.breakpoints.stop_at_step(step.saturating_add(steps));
+					writeln!(
+						self.replies,
+						"stopping after {steps} more instructions, at step {}",
+						step.saturating_add(steps)
+					)?;
+				}
+				_ => writeln!(
+					self.replies,
+					"error: expected a number of instructions, found `{argument}`"
+				)?,
+			},
+			"step" | "s" => {
+				self.breakpoints.stop_at_step(step + 1);
+				return Ok(ControlFlow::Break(()));
+			}
+			"continue" | "c" => return Ok(ControlFlow::Break(())),
+			"print" | "p" => self.show(step, instruction, bf)?,
+			"frames" | "bt" => {
+				if bf.loops().is_empty() {
+					writeln!(self.replies, "not in a loop")?;
+				}
+				for number in 0..bf.loops().len() {
+					self.show_frame(number, bf)?;
+				}
+			}
+			"frame" | "f" | "up" | "down" => {
+				let frames = bf.loops().len();
+				let number = match name {
+					"up" => self.frame.checked_add(1),
+					"down" => self.frame.checked_sub(1),
+					_ => argument.trim().parse().ok(),
+				};
+
+				match number {
+					Some(number) if number < frames => {
+						self.frame = number;
+						self.show_frame(number, bf)?;
+					}
+					_ if frames == 0 => writeln!(self.replies, "error: not in a loop")?,
+					_ => writeln!(
+						self.replies,
+						"error: expected a frame from 0 to {}, the outermost loop",
+						frames - 1
+					)?,
+				}
+			}
+			"detach" | "d" => {
+				self.detached = true;
+				return Ok(ControlFlow::Break(()));
+			}
+			"quit" | "q" => {
+				self.quit = true;
+				return Ok(ControlFlow::Break(()));
+			}
+			"help" | "h" => write!(self.replies, "{HELP}")?,
+			_ => writeln!(self.replies, "error: unknown command `{name}`, see `help`")?,
+		}
+
+		Ok(ControlFlow::Continue(()))
+	}
+
+	/// Tell where the program is, like `step 4 at 1:5 `+`, cell 1 = 0`.
+	fn show(&mut self, step: u64, instruction: &Instruction, bf: EngineView<'_>) -> io::Result<()> {
+		let place = self
+			.program
+			.index_of(instruction)
+			.and_then(|index| self.program.span(index))
+			.map_or_else(|| "?".to_owned(), |span| span.to_string());
+
+		writeln!(
+			self.replies,
+			"step {step} at {place} `{}`, {} = {}",
+			instruction.symbol(),
+			bf.labels().name(bf.pointer()),
+			bf.cell()
+		)
+	}
+
+	/// Tell about the loop with frame `number`, counted from the innermost one, like
+	/// `#1 at 1:3, iteration 2, cell 0 = 1`.
+	fn show_frame(&mut self, number: usize, bf: EngineView<'_>) -> io::Result<()> {
+		let loops = bf.loops();
+		let frame = loops[loops.len() - 1 - number];
+		let place = frame
+			.index(self.program)
+			.and_then(|index| self.program.span(index))
+			.map_or_else(|| "?".to_owned(), |span| span.to_string());
+
+		writeln!(
+			self.replies,
+			"#{number} at {place}, iteration {}, {} = {}",
+			frame.iterations,
+			bf.labels().name(frame.guard),
+			bf.get(frame.guard).unwrap_or_default()
+		)
+	}
+}
+
+/// What `help` replies.
+const HELP: &str = "\
+break LINE:COLUMN [if CONDITION]  stop whenever the program gets there (b)
+until LINE:COLUMN                 run until the program gets there (u)
+tbreak STEPS                      stop once the program ran that many more instructions
+step                              run a single instruction (s)
+continue                          run until a breakpoint (c)
+print                             show where the program is and the current cell (p)
+frames                            list the loops the program is in, innermost first (bt)
+frame N                           show the loop with that number (f)
+up                                show the loop around the one shown
+down                              show the loop in the one shown
+detach                            let the program run on without the debugger (d)
+quit                              end the program (q)
+help                              show this (h)
+";
+
+impl<C: BufRead, R: Write> Hooks for Debugger<'_, C, R> {
+	fn before_instruction(&mut self, step: u64, instruction: &Instruction, bf: EngineView<'_>) {
+		if self.quit || self.detached {
+			return;
+		}
+
+		self.breakpoints.before_instruction(step, instruction, bf);
+		let Some(hit) = self.breakpoints.take_hit() else {
+			return;
+		};
+
+		if let Err(e) = self.prompt(&hit, instruction, bf) {
+			self.error = Some(e);
+			// NOTE: losing whoever attached the debugger is no reason to end the program
+			if self.attached {
+				self.detached = true;
+			} else {
+				self.quit = true;
+			}
+		}
+	}
+
+	fn should_break(&mut self) -> bool {
+		self.quit
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::{
+		engine::{Engine, RuntimeSettings},
+		host::StdHost,
+	};
+
+	#[test]
+	fn until_and_tbreak_stop_once_and_loops_are_frames() {
+		let program = Program::parse("++[>+<-]\n>.").unwrap();
+		let commands = "b 1:6\nuntil 2:1\nprint\nbt\nup\ntbreak 0\ntbreak 1\nc\nq\n";
+		let mut replies = vec![];
+		let mut output = vec![];
+
+		let mut debugger = Debugger::new(&program, commands.as_bytes(), &mut replies);
+		let summary = Engine::default()
+			.run_with(
+				&program.instructions,
+				&mut [].as_slice(),
+				&mut output,
+				RuntimeSettings::default(),
+				&mut StdHost::default(),
+				&mut debugger,
+			)
+			.unwrap();
+		assert!(debugger.quit());
+		debugger.finish().unwrap();
+
+		// NOTE: `until` stops at the breakpoint on its way, and the breakpoint stays
+		assert_eq!(
+			"step 0 at 1:1 `+`, cell 0 = 0\n\
+			 (bf) breakpoint at 1:6\n\
+			 (bf) stopped at breakpoint 1:6\n\
+			 step 5 at 1:6 `<`, cell 1 = 1\n\
+			 (bf) step 5 at 1:6 `<`, cell 1 = 1\n\
+			 (bf) #0 at 1:3, iteration 1, cell 0 = 2\n\
+			 (bf) error: expected a frame from 0 to 0, the outermost loop\n\
+			 (bf) error: expected a number of instructions, found `0`\n\
+			 (bf) stopping after 1 more instructions, at step 6\n\
+			 (bf) step 6 at 1:7 `-`, cell 0 = 2\n\
+			 (bf) ",
+			String::from_utf8(replies).unwrap()
+		);
+		assert!(summary.breakpoint_hit);
+		assert!(output.is_empty());
+	}
+}
@@ -0,0 +1,236 @@
+use std::{
+	collections::{BTreeMap, HashMap},
+	fmt,
+	io::Write,
+};
+
+use crate::{
+	hooks::{Hooks, Wrap},
+	instruction::Instruction,
+	program::Program,
+	token::Span,
+	view::EngineView,
+};
+
+/// Hooks that log every time a cell or the pointer wraps around while the program runs on the tree
+/// engine, one line each, e.g. `step 12 at 1:5: cell 3 wrapped from 0 to 255`, for checking that
+/// a program doesn't rely on wrapping by accident. Execution isn't affected.
+///
+/// # Usage
+///
+/// ```
+/// # use brainfuck_rs::{
+/// #   diagnostics::WrapCheck,
+/// #   engine::{Engine, RuntimeSettings},
+/// #   host::StdHost,
+/// #   program::Program,
+/// # };
+/// let program = Program::parse("+\n-->").unwrap();
+///
+/// let mut log = vec![];
+/// let mut check = WrapCheck::new(&program, &mut log);
+/// Engine::default()
+///     .run_with(
+///         &program.instructions,
+///         &mut [].as_slice(),
+///         &mut vec![],
+///         RuntimeSettings::default(),
+///         &mut StdHost::default(),
+///         &mut check,
+///     )
+///     .unwrap();
+/// let summary = check.finish();
+///
+/// assert_eq!(1, summary.total());
+/// assert_eq!(
+///     "step 2 at 2:2: cell 0 wrapped from 0 to 255\n",
+///     String::from_utf8(log).unwrap(),
+/// );
+/// ```
+pub struct WrapCheck<'a, W> {
+	program: &'a Program,
+	/// Pre-order index of every instruction, keyed by its address, since the engine only hands
+	/// out references.
+	indices: HashMap<*const Instruction, usize>,
+	current: Option<usize>,
+	log: W,
+	summary: WrapSummary,
+}
+
+impl<'a, W: Write> WrapCheck<'a, W> {
+	/// Check a run of `program`, writing every wrap into `log`.
+	pub fn new(program: &'a Program, log: W) -> Self {
+		fn walk(instructions: &[Instruction], indices: &mut HashMap<*const Instruction, usize>) {
+			for instruction in instructions {
+				indices.insert(instruction, indices.len());
+
+				if let Instruction::Loop(body) = instruction {
+					walk(body, indices);
+				}
+			}
+		}
+
+		let mut indices = HashMap::new();
+		walk(&program.instructions, &mut indices);
+
+		Self {
+			program,
+			indices,
+			current: None,
+			log,
+			summary: WrapSummary::default(),
+		}
+	}
+
+	/// Stop checking, returning how many times things wrapped around where.
+	pub fn finish(self) -> WrapSummary {
+		self.summary
+	}
+
+	fn span(&self) -> Option<Span> {
+		self.current.and_then(|index| self.program.span(index))
+	}
+}
+
+impl<W: Write> Hooks for WrapCheck<'_, W> {
+	fn before_instruction(&mut self, _step: u64, instruction: &Instruction, _bf: EngineView<'_>) {
+		self.current = self.indices.get(&(instruction as *const _)).copied();
+	}
+
+	fn on_wrap(&mut self, step: u64, wrap: Wrap) {
+		let span = self.span();
+		let location = span.map_or_else(|| "?".to_owned(), |span| span.to_string());
+		let description = match wrap {
+			Wrap::CellOverflow(cell) => format!("cell {cell} wrapped from 255 to 0"),
+			Wrap::CellUnderflow(cell) => format!("cell {cell} wrapped from 0 to 255"),
+			Wrap::PointerPastEnd => "pointer wrapped from the last cell to the first".to_owned(),
+			Wrap::PointerPastStart => "pointer wrapped from the first cell to the last".to_owned(),
+		};
+
+		// NOTE: hooks can't fail, and a broken log shouldn't stop the program
+		let _ = writeln!(self.log, "step {step} at {location}: {description}");
+
+		*self
+			.summary
+			.counts
+			.entry((span, WrapKind::of(wrap)))
+			.or_default() += 1;
+	}
+}
+
+/// How many times things wrapped around where, see [`WrapCheck::finish`].
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct WrapSummary {
+	/// Number of wraps of every kind, by the location of the instruction that caused them, if it's
+	/// known.
+	pub counts: BTreeMap<(Option<Span>, WrapKind), u64>,
+}
+
+impl WrapSummary {
+	/// Number of wraps in total.
+	pub fn total(&self) -> u64 {
+		self.counts.values().sum()
+	}
+}
+
+impl fmt::Display for WrapSummary {
+	/// The total, followed by a line for every location and kind, in the order of the source.
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		if self.counts.is_empty() {
+			return writeln!(f, "nothing wrapped around");
+		}
+
+		writeln!(f, "wrapped around {} times:", self.total())?;
+		for (&(span, kind), count) in &self.counts {
+			let location = span.map_or_else(|| "?".to_owned(), |span| span.to_string());
+			writeln!(f, "  {location}: {count}× {kind}")?;
+		}
+
+		Ok(())
+	}
+}
+
+/// [`Wrap`] without the cell, for counting.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum WrapKind {
+	/// See [`Wrap::CellOverflow`].
+	CellOverflow,
+	/// See [`Wrap::CellUnderflow`].
+	CellUnderflow,
+	/// See [`Wrap::PointerPastEnd`].
+	PointerPastEnd,
+	/// See [`Wrap::PointerPastStart`].
+	PointerPastStart,
+}
+
+impl WrapKind {
+	const fn of(wrap: Wrap) -> Self {
+		match wrap {
+			Wrap::CellOverflow(_) => Self::CellOverflow,
+			Wrap::CellUnderflow(_) => Self::CellUnderflow,
+			Wrap::PointerPastEnd => Self::PointerPastEnd,
+			Wrap::PointerPastStart => Self::PointerPastStart,
+		}
+	}
+}
+
+impl fmt::Display for WrapKind {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		f.write_str(match self {
+			Self::CellOverflow => "cell overflowed",
+			Self::CellUnderflow => "cell underflowed",
+			Self::PointerPastEnd => "pointer wrapped past the end",
+			Self::PointerPastStart => "pointer wrapped past the start",
+		})
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use crate::{
+		engine::{Engine, RuntimeSettings},
+		host::StdHost,
+	};
+
+	use super::*;
+
+	#[test]
+	fn wraps() {
+		let program = Program::parse("-+<>>\n[-]-[+]").unwrap();
+
+		let mut log = vec![];
+		let mut check = WrapCheck::new(&program, &mut log);
+		Engine::new(2)
+			.run_with(
+				&program.instructions,
+				&mut [].as_slice(),
+				&mut vec![],
+				RuntimeSettings::default(),
+				&mut StdHost::default(),
+				&mut check,
+			)
+			.unwrap();
+		let summary = check.finish();
+
+		assert_eq!(
+			"step 0 at 1:1: cell 0 wrapped from 0 to 255\n\
+			 step 1 at 1:2: cell 0 wrapped from 255 to 0\n\
+			 step 2 at 1:3: pointer wrapped from the first cell to the last\n\
+			 step 3 at 1:4: pointer wrapped from the last cell to the first\n\
+			 step 6 at 2:4: cell 1 wrapped from 0 to 255\n\
+			 step 8 at 2:6: cell 1 wrapped from 255 to 0\n",
+			String::from_utf8(log).unwrap()
+		);
+		assert_eq!(6, summary.total());
+		assert_eq!(
+			"wrapped around 6 times:\n  \
+			 1:1: 1× cell underflowed\n  \
+			 1:2: 1× cell overflowed\n  \
+			 1:3: 1× pointer wrapped past the start\n  \
+			 1:4: 1× pointer wrapped past the end\n  \
+			 2:4: 1× cell underflowed\n  \
+			 2:6: 1× cell overflowed\n",
+			summary.to_string()
+		);
+	}
+}
@@ -0,0 +1,258 @@
+use std::{
+	env,
+	fmt::Write as _,
+	fs, io,
+	path::{Path, PathBuf},
+};
+
+use crate::{
+	bytecode::{Bytecode, Fusion, Op},
+	capability::Capability,
+	extension::Extension,
+	optimize::{OptLevel, PassManager},
+	program::Program,
+	token::{Span, Token},
+};
+
+/// A program compiled when the crate embedding it was built, see [`Embed`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct CompiledProgram {
+	/// Ops of the program, as the `fused` engine runs them.
+	pub ops: &'static [Op],
+	/// Capabilities its extension instructions need.
+	pub capabilities: &'static [Capability],
+}
+
+impl CompiledProgram {
+	/// The bytecode of the program, for
+	/// [`Engine::run_bytecode`](`crate::engine::Engine::run_bytecode`).
+	pub fn to_bytecode(&self) -> Bytecode {
+		Bytecode::from_parts(
+			self.ops.to_vec(),
+			self.capabilities.iter().copied().collect(),
+		)
+	}
+}
+
+/// Compiles Brainfuck programs into Rust code in build scripts, so crates can ship them compiled
+/// instead of parsing them whenever they start.
+///
+/// The generated code is an expression of a [`CompiledProgram`], meant to be included where the
+/// program is needed. A program that doesn't parse becomes a `compile_error!` telling where in
+/// the Brainfuck source the problem is, so it fails the build like Rust code would.
+///
+/// # Usage
+///
+/// In `build.rs`:
+///
+/// ```no_run
+/// # use brainfuck_rs::embed::Embed;
+/// Embed::default().write("programs/hello.b").unwrap();
+/// ```
+///
+/// And in the crate:
+///
+/// ```ignore
+/// use brainfuck_rs::{embed::CompiledProgram, engine::{Engine, RuntimeSettings}};
+///
+/// static HELLO: CompiledProgram = include!(concat!(env!("OUT_DIR"), "/hello.rs"));
+///
+/// Engine::default().run_bytecode(
+///     &HELLO.to_bytecode(),
+///     &mut std::io::stdin(),
+///     &mut std::io::stdout(),
+///     RuntimeSettings::default(),
+/// )?;
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Embed {
+	/// Extensions whose instructions programs may use.
+	pub extensions: Vec<Extension>,
+	/// How hard programs are optimized, see [`PassManager::preset`].
+	pub opt_level: OptLevel,
+	/// Length of the tape programs will run on, which some passes need to know.
+	pub tape_length: usize,
+}
+
+impl Default for Embed {
+	/// Standard Brainfuck, optimized as much as it can be for a tape of 30000 cells.
+	fn default() -> Self {
+		Self {
+			extensions: vec![],
+			opt_level: OptLevel::Aggressive,
+			tape_length: 30_000,
+		}
+	}
+}
+
+impl Embed {
+	/// Rust code of the program with the given source code, or of a `compile_error!` if it doesn't
+	/// parse. `origin` tells where the source code is from in the error.
+	///
+	/// # Usage
+	///
+	/// ```
+	/// # use brainfuck_rs::embed::Embed;
+	/// let code = Embed::default().generate("+[-]]", "clear.b");
+	///
+	/// assert_eq!(
+	///     r#"::core::compile_error!("clear.b:1:5: could not find match for `]`")"#,
+	///     code
+	/// );
+	/// ```
+	pub fn generate(&self, code: &str, origin: &str) -> String {
+		let program = match Program::parse_with_extensions(code, &self.extensions) {
+			Ok(program) => PassManager::preset(self.opt_level, self.tape_length).run(&program),
+			Err(error) => {
+				let location = self
+					.unmatched(code)
+					.map_or_else(String::new, |span| format!(":{span}"));
+
+				return format!(
+					"::core::compile_error!({:?})",
+					format!("{origin}{location}: {error}")
+				);
+			}
+		};
+
+		let bytecode = Bytecode::compile(&program.instructions)
+			.collapse()
+			.multiply_loops()
+			.count_loops()
+			.address_offsets()
+			.fuse(Fusion::ALL);
+
+		let mut output = String::from("::brainfuck_rs::embed::CompiledProgram {\n\tops: &[\n");
+		for &op in bytecode.ops() {
+			let _ = writeln!(output, "\t\t{},", rust_op(op));
+		}
+		output.push_str("\t],\n\tcapabilities: &[");
+		for capability in bytecode.required_capabilities() {
+			let _ = write!(
+				output,
+				"::brainfuck_rs::capability::Capability::{capability:?}, "
+			);
+		}
+		output.push_str("],\n}\n");
+
+		output
+	}
+
+	/// Compile the program in the file at `path` into `<name>.rs` in the build script's output
+	/// directory, where `<name>` is the name of the file without its extension, and have Cargo
+	/// run the build script again when the file changes. Relative paths are relative to the
+	/// crate's manifest, like in build scripts.
+	///
+	/// Returns the path of the generated file.
+	///
+	/// # Errors
+	///
+	/// Fails if the file can't be read, or the generated code can't be written, like when it
+	/// doesn't run in a build script.
+	pub fn write(&self, path: impl AsRef<Path>) -> io::Result<PathBuf> {
+		let path = path.as_ref();
+		let output_directory = env::var_os("OUT_DIR").ok_or_else(|| {
+			io::Error::new(
+				io::ErrorKind::NotFound,
+				"`OUT_DIR` isn't set, since this isn't a build script",
+			)
+		})?;
+
+		let code = fs::read_to_string(path)?;
+		let generated = self.generate(&code, &path.display().to_string());
+
+		let name = path.file_stem().unwrap_or(path.as_os_str());
+		let output = Path::new(&output_directory).join(name).with_extension("rs");
+		fs::write(&output, generated)?;
+		println!("cargo:rerun-if-changed={}", path.display());
+
+		Ok(output)
+	}
+
+	/// Where the first unmatched bracket of `code` is, if there is one.
+	fn unmatched(&self, code: &str) -> Option<Span> {
+		let mut open = vec![];
+
+		for (token, span) in Token::tokenize_spanned(code, &self.extensions) {
+			match token {
+				Token::LoopStart => open.push(span),
+				Token::LoopEnd if open.pop().is_none() => return Some(span),
+				_ => {}
+			}
+		}
+
+		open.pop()
+	}
+}
+
+/// Rust expression of `op`.
+fn rust_op(op: Op) -> String {
+	const OP: &str = "::brainfuck_rs::bytecode::Op";
+
+	match op {
+		Op::Inc => format!("{OP}::Inc"),
+		Op::Dec => format!("{OP}::Dec"),
+		Op::Next => format!("{OP}::Next"),
+		Op::Prev => format!("{OP}::Prev"),
+		Op::Print => format!("{OP}::Print"),
+		Op::Read => format!("{OP}::Read"),
+		Op::JumpIfZero(target) => format!("{OP}::JumpIfZero({target})"),
+		Op::JumpIfNonZero(target) => format!("{OP}::JumpIfNonZero({target})"),
+		Op::Extension(extension) => {
+			let extension = match extension {
+				Extension::Time => "Time".to_owned(),
+				Extension::Exit => "Exit".to_owned(),
+				Extension::Custom(symbol) => format!("Custom({symbol:?})"),
+			};
+			format!("{OP}::Extension(::brainfuck_rs::extension::Extension::{extension})")
+		}
+		Op::Add(delta) => format!("{OP}::Add({delta})"),
+		Op::Move(offset) => format!("{OP}::Move({offset})"),
+		Op::Clear => format!("{OP}::Clear"),
+		Op::AddMove(delta, offset) => format!("{OP}::AddMove({delta}, {offset})"),
+		Op::ClearMove(offset) => format!("{OP}::ClearMove({offset})"),
+		Op::MoveAdd(offset, delta) => format!("{OP}::MoveAdd({offset}, {delta})"),
+		Op::JumpIfZeroAdd(target, delta) => format!("{OP}::JumpIfZeroAdd({target}, {delta})"),
+		Op::RepeatN(target, factor) => format!("{OP}::RepeatN({target}, {factor})"),
+		Op::RepeatEnd(target) => format!("{OP}::RepeatEnd({target})"),
+		Op::MulAdd(offset, factor) => format!("{OP}::MulAdd({offset}, {factor})"),
+		Op::ScanRight => format!("{OP}::ScanRight"),
+		Op::ScanLeft => format!("{OP}::ScanLeft"),
+		Op::AddAt(offset, delta) => format!("{OP}::AddAt({offset}, {delta})"),
+		Op::Set(value) => format!("{OP}::Set({value})"),
+		Op::AddPrint(delta) => format!("{OP}::AddPrint({delta})"),
+		Op::Fill(offset, value) => format!("{OP}::Fill({offset}, {value})"),
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn generated_ops() {
+		let code = Embed {
+			extensions: vec![Extension::Time],
+			..Default::default()
+		}
+		.generate("+++[->++<]~.", "program.b");
+
+		assert_eq!(
+			"::brainfuck_rs::embed::CompiledProgram {
+	ops: &[
+		::brainfuck_rs::bytecode::Op::Add(3),
+		::brainfuck_rs::bytecode::Op::MulAdd(1, 2),
+		::brainfuck_rs::bytecode::Op::Clear,
+		::brainfuck_rs::bytecode::Op::Extension(::brainfuck_rs::extension::Extension::Time),
+		::brainfuck_rs::bytecode::Op::Print,
+	],
+	capabilities: &[::brainfuck_rs::capability::Capability::Clock, ],
+}
+",
+			code
+		);
+		assert!(Embed::default()
+			.generate("\n [[]", "program.b")
+			.contains("program.b:2:2: could not find match for `[`"));
+	}
+}
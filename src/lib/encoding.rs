@@ -0,0 +1,418 @@
+use std::{
+	collections::VecDeque,
+	fmt,
+	io::{self, Read, Write},
+	str::FromStr,
+};
+
+use thiserror::Error;
+
+/// How bytes going into or out of a program are written as text, so that programs dealing in
+/// binary data can be fed and inspected on a terminal.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Encoding {
+	/// Bytes as they are.
+	#[default]
+	Raw,
+	/// Two lowercase hexadecimal digits per byte, e.g. `48690a`. Whitespace between them is
+	/// ignored when decoding.
+	Hex,
+	/// Standard base64 with padding, e.g. `SGkK`. Whitespace is ignored when decoding.
+	Base64,
+	/// Printable ASCII and line feeds as they are, anything else escaped like `\t`, `\\` or
+	/// `\x00`.
+	Escaped,
+}
+
+impl Encoding {
+	/// All the known encodings.
+	pub const ALL: &'static [Self] = &[Self::Raw, Self::Hex, Self::Base64, Self::Escaped];
+
+	/// Name of the encoding, as used on the command line.
+	pub const fn name(self) -> &'static str {
+		match self {
+			Self::Raw => "raw",
+			Self::Hex => "hex",
+			Self::Base64 => "base64",
+			Self::Escaped => "escaped",
+		}
+	}
+}
+
+impl fmt::Display for Encoding {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		f.write_str(self.name())
+	}
+}
+
+impl FromStr for Encoding {
+	type Err = UnknownEncoding;
+
+	fn from_str(name: &str) -> Result<Self, Self::Err> {
+		Self::ALL
+			.iter()
+			.copied()
+			.find(|encoding| encoding.name() == name)
+			.ok_or_else(|| UnknownEncoding(name.to_owned()))
+	}
+}
+
+/// An error returned when parsing the name of an encoding that doesn't exist.
+#[derive(Debug, Clone, PartialEq, Eq, Error)]
+#[error("unknown encoding `{0}`")]
+pub struct UnknownEncoding(pub String);
+
+const BASE64: &[u8; 64] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+
+/// Writer that encodes everything written into it before passing it on to `inner`.
+///
+/// Base64 encodes bytes in groups of three, so up to two bytes are held back until more arrive,
+/// or the encoder is finished or dropped.
+///
+/// # Usage
+///
+/// ```
+/// # use std::io::Write;
+/// # use brainfuck_rs::encoding::{Encoder, Encoding};
+/// let mut encoder = Encoder::new(vec![], Encoding::Hex);
+/// encoder.write_all(b"Hi\n").unwrap();
+///
+/// assert_eq!(b"48690a", encoder.finish().unwrap().as_slice());
+/// ```
+pub struct Encoder<W: Write> {
+	/// [`None`] only after the encoder was finished.
+	inner: Option<W>,
+	encoding: Encoding,
+	/// Bytes not encoded yet, see [`Encoder`].
+	pending: Vec<u8>,
+}
+
+impl<W: Write> Encoder<W> {
+	/// Encode everything written into `inner`.
+	pub const fn new(inner: W, encoding: Encoding) -> Self {
+		Self {
+			inner: Some(inner),
+			encoding,
+			pending: Vec::new(),
+		}
+	}
+
+	/// Write whatever was held back, returning the inner writer.
+	///
+	/// # Errors
+	///
+	/// Fails if writing into the inner writer fails.
+	pub fn finish(mut self) -> io::Result<W> {
+		self.write_tail()?;
+
+		Ok(self.inner.take().expect("encoder is only finished once"))
+	}
+
+	fn write_tail(&mut self) -> io::Result<()> {
+		let Some(inner) = &mut self.inner else {
+			return Ok(());
+		};
+
+		if !self.pending.is_empty() {
+			let tail = encode_base64_group(&self.pending);
+			self.pending.clear();
+			inner.write_all(&tail)?;
+		}
+
+		inner.flush()
+	}
+}
+
+impl<W: Write> Write for Encoder<W> {
+	fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+		let inner = self
+			.inner
+			.as_mut()
+			.expect("encoder is used after finishing");
+
+		let encoded = match self.encoding {
+			Encoding::Raw => return inner.write(buf),
+			Encoding::Hex => buf.iter().map(|byte| format!("{byte:02x}")).collect(),
+			Encoding::Base64 => {
+				self.pending.extend_from_slice(buf);
+				let complete = self.pending.len() - self.pending.len() % 3;
+
+				let encoded = self.pending[..complete]
+					.chunks(3)
+					.flat_map(encode_base64_group)
+					.collect();
+				self.pending.drain(..complete);
+
+				String::from_utf8(encoded).expect("base64 is ASCII")
+			}
+			Encoding::Escaped => buf.iter().map(|&byte| escape(byte)).collect(),
+		};
+		inner.write_all(encoded.as_bytes())?;
+
+		Ok(buf.len())
+	}
+
+	fn flush(&mut self) -> io::Result<()> {
+		self.inner
+			.as_mut()
+			.expect("encoder is used after finishing")
+			.flush()
+	}
+}
+
+impl<W: Write> Drop for Encoder<W> {
+	fn drop(&mut self) {
+		// NOTE: like `BufWriter`, errors can't be reported from here, call `finish` to see them
+		let _ = self.write_tail();
+	}
+}
+
+/// Encode up to three bytes, padding the group if there are fewer.
+fn encode_base64_group(group: &[u8]) -> [u8; 4] {
+	let byte = |index: usize| u32::from(group.get(index).copied().unwrap_or(0));
+	let bits = byte(0) << 16 | byte(1) << 8 | byte(2);
+
+	let mut encoded = [b'='; 4];
+	for (index, digit) in encoded.iter_mut().enumerate().take(group.len() + 1) {
+		*digit = BASE64[(bits >> (18 - 6 * index) & 0x3f) as usize];
+	}
+
+	encoded
+}
+
+fn escape(byte: u8) -> String {
+	match byte {
+		b'\n' => "\n".to_owned(),
+		b'\t' => "\\t".to_owned(),
+		b'\r' => "\\r".to_owned(),
+		b'\\' => "\\\\".to_owned(),
+		0x20..=0x7e => char::from(byte).to_string(),
+		_ => format!("\\x{byte:02x}"),
+	}
+}
+
+/// Reader that decodes everything read from `inner`.
+///
+/// Decoded bytes are handed out as soon as the text that makes them up arrived, so interactive
+/// programs keep working. Text that can't be decoded is reported as
+/// [`io::ErrorKind::InvalidData`].
+///
+/// # Usage
+///
+/// ```
+/// # use std::io::Read;
+/// # use brainfuck_rs::encoding::{Decoder, Encoding};
+/// let mut decoder = Decoder::new(b"SGkK".as_slice(), Encoding::Base64);
+///
+/// let mut decoded = vec![];
+/// decoder.read_to_end(&mut decoded).unwrap();
+///
+/// assert_eq!(b"Hi\n", decoded.as_slice());
+/// ```
+pub struct Decoder<R> {
+	inner: R,
+	encoding: Encoding,
+	/// Text that doesn't make up a whole byte yet.
+	pending: Vec<u8>,
+	/// Bytes decoded, but not read yet.
+	decoded: VecDeque<u8>,
+}
+
+impl<R: Read> Decoder<R> {
+	/// Decode everything read from `inner`.
+	pub const fn new(inner: R, encoding: Encoding) -> Self {
+		Self {
+			inner,
+			encoding,
+			pending: Vec::new(),
+			decoded: VecDeque::new(),
+		}
+	}
+
+	/// Decode as much of the pending text as possible.
+	fn decode(&mut self) -> io::Result<()> {
+		let consumed = match self.encoding {
+			Encoding::Raw => {
+				self.decoded.extend(&self.pending);
+				self.pending.len()
+			}
+			Encoding::Hex => {
+				self.pending.retain(|byte| !byte.is_ascii_whitespace());
+
+				let complete = self.pending.len() - self.pending.len() % 2;
+				for pair in self.pending[..complete].chunks(2) {
+					let digits = std::str::from_utf8(pair).map_err(|_| invalid("hex"))?;
+					let byte = u8::from_str_radix(digits, 16).map_err(|_| invalid("hex"))?;
+					self.decoded.push_back(byte);
+				}
+
+				complete
+			}
+			Encoding::Base64 => {
+				self.pending.retain(|byte| !byte.is_ascii_whitespace());
+
+				let complete = self.pending.len() - self.pending.len() % 4;
+				for group in self.pending[..complete].chunks(4) {
+					self.decoded.extend(decode_base64_group(group)?);
+				}
+
+				complete
+			}
+			Encoding::Escaped => {
+				let mut consumed = 0;
+				while let Some((byte, length)) = unescape(&self.pending[consumed..])? {
+					self.decoded.push_back(byte);
+					consumed += length;
+				}
+
+				consumed
+			}
+		};
+		self.pending.drain(..consumed);
+
+		Ok(())
+	}
+}
+
+impl<R: Read> Read for Decoder<R> {
+	fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+		let mut chunk = [0; 256];
+
+		while self.decoded.is_empty() {
+			let read = self.inner.read(&mut chunk)?;
+			if read == 0 {
+				if self.pending.iter().any(|byte| !byte.is_ascii_whitespace()) {
+					return Err(io::Error::new(
+						io::ErrorKind::InvalidData,
+						format!("{} input ends in the middle of a byte", self.encoding),
+					));
+				}
+
+				return Ok(0);
+			}
+
+			self.pending.extend_from_slice(&chunk[..read]);
+			self.decode()?;
+		}
+
+		let length = buf.len().min(self.decoded.len());
+		for (slot, byte) in buf.iter_mut().zip(self.decoded.drain(..length)) {
+			*slot = byte;
+		}
+
+		Ok(length)
+	}
+}
+
+fn invalid(encoding: &str) -> io::Error {
+	io::Error::new(
+		io::ErrorKind::InvalidData,
+		format!("input isn't valid {encoding}"),
+	)
+}
+
+/// Decode a group of four base64 digits, which may end in padding.
+fn decode_base64_group(group: &[u8]) -> io::Result<Vec<u8>> {
+	let padding = group
+		.iter()
+		.rev()
+		.take_while(|&&digit| digit == b'=')
+		.count();
+	if padding > 2 {
+		return Err(invalid("base64"));
+	}
+
+	let mut bits = 0;
+	for &digit in &group[..4 - padding] {
+		let value = BASE64
+			.iter()
+			.position(|&known| known == digit)
+			.ok_or_else(|| invalid("base64"))?;
+		bits = bits << 6 | value as u32;
+	}
+	bits <<= 6 * padding;
+
+	Ok(bits.to_be_bytes()[1..4 - padding].to_vec())
+}
+
+/// Decode the first byte of escaped text, returning it along with the length of its text, or
+/// [`None`] if the text is empty or ends in the middle of an escape.
+fn unescape(text: &[u8]) -> io::Result<Option<(u8, usize)>> {
+	let (kind, rest) = match text {
+		[] | [b'\\'] => return Ok(None),
+		[b'\\', kind, rest @ ..] => (*kind, rest),
+		[byte, ..] => return Ok(Some((*byte, 1))),
+	};
+
+	let byte = match kind {
+		b'n' => b'\n',
+		b't' => b'\t',
+		b'r' => b'\r',
+		b'0' => 0,
+		b'\\' => b'\\',
+		b'x' => {
+			let Some(digits) = rest.get(..2) else {
+				return Ok(None);
+			};
+			let digits = std::str::from_utf8(digits).map_err(|_| invalid("escaped text"))?;
+
+			return u8::from_str_radix(digits, 16)
+				.map(|byte| Some((byte, 4)))
+				.map_err(|_| invalid("escaped text"));
+		}
+		_ => return Err(invalid("escaped text")),
+	};
+
+	Ok(Some((byte, 2)))
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn round_trips() {
+		let bytes: Vec<u8> = (0..=255).chain(*b"Hello\n\\").collect();
+
+		for &encoding in Encoding::ALL {
+			// NOTE: writing byte by byte holds base64 back between groups
+			let mut encoder = Encoder::new(vec![], encoding);
+			for byte in &bytes {
+				encoder.write_all(std::slice::from_ref(byte)).unwrap();
+			}
+			let encoded = encoder.finish().unwrap();
+
+			let mut decoded = vec![];
+			Decoder::new(encoded.as_slice(), encoding)
+				.read_to_end(&mut decoded)
+				.unwrap();
+
+			assert_eq!(bytes, decoded, "{encoding}");
+		}
+
+		let encode = |encoding, bytes: &[u8]| {
+			let mut encoder = Encoder::new(vec![], encoding);
+			encoder.write_all(bytes).unwrap();
+			String::from_utf8(encoder.finish().unwrap()).unwrap()
+		};
+		assert_eq!(
+			"TWFu TWE= TQ==",
+			[b"Man".as_slice(), b"Ma", b"M"]
+				.map(|bytes| encode(Encoding::Base64, bytes))
+				.join(" ")
+		);
+		assert_eq!("a\\tb\n\\x00\\\\", encode(Encoding::Escaped, b"a\tb\n\0\\"));
+
+		let decode = |encoding, text: &[u8]| {
+			let mut decoded = vec![];
+			Decoder::new(text, encoding)
+				.read_to_end(&mut decoded)
+				.map(|_| decoded)
+		};
+		assert_eq!(b"Hi".to_vec(), decode(Encoding::Hex, b"48 69\n").unwrap());
+		assert!(decode(Encoding::Hex, b"486").is_err());
+		assert!(decode(Encoding::Hex, b"zz").is_err());
+		assert!(decode(Encoding::Base64, b"SG!=").is_err());
+		assert!(decode(Encoding::Escaped, b"\\q").is_err());
+	}
+}
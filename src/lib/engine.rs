@@ -1,25 +1,182 @@
 use std::{
+	collections::BTreeSet,
 	io::{self, ErrorKind, Read, Write},
-	num::Wrapping,
+	mem,
+	num::{NonZeroU64, Wrapping},
+	ops::ControlFlow,
+	panic::{self, AssertUnwindSafe},
+	slice,
 };
 
-use crate::instruction::Instruction;
+use thiserror::Error;
+
+#[cfg(feature = "monitoring")]
+use crate::idle::IdleLoopDetector;
+use crate::{
+	analysis::{self, PointerBounds},
+	bytecode::{counting_tape_length, Bytecode, Op},
+	capability::{self, Capability},
+	extension::{Extension, ExtensionSet},
+	hooks::{Hooks, Wrap},
+	host::{HostServices, StdHost},
+	instruction::Instruction,
+	labels::CellLabels,
+	location::{Location, LoopFrame, Recent},
+	pause::{PauseFlag, SafePoint},
+	specialize::HotLoops,
+	view::{EngineMut, EngineView, OutOfTape, ProgramIo},
+};
 
 /// Contains the state of the program.
+#[derive(Debug, Clone)]
 pub struct Engine {
 	/// Current cursor/pointer index.
 	pub pointer: usize,
 	/// The tape that contains all the cells.
 	pub tape: Vec<Wrapping<u8>>,
+	/// Number of cells at the start of the tape that programs may have changed since the engine
+	/// was created or reset, see [`Engine::reset_fast`].
+	pub(crate) touched: usize,
+	/// Names of cells, shown by hooks, crash dumps and narration.
+	pub labels: CellLabels,
+	/// Where the last run stopped early, see [`Engine::location`].
+	location: Option<Location>,
+	/// Loops the tree engine is running, the outermost first, see [`EngineView::loops`].
+	pub(crate) loops: Vec<LoopFrame>,
 }
 
 impl Engine {
+	/// An engine with `tape_length` cells set to zero and the pointer on the first one.
+	pub fn new(tape_length: usize) -> Self {
+		Self {
+			pointer: 0,
+			tape: vec![Wrapping(0); tape_length],
+			touched: 0,
+			labels: CellLabels::default(),
+			location: None,
+			loops: vec![],
+		}
+	}
+
+	/// An engine with the given tape and the pointer on its first cell.
+	pub fn with_tape(tape: Vec<Wrapping<u8>>) -> Self {
+		Self {
+			pointer: 0,
+			touched: tape.len(),
+			tape,
+			labels: CellLabels::default(),
+			location: None,
+			loops: vec![],
+		}
+	}
+
+	/// Where the last run stopped, if it was paused, stopped at a breakpoint, or failed while
+	/// running an instruction, like when it ran out of budget or couldn't print. The instruction
+	/// there didn't run, or failed.
+	///
+	/// [`None`] if the run finished, or failed before it started or due to a panic. Errors tell
+	/// the same, see [`RunError::location`].
+	pub const fn location(&self) -> Option<&Location> {
+		self.location.as_ref()
+	}
+
+	/// Set every cell to zero and move the pointer to the first one, keeping the length of the
+	/// tape.
+	pub fn reset(&mut self) {
+		self.tape.fill(Wrapping(0));
+		self.pointer = 0;
+		self.touched = 0;
+	}
+
+	/// Same as [`Engine::reset`], but only set the cells to zero that programs may have changed,
+	/// the ones up to the rightmost cell the pointer reached. Resetting a large tape after a short
+	/// program is cheap that way.
+	///
+	/// Cells changed through [`Engine::tape`] directly aren't tracked, so engines whose tape was
+	/// changed by hand, other than through [`Engine::with_tape`], must be reset with
+	/// [`Engine::reset`] instead.
+	///
+	/// # Usage
+	///
+	/// ```
+	/// # use brainfuck_rs::{
+	/// #   engine::{Engine, RuntimeSettings},
+	/// #   instruction::Instruction,
+	/// #   token::Token,
+	/// # };
+	/// let instructions = Instruction::parse(Token::tokenize(">>+++<")).unwrap();
+	///
+	/// let mut bf = Engine::new(1_000_000);
+	/// bf.run(&instructions, &mut [].as_slice(), &mut vec![], RuntimeSettings::default())
+	///     .unwrap();
+	/// assert_eq!(3, bf.tape[2].0);
+	///
+	/// // only sets the first 3 cells to zero
+	/// bf.reset_fast();
+	/// assert_eq!(Engine::new(1_000_000), bf);
+	/// ```
+	pub fn reset_fast(&mut self) {
+		let touched = self.touched.min(self.tape.len());
+		self.tape[..touched].fill(Wrapping(0));
+		self.pointer = 0;
+		self.touched = 0;
+	}
+
 	/// Shift pointer to the next cell or wraps around.
 	pub fn next(&mut self) {
 		if self.pointer == self.tape.len() - 1 {
 			self.pointer = 0;
 		} else {
 			self.pointer += 1;
+			self.touched = self.touched.max(self.pointer + 1);
+		}
+	}
+
+	/// The current cell, without checking that the pointer is within the tape.
+	///
+	/// # Safety
+	///
+	/// The pointer must be within the tape.
+	#[inline(always)]
+	unsafe fn cell_unchecked(&mut self) -> &mut Wrapping<u8> {
+		debug_assert!(
+			self.pointer < self.tape.len(),
+			"pointer is outside of the tape"
+		);
+
+		// SAFETY: guaranteed by the caller
+		unsafe { self.tape.get_unchecked_mut(self.pointer) }
+	}
+
+	/// The current cell while running bytecode, without checking that the pointer is within the
+	/// tape.
+	///
+	/// # Safety
+	///
+	/// The pointer must be on the tape. Between the ops of [`Engine::execute_bytecode`] it always
+	/// is: the run only starts once [`Engine::check_pointer`] passes, the tape never shrinks, and
+	/// every op that moves the pointer leaves it on the tape. [`Engine::next`], [`Engine::prev`]
+	/// and [`Engine::shift`] wrap it around the ends of the tape, or grow the tape up to it,
+	/// [`Engine::fill`], [`Engine::scan_right`] and [`Engine::scan_left`] stop on a cell of the
+	/// tape, and hot loops and extensions only move it where indexing the tape with it works.
+	#[inline(always)]
+	unsafe fn bytecode_cell(&mut self) -> &mut Wrapping<u8> {
+		// SAFETY: guaranteed by the caller
+		unsafe { self.cell_unchecked() }
+	}
+
+	/// The current cell, only checking that the pointer is within the tape unless it's `bounded`.
+	///
+	/// # Safety
+	///
+	/// If `bounded`, the pointer must be within the tape.
+	#[inline(always)]
+	unsafe fn cell_if_unbounded(&mut self, bounded: bool) -> &mut Wrapping<u8> {
+		if bounded {
+			// SAFETY: guaranteed by the caller
+			unsafe { self.cell_unchecked() }
+		} else {
+			&mut self.tape[self.pointer]
 		}
 	}
 
@@ -27,6 +184,7 @@ impl Engine {
 	pub fn prev(&mut self) {
 		if self.pointer == 0 {
 			self.pointer = self.tape.len() - 1;
+			self.touched = self.tape.len();
 		} else {
 			self.pointer -= 1;
 		}
@@ -78,183 +236,3093 @@ impl Engine {
 	///
 	/// You can use any buffer, as long as it implements [`std::io::Write`] and [`std::io::Read`].
 	///
+	/// Once the program finishes, it returns a [`RunSummary`].
+	///
 	/// # Errors
 	///
-	/// In case of an IO error, it returns [`io::Error`] without continuing function execution.
-	#[deny(clippy::unwrap_in_result, clippy::panic_in_result_fn)]
+	/// In case of an IO error, it returns [`RunError::Io`] without continuing function execution.
+	///
+	/// If the instructions need capabilities that aren't granted in the settings, it returns
+	/// [`RunError::CapabilitiesNotGranted`] without executing anything.
+	///
+	/// If the next instruction would exceed [`RuntimeSettings::budget`], it returns
+	/// [`RunError::BudgetExhausted`] without executing it.
+	///
+	/// If the engine panics, it returns [`RunError::InternalError`], unless
+	/// [`RuntimeSettings::contain_panics`] is off.
 	pub fn run<'a, I>(
 		&mut self,
 		instructions: I,
 		stdin: &mut impl Read,
 		stdout: &mut impl Write,
 		settings: RuntimeSettings,
-	) -> Result<(), io::Error>
+	) -> Result<RunSummary, RunError>
+	where
+		I: IntoIterator<Item = &'a Instruction>,
+		I::IntoIter: DoubleEndedIterator,
+	{
+		self.run_with_host(
+			instructions,
+			stdin,
+			stdout,
+			settings,
+			&mut StdHost::default(),
+		)
+	}
+
+	/// Run Brainfuck instructions like [`Engine::run`], but route every interaction with the
+	/// outside world that extension instructions make through `host`.
+	///
+	/// # Errors
+	///
+	/// Same as [`Engine::run`].
+	pub fn run_with_host<'a, I>(
+		&mut self,
+		instructions: I,
+		stdin: &mut impl Read,
+		stdout: &mut impl Write,
+		settings: RuntimeSettings,
+		host: &mut impl HostServices,
+	) -> Result<RunSummary, RunError>
+	where
+		I: IntoIterator<Item = &'a Instruction>,
+		I::IntoIter: DoubleEndedIterator,
+	{
+		self.run_with(instructions, stdin, stdout, settings, host, &mut ())
+	}
+
+	/// Run Brainfuck instructions like [`Engine::run_with_host`], additionally notifying `hooks`
+	/// about the progress of execution.
+	///
+	/// Pass `&mut ()` to not use any hooks, which costs nothing at runtime.
+	///
+	/// # Errors
+	///
+	/// Same as [`Engine::run`].
+	pub fn run_with<'a, I>(
+		&mut self,
+		instructions: I,
+		stdin: &mut impl Read,
+		stdout: &mut impl Write,
+		settings: RuntimeSettings,
+		host: &mut impl HostServices,
+		hooks: &mut impl Hooks,
+	) -> Result<RunSummary, RunError>
+	where
+		I: IntoIterator<Item = &'a Instruction>,
+		I::IntoIter: DoubleEndedIterator,
+	{
+		// NOTE: the pointer may have been moved by hand
+		self.touched = self.touched.max(self.pointer + 1);
+
+		contain(settings.contain_panics, "tree", || {
+			if settings.watch_steps() {
+				self.execute::<_, _, true>(instructions, stdin, stdout, settings, host, hooks)
+			} else {
+				self.execute::<_, _, false>(instructions, stdin, stdout, settings, host, hooks)
+			}
+		})
+		.map_err(|error| error.stopped_at(self.location.as_ref()))
+	}
+
+	#[deny(clippy::unwrap_in_result, clippy::panic_in_result_fn)]
+	fn execute<'a, I, H: Hooks, const WATCHED: bool>(
+		&mut self,
+		instructions: I,
+		stdin: &mut impl Read,
+		stdout: &mut impl Write,
+		settings: RuntimeSettings,
+		host: &mut impl HostServices,
+		hooks: &mut H,
+	) -> Result<RunSummary, RunError>
 	where
 		I: IntoIterator<Item = &'a Instruction>,
 		I::IntoIter: DoubleEndedIterator,
 	{
 		let mut stack: Vec<&Instruction> = instructions.into_iter().rev().collect();
+		let roots: Vec<&Instruction> = stack.iter().rev().copied().collect();
+		let mut steps: u64 = 0;
+		let mut spent: u64 = 0;
+		let mut exit_code = None;
+		let mut output_matched = false;
+		let mut breakpoint_hit = false;
+		let mut paused = false;
+
+		self.location = None;
+		self.loops.clear();
+		check_capabilities(&capability::required_by(stack.iter().copied()), &settings)?;
+
+		// NOTE: while the pointer provably stays on the tape, it's moved without checking whether
+		// it wraps around, and cells are accessed unchecked: everywhere, or within an iteration of
+		// the loop here
+		let ranges = analysis::pointer_ranges_of(roots.iter().copied());
+		let everywhere = ranges.program.is_some_and(|bounds| self.fits(bounds));
+		let mut unchecked_loop: Option<*const Instruction> = None;
+
+		let mut pattern = settings.break_on_output.as_deref().map(OutputPattern::new);
+		#[cfg(feature = "monitoring")]
+		let mut idle =
+			(WATCHED && settings.idle_loops != IdleLoops::Ignore).then(IdleLoopDetector::default);
+
+		let mut stdin = Input::new(stdin, settings.read_ahead, settings.newlines);
+		let mut stdout = Output::new(stdout, settings.should_flush, settings.newlines);
+
+		let yield_every = settings.yield_every.map_or(u64::MAX, NonZeroU64::get);
+		let mut next_yield = yield_every;
+
+		let mut recent = Recent::default();
+		let remember_recent = WATCHED && settings.remember_recent;
+		let pause = settings.pause.as_ref().filter(|_| WATCHED);
+		let budget = settings.budget.filter(|_| WATCHED);
 
 		while let Some(current_instruction) = stack.pop() {
+			if remember_recent {
+				recent.push(std::ptr::from_ref(current_instruction).addr());
+			}
+
+			if WATCHED && steps >= next_yield {
+				hooks.on_yield(steps, EngineView::new(self));
+				next_yield = steps.saturating_add(yield_every);
+			}
+
+			if let Some(pause) = pause {
+				let at = match current_instruction {
+					Instruction::Loop(_) => Some(SafePoint::Loop),
+					Instruction::Read => Some(SafePoint::Read),
+					Instruction::Print => Some(SafePoint::Print),
+					Instruction::Extension(_) => Some(SafePoint::Extension),
+					_ => None,
+				};
+
+				if let Some(at) = at.filter(|_| pause.take()) {
+					stdout.flush().inspect_err(|_| {
+						self.stop_at_instruction(&roots, current_instruction, &recent)
+					})?;
+
+					if hooks.on_pause(steps, at, EngineView::new(self)).is_break() {
+						self.stop_at_instruction(&roots, current_instruction, &recent);
+						paused = true;
+						break;
+					}
+				}
+			}
+
+			if let Some(budget) = budget {
+				let cost = (settings.cost)(current_instruction);
+
+				match spent.checked_add(cost) {
+					Some(total) if total <= budget => spent = total,
+					_ => {
+						self.stop_at_instruction(&roots, current_instruction, &recent);
+						return Err(RunError::BudgetExhausted {
+							spent,
+							budget,
+							location: None,
+						});
+					}
+				}
+			}
+
+			if H::ENABLED {
+				hooks.before_instruction(steps, current_instruction, EngineView::new(self));
+				if hooks.should_break() {
+					self.stop_at_instruction(&roots, current_instruction, &recent);
+					breakpoint_hit = true;
+					break;
+				}
+			}
+
+			steps += 1;
+
 			match current_instruction {
 				Instruction::Loop(inner) => {
-					if self.tape[self.pointer].0 != 0 {
+					// SAFETY: bounded ranges only ever contain cells that are on the tape
+					let cell =
+						unsafe { self.cell_if_unbounded(everywhere || unchecked_loop.is_some()) };
+					if cell.0 != 0 {
+						#[cfg(feature = "monitoring")]
+						if let Some(detector) = &mut idle {
+							if detector.check(current_instruction, self, steps - 1) {
+								hooks.on_idle_loop(steps - 1, current_instruction);
+
+								if settings.idle_loops == IdleLoops::Abort {
+									self.stop_at_instruction(&roots, current_instruction, &recent);
+									return Err(RunError::IdleLoop {
+										step: steps - 1,
+										location: None,
+									});
+								}
+								idle = None;
+							}
+						}
+
+						// NOTE: a loop is only ever nested in itself by running it again
+						match self.loops.last_mut() {
+							Some(frame) if frame.is(current_instruction) => {
+								frame.iterations += 1;
+								frame.guard = self.pointer;
+							}
+							_ => {
+								// NOTE: looking loops up whenever they're entered costs more than the
+								// checks it saves, so only loops at the top level are, which covers
+								// the ones in them. Bounded loops come back to the same cell on every
+								// iteration, so whether they fit only needs checking once.
+								let top_level = self.loops.is_empty();
+								if !everywhere
+									&& top_level && ranges
+									.of_loop(current_instruction)
+									.is_some_and(|bounds| self.fits(bounds))
+								{
+									unchecked_loop = Some(current_instruction);
+								}
+
+								self.loops
+									.push(LoopFrame::new(current_instruction, self.pointer));
+							}
+						}
+
 						// NOTE: since we're executing in reverse order, we must push in reverse too
 						stack.push(current_instruction);
 
 						for inner_instruction in inner.iter().rev() {
 							stack.push(inner_instruction);
 						}
+					} else {
+						if unchecked_loop == Some(std::ptr::from_ref(current_instruction)) {
+							unchecked_loop = None;
+						}
+
+						if self
+							.loops
+							.last()
+							.is_some_and(|frame| frame.is(current_instruction))
+						{
+							self.loops.pop();
+						}
 					}
 				}
-				Instruction::Inc => self.tape[self.pointer] += 1,
-				Instruction::Dec => self.tape[self.pointer] -= 1,
-				Instruction::Next => self.next(),
-				Instruction::Prev => self.prev(),
-				Instruction::Print => {
-					let output = self.tape[self.pointer].0;
+				Instruction::Inc => {
+					let pointer = self.pointer;
+					// SAFETY: bounded ranges only ever contain cells that are on the tape
+					let cell =
+						unsafe { self.cell_if_unbounded(everywhere || unchecked_loop.is_some()) };
+					if H::WATCHES_WRAPS && cell.0 == u8::MAX {
+						hooks.on_wrap(steps - 1, Wrap::CellOverflow(pointer));
+					}
+
+					*cell += 1;
+				}
+				Instruction::Dec => {
+					let pointer = self.pointer;
+					// SAFETY: bounded ranges only ever contain cells that are on the tape
+					let cell =
+						unsafe { self.cell_if_unbounded(everywhere || unchecked_loop.is_some()) };
+					if H::WATCHES_WRAPS && cell.0 == 0 {
+						hooks.on_wrap(steps - 1, Wrap::CellUnderflow(pointer));
+					}
+
+					*cell -= 1;
+				}
+				Instruction::Add(amount) => {
+					let pointer = self.pointer;
+					// SAFETY: bounded ranges only ever contain cells that are on the tape
+					let cell =
+						unsafe { self.cell_if_unbounded(everywhere || unchecked_loop.is_some()) };
+					let (sum, wrapped) = cell.0.overflowing_add_signed(*amount);
+					// NOTE: a run is shorter than the 256 `+` or `-` it'd take to wrap around twice
+					if H::WATCHES_WRAPS && wrapped {
+						hooks.on_wrap(
+							steps - 1,
+							if *amount < 0 {
+								Wrap::CellUnderflow(pointer)
+							} else {
+								Wrap::CellOverflow(pointer)
+							},
+						);
+					}
 
-					stdout.write_all(&[output])?;
+					*cell = Wrapping(sum);
+				}
+				Instruction::Clear => {
+					// SAFETY: bounded ranges only ever contain cells that are on the tape
+					let cell =
+						unsafe { self.cell_if_unbounded(everywhere || unchecked_loop.is_some()) };
+					*cell = Wrapping(0);
+				}
+				Instruction::Next if settings.grow_tape && self.pointer == self.tape.len() - 1 => {
+					self.tape.push(Wrapping(0));
+					self.pointer += 1;
+					self.touched = self.tape.len();
 
-					if settings.should_flush {
-						stdout.flush()?;
+					#[cfg(feature = "monitoring")]
+					if let Some(detector) = &mut idle {
+						detector.moved(self.pointer);
 					}
 				}
-				Instruction::Read => {
-					if !settings.should_flush {
-						stdout.flush()?;
+				Instruction::Next => {
+					if everywhere || unchecked_loop.is_some() {
+						self.pointer += 1;
+						self.touched = self.touched.max(self.pointer + 1);
+					} else {
+						if H::WATCHES_WRAPS && self.pointer == self.tape.len() - 1 {
+							hooks.on_wrap(steps - 1, Wrap::PointerPastEnd);
+						}
+
+						self.next();
+					}
+
+					#[cfg(feature = "monitoring")]
+					if let Some(detector) = &mut idle {
+						detector.moved(self.pointer);
+					}
+				}
+				Instruction::Prev => {
+					if everywhere || unchecked_loop.is_some() {
+						self.pointer -= 1;
+					} else {
+						if H::WATCHES_WRAPS && self.pointer == 0 {
+							hooks.on_wrap(steps - 1, Wrap::PointerPastStart);
+						}
+
+						self.prev();
 					}
 
-					let mut input_char: [u8; 1] = [0];
+					#[cfg(feature = "monitoring")]
+					if let Some(detector) = &mut idle {
+						detector.moved(self.pointer);
+					}
+				}
+				Instruction::Move(distance) => {
+					let mut wraps = 0;
+					if everywhere || unchecked_loop.is_some() {
+						self.pointer = self.pointer.wrapping_add_signed(*distance as isize);
+						self.touched = self.touched.max(self.pointer + 1);
+					} else {
+						// NOTE: like as many `>` or `<`, which wrap around every time they pass an
+						// end of the tape, unless it grows
+						let target = self.pointer as isize + *distance as isize;
+						if !(settings.grow_tape && target >= 0) {
+							wraps = target.div_euclid(self.tape.len() as isize).unsigned_abs();
+						}
+						if H::WATCHES_WRAPS {
+							let wrap = if *distance < 0 {
+								Wrap::PointerPastStart
+							} else {
+								Wrap::PointerPastEnd
+							};
+							for _ in 0..wraps {
+								hooks.on_wrap(steps - 1, wrap);
+							}
+						}
 
-					match stdin.read_exact(&mut input_char) {
-						Ok(_) => {}
-						Err(e) if settings.quit_on_eof && e.kind() == ErrorKind::UnexpectedEof => {
-							return Ok(());
+						self.shift(*distance, settings.grow_tape);
+						if wraps > 0 {
+							self.touched = self.tape.len();
 						}
-						Err(e) if !settings.quit_on_eof && e.kind() == ErrorKind::UnexpectedEof => {
+					}
+
+					#[cfg(feature = "monitoring")]
+					if let Some(detector) = &mut idle {
+						if wraps > 0 {
+							detector.moved(0);
+							detector.moved(self.tape.len() - 1);
 						}
-						other_error => return other_error,
+						detector.moved(self.pointer);
+					}
+				}
+				Instruction::Print => {
+					// SAFETY: bounded ranges only ever contain cells that are on the tape
+					let output =
+						unsafe { self.cell_if_unbounded(everywhere || unchecked_loop.is_some()) }.0;
+					stdout.write(output).inspect_err(|_| {
+						self.stop_at_instruction(&roots, current_instruction, &recent)
+					})?;
+					hooks.on_output(steps - 1, output);
+
+					#[cfg(feature = "monitoring")]
+					if let Some(detector) = &mut idle {
+						detector.reset();
+					}
+
+					if pattern.as_mut().is_some_and(|pattern| pattern.push(output)) {
+						output_matched = true;
+						break;
+					}
+				}
+				Instruction::Read => {
+					#[cfg(feature = "monitoring")]
+					if let Some(detector) = &mut idle {
+						detector.reset();
+					}
+
+					match self
+						.read(&mut stdin, &mut stdout, settings.eof)
+						.inspect_err(|_| {
+							self.stop_at_instruction(&roots, current_instruction, &recent)
+						})? {
+						Some(input) => hooks.on_input(steps - 1, input),
+						None if settings.quit_on_eof => break,
+						None => {}
+					}
+				}
+				Instruction::Extension(extension) => {
+					#[cfg(feature = "monitoring")]
+					if let Some(detector) = &mut idle {
+						detector.reset();
 					}
 
-					self.tape[self.pointer] = Wrapping(input_char[0]);
+					if let ControlFlow::Break(code) = self
+						.extension(
+							*extension,
+							steps - 1,
+							&settings,
+							host,
+							&mut stdin,
+							&mut stdout,
+						)
+						.inspect_err(|_| {
+							self.stop_at_instruction(&roots, current_instruction, &recent)
+						})? {
+						exit_code = Some(code);
+						break;
+					}
 				}
 			}
 		}
 
-		Ok(())
+		stdout.write_pending()?;
+
+		Ok(RunSummary {
+			steps,
+			unread_input: stdin.into_unread(),
+			exit_code,
+			output_matched,
+			breakpoint_hit,
+			paused,
+			location: self.location.clone(),
+		})
 	}
-}
 
-impl Default for Engine {
-	/// Creates a new `Engine` with default values:
+	/// Run a program compiled into [`Bytecode`].
+	///
+	/// Produces the same results as [`Engine::run`] on the instructions the bytecode was compiled
+	/// from, including the number of steps, but dispatches over a flat slice of ops, which is
+	/// considerably faster.
+	///
+	/// # Usage
 	///
 	/// ```
-	/// # use std::num::Wrapping;
-	/// # use brainfuck_rs::engine::Engine;
-	/// Engine {
-	///     pointer: 0,
-	///     tape: vec![Wrapping(0); 30_000],
-	/// }
-	/// # ;
+	/// # use std::io::{BufReader, BufWriter};
+	/// # use brainfuck_rs::{
+	/// #   bytecode::Bytecode,
+	/// #   engine::{Engine, RuntimeSettings},
+	/// #   instruction::Instruction,
+	/// #   token::Token,
+	/// # };
+	/// let instructions = Instruction::parse(Token::tokenize("++++++[->++++++++<]>.")).unwrap();
+	/// let bytecode = Bytecode::compile(&instructions);
+	///
+	/// let mut input = BufReader::new(<&[u8]>::default());
+	/// let mut output = BufWriter::new(vec![]);
+	///
+	/// Engine::default()
+	///     .run_bytecode(&bytecode, &mut input, &mut output, RuntimeSettings::default())
+	///     .unwrap();
+	///
+	/// assert_eq!(b"0", output.into_inner().unwrap().as_slice());
 	/// ```
-	fn default() -> Self {
-		Self {
-			pointer: 0,
-			tape: vec![Wrapping(0); 30_000],
-		}
+	///
+	/// # Errors
+	///
+	/// Same as [`Engine::run`].
+	pub fn run_bytecode(
+		&mut self,
+		bytecode: &Bytecode,
+		stdin: &mut impl Read,
+		stdout: &mut impl Write,
+		settings: RuntimeSettings,
+	) -> Result<RunSummary, RunError> {
+		self.run_bytecode_with_host(bytecode, stdin, stdout, settings, &mut StdHost::default())
 	}
-}
 
-/// Settings that determine how interpreter should behave.
-#[derive(Debug, Clone, PartialEq, Eq)]
-pub struct RuntimeSettings {
-	/// If `true`, the output is flushed on every print instruction, otherwise the output is buffered.
-	pub should_flush: bool,
-	/// Stop execution on EOF.
+	/// Run a program compiled into [`Bytecode`] like [`Engine::run_bytecode`], but route every
+	/// interaction with the outside world that extension instructions make through `host`.
 	///
-	/// Particularly usefull for environments with less control, like piped data and input buffers.
-	pub quit_on_eof: bool,
-}
+	/// # Errors
+	///
+	/// Same as [`Engine::run`].
+	pub fn run_bytecode_with_host(
+		&mut self,
+		bytecode: &Bytecode,
+		stdin: &mut impl Read,
+		stdout: &mut impl Write,
+		settings: RuntimeSettings,
+		host: &mut impl HostServices,
+	) -> Result<RunSummary, RunError> {
+		self.run_bytecode_with(bytecode, stdin, stdout, settings, host, &mut ())
+	}
 
-impl Default for RuntimeSettings {
-	/// Creates a new `RuntimeSettings` with default values:
+	/// Run a program compiled into [`Bytecode`] like [`Engine::run_bytecode_with_host`],
+	/// additionally notifying `hooks` about the progress of execution.
 	///
-	/// ```
-	/// # use brainfuck_rs::engine::RuntimeSettings;
-	/// RuntimeSettings {
-	///     should_flush: true,
-	///     quit_on_eof: false,
-	/// }
-	/// # ;
-	/// ```
-	fn default() -> Self {
-		Self {
-			should_flush: true,
-			quit_on_eof: false,
-		}
+	/// [`Hooks::before_op`] is called instead of [`Hooks::before_instruction`]. It isn't called
+	/// for the ops of loops specialized due to [`RuntimeSettings::specialize_after`].
+	///
+	/// # Errors
+	///
+	/// Same as [`Engine::run`].
+	pub fn run_bytecode_with(
+		&mut self,
+		bytecode: &Bytecode,
+		stdin: &mut impl Read,
+		stdout: &mut impl Write,
+		settings: RuntimeSettings,
+		host: &mut impl HostServices,
+		hooks: &mut impl Hooks,
+	) -> Result<RunSummary, RunError> {
+		// NOTE: the pointer may have been moved by hand
+		self.touched = self.touched.max(self.pointer + 1);
+
+		contain(settings.contain_panics, "bytecode", || {
+			if settings.watch_steps() {
+				self.execute_bytecode::<_, true>(bytecode, stdin, stdout, settings, host, hooks)
+			} else {
+				self.execute_bytecode::<_, false>(bytecode, stdin, stdout, settings, host, hooks)
+			}
+		})
+		.map_err(|error| error.stopped_at(self.location.as_ref()))
 	}
-}
 
-#[cfg(test)]
-mod tests {
-	use std::io::{BufReader, BufWriter};
-	use std::str;
+	#[deny(clippy::unwrap_in_result, clippy::panic_in_result_fn)]
+	fn execute_bytecode<H: Hooks, const WATCHED: bool>(
+		&mut self,
+		bytecode: &Bytecode,
+		stdin: &mut impl Read,
+		stdout: &mut impl Write,
+		settings: RuntimeSettings,
+		host: &mut impl HostServices,
+		hooks: &mut H,
+	) -> Result<RunSummary, RunError> {
+		self.location = None;
+		check_capabilities(bytecode.required_capabilities(), &settings)?;
 
-	use lazy_static::lazy_static;
+		let mut stdin = Input::new(stdin, settings.read_ahead, settings.newlines);
+		let mut stdout = Output::new(stdout, settings.should_flush, settings.newlines);
 
-	use crate::token::Token;
-	use crate::utils::StripShebang;
+		// NOTE: the pointer may have been moved by hand. From here on, every op keeps it within the
+		// tape, so the current cell is accessed unchecked, see `Engine::bytecode_cell`
+		self.check_pointer("bytecode")?;
 
-	use super::*;
+		let ops = bytecode.ops();
+		let mut hot_loops = settings
+			.specialize_after
+			.filter(|_| settings.budget.is_none())
+			.map(|threshold| {
+				let hot_loops = HotLoops::new(ops, threshold);
+				// NOTE: native code is compiled for the tape as long as it is
+				#[cfg(feature = "jit")]
+				let hot_loops = hot_loops.native(!settings.grow_tape);
+				hot_loops
+			});
+		// NOTE: the tape only grows, so loops that can be counted now can be counted for good
+		let counting = self.tape.len() >= counting_tape_length(ops);
+		let mut pc = 0;
+		let mut repetitions: u8 = 0;
+		let mut steps: u64 = 0;
+		let mut spent: u64 = 0;
+		let mut exit_code = None;
+		let mut output_matched = false;
+		let mut breakpoint_hit = false;
+		let mut paused = false;
+		let mut pattern = settings.break_on_output.as_deref().map(OutputPattern::new);
+		let yield_every = settings.yield_every.map_or(u64::MAX, NonZeroU64::get);
+		let mut next_yield = yield_every;
 
-	lazy_static! {
-		static ref HELLO_WORLD: &'static str =
-			include_str!("../../examples/brainfuck-programs/hello-world.b").strip_shebang();
+		let mut recent = Recent::default();
+		let remember_recent = WATCHED && settings.remember_recent;
+		let pause = settings.pause.as_ref().filter(|_| WATCHED);
+		let budget = settings.budget.filter(|_| WATCHED);
+
+		while let Some(&op) = ops.get(pc) {
+			let index = pc;
+			if remember_recent {
+				recent.push(index);
+			}
+
+			if WATCHED && steps >= next_yield {
+				hooks.on_yield(steps, EngineView::new(self));
+				next_yield = steps.saturating_add(yield_every);
+			}
+
+			if let Some(pause) = pause {
+				let at = match op {
+					Op::JumpIfZero(_)
+					| Op::JumpIfNonZero(_)
+					| Op::JumpIfZeroAdd(..)
+					| Op::RepeatN(..)
+					| Op::RepeatEnd(_)
+					| Op::ScanRight
+					| Op::ScanLeft => Some(SafePoint::Loop),
+					Op::Read => Some(SafePoint::Read),
+					// NOTE: the cell is added to after the pause, right before it's printed
+					Op::Print | Op::AddPrint(_) => Some(SafePoint::Print),
+					Op::Extension(_) => Some(SafePoint::Extension),
+					_ => None,
+				};
+
+				if let Some(at) = at.filter(|_| pause.take()) {
+					stdout
+						.flush()
+						.inspect_err(|_| self.stop_at_op(ops, index, &recent))?;
+
+					if hooks.on_pause(steps, at, EngineView::new(self)).is_break() {
+						self.stop_at_op(ops, index, &recent);
+						paused = true;
+						break;
+					}
+				}
+			}
+
+			if let Some(budget) = budget {
+				// NOTE: collapsed and fused ops are charged like the first instruction they replace,
+				// and an empty loop stands in for every loop, since loops don't exist as such in
+				// bytecode
+				let cost = (settings.cost)(&match op {
+					Op::Inc | Op::Add(_) | Op::AddMove(..) | Op::AddAt(..) | Op::AddPrint(_) => {
+						Instruction::Inc
+					}
+					Op::Dec => Instruction::Dec,
+					Op::Next | Op::Move(_) | Op::MoveAdd(..) => Instruction::Next,
+					Op::Prev => Instruction::Prev,
+					Op::Print => Instruction::Print,
+					Op::Read => Instruction::Read,
+					Op::JumpIfZero(_)
+					| Op::JumpIfNonZero(_)
+					| Op::JumpIfZeroAdd(..)
+					| Op::Clear
+					| Op::ClearMove(_)
+					| Op::Set(_)
+					| Op::Fill(..)
+					| Op::RepeatN(..)
+					| Op::RepeatEnd(_)
+					| Op::MulAdd(..)
+					| Op::ScanRight
+					| Op::ScanLeft => Instruction::Loop(Vec::new()),
+					Op::Extension(extension) => Instruction::Extension(extension),
+				});
+
+				match spent.checked_add(cost) {
+					Some(total) if total <= budget => spent = total,
+					_ => {
+						self.stop_at_op(ops, index, &recent);
+						return Err(RunError::BudgetExhausted {
+							spent,
+							budget,
+							location: None,
+						});
+					}
+				}
+			}
+
+			if H::ENABLED {
+				hooks.before_op(steps, pc, op, EngineView::new(self));
+				if hooks.should_break() {
+					self.stop_at_op(ops, index, &recent);
+					breakpoint_hit = true;
+					break;
+				}
+			}
+
+			steps += 1;
+			pc += 1;
+
+			// NOTE: the common ops are handled inline, everything that talks to the outside world
+			// lives in separate functions to keep this loop small
+			match op {
+				// SAFETY: the pointer is on the tape between ops, see `Engine::bytecode_cell`
+				Op::Inc => unsafe { *self.bytecode_cell() += 1 },
+				// SAFETY: the pointer is on the tape between ops, see `Engine::bytecode_cell`
+				Op::Dec => unsafe { *self.bytecode_cell() -= 1 },
+				Op::Next if settings.grow_tape && self.pointer == self.tape.len() - 1 => {
+					self.tape.push(Wrapping(0));
+					self.pointer += 1;
+					self.touched = self.tape.len();
+				}
+				Op::Next => self.next(),
+				Op::Prev => self.prev(),
+				Op::JumpIfZero(target) => {
+					// SAFETY: the pointer is on the tape between ops, see `Engine::bytecode_cell`
+					if unsafe { self.bytecode_cell() }.0 == 0 {
+						pc = target as usize;
+					} else if let Some(run) = hot_loops.as_mut().and_then(|hot_loops| {
+						hot_loops.enter(ops, pc - 1, &mut self.tape, &mut self.pointer)
+					}) {
+						steps += run.steps;
+						self.touched = self.touched.max(run.reach);
+
+						// NOTE: otherwise, the interpreter continues with the body of the loop, as if
+						// it just jumped back to it
+						if run.finished {
+							pc = target as usize;
+						}
+					}
+				}
+				Op::JumpIfNonZero(target) => {
+					// SAFETY: the pointer is on the tape between ops, see `Engine::bytecode_cell`
+					if unsafe { self.bytecode_cell() }.0 != 0 {
+						pc = target as usize;
+					}
+				}
+				// SAFETY: the pointer is on the tape between ops, see `Engine::bytecode_cell`
+				Op::Add(delta) => unsafe { *self.bytecode_cell() += delta },
+				Op::Move(offset) => self.shift(offset, settings.grow_tape),
+				// SAFETY: the pointer is on the tape between ops, see `Engine::bytecode_cell`
+				Op::Clear => unsafe { *self.bytecode_cell() = Wrapping(0) },
+				// SAFETY: the pointer is on the tape between ops, see `Engine::bytecode_cell`
+				Op::Set(value) => unsafe { *self.bytecode_cell() = Wrapping(value) },
+				Op::Fill(offset, value) => self.fill(offset, value, settings.grow_tape),
+				Op::AddMove(delta, offset) => {
+					// SAFETY: the pointer is on the tape between ops, see `Engine::bytecode_cell`
+					unsafe { *self.bytecode_cell() += delta };
+					self.shift(offset, settings.grow_tape);
+				}
+				Op::ClearMove(offset) => {
+					// SAFETY: the pointer is on the tape between ops, see `Engine::bytecode_cell`
+					unsafe { *self.bytecode_cell() = Wrapping(0) };
+					self.shift(offset, settings.grow_tape);
+				}
+				Op::MoveAdd(offset, delta) => {
+					self.shift(offset, settings.grow_tape);
+					// SAFETY: `shift` leaves the pointer on the tape, see `Engine::bytecode_cell`
+					unsafe { *self.bytecode_cell() += delta };
+				}
+				Op::JumpIfZeroAdd(target, delta) => {
+					// SAFETY: the pointer is on the tape between ops, see `Engine::bytecode_cell`
+					if unsafe { self.bytecode_cell() }.0 == 0 {
+						pc = target as usize;
+					} else if let Some(run) = hot_loops.as_mut().and_then(|hot_loops| {
+						hot_loops.enter(ops, pc - 1, &mut self.tape, &mut self.pointer)
+					}) {
+						steps += run.steps;
+						self.touched = self.touched.max(run.reach);
+
+						// NOTE: otherwise, the start of the loop is executed again, just like after
+						// the matching jump back
+						pc = if run.finished {
+							target as usize
+						} else {
+							pc - 1
+						};
+					} else {
+						// SAFETY: the pointer is on the tape between ops, see `Engine::bytecode_cell`
+						unsafe { *self.bytecode_cell() += delta };
+					}
+				}
+				Op::RepeatN(end, factor) => {
+					// SAFETY: the pointer is on the tape between ops, see `Engine::bytecode_cell`
+					let cell = unsafe { self.bytecode_cell() };
+
+					// NOTE: repeat loops can't be nested, so a single counter is enough
+					repetitions = cell.0.wrapping_mul(factor);
+					if repetitions == 0 {
+						pc = end as usize;
+					} else if let Some(run) = hot_loops.as_mut().and_then(|hot_loops| {
+						hot_loops.enter(ops, pc - 1, &mut self.tape, &mut self.pointer)
+					}) {
+						steps += run.steps;
+						self.touched = self.touched.max(run.reach);
+						pc = end as usize;
+					}
+				}
+				Op::MulAdd(offset, factor) => {
+					// SAFETY: the pointer is on the tape between ops, see `Engine::bytecode_cell`
+					let value = unsafe { self.bytecode_cell() }.0;
+
+					// NOTE: the loop wouldn't have run, so the cell at the offset is left alone,
+					// even if it's past the end of a growing tape
+					if value != 0 {
+						let pointer = self.pointer;
+						self.shift(offset, settings.grow_tape);
+						// SAFETY: `shift` leaves the pointer on the tape, see `Engine::bytecode_cell`
+						unsafe { *self.bytecode_cell() += value.wrapping_mul(factor) };
+						self.pointer = pointer;
+					}
+				}
+				Op::RepeatEnd(start) => {
+					let again = if counting {
+						repetitions -= 1;
+						repetitions != 0
+					} else {
+						// SAFETY: the pointer is on the tape between ops, see `Engine::bytecode_cell`
+						unsafe { self.bytecode_cell() }.0 != 0
+					};
+
+					if again {
+						pc = start as usize;
+					}
+				}
+				Op::AddAt(offset, delta) => self.add_at(offset, delta, settings.grow_tape),
+				// NOTE: without a zero cell anywhere, the loop never ends, so the op runs again,
+				// still counting steps, until the budget runs out or the run is paused
+				Op::ScanRight => {
+					if !self.scan_right(settings.grow_tape) {
+						pc -= 1;
+					}
+				}
+				Op::ScanLeft => {
+					if !self.scan_left() {
+						pc -= 1;
+					}
+				}
+				Op::Print | Op::AddPrint(_) => {
+					if let Op::AddPrint(delta) = op {
+						// SAFETY: the pointer is on the tape between ops, see `Engine::bytecode_cell`
+						unsafe { *self.bytecode_cell() += delta };
+					}
+
+					// SAFETY: the pointer is on the tape between ops, see `Engine::bytecode_cell`
+					let output = unsafe { self.bytecode_cell() }.0;
+					stdout
+						.write(output)
+						.inspect_err(|_| self.stop_at_op(ops, index, &recent))?;
+					hooks.on_output(steps - 1, output);
+
+					if pattern.as_mut().is_some_and(|pattern| pattern.push(output)) {
+						output_matched = true;
+						break;
+					}
+				}
+				Op::Read => match self
+					.read(&mut stdin, &mut stdout, settings.eof)
+					.inspect_err(|_| self.stop_at_op(ops, index, &recent))?
+				{
+					Some(input) => hooks.on_input(steps - 1, input),
+					None if settings.quit_on_eof => break,
+					None => {}
+				},
+				Op::Extension(extension) => {
+					if let ControlFlow::Break(code) = self
+						.extension(
+							extension,
+							steps - 1,
+							&settings,
+							host,
+							&mut stdin,
+							&mut stdout,
+						)
+						.inspect_err(|_| self.stop_at_op(ops, index, &recent))?
+					{
+						exit_code = Some(code);
+						break;
+					}
+				}
+			}
+		}
+
+		stdout.write_pending()?;
+
+		Ok(RunSummary {
+			steps,
+			unread_input: stdin.into_unread(),
+			exit_code,
+			output_matched,
+			breakpoint_hit,
+			paused,
+			location: self.location.clone(),
+		})
+	}
+
+	/// Preview what running instructions would do, without changing the engine.
+	///
+	/// The instructions run on a copy of the engine, reading `input` and stopping after
+	/// `max_steps` instructions, which makes it safe to peek at programs that never end. Settings
+	/// are the same as in [`Engine::run`], except for [`RuntimeSettings::budget`] and
+	/// [`RuntimeSettings::cost`], which are ignored.
+	///
+	/// # Usage
+	///
+	/// ```
+	/// # use brainfuck_rs::{
+	/// #   engine::{CellChange, Engine, RuntimeSettings},
+	/// #   instruction::Instruction,
+	/// #   token::Token,
+	/// # };
+	/// let bf = Engine::default();
+	/// let instructions = Instruction::parse(Token::tokenize(",[->+<]>.")).unwrap();
+	///
+	/// let preview = bf
+	///     .peek(&instructions, b"A", RuntimeSettings::default(), 1000)
+	///     .unwrap();
+	///
+	/// assert_eq!(b"A", preview.output.as_slice());
+	/// assert_eq!(vec![CellChange { cell: 1, before: 0, after: 65 }], preview.changes);
+	/// assert!(preview.finished);
+	/// assert_eq!(0, bf.pointer);
+	/// ```
+	///
+	/// # Errors
+	///
+	/// Same as [`Engine::run`], except that running out of `max_steps` isn't an error.
+	pub fn peek(
+		&self,
+		instructions: &[Instruction],
+		mut input: &[u8],
+		settings: RuntimeSettings,
+		max_steps: u64,
+	) -> Result<Preview, RunError> {
+		let mut bf = self.clone();
+		let mut output = vec![];
+
+		let settings = RuntimeSettings {
+			budget: Some(max_steps),
+			cost: unit_cost,
+			..settings
+		};
+
+		let (steps, finished) = match bf.run(instructions, &mut input, &mut output, settings) {
+			Ok(summary) => (summary.steps, true),
+			Err(RunError::BudgetExhausted { spent, .. }) => (spent, false),
+			Err(e) => return Err(e),
+		};
+
+		let changes = bf
+			.tape
+			.iter()
+			.enumerate()
+			.filter_map(|(cell, after)| {
+				let before = self.tape.get(cell).copied().unwrap_or_default();
+
+				(before != *after).then_some(CellChange {
+					cell,
+					before: before.0,
+					after: after.0,
+				})
+			})
+			.collect();
+
+		Ok(Preview {
+			output,
+			changes,
+			pointer: bf.pointer,
+			steps,
+			finished,
+		})
+	}
+
+	/// Move the pointer by `offset` cells, wrapping around the ends of the tape, unless
+	/// `grow_tape` is set and it moves past the right end, in which case the tape is extended.
+	fn shift(&mut self, offset: i32, grow_tape: bool) {
+		let target = self.pointer as isize + offset as isize;
+		let length = self.tape.len() as isize;
+
+		if grow_tape && target >= length {
+			self.tape.resize(target as usize + 1, Wrapping(0));
+			self.pointer = target as usize;
+		} else {
+			self.pointer = target.rem_euclid(length) as usize;
+		}
+		self.touched = self.touched.max(self.pointer + 1);
+	}
+
+	/// Add `delta` to the cell `offset` cells away from the current one, which may wrap around the
+	/// ends of the tape, or be past the right end of a growing tape, like [`Engine::shift`] does.
+	fn add_at(&mut self, offset: i32, delta: u8, grow_tape: bool) {
+		let cell = self
+			.pointer
+			.checked_add_signed(offset as isize)
+			.filter(|&cell| cell < self.tape.len());
+
+		if let Some(cell) = cell {
+			self.tape[cell] += delta;
+			self.touched = self.touched.max(cell + 1);
+		} else {
+			let pointer = self.pointer;
+			self.shift(offset, grow_tape);
+			self.tape[self.pointer] += delta;
+			self.pointer = pointer;
+		}
+	}
+
+	/// Remember that the tree engine stopped at `instruction` among `roots`, see
+	/// [`Engine::location`].
+	#[cold]
+	fn stop_at_instruction(
+		&mut self,
+		roots: &[&Instruction],
+		instruction: &Instruction,
+		recent: &Recent,
+	) {
+		self.location = Location::of_instruction(roots, instruction).map(|location| {
+			location.with_recent_instructions(roots, recent, &self.tape, self.pointer)
+		});
+	}
+
+	/// Remember that the bytecode engine stopped at the op at `index`, see [`Engine::location`].
+	#[cold]
+	fn stop_at_op(&mut self, ops: &[Op], index: usize, recent: &Recent) {
+		self.location =
+			Some(Location::of_op(ops, index).with_recent_ops(recent, &self.tape, self.pointer));
+	}
+
+	/// Fail as the `engine` that's about to run if the pointer is off the tape, which engines that
+	/// access cells unchecked or run native code do before they start.
+	pub(crate) fn check_pointer(&self, engine: &'static str) -> Result<(), RunError> {
+		if self.pointer >= self.tape.len() {
+			return Err(RunError::InternalError {
+				engine,
+				message: OutOfTape {
+					index: self.pointer,
+					length: self.tape.len(),
+				}
+				.to_string(),
+			});
+		}
+
+		Ok(())
+	}
+
+	/// Whether the cells `bounds` reach from the current one are all on the tape, without wrapping
+	/// around either end.
+	fn fits(&self, bounds: PointerBounds) -> bool {
+		self.pointer.checked_add_signed(bounds.min).is_some()
+			&& self
+				.pointer
+				.checked_add_signed(bounds.max)
+				.is_some_and(|last| last < self.tape.len())
+	}
+
+	/// Set the current cell and the ones up to `offset` cells away to `value`, moving the pointer
+	/// to the last one, with a single `fill()` unless the cells wrap around an end of the tape.
+	fn fill(&mut self, offset: i32, value: u8, grow_tape: bool) {
+		let last = self
+			.pointer
+			.checked_add_signed(offset as isize)
+			.filter(|&last| last < self.tape.len() || grow_tape);
+
+		let Some(last) = last else {
+			for _ in 0..offset.unsigned_abs() {
+				self.tape[self.pointer] = Wrapping(value);
+				self.shift(offset.signum(), grow_tape);
+			}
+			self.tape[self.pointer] = Wrapping(value);
+			return;
+		};
+
+		if last >= self.tape.len() {
+			self.tape.resize(last + 1, Wrapping(0));
+		}
+		let (from, to) = (self.pointer.min(last), self.pointer.max(last));
+		self.tape[from..=to].fill(Wrapping(value));
+		self.pointer = last;
+		self.touched = self.touched.max(to + 1);
+	}
+
+	/// Move the pointer to the nearest zero cell at or right of it, like `[>]`, wrapping around
+	/// the end of the tape, or extending it if `grow_tape` is set. Returns whether there was a
+	/// zero cell to move to.
+	fn scan_right(&mut self, grow_tape: bool) -> bool {
+		let tape = cells(&self.tape);
+		let length = tape.len();
+
+		if let Some(index) = memchr::memchr(0, &tape[self.pointer..]) {
+			self.pointer += index;
+			self.touched = self.touched.max(self.pointer + 1);
+			return true;
+		}
+
+		if grow_tape {
+			self.tape.push(Wrapping(0));
+			self.pointer = length;
+			self.touched = length + 1;
+			return true;
+		}
+
+		self.touched = length;
+		match memchr::memchr(0, &tape[..self.pointer]) {
+			Some(index) => {
+				self.pointer = index;
+				true
+			}
+			None => false,
+		}
+	}
+
+	/// Move the pointer to the nearest zero cell at or left of it, like `[<]`, wrapping around
+	/// the start of the tape. Returns whether there was a zero cell to move to.
+	fn scan_left(&mut self) -> bool {
+		let tape = cells(&self.tape);
+
+		if let Some(index) = memchr::memrchr(0, &tape[..=self.pointer]) {
+			self.pointer = index;
+			return true;
+		}
+
+		self.touched = tape.len();
+		match memchr::memrchr(0, &tape[self.pointer + 1..]) {
+			Some(index) => {
+				self.pointer += 1 + index;
+				true
+			}
+			None => false,
+		}
+	}
+
+	/// Read a byte from `stdin` into the current cell, returning it, or [`None`] on EOF, in
+	/// which case the cell is set to zero.
+	#[inline(never)]
+	fn read(
+		&mut self,
+		stdin: &mut Input<impl Read>,
+		stdout: &mut Output<impl Write>,
+		eof: Eof,
+	) -> io::Result<Option<u8>> {
+		let input = stdin.read(stdout)?;
+
+		match (input, eof) {
+			(Some(byte), _) => self.tape[self.pointer] = Wrapping(byte),
+			(None, Eof::Zero) => self.tape[self.pointer] = Wrapping(0),
+			(None, Eof::MinusOne) => self.tape[self.pointer] = Wrapping(u8::MAX),
+			(None, Eof::Unchanged) => {}
+		}
+
+		Ok(input)
+	}
+
+	/// Execute an extension instruction, `step` being the number of instructions executed before
+	/// it. Breaks with the exit status if the program should end.
+	#[cold]
+	fn extension(
+		&mut self,
+		extension: Extension,
+		step: u64,
+		settings: &RuntimeSettings,
+		host: &mut impl HostServices,
+		stdin: &mut Input<impl Read>,
+		stdout: &mut Output<impl Write>,
+	) -> io::Result<ControlFlow<u8>> {
+		match extension {
+			Extension::Time => {
+				let time = match settings.time_source {
+					TimeSource::Counter => step,
+					TimeSource::HostMillis => host.clock_millis()?,
+				};
+
+				// NOTE: only the lowest byte fits into a cell, which is enough for measuring short
+				// intervals
+				self.tape[self.pointer] = Wrapping(time as u8);
+			}
+			Extension::Exit => return Ok(ControlFlow::Break(self.tape[self.pointer].0)),
+			Extension::Custom(symbol) => match settings.extensions.handler(symbol) {
+				Some(handler) => {
+					let mut io = HandlerIo { stdin, stdout };
+					handler.call(&mut EngineMut::new(self, &mut io))?;
+				}
+				None => {
+					return Err(io::Error::new(
+						ErrorKind::Unsupported,
+						format!("there's no handler for the custom instruction `{symbol}`"),
+					));
+				}
+			},
+		}
+
+		Ok(ControlFlow::Continue(()))
+	}
+}
+
+/// Input of a running program, read in chunks if [`RuntimeSettings::read_ahead`] is set.
+///
+/// Translates line endings if [`RuntimeSettings::newlines`] is [`NewlineMode::CrlfToLf`].
+struct Input<'a, R: Read> {
+	reader: &'a mut R,
+	buffer: Vec<u8>,
+	position: usize,
+	chunk_size: usize,
+	newlines: NewlineMode,
+	/// Whether the last byte read was a carriage return, which the next line feed belongs to.
+	after_cr: bool,
+}
+
+impl<'a, R: Read> Input<'a, R> {
+	const CHUNK_SIZE: usize = 8 * 1024;
+
+	fn new(reader: &'a mut R, read_ahead: bool, newlines: NewlineMode) -> Self {
+		let chunk_size = if read_ahead { Self::CHUNK_SIZE } else { 1 };
+
+		Self {
+			reader,
+			buffer: Vec::with_capacity(chunk_size),
+			position: 0,
+			chunk_size,
+			newlines,
+			after_cr: false,
+		}
+	}
+
+	/// Read a byte, or [`None`] on EOF.
+	#[inline]
+	fn read(&mut self, stdout: &mut Output<impl Write>) -> io::Result<Option<u8>> {
+		if self.position == self.buffer.len() {
+			// NOTE: the program may be waiting for a response to what it just printed
+			stdout.flush()?;
+
+			if !self.fill()? {
+				return Ok(None);
+			}
+		}
+
+		let byte = self.buffer[self.position];
+		self.position += 1;
+
+		if self.newlines == NewlineMode::CrlfToLf {
+			return self.translate(byte, stdout);
+		}
+
+		Ok(Some(byte))
+	}
+
+	/// Turn a carriage return into a line feed, and skip the line feed that follows it.
+	///
+	/// Looking ahead for the line feed would wait on an interactive terminal, so the carriage
+	/// return is translated right away, and the line feed dropped once it arrives.
+	#[cold]
+	fn translate(&mut self, byte: u8, stdout: &mut Output<impl Write>) -> io::Result<Option<u8>> {
+		let after_cr = mem::replace(&mut self.after_cr, byte == b'\r');
+
+		match byte {
+			b'\r' => Ok(Some(b'\n')),
+			b'\n' if after_cr => self.read(stdout),
+			_ => Ok(Some(byte)),
+		}
+	}
+
+	/// Read the next chunk, returning `false` on EOF.
+	///
+	/// A single `read` call returns whatever is available, so an interactive terminal isn't
+	/// waited on for more than the line that was entered.
+	#[cold]
+	fn fill(&mut self) -> io::Result<bool> {
+		self.buffer.resize(self.chunk_size, 0);
+		self.position = 0;
+
+		loop {
+			match self.reader.read(&mut self.buffer) {
+				Ok(read) => {
+					self.buffer.truncate(read);
+					return Ok(read != 0);
+				}
+				Err(e) if e.kind() == ErrorKind::Interrupted => {}
+				Err(e) => {
+					self.buffer.clear();
+					return Err(e);
+				}
+			}
+		}
+	}
+
+	/// Input that was read ahead, but not consumed.
+	fn into_unread(self) -> Vec<u8> {
+		self.buffer[self.position..].to_vec()
+	}
+}
+
+/// Output of a running program. Unless every print must be flushed, prints are collected into
+/// chunks, so the writer sees one write per chunk rather than per byte.
+///
+/// Translates line endings if [`RuntimeSettings::newlines`] is [`NewlineMode::LfToCrlf`].
+struct Output<'a, W: Write> {
+	writer: &'a mut W,
+	buffer: Vec<u8>,
+	should_flush: bool,
+	newlines: NewlineMode,
+}
+
+impl<'a, W: Write> Output<'a, W> {
+	const CHUNK_SIZE: usize = 8 * 1024;
+
+	fn new(writer: &'a mut W, should_flush: bool, newlines: NewlineMode) -> Self {
+		Self {
+			writer,
+			buffer: Vec::with_capacity(if should_flush { 0 } else { Self::CHUNK_SIZE }),
+			should_flush,
+			newlines,
+		}
+	}
+
+	/// Print a byte.
+	#[inline]
+	fn write(&mut self, byte: u8) -> io::Result<()> {
+		let bytes: &[u8] = if byte == b'\n' && self.newlines == NewlineMode::LfToCrlf {
+			b"\r\n"
+		} else {
+			slice::from_ref(&byte)
+		};
+
+		if self.should_flush {
+			self.writer.write_all(bytes)?;
+			return self.writer.flush();
+		}
+
+		self.buffer.extend_from_slice(bytes);
+
+		if self.buffer.len() >= Self::CHUNK_SIZE {
+			self.write_pending()?;
+		}
+
+		Ok(())
+	}
+
+	/// Hand the collected chunk over to the writer.
+	#[cold]
+	fn write_pending(&mut self) -> io::Result<()> {
+		if !self.buffer.is_empty() {
+			self.writer.write_all(&self.buffer)?;
+			self.buffer.clear();
+		}
+
+		Ok(())
+	}
+
+	/// Write the collected chunk and flush the writer, so everything printed so far is visible.
+	fn flush(&mut self) -> io::Result<()> {
+		self.write_pending()?;
+		self.writer.flush()
+	}
+}
+
+impl<W: Write> Drop for Output<'_, W> {
+	/// Output printed before an error is still written, errors aren't reported at this point
+	/// though.
+	fn drop(&mut self) {
+		let _ = self.write_pending();
+	}
+}
+
+/// Program IO as seen by the handlers of custom instructions.
+struct HandlerIo<'a, I, O> {
+	stdin: &'a mut I,
+	stdout: &'a mut O,
+}
+
+impl<R: Read, W: Write> ProgramIo for HandlerIo<'_, Input<'_, R>, Output<'_, W>> {
+	fn read(&mut self) -> io::Result<Option<u8>> {
+		self.stdin.read(self.stdout)
+	}
+
+	fn write(&mut self, byte: u8) -> io::Result<()> {
+		self.stdout.write(byte)
+	}
+}
+
+/// Finds [`RuntimeSettings::break_on_output`] in the output as it's printed.
+struct OutputPattern<'a> {
+	pattern: &'a [u8],
+	/// Length of the longest proper prefix of `pattern[..=i]` that is also its suffix, which is
+	/// how much of the pattern is still matched when the next byte doesn't fit.
+	fallback: Vec<usize>,
+	/// Length of the prefix of the pattern the output currently ends with.
+	matched: usize,
+}
+
+impl<'a> OutputPattern<'a> {
+	fn new(pattern: &'a [u8]) -> Self {
+		let mut fallback = vec![0; pattern.len()];
+		let mut matched = 0;
+
+		for i in 1..pattern.len() {
+			while matched > 0 && pattern[i] != pattern[matched] {
+				matched = fallback[matched - 1];
+			}
+			if pattern[i] == pattern[matched] {
+				matched += 1;
+			}
+			fallback[i] = matched;
+		}
+
+		Self {
+			pattern,
+			fallback,
+			matched: 0,
+		}
+	}
+
+	/// Take the next printed byte, returning whether the output now contains the pattern.
+	#[cold]
+	fn push(&mut self, byte: u8) -> bool {
+		if self.pattern.is_empty() {
+			return true;
+		}
+
+		while self.matched > 0 && self.pattern[self.matched] != byte {
+			self.matched = self.fallback[self.matched - 1];
+		}
+		if self.pattern[self.matched] == byte {
+			self.matched += 1;
+		}
+
+		self.matched == self.pattern.len()
+	}
+}
+
+/// Catch panics of an engine while it runs `execute`, and return them as
+/// [`RunError::InternalError`] of `engine`, if `enabled`, see [`RuntimeSettings::contain_panics`].
+pub(crate) fn contain(
+	enabled: bool,
+	engine: &'static str,
+	execute: impl FnOnce() -> Result<RunSummary, RunError>,
+) -> Result<RunSummary, RunError> {
+	if !enabled {
+		return execute();
+	}
+
+	// NOTE: the engine is left as it was when the panic happened, which is safe, since its tape
+	// and pointer are always valid on their own
+	panic::catch_unwind(AssertUnwindSafe(execute)).unwrap_or_else(|payload| {
+		let message = payload
+			.downcast_ref::<&str>()
+			.map(|&message| message.to_owned())
+			.or_else(|| payload.downcast_ref::<String>().cloned())
+			.unwrap_or_else(|| "unknown cause".to_owned());
+
+		Err(RunError::InternalError { engine, message })
+	})
+}
+
+/// Refuse to run programs that need capabilities that aren't granted.
+fn check_capabilities(
+	required: &BTreeSet<Capability>,
+	settings: &RuntimeSettings,
+) -> Result<(), RunError> {
+	let missing_capabilities: Vec<Capability> = required
+		.difference(&settings.capabilities)
+		.copied()
+		.collect();
+
+	if missing_capabilities.is_empty() {
+		Ok(())
+	} else {
+		Err(RunError::CapabilitiesNotGranted(missing_capabilities))
+	}
+}
+
+impl Default for Engine {
+	/// Creates a new `Engine` with default values:
+	///
+	/// ```
+	/// # use brainfuck_rs::engine::Engine;
+	/// Engine::new(30_000)
+	/// # ;
+	/// ```
+	fn default() -> Self {
+		Self::new(30_000)
+	}
+}
+
+// NOTE: which cells were touched is bookkeeping, not part of the state
+impl PartialEq for Engine {
+	fn eq(&self, other: &Self) -> bool {
+		self.pointer == other.pointer && self.tape == other.tape
+	}
+}
+
+impl Eq for Engine {}
+
+/// Settings that determine how interpreter should behave.
+#[derive(Debug, Clone)]
+pub struct RuntimeSettings {
+	/// If `true`, the output is flushed on every print instruction, otherwise the output is
+	/// collected into chunks that are written at once, when they fill up, before reading input and
+	/// when the program ends.
+	pub should_flush: bool,
+	/// Stop execution on EOF.
+	///
+	/// Particularly usefull for environments with less control, like piped data and input buffers.
+	pub quit_on_eof: bool,
+	/// What a read leaves in the cell once the input ended, unless the program quits then.
+	pub eof: Eof,
+	/// What the [`Extension::Time`] instruction writes into the cell.
+	pub time_source: TimeSource,
+	/// Capabilities the program is allowed to use. Programs that need anything else are refused
+	/// before they start.
+	pub capabilities: BTreeSet<Capability>,
+	/// If `true`, moving right from the last cell appends a new cell instead of wrapping around to
+	/// the first one.
+	pub grow_tape: bool,
+	/// Maximum total cost of the executed instructions, as measured by [`RuntimeSettings::cost`].
+	/// Execution stops right before the instruction that would exceed it.
+	///
+	/// With the default cost of 1 per instruction, this is the maximum number of instructions.
+	pub budget: Option<u64>,
+	/// Cost of executing an instruction, consumed from [`RuntimeSettings::budget`]. A loop is
+	/// charged every time its condition is checked.
+	///
+	/// Allows giving IO a different weight than arithmetic, for example. Not called at all when
+	/// there's no budget.
+	pub cost: CostFn,
+	/// Compile innermost loops of [`Bytecode`] into closures once they were entered this many
+	/// times, which run whole iterations at once instead of dispatching op by op. Only loops that
+	/// do nothing but arithmetic and pointer movement are compiled.
+	///
+	/// With the `jit` feature, other hot loops that don't do IO, like ones with nested loops, are
+	/// compiled to native code with Cranelift instead, unless the tape grows. Steps are counted
+	/// the same either way.
+	///
+	/// Has no effect on the tree engine, or when there's a budget.
+	pub specialize_after: Option<u64>,
+	/// Read input in chunks rather than byte by byte. Input that was read, but not consumed by
+	/// the program, is returned in [`RunSummary::unread_input`] instead of staying in the reader.
+	///
+	/// Chunked reads never wait for more input than is available, so interactive programs keep
+	/// working.
+	pub read_ahead: bool,
+	/// Stop the program as soon as its output contains this pattern, setting
+	/// [`RunSummary::output_matched`]. Useful for running a program until it prints a prompt.
+	pub break_on_output: Option<Vec<u8>>,
+	/// What to do when the program gets stuck in a loop it can never leave, because it came back
+	/// to exactly the same state without any IO in between, like `+[]` or `+[>+<]`. Such loops
+	/// are reported through [`Hooks::on_idle_loop`].
+	///
+	/// Only the tree engine detects them. Every so often, it keeps a copy of the tape, so this
+	/// slows programs with a large tape down. Without the `monitoring` feature, which is enabled
+	/// by default, they aren't detected at all, and the engine doesn't even check whether to.
+	pub idle_loops: IdleLoops,
+	/// How line endings are translated between the program and the outside world, for programs
+	/// that expect a different one than the platform they run on uses.
+	///
+	/// Hooks and [`RuntimeSettings::break_on_output`] see the bytes as the program does.
+	pub newlines: NewlineMode,
+	/// Extensions the program was parsed with, whose handlers run its
+	/// [`Extension::Custom`] instructions. Other extensions don't need to be listed.
+	pub extensions: ExtensionSet,
+	/// If `true`, a panic while running the program, be it due to a bug in the engine or its
+	/// optimizations, or in a hook or handler, is returned as [`RunError::InternalError`] instead
+	/// of unwinding into the caller, so it doesn't take down a whole server that runs programs.
+	/// The panic hook still runs, and reports the panic as usual.
+	///
+	/// Panics can only be contained where they unwind, so builds with `panic = "abort"` still
+	/// abort, as do panics of the reader or writer while code compiled with the `jit` feature calls
+	/// them.
+	///
+	/// Turn this off when debugging the crate itself, to let panics reach the test or debugger as
+	/// usual.
+	pub contain_panics: bool,
+	/// Call [`Hooks::on_yield`] every this many instructions (or ops), giving embedders that run
+	/// programs on a game loop or UI thread a chance to do their own work in between, or to
+	/// cooperatively schedule several programs on one thread.
+	///
+	/// Checking whether it's time costs a single comparison per instruction. Loops compiled due
+	/// to [`RuntimeSettings::specialize_after`] run all their iterations at once, so the engine
+	/// may yield later than asked for.
+	pub yield_every: Option<NonZeroU64>,
+	/// Pause the program at the next [`SafePoint`] once a pause is requested through this flag,
+	/// calling [`Hooks::on_pause`] with the engine as it is then. Debuggers, Ctrl-C handlers and
+	/// the like request pauses from another thread or a signal handler.
+	///
+	/// Programs can pause before loop conditions and IO, which costs a check of the flag there,
+	/// and nothing at all without one. Loops compiled due to
+	/// [`RuntimeSettings::specialize_after`] run all their iterations at once, so the engine may
+	/// pause later than asked for.
+	pub pause: Option<PauseFlag>,
+	/// Remember the last instructions (or ops) that ran, so [`Engine::location`] can tell what
+	/// happened right before the run stopped, see [`Location::recent`].
+	///
+	/// Costs a write per instruction, so it's off by default. The cells around the pointer are
+	/// there either way.
+	pub remember_recent: bool,
+}
+
+/// Function that determines the cost of executing an instruction.
+pub type CostFn = fn(&Instruction) -> u64;
+
+/// The default [`CostFn`], where every instruction costs 1.
+pub const fn unit_cost(_instruction: &Instruction) -> u64 {
+	1
+}
+
+impl RuntimeSettings {
+	/// Whether the engines need to check something on every step, for a budget, pauses, yields
+	/// and the like. The checks add up, so the engines are compiled once more without them, for
+	/// the runs that don't ask for any.
+	pub(crate) fn watch_steps(&self) -> bool {
+		self.budget.is_some()
+			|| self.pause.is_some()
+			|| self.yield_every.is_some()
+			|| self.remember_recent
+			|| self.idle_loops != IdleLoops::Ignore
+	}
+}
+
+impl Default for RuntimeSettings {
+	/// Creates a new `RuntimeSettings` with default values:
+	///
+	/// ```
+	/// # use std::collections::BTreeSet;
+	/// # use brainfuck_rs::{
+	/// #   engine::{unit_cost, Eof, IdleLoops, NewlineMode, RuntimeSettings, TimeSource},
+	/// #   extension::ExtensionSet,
+	/// # };
+	/// RuntimeSettings {
+	///     should_flush: true,
+	///     quit_on_eof: false,
+	///     eof: Eof::Zero,
+	///     time_source: TimeSource::Counter,
+	///     capabilities: BTreeSet::new(),
+	///     grow_tape: false,
+	///     budget: None,
+	///     cost: unit_cost,
+	///     specialize_after: None,
+	///     read_ahead: false,
+	///     break_on_output: None,
+	///     idle_loops: IdleLoops::Ignore,
+	///     newlines: NewlineMode::Passthrough,
+	///     extensions: ExtensionSet::default(),
+	///     contain_panics: true,
+	///     yield_every: None,
+	///     pause: None,
+	///     remember_recent: false,
+	/// }
+	/// # ;
+	/// ```
+	fn default() -> Self {
+		Self {
+			should_flush: true,
+			quit_on_eof: false,
+			eof: Eof::Zero,
+			time_source: TimeSource::Counter,
+			capabilities: BTreeSet::new(),
+			grow_tape: false,
+			budget: None,
+			cost: unit_cost,
+			specialize_after: None,
+			read_ahead: false,
+			break_on_output: None,
+			idle_loops: IdleLoops::Ignore,
+			newlines: NewlineMode::Passthrough,
+			extensions: ExtensionSet::default(),
+			contain_panics: true,
+			yield_every: None,
+			pause: None,
+			remember_recent: false,
+		}
+	}
+}
+
+/// Information about a finished run.
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct RunSummary {
+	/// Number of executed instructions, counting every check of a loop's condition as one.
+	pub steps: u64,
+	/// Input read ahead due to [`RuntimeSettings::read_ahead`], but not consumed by the program.
+	/// It's lost if the run fails.
+	pub unread_input: Vec<u8>,
+	/// Exit status the program ended with through [`Extension::Exit`], or [`None`] if it ran to
+	/// the end.
+	pub exit_code: Option<u8>,
+	/// `true` if the program was stopped because its output matched
+	/// [`RuntimeSettings::break_on_output`].
+	pub output_matched: bool,
+	/// `true` if the program was stopped by [`Hooks::should_break`] before the instruction it
+	/// would have executed next.
+	pub breakpoint_hit: bool,
+	/// `true` if the program was paused through [`RuntimeSettings::pause`], and
+	/// [`Hooks::on_pause`] ended the run there.
+	pub paused: bool,
+	/// Where the program was paused or stopped at a breakpoint, see [`Engine::location`].
+	pub location: Option<Location>,
+}
+
+/// What running instructions would do, see [`Engine::peek`].
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct Preview {
+	/// Everything the instructions would print.
+	pub output: Vec<u8>,
+	/// Cells that would end up with a different value, in the order of the tape.
+	pub changes: Vec<CellChange>,
+	/// Where the pointer would end up.
+	pub pointer: usize,
+	/// Number of instructions that would run, counted like [`RunSummary::steps`].
+	pub steps: u64,
+	/// `false` if the instructions would run for longer than allowed.
+	pub finished: bool,
+}
+
+/// A cell whose value differs, see [`Preview::changes`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct CellChange {
+	/// Index of the cell on the tape.
+	pub cell: usize,
+	/// Value of the cell before running the instructions.
+	pub before: u8,
+	/// Value of the cell after running them.
+	pub after: u8,
+}
+
+/// An error that could be created while running a program.
+///
+/// Errors that stopped the program at an instruction (or op) tell where, see
+/// [`RunError::location`].
+#[derive(Debug, Error)]
+pub enum RunError {
+	/// Reading input, writing output or accessing the host failed
+	#[error("{error}")]
+	Io {
+		/// What failed.
+		error: io::Error,
+		/// Where the program stopped, see [`RunError::location`].
+		location: Option<Box<Location>>,
+	},
+	/// The program needs capabilities that weren't granted
+	#[error("program needs capabilities that weren't granted: {}", list(.0))]
+	CapabilitiesNotGranted(Vec<Capability>),
+	/// The next instruction would exceed the budget
+	#[error("execution budget of {budget} exhausted after spending {spent}")]
+	BudgetExhausted {
+		/// Cost of the instructions executed so far.
+		spent: u64,
+		/// The budget from [`RuntimeSettings::budget`].
+		budget: u64,
+		/// Where the program stopped, see [`RunError::location`].
+		location: Option<Box<Location>>,
+	},
+	/// The program got stuck in a loop, see [`RuntimeSettings::idle_loops`]
+	#[error("program got stuck in a loop that never ends at step {step}")]
+	IdleLoop {
+		/// Number of instructions executed before the loop was found out.
+		step: u64,
+		/// Where the program stopped, see [`RunError::location`].
+		location: Option<Box<Location>>,
+	},
+	/// The engine panicked, see [`RuntimeSettings::contain_panics`]
+	#[error("the {engine} engine panicked: {message}")]
+	InternalError {
+		/// The engine that was running: `tree`, `bytecode` for the fused and tiered engines too, or
+		/// `native` for the jit engine.
+		engine: &'static str,
+		/// What the panic said.
+		message: String,
+	},
+}
+
+impl RunError {
+	/// Where the program stopped, with the cells around the pointer and what ran right before,
+	/// like [`Engine::location`] tells after the run.
+	///
+	/// [`None`] if the run failed before it started or due to a panic, or if the error didn't
+	/// come from an engine.
+	pub fn location(&self) -> Option<&Location> {
+		match self {
+			Self::Io { location, .. }
+			| Self::BudgetExhausted { location, .. }
+			| Self::IdleLoop { location, .. } => location.as_deref(),
+			Self::CapabilitiesNotGranted(_) | Self::InternalError { .. } => None,
+		}
+	}
+
+	/// Tell that the program stopped at `stopped`, unless the error tells where already.
+	fn stopped_at(mut self, stopped: Option<&Location>) -> Self {
+		if let Self::Io { location, .. }
+		| Self::BudgetExhausted { location, .. }
+		| Self::IdleLoop { location, .. } = &mut self
+		{
+			if location.is_none() {
+				*location = stopped.cloned().map(Box::new);
+			}
+		}
+
+		self
+	}
+}
+
+impl From<io::Error> for RunError {
+	fn from(error: io::Error) -> Self {
+		Self::Io {
+			error,
+			location: None,
+		}
+	}
+}
+
+/// The cells of `tape` as plain bytes, for searching them with `memchr`.
+fn cells(tape: &[Wrapping<u8>]) -> &[u8] {
+	// SAFETY: `Wrapping` is `repr(transparent)`, so it has the same layout as the byte it wraps
+	unsafe { slice::from_raw_parts(tape.as_ptr().cast(), tape.len()) }
+}
+
+/// Join items into a comma-separated list.
+fn list(items: &[impl ToString]) -> String {
+	items
+		.iter()
+		.map(ToString::to_string)
+		.collect::<Vec<_>>()
+		.join(", ")
+}
+
+/// What to do about loops that never end, see [`RuntimeSettings::idle_loops`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum IdleLoops {
+	/// Don't look for them at all.
+	Ignore,
+	/// Report the first one, then let the program keep running.
+	Report,
+	/// Report the first one, then stop the program with [`RunError::IdleLoop`].
+	Abort,
+}
+
+/// How line endings are translated, see [`RuntimeSettings::newlines`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum NewlineMode {
+	/// Bytes are passed on as they are.
+	Passthrough,
+	/// Line endings in the input become a single line feed (10), whether they're a carriage
+	/// return followed by a line feed, as on Windows, or a carriage return (13) alone. Suits
+	/// programs that read a line with `,` until they see 10.
+	CrlfToLf,
+	/// Every line feed the program prints is preceded by a carriage return.
+	LfToCrlf,
+}
+
+/// What a read leaves in the cell once the input ended, see [`RuntimeSettings::eof`].
+///
+/// Interpreters disagree on this, and programs rely on what theirs does, usually to end a loop
+/// reading the input.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Eof {
+	/// The cell is set to 0.
+	#[default]
+	Zero,
+	/// The cell is set to -1, which is 255, like C's `getchar` returns.
+	MinusOne,
+	/// The cell keeps its value.
+	Unchanged,
+}
+
+/// Source of the value written by the [`Extension::Time`] instruction.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TimeSource {
+	/// Number of instructions executed before the time instruction. Deterministic, which makes it
+	/// suitable for tests.
+	Counter,
+	/// Milliseconds reported by [`HostServices::clock_millis`].
+	HostMillis,
+}
+
+#[cfg(test)]
+mod tests {
+	use std::io::{BufReader, BufWriter};
+	use std::path::Path;
+	use std::str;
+
+	use lazy_static::lazy_static;
+
+	use crate::bytecode::{Fusion, Profile};
+	use crate::host::{DenyAll, FileMode, HostFile};
+	use crate::program::Program;
+	use crate::token::Token;
+	use crate::utils::StripShebang;
+
+	use super::*;
+
+	lazy_static! {
+		static ref HELLO_WORLD: &'static str =
+			include_str!("../../examples/brainfuck-programs/hello-world.b").strip_shebang();
 		static ref ROT13: &'static str =
 			include_str!("../../examples/brainfuck-programs/rot13.b").strip_shebang();
 	}
 
 	#[test]
-	fn non_stdout_buffer() {
+	fn non_stdout_buffer() {
+		let mut bf = Engine::default();
+		let settings = RuntimeSettings::default();
+
+		let mut input = BufReader::new(<&[u8]>::default());
+		let mut output = BufWriter::new(vec![]);
+
+		let tokens = Token::tokenize(&HELLO_WORLD);
+		let instructions = Instruction::parse(tokens).unwrap();
+
+		bf.run(&instructions, &mut input, &mut output, settings)
+			.unwrap();
+
+		assert_eq!(
+			"Hello World!\n",
+			str::from_utf8(output.into_inner().unwrap().as_slice()).unwrap()
+		);
+	}
+
+	#[test]
+	fn non_stdin_buffer() {
+		let mut bf = Engine::default();
+		let settings = RuntimeSettings {
+			quit_on_eof: true,
+			..Default::default()
+		};
+
+		let mut input = BufReader::new(b"Hello, World!".as_slice());
+		let mut output = BufWriter::new(vec![]);
+
+		let tokens = Token::tokenize(&ROT13);
+		let instructions = Instruction::parse(tokens).unwrap();
+
+		bf.run(&instructions, &mut input, &mut output, settings)
+			.unwrap();
+
+		assert_eq!(
+			"Uryyb, Jbeyq!",
+			str::from_utf8(output.into_inner().unwrap().as_slice()).unwrap()
+		);
+	}
+
+	#[test]
+	fn time_extension() {
+		struct FrozenClock;
+
+		impl HostServices for FrozenClock {
+			fn clock_millis(&mut self) -> io::Result<u64> {
+				Ok(1234)
+			}
+
+			fn random_byte(&mut self) -> io::Result<u8> {
+				DenyAll.random_byte()
+			}
+
+			fn env_var(&mut self, name: &str) -> io::Result<Option<String>> {
+				DenyAll.env_var(name)
+			}
+
+			fn open_file(&mut self, path: &Path, mode: FileMode) -> io::Result<Box<dyn HostFile>> {
+				DenyAll.open_file(path, mode)
+			}
+		}
+
+		let mut bf = Engine::default();
+
+		let mut input = BufReader::new(<&[u8]>::default());
+		let mut output = BufWriter::new(vec![]);
+
+		let instructions =
+			Instruction::parse(Token::tokenize_with_extensions("++~>~", &[Extension::Time]))
+				.unwrap();
+
+		let settings = RuntimeSettings {
+			capabilities: BTreeSet::from([Capability::Clock]),
+			..Default::default()
+		};
+
+		bf.run(&instructions, &mut input, &mut output, settings.clone())
+			.unwrap();
+
+		assert_eq!(vec![Wrapping(2), Wrapping(4)], bf.tape[..2]);
+
+		let settings = RuntimeSettings {
+			time_source: TimeSource::HostMillis,
+			..settings
+		};
+
+		bf.run_with_host(
+			&instructions,
+			&mut input,
+			&mut output,
+			settings.clone(),
+			&mut FrozenClock,
+		)
+		.unwrap();
+
+		assert_eq!(Wrapping((1234 % 256) as u8), bf.tape[1]);
+
+		let error = bf
+			.run_with_host(
+				&instructions,
+				&mut input,
+				&mut output,
+				settings,
+				&mut DenyAll,
+			)
+			.unwrap_err();
+
+		assert!(
+			matches!(error, RunError::Io { error: e, .. } if e.kind() == ErrorKind::PermissionDenied)
+		);
+	}
+
+	#[test]
+	fn break_on_output() {
+		let instructions = Instruction::parse(Token::tokenize(&HELLO_WORLD)).unwrap();
+		let bytecode = Bytecode::compile(&instructions).collapse();
+		let settings = RuntimeSettings {
+			break_on_output: Some(b"o W".to_vec()),
+			..Default::default()
+		};
+
+		let mut output = vec![];
+		let tree = Engine::default()
+			.run(
+				&instructions,
+				&mut [].as_slice(),
+				&mut output,
+				settings.clone(),
+			)
+			.unwrap();
+
+		assert!(tree.output_matched);
+		assert_eq!(b"Hello W", output.as_slice());
+
+		let mut output = vec![];
+		let vm = Engine::default()
+			.run_bytecode(&bytecode, &mut [].as_slice(), &mut output, settings)
+			.unwrap();
+
+		assert!(vm.output_matched);
+		assert_eq!(b"Hello W", output.as_slice());
+
+		// NOTE: a partial match that fails must fall back to what still matches
+		let mut pattern = OutputPattern::new(b"aab");
+		assert!(!b"aaa".iter().any(|&byte| pattern.push(byte)));
+		assert!(pattern.push(b'b'));
+	}
+
+	#[test]
+	#[cfg(feature = "monitoring")]
+	fn idle_loops() {
+		fn run(code: &str, input: &[u8], settings: RuntimeSettings) -> Result<Vec<u8>, RunError> {
+			let instructions = Instruction::parse(Token::tokenize(code)).unwrap();
+
+			let mut output = vec![];
+			Engine::default().run(&instructions, &mut &input[..], &mut output, settings)?;
+
+			Ok(output)
+		}
+
+		let abort = RuntimeSettings {
+			quit_on_eof: true,
+			idle_loops: IdleLoops::Abort,
+			..Default::default()
+		};
+
+		assert!(matches!(
+			run("+[]", b"", abort.clone()),
+			Err(RunError::IdleLoop { .. })
+		));
+		// NOTE: the state only repeats once the second cell wraps around
+		assert!(matches!(
+			run("+[>+<]", b"", abort.clone()),
+			Err(RunError::IdleLoop { .. })
+		));
+		assert_eq!(
+			b"Hello World!\n".as_slice(),
+			run(&HELLO_WORLD, b"", abort.clone()).unwrap()
+		);
+		assert_eq!(b"nop".as_slice(), run(&ROT13, b"abc", abort).unwrap());
+
+		#[derive(Default)]
+		struct Stuck(Vec<u64>);
+
+		impl Hooks for Stuck {
+			fn on_idle_loop(&mut self, step: u64, _loop_instruction: &Instruction) {
+				self.0.push(step);
+			}
+		}
+
+		// NOTE: reporting lets the program run into the budget, and reports only once
+		let instructions = Instruction::parse(Token::tokenize("+[]")).unwrap();
+		let mut stuck = Stuck::default();
+		let result = Engine::default().run_with(
+			&instructions,
+			&mut [].as_slice(),
+			&mut vec![],
+			RuntimeSettings {
+				idle_loops: IdleLoops::Report,
+				budget: Some(100),
+				..Default::default()
+			},
+			&mut StdHost::default(),
+			&mut stuck,
+		);
+
+		assert!(matches!(result, Err(RunError::BudgetExhausted { .. })));
+		assert_eq!(vec![2], stuck.0);
+	}
+
+	#[test]
+	fn yields() {
+		#[derive(Default)]
+		struct Yields(Vec<u64>);
+
+		impl Hooks for Yields {
+			fn on_yield(&mut self, step: u64, _bf: EngineView<'_>) {
+				self.0.push(step);
+			}
+		}
+
+		let instructions = Instruction::parse(Token::tokenize(&HELLO_WORLD)).unwrap();
+		let settings = RuntimeSettings {
+			yield_every: NonZeroU64::new(100),
+			..Default::default()
+		};
+
+		let mut tree = Yields::default();
+		let summary = Engine::default()
+			.run_with(
+				&instructions,
+				&mut [].as_slice(),
+				&mut vec![],
+				settings.clone(),
+				&mut StdHost::default(),
+				&mut tree,
+			)
+			.unwrap();
+		let expected: Vec<u64> = (100..summary.steps).step_by(100).collect();
+		assert_eq!(expected, tree.0);
+
+		let mut bytecode = Yields::default();
+		let summary = Engine::default()
+			.run_bytecode_with(
+				&Bytecode::compile(&instructions),
+				&mut [].as_slice(),
+				&mut vec![],
+				settings,
+				&mut StdHost::default(),
+				&mut bytecode,
+			)
+			.unwrap();
+		let expected: Vec<u64> = (100..summary.steps).step_by(100).collect();
+		assert_eq!(expected, bytecode.0);
+	}
+
+	#[test]
+	fn pauses_at_safe_points() {
+		struct Pauses {
+			flag: PauseFlag,
+			at: Vec<(u64, SafePoint, u8)>,
+		}
+
+		impl Hooks for Pauses {
+			fn on_pause(
+				&mut self,
+				step: u64,
+				at: SafePoint,
+				bf: EngineView<'_>,
+			) -> ControlFlow<()> {
+				self.at.push((step, at, bf.cell()));
+				self.flag.request();
+
+				if self.at.len() < 3 {
+					ControlFlow::Continue(())
+				} else {
+					ControlFlow::Break(())
+				}
+			}
+		}
+
+		let instructions = Instruction::parse(Token::tokenize(",++[->+<]>.")).unwrap();
+		let flag = PauseFlag::new();
+		flag.request();
+		let settings = RuntimeSettings {
+			pause: Some(flag.clone()),
+			..Default::default()
+		};
+		let expected = vec![
+			(0, SafePoint::Read, 0),
+			(3, SafePoint::Loop, 2),
+			(8, SafePoint::Loop, 1),
+		];
+
+		let mut tree = Pauses {
+			flag: flag.clone(),
+			at: vec![],
+		};
+		let summary = Engine::default()
+			.run_with(
+				&instructions,
+				&mut [].as_slice(),
+				&mut vec![],
+				settings.clone(),
+				&mut StdHost::default(),
+				&mut tree,
+			)
+			.unwrap();
+		assert_eq!(expected, tree.at);
+		assert!(summary.paused);
+		assert_eq!(
+			Some((3, vec![])),
+			summary
+				.location
+				.map(|location| (location.index, location.loops))
+		);
+		assert_eq!(8, summary.steps);
+
+		flag.request();
+		let mut bytecode = Pauses { flag, at: vec![] };
+		let summary = Engine::default()
+			.run_bytecode_with(
+				&Bytecode::compile(&instructions),
+				&mut [].as_slice(),
+				&mut vec![],
+				settings,
+				&mut StdHost::default(),
+				&mut bytecode,
+			)
+			.unwrap();
+		assert_eq!(expected, bytecode.at);
+		assert!(summary.paused);
+		assert_eq!(Some(8), summary.location.map(|location| location.index));
+	}
+
+	#[test]
+	fn newline_translation() {
+		// NOTE: echoes its input
+		let instructions = Instruction::parse(Token::tokenize(",[.,]")).unwrap();
+		let bytecode = Bytecode::compile(&instructions);
+
+		let run = |input: &[u8], newlines, read_ahead| {
+			let settings = RuntimeSettings {
+				quit_on_eof: true,
+				read_ahead,
+				newlines,
+				..Default::default()
+			};
+
+			let mut tree = vec![];
+			Engine::default()
+				.run(&instructions, &mut &input[..], &mut tree, settings.clone())
+				.unwrap();
+			let mut bytecode_output = vec![];
+			Engine::default()
+				.run_bytecode(&bytecode, &mut &input[..], &mut bytecode_output, settings)
+				.unwrap();
+			assert_eq!(tree, bytecode_output);
+
+			tree
+		};
+
+		let input = b"a\r\nb\rc\n\n";
+		assert_eq!(input.to_vec(), run(input, NewlineMode::Passthrough, false));
+		for read_ahead in [false, true] {
+			assert_eq!(
+				b"a\nb\nc\n\n".to_vec(),
+				run(input, NewlineMode::CrlfToLf, read_ahead)
+			);
+		}
+		assert_eq!(
+			b"a\r\r\nb\rc\r\n\r\n".to_vec(),
+			run(input, NewlineMode::LfToCrlf, false)
+		);
+	}
+
+	#[test]
+	fn exit_extension() {
+		let instructions = Instruction::parse(Token::tokenize_with_extensions(
+			"+++[>++++++++++++++++<-]>.@.",
+			&[Extension::Exit],
+		))
+		.unwrap();
+		let bytecode = Bytecode::compile(&instructions);
+
+		let mut output = vec![];
+		let tree = Engine::default()
+			.run(
+				&instructions,
+				&mut [].as_slice(),
+				&mut output,
+				RuntimeSettings::default(),
+			)
+			.unwrap();
+
+		assert_eq!(Some(48), tree.exit_code);
+		assert_eq!(b"0", output.as_slice());
+
+		let mut output = vec![];
+		let bytecode = Engine::default()
+			.run_bytecode(
+				&bytecode,
+				&mut [].as_slice(),
+				&mut output,
+				RuntimeSettings::default(),
+			)
+			.unwrap();
+
+		assert_eq!(tree, bytecode);
+		assert_eq!(b"0", output.as_slice());
+
+		let summary = Engine::default()
+			.run(
+				&[],
+				&mut [].as_slice(),
+				&mut vec![],
+				RuntimeSettings::default(),
+			)
+			.unwrap();
+
+		assert_eq!(None, summary.exit_code);
+	}
+
+	#[test]
+	fn capabilities_must_be_granted() {
 		let mut bf = Engine::default();
 		let settings = RuntimeSettings::default();
 
 		let mut input = BufReader::new(<&[u8]>::default());
 		let mut output = BufWriter::new(vec![]);
 
-		let tokens = Token::tokenize(&HELLO_WORLD);
-		let instructions = Instruction::parse(tokens).unwrap();
+		let instructions =
+			Instruction::parse(Token::tokenize_with_extensions("+[~-]", &[Extension::Time]))
+				.unwrap();
 
-		bf.run(&instructions, &mut input, &mut output, settings)
-			.unwrap();
+		let error = bf
+			.run(&instructions, &mut input, &mut output, settings)
+			.unwrap_err();
 
+		assert!(matches!(
+			&error,
+			RunError::CapabilitiesNotGranted(missing) if missing == &[Capability::Clock]
+		));
 		assert_eq!(
-			"Hello World!\n",
-			str::from_utf8(output.into_inner().unwrap().as_slice()).unwrap()
+			"program needs capabilities that weren't granted: clock",
+			error.to_string()
 		);
+		assert_eq!(Wrapping(0), bf.tape[0]);
 	}
 
 	#[test]
-	fn non_stdin_buffer() {
+	fn budget_with_custom_costs() {
+		fn io_is_expensive(instruction: &Instruction) -> u64 {
+			match instruction {
+				Instruction::Print | Instruction::Read => 10,
+				_ => 1,
+			}
+		}
+
 		let mut bf = Engine::default();
+		let settings = RuntimeSettings {
+			budget: Some(24),
+			cost: io_is_expensive,
+			..Default::default()
+		};
+
+		let mut input = BufReader::new(<&[u8]>::default());
+		let mut output = BufWriter::new(vec![]);
+
+		let instructions = Instruction::parse(Token::tokenize("+++..+.")).unwrap();
+
+		let error = bf
+			.run(&instructions, &mut input, &mut output, settings)
+			.unwrap_err();
+
+		assert!(matches!(
+			error,
+			RunError::BudgetExhausted {
+				spent: 24,
+				budget: 24,
+				..
+			}
+		));
+		// NOTE: the last print was about to run
+		assert_eq!(Some(6), error.location().map(|location| location.index));
+		assert_eq!(Wrapping(4), bf.tape[0]);
+		assert_eq!(vec![3, 3], output.into_inner().unwrap());
+	}
+
+	#[test]
+	fn bytecode_matches_tree() {
+		let programs = [(*HELLO_WORLD, ""), (*ROT13, "Hello, World!")];
+
+		for (code, input) in programs {
+			let instructions = Instruction::parse(Token::tokenize(code)).unwrap();
+			let bytecode = Bytecode::compile(&instructions);
+			let settings = RuntimeSettings {
+				quit_on_eof: true,
+				..Default::default()
+			};
+
+			let mut tree = Engine::default();
+			let mut tree_output = vec![];
+			let tree_summary = tree
+				.run(
+					&instructions,
+					&mut input.as_bytes(),
+					&mut tree_output,
+					settings.clone(),
+				)
+				.unwrap();
+
+			let mut vm = Engine::default();
+			let mut vm_output = vec![];
+			let vm_summary = vm
+				.run_bytecode(&bytecode, &mut input.as_bytes(), &mut vm_output, settings)
+				.unwrap();
+
+			assert_eq!(tree_output, vm_output);
+			assert_eq!(tree_summary, vm_summary);
+			assert_eq!(tree.tape, vm.tape);
+			assert_eq!(tree.pointer, vm.pointer);
+		}
+	}
+
+	#[test]
+	fn bytecode_budget() {
+		let instructions = Instruction::parse(Token::tokenize("+[+]")).unwrap();
+		let settings = RuntimeSettings {
+			budget: Some(100),
+			remember_recent: true,
+			..Default::default()
+		};
+
+		let error = Engine::default()
+			.run_bytecode(
+				&Bytecode::compile(&instructions),
+				&mut <&[u8]>::default(),
+				&mut vec![],
+				settings,
+			)
+			.unwrap_err();
+
+		assert!(matches!(
+			error,
+			RunError::BudgetExhausted {
+				spent: 100,
+				budget: 100,
+				..
+			}
+		));
+		let location = error.location().unwrap();
+		assert_eq!(2, location.index);
+		assert_eq!(vec![2, 3, 2, 3, 2, 3, 2, 3], location.recent);
+		assert_eq!("cells 0-4: [50] 0 0 0 0", location.cells.to_string());
+	}
+
+	#[test]
+	fn fused_bytecode_matches_tree() {
+		let instructions = Instruction::parse(Token::tokenize(&ROT13)).unwrap();
 		let settings = RuntimeSettings {
 			quit_on_eof: true,
 			..Default::default()
 		};
 
-		let mut input = BufReader::new(b"Hello, World!".as_slice());
+		let mut tree_output = vec![];
+		Engine::default()
+			.run(
+				&instructions,
+				&mut b"Hello, World!".as_slice(),
+				&mut tree_output,
+				settings.clone(),
+			)
+			.unwrap();
+
+		let collapsed = Bytecode::compile(&instructions)
+			.collapse()
+			.multiply_loops()
+			.count_loops();
+		let mut profile = Profile::new();
+		let mut profiled_output = vec![];
+		Engine::default()
+			.run_bytecode_with(
+				&collapsed,
+				&mut b"Hello, World!".as_slice(),
+				&mut profiled_output,
+				settings.clone(),
+				&mut DenyAll,
+				&mut profile,
+			)
+			.unwrap();
+
+		assert_eq!(tree_output, profiled_output);
+
+		for fusions in [Fusion::ALL, &profile.hot_fusions(&collapsed, 0.01)] {
+			let mut fused_output = vec![];
+			Engine::default()
+				.run_bytecode(
+					&collapsed.fuse(fusions),
+					&mut b"Hello, World!".as_slice(),
+					&mut fused_output,
+					settings.clone(),
+				)
+				.unwrap();
+
+			assert_eq!(tree_output, fused_output);
+		}
+	}
+
+	#[test]
+	fn scans_match_tree() {
+		for (code, grow_tape) in [
+			("+>+>+>>+<<<<[>]+", false),
+			(">>>>>+>+>+[>]+", false),
+			("+>+>+[<]+", false),
+			("+>+>+>+>+>+>+>+<<[>]+", true),
+		] {
+			let instructions = Instruction::parse(Token::tokenize(code)).unwrap();
+			let bytecode = Bytecode::compile(&instructions).collapse();
+			assert!(
+				bytecode.ops().contains(&Op::ScanRight) || bytecode.ops().contains(&Op::ScanLeft)
+			);
+
+			let settings = RuntimeSettings {
+				grow_tape,
+				..Default::default()
+			};
+			let mut tree = Engine::new(8);
+			tree.run(
+				&instructions,
+				&mut [].as_slice(),
+				&mut vec![],
+				settings.clone(),
+			)
+			.unwrap();
+			let mut scanned = Engine::new(8);
+			scanned
+				.run_bytecode(&bytecode, &mut [].as_slice(), &mut vec![], settings)
+				.unwrap();
+
+			assert_eq!(tree, scanned, "{code}");
+		}
+
+		// NOTE: without a zero cell, the scan never ends, just like the loop
+		let bytecode =
+			Bytecode::compile(&Instruction::parse(Token::tokenize("+>+[>]")).unwrap()).collapse();
+		let result = Engine::new(2).run_bytecode(
+			&bytecode,
+			&mut [].as_slice(),
+			&mut vec![],
+			RuntimeSettings {
+				budget: Some(100),
+				..Default::default()
+			},
+		);
+
+		assert!(matches!(result, Err(RunError::BudgetExhausted { .. })));
+	}
+
+	#[test]
+	fn moves_match_tree_on_growing_tapes() {
+		for (code, length, input) in [
+			("><<", 1, &[][..]),
+			("<>>", 1, &[]),
+			("+<<,[<+++[-]]+-+>>>-[>><+[<+]]>", 8, &[252, 39]),
+		] {
+			let instructions = Instruction::parse(Token::tokenize(code)).unwrap();
+			let bytecode = Bytecode::compile(&instructions)
+				.collapse()
+				.multiply_loops()
+				.count_loops()
+				.address_offsets()
+				.fuse(Fusion::ALL);
+
+			let settings = RuntimeSettings {
+				grow_tape: true,
+				budget: Some(10_000),
+				..Default::default()
+			};
+			let mut tree = Engine::new(length);
+			let mut tree_output = vec![];
+			tree.run(
+				&instructions,
+				&mut &*input,
+				&mut tree_output,
+				settings.clone(),
+			)
+			.unwrap();
+
+			for specialize_after in [None, Some(1)] {
+				let mut fused = Engine::new(length);
+				let mut fused_output = vec![];
+				fused
+					.run_bytecode(
+						&bytecode,
+						&mut &*input,
+						&mut fused_output,
+						RuntimeSettings {
+							specialize_after,
+							..settings.clone()
+						},
+					)
+					.unwrap();
+
+				assert_eq!(tree_output, fused_output, "{code}");
+				assert_eq!(tree.tape, fused.tape, "{code}");
+				assert_eq!(tree.pointer, fused.pointer, "{code}");
+			}
+		}
+	}
+
+	#[test]
+	fn counted_loops_match_tree_on_tiny_tapes() {
+		// NOTE: on these tapes, a cell the body changes is the condition cell after wrapping around
+		for (code, length, input) in [
+			("-[++<.>-]", 1, ""),
+			("-[++<<.>>-]", 2, ""),
+			("-[++>,<-]", 1, "a"),
+			("-[++<<.>>-]", 3, ""),
+			("+++[>+.<-]", 2, ""),
+		] {
+			let instructions = Instruction::parse(Token::tokenize(code)).unwrap();
+			let bytecode = Bytecode::compile(&instructions)
+				.collapse()
+				.multiply_loops()
+				.count_loops()
+				.address_offsets()
+				.fuse(Fusion::ALL);
+			assert!(
+				bytecode
+					.ops()
+					.iter()
+					.any(|op| matches!(op, Op::RepeatN(..))),
+				"{code}"
+			);
+
+			let settings = RuntimeSettings {
+				quit_on_eof: true,
+				budget: Some(10_000),
+				..Default::default()
+			};
+			let mut tree = Engine::new(length);
+			let mut tree_output = vec![];
+			tree.run(
+				&instructions,
+				&mut input.as_bytes(),
+				&mut tree_output,
+				settings.clone(),
+			)
+			.unwrap();
+
+			for specialize_after in [None, Some(1)] {
+				let mut fused = Engine::new(length);
+				let mut fused_output = vec![];
+				fused
+					.run_bytecode(
+						&bytecode,
+						&mut input.as_bytes(),
+						&mut fused_output,
+						RuntimeSettings {
+							specialize_after,
+							..settings.clone()
+						},
+					)
+					.unwrap();
+
+				assert_eq!(tree_output, fused_output, "{code}");
+				assert_eq!(tree.tape, fused.tape, "{code}");
+				assert_eq!(tree.pointer, fused.pointer, "{code}");
+			}
+		}
+	}
+
+	#[test]
+	fn specialized_loops_match_interpreted() {
+		// NOTE: the first loop runs into the start of the tape after a few iterations and the last
+		// one right away, so their specialized forms have to hand over to the interpreter
+		let instructions = Instruction::parse(Token::tokenize(
+			"+>+>+>+[<]>>>>++++[>++++<-]>[<+>>+>-<<-]<<<<<[-<+>]<<.",
+		))
+		.unwrap();
+		let bytecode = Bytecode::compile(&instructions)
+			.collapse()
+			.count_loops()
+			.address_offsets()
+			.fuse(Fusion::ALL);
+
+		let mut interpreted = Engine::default();
+		let mut interpreted_output = vec![];
+		let interpreted_summary = interpreted
+			.run_bytecode(
+				&bytecode,
+				&mut <&[u8]>::default(),
+				&mut interpreted_output,
+				RuntimeSettings::default(),
+			)
+			.unwrap();
+
+		let mut specialized = Engine::default();
+		let mut specialized_output = vec![];
+		let specialized_summary = specialized
+			.run_bytecode(
+				&bytecode,
+				&mut <&[u8]>::default(),
+				&mut specialized_output,
+				RuntimeSettings {
+					specialize_after: Some(1),
+					..Default::default()
+				},
+			)
+			.unwrap();
+
+		assert_eq!(interpreted_output, specialized_output);
+		assert_eq!(interpreted_summary, specialized_summary);
+		assert_eq!(interpreted.tape, specialized.tape);
+		assert_eq!(interpreted.pointer, specialized.pointer);
+	}
+
+	#[test]
+	fn reset_fast_clears_touched_cells() {
+		// NOTE: the last program wraps around to the end of the tape
+		for code in [
+			"+>+>+>+[<]>>>>++++[>++++<-]>[<+>>+>-<<-]<<<<<[-<+>]",
+			"++++[>++++<-]>[>>>+<<<-]",
+			"<+",
+		] {
+			let instructions = Instruction::parse(Token::tokenize(code)).unwrap();
+			let bytecode = Bytecode::compile(&instructions)
+				.collapse()
+				.count_loops()
+				.fuse(Fusion::ALL);
+
+			let mut tree = Engine::new(64);
+			tree.run(
+				&instructions,
+				&mut [].as_slice(),
+				&mut vec![],
+				RuntimeSettings::default(),
+			)
+			.unwrap();
+			tree.reset_fast();
+			assert_eq!(Engine::new(64), tree, "{code}");
+
+			let mut specialized = Engine::new(64);
+			specialized
+				.run_bytecode(
+					&bytecode,
+					&mut [].as_slice(),
+					&mut vec![],
+					RuntimeSettings {
+						specialize_after: Some(1),
+						..Default::default()
+					},
+				)
+				.unwrap();
+			specialized.reset_fast();
+			assert_eq!(Engine::new(64), specialized, "{code}");
+		}
+	}
+
+	#[test]
+	fn output_is_written_in_chunks() {
+		#[derive(Default)]
+		struct CountingWriter {
+			bytes: Vec<u8>,
+			writes: usize,
+		}
+
+		impl Write for CountingWriter {
+			fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+				self.writes += 1;
+				self.bytes.extend_from_slice(buf);
+				Ok(buf.len())
+			}
+
+			fn flush(&mut self) -> io::Result<()> {
+				Ok(())
+			}
+		}
+
+		let instructions = Instruction::parse(Token::tokenize(&HELLO_WORLD)).unwrap();
+		let settings = RuntimeSettings {
+			should_flush: false,
+			..Default::default()
+		};
+
+		let mut output = CountingWriter::default();
+		let summary = Engine::default()
+			.run(
+				&instructions,
+				&mut <&[u8]>::default(),
+				&mut output,
+				settings.clone(),
+			)
+			.unwrap();
+
+		assert_eq!(b"Hello World!\n", output.bytes.as_slice());
+		assert_eq!(1, output.writes);
+
+		// NOTE: output printed before an error isn't lost
+		let mut output = CountingWriter::default();
+		Engine::default()
+			.run_bytecode(
+				&Bytecode::compile(&instructions),
+				&mut <&[u8]>::default(),
+				&mut output,
+				RuntimeSettings {
+					budget: Some(summary.steps - 1),
+					..settings
+				},
+			)
+			.unwrap_err();
+
+		assert!(!output.bytes.is_empty());
+		assert!(b"Hello World!\n".starts_with(&output.bytes));
+	}
+
+	#[test]
+	fn read_ahead() {
+		let instructions = Instruction::parse(Token::tokenize(",.,.,.")).unwrap();
+
+		let mut input = b"ab".as_slice();
+		let mut output = vec![];
+		let summary = Engine::default()
+			.run(
+				&instructions,
+				&mut input,
+				&mut output,
+				RuntimeSettings {
+					read_ahead: true,
+					..Default::default()
+				},
+			)
+			.unwrap();
+
+		assert_eq!(b"ab\0", output.as_slice());
+		assert!(summary.unread_input.is_empty());
+
+		let mut input = b"abcdef".as_slice();
+		let mut output = vec![];
+		let summary = Engine::default()
+			.run_bytecode(
+				&Bytecode::compile(&instructions),
+				&mut input,
+				&mut output,
+				RuntimeSettings {
+					read_ahead: true,
+					..Default::default()
+				},
+			)
+			.unwrap();
+
+		assert_eq!(b"abc", output.as_slice());
+		assert_eq!(b"def", summary.unread_input.as_slice());
+	}
+
+	#[test]
+	fn binary_data_passes_through() {
+		// NOTE: clearing the cell after printing it keeps zero bytes from ending the loop
+		let instructions = Instruction::parse(Token::tokenize("+[,.[-]+]")).unwrap();
+		let settings = RuntimeSettings {
+			quit_on_eof: true,
+			..Default::default()
+		};
+
+		let input: Vec<u8> = (0..=u8::MAX).rev().chain(0..=u8::MAX).collect();
+		let mut output = vec![];
+
+		Engine::default()
+			.run(&instructions, &mut input.as_slice(), &mut output, settings)
+			.unwrap();
+
+		assert_eq!(input, output);
+	}
+
+	#[test]
+	fn peeking_is_bounded() {
+		let mut bf = Engine::default();
+		bf.tape[0] = Wrapping(7);
+
+		let instructions = Instruction::parse(Token::tokenize("[>+<]")).unwrap();
+		let preview = bf
+			.peek(&instructions, &[], RuntimeSettings::default(), 50)
+			.unwrap();
+
+		assert!(!preview.finished);
+		assert_eq!(50, preview.steps);
+		assert_eq!(
+			vec![CellChange {
+				cell: 1,
+				before: 0,
+				after: 12,
+			}],
+			preview.changes
+		);
+		assert_eq!(1, preview.pointer);
+
+		assert_eq!(0, bf.pointer);
+		assert_eq!(Wrapping(0), bf.tape[1]);
+	}
+
+	#[test]
+	fn growable_tape() {
+		let mut bf = Engine::new(1);
+		let settings = RuntimeSettings {
+			grow_tape: true,
+			..Default::default()
+		};
+
+		let mut input = BufReader::new(<&[u8]>::default());
 		let mut output = BufWriter::new(vec![]);
 
-		let tokens = Token::tokenize(&ROT13);
-		let instructions = Instruction::parse(tokens).unwrap();
+		let instructions = Instruction::parse(Token::tokenize("+>++>+++<<<")).unwrap();
 
 		bf.run(&instructions, &mut input, &mut output, settings)
 			.unwrap();
 
-		assert_eq!(
-			"Uryyb, Jbeyq!",
-			str::from_utf8(output.into_inner().unwrap().as_slice()).unwrap()
+		assert_eq!(vec![Wrapping(1), Wrapping(2), Wrapping(3)], bf.tape);
+		assert_eq!(2, bf.pointer);
+	}
+
+	#[test]
+	fn pointer_wraps_outside_of_bounded_loops() {
+		struct Wraps(Vec<Wrap>);
+
+		impl Hooks for Wraps {
+			fn on_wrap(&mut self, _step: u64, wrap: Wrap) {
+				self.0.push(wrap);
+			}
+		}
+
+		// NOTE: the first program stays on the tape, the loop of the second one only fits once
+		// the pointer wrapped around to the cell it starts at, and the one of the third one never
+		for (code, tape, pointer, wraps) in [
+			("++[->+>+<<]", [0, 2, 2], 0, vec![]),
+			("<<+[->+<]", [0, 0, 1], 1, vec![Wrap::PointerPastStart]),
+			(
+				"<++[->+>+<<]",
+				[2, 2, 0],
+				2,
+				vec![
+					Wrap::PointerPastStart,
+					Wrap::PointerPastEnd,
+					Wrap::PointerPastStart,
+					Wrap::PointerPastEnd,
+					Wrap::PointerPastStart,
+				],
+			),
+		] {
+			let instructions = Instruction::parse(Token::tokenize(code)).unwrap();
+			let mut bf = Engine::new(3);
+			let mut hooks = Wraps(vec![]);
+
+			bf.run_with(
+				&instructions,
+				&mut [].as_slice(),
+				&mut vec![],
+				RuntimeSettings::default(),
+				&mut StdHost::default(),
+				&mut hooks,
+			)
+			.unwrap();
+
+			assert_eq!(tape.map(Wrapping).to_vec(), bf.tape, "{code}");
+			assert_eq!(pointer, bf.pointer, "{code}");
+			assert_eq!(wraps, hooks.0, "{code}");
+		}
+	}
+
+	#[test]
+	fn collapsed_runs_run_like_the_instructions_they_replace() {
+		struct Wraps(Vec<Wrap>);
+
+		impl Hooks for Wraps {
+			fn on_wrap(&mut self, _step: u64, wrap: Wrap) {
+				self.0.push(wrap);
+			}
+		}
+
+		// NOTE: runs that wrap the cell and pointer around, some more than once, that grow the
+		// tape, and that run in loops that fit on the tape and ones that don't
+		for (code, length, grow_tape) in [
+			(
+				format!("{}.{}.", "+".repeat(300), "-".repeat(200)),
+				1,
+				false,
+			),
+			("<<<<<+++>>>>>>>-----.".to_owned(), 2, false),
+			("+++<<<<<<<+>>>>>>>>>>>>.".to_owned(), 3, true),
+			("++[->>>+<<<]>>>.".to_owned(), 5, false),
+			("++[->>>+<<<]>>>.".to_owned(), 2, false),
+			("----[->>>>>+<<]>.".to_owned(), 2, true),
+		] {
+			let program = Program::parse(&code).unwrap();
+			let collapsed = program.collapse_runs();
+			assert_ne!(program.instructions, collapsed.instructions, "{code}");
+
+			let run = |instructions: &[Instruction]| {
+				let mut bf = Engine::new(length);
+				let mut output = vec![];
+				let mut hooks = Wraps(vec![]);
+				bf.run_with(
+					instructions,
+					&mut [].as_slice(),
+					&mut output,
+					RuntimeSettings {
+						grow_tape,
+						..Default::default()
+					},
+					&mut StdHost::default(),
+					&mut hooks,
+				)
+				.unwrap();
+
+				(output, bf.tape, bf.pointer, bf.touched, hooks.0)
+			};
+
+			assert_eq!(
+				run(&program.instructions),
+				run(&collapsed.instructions),
+				"{code}"
+			);
+		}
+	}
+
+	#[test]
+	fn panics_are_contained() {
+		let extensions = ExtensionSet::default()
+			.with_handler('%', |_| panic!("bug in a handler"))
+			.unwrap();
+		let instructions = [Instruction::Extension(Extension::Custom('%'))];
+		let settings = RuntimeSettings {
+			extensions,
+			..Default::default()
+		};
+
+		let tree = Engine::default().run(
+			&instructions,
+			&mut io::empty(),
+			&mut io::sink(),
+			settings.clone(),
+		);
+		assert!(matches!(
+			tree,
+			Err(RunError::InternalError { engine: "tree", message }) if message == "bug in a handler"
+		));
+
+		let bytecode = Engine::default().run_bytecode(
+			&Bytecode::compile(&instructions),
+			&mut io::empty(),
+			&mut io::sink(),
+			settings.clone(),
+		);
+		assert!(matches!(
+			bytecode,
+			Err(RunError::InternalError {
+				engine: "bytecode",
+				..
+			})
+		));
+
+		let uncontained = panic::catch_unwind(AssertUnwindSafe(|| {
+			Engine::default().run(
+				&instructions,
+				&mut io::empty(),
+				&mut io::sink(),
+				RuntimeSettings {
+					contain_panics: false,
+					..settings
+				},
+			)
+		}));
+		assert!(uncontained.is_err());
+
+		// NOTE: the bytecode engine accesses cells unchecked, so a pointer moved past the tape by
+		// hand must be caught before it runs, contained or not
+		let mut bf = Engine::new(4);
+		bf.pointer = 4;
+		let outside = bf.run_bytecode(
+			&Bytecode::compile(&Instruction::parse(Token::tokenize("+")).unwrap()),
+			&mut io::empty(),
+			&mut io::sink(),
+			RuntimeSettings {
+				contain_panics: false,
+				..Default::default()
+			},
 		);
+		assert!(matches!(
+			outside,
+			Err(RunError::InternalError { engine: "bytecode", message })
+				if message == "cell 4 is past the end of the tape, which has 4 cells"
+		));
 	}
 }
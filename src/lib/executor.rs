@@ -0,0 +1,267 @@
+use std::io::{Read, Write};
+
+use crate::{
+	bytecode::{Bytecode, Fusion},
+	engine::{Engine, RunError, RunSummary, RuntimeSettings},
+	program::Program,
+};
+
+/// Something that runs programs, be it the tree-walking [`Engine`] or one of the bytecode VMs, so
+/// code that runs programs can be handed any of them, and differential tests can run the same
+/// program on all of them.
+///
+/// # Usage
+///
+/// ```
+/// # use brainfuck_rs::{
+/// #   engine::{Engine, RuntimeSettings},
+/// #   executor::{BytecodeVm, Executor, FusedVm},
+/// #   program::Program,
+/// # };
+/// let program = Program::parse("++++++[->++++++++<]>.").unwrap();
+/// let executors: Vec<Box<dyn Executor>> = vec![
+///     Box::new(Engine::default()),
+///     Box::new(BytecodeVm::default()),
+///     Box::new(FusedVm::default()),
+/// ];
+///
+/// for mut executor in executors {
+///     let mut output = vec![];
+///     executor
+///         .run_program(&program, &mut [].as_slice(), &mut output, RuntimeSettings::default())
+///         .unwrap();
+///
+///     assert_eq!(b"0", output.as_slice(), "{}", executor.name());
+/// }
+/// ```
+pub trait Executor {
+	/// Name of the backend, like the `--engine` of the command line.
+	fn name(&self) -> &'static str;
+
+	/// Run `program` like [`Engine::run`] runs its instructions, compiling it first if the
+	/// backend runs something else.
+	///
+	/// # Errors
+	///
+	/// Same as [`Engine::run`].
+	fn run_program(
+		&mut self,
+		program: &Program,
+		stdin: &mut dyn Read,
+		stdout: &mut dyn Write,
+		settings: RuntimeSettings,
+	) -> Result<RunSummary, RunError>;
+
+	/// The engine the programs run on, with the tape and pointer they left behind.
+	fn engine(&self) -> &Engine;
+}
+
+impl Executor for Engine {
+	fn name(&self) -> &'static str {
+		"tree"
+	}
+
+	fn run_program(
+		&mut self,
+		program: &Program,
+		mut stdin: &mut dyn Read,
+		mut stdout: &mut dyn Write,
+		settings: RuntimeSettings,
+	) -> Result<RunSummary, RunError> {
+		self.run(&program.instructions, &mut stdin, &mut stdout, settings)
+	}
+
+	fn engine(&self) -> &Engine {
+		self
+	}
+}
+
+/// Runs programs compiled into plain [`Bytecode`], op for instruction, with the same step counts
+/// as the tree engine.
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct BytecodeVm {
+	/// Engine the bytecode runs on.
+	pub engine: Engine,
+}
+
+impl Executor for BytecodeVm {
+	fn name(&self) -> &'static str {
+		"bytecode"
+	}
+
+	fn run_program(
+		&mut self,
+		program: &Program,
+		mut stdin: &mut dyn Read,
+		mut stdout: &mut dyn Write,
+		settings: RuntimeSettings,
+	) -> Result<RunSummary, RunError> {
+		self.engine.run_bytecode(
+			&Bytecode::compile(&program.instructions),
+			&mut stdin,
+			&mut stdout,
+			settings,
+		)
+	}
+
+	fn engine(&self) -> &Engine {
+		&self.engine
+	}
+}
+
+/// Runs programs compiled into bytecode whose common loops are collapsed and whose common
+/// sequences of ops are fused into superinstructions, the fastest way to run them. Fewer steps
+/// are counted than on the tree engine.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FusedVm {
+	/// Engine the bytecode runs on.
+	pub engine: Engine,
+	/// Sequences of ops to fuse, see [`Bytecode::fuse`].
+	pub fusions: Vec<Fusion>,
+	/// Compile hot loops into closures, like a JIT, once they were entered this many times. Set,
+	/// it takes the place of [`RuntimeSettings::specialize_after`], making this the `tiered`
+	/// engine.
+	pub specialize_after: Option<u64>,
+}
+
+impl Default for FusedVm {
+	/// Every fusion, without compiling loops.
+	fn default() -> Self {
+		Self {
+			engine: Engine::default(),
+			fusions: Fusion::ALL.to_vec(),
+			specialize_after: None,
+		}
+	}
+}
+
+impl Executor for FusedVm {
+	fn name(&self) -> &'static str {
+		if self.specialize_after.is_some() {
+			"tiered"
+		} else {
+			"fused"
+		}
+	}
+
+	fn run_program(
+		&mut self,
+		program: &Program,
+		mut stdin: &mut dyn Read,
+		mut stdout: &mut dyn Write,
+		settings: RuntimeSettings,
+	) -> Result<RunSummary, RunError> {
+		let bytecode = Bytecode::compile(&program.instructions)
+			.collapse()
+			.multiply_loops()
+			.count_loops()
+			.address_offsets()
+			.fuse(&self.fusions);
+
+		self.engine.run_bytecode(
+			&bytecode,
+			&mut stdin,
+			&mut stdout,
+			RuntimeSettings {
+				specialize_after: self.specialize_after.or(settings.specialize_after),
+				..settings
+			},
+		)
+	}
+
+	fn engine(&self) -> &Engine {
+		&self.engine
+	}
+}
+
+/// Runs programs compiled to native code with Cranelift, see [`JitProgram`](`crate::jit::JitProgram`).
+/// No steps are counted, and programs with extensions or settings that need steps counted fail
+/// with [`ErrorKind::Unsupported`](`std::io::ErrorKind::Unsupported`).
+#[cfg(feature = "jit")]
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct JitVm {
+	/// Engine whose tape the code runs on.
+	pub engine: Engine,
+}
+
+#[cfg(feature = "jit")]
+impl Executor for JitVm {
+	fn name(&self) -> &'static str {
+		"native"
+	}
+
+	fn run_program(
+		&mut self,
+		program: &Program,
+		mut stdin: &mut dyn Read,
+		mut stdout: &mut dyn Write,
+		settings: RuntimeSettings,
+	) -> Result<RunSummary, RunError> {
+		// NOTE: checked before compiling, though running checks it too, to not compile for nothing
+		self.engine.check_pointer("native")?;
+
+		let bytecode = Bytecode::compile(&program.instructions)
+			.collapse()
+			.multiply_loops()
+			.address_offsets();
+		let compiled = crate::jit::JitProgram::compile(&bytecode, self.engine.tape.len())
+			.map_err(|e| std::io::Error::new(std::io::ErrorKind::Unsupported, e))?;
+
+		compiled.run(&mut self.engine, &mut stdin, &mut stdout, &settings)
+	}
+
+	fn engine(&self) -> &Engine {
+		&self.engine
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn backends_agree() {
+		let program = Program::parse(",[>+++[<+>-]<.>>+<<,]").unwrap();
+		let mut executors: Vec<Box<dyn Executor>> = vec![
+			Box::new(Engine::new(16)),
+			Box::new(BytecodeVm {
+				engine: Engine::new(16),
+			}),
+			Box::new(FusedVm {
+				engine: Engine::new(16),
+				..Default::default()
+			}),
+			Box::new(FusedVm {
+				engine: Engine::new(16),
+				specialize_after: Some(1),
+				..Default::default()
+			}),
+		];
+
+		let mut outputs = vec![];
+		for executor in &mut executors {
+			let mut output = vec![];
+			executor
+				.run_program(
+					&program,
+					&mut b"HAL".as_slice(),
+					&mut output,
+					RuntimeSettings::default(),
+				)
+				.unwrap();
+			outputs.push(output);
+		}
+
+		assert_eq!(vec![b"KDO".to_vec(); 4], outputs);
+		assert_eq!(
+			vec!["tree", "bytecode", "fused", "tiered"],
+			executors
+				.iter()
+				.map(|executor| executor.name())
+				.collect::<Vec<_>>()
+		);
+		for executor in &executors[1..] {
+			assert_eq!(executors[0].engine(), executor.engine());
+		}
+	}
+}
@@ -0,0 +1,328 @@
+use std::{
+	collections::VecDeque,
+	fmt::Write as _,
+	io::{self, BufRead, Read, Write},
+};
+
+use similar::{ChangeTag, TextDiff};
+use thiserror::Error;
+
+/// Lines around a difference shown in a [`ExpectError::Mismatch`].
+pub const CONTEXT_LINES: usize = 3;
+
+/// Lines of a diff shown in a [`ExpectError::Mismatch`] at most.
+pub const DIFF_LINES: usize = 100;
+
+/// Bytes of the expected and the actual output kept once they differ, for showing the difference.
+pub const WINDOW: usize = 64 * 1024;
+
+/// Output of a program, compared with what it's expected to print while it's printed.
+///
+/// Neither output is kept whole, so huge ones can be compared too: only the last few lines that
+/// matched are, and once they differ, up to [`WINDOW`] bytes of what follows in both. Call
+/// [`ExpectedOutput::finish`] once the program ends to learn whether they matched.
+///
+/// # Usage
+///
+/// ```
+/// # use brainfuck_rs::{
+/// #   engine::{Engine, RuntimeSettings},
+/// #   expect::{ExpectError, ExpectedOutput},
+/// #   instruction::Instruction,
+/// #   token::Token,
+/// # };
+/// let instructions = Instruction::parse(Token::tokenize("++++++++++.")).unwrap();
+/// let mut output = ExpectedOutput::new(&b"\n"[..]);
+///
+/// Engine::default()
+///     .run(&instructions, &mut std::io::empty(), &mut output, RuntimeSettings::default())
+///     .unwrap();
+///
+/// assert!(output.finish().is_ok());
+/// ```
+#[derive(Debug)]
+pub struct ExpectedOutput<R> {
+	expected: R,
+	/// Bytes that matched so far.
+	matched: u64,
+	/// The last lines that matched, the last of them possibly incomplete, and the number of the
+	/// first one.
+	context: VecDeque<Vec<u8>>,
+	first_line: usize,
+	/// Once the outputs differ, what follows in the actual one.
+	diverged: Option<Divergence>,
+}
+
+#[derive(Debug, Default)]
+struct Divergence {
+	actual: Vec<u8>,
+	/// Whether the actual output goes on past the window.
+	truncated: bool,
+	/// Why the expected output couldn't be read, if it couldn't.
+	failure: Option<io::Error>,
+}
+
+/// An error returned by [`ExpectedOutput::finish`].
+#[derive(Debug, Error)]
+pub enum ExpectError {
+	/// The expected output couldn't be read
+	#[error("couldn't read the expected output: {0}")]
+	Io(#[from] io::Error),
+	/// The output differs from what's expected
+	#[error("output differs from what's expected from byte {offset} on, on line {line}")]
+	Mismatch {
+		/// Number of bytes that matched.
+		offset: u64,
+		/// Line of the first difference, starting from 1.
+		line: usize,
+		/// The lines around the difference as a unified diff, from the expected output to the
+		/// actual one.
+		diff: String,
+	},
+}
+
+impl<R: BufRead> ExpectedOutput<R> {
+	/// Compare the output with what `expected` reads.
+	pub fn new(expected: R) -> Self {
+		Self {
+			expected,
+			matched: 0,
+			context: VecDeque::from([vec![]]),
+			first_line: 1,
+			diverged: None,
+		}
+	}
+
+	/// Check that the output didn't end early, once the program ended.
+	///
+	/// # Errors
+	///
+	/// Fails if the output differs from what's expected, or if that couldn't be read.
+	pub fn finish(mut self) -> Result<(), ExpectError> {
+		if self.diverged.is_none() {
+			match self.expected.fill_buf() {
+				Ok([]) => return Ok(()),
+				Ok(_) => self.diverged = Some(Divergence::default()),
+				Err(e) => return Err(e.into()),
+			}
+		}
+		let divergence = self.diverged.take().expect("the outputs diverged");
+		if let Some(failure) = divergence.failure {
+			return Err(failure.into());
+		}
+
+		// NOTE: whatever is left of the expected output beyond the window isn't worth reading
+		let mut expected = vec![];
+		(&mut self.expected)
+			.take(WINDOW as u64 + 1)
+			.read_to_end(&mut expected)?;
+		let cut = (expected.len() > WINDOW, divergence.truncated);
+
+		let line = self.first_line + self.context.len() - 1;
+		let context = self.context.into_iter().flatten().collect::<Vec<u8>>();
+		let expected = [&context[..], whole_lines(&expected, cut.0)].concat();
+		let actual = [&context[..], whole_lines(&divergence.actual, cut.1)].concat();
+
+		let diff = unified_diff(&expected, &actual, self.first_line, cut);
+
+		Err(ExpectError::Mismatch {
+			offset: self.matched,
+			line,
+			diff,
+		})
+	}
+
+	/// Remember `matched` as part of the last lines that matched.
+	fn keep_context(&mut self, matched: &[u8]) {
+		for piece in matched.split_inclusive(|&byte| byte == b'\n') {
+			let last = self
+				.context
+				.back_mut()
+				.expect("there's always a current line");
+			last.extend_from_slice(piece);
+
+			if piece.ends_with(b"\n") {
+				self.context.push_back(vec![]);
+				if self.context.len() > CONTEXT_LINES + 1 {
+					self.context.pop_front();
+					self.first_line += 1;
+				}
+			}
+		}
+	}
+}
+
+impl<R: BufRead> Write for ExpectedOutput<R> {
+	fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+		let mut output = buf;
+
+		while self.diverged.is_none() && !output.is_empty() {
+			let expected = match self.expected.fill_buf() {
+				Ok(expected) => expected,
+				Err(e) if e.kind() == io::ErrorKind::Interrupted => continue,
+				// NOTE: the program isn't to blame, so it's reported once it ends
+				Err(e) => {
+					self.diverged = Some(Divergence {
+						failure: Some(e),
+						..Default::default()
+					});
+					break;
+				}
+			};
+
+			let length = output.len().min(expected.len());
+			let same = output[..length]
+				.iter()
+				.zip(expected)
+				.take_while(|(output, expected)| output == expected)
+				.count();
+			let diverged = same < length || expected.is_empty();
+
+			// NOTE: the rest of the expected output is read once the program ends
+			self.expected.consume(same);
+			self.matched += same as u64;
+			self.keep_context(&output[..same]);
+			output = &output[same..];
+
+			if diverged {
+				self.diverged = Some(Divergence::default());
+			}
+		}
+
+		if let Some(divergence) = &mut self.diverged {
+			let room = WINDOW.saturating_sub(divergence.actual.len());
+			divergence
+				.actual
+				.extend_from_slice(&output[..room.min(output.len())]);
+			divergence.truncated |= output.len() > room;
+		}
+
+		Ok(buf.len())
+	}
+
+	fn flush(&mut self) -> io::Result<()> {
+		Ok(())
+	}
+}
+
+/// The complete lines of a window of output, if it was `cut` short, up to [`WINDOW`] bytes.
+fn whole_lines(window: &[u8], cut: bool) -> &[u8] {
+	let window = &window[..window.len().min(WINDOW)];
+	if !cut {
+		return window;
+	}
+
+	window
+		.iter()
+		.rposition(|&byte| byte == b'\n')
+		.map_or(window, |end| &window[..=end])
+}
+
+/// Unified diff from `expected` to `actual`, whose first lines are line `first_line`.
+///
+/// Either may have been `cut` short, in which case differences at the end of it, other than the
+/// first one, aren't shown, since they would only be there because of the cut. At most
+/// [`DIFF_LINES`] changed and unchanged lines are shown.
+fn unified_diff(expected: &[u8], actual: &[u8], first_line: usize, cut: (bool, bool)) -> String {
+	let expected = String::from_utf8_lossy(expected);
+	let actual = String::from_utf8_lossy(actual);
+	let diff = TextDiff::from_lines(expected.as_ref(), actual.as_ref());
+
+	const REST: &str = "... (the rest isn't shown)\n";
+
+	let mut unified = String::from("--- expected\n+++ output\n");
+	let mut shown = 0;
+	for (index, group) in diff.grouped_ops(CONTEXT_LINES).into_iter().enumerate() {
+		let (Some(first), Some(last)) = (group.first(), group.last()) else {
+			continue;
+		};
+		let old = first.old_range().start..last.old_range().end;
+		let new = first.new_range().start..last.new_range().end;
+		// NOTE: the first difference is always shown, even if it runs into the cut
+		if index > 0
+			&& ((cut.0 && old.end == diff.old_slices().len())
+				|| (cut.1 && new.end == diff.new_slices().len()))
+		{
+			break;
+		}
+		let _ = writeln!(
+			unified,
+			"@@ -{},{} +{},{} @@",
+			old.start + first_line,
+			old.len(),
+			new.start + first_line,
+			new.len()
+		);
+
+		for op in &group {
+			for change in diff.iter_changes(op) {
+				let sign = match change.tag() {
+					ChangeTag::Equal => ' ',
+					ChangeTag::Delete => '-',
+					ChangeTag::Insert => '+',
+				};
+				if shown == DIFF_LINES {
+					unified.push_str(REST);
+					return unified;
+				}
+				shown += 1;
+
+				unified.push(sign);
+				unified.push_str(change.value());
+				if change.missing_newline() {
+					unified.push_str("\n\\ No newline at end of file\n");
+				}
+			}
+		}
+	}
+	if cut.0 || cut.1 {
+		unified.push_str(REST);
+	}
+
+	unified
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	fn compare(expected: &str, chunks: &[&str]) -> Result<(), ExpectError> {
+		let mut output = ExpectedOutput::new(expected.as_bytes());
+		for chunk in chunks {
+			output.write_all(chunk.as_bytes()).unwrap();
+		}
+
+		output.finish()
+	}
+
+	#[test]
+	fn differences() {
+		let expected = "1\n2\n3\n4\n5\n6\n";
+		assert!(compare(expected, &["1\n2\n3", "\n4\n5\n6\n"]).is_ok());
+
+		let Err(ExpectError::Mismatch { offset, line, diff }) =
+			compare(expected, &["1\n2\n3\n4\n", "five\n6\n"])
+		else {
+			panic!("the outputs differ");
+		};
+		assert_eq!((8, 5), (offset, line));
+		assert_eq!(
+			"--- expected\n+++ output\n@@ -2,5 +2,5 @@\n 2\n 3\n 4\n-5\n+five\n 6\n",
+			diff
+		);
+
+		// NOTE: output that ends early, or goes on for longer, differs too
+		assert!(matches!(
+			compare(expected, &["1\n2\n"]),
+			Err(ExpectError::Mismatch { offset: 4, .. })
+		));
+		assert!(matches!(
+			compare(expected, &[expected, "7\n"]),
+			Err(ExpectError::Mismatch {
+				offset: 12,
+				line: 7,
+				..
+			})
+		));
+	}
+}
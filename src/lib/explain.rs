@@ -0,0 +1,585 @@
+use std::{
+	collections::BTreeMap,
+	fmt::Display,
+	io::{Read, Write},
+	num::Wrapping,
+	ops::ControlFlow,
+	slice,
+};
+
+use crate::{
+	analysis,
+	engine::{unit_cost, Engine, Eof, RunError, RunSummary, RuntimeSettings},
+	extension::Extension,
+	hooks::Hooks,
+	host::StdHost,
+	instruction::Instruction,
+	program::{self, Program},
+};
+
+/// Run a program like [`Engine::run`], narrating every construct it executes in plain English
+/// into `narration`, one line each, e.g. `1:4 loop repeats while cell 0 ≠ 0; it ran 8 times and
+/// moved 8 into cell 1`.
+///
+/// Runs of `+` and `-`, and of `>` and `<`, are narrated as a whole. So are loops that do nothing
+/// but arithmetic (see [`analysis::cell_deltas`]), while the iterations of other loops are
+/// narrated one by one.
+///
+/// Execution stops after `max_steps` instructions, counted like [`RunSummary::steps`].
+///
+/// # Usage
+///
+/// ```
+/// # use brainfuck_rs::{engine::{Engine, RuntimeSettings}, explain, program::Program};
+/// let program = Program::parse("++++++++[->+<]").unwrap();
+///
+/// let mut narration = vec![];
+/// explain::explain(
+///     &mut Engine::default(),
+///     &program,
+///     &mut [].as_slice(),
+///     &mut vec![],
+///     &mut narration,
+///     RuntimeSettings::default(),
+///     100,
+/// )
+/// .unwrap();
+///
+/// assert_eq!(
+///     "1:1 adds 8 to cell 0, making it 8\n\
+///      1:9 loop repeats while cell 0 ≠ 0; it ran 8 times and moved 8 into cell 1\n",
+///     String::from_utf8(narration).unwrap(),
+/// );
+/// ```
+///
+/// # Errors
+///
+/// Same as [`Engine::run`], except that running out of `max_steps` isn't an error.
+/// [`RuntimeSettings::budget`], [`RuntimeSettings::read_ahead`] and
+/// [`RuntimeSettings::break_on_output`] are ignored.
+pub fn explain(
+	bf: &mut Engine,
+	program: &Program,
+	stdin: &mut impl Read,
+	stdout: &mut impl Write,
+	narration: &mut impl Write,
+	settings: RuntimeSettings,
+	max_steps: u64,
+) -> Result<RunSummary, RunError> {
+	let missing: Vec<_> = program
+		.required_capabilities()
+		.difference(&settings.capabilities)
+		.copied()
+		.collect();
+	if !missing.is_empty() {
+		return Err(RunError::CapabilitiesNotGranted(missing));
+	}
+
+	let mut narrator = Narrator {
+		bf,
+		program,
+		stdin,
+		stdout,
+		narration,
+		settings,
+		steps: 0,
+		max_steps,
+		exit_code: None,
+	};
+	// NOTE: why the program stopped early is already narrated
+	let _ = narrator.block(&program.instructions, 0, 0)?;
+
+	Ok(RunSummary {
+		steps: narrator.steps,
+		unread_input: vec![],
+		exit_code: narrator.exit_code,
+		output_matched: false,
+		breakpoint_hit: false,
+		paused: false,
+		location: None,
+	})
+}
+
+/// Number of changed cells listed after a loop, before the rest are only counted.
+const MAX_LISTED_CELLS: usize = 4;
+
+struct Narrator<'a, R, W, N> {
+	bf: &'a mut Engine,
+	program: &'a Program,
+	stdin: &'a mut R,
+	stdout: &'a mut W,
+	narration: &'a mut N,
+	settings: RuntimeSettings,
+	steps: u64,
+	max_steps: u64,
+	exit_code: Option<u8>,
+}
+
+impl<R: Read, W: Write, N: Write> Narrator<'_, R, W, N> {
+	/// Run `instructions`, the first of which has the pre-order index `index`, narrating them
+	/// `depth` loops deep.
+	fn block(
+		&mut self,
+		instructions: &[Instruction],
+		mut index: usize,
+		depth: usize,
+	) -> Result<ControlFlow<()>, RunError> {
+		let mut start = 0;
+
+		while start < instructions.len() {
+			let same_kind = |instruction: &Instruction| match instructions[start] {
+				Instruction::Inc | Instruction::Dec | Instruction::Add(_) => matches!(
+					instruction,
+					Instruction::Inc | Instruction::Dec | Instruction::Add(_)
+				),
+				Instruction::Next | Instruction::Prev | Instruction::Move(_) => matches!(
+					instruction,
+					Instruction::Next | Instruction::Prev | Instruction::Move(_)
+				),
+				_ => false,
+			};
+			let end = start
+				+ 1 + instructions[start + 1..]
+				.iter()
+				.take_while(|instruction| same_kind(instruction))
+				.count();
+
+			let construct = &instructions[start..end];
+			let flow = match &construct[0] {
+				Instruction::Loop(body) => self.run_loop(&construct[0], body, index, depth)?,
+				_ => self.run_straight(construct, index, depth)?,
+			};
+
+			if flow.is_break() {
+				return Ok(flow);
+			}
+
+			index += program::count_instructions(construct);
+			start = end;
+		}
+
+		Ok(ControlFlow::Continue(()))
+	}
+
+	/// Run instructions that aren't loops, which are all of the same kind.
+	fn run_straight(
+		&mut self,
+		instructions: &[Instruction],
+		index: usize,
+		depth: usize,
+	) -> Result<ControlFlow<()>, RunError> {
+		let cell = self.bf.pointer;
+		let before = self.bf.tape[cell].0;
+
+		let mut input = LastInput::default();
+		let ControlFlow::Continue(summary) = self.execute(instructions, &mut input)? else {
+			return Ok(ControlFlow::Break(()));
+		};
+
+		let after = self.bf.tape[cell].0;
+		let name = self.bf.labels.name(cell).to_string();
+		let sentence = match &instructions[0] {
+			Instruction::Inc | Instruction::Dec | Instruction::Add(_) => {
+				match after.wrapping_sub(before) as i8 {
+					0 => format!("leaves {name} at {after}"),
+					delta @ 1.. => format!("adds {delta} to {name}, making it {after}"),
+					delta => format!(
+						"subtracts {} from {name}, making it {after}",
+						delta.unsigned_abs()
+					),
+				}
+			}
+			Instruction::Next | Instruction::Prev | Instruction::Move(_) => {
+				format!(
+					"moves the pointer to {}",
+					self.bf.labels.name(self.bf.pointer)
+				)
+			}
+			Instruction::Clear => format!("clears {name}"),
+			Instruction::Print => format!("prints {name}, which is {}", byte(after)),
+			Instruction::Read => match input.0 {
+				Some(input) => format!("reads {} into {name}", byte(input)),
+				None if self.settings.quit_on_eof => {
+					self.narrate(index, depth, "reaches the end of input and stops")?;
+
+					return Ok(ControlFlow::Break(()));
+				}
+				None => match self.settings.eof {
+					Eof::Unchanged => {
+						format!("reaches the end of input, leaving {name} as it is")
+					}
+					Eof::Zero | Eof::MinusOne => {
+						format!("reaches the end of input, setting {name} to {after}")
+					}
+				},
+			},
+			Instruction::Extension(Extension::Time) => {
+				format!("writes the time, {after}, into {name}")
+			}
+			Instruction::Extension(Extension::Exit) => {
+				format!("ends the program with exit status {before}")
+			}
+			Instruction::Extension(Extension::Custom(symbol)) => {
+				format!("runs the handler of `{symbol}`")
+			}
+			Instruction::Loop(_) => unreachable!("loops are run by `run_loop`"),
+		};
+		self.narrate(index, depth, sentence)?;
+
+		if summary.exit_code.is_some() {
+			self.exit_code = summary.exit_code;
+
+			return Ok(ControlFlow::Break(()));
+		}
+
+		Ok(ControlFlow::Continue(()))
+	}
+
+	/// Run a loop, narrating it as a whole if its body only does arithmetic, or iteration by
+	/// iteration otherwise.
+	fn run_loop(
+		&mut self,
+		instruction: &Instruction,
+		body: &[Instruction],
+		index: usize,
+		depth: usize,
+	) -> Result<ControlFlow<()>, RunError> {
+		let cell = self.bf.pointer;
+		let value = self.bf.tape[cell].0;
+		let name = self.bf.labels.name(cell).to_string();
+
+		if value == 0 {
+			if self.charge()?.is_break() {
+				return Ok(ControlFlow::Break(()));
+			}
+
+			return self
+				.narrate(index, depth, format!("loop is skipped, since {name} is 0"))
+				.map(|()| ControlFlow::Continue(()));
+		}
+
+		if let Some(deltas) = analysis::cell_deltas(body) {
+			let ControlFlow::Continue(summary) =
+				self.execute(slice::from_ref(instruction), &mut ())?
+			else {
+				return Ok(ControlFlow::Break(()));
+			};
+
+			// NOTE: every iteration checks the condition and runs the body, and the last check
+			// leaves the loop
+			let iterations = (summary.steps - 1) / (body.len() as u64 + 1);
+			let effect = self.describe_deltas(cell, &deltas, iterations);
+
+			return self
+				.narrate(
+					index,
+					depth,
+					format!(
+						"loop repeats while {name} ≠ 0; it ran {} and {effect}",
+						times(iterations)
+					),
+				)
+				.map(|()| ControlFlow::Continue(()));
+		}
+
+		let before = self.bf.tape.clone();
+		let mut iterations: u64 = 0;
+
+		loop {
+			if self.charge()?.is_break() {
+				return Ok(ControlFlow::Break(()));
+			}
+
+			let cell = self.bf.pointer;
+			let value = self.bf.tape[cell].0;
+			let name = self.bf.labels.name(cell).to_string();
+
+			if value == 0 {
+				break;
+			}
+
+			iterations += 1;
+			self.narrate(
+				index,
+				depth,
+				format!("loop runs iteration {iterations}, since {name} is {value}"),
+			)?;
+
+			if self.block(body, index + 1, depth + 1)?.is_break() {
+				return Ok(ControlFlow::Break(()));
+			}
+		}
+
+		let changes = self.describe_changes(&before);
+		self.narrate(
+			index,
+			depth,
+			format!(
+				"loop ends after {}, since {} is 0; {changes}",
+				plural(iterations, "iteration"),
+				self.bf.labels.name(self.bf.pointer)
+			),
+		)?;
+
+		Ok(ControlFlow::Continue(()))
+	}
+
+	/// Run instructions on the engine without going over `max_steps`.
+	fn execute(
+		&mut self,
+		instructions: &[Instruction],
+		hooks: &mut impl Hooks,
+	) -> Result<ControlFlow<(), RunSummary>, RunError> {
+		let settings = RuntimeSettings {
+			budget: Some(self.max_steps - self.steps),
+			cost: unit_cost,
+			// NOTE: input must stay in the reader between runs
+			read_ahead: false,
+			break_on_output: None,
+			..self.settings.clone()
+		};
+
+		match self.bf.run_with(
+			instructions,
+			self.stdin,
+			self.stdout,
+			settings,
+			&mut StdHost::default(),
+			hooks,
+		) {
+			Ok(summary) => {
+				self.steps += summary.steps;
+
+				Ok(ControlFlow::Continue(summary))
+			}
+			Err(RunError::BudgetExhausted { spent, .. }) => {
+				self.steps += spent;
+				self.stop()?;
+
+				Ok(ControlFlow::Break(()))
+			}
+			Err(e) => Err(e),
+		}
+	}
+
+	/// Count a step that isn't run on the engine, like checking a loop's condition.
+	fn charge(&mut self) -> Result<ControlFlow<()>, RunError> {
+		if self.steps == self.max_steps {
+			self.stop()?;
+
+			return Ok(ControlFlow::Break(()));
+		}
+
+		self.steps += 1;
+
+		Ok(ControlFlow::Continue(()))
+	}
+
+	fn stop(&mut self) -> Result<(), RunError> {
+		writeln!(
+			self.narration,
+			"stopped after {}",
+			plural(self.steps, "step")
+		)?;
+
+		Ok(())
+	}
+
+	fn narrate(
+		&mut self,
+		index: usize,
+		depth: usize,
+		sentence: impl Display,
+	) -> Result<(), RunError> {
+		let location = self
+			.program
+			.span(index)
+			.map_or_else(|| "?".to_owned(), |span| span.to_string());
+
+		writeln!(
+			self.narration,
+			"{:indent$}{location} {sentence}",
+			"",
+			indent = depth * 2
+		)?;
+
+		Ok(())
+	}
+
+	/// What a loop with the given body `deltas` did to the cells around `cell` in `iterations`.
+	fn describe_deltas(
+		&self,
+		cell: usize,
+		deltas: &BTreeMap<isize, u8>,
+		iterations: u64,
+	) -> String {
+		let others: Vec<(usize, u8)> = deltas
+			.iter()
+			.filter(|&(&offset, _)| offset != 0)
+			.map(|(&offset, &delta)| {
+				let target = (cell as isize + offset).rem_euclid(self.bf.tape.len() as isize);
+
+				(target as usize, delta)
+			})
+			.collect();
+
+		if others.is_empty() {
+			return "changed nothing else".to_owned();
+		}
+
+		// NOTE: a loop that counts its cell down by one and adds one elsewhere moves the value
+		if deltas.get(&0) == Some(&u8::MAX) && others.iter().all(|&(_, delta)| delta == 1) {
+			if others
+				.iter()
+				.any(|&(target, _)| self.bf.labels.get(target).is_some())
+			{
+				let cells: Vec<String> = others
+					.iter()
+					.map(|&(target, _)| self.bf.labels.name(target).to_string())
+					.collect();
+
+				return format!("moved {iterations} into {}", join(&cells));
+			}
+
+			let cells: Vec<String> = others
+				.iter()
+				.map(|(target, _)| target.to_string())
+				.collect();
+			let noun = if cells.len() == 1 { "cell" } else { "cells" };
+
+			return format!("moved {iterations} into {noun} {}", join(&cells));
+		}
+
+		let changes: Vec<String> = others
+			.iter()
+			.map(|&(target, delta)| {
+				// NOTE: truncating is fine, since cells wrap around anyway
+				let total = Wrapping(delta) * Wrapping(iterations as u8);
+
+				let target = self.bf.labels.name(target);
+
+				match total.0 as i8 {
+					0 => format!("left {target} as it was"),
+					total @ 1.. => format!("added {total} to {target}"),
+					total => format!("subtracted {} from {target}", total.unsigned_abs()),
+				}
+			})
+			.collect();
+
+		join(&changes)
+	}
+
+	/// Which cells differ from `before`.
+	fn describe_changes(&self, before: &[Wrapping<u8>]) -> String {
+		let changes: Vec<String> = self
+			.bf
+			.tape
+			.iter()
+			.enumerate()
+			.filter(|&(cell, value)| before.get(cell).copied().unwrap_or_default() != *value)
+			.map(|(cell, value)| format!("{} to {value}", self.bf.labels.name(cell)))
+			.collect();
+
+		match changes.len() {
+			0 => "it changed nothing".to_owned(),
+			count if count > MAX_LISTED_CELLS => format!(
+				"it changed {} and {} more",
+				changes[..MAX_LISTED_CELLS].join(", "),
+				plural((count - MAX_LISTED_CELLS) as u64, "cell")
+			),
+			_ => format!("it changed {}", join(&changes)),
+		}
+	}
+}
+
+/// [`Hooks`] that remember the last byte read.
+#[derive(Default)]
+struct LastInput(Option<u8>);
+
+impl Hooks for LastInput {
+	fn on_input(&mut self, _step: u64, byte: u8) {
+		self.0 = Some(byte);
+	}
+}
+
+/// A byte along with the character it stands for, e.g. `72 ('H')`.
+fn byte(byte: u8) -> String {
+	format!("{byte} ({:?})", char::from(byte))
+}
+
+pub(crate) fn plural(count: u64, noun: &str) -> String {
+	if count == 1 {
+		format!("1 {noun}")
+	} else {
+		format!("{count} {noun}s")
+	}
+}
+
+fn times(count: u64) -> String {
+	if count == 1 {
+		"once".to_owned()
+	} else {
+		format!("{count} times")
+	}
+}
+
+/// Join items into a list like `a, b and c`.
+fn join(items: &[String]) -> String {
+	match items {
+		[] => String::new(),
+		[item] => item.clone(),
+		[rest @ .., last] => format!("{} and {last}", rest.join(", ")),
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	fn narrate(code: &str, input: &[u8], max_steps: u64) -> String {
+		let program = Program::parse(code).unwrap();
+
+		let mut narration = vec![];
+		explain(
+			&mut Engine::default(),
+			&program,
+			&mut &input[..],
+			&mut vec![],
+			&mut narration,
+			RuntimeSettings::default(),
+			max_steps,
+		)
+		.unwrap();
+
+		String::from_utf8(narration).unwrap()
+	}
+
+	#[test]
+	fn narration() {
+		assert_eq!(
+			"1:1 reads 65 ('A') into cell 0\n\
+			 1:2 loop runs iteration 1, since cell 0 is 65\n\
+			 \x20 1:3 prints cell 0, which is 65 ('A')\n\
+			 \x20 1:4 moves the pointer to cell 2\n\
+			 \x20 1:6 adds 1 to cell 2, making it 1\n\
+			 \x20 1:7 moves the pointer to cell 1\n\
+			 \x20 1:8 reaches the end of input, setting cell 1 to 0\n\
+			 1:2 loop ends after 1 iteration, since cell 1 is 0; it changed cell 2 to 1\n\
+			 1:10 subtracts 2 from cell 1, making it 254\n",
+			narrate(",[.>>+<,]--", b"A", 100)
+		);
+	}
+
+	#[test]
+	fn steps_are_capped() {
+		assert_eq!(
+			"1:1 adds 1 to cell 0, making it 1\n\
+			 1:2 loop runs iteration 1, since cell 0 is 1\n\
+			 \x20 1:3 moves the pointer to cell 1\n\
+			 \x20 1:4 loop is skipped, since cell 1 is 0\n\
+			 \x20 1:7 moves the pointer to cell 0\n\
+			 stopped after 5 steps\n",
+			narrate("+[>[-]<]", b"", 5)
+		);
+	}
+}
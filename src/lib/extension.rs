@@ -0,0 +1,429 @@
+use std::{cell::RefCell, fmt, io, ops::Deref, rc::Rc, str::FromStr};
+
+use thiserror::Error;
+
+use crate::{capability::Capability, token::Token, view::EngineMut};
+
+/// Nonstandard instructions that a program can opt into.
+///
+/// Symbols of disabled extensions are treated as comments, just like any other character.
+///
+/// Everything the rest of the crate needs to know about an extension up front is declared in its
+/// [`Definition`], so supporting a new one takes a variant, a definition, and its behavior in
+/// [`Engine`](`crate::engine::Engine`) and the analyses that run programs.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub enum Extension {
+	/// `~` writes the current time into the cell, see [`TimeSource`](`crate::engine::TimeSource`).
+	Time,
+	/// `@` ends the program, with the cell as its exit status, see
+	/// [`RunSummary::exit_code`](`crate::engine::RunSummary::exit_code`).
+	Exit,
+	/// An instruction of the embedder's, run by the [`Handler`] registered for the symbol with
+	/// [`ExtensionSet::with_handler`].
+	Custom(char),
+}
+
+impl Extension {
+	/// All the known extensions.
+	pub const ALL: &'static [Self] = &[Self::Time, Self::Exit];
+
+	/// Everything there is to know about a built-in extension, or [`None`] for
+	/// [`Extension::Custom`].
+	pub const fn definition(self) -> Option<&'static Definition> {
+		Some(match self {
+			Self::Time => &Definition {
+				name: "time",
+				symbol: '~',
+				capability: Some(Capability::Clock),
+				opcode: 8,
+				summary: "writes the current time into the cell",
+			},
+			Self::Exit => &Definition {
+				name: "exit",
+				symbol: '@',
+				capability: None,
+				opcode: 9,
+				summary: "ends the program, with the cell as its exit status",
+			},
+			Self::Custom(_) => return None,
+		})
+	}
+
+	/// Character that represents the extension's instruction in source code.
+	pub const fn symbol(self) -> char {
+		match (self, self.definition()) {
+			(_, Some(definition)) => definition.symbol,
+			(Self::Custom(symbol), None) => symbol,
+			(_, None) => unreachable!(),
+		}
+	}
+
+	/// Capability the extension's instruction needs from the host, if any.
+	///
+	/// Custom instructions don't need any, since they run the embedder's own code.
+	pub const fn capability(self) -> Option<Capability> {
+		match self.definition() {
+			Some(definition) => definition.capability,
+			None => None,
+		}
+	}
+
+	/// Name of the extension, as used on the command line.
+	pub const fn name(self) -> &'static str {
+		match self.definition() {
+			Some(definition) => definition.name,
+			None => "custom",
+		}
+	}
+
+	/// Byte that stands for the instruction in `.bfo` files, see [`Definition::opcode`]. Custom
+	/// instructions, whose symbols are ASCII, have the highest bit set on top of their symbol.
+	pub const fn opcode(self) -> u8 {
+		match (self, self.definition()) {
+			(_, Some(definition)) => definition.opcode,
+			(Self::Custom(symbol), None) => 0x80 | symbol as u8,
+			(_, None) => unreachable!(),
+		}
+	}
+
+	/// The extension with the given `.bfo` opcode, see [`Extension::opcode`].
+	pub fn from_opcode(opcode: u8) -> Option<Self> {
+		if opcode & 0x80 != 0 {
+			return Some(Self::Custom(char::from(opcode & 0x7f)));
+		}
+
+		Self::ALL
+			.iter()
+			.copied()
+			.find(|extension| extension.opcode() == opcode)
+	}
+}
+
+/// Declaration of an [`Extension`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Definition {
+	/// Name of the extension, as used on the command line.
+	pub name: &'static str,
+	/// Character that represents the extension's instruction in source code, which must not be
+	/// claimed by standard Brainfuck or any other extension.
+	pub symbol: char,
+	/// Capability the extension's instruction needs from the host, if any.
+	pub capability: Option<Capability>,
+	/// Byte that stands for the instruction in `.bfo` files. Must stay the same once released,
+	/// so files written by earlier versions keep loading.
+	pub opcode: u8,
+	/// What the instruction does, in a few words.
+	pub summary: &'static str,
+}
+
+/// A combination of extensions to enable, checked for extensions that would claim the same
+/// symbol, along with the handlers of custom instructions.
+///
+/// # Usage
+///
+/// ```
+/// # use brainfuck_rs::{
+/// #   engine::{Engine, RuntimeSettings},
+/// #   extension::{Extension, ExtensionSet},
+/// #   program::Program,
+/// # };
+/// // `%` doubles the cell
+/// let extensions = ExtensionSet::new(&[Extension::Exit])
+///     .unwrap()
+///     .with_handler('%', |bf| {
+///         bf.set_cell(bf.cell().wrapping_mul(2));
+///         Ok(())
+///     })
+///     .unwrap();
+///
+/// let program = Program::parse_with_extensions("+++++%%%.", &extensions).unwrap();
+///
+/// let mut output = vec![];
+/// Engine::default()
+///     .run(
+///         &program.instructions,
+///         &mut [].as_slice(),
+///         &mut output,
+///         RuntimeSettings {
+///             extensions,
+///             ..Default::default()
+///         },
+///     )
+///     .unwrap();
+///
+/// assert_eq!(vec![5 * 8], output);
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct ExtensionSet {
+	extensions: Vec<Extension>,
+	handlers: Vec<(char, Handler)>,
+}
+
+impl ExtensionSet {
+	/// Enable `extensions`, ignoring duplicates.
+	///
+	/// # Errors
+	///
+	/// Fails if two of them use the same symbol, or one uses a symbol of standard Brainfuck.
+	pub fn new(extensions: &[Extension]) -> Result<Self, ExtensionConflict> {
+		let mut set = Self::default();
+
+		for &extension in extensions {
+			set.enable(extension)?;
+		}
+
+		Ok(set)
+	}
+
+	/// Make `symbol` a custom instruction that runs `handler`, which gets access to the engine
+	/// through [`EngineMut`]. Running a program with such instructions takes the set in
+	/// [`RuntimeSettings::extensions`](`crate::engine::RuntimeSettings::extensions`).
+	///
+	/// # Errors
+	///
+	/// Fails if the symbol isn't a printable ASCII character, or is already taken.
+	pub fn with_handler(
+		mut self,
+		symbol: char,
+		handler: impl FnMut(&mut EngineMut<'_>) -> io::Result<()> + 'static,
+	) -> Result<Self, ExtensionConflict> {
+		if !symbol.is_ascii_graphic() {
+			return Err(ExtensionConflict::NotAscii { symbol });
+		}
+
+		self.enable(Extension::Custom(symbol))?;
+		self.handlers
+			.push((symbol, Handler(Rc::new(RefCell::new(handler)))));
+
+		Ok(self)
+	}
+
+	fn enable(&mut self, extension: Extension) -> Result<(), ExtensionConflict> {
+		let symbol = extension.symbol();
+
+		if Token::from_char(symbol).is_some() {
+			return Err(ExtensionConflict::Standard { extension, symbol });
+		}
+		if let Some(&other) = self
+			.extensions
+			.iter()
+			.find(|other| other.symbol() == symbol)
+		{
+			if other == extension && !matches!(extension, Extension::Custom(_)) {
+				return Ok(());
+			}
+
+			return Err(ExtensionConflict::Symbol {
+				extensions: (other, extension),
+				symbol,
+			});
+		}
+
+		self.extensions.push(extension);
+
+		Ok(())
+	}
+
+	/// Handler of the custom instruction `symbol`, if there is one.
+	pub fn handler(&self, symbol: char) -> Option<&Handler> {
+		self.handlers
+			.iter()
+			.find(|(other, _)| *other == symbol)
+			.map(|(_, handler)| handler)
+	}
+
+	/// The enabled extension that `symbol` stands for, if any.
+	pub fn by_symbol(&self, symbol: char) -> Option<Extension> {
+		self.extensions
+			.iter()
+			.copied()
+			.find(|extension| extension.symbol() == symbol)
+	}
+}
+
+impl Deref for ExtensionSet {
+	type Target = [Extension];
+
+	/// The enabled extensions, in the order they were given.
+	fn deref(&self) -> &Self::Target {
+		&self.extensions
+	}
+}
+
+/// Code of the embedder that runs an [`Extension::Custom`] instruction, see
+/// [`ExtensionSet::with_handler`].
+///
+/// Clones share the same closure, along with whatever it captured.
+#[derive(Clone)]
+pub struct Handler(Rc<RefCell<HandlerFn>>);
+
+type HandlerFn = dyn FnMut(&mut EngineMut<'_>) -> io::Result<()>;
+
+impl Handler {
+	/// Run the instruction.
+	///
+	/// # Errors
+	///
+	/// Whatever the handler returns, which ends the program with
+	/// [`RunError::Io`](`crate::engine::RunError::Io`).
+	///
+	/// # Panics
+	///
+	/// If the handler runs a program that calls it again.
+	pub fn call(&self, bf: &mut EngineMut<'_>) -> io::Result<()> {
+		(self.0.borrow_mut())(bf)
+	}
+}
+
+impl fmt::Debug for Handler {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		f.write_str("Handler(..)")
+	}
+}
+
+impl PartialEq for Handler {
+	fn eq(&self, other: &Self) -> bool {
+		Rc::ptr_eq(&self.0, &other.0)
+	}
+}
+
+impl Eq for Handler {}
+
+/// An error returned when enabling extensions that can't be used together.
+#[derive(Debug, Clone, PartialEq, Eq, Error)]
+pub enum ExtensionConflict {
+	/// Two extensions use the same symbol
+	#[error("extensions `{}` and `{}` both use `{symbol}`", .extensions.0, .extensions.1)]
+	Symbol {
+		/// The extensions, in the order they were given.
+		extensions: (Extension, Extension),
+		/// The symbol they share.
+		symbol: char,
+	},
+	/// An extension uses a symbol of standard Brainfuck
+	#[error("extension `{extension}` uses `{symbol}`, which is a standard instruction")]
+	Standard {
+		/// The extension.
+		extension: Extension,
+		/// Its symbol.
+		symbol: char,
+	},
+	/// A custom instruction was given a symbol that isn't a printable ASCII character
+	#[error("`{symbol}` can't be a custom instruction, only printable ASCII characters can")]
+	NotAscii {
+		/// The symbol.
+		symbol: char,
+	},
+}
+
+impl fmt::Display for Extension {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		match self {
+			Self::Custom(symbol) => write!(f, "custom `{symbol}`"),
+			_ => f.write_str(self.name()),
+		}
+	}
+}
+
+impl FromStr for Extension {
+	type Err = UnknownExtension;
+
+	fn from_str(name: &str) -> Result<Self, Self::Err> {
+		Self::ALL
+			.iter()
+			.copied()
+			.find(|extension| extension.name() == name)
+			.ok_or_else(|| UnknownExtension(name.to_owned()))
+	}
+}
+
+/// An error returned when parsing the name of an extension that doesn't exist.
+#[derive(Debug, Clone, PartialEq, Eq, Error)]
+#[error("unknown extension `{0}`")]
+pub struct UnknownExtension(pub String);
+
+#[cfg(test)]
+mod tests {
+	use crate::{
+		bytecode::Bytecode,
+		engine::{Engine, RuntimeSettings},
+		instruction::Instruction,
+	};
+
+	use super::*;
+
+	#[test]
+	fn definitions_are_consistent() {
+		let all = ExtensionSet::new(&[Extension::Time, Extension::Exit, Extension::Time]).unwrap();
+		assert_eq!(Extension::ALL, &*all);
+		assert_eq!(Some(Extension::Exit), all.by_symbol('@'));
+
+		for (index, extension) in Extension::ALL.iter().enumerate() {
+			let definition = extension.definition().unwrap();
+
+			assert_eq!(Ok(*extension), definition.name.parse());
+			assert_eq!(Some(*extension), Extension::from_opcode(definition.opcode));
+			// NOTE: opcodes below 8 belong to standard instructions, and ones with the highest
+			// bit set to custom instructions
+			assert!((8..0x80).contains(&definition.opcode));
+			assert!(Extension::ALL[..index]
+				.iter()
+				.all(|other| other.opcode() != definition.opcode));
+		}
+	}
+
+	#[test]
+	fn custom_instructions() {
+		let custom = Extension::Custom('%');
+		assert_eq!(Some(custom), Extension::from_opcode(custom.opcode()));
+
+		let set = ExtensionSet::default()
+			.with_handler('%', |bf| {
+				bf.set_cell(bf.cell() + 1);
+				bf.write(bf.cell())
+			})
+			.unwrap();
+		assert!(set.handler('%').is_some());
+		assert_eq!(&[custom], &*set);
+
+		let mut bf = Engine::default();
+		let mut output = vec![];
+		let bytecode = Bytecode::compile(&[Instruction::Inc, Instruction::Extension(custom)]);
+		let settings = RuntimeSettings {
+			extensions: set,
+			..Default::default()
+		};
+		bf.run_bytecode(&bytecode, &mut [].as_slice(), &mut output, settings)
+			.unwrap();
+		assert_eq!(vec![2], output);
+		assert!(bf
+			.run_bytecode(
+				&bytecode,
+				&mut [].as_slice(),
+				&mut vec![],
+				RuntimeSettings::default()
+			)
+			.is_err());
+
+		assert_eq!(
+			Err(ExtensionConflict::Symbol {
+				extensions: (Extension::Time, Extension::Custom('~')),
+				symbol: '~',
+			}),
+			ExtensionSet::new(&[Extension::Time])
+				.unwrap()
+				.with_handler('~', |_| Ok(()))
+		);
+		assert_eq!(
+			Err(ExtensionConflict::Standard {
+				extension: Extension::Custom('+'),
+				symbol: '+',
+			}),
+			ExtensionSet::default().with_handler('+', |_| Ok(()))
+		);
+		assert_eq!(
+			Err(ExtensionConflict::NotAscii { symbol: 'λ' }),
+			ExtensionSet::default().with_handler('λ', |_| Ok(()))
+		);
+	}
+}
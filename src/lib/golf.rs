@@ -0,0 +1,121 @@
+use std::iter;
+
+use serde::Serialize;
+
+use crate::{
+	extension::Extension,
+	instruction::{Instruction, ParseError},
+	program::Program,
+	token::Token,
+};
+
+/// How long a program is, by the measures code golf contests use.
+///
+/// # Usage
+///
+/// ```
+/// # use brainfuck_rs::golf::Score;
+/// let score = Score::of("[print a zero] ++++++[>++++++++<-]>. +-", &[]).unwrap();
+///
+/// assert_eq!(39, score.bytes);
+/// assert_eq!(25, score.commands);
+/// assert_eq!("++++++[>++++++++<-]>.", score.minified);
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct Score {
+	/// Size of the source code in bytes, comments included.
+	pub bytes: usize,
+	/// Number of commands in the source code, leaving comments out.
+	pub commands: usize,
+	/// The shortest source code found that runs the same: without comments, instructions that
+	/// undo each other, like `+-`, and loops that are never entered, like comment loops at the
+	/// start, on a tape of 30000 cells.
+	pub minified: String,
+}
+
+impl Score {
+	/// Score the source code of a program that may use the given extensions.
+	///
+	/// # Errors
+	///
+	/// It may error if there is unmatched loop start or loop end.
+	pub fn of(code: &str, extensions: &[Extension]) -> Result<Self, ParseError> {
+		let program = Program::parse_with_extensions(code, extensions)?;
+
+		Ok(Self {
+			bytes: code.len(),
+			commands: Token::tokenize_with_extensions(code, extensions).count(),
+			minified: minify(
+				&program
+					.cancel_opposites()
+					.remove_dead_loops(30_000)
+					.instructions,
+			),
+		})
+	}
+}
+
+/// Source code of `instructions`, without any comments.
+pub fn minify(instructions: &[Instruction]) -> String {
+	fn write(instructions: &[Instruction], output: &mut String) {
+		for instruction in instructions {
+			let token = match instruction {
+				Instruction::Inc => Token::Inc,
+				Instruction::Dec => Token::Dec,
+				Instruction::Next => Token::Next,
+				Instruction::Prev => Token::Prev,
+				Instruction::Print => Token::Print,
+				Instruction::Read => Token::Read,
+				Instruction::Extension(extension) => Token::Extension(*extension),
+				Instruction::Clear => {
+					output.push_str("[-]");
+					continue;
+				}
+				Instruction::Add(amount) => {
+					let symbol = if *amount < 0 { '-' } else { '+' };
+					output.extend(iter::repeat_n(symbol, amount.unsigned_abs().into()));
+					continue;
+				}
+				Instruction::Move(distance) => {
+					let symbol = if *distance < 0 { '<' } else { '>' };
+					output.extend(iter::repeat_n(symbol, distance.unsigned_abs() as usize));
+					continue;
+				}
+				Instruction::Loop(body) => {
+					output.push(Token::LoopStart.symbol());
+					write(body, output);
+					Token::LoopEnd
+				}
+			};
+			output.push(token.symbol());
+		}
+	}
+
+	let mut output = String::new();
+	write(instructions, &mut output);
+	output
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn minified_code_parses_back() {
+		let code = "~ time [>, loop ] <>++-[~<]";
+		let extensions = [Extension::Time];
+		let score = Score::of(code, &extensions).unwrap();
+
+		assert_eq!(14, score.commands);
+		assert_eq!("~[>,]+[~<]", score.minified);
+		assert_eq!(
+			Program::parse_with_extensions(code, &extensions)
+				.unwrap()
+				.cancel_opposites()
+				.instructions,
+			Program::parse_with_extensions(&score.minified, &extensions)
+				.unwrap()
+				.instructions
+		);
+	}
+}
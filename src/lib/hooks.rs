@@ -0,0 +1,95 @@
+use std::ops::ControlFlow;
+
+use crate::{bytecode::Op, instruction::Instruction, pause::SafePoint, view::EngineView};
+
+/// Callbacks the engine invokes while running a program, see
+/// [`Engine::run_with`](`crate::engine::Engine::run_with`).
+///
+/// Every method does nothing by default, so implementors only override what they need. `step` is
+/// always the number of instructions (or ops) executed before the current one.
+///
+/// `()` implements this trait without any callbacks, which the compiler optimizes away entirely.
+pub trait Hooks {
+	/// Whether the engines call the hooks at all. Only `()` turns this off, which lets the
+	/// engines leave out the calls made on every step, along with what they'd be passed.
+	const ENABLED: bool = true;
+
+	/// Whether the tree engine checks every step for wraps to call [`Hooks::on_wrap`] about,
+	/// which is decided when the engine is compiled for the hooks, rather than as it runs. Hooks
+	/// that don't override [`Hooks::on_wrap`] may turn this off to not pay for the checks.
+	const WATCHES_WRAPS: bool = Self::ENABLED;
+
+	/// Called right before an instruction is executed, with a look at the engine as it is at that
+	/// point.
+	#[inline]
+	fn before_instruction(&mut self, _step: u64, _instruction: &Instruction, _bf: EngineView<'_>) {}
+
+	/// Called right before an op of [`Bytecode`](`crate::bytecode::Bytecode`) is executed, `index`
+	/// being its position in [`Bytecode::ops`](`crate::bytecode::Bytecode::ops`).
+	#[inline]
+	fn before_op(&mut self, _step: u64, _index: usize, _op: Op, _bf: EngineView<'_>) {}
+
+	/// Asked right after [`Hooks::before_instruction`] and [`Hooks::before_op`] whether to stop
+	/// the program before it executes the instruction, setting
+	/// [`RunSummary::breakpoint_hit`](`crate::engine::RunSummary::breakpoint_hit`), like at a
+	/// breakpoint.
+	#[inline]
+	fn should_break(&mut self) -> bool {
+		false
+	}
+
+	/// Called after a byte was read from the input.
+	#[inline]
+	fn on_input(&mut self, _step: u64, _byte: u8) {}
+
+	/// Called after a byte was written to the output.
+	#[inline]
+	fn on_output(&mut self, _step: u64, _byte: u8) {}
+
+	/// Called after a cell or the pointer wrapped around.
+	///
+	/// Only the tree engine calls this, since bytecode combines instructions, which would hide
+	/// some of the wraps.
+	#[inline]
+	fn on_wrap(&mut self, _step: u64, _wrap: Wrap) {}
+
+	/// Called when the program got stuck in `loop_instruction`, see
+	/// [`RuntimeSettings::idle_loops`](`crate::engine::RuntimeSettings::idle_loops`).
+	#[inline]
+	fn on_idle_loop(&mut self, _step: u64, _loop_instruction: &Instruction) {}
+
+	/// Called between instructions every so often, see
+	/// [`RuntimeSettings::yield_every`](`crate::engine::RuntimeSettings::yield_every`).
+	#[inline]
+	fn on_yield(&mut self, _step: u64, _bf: EngineView<'_>) {}
+
+	/// Called when the program paused at a safe point, because a pause was requested through
+	/// [`RuntimeSettings::pause`](`crate::engine::RuntimeSettings::pause`). Everything printed so
+	/// far has been written by then.
+	///
+	/// The program stays paused while this runs, so it may take as long as it likes to look at
+	/// the engine, like a debugger waiting for commands. Returning [`ControlFlow::Continue`]
+	/// resumes the program, and [`ControlFlow::Break`], which is the default, ends the run there,
+	/// setting [`RunSummary::paused`](`crate::engine::RunSummary::paused`).
+	#[inline]
+	fn on_pause(&mut self, _step: u64, _at: SafePoint, _bf: EngineView<'_>) -> ControlFlow<()> {
+		ControlFlow::Break(())
+	}
+}
+
+impl Hooks for () {
+	const ENABLED: bool = false;
+}
+
+/// Something that wrapped around, see [`Hooks::on_wrap`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Wrap {
+	/// `+` turned the cell with this index from 255 into 0.
+	CellOverflow(usize),
+	/// `-` turned the cell with this index from 0 into 255.
+	CellUnderflow(usize),
+	/// `>` moved the pointer from the last cell to the first one.
+	PointerPastEnd,
+	/// `<` moved the pointer from the first cell to the last one.
+	PointerPastStart,
+}
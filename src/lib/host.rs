@@ -0,0 +1,191 @@
+use std::{
+	collections::{hash_map::RandomState, BTreeSet},
+	env,
+	fs::{File, OpenOptions},
+	hash::{BuildHasher, Hasher},
+	io::{self, ErrorKind, Read, Write},
+	path::Path,
+	time::Instant,
+};
+
+use crate::capability::Capability;
+
+/// Everything the engine may need from the outside world, besides program input and output.
+///
+/// Extension instructions never touch the host directly, but go through this trait instead, so
+/// embedders can mock it in tests, or refuse access altogether with [`DenyAll`].
+pub trait HostServices {
+	/// Milliseconds elapsed since an arbitrary, but fixed, point in time.
+	///
+	/// # Errors
+	///
+	/// It errors if the host doesn't allow reading the clock.
+	fn clock_millis(&mut self) -> io::Result<u64>;
+
+	/// A random byte.
+	///
+	/// # Errors
+	///
+	/// It errors if the host doesn't allow generating random numbers.
+	fn random_byte(&mut self) -> io::Result<u8>;
+
+	/// Value of an environment variable, if it's set.
+	///
+	/// # Errors
+	///
+	/// It errors if the host doesn't allow reading the environment.
+	fn env_var(&mut self, name: &str) -> io::Result<Option<String>>;
+
+	/// Open a file for reading or writing.
+	///
+	/// # Errors
+	///
+	/// It errors if the host doesn't allow file access or the file couldn't be opened.
+	fn open_file(&mut self, path: &Path, mode: FileMode) -> io::Result<Box<dyn HostFile>>;
+}
+
+/// A file handle given out by [`HostServices::open_file`].
+pub trait HostFile: Read + Write {}
+
+impl<T: Read + Write> HostFile for T {}
+
+/// How a file is opened by [`HostServices::open_file`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FileMode {
+	/// Open an existing file for reading.
+	Read,
+	/// Create or truncate a file for writing.
+	Write,
+	/// Create a file or append to the existing one.
+	Append,
+}
+
+/// [`HostServices`] backed by the standard library.
+#[derive(Debug, Clone)]
+pub struct StdHost {
+	start: Instant,
+	random_state: u64,
+}
+
+impl Default for StdHost {
+	/// Creates a new `StdHost` whose clock starts now and whose random numbers are seeded
+	/// differently on every run.
+	fn default() -> Self {
+		let seed = RandomState::new().build_hasher().finish();
+
+		Self {
+			start: Instant::now(),
+			// NOTE: xorshift gets stuck on zero
+			random_state: seed | 1,
+		}
+	}
+}
+
+impl HostServices for StdHost {
+	fn clock_millis(&mut self) -> io::Result<u64> {
+		Ok(u64::try_from(self.start.elapsed().as_millis()).unwrap_or(u64::MAX))
+	}
+
+	fn random_byte(&mut self) -> io::Result<u8> {
+		// NOTE: xorshift64 is more than enough for toy programs and avoids a dependency
+		self.random_state ^= self.random_state << 13;
+		self.random_state ^= self.random_state >> 7;
+		self.random_state ^= self.random_state << 17;
+
+		Ok(self.random_state.to_le_bytes()[0])
+	}
+
+	fn env_var(&mut self, name: &str) -> io::Result<Option<String>> {
+		Ok(env::var(name).ok())
+	}
+
+	fn open_file(&mut self, path: &Path, mode: FileMode) -> io::Result<Box<dyn HostFile>> {
+		let file: File = match mode {
+			FileMode::Read => File::open(path)?,
+			FileMode::Write => File::create(path)?,
+			FileMode::Append => OpenOptions::new().create(true).append(true).open(path)?,
+		};
+
+		Ok(Box::new(file))
+	}
+}
+
+/// [`HostServices`] that refuse every request with [`ErrorKind::PermissionDenied`].
+///
+/// Meant for running untrusted programs, where extensions must not reach the outside world.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct DenyAll;
+
+impl DenyAll {
+	fn deny<T>(what: &str) -> io::Result<T> {
+		Err(io::Error::new(
+			ErrorKind::PermissionDenied,
+			format!("access to {what} is denied by the host"),
+		))
+	}
+}
+
+impl HostServices for DenyAll {
+	fn clock_millis(&mut self) -> io::Result<u64> {
+		Self::deny("the clock")
+	}
+
+	fn random_byte(&mut self) -> io::Result<u8> {
+		Self::deny("random numbers")
+	}
+
+	fn env_var(&mut self, _name: &str) -> io::Result<Option<String>> {
+		Self::deny("environment variables")
+	}
+
+	fn open_file(&mut self, _path: &Path, _mode: FileMode) -> io::Result<Box<dyn HostFile>> {
+		Self::deny("files")
+	}
+}
+
+/// [`HostServices`] that pass on to `host` only what `capabilities` allow, and refuse the rest
+/// like [`DenyAll`] does.
+///
+/// Meant for code that can't be checked for the capabilities it needs before it runs, like
+/// handlers loaded from plugins.
+#[derive(Debug, Clone, Default)]
+pub struct Granted<H> {
+	/// Host that allowed requests go to.
+	pub host: H,
+	/// What requests are allowed.
+	pub capabilities: BTreeSet<Capability>,
+}
+
+impl<H: HostServices> HostServices for Granted<H> {
+	fn clock_millis(&mut self) -> io::Result<u64> {
+		if self.capabilities.contains(&Capability::Clock) {
+			self.host.clock_millis()
+		} else {
+			DenyAll.clock_millis()
+		}
+	}
+
+	fn random_byte(&mut self) -> io::Result<u8> {
+		if self.capabilities.contains(&Capability::Random) {
+			self.host.random_byte()
+		} else {
+			DenyAll.random_byte()
+		}
+	}
+
+	fn env_var(&mut self, name: &str) -> io::Result<Option<String>> {
+		if self.capabilities.contains(&Capability::Env) {
+			self.host.env_var(name)
+		} else {
+			DenyAll.env_var(name)
+		}
+	}
+
+	fn open_file(&mut self, path: &Path, mode: FileMode) -> io::Result<Box<dyn HostFile>> {
+		if self.capabilities.contains(&Capability::Files) {
+			self.host.open_file(path, mode)
+		} else {
+			DenyAll.open_file(path, mode)
+		}
+	}
+}
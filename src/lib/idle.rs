@@ -0,0 +1,90 @@
+use std::num::Wrapping;
+
+use crate::{engine::Engine, instruction::Instruction};
+
+/// Finds out whether a program is stuck, by checking if it gets back to exactly the same state,
+/// without any IO in between, at which point it's bound to repeat itself forever.
+///
+/// Comparing every state with every earlier one would be too slow, so only a single snapshot is
+/// kept, and replaced with the current state after twice as many steps as the last time (Brent's
+/// cycle detection). Once the program is in a cycle, a snapshot eventually lands in it, and the
+/// cycle is found as soon as the snapshot's interval outgrows the cycle's length.
+///
+/// Only cells the pointer visited since the snapshot was taken can have changed, so only those are
+/// compared.
+#[derive(Debug)]
+pub(crate) struct IdleLoopDetector {
+	snapshot: Option<Snapshot>,
+	/// Step at which the snapshot was taken.
+	taken_at: u64,
+	/// Number of steps after which the snapshot is replaced.
+	interval: u64,
+	/// Lowest and highest cell the pointer visited since the snapshot was taken.
+	touched: (usize, usize),
+}
+
+impl Default for IdleLoopDetector {
+	fn default() -> Self {
+		Self {
+			snapshot: None,
+			taken_at: 0,
+			interval: 1,
+			touched: (0, 0),
+		}
+	}
+}
+
+/// State of a program at the start of a loop iteration.
+#[derive(Debug)]
+struct Snapshot {
+	/// The loop, identified by its address, which also identifies what runs after it.
+	instruction: *const Instruction,
+	pointer: usize,
+	tape: Vec<Wrapping<u8>>,
+}
+
+impl IdleLoopDetector {
+	/// Take a look at the state at the start of an iteration of `instruction`, returning whether
+	/// it was seen before.
+	pub fn check(&mut self, instruction: &Instruction, bf: &Engine, step: u64) -> bool {
+		if let Some(snapshot) = &self.snapshot {
+			let (low, high) = self.touched;
+
+			if std::ptr::eq(snapshot.instruction, instruction)
+				&& snapshot.pointer == bf.pointer
+				&& snapshot.tape.len() == bf.tape.len()
+				&& snapshot.tape[low..=high] == bf.tape[low..=high]
+			{
+				return true;
+			}
+		}
+
+		if self.snapshot.is_none() || step - self.taken_at >= self.interval {
+			self.snapshot = Some(Snapshot {
+				instruction,
+				pointer: bf.pointer,
+				tape: bf.tape.clone(),
+			});
+			self.taken_at = step;
+			self.interval = self.interval.saturating_mul(2);
+			self.touched = (bf.pointer, bf.pointer);
+		}
+
+		false
+	}
+
+	/// Note that the pointer moved to `pointer`.
+	#[inline]
+	pub fn moved(&mut self, pointer: usize) {
+		let (low, high) = &mut self.touched;
+
+		*low = (*low).min(pointer);
+		*high = (*high).max(pointer);
+	}
+
+	/// Forget the snapshot after the program talked to the outside world, since the state of it
+	/// isn't part of the snapshot.
+	pub fn reset(&mut self) {
+		*self = Self::default();
+	}
+}
@@ -1,9 +1,11 @@
+use std::{mem, slice};
+
 use thiserror::Error;
 
-use crate::token::Token;
+use crate::{extension::Extension, token::Token};
 
 /// Instructions that are executed.
-#[derive(Debug, Clone, PartialEq, Eq)]
+#[derive(Debug, PartialEq, Eq)]
 pub enum Instruction {
 	/// `+`
 	Inc,
@@ -18,35 +20,121 @@ pub enum Instruction {
 	/// `.`
 	Read,
 	/// `[` and `]`
-	Loop(Vec<Instruction>),
+	Loop(Vec<Self>),
+	/// `[-]` or `[+]`, which sets the cell to zero at once, as made by
+	/// [`ClearLoops`](`crate::optimize::ClearLoops`)
+	Clear,
+	/// `+` or `-` repeated, which adds to the cell at once, negative for `-`, as made by
+	/// [`CollapseRuns`](`crate::optimize::CollapseRuns`)
+	Add(i8),
+	/// `>` or `<` repeated, which moves the pointer at once, negative for `<`, as made by
+	/// [`CollapseRuns`](`crate::optimize::CollapseRuns`)
+	Move(i32),
+	/// Instruction of an [`Extension`]
+	Extension(Extension),
+}
+
+impl Clone for Instruction {
+	/// Clones the instruction, along with the loops nested in it. Unlike a derived `Clone`, it
+	/// doesn't recurse into them, so it works however deeply they're nested.
+	fn clone(&self) -> Self {
+		let body = match self {
+			Self::Inc => return Self::Inc,
+			Self::Dec => return Self::Dec,
+			Self::Next => return Self::Next,
+			Self::Prev => return Self::Prev,
+			Self::Print => return Self::Print,
+			Self::Read => return Self::Read,
+			Self::Clear => return Self::Clear,
+			Self::Add(amount) => return Self::Add(*amount),
+			Self::Move(distance) => return Self::Move(*distance),
+			Self::Extension(extension) => return Self::Extension(*extension),
+			Self::Loop(body) => body,
+		};
+
+		let mut rest = body.iter();
+		let mut cloned = Vec::with_capacity(body.len());
+		// NOTE: the rest of the bodies around the one being cloned, and what's cloned of them so
+		// far, innermost last
+		let mut open: Vec<(slice::Iter<'_, Self>, Vec<Self>)> = vec![];
+
+		loop {
+			match rest.next() {
+				Some(Self::Loop(inner)) => open.push((
+					mem::replace(&mut rest, inner.iter()),
+					mem::replace(&mut cloned, Vec::with_capacity(inner.len())),
+				)),
+				Some(instruction) => cloned.push(instruction.clone()),
+				None => {
+					let Some((outer_rest, outer_cloned)) = open.pop() else {
+						return Self::Loop(cloned);
+					};
+
+					rest = outer_rest;
+					let inner = mem::replace(&mut cloned, outer_cloned);
+					cloned.push(Self::Loop(inner));
+				}
+			}
+		}
+	}
 }
 
 impl From<Token> for Instruction {
 	fn from(token: Token) -> Self {
 		match token {
-			Token::Inc => Instruction::Inc,
-			Token::Dec => Instruction::Dec,
-			Token::Next => Instruction::Next,
-			Token::Prev => Instruction::Prev,
-			Token::Print => Instruction::Print,
-			Token::Read => Instruction::Read,
-			loop_token => panic!("attempt to convert {:?} into Instruction", loop_token),
+			Token::Inc => Self::Inc,
+			Token::Dec => Self::Dec,
+			Token::Next => Self::Next,
+			Token::Prev => Self::Prev,
+			Token::Print => Self::Print,
+			Token::Read => Self::Read,
+			Token::Extension(extension) => Self::Extension(extension),
+			loop_token => panic!("attempt to convert {loop_token:?} into Instruction"),
 		}
 	}
 }
 
 impl Instruction {
+	/// The symbol of the instruction in source code, `[` for loops, and for clears, which were
+	/// loops. Runs have the symbol they repeat.
+	pub const fn symbol(&self) -> char {
+		match self {
+			Self::Inc => '+',
+			Self::Dec => '-',
+			Self::Add(amount) => {
+				if *amount < 0 {
+					'-'
+				} else {
+					'+'
+				}
+			}
+			Self::Next => '>',
+			Self::Prev => '<',
+			Self::Move(distance) => {
+				if *distance < 0 {
+					'<'
+				} else {
+					'>'
+				}
+			}
+			Self::Print => '.',
+			Self::Read => ',',
+			Self::Loop(_) | Self::Clear => '[',
+			Self::Extension(extension) => extension.symbol(),
+		}
+	}
+
 	/// Get the inside of [`Instruction::Loop`]
 	pub fn get_inner_mut(&mut self) -> Option<&mut Vec<Self>> {
 		match self {
-			Instruction::Loop(x) => Some(x),
+			Self::Loop(x) => Some(x),
 			_ => None,
 		}
 	}
 
 	/// Get the deepest [`Instruction::Loop`] inside a nested [`Instruction::Loop`].
 	pub fn get_last_deepest_mut(&mut self, nesting: usize) -> &mut Self {
-		let mut instruction_ref: &mut Instruction = self;
+		let mut instruction_ref: &mut Self = self;
 
 		for _ in 1..nesting {
 			instruction_ref = instruction_ref.get_inner_mut().unwrap().last_mut().unwrap();
@@ -57,6 +145,8 @@ impl Instruction {
 
 	/// Parse a sequence of [`Token`]s into a [`Vec`] of [`Instruction`]s.
 	///
+	/// It takes time linear in the number of tokens, however deeply loops are nested.
+	///
 	/// # Usage
 	///
 	/// ```
@@ -71,51 +161,25 @@ impl Instruction {
 	/// # Errors
 	///
 	/// It may error if there is unmatched loop start or loop end.
-	pub fn parse(tokens: impl IntoIterator<Item = Token>) -> Result<Vec<Instruction>, ParseError> {
-		let mut instructions: Vec<Instruction> = vec![];
+	pub fn parse(tokens: impl IntoIterator<Item = Token>) -> Result<Vec<Self>, ParseError> {
+		let mut instructions: Vec<Self> = vec![];
+		// NOTE: bodies of the loops that are open, innermost last; the innermost one is
+		// `instructions` itself, which is swapped in and out so pushing never walks the tree
+		let mut open: Vec<Vec<Self>> = vec![];
 
-		let mut nesting = 0;
-		for token in tokens.into_iter() {
+		for token in tokens {
 			match token {
-				Token::LoopStart => {
-					if nesting > 0 {
-						instructions
-							.last_mut()
-							.unwrap()
-							.get_last_deepest_mut(nesting)
-							.get_inner_mut()
-							.unwrap()
-							.push(Instruction::Loop(vec![]));
-					} else {
-						instructions.push(Instruction::Loop(vec![]));
-					}
-
-					nesting += 1;
-				}
+				Token::LoopStart => open.push(std::mem::take(&mut instructions)),
 				Token::LoopEnd => {
-					if nesting == 0 {
-						return Err(ParseError::UnmatchedLoopEnd);
-					}
-
-					nesting -= 1;
-				}
-				other => {
-					if nesting > 0 {
-						instructions
-							.last_mut()
-							.unwrap()
-							.get_last_deepest_mut(nesting)
-							.get_inner_mut()
-							.unwrap()
-							.push(other.into());
-					} else {
-						instructions.push(other.into());
-					}
+					let outer = open.pop().ok_or(ParseError::UnmatchedLoopEnd)?;
+					let body = std::mem::replace(&mut instructions, outer);
+					instructions.push(Self::Loop(body));
 				}
+				other => instructions.push(other.into()),
 			}
 		}
 
-		if nesting > 0 {
+		if !open.is_empty() {
 			return Err(ParseError::UnmatchedLoopStart);
 		}
 
@@ -218,6 +282,8 @@ mod tests {
 		}
 	}
 
+	// NOTE: these are kept as they were written, borrowing programs that are already `&str`
+	#[allow(clippy::needless_borrow)]
 	mod parser_tests {
 		use super::*;
 
@@ -292,5 +358,23 @@ mod tests {
 
 			assert_eq!(expected, instructions);
 		}
+
+		#[test]
+		fn deep_nesting() {
+			let depth = 10_000;
+			let program = format!("{}+{}", "[".repeat(depth), "]".repeat(depth));
+
+			let mut instructions =
+				Instruction::parse(Token::tokenize(&program)).expect("parsing failed");
+
+			for _ in 0..depth {
+				let Some(Instruction::Loop(body)) = instructions.pop() else {
+					panic!("expected a loop");
+				};
+				assert!(instructions.is_empty());
+				instructions = body;
+			}
+			assert_eq!(vec![Instruction::Inc], instructions);
+		}
 	}
 }
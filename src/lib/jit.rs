@@ -0,0 +1,1187 @@
+use std::{
+	io::{self, ErrorKind, Read, Write},
+	mem,
+	num::Wrapping,
+};
+
+use cranelift_codegen::{
+	ir::{condcodes::IntCC, types, AbiParam, Block, FuncRef, InstBuilder, MemFlags, Type, Value},
+	isa::OwnedTargetIsa,
+	settings::{self, Configurable},
+};
+use cranelift_frontend::{FunctionBuilder, FunctionBuilderContext, Variable};
+use cranelift_jit::{JITBuilder, JITModule};
+use cranelift_module::{default_libcall_names, DataDescription, FuncId, Linkage, Module};
+use thiserror::Error;
+
+use crate::{
+	bytecode::{counting_tape_length, Bytecode, Op},
+	engine::{contain, Engine, Eof, NewlineMode, RunError, RunSummary, RuntimeSettings},
+};
+
+/// Output is written in chunks of this many bytes, unless it's flushed on every print.
+const CHUNK: usize = 8 * 1024;
+
+/// What the compiled code returns when the program ends, and the callbacks when it goes on.
+const FINISHED: i32 = 0;
+/// Returned by [`read`] when the program ends because input ended.
+const QUIT_ON_EOF: i32 = 1;
+/// Returned by the callbacks when IO failed, with the error left in [`Io::error`].
+const FAILED: i32 = 2;
+
+/// Signature of the compiled code: the tape, the pointer, which is updated once it returns, and
+/// the IO it talks to through the callbacks.
+type Compiled = unsafe extern "C" fn(*mut u8, *mut usize, *mut Io<'_>) -> i32;
+
+/// A program compiled to native code with Cranelift, for programs that run long enough that even
+/// the fused bytecode is too slow.
+///
+/// The code is compiled for a tape of a given length, and only runs on engines with a tape that
+/// long. Cells and the pointer wrap around like on the other engines, but steps aren't counted, so
+/// budgets, hooks and the like aren't supported, nor are extensions.
+///
+/// Cells are accessed without bounds checks, and without guard pages around the tape either: since
+/// the tape length is compiled in, moving the pointer wraps it around with a compare and a select,
+/// so it's never off the tape when a cell is accessed, and there's nothing for a guard page to
+/// catch.
+///
+/// # Usage
+///
+/// ```
+/// # use brainfuck_rs::{bytecode::Bytecode, engine::{Engine, RuntimeSettings}, jit::JitProgram, program::Program};
+/// let program = Program::parse("++++++[->++++++++<]>.").unwrap();
+/// let bytecode = Bytecode::compile(&program.instructions).collapse().multiply_loops();
+/// let compiled = JitProgram::compile(&bytecode, 30_000).unwrap();
+///
+/// let mut output = vec![];
+/// compiled
+///     .run(&mut Engine::default(), &mut [].as_slice(), &mut output, &RuntimeSettings::default())
+///     .unwrap();
+///
+/// assert_eq!(b"0", output.as_slice());
+/// ```
+pub struct JitProgram {
+	/// Owns the memory the code lives in, freed when the program is dropped.
+	module: Option<JITModule>,
+	code: Compiled,
+	tape_length: usize,
+}
+
+impl JitProgram {
+	/// Compile `bytecode` for a tape of `tape_length` cells.
+	///
+	/// Any bytecode without extensions compiles, though [collapsed](`Bytecode::collapse`)
+	/// bytecode, with [multiply loops](`Bytecode::multiply_loops`) and the like, compiles into
+	/// faster code.
+	///
+	/// # Errors
+	///
+	/// It errors if the bytecode has ops that can't be compiled, like extensions, or if Cranelift
+	/// doesn't support the machine it runs on.
+	///
+	/// # Panics
+	///
+	/// If `tape_length` is zero.
+	pub fn compile(bytecode: &Bytecode, tape_length: usize) -> Result<Self, JitError> {
+		assert!(tape_length > 0, "the tape has no cells");
+
+		check(bytecode.ops(), true)?;
+
+		let mut builder = JITBuilder::with_isa(isa(false)?, default_libcall_names());
+		builder.symbol("bf_print", print as *const u8);
+		builder.symbol("bf_read", read as *const u8);
+		let mut module = JITModule::new(builder);
+
+		let id = translate(
+			&mut module,
+			bytecode.ops(),
+			tape_length,
+			Runtime::Callbacks,
+			None,
+		)?;
+		module.finalize_definitions().map_err(codegen)?;
+
+		// SAFETY: the function was defined with the signature of `Compiled`
+		let code =
+			unsafe { mem::transmute::<*const u8, Compiled>(module.get_finalized_function(id)) };
+
+		Ok(Self {
+			module: Some(module),
+			code,
+			tape_length,
+		})
+	}
+
+	/// Run the program on `bf`, reading from `stdin` and writing to `stdout`, like
+	/// [`Engine::run_bytecode`] does.
+	///
+	/// Of the settings, only [`RuntimeSettings::eof`], [`RuntimeSettings::quit_on_eof`] and
+	/// [`RuntimeSettings::should_flush`] are honored. [`RunSummary::steps`] is always zero.
+	///
+	/// # Errors
+	///
+	/// It errors if reading input or writing output fails, with [`ErrorKind::Unsupported`] if
+	/// the settings ask for something the compiled code can't do, like a budget, or with
+	/// [`RunError::InternalError`] if the pointer of `bf` is off its tape, or if it panics, unless
+	/// [`RuntimeSettings::contain_panics`] is off. Panics of `stdin` or `stdout` while the compiled
+	/// code calls them can't unwind through it, and abort.
+	///
+	/// # Panics
+	///
+	/// If the tape of `bf` isn't as long as the one the program was compiled for.
+	pub fn run(
+		&self,
+		bf: &mut Engine,
+		stdin: &mut impl Read,
+		stdout: &mut impl Write,
+		settings: &RuntimeSettings,
+	) -> Result<RunSummary, RunError> {
+		assert_eq!(
+			self.tape_length,
+			bf.tape.len(),
+			"the program was compiled for another tape length"
+		);
+		// NOTE: the pointer may have been moved by hand, and the code trusts it to be on the tape
+		bf.check_pointer("native")?;
+
+		let unsupported = [
+			(settings.budget.is_some(), "budgets"),
+			(settings.grow_tape, "growing tapes"),
+			(settings.break_on_output.is_some(), "breaking on output"),
+			(settings.yield_every.is_some(), "yielding"),
+			(settings.pause.is_some(), "pausing"),
+			(
+				settings.newlines != NewlineMode::Passthrough,
+				"newline translation",
+			),
+		];
+		if let Some((_, what)) = unsupported.iter().find(|(asked, _)| *asked) {
+			return Err(io::Error::new(
+				ErrorKind::Unsupported,
+				format!("compiled programs don't support {what}"),
+			)
+			.into());
+		}
+
+		contain(settings.contain_panics, "native", || {
+			let mut io = Io {
+				stdin,
+				stdout,
+				output: Vec::with_capacity(CHUNK),
+				should_flush: settings.should_flush,
+				eof: settings.eof,
+				quit_on_eof: settings.quit_on_eof,
+				error: None,
+			};
+
+			// SAFETY: the code only touches cells within the tape, which is as long as it was
+			// compiled for, and the pointer stays on it
+			let status =
+				unsafe { (self.code)(bf.tape.as_mut_ptr().cast(), &mut bf.pointer, &mut io) };
+			bf.touched = bf.tape.len();
+
+			if let Some(e) = io.error.take() {
+				return Err(e.into());
+			}
+			io.flush()?;
+			debug_assert!(matches!(status, FINISHED | QUIT_ON_EOF));
+
+			Ok(RunSummary::default())
+		})
+	}
+}
+
+impl Drop for JitProgram {
+	fn drop(&mut self) {
+		if let Some(module) = self.module.take() {
+			// SAFETY: the code can't be called anymore, since it's only reachable through `self`
+			unsafe { module.free_memory() };
+		}
+	}
+}
+
+/// Signature of a compiled hot loop: the tape and the pointer, which is updated once it returns,
+/// returning the number of ops that ran.
+type CompiledLoop = unsafe extern "C" fn(*mut u8, *mut usize) -> u64;
+
+/// A hot loop of the tiered engine compiled to native code, for loops that can't be specialized
+/// into closures, like ones with nested loops, see
+/// [`RuntimeSettings::specialize_after`].
+pub(crate) struct NativeLoop {
+	/// Owns the memory the code lives in, freed when the loop is dropped.
+	module: Option<JITModule>,
+	code: CompiledLoop,
+	tape_length: usize,
+}
+
+impl NativeLoop {
+	/// Compile the loop whose first op is at `start` for a tape of `tape_length` cells, unless it
+	/// does IO, or isn't a loop that runs until its cell is zero.
+	pub fn new(ops: &[Op], start: usize, tape_length: usize) -> Option<Self> {
+		let end = match ops[start] {
+			Op::JumpIfZero(end) | Op::JumpIfZeroAdd(end, _) => end as usize,
+			_ => return None,
+		};
+		let body = &ops[start..end];
+		check(body, false).ok()?;
+
+		let mut module = JITModule::new(JITBuilder::with_isa(
+			isa(false).ok()?,
+			default_libcall_names(),
+		));
+		let id = translate(&mut module, body, tape_length, Runtime::Steps, None).ok()?;
+		module.finalize_definitions().ok()?;
+
+		// SAFETY: the function was defined with the signature of `CompiledLoop`
+		let code =
+			unsafe { mem::transmute::<*const u8, CompiledLoop>(module.get_finalized_function(id)) };
+
+		Some(Self {
+			module: Some(module),
+			code,
+			tape_length,
+		})
+	}
+
+	/// Run the loop until it's left, after its start found the current cell not to be zero,
+	/// returning the number of ops the interpreter would have dispatched.
+	///
+	/// # Panics
+	///
+	/// If the tape isn't as long as the one the loop was compiled for, or the pointer is off it.
+	pub fn run(&self, tape: &mut [Wrapping<u8>], pointer: &mut usize) -> u64 {
+		assert_eq!(
+			self.tape_length,
+			tape.len(),
+			"the loop was compiled for another tape length"
+		);
+
+		assert!(*pointer < tape.len(), "the pointer is off the tape");
+
+		// SAFETY: the code only touches cells within the tape, which is as long as it was
+		// compiled for, and the pointer stays on it
+		let steps = unsafe { (self.code)(tape.as_mut_ptr().cast(), pointer) };
+
+		// NOTE: the interpreter counted the start of the loop already
+		steps - 1
+	}
+}
+
+impl Drop for NativeLoop {
+	fn drop(&mut self) {
+		if let Some(module) = self.module.take() {
+			// SAFETY: the code can't be called anymore, since it's only reachable through `self`
+			unsafe { module.free_memory() };
+		}
+	}
+}
+
+/// Compile `bytecode` into an object file with a C `main` function that runs it on a tape of
+/// `tape_length` cells, reading input with `getchar` and printing output with `putchar`, so a C
+/// compiler can link it into an executable of its own: `cc program.o -o program`.
+///
+/// Cells are set to what `eof` says once input ends. The limits of [`JitProgram::compile`]
+/// apply.
+///
+/// # Errors
+///
+/// Same as [`JitProgram::compile`].
+///
+/// # Panics
+///
+/// If `tape_length` is zero.
+#[cfg(feature = "aot")]
+pub fn compile_object(
+	bytecode: &Bytecode,
+	tape_length: usize,
+	eof: Eof,
+) -> Result<Vec<u8>, JitError> {
+	use cranelift_object::{ObjectBuilder, ObjectModule};
+
+	assert!(tape_length > 0, "the tape has no cells");
+	check(bytecode.ops(), true)?;
+
+	let builder =
+		ObjectBuilder::new(isa(true)?, "brainfuck", default_libcall_names()).map_err(codegen)?;
+	let mut module = ObjectModule::new(builder);
+	translate(
+		&mut module,
+		bytecode.ops(),
+		tape_length,
+		Runtime::Libc(eof),
+		None,
+	)?;
+
+	module.finish().emit().map_err(codegen)
+}
+
+/// The code [`JitProgram::compile`] generates, for seeing what Cranelift made of a program.
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct Listing {
+	/// The function in Cranelift IR, as it's handed to Cranelift.
+	pub clif: String,
+	/// The machine instructions Cranelift lowered the function to, before they're encoded.
+	pub asm: String,
+}
+
+impl Listing {
+	/// List the code [`JitProgram::compile`] generates for `bytecode` on a tape of `tape_length`
+	/// cells, without running it.
+	///
+	/// # Errors
+	///
+	/// Same as [`JitProgram::compile`].
+	///
+	/// # Panics
+	///
+	/// If `tape_length` is zero.
+	pub fn of(bytecode: &Bytecode, tape_length: usize) -> Result<Self, JitError> {
+		assert!(tape_length > 0, "the tape has no cells");
+		check(bytecode.ops(), true)?;
+
+		// NOTE: the code is never finalized, so the callbacks aren't needed, and nothing is left
+		// to free
+		let mut module = JITModule::new(JITBuilder::with_isa(isa(false)?, default_libcall_names()));
+		let mut listing = Self::default();
+		translate(
+			&mut module,
+			bytecode.ops(),
+			tape_length,
+			Runtime::Callbacks,
+			Some(&mut listing),
+		)?;
+
+		Ok(listing)
+	}
+}
+
+/// Make sure that every op of `ops` can be compiled, with IO or without.
+fn check(ops: &[Op], io: bool) -> Result<(), JitError> {
+	match ops.iter().find(|op| match op {
+		Op::Extension(_) => true,
+		Op::Print | Op::AddPrint(_) | Op::Read => !io,
+		_ => false,
+	}) {
+		Some(&op) => Err(JitError::Unsupported(op)),
+		None => Ok(()),
+	}
+}
+
+/// An error compiling a program with [`JitProgram::compile`].
+#[derive(Debug, Clone, PartialEq, Eq, Error)]
+pub enum JitError {
+	/// The bytecode has an op the compiler doesn't handle
+	#[error("`{0}` can't be compiled to native code")]
+	Unsupported(Op),
+	/// Cranelift failed
+	#[error("Cranelift failed to compile the program: {0}")]
+	Codegen(String),
+}
+
+// NOTE: Cranelift's errors are large, and only their messages are of use
+fn codegen(error: impl std::fmt::Display) -> JitError {
+	JitError::Codegen(error.to_string())
+}
+
+/// The IO of a running program, which the compiled code calls back into.
+struct Io<'a> {
+	stdin: &'a mut dyn Read,
+	stdout: &'a mut dyn Write,
+	output: Vec<u8>,
+	should_flush: bool,
+	eof: Eof,
+	quit_on_eof: bool,
+	/// The error that made a callback return [`FAILED`].
+	error: Option<io::Error>,
+}
+
+impl Io<'_> {
+	fn flush(&mut self) -> io::Result<()> {
+		self.stdout.write_all(&self.output)?;
+		self.output.clear();
+		self.stdout.flush()
+	}
+}
+
+/// Print `byte`, returning [`FINISHED`], or [`FAILED`] if writing failed.
+extern "C" fn print(io: *mut Io<'_>, byte: u8) -> i32 {
+	// SAFETY: the compiled code passes on the pointer it was called with
+	let io = unsafe { &mut *io };
+
+	io.output.push(byte);
+	if io.should_flush || io.output.len() >= CHUNK {
+		if let Err(e) = io.flush() {
+			io.error = Some(e);
+			return FAILED;
+		}
+	}
+
+	FINISHED
+}
+
+/// Read a byte of input into `cell`, returning [`FINISHED`], [`QUIT_ON_EOF`] if the program ends
+/// because input ended, or [`FAILED`] if reading failed.
+extern "C" fn read(io: *mut Io<'_>, cell: *mut u8) -> i32 {
+	// SAFETY: the compiled code passes on the pointer it was called with, and the current cell
+	let (io, cell) = unsafe { (&mut *io, &mut *cell) };
+
+	// NOTE: programs often print a prompt before reading, which has to show up first
+	if let Err(e) = io.flush() {
+		io.error = Some(e);
+		return FAILED;
+	}
+
+	let mut byte = 0;
+	loop {
+		match io.stdin.read(std::slice::from_mut(&mut byte)) {
+			Ok(0) => {
+				match io.eof {
+					Eof::Zero => *cell = 0,
+					Eof::MinusOne => *cell = u8::MAX,
+					Eof::Unchanged => {}
+				}
+				// NOTE: like the engine, the cell is set before the program ends
+				return if io.quit_on_eof {
+					QUIT_ON_EOF
+				} else {
+					FINISHED
+				};
+			}
+			Ok(_) => {
+				*cell = byte;
+				return FINISHED;
+			}
+			Err(e) if e.kind() == ErrorKind::Interrupted => {}
+			Err(e) => {
+				io.error = Some(e);
+				return FAILED;
+			}
+		}
+	}
+}
+
+/// Define a function in `module` that runs `ops` on a tape of `tape_length` cells, doing IO like
+/// `runtime` does, and fill `listing` with its code, if given.
+fn translate<M: Module>(
+	module: &mut M,
+	ops: &[Op],
+	tape_length: usize,
+	runtime: Runtime,
+	mut listing: Option<&mut Listing>,
+) -> Result<FuncId, JitError> {
+	let pointer_type = module.target_config().pointer_type();
+
+	let mut context = module.make_context();
+	let (name, params, returns) = match runtime {
+		Runtime::Callbacks => ("program", 3, types::I32),
+		Runtime::Libc(_) => ("main", 0, types::I32),
+		Runtime::Steps => ("hot_loop", 2, types::I64),
+	};
+	for _ in 0..params {
+		context
+			.func
+			.signature
+			.params
+			.push(AbiParam::new(pointer_type));
+	}
+	context.func.signature.returns.push(AbiParam::new(returns));
+	let id = module
+		.declare_function(name, Linkage::Export, &context.func.signature)
+		.map_err(codegen)?;
+
+	let mut builder_context = FunctionBuilderContext::new();
+	let mut b = FunctionBuilder::new(&mut context.func, &mut builder_context);
+
+	let entry = b.create_block();
+	b.append_block_params_for_function_params(entry);
+	b.switch_to_block(entry);
+	b.seal_block(entry);
+
+	let pointer = Variable::from_u32(0);
+	b.declare_var(pointer, pointer_type);
+
+	let (tape, slot, calls) = match runtime {
+		Runtime::Callbacks => {
+			let print = import(module, &mut b, "bf_print", &[pointer_type, types::I8])?;
+			let read = import(module, &mut b, "bf_read", &[pointer_type, pointer_type])?;
+
+			let [tape, slot, io] = b.block_params(entry)[..] else {
+				unreachable!("the function has three parameters")
+			};
+			let start = b.ins().load(pointer_type, MemFlags::trusted(), slot, 0);
+			b.def_var(pointer, start);
+
+			(tape, Some(slot), Calls::Callbacks { io, print, read })
+		}
+		Runtime::Libc(eof) => {
+			let putchar = import(module, &mut b, "putchar", &[types::I32])?;
+			let getchar = import(module, &mut b, "getchar", &[])?;
+
+			// NOTE: programs that run on their own keep the tape in a zeroed data section
+			let data = module
+				.declare_data("bf_tape", Linkage::Local, true, false)
+				.map_err(codegen)?;
+			let mut description = DataDescription::new();
+			description.define_zeroinit(tape_length);
+			module.define_data(data, &description).map_err(codegen)?;
+
+			let global = module.declare_data_in_func(data, b.func);
+			let tape = b.ins().global_value(pointer_type, global);
+			let start = b.ins().iconst(pointer_type, 0);
+			b.def_var(pointer, start);
+
+			(
+				tape,
+				None,
+				Calls::Libc {
+					eof,
+					putchar,
+					getchar,
+				},
+			)
+		}
+		Runtime::Steps => {
+			let [tape, slot] = b.block_params(entry)[..] else {
+				unreachable!("the function has two parameters")
+			};
+			let start = b.ins().load(pointer_type, MemFlags::trusted(), slot, 0);
+			b.def_var(pointer, start);
+
+			(tape, Some(slot), Calls::None)
+		}
+	};
+
+	let steps = matches!(runtime, Runtime::Steps).then(|| {
+		let steps = Variable::from_u32(1);
+		b.declare_var(steps, types::I64);
+		let zero = b.ins().iconst(types::I64, 0);
+		b.def_var(steps, zero);
+		steps
+	});
+
+	// NOTE: repeat loops can't be nested, so a single counter is enough. On a tape too short to
+	// count on, they check the cell like plain loops
+	let counting = tape_length >= counting_tape_length(ops);
+	let repetitions = Variable::from_u32(2);
+	b.declare_var(repetitions, types::I8);
+
+	// NOTE: every way out stores the pointer back first
+	let exit = b.create_block();
+	b.append_block_param(exit, types::I32);
+
+	let compiler = Compiler {
+		tape,
+		pointer,
+		steps,
+		tape_length,
+	};
+	// NOTE: the block the end of a loop jumps back to, and the one after the loop
+	let mut loops: Vec<(Block, Block)> = vec![];
+
+	for &op in ops {
+		// NOTE: the start of these loops runs again on every iteration, so it's counted there
+		if !matches!(op, Op::JumpIfZeroAdd(..)) {
+			compiler.step(&mut b);
+		}
+
+		match op {
+			Op::Inc => compiler.add(&mut b, 0, 1),
+			Op::Dec => compiler.add(&mut b, 0, u8::MAX),
+			Op::Add(delta) => compiler.add(&mut b, 0, delta),
+			Op::AddAt(offset, delta) => compiler.add(&mut b, offset, delta),
+			Op::Next => compiler.shift(&mut b, 1),
+			Op::Prev => compiler.shift(&mut b, -1),
+			Op::Move(offset) => compiler.shift(&mut b, offset),
+			Op::Clear => compiler.set(&mut b, 0),
+			Op::Set(value) => compiler.set(&mut b, value),
+			Op::Fill(offset, value) => {
+				// NOTE: the cells may wrap around an end of the tape, so they're stored one by
+				// one, which Cranelift turns into a few wide stores at most
+				for cell in 0..=offset.unsigned_abs() as i32 {
+					let address = compiler.address(&mut b, cell * offset.signum());
+					let value = b.ins().iconst(types::I8, i64::from(value));
+					b.ins().store(MemFlags::trusted(), value, address, 0);
+				}
+				compiler.shift(&mut b, offset);
+			}
+			Op::AddMove(delta, offset) => {
+				compiler.add(&mut b, 0, delta);
+				compiler.shift(&mut b, offset);
+			}
+			Op::ClearMove(offset) => {
+				compiler.set(&mut b, 0);
+				compiler.shift(&mut b, offset);
+			}
+			Op::MoveAdd(offset, delta) => {
+				compiler.shift(&mut b, offset);
+				compiler.add(&mut b, 0, delta);
+			}
+			Op::MulAdd(offset, factor) => {
+				let value = compiler.load(&mut b, 0);
+				let product = b.ins().imul_imm(value, i64::from(factor));
+				let target = compiler.address(&mut b, offset);
+				let cell = b.ins().load(types::I8, MemFlags::trusted(), target, 0);
+				let sum = b.ins().iadd(cell, product);
+				b.ins().store(MemFlags::trusted(), sum, target, 0);
+			}
+			Op::ScanRight | Op::ScanLeft => {
+				let header = b.create_block();
+				let body = b.create_block();
+				let after = b.create_block();
+
+				b.ins().jump(header, &[]);
+				b.switch_to_block(header);
+				let cell = compiler.load(&mut b, 0);
+				b.ins().brif(cell, body, &[], after, &[]);
+
+				b.switch_to_block(body);
+				b.seal_block(body);
+				compiler.shift(&mut b, if op == Op::ScanRight { 1 } else { -1 });
+				b.ins().jump(header, &[]);
+				b.seal_block(header);
+
+				b.switch_to_block(after);
+				b.seal_block(after);
+			}
+			Op::JumpIfZero(_) => {
+				let body = b.create_block();
+				let after = b.create_block();
+
+				let cell = compiler.load(&mut b, 0);
+				b.ins().brif(cell, body, &[], after, &[]);
+				b.switch_to_block(body);
+				loops.push((body, after));
+			}
+			Op::JumpIfZeroAdd(_, delta) => {
+				let header = b.create_block();
+				let body = b.create_block();
+				let after = b.create_block();
+
+				b.ins().jump(header, &[]);
+				b.switch_to_block(header);
+				compiler.step(&mut b);
+				let cell = compiler.load(&mut b, 0);
+				b.ins().brif(cell, body, &[], after, &[]);
+
+				b.switch_to_block(body);
+				b.seal_block(body);
+				compiler.add(&mut b, 0, delta);
+				loops.push((header, after));
+			}
+			Op::RepeatN(_, factor) => {
+				let body = b.create_block();
+				let after = b.create_block();
+
+				let cell = compiler.load(&mut b, 0);
+				let count = b.ins().imul_imm(cell, i64::from(factor));
+				b.def_var(repetitions, count);
+				b.ins().brif(count, body, &[], after, &[]);
+				b.switch_to_block(body);
+				loops.push((body, after));
+			}
+			Op::JumpIfNonZero(_) | Op::RepeatEnd(_) => {
+				let (back, after) = loops.pop().expect("loops are balanced");
+
+				let condition = if matches!(op, Op::JumpIfNonZero(_)) || !counting {
+					compiler.load(&mut b, 0)
+				} else {
+					let left = b.use_var(repetitions);
+					let left = b.ins().iadd_imm(left, -1);
+					b.def_var(repetitions, left);
+					left
+				};
+				b.ins().brif(condition, back, &[], after, &[]);
+				b.seal_block(back);
+				b.switch_to_block(after);
+				b.seal_block(after);
+			}
+			Op::Print | Op::AddPrint(_) => {
+				if let Op::AddPrint(delta) = op {
+					compiler.add(&mut b, 0, delta);
+				}
+
+				let cell = compiler.load(&mut b, 0);
+				match calls {
+					Calls::Callbacks { io, print, .. } => {
+						let call = b.ins().call(print, &[io, cell]);
+						let status = b.inst_results(call)[0];
+
+						let next = b.create_block();
+						b.ins().brif(status, exit, &[status], next, &[]);
+						b.switch_to_block(next);
+						b.seal_block(next);
+					}
+					Calls::Libc { putchar, .. } => {
+						let character = b.ins().uextend(types::I32, cell);
+						b.ins().call(putchar, &[character]);
+					}
+					Calls::None => unreachable!("checked before compiling"),
+				}
+			}
+			Op::Read => {
+				let address = compiler.address(&mut b, 0);
+				match calls {
+					Calls::Callbacks { io, read, .. } => {
+						let call = b.ins().call(read, &[io, address]);
+						let status = b.inst_results(call)[0];
+
+						let next = b.create_block();
+						b.ins().brif(status, exit, &[status], next, &[]);
+						b.switch_to_block(next);
+						b.seal_block(next);
+					}
+					Calls::Libc { eof, getchar, .. } => {
+						let call = b.ins().call(getchar, &[]);
+						let character = b.inst_results(call)[0];
+						// NOTE: `EOF` is negative, and -1 wherever it matters, which is 255 in a
+						// cell already
+						let ended = b.ins().icmp_imm(IntCC::SignedLessThan, character, 0);
+
+						let store = b.create_block();
+						let next = b.create_block();
+						b.append_block_param(store, types::I32);
+						match eof {
+							Eof::Zero => {
+								let zero = b.ins().iconst(types::I32, 0);
+								let value = b.ins().select(ended, zero, character);
+								b.ins().jump(store, &[value]);
+							}
+							Eof::MinusOne => {
+								b.ins().jump(store, &[character]);
+							}
+							Eof::Unchanged => {
+								b.ins().brif(ended, next, &[], store, &[character]);
+							}
+						}
+
+						b.switch_to_block(store);
+						b.seal_block(store);
+						let value = b.block_params(store)[0];
+						let byte = b.ins().ireduce(types::I8, value);
+						b.ins().store(MemFlags::trusted(), byte, address, 0);
+						b.ins().jump(next, &[]);
+
+						b.switch_to_block(next);
+						b.seal_block(next);
+					}
+					Calls::None => unreachable!("checked before compiling"),
+				}
+			}
+			Op::Extension(_) => unreachable!("checked before compiling"),
+		}
+	}
+
+	let finished = b.ins().iconst(types::I32, i64::from(FINISHED));
+	b.ins().jump(exit, &[finished]);
+
+	b.switch_to_block(exit);
+	b.seal_block(exit);
+	if let Some(slot) = slot {
+		let end = b.use_var(pointer);
+		b.ins().store(MemFlags::trusted(), end, slot, 0);
+	}
+	let returned = match steps {
+		Some(steps) => b.use_var(steps),
+		None => b.block_params(exit)[0],
+	};
+	b.ins().return_(&[returned]);
+	b.finalize();
+
+	// NOTE: the IR is listed before it's compiled, since compiling optimizes it in place
+	if let Some(listing) = listing.as_deref_mut() {
+		listing.clif = context.func.display().to_string();
+		context.set_disasm(true);
+	}
+	module.define_function(id, &mut context).map_err(codegen)?;
+	if let Some(listing) = listing {
+		listing.asm = context
+			.compiled_code()
+			.and_then(|code| code.vcode.clone())
+			.unwrap_or_default();
+	}
+	module.clear_context(&mut context);
+
+	Ok(id)
+}
+
+/// The machine the crate runs on, with position-independent code for object files.
+fn isa(pic: bool) -> Result<OwnedTargetIsa, JitError> {
+	let mut flags = settings::builder();
+	flags.set("opt_level", "speed").map_err(codegen)?;
+	flags
+		.set("is_pic", if pic { "true" } else { "false" })
+		.map_err(codegen)?;
+
+	cranelift_native::builder()
+		.map_err(codegen)?
+		.finish(settings::Flags::new(flags))
+		.map_err(codegen)
+}
+
+/// Declare a C function called `name` that returns an `int`, to be called by the function `b`
+/// builds.
+fn import<M: Module>(
+	module: &mut M,
+	b: &mut FunctionBuilder<'_>,
+	name: &str,
+	params: &[Type],
+) -> Result<FuncRef, JitError> {
+	let mut signature = module.make_signature();
+	signature
+		.params
+		.extend(params.iter().map(|&param| AbiParam::new(param)));
+	signature.returns.push(AbiParam::new(types::I32));
+
+	let id = module
+		.declare_function(name, Linkage::Import, &signature)
+		.map_err(codegen)?;
+	Ok(module.declare_func_in_func(id, b.func))
+}
+
+/// What compiled code reads input from and prints output to.
+#[derive(Debug, Clone, Copy)]
+enum Runtime {
+	/// The callbacks of a [`JitProgram`], through the [`Io`] it runs with.
+	Callbacks,
+	/// `getchar` and `putchar` of the C library, for programs that run on their own, with what
+	/// cells are set to once input ends.
+	#[cfg_attr(not(feature = "aot"), allow(dead_code))]
+	Libc(Eof),
+	/// No IO at all, returning the number of ops the interpreter would have dispatched instead,
+	/// for the hot loops of [`NativeLoop`].
+	Steps,
+}
+
+/// The functions of a [`Runtime`], declared in the function being compiled.
+#[derive(Debug, Clone, Copy)]
+enum Calls {
+	Callbacks {
+		io: Value,
+		print: FuncRef,
+		read: FuncRef,
+	},
+	Libc {
+		eof: Eof,
+		putchar: FuncRef,
+		getchar: FuncRef,
+	},
+	None,
+}
+
+/// Emits the code for cells and the pointer.
+struct Compiler {
+	tape: Value,
+	pointer: Variable,
+	/// Counts the ops that ran, if the runtime asks for it.
+	steps: Option<Variable>,
+	tape_length: usize,
+}
+
+impl Compiler {
+	/// The index of the cell `offset` cells away from the current one, wrapping around the ends
+	/// of the tape.
+	fn index(&self, b: &mut FunctionBuilder<'_>, offset: i32) -> Value {
+		let pointer = b.use_var(self.pointer);
+
+		// NOTE: the tape length is known, so moving left becomes moving right by the rest of
+		// the tape, and the pointer wraps around at most once
+		let offset = i64::from(offset).rem_euclid(self.tape_length as i64);
+		if offset == 0 {
+			return pointer;
+		}
+
+		let moved = b.ins().iadd_imm(pointer, offset);
+		let wrapped = b.ins().iadd_imm(moved, -(self.tape_length as i64));
+		let past_end = b.ins().icmp_imm(
+			IntCC::UnsignedGreaterThanOrEqual,
+			moved,
+			self.tape_length as i64,
+		);
+		b.ins().select(past_end, wrapped, moved)
+	}
+
+	fn address(&self, b: &mut FunctionBuilder<'_>, offset: i32) -> Value {
+		let index = self.index(b, offset);
+		b.ins().iadd(self.tape, index)
+	}
+
+	fn load(&self, b: &mut FunctionBuilder<'_>, offset: i32) -> Value {
+		let address = self.address(b, offset);
+		b.ins().load(types::I8, MemFlags::trusted(), address, 0)
+	}
+
+	fn add(&self, b: &mut FunctionBuilder<'_>, offset: i32, delta: u8) {
+		let address = self.address(b, offset);
+		let cell = b.ins().load(types::I8, MemFlags::trusted(), address, 0);
+		let sum = b.ins().iadd_imm(cell, i64::from(delta));
+		b.ins().store(MemFlags::trusted(), sum, address, 0);
+	}
+
+	fn set(&self, b: &mut FunctionBuilder<'_>, value: u8) {
+		let address = self.address(b, 0);
+		let value = b.ins().iconst(types::I8, i64::from(value));
+		b.ins().store(MemFlags::trusted(), value, address, 0);
+	}
+
+	fn shift(&self, b: &mut FunctionBuilder<'_>, offset: i32) {
+		let index = self.index(b, offset);
+		b.def_var(self.pointer, index);
+	}
+
+	/// Count an op, if ops are counted.
+	fn step(&self, b: &mut FunctionBuilder<'_>) {
+		if let Some(steps) = self.steps {
+			let counted = b.use_var(steps);
+			let counted = b.ins().iadd_imm(counted, 1);
+			b.def_var(steps, counted);
+		}
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::{
+		bytecode::Fusion, extension::Extension, instruction::Instruction, program::Program,
+	};
+
+	#[test]
+	fn compiled_code_runs_like_the_engine() {
+		// NOTE: the tape is short, so the pointer wraps around both ends
+		let program = Program::parse(",[>+>--<<[-<<+>>]>[<]<,.]<<<-.>[>]").unwrap();
+		let bytecode = Bytecode::compile(&program.instructions)
+			.collapse()
+			.multiply_loops()
+			.address_offsets();
+		let compiled = JitProgram::compile(&bytecode, 5).unwrap();
+
+		for (eof, quit_on_eof) in [(Eof::Zero, false), (Eof::MinusOne, true)] {
+			let settings = RuntimeSettings {
+				eof,
+				quit_on_eof,
+				..Default::default()
+			};
+
+			let mut engine = Engine::new(5);
+			let mut engine_output = vec![];
+			engine
+				.run(
+					&program.instructions,
+					&mut b"jit".as_slice(),
+					&mut engine_output,
+					settings.clone(),
+				)
+				.unwrap();
+
+			let mut native = Engine::new(5);
+			let mut native_output = vec![];
+			compiled
+				.run(
+					&mut native,
+					&mut b"jit".as_slice(),
+					&mut native_output,
+					&settings,
+				)
+				.unwrap();
+
+			assert_eq!(engine_output, native_output, "{eof:?}");
+			assert_eq!(engine.tape, native.tape, "{eof:?}");
+			assert_eq!(engine.pointer, native.pointer, "{eof:?}");
+		}
+
+		let time = Program::parse_with_extensions("+~", &[Extension::Time]).unwrap();
+		assert_eq!(
+			Err(JitError::Unsupported(Op::Extension(Extension::Time))),
+			JitProgram::compile(&Bytecode::compile(&time.instructions), 5).map(|_| ())
+		);
+	}
+
+	#[test]
+	fn counted_loops_check_the_cell_on_tiny_tapes() {
+		// NOTE: on a single cell, the read is into the condition cell
+		let program = Program::parse("-[++>,<-]").unwrap();
+		let bytecode = Bytecode::compile(&program.instructions)
+			.collapse()
+			.count_loops();
+		assert!(bytecode
+			.ops()
+			.iter()
+			.any(|op| matches!(op, Op::RepeatN(..))));
+
+		for length in [1, 2] {
+			let settings = RuntimeSettings {
+				quit_on_eof: true,
+				..Default::default()
+			};
+
+			let mut engine = Engine::new(length);
+			engine
+				.run(
+					&program.instructions,
+					&mut b"a".as_slice(),
+					&mut vec![],
+					settings.clone(),
+				)
+				.unwrap();
+
+			let mut native = Engine::new(length);
+			JitProgram::compile(&bytecode, length)
+				.unwrap()
+				.run(&mut native, &mut b"a".as_slice(), &mut vec![], &settings)
+				.unwrap();
+
+			assert_eq!(engine.tape, native.tape, "{length}");
+			assert_eq!(engine.pointer, native.pointer, "{length}");
+		}
+	}
+
+	#[test]
+	fn listings_show_the_generated_code() {
+		let program = Program::parse("+[->+<].").unwrap();
+		let bytecode = Bytecode::compile(&program.instructions)
+			.collapse()
+			.multiply_loops();
+		let listing = Listing::of(&bytecode, 30_000).unwrap();
+
+		assert!(listing.clif.starts_with("function "));
+		assert!(listing.clif.contains("iadd_imm"));
+		assert!(!listing.asm.is_empty());
+	}
+
+	#[test]
+	fn pointers_off_the_tape_are_refused() {
+		let compiled = JitProgram::compile(&Bytecode::compile(&[Instruction::Inc]), 5).unwrap();
+
+		let mut bf = Engine::new(5);
+		bf.pointer = 5;
+		assert!(matches!(
+			compiled.run(
+				&mut bf,
+				&mut [].as_slice(),
+				&mut vec![],
+				&RuntimeSettings::default()
+			),
+			Err(RunError::InternalError {
+				engine: "native",
+				..
+			})
+		));
+		assert_eq!(Engine::new(5).tape, bf.tape);
+	}
+
+	#[test]
+	fn panics_are_contained() {
+		/// Panics once what was printed is written out, after the compiled code returned.
+		struct Panicking;
+
+		impl Write for Panicking {
+			fn write(&mut self, _buf: &[u8]) -> io::Result<usize> {
+				panic!("bug in a writer")
+			}
+
+			fn flush(&mut self) -> io::Result<()> {
+				Ok(())
+			}
+		}
+
+		let compiled = JitProgram::compile(
+			&Bytecode::compile(&[Instruction::Inc, Instruction::Print]),
+			5,
+		)
+		.unwrap();
+		let settings = RuntimeSettings {
+			should_flush: false,
+			..Default::default()
+		};
+
+		assert!(matches!(
+			compiled.run(&mut Engine::new(5), &mut [].as_slice(), &mut Panicking, &settings),
+			Err(RunError::InternalError { engine: "native", message }) if message == "bug in a writer"
+		));
+	}
+
+	#[test]
+	fn hot_loops_count_the_same_steps_natively() {
+		use crate::executor::{Executor, FusedVm};
+
+		// NOTE: the outer loop has loops nested in it, so it can't become a closure
+		let program =
+			Program::parse("+++++[->+++++++[--->+<]>[>+<-]>[<]>>[-<+>]<<<]>>>[-<+>]<++.").unwrap();
+
+		let mut fused = FusedVm::default();
+		let mut tiered = FusedVm {
+			specialize_after: Some(1),
+			..Default::default()
+		};
+		let mut outputs = vec![];
+		let mut summaries = vec![];
+		for vm in [&mut fused, &mut tiered] {
+			let mut output = vec![];
+			summaries.push(
+				vm.run_program(
+					&program,
+					&mut [].as_slice(),
+					&mut output,
+					RuntimeSettings::default(),
+				)
+				.unwrap(),
+			);
+			outputs.push(output);
+		}
+
+		assert_eq!(outputs[0], outputs[1]);
+		assert_eq!(summaries[0].steps, summaries[1].steps);
+		assert_eq!(fused.engine, tiered.engine);
+
+		let bytecode = Bytecode::compile(&program.instructions)
+			.collapse()
+			.multiply_loops()
+			.count_loops()
+			.address_offsets()
+			.fuse(Fusion::ALL);
+		let start = bytecode
+			.ops()
+			.iter()
+			.position(|op| matches!(op, Op::JumpIfZeroAdd(..)))
+			.unwrap();
+		assert!(NativeLoop::new(bytecode.ops(), start, 30_000).is_some());
+	}
+
+	#[test]
+	#[cfg(all(feature = "aot", unix))]
+	fn object_files_link_into_executables() {
+		use std::process::{Command, Stdio};
+
+		let program = Program::parse(",[.,]++++++++++.").unwrap();
+		let bytecode = Bytecode::compile(&program.instructions).collapse();
+		let object = compile_object(&bytecode, 30_000, Eof::Zero).unwrap();
+
+		let directory =
+			std::env::temp_dir().join(format!("brainfuck-rs-aot-{}", std::process::id()));
+		std::fs::create_dir_all(&directory).unwrap();
+		std::fs::write(directory.join("cat.o"), object).unwrap();
+
+		// NOTE: nothing to check without a C compiler to link with
+		let Ok(status) = Command::new("cc")
+			.arg("cat.o")
+			.args(["-o", "cat"])
+			.current_dir(&directory)
+			.status()
+		else {
+			return;
+		};
+		assert!(status.success());
+
+		let mut child = Command::new(directory.join("cat"))
+			.stdin(Stdio::piped())
+			.stdout(Stdio::piped())
+			.spawn()
+			.unwrap();
+		child.stdin.take().unwrap().write_all(b"aot").unwrap();
+		let output = child.wait_with_output().unwrap();
+		std::fs::remove_dir_all(&directory).unwrap();
+
+		assert!(output.status.success());
+		assert_eq!(b"aot\n", output.stdout.as_slice());
+	}
+}
@@ -0,0 +1,132 @@
+use std::{collections::BTreeMap, fmt};
+
+use serde::{Deserialize, Serialize};
+
+/// Names of cells, for showing them in tape dumps, hooks and narration instead of bare indices.
+///
+/// Programs with a planned memory layout can name their cells with directives in comments, one
+/// per cell, like `@cell 3 "counter"`, see [`CellLabels::parse`].
+///
+/// # Usage
+///
+/// ```
+/// # use brainfuck_rs::labels::CellLabels;
+/// let labels = CellLabels::parse("@cell 0 \"counter\"\n@cell 2 \"sum\"\n++++[>++<-]");
+///
+/// assert_eq!(Some("counter"), labels.get(0));
+/// assert_eq!("cell 2 (sum)", labels.name(2).to_string());
+/// assert_eq!("cell 1", labels.name(1).to_string());
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(transparent)]
+pub struct CellLabels(BTreeMap<usize, String>);
+
+impl CellLabels {
+	/// The directive that names a cell.
+	const DIRECTIVE: &'static str = "@cell";
+
+	/// Find the `@cell <index> "<label>"` directives in source code.
+	///
+	/// Text that only looks like a directive is left alone, as it's a comment either way. Since
+	/// directives sit in comments, labels shouldn't contain Brainfuck instructions, and they
+	/// can't be used along with the `exit` extension, which `@` is the symbol of.
+	pub fn parse(code: &str) -> Self {
+		let mut labels = Self::default();
+
+		for (offset, _) in code.match_indices(Self::DIRECTIVE) {
+			let rest = &code[offset + Self::DIRECTIVE.len()..];
+			let Some(rest) = rest.strip_prefix([' ', '\t']) else {
+				continue;
+			};
+			let rest = rest.trim_start_matches([' ', '\t']);
+
+			let digits = rest.len()
+				- rest
+					.trim_start_matches(|ch: char| ch.is_ascii_digit())
+					.len();
+			let Ok(index) = rest[..digits].parse() else {
+				continue;
+			};
+			let Some(rest) = rest[digits..]
+				.trim_start_matches([' ', '\t'])
+				.strip_prefix('"')
+			else {
+				continue;
+			};
+			let Some((label, _)) = rest
+				.split_once('"')
+				.filter(|(label, _)| !label.contains('\n'))
+			else {
+				continue;
+			};
+
+			labels.insert(index, label);
+		}
+
+		labels
+	}
+
+	/// Name the cell at `index`, replacing its label if it had one.
+	pub fn insert(&mut self, index: usize, label: impl Into<String>) {
+		self.0.insert(index, label.into());
+	}
+
+	/// Label of the cell at `index`, if it has one.
+	pub fn get(&self, index: usize) -> Option<&str> {
+		self.0.get(&index).map(String::as_str)
+	}
+
+	/// Whether no cell has a label.
+	pub fn is_empty(&self) -> bool {
+		self.0.is_empty()
+	}
+
+	/// Every label, by index of the cell.
+	pub fn iter(&self) -> impl Iterator<Item = (usize, &str)> {
+		self.0.iter().map(|(&index, label)| (index, label.as_str()))
+	}
+
+	/// The cell at `index`, for telling it in prose, like `cell 3 (counter)`.
+	pub fn name(&self, index: usize) -> CellName<'_> {
+		CellName {
+			index,
+			label: self.get(index),
+		}
+	}
+}
+
+/// A cell as told in prose, see [`CellLabels::name`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct CellName<'a> {
+	index: usize,
+	label: Option<&'a str>,
+}
+
+impl fmt::Display for CellName<'_> {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		write!(f, "cell {}", self.index)?;
+
+		if let Some(label) = self.label {
+			write!(f, " ({label})")?;
+		}
+
+		Ok(())
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn malformed_directives_are_comments() {
+		let labels = CellLabels::parse(
+			"@cell 1 \"one\" @cell 1 \"uno\"\n@cells 2 \"x\"\n@cell x \"y\"\n@cell 3 \"open\n\"\n@cell\t4\t\"tab\"",
+		);
+
+		assert_eq!(
+			vec![(1, "uno"), (4, "tab")],
+			labels.iter().collect::<Vec<_>>()
+		);
+	}
+}
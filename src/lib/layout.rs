@@ -0,0 +1,248 @@
+use std::collections::BTreeMap;
+
+use thiserror::Error;
+
+use crate::{instruction::Instruction, labels::CellLabels};
+
+/// Where the named cells of a generated program go on the tape, so pieces of code that each ask
+/// for cells of their own, like [`stdlib`](`crate::stdlib`) snippets, can be put together without
+/// stepping on each other's cells.
+///
+/// Cells are either pinned to an offset, or allocated at the lowest offset that's free. Snippets
+/// get blocks of consecutive cells, as many as their layout says, and cells that are done with
+/// can be freed for others to reuse. Offsets are relative to the cell the program starts at.
+///
+/// # Usage
+///
+/// ```
+/// # use brainfuck_rs::{
+/// #   engine::{Engine, RuntimeSettings},
+/// #   layout::Layout,
+/// #   stdlib,
+/// # };
+/// let mut layout = Layout::new();
+/// layout.pin("flag", 0).unwrap();
+/// // NOTE: `print_number` works on 10 cells, the first 4 of which `multiply` works on
+/// let math = layout.alloc_block("math", 10).unwrap();
+///
+/// let mut program = layout.go(0, "math").unwrap();
+/// program.extend(stdlib::snippet("++++++>+++++++<"));
+/// program.extend(stdlib::multiply());
+/// program.extend(stdlib::print_number());
+///
+/// let mut bf = Engine::default();
+/// bf.labels = layout.labels();
+/// let mut output = vec![];
+/// bf.run(&program, &mut [].as_slice(), &mut output, RuntimeSettings::default())
+///     .unwrap();
+///
+/// assert_eq!(1, math);
+/// assert_eq!(b"42", output.as_slice());
+/// assert_eq!(Some("math[1]"), bf.labels.get(2));
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct Layout {
+	/// Offset and number of cells of everything placed, by name.
+	cells: BTreeMap<String, (usize, usize)>,
+	/// Name of what every taken offset belongs to.
+	owners: BTreeMap<usize, String>,
+}
+
+/// Error planning a [`Layout`].
+#[derive(Debug, Clone, PartialEq, Eq, Error)]
+pub enum LayoutError {
+	/// The name is taken already
+	#[error("`{name}` is already at cell {offset}")]
+	Duplicate {
+		/// The name.
+		name: String,
+		/// Where what has the name is.
+		offset: usize,
+	},
+	/// Two names want the same cell
+	#[error("`{name}` wants cell {offset}, which belongs to `{owner}`")]
+	Conflict {
+		/// The name that wants the cell.
+		name: String,
+		/// The cell.
+		offset: usize,
+		/// The name the cell belongs to.
+		owner: String,
+	},
+	/// Nothing has the name
+	#[error("there's no cell named `{0}`")]
+	Unknown(String),
+}
+
+impl Layout {
+	/// A layout without cells.
+	pub fn new() -> Self {
+		Self::default()
+	}
+
+	/// Place the cell `name` at `offset`, like a cell the program's input or output depends on.
+	///
+	/// # Errors
+	///
+	/// Fails if the name or the cell are taken.
+	pub fn pin(&mut self, name: &str, offset: usize) -> Result<usize, LayoutError> {
+		self.place(name, offset, 1)
+	}
+
+	/// Place the cell `name` at the lowest offset that's free.
+	///
+	/// # Errors
+	///
+	/// Fails if the name is taken.
+	pub fn alloc(&mut self, name: &str) -> Result<usize, LayoutError> {
+		self.alloc_block(name, 1)
+	}
+
+	/// Place `width` consecutive cells called `name` at the lowest offset where they're all free,
+	/// for a snippet that works on that many cells. The block is at the offset of its first cell,
+	/// and its cells are labelled `name[0]`, `name[1]` and so on.
+	///
+	/// # Errors
+	///
+	/// Fails if the name is taken.
+	pub fn alloc_block(&mut self, name: &str, width: usize) -> Result<usize, LayoutError> {
+		let mut offset = 0;
+		// NOTE: jumps past the last taken cell in the way, until there's none
+		while let Some(taken) = self.owners.range(offset..offset + width).next_back() {
+			offset = taken.0 + 1;
+		}
+
+		self.place(name, offset, width)
+	}
+
+	/// Free what `name` took, so its cells can be reused. Reused cells must be cleared first, since
+	/// snippets expect the cells they work on to be zero.
+	///
+	/// # Errors
+	///
+	/// Fails if nothing has the name.
+	pub fn free(&mut self, name: &str) -> Result<(), LayoutError> {
+		let (offset, width) = self
+			.cells
+			.remove(name)
+			.ok_or_else(|| LayoutError::Unknown(name.to_owned()))?;
+
+		for cell in offset..offset + width {
+			self.owners.remove(&cell);
+		}
+
+		Ok(())
+	}
+
+	/// Offset of the cell or the first cell of the block called `name`.
+	///
+	/// # Errors
+	///
+	/// Fails if nothing has the name.
+	pub fn offset(&self, name: &str) -> Result<usize, LayoutError> {
+		self.cells
+			.get(name)
+			.map(|&(offset, _)| offset)
+			.ok_or_else(|| LayoutError::Unknown(name.to_owned()))
+	}
+
+	/// Instructions that move the pointer from `pointer` to the cell called `name`.
+	///
+	/// # Errors
+	///
+	/// Fails if nothing has the name.
+	pub fn go(&self, pointer: usize, name: &str) -> Result<Vec<Instruction>, LayoutError> {
+		let offset = self.offset(name)?;
+		let instruction = if offset < pointer {
+			Instruction::Prev
+		} else {
+			Instruction::Next
+		};
+
+		Ok(vec![instruction; offset.abs_diff(pointer)])
+	}
+
+	/// Number of cells the layout takes up, from the first one to the last one taken.
+	pub fn len(&self) -> usize {
+		self.owners.keys().next_back().map_or(0, |&last| last + 1)
+	}
+
+	/// Whether no cell is taken.
+	pub fn is_empty(&self) -> bool {
+		self.owners.is_empty()
+	}
+
+	/// Labels of every cell taken, for [`Engine::labels`](`crate::engine::Engine::labels`).
+	pub fn labels(&self) -> CellLabels {
+		let mut labels = CellLabels::default();
+
+		for (name, &(offset, width)) in &self.cells {
+			if width == 1 {
+				labels.insert(offset, name.clone());
+			} else {
+				for index in 0..width {
+					labels.insert(offset + index, format!("{name}[{index}]"));
+				}
+			}
+		}
+
+		labels
+	}
+
+	fn place(&mut self, name: &str, offset: usize, width: usize) -> Result<usize, LayoutError> {
+		if let Some(&(offset, _)) = self.cells.get(name) {
+			return Err(LayoutError::Duplicate {
+				name: name.to_owned(),
+				offset,
+			});
+		}
+		if let Some((&taken, owner)) = self.owners.range(offset..offset + width).next() {
+			return Err(LayoutError::Conflict {
+				name: name.to_owned(),
+				offset: taken,
+				owner: owner.clone(),
+			});
+		}
+
+		self.cells.insert(name.to_owned(), (offset, width));
+		for cell in offset..offset + width {
+			self.owners.insert(cell, name.to_owned());
+		}
+
+		Ok(offset)
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn blocks_fit_between_taken_cells() {
+		let mut layout = Layout::new();
+
+		assert_eq!(Ok(0), layout.alloc("counter"));
+		assert_eq!(Ok(3), layout.pin("output", 3));
+		assert_eq!(Ok(1), layout.alloc_block("pair", 2));
+		assert_eq!(Ok(4), layout.alloc_block("copy", 3));
+		assert_eq!(
+			Err(LayoutError::Conflict {
+				name: "input".to_owned(),
+				offset: 5,
+				owner: "copy".to_owned(),
+			}),
+			layout.pin("input", 5)
+		);
+		assert_eq!(
+			Err(LayoutError::Duplicate {
+				name: "pair".to_owned(),
+				offset: 1,
+			}),
+			layout.alloc("pair")
+		);
+
+		layout.free("pair").unwrap();
+		assert_eq!(Ok(1), layout.alloc("temporary"));
+		assert_eq!(7, layout.len());
+	}
+}
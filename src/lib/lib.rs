@@ -43,13 +43,13 @@
 //! let mut input = BufReader::new(b"some input".as_slice());
 //! let mut output = BufWriter::new(vec![]);
 //! ```
-#[warn(
+#![warn(
 	clippy::use_self,
 	clippy::unnested_or_patterns,
 	clippy::unnecessary_box_returns,
 	clippy::uninlined_format_args,
 	clippy::unicode_not_nfc,
-	clippy::string_to_string,
+	clippy::implicit_clone,
 	clippy::string_add_assign,
 	clippy::string_add,
 	clippy::str_to_string,
@@ -62,11 +62,98 @@
 	clippy::cloned_instead_of_copied
 )]
 
+/// Static analysis of programs.
+pub mod analysis;
+/// Binary format for compiled and optimized programs.
+pub mod bfc;
+/// Compact binary format for pre-parsed programs.
+pub mod bfo;
+/// Stopping programs at places in their source code, when conditions on their cells hold.
+pub mod breakpoint;
+/// Programs flattened into a sequence of ops for faster execution.
+pub mod bytecode;
+/// Access to the outside world that programs must be granted.
+pub mod capability;
+/// Post-mortem dumps of runs that were aborted.
+pub mod crash;
+/// Debugging programs interactively, stopping at breakpoints and taking commands.
+pub mod debugger;
+/// Diagnostics reported while programs run.
+pub mod diagnostics;
+/// Programs compiled at build time, for embedding them without parsing them at startup.
+pub mod embed;
+/// Encoding program IO as text, for feeding and inspecting binary data on a terminal.
+pub mod encoding;
 /// The interpreter that can run Brainfuck programs.
 pub mod engine;
+/// Running programs on any backend through one interface.
+pub mod executor;
+/// Comparing the output of programs with what they're expected to print.
+pub mod expect;
+/// Plain-English narration of running programs, for learning how they work.
+pub mod explain;
+/// Nonstandard instructions that programs can opt into.
+pub mod extension;
+/// Measuring programs for code golf.
+pub mod golf;
+/// Callbacks for observing a running program.
+pub mod hooks;
+/// Access to the outside world for extension instructions.
+pub mod host;
+/// Finding loops that never end.
+#[cfg(feature = "monitoring")]
+mod idle;
 /// An AST that is fed to [`Engine`](`crate::engine::Engine`) in order to run Brainfuck programs.
 pub mod instruction;
+/// Compiling programs to native code with Cranelift.
+#[cfg(feature = "jit")]
+pub mod jit;
+/// Names of cells, for programs with a planned memory layout.
+pub mod labels;
+/// Planning where the named cells of generated programs go on the tape.
+pub mod layout;
+/// Where in a program the engine stopped.
+pub mod location;
+/// Optimization passes over programs, and presets of them.
+pub mod optimize;
+/// Pausing running programs at safe points, from other threads or signal handlers.
+pub mod pause;
+/// Chaining programs so the output of one is the input of the next.
+pub mod pipe;
+/// Handlers of custom instructions loaded from dynamic libraries, through a C ABI.
+#[cfg(feature = "plugins")]
+pub mod plugin;
+/// Reusing tapes across many runs of programs.
+pub mod pool;
+/// A parsed program that keeps track of where its instructions came from.
+pub mod program;
+/// Debugging running programs from other processes over TCP.
+pub mod remote;
+/// Peephole rewrite rules loaded at runtime.
+pub mod rules;
+/// Scripts that drive interactive programs by waiting for output and sending input.
+pub mod script;
+/// Searching for input that makes a program print a given output.
+pub mod search;
+/// Presets of semantics that match well-known interpreters.
+pub mod semantics;
+/// Hot loops compiled into closures.
+mod specialize;
+/// Reusable snippets of Brainfuck with documented cell layouts.
+pub mod stdlib;
+/// Bounded symbolic execution, for finding out which inputs lead where.
+pub mod symexec;
+/// Tracking which input bytes every output byte depends on.
+pub mod taint;
 /// Tokens used to generate an AST.
 pub mod token;
+/// Recording of program IO, with optional redaction.
+pub mod trace;
+/// Printing the instructions a program runs, picked by filters.
+pub mod tracer;
 /// Misc utilities
 pub mod utils;
+/// Restricted access to a running engine, for hooks and custom instructions.
+pub mod view;
+/// Step-by-step pages of runs, for clicking through them in course materials.
+pub mod walkthrough;
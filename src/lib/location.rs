@@ -0,0 +1,417 @@
+use std::{fmt, num::Wrapping};
+
+use crate::{bytecode::Op, instruction::Instruction, program::Program, token::Span};
+
+/// Number of instructions (or ops) that ran last which a [`Location`] remembers, see
+/// [`RuntimeSettings::remember_recent`](`crate::engine::RuntimeSettings::remember_recent`).
+pub const RECENT: usize = 8;
+
+/// Number of cells on either side of the pointer a [`Neighborhood`] has.
+pub const NEIGHBORHOOD_RADIUS: usize = 4;
+
+/// Where in a program the engine was when it stopped early, see
+/// [`Engine::location`](`crate::engine::Engine::location`) and
+/// [`RunError::location`](`crate::engine::RunError::location`).
+///
+/// For the tree engine, instructions are counted in pre-order, like [`Program::spans`], so
+/// locations in a program's instructions can be looked up in its spans. For bytecode, they're
+/// indices of ops in [`Bytecode::ops`](`crate::bytecode::Bytecode::ops`).
+///
+/// # Usage
+///
+/// ```
+/// # use brainfuck_rs::{engine::{Engine, RuntimeSettings}, program::Program};
+/// let program = Program::parse("+[>+[\n  +<-]]").unwrap();
+///
+/// let mut bf = Engine::default();
+/// let error = bf
+///     .run(
+///         &program.instructions,
+///         &mut [].as_slice(),
+///         &mut vec![],
+///         RuntimeSettings {
+///             budget: Some(5),
+///             remember_recent: true,
+///             ..Default::default()
+///         },
+///     )
+///     .unwrap_err();
+///
+/// let location = error.location().unwrap();
+/// assert_eq!(bf.location(), Some(location));
+/// assert_eq!("2:3", location.span(&program).unwrap().to_string());
+/// assert_eq!(
+///     vec!["1:2".to_owned(), "1:5".to_owned()],
+///     location
+///         .loop_spans(&program)
+///         .iter()
+///         .map(ToString::to_string)
+///         .collect::<Vec<_>>()
+/// );
+/// assert_eq!(vec![0, 1, 2, 3, 4], location.recent);
+/// assert_eq!("cells 0-5: 1 [1] 0 0 0 0", location.cells.to_string());
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct Location {
+	/// Index of the instruction (or op) that was about to run.
+	pub index: usize,
+	/// Indices of the loops around it, the outermost first.
+	pub loops: Vec<usize>,
+	/// Indices of the instructions (or ops) that ran right before it, the oldest first, at most
+	/// [`RECENT`] of them. Loops are there every time their condition was checked.
+	///
+	/// Empty unless the engine was asked to
+	/// [remember them](`crate::engine::RuntimeSettings::remember_recent`).
+	pub recent: Vec<usize>,
+	/// The cells around the pointer, as they were right before it.
+	pub cells: Neighborhood,
+}
+
+impl Location {
+	/// Location of `target` among `instructions`, found by its address.
+	pub(crate) fn of_instruction(
+		instructions: &[&Instruction],
+		target: &Instruction,
+	) -> Option<Self> {
+		fn walk<'a>(
+			instructions: impl IntoIterator<Item = &'a Instruction>,
+			target: &Instruction,
+			location: &mut Location,
+		) -> bool {
+			for instruction in instructions {
+				if std::ptr::eq(instruction, target) {
+					return true;
+				}
+				let index = location.index;
+				location.index += 1;
+
+				if let Instruction::Loop(body) = instruction {
+					location.loops.push(index);
+					if walk(body, target, location) {
+						return true;
+					}
+					location.loops.pop();
+				}
+			}
+
+			false
+		}
+
+		let mut location = Self::default();
+		walk(instructions.iter().copied(), target, &mut location).then_some(location)
+	}
+
+	/// Location of the op at `index`.
+	pub(crate) fn of_op(ops: &[Op], index: usize) -> Self {
+		// NOTE: loops are entered through their only forward jump, which lands right past their end
+		let loops = ops[..index]
+			.iter()
+			.enumerate()
+			.filter(|&(_, op)| match *op {
+				Op::JumpIfZero(end) | Op::JumpIfZeroAdd(end, _) | Op::RepeatN(end, _) => {
+					end as usize > index
+				}
+				_ => false,
+			})
+			.map(|(start, _)| start)
+			.collect();
+
+		Self {
+			index,
+			loops,
+			..Self::default()
+		}
+	}
+
+	/// Add what ran right before the instruction, from the addresses of `recent` instructions
+	/// among `instructions`, and the cells around the pointer on `tape`.
+	pub(crate) fn with_recent_instructions(
+		self,
+		instructions: &[&Instruction],
+		recent: &Recent,
+		tape: &[Wrapping<u8>],
+		pointer: usize,
+	) -> Self {
+		fn walk<'a>(
+			instructions: impl IntoIterator<Item = &'a Instruction>,
+			addresses: &[usize],
+			index: &mut usize,
+			indices: &mut [Option<usize>],
+		) {
+			for instruction in instructions {
+				let address = std::ptr::from_ref(instruction).addr();
+				for (position, _) in addresses
+					.iter()
+					.enumerate()
+					.filter(|&(_, &recent)| recent == address)
+				{
+					indices[position] = Some(*index);
+				}
+				*index += 1;
+
+				if let Instruction::Loop(body) = instruction {
+					walk(body, addresses, index, indices);
+				}
+			}
+		}
+
+		let addresses: Vec<usize> = recent.before_last().collect();
+		let mut indices = vec![None; addresses.len()];
+		walk(
+			instructions.iter().copied(),
+			&addresses,
+			&mut 0,
+			&mut indices,
+		);
+
+		Self {
+			recent: indices.into_iter().flatten().collect(),
+			cells: Neighborhood::around(tape, pointer),
+			..self
+		}
+	}
+
+	/// Add what ran right before the op, from the indices of `recent` ops, and the cells around
+	/// the pointer on `tape`.
+	pub(crate) fn with_recent_ops(
+		self,
+		recent: &Recent,
+		tape: &[Wrapping<u8>],
+		pointer: usize,
+	) -> Self {
+		Self {
+			recent: recent.before_last().collect(),
+			cells: Neighborhood::around(tape, pointer),
+			..self
+		}
+	}
+
+	/// Source locations and symbols of the instructions that ran right before it, if `program` is
+	/// what ran on the tree engine, see [`Location::recent`].
+	pub fn recent_instructions(&self, program: &Program) -> Vec<(Option<Span>, char)> {
+		fn walk(instructions: &[Instruction], symbols: &mut Vec<char>) {
+			for instruction in instructions {
+				symbols.push(instruction.symbol());
+				if let Instruction::Loop(body) = instruction {
+					walk(body, symbols);
+				}
+			}
+		}
+
+		let mut symbols = vec![];
+		walk(&program.instructions, &mut symbols);
+
+		self.recent
+			.iter()
+			.filter_map(|&index| Some((program.span(index), *symbols.get(index)?)))
+			.collect()
+	}
+
+	/// Source location of the instruction, if `program` is what ran on the tree engine and has
+	/// spans.
+	pub fn span(&self, program: &Program) -> Option<Span> {
+		program.span(self.index)
+	}
+
+	/// Source locations of the loops around the instruction, the outermost first, see
+	/// [`Location::span`].
+	pub fn loop_spans(&self, program: &Program) -> Vec<Span> {
+		self.loops
+			.iter()
+			.filter_map(|&index| program.span(index))
+			.collect()
+	}
+}
+
+/// The cells around the pointer, see [`Location::cells`], shown like `cells 3-11: 0 0 1 [72] 101
+/// 0 0 0 0`, with the current cell in brackets.
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct Neighborhood {
+	/// Index of the first of the cells.
+	pub first: usize,
+	/// Index of the current cell.
+	pub pointer: usize,
+	/// Values of the cells, from the first one on.
+	pub cells: Vec<u8>,
+}
+
+impl Neighborhood {
+	/// The cells up to [`NEIGHBORHOOD_RADIUS`] away from `pointer` on `tape`, without wrapping
+	/// around its ends.
+	pub(crate) fn around(tape: &[Wrapping<u8>], pointer: usize) -> Self {
+		let first = pointer.saturating_sub(NEIGHBORHOOD_RADIUS);
+		let end = pointer
+			.saturating_add(NEIGHBORHOOD_RADIUS + 1)
+			.min(tape.len());
+
+		Self {
+			first,
+			pointer,
+			cells: tape
+				.get(first..end)
+				.unwrap_or_default()
+				.iter()
+				.map(|cell| cell.0)
+				.collect(),
+		}
+	}
+}
+
+impl fmt::Display for Neighborhood {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		if self.cells.is_empty() {
+			return write!(f, "cell {} is off the tape", self.pointer);
+		}
+
+		let last = self.first + self.cells.len() - 1;
+		write!(f, "cells {}-{last}:", self.first)?;
+
+		for (index, cell) in (self.first..).zip(&self.cells) {
+			if index == self.pointer {
+				write!(f, " [{cell}]")?;
+			} else {
+				write!(f, " {cell}")?;
+			}
+		}
+
+		Ok(())
+	}
+}
+
+/// Addresses of the instructions (or indices of the ops) the engine ran last, the current one
+/// included, see [`Location::recent`].
+#[derive(Debug, Clone, Default)]
+pub(crate) struct Recent {
+	// NOTE: a power of two, so the ring wraps around with a mask rather than a division
+	entries: [usize; 16],
+	/// Number of entries pushed so far, which wraps around.
+	pushed: usize,
+}
+
+impl Recent {
+	#[inline(always)]
+	pub(crate) fn push(&mut self, entry: usize) {
+		self.entries[self.pushed & (self.entries.len() - 1)] = entry;
+		self.pushed = self.pushed.wrapping_add(1);
+	}
+
+	/// Up to [`RECENT`] entries before the last one, which is the current one, the oldest first.
+	fn before_last(&self) -> impl Iterator<Item = usize> + '_ {
+		let count = self
+			.pushed
+			.min(self.entries.len())
+			.saturating_sub(1)
+			.min(RECENT);
+		let first = self.pushed.wrapping_sub(count + 1);
+
+		(0..count)
+			.map(move |offset| self.entries[first.wrapping_add(offset) & (self.entries.len() - 1)])
+	}
+}
+
+/// A loop the tree engine is running, see [`EngineView::loops`](`crate::view::EngineView::loops`).
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct LoopFrame {
+	/// Address of the loop instruction, which is only compared, since it may be gone once the
+	/// program ends.
+	address: usize,
+	/// Iteration the loop is in, starting from 1.
+	pub iterations: u64,
+	/// Index of the cell the loop checked to get into this iteration, which it checks again
+	/// before the next one, unless it moves the pointer.
+	pub guard: usize,
+}
+
+impl LoopFrame {
+	pub(crate) fn new(instruction: &Instruction, guard: usize) -> Self {
+		Self {
+			address: std::ptr::from_ref(instruction).addr(),
+			iterations: 1,
+			guard,
+		}
+	}
+
+	/// Whether the frame is of `instruction`.
+	pub fn is(&self, instruction: &Instruction) -> bool {
+		std::ptr::from_ref(instruction).addr() == self.address
+	}
+
+	/// Pre-order index of the loop, if `program` is what runs, see [`Location`].
+	pub fn index(&self, program: &Program) -> Option<usize> {
+		program
+			.indices()
+			.into_iter()
+			.find(|&(instruction, _)| instruction.addr() == self.address)
+			.map(|(_, index)| index)
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use crate::{bytecode::Bytecode, token::Token};
+
+	use super::*;
+
+	#[test]
+	fn instructions_and_ops_agree() {
+		let instructions = Instruction::parse(Token::tokenize("+[>[-]<[.]-]")).unwrap();
+		let roots: Vec<&Instruction> = instructions.iter().collect();
+		let Instruction::Loop(body) = &instructions[1] else {
+			unreachable!()
+		};
+		let Instruction::Loop(inner) = &body[3] else {
+			unreachable!()
+		};
+
+		assert_eq!(
+			Some(Location {
+				index: 7,
+				loops: vec![1, 6],
+				..Location::default()
+			}),
+			Location::of_instruction(&roots, &inner[0])
+		);
+		assert_eq!(None, Location::of_instruction(&roots, &Instruction::Inc));
+
+		// NOTE: `+ [ > [ - ] < [ . ] - ]`, the same instructions, but loops end in an op of their own
+		assert_eq!(
+			Location {
+				index: 8,
+				loops: vec![1, 7],
+				..Location::default()
+			},
+			Location::of_op(Bytecode::compile(&instructions).ops(), 8)
+		);
+	}
+
+	#[test]
+	fn neighborhoods_stay_on_the_tape() {
+		let tape: Vec<Wrapping<u8>> = (1..=6).map(Wrapping).collect();
+
+		assert_eq!(
+			"cells 1-5: 2 3 4 5 [6]",
+			Neighborhood::around(&tape, 5).to_string()
+		);
+		assert_eq!(
+			"cells 0-4: [1] 2 3 4 5",
+			Neighborhood::around(&tape, 0).to_string()
+		);
+		assert_eq!(
+			"cell 10 is off the tape",
+			Neighborhood::around(&tape, 10).to_string()
+		);
+	}
+
+	#[test]
+	fn recent_keeps_the_last_entries() {
+		let mut recent = Recent::default();
+		for entry in 0..20 {
+			recent.push(entry);
+		}
+
+		assert_eq!(
+			(11..19).collect::<Vec<_>>(),
+			recent.before_last().collect::<Vec<_>>()
+		);
+	}
+}
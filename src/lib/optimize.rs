@@ -0,0 +1,538 @@
+use std::{collections::BTreeSet, fmt, io, slice};
+
+use crate::{
+	analysis,
+	engine::{Engine, RuntimeSettings},
+	explain::plural,
+	instruction::Instruction,
+	program::{count_instructions, needs_outside, Program},
+};
+
+/// A transformation of a program that keeps what it prints, see [`PassManager`].
+///
+/// Passes that change the instructions should keep [`Program::spans`] in step with them, or clear
+/// them.
+///
+/// # Usage
+///
+/// ```
+/// # use brainfuck_rs::{instruction::Instruction, optimize::{Pass, PassManager}, program::Program};
+/// /// Drops `.` at the end of the program, for programs whose output doesn't matter.
+/// struct Quiet;
+///
+/// impl Pass for Quiet {
+///     fn name(&self) -> &str {
+///         "quiet"
+///     }
+///
+///     fn run(&self, mut program: Program) -> Program {
+///         if program.instructions.last() == Some(&Instruction::Print) {
+///             program.instructions.pop();
+///             program.spans.pop();
+///         }
+///         program
+///     }
+/// }
+///
+/// let passes = PassManager::new().with_pass(Quiet);
+///
+/// assert_eq!(1, passes.run(&Program::parse("+.").unwrap()).instructions.len());
+/// ```
+pub trait Pass {
+	/// Name of the pass, for telling passes apart and removing them from a [`PassManager`].
+	fn name(&self) -> &str;
+
+	/// Transform the program.
+	fn run(&self, program: Program) -> Program;
+
+	/// Check that `after`, what the pass turned `before` into, runs the same as `before`, by
+	/// conditions particular to the pass, like that the loops it removed are never entered.
+	///
+	/// Passes that don't know how to check their output only tell whether they changed nothing.
+	fn validate(&self, before: &Program, after: &Program) -> Verdict {
+		unchanged(before, after).unwrap_or(Verdict::Unchecked)
+	}
+}
+
+/// What [`Pass::validate`] concluded about the output of a pass.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Verdict {
+	/// The output runs the same as the input, for the given reason.
+	Proven(String),
+	/// The pass can't check its output.
+	Unchecked,
+	/// The output may run differently than the input, for the given reason.
+	Refuted(String),
+}
+
+/// The verdict on one of the passes of [`PassManager::run_validated`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Validation {
+	/// Name of the pass.
+	pub pass: String,
+	/// What the pass concluded about its output.
+	pub verdict: Verdict,
+}
+
+impl fmt::Display for Validation {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		match &self.verdict {
+			Verdict::Proven(reason) => write!(f, "{}: proven, {reason}", self.pass),
+			Verdict::Unchecked => write!(
+				f,
+				"{}: unchecked, the pass can't check its output",
+				self.pass
+			),
+			Verdict::Refuted(reason) => write!(f, "{}: refuted, {reason}", self.pass),
+		}
+	}
+}
+
+/// [`Verdict::Proven`] if the pass changed nothing.
+fn unchanged(before: &Program, after: &Program) -> Option<Verdict> {
+	(before.instructions == after.instructions)
+		.then(|| Verdict::Proven("nothing changed".to_owned()))
+}
+
+/// Removes adjacent instructions that undo each other, see [`Program::cancel_opposites`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct CancelOpposites;
+
+impl Pass for CancelOpposites {
+	fn name(&self) -> &str {
+		"cancel-opposites"
+	}
+
+	fn run(&self, program: Program) -> Program {
+		program.cancel_opposites()
+	}
+
+	fn validate(&self, before: &Program, after: &Program) -> Verdict {
+		if let Some(verdict) = unchanged(before, after) {
+			return verdict;
+		}
+
+		// NOTE: arithmetic and pointer movement are folded in the normal form, so pairs that undo
+		// each other vanish from it
+		if before.structurally_eq(after) {
+			Verdict::Proven("the normal form of the program is the same".to_owned())
+		} else {
+			Verdict::Refuted("the normal form of the program changed".to_owned())
+		}
+	}
+}
+
+/// Turns `[-]` and `[+]` into clears, see [`Program::clear_loops`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct ClearLoops;
+
+impl Pass for ClearLoops {
+	fn name(&self) -> &str {
+		"clear-loops"
+	}
+
+	fn run(&self, program: Program) -> Program {
+		program.clear_loops()
+	}
+
+	fn validate(&self, before: &Program, after: &Program) -> Verdict {
+		if let Some(verdict) = unchanged(before, after) {
+			return verdict;
+		}
+
+		// NOTE: loops that clear their cell have the same normal form as clears
+		if before.structurally_eq(after) {
+			Verdict::Proven("the normal form of the program is the same".to_owned())
+		} else {
+			Verdict::Refuted("the normal form of the program changed".to_owned())
+		}
+	}
+}
+
+/// Collapses runs of `+`, `-`, `>` and `<` into adds and moves, see [`Program::collapse_runs`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct CollapseRuns;
+
+impl Pass for CollapseRuns {
+	fn name(&self) -> &str {
+		"collapse-runs"
+	}
+
+	fn run(&self, program: Program) -> Program {
+		program.collapse_runs()
+	}
+
+	fn validate(&self, before: &Program, after: &Program) -> Verdict {
+		if let Some(verdict) = unchanged(before, after) {
+			return verdict;
+		}
+
+		// NOTE: arithmetic and pointer movement are folded in the normal form, however they're
+		// written
+		if before.structurally_eq(after) {
+			Verdict::Proven("the normal form of the program is the same".to_owned())
+		} else {
+			Verdict::Refuted("the normal form of the program changed".to_owned())
+		}
+	}
+}
+
+/// Removes loops that are never entered, see [`Program::remove_dead_loops`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RemoveDeadLoops {
+	/// Length of the tape the program runs on.
+	pub tape_length: usize,
+}
+
+impl Pass for RemoveDeadLoops {
+	fn name(&self) -> &str {
+		"remove-dead-loops"
+	}
+
+	fn run(&self, program: Program) -> Program {
+		program.remove_dead_loops(self.tape_length)
+	}
+
+	fn validate(&self, before: &Program, after: &Program) -> Verdict {
+		/// Whether `after` is `before` without its dead loops, counting those in `removed`.
+		fn only_dead_removed(
+			before: &[Instruction],
+			after: &[Instruction],
+			dead: &BTreeSet<usize>,
+			index: &mut usize,
+			removed: &mut u64,
+		) -> bool {
+			let mut kept = after.iter();
+			let mut next = kept.next();
+
+			for instruction in before {
+				if dead.contains(index) {
+					*index += count_instructions(slice::from_ref(instruction));
+					*removed += 1;
+					continue;
+				}
+				*index += 1;
+
+				let same = match (instruction, next) {
+					(Instruction::Loop(body), Some(Instruction::Loop(kept_body))) => {
+						only_dead_removed(body, kept_body, dead, index, removed)
+					}
+					(instruction, Some(kept)) => instruction == kept,
+					(_, None) => false,
+				};
+				if !same {
+					return false;
+				}
+				next = kept.next();
+			}
+
+			next.is_none()
+		}
+
+		if let Some(verdict) = unchanged(before, after) {
+			return verdict;
+		}
+
+		let ranges = analysis::value_ranges(before);
+		// NOTE: value ranges only hold on tapes long enough, unlike loops that are dead for where
+		// they are
+		let (dead, why) = if ranges.spread < self.tape_length {
+			(
+				ranges.dead_loops.into_iter().collect(),
+				"the value ranges of cells show",
+			)
+		} else {
+			(
+				before.trivially_dead_loops(),
+				"are first in the program or right after another loop, which shows",
+			)
+		};
+		let mut removed: u64 = 0;
+		if only_dead_removed(
+			&before.instructions,
+			&after.instructions,
+			&dead,
+			&mut 0,
+			&mut removed,
+		) {
+			Verdict::Proven(format!(
+				"removed {} that {why} can't be entered",
+				plural(removed, "loop")
+			))
+		} else {
+			Verdict::Refuted(format!(
+				"more changed than loops that {why} are never entered"
+			))
+		}
+	}
+}
+
+/// Runs the part of the program before it reads input ahead of time, see
+/// [`Program::evaluate_prefix`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct EvaluatePrefix {
+	/// Length of the tape the program runs on.
+	pub tape_length: usize,
+	/// Number of instructions to run at most, before giving up.
+	pub max_steps: u64,
+}
+
+impl EvaluatePrefix {
+	/// Number of instructions [`OptLevel::Evaluate`] runs ahead of time at most.
+	pub const MAX_STEPS: u64 = 1_000_000;
+}
+
+impl Pass for EvaluatePrefix {
+	fn name(&self) -> &str {
+		"evaluate-prefix"
+	}
+
+	fn run(&self, program: Program) -> Program {
+		program.evaluate_prefix(self.tape_length, self.max_steps)
+	}
+
+	fn validate(&self, before: &Program, after: &Program) -> Verdict {
+		if let Some(verdict) = unchanged(before, after) {
+			return verdict;
+		}
+
+		let length = before
+			.instructions
+			.iter()
+			.position(needs_outside)
+			.unwrap_or(before.instructions.len());
+		let (prefix, rest) = before.instructions.split_at(length);
+		let Some(replacement) = after.instructions.strip_suffix(rest) else {
+			return Verdict::Refuted(
+				"the instructions from the first read or extension on changed".to_owned(),
+			);
+		};
+		if replacement.iter().any(|instruction| {
+			matches!(
+				instruction,
+				Instruction::Loop(_) | Instruction::Read | Instruction::Extension(_)
+			)
+		}) {
+			return Verdict::Refuted("the instructions run instead have loops or read".to_owned());
+		}
+
+		let run = |instructions: &[Instruction], budget: Option<u64>| {
+			let mut bf = Engine::new(self.tape_length);
+			let mut output = vec![];
+			bf.run(
+				instructions,
+				&mut io::empty(),
+				&mut output,
+				RuntimeSettings {
+					budget,
+					..Default::default()
+				},
+			)
+			.ok()
+			.map(|_| (output, bf.tape, bf.pointer))
+		};
+
+		let Some((output, tape, pointer)) = run(prefix, Some(self.max_steps)) else {
+			return Verdict::Refuted(format!(
+				"the instructions replaced don't end within {} steps",
+				self.max_steps
+			));
+		};
+		// NOTE: the replacement is straight-line code, so it ends
+		let (replaced_output, replaced_tape, replaced_pointer) = run(replacement, None)
+			.expect("straight-line code can't fail without a budget or input");
+
+		// NOTE: the tape only matters if there's something left to run on it
+		if output != replaced_output {
+			Verdict::Refuted("the instructions run instead print something else".to_owned())
+		} else if !rest.is_empty() && (tape != replaced_tape || pointer != replaced_pointer) {
+			Verdict::Refuted("the instructions run instead leave another tape behind".to_owned())
+		} else {
+			Verdict::Proven(format!(
+				"the {} instructions replaced and the {} run instead print the same {} and leave \
+				 the same tape behind, on a tape of {} cells",
+				count_instructions(prefix),
+				replacement.len(),
+				plural(output.len() as u64, "byte"),
+				self.tape_length
+			))
+		}
+	}
+}
+
+/// How hard programs are optimized before they run, a preset of passes for
+/// [`PassManager::preset`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Default)]
+pub enum OptLevel {
+	/// No passes, the program runs instruction by instruction as it's written.
+	None,
+	/// Only passes that are cheap and local, like [`CancelOpposites`], [`ClearLoops`] and
+	/// [`CollapseRuns`].
+	Basic,
+	/// Passes that analyze the whole program, like [`RemoveDeadLoops`], too.
+	#[default]
+	Aggressive,
+	/// Every pass, including [`EvaluatePrefix`], which runs part of the program while it's
+	/// optimized.
+	Evaluate,
+}
+
+/// Passes run one after another.
+///
+/// # Usage
+///
+/// ```
+/// # use brainfuck_rs::{optimize::{OptLevel, PassManager}, program::Program};
+/// let program = Program::parse("[comment]+-+").unwrap();
+///
+/// let basic = PassManager::preset(OptLevel::Basic, 30_000);
+/// let aggressive = PassManager::preset(OptLevel::Aggressive, 30_000);
+///
+/// assert_eq!(Program::parse("[comment]+").unwrap().instructions, basic.run(&program).instructions);
+/// assert_eq!(Program::parse("+").unwrap().instructions, aggressive.run(&program).instructions);
+/// assert_eq!(vec!["cancel-opposites", "clear-loops", "collapse-runs"], aggressive.without("remove-dead-loops").names().collect::<Vec<_>>());
+/// ```
+#[derive(Default)]
+pub struct PassManager {
+	passes: Vec<Box<dyn Pass>>,
+}
+
+impl PassManager {
+	/// A pass manager without passes.
+	pub fn new() -> Self {
+		Self::default()
+	}
+
+	/// The passes of `level`, for programs that run on a tape of `tape_length` cells.
+	///
+	/// Runs are collapsed last, so that the other passes see every instruction as it's written.
+	pub fn preset(level: OptLevel, tape_length: usize) -> Self {
+		let mut passes = Self::new();
+		if level == OptLevel::None {
+			return passes;
+		}
+
+		passes = passes.with_pass(CancelOpposites).with_pass(ClearLoops);
+		if level >= OptLevel::Aggressive {
+			passes = passes.with_pass(RemoveDeadLoops { tape_length });
+		}
+		if level >= OptLevel::Evaluate {
+			passes = passes.with_pass(EvaluatePrefix {
+				tape_length,
+				max_steps: EvaluatePrefix::MAX_STEPS,
+			});
+		}
+
+		passes.with_pass(CollapseRuns)
+	}
+
+	/// Run `pass` after the other passes.
+	#[must_use]
+	pub fn with_pass(mut self, pass: impl Pass + 'static) -> Self {
+		self.passes.push(Box::new(pass));
+		self
+	}
+
+	/// Remove the passes called `name`.
+	#[must_use]
+	pub fn without(mut self, name: &str) -> Self {
+		self.passes.retain(|pass| pass.name() != name);
+		self
+	}
+
+	/// Names of the passes, in the order they run.
+	pub fn names(&self) -> impl Iterator<Item = &str> {
+		self.passes.iter().map(|pass| pass.name())
+	}
+
+	/// Run every pass on `program`.
+	pub fn run(&self, program: &Program) -> Program {
+		self.passes
+			.iter()
+			.fold(program.clone(), |program, pass| pass.run(program))
+	}
+
+	/// Run every pass on `program`, like [`PassManager::run`], and [validate](`Pass::validate`)
+	/// what each of them made of the output of the one before.
+	///
+	/// # Usage
+	///
+	/// ```
+	/// # use brainfuck_rs::{optimize::{OptLevel, PassManager, Verdict}, program::Program};
+	/// let program = Program::parse("[comment]+-+.").unwrap();
+	/// let (_, validations) = PassManager::preset(OptLevel::Evaluate, 30_000).run_validated(&program);
+	///
+	/// assert!(validations.iter().all(|validation| matches!(validation.verdict, Verdict::Proven(_))));
+	/// ```
+	pub fn run_validated(&self, program: &Program) -> (Program, Vec<Validation>) {
+		let mut validations = Vec::with_capacity(self.passes.len());
+		let mut program = program.clone();
+
+		for pass in &self.passes {
+			let output = pass.run(program.clone());
+			validations.push(Validation {
+				pass: pass.name().to_owned(),
+				verdict: pass.validate(&program, &output),
+			});
+			program = output;
+		}
+
+		(program, validations)
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn none_keeps_the_program() {
+		let program = Program::parse("+[-]+-<>").unwrap();
+
+		assert_eq!(
+			program,
+			PassManager::preset(OptLevel::None, 30_000).run(&program)
+		);
+		assert_eq!(
+			Program::parse("+[-]").unwrap().clear_loops(),
+			PassManager::preset(OptLevel::Aggressive, 30_000).run(&program)
+		);
+	}
+
+	#[test]
+	fn validation_refutes_wrong_outputs() {
+		let program = Program::parse("[dead.]++[->+<]>.,").unwrap();
+		let wrong = |code| Program::parse(code).unwrap();
+		let refuted = |verdict| matches!(verdict, Verdict::Refuted(_));
+
+		assert!(refuted(
+			CancelOpposites.validate(&program, &wrong("[]++[->+<]>.,"))
+		));
+		assert!(refuted(
+			RemoveDeadLoops {
+				tape_length: 30_000
+			}
+			.validate(&program, &wrong("++>.,"))
+		));
+		assert!(refuted(
+			EvaluatePrefix {
+				tape_length: 30_000,
+				max_steps: 1000,
+			}
+			.validate(&program, &wrong(">+++.,"))
+		));
+
+		let (_, validations) =
+			PassManager::preset(OptLevel::Evaluate, 30_000).run_validated(&program);
+		assert_eq!(
+			vec![
+				"cancel-opposites: proven, nothing changed",
+				"clear-loops: proven, nothing changed",
+				"remove-dead-loops: proven, removed 1 loop that the value ranges of cells show can't be entered",
+				"evaluate-prefix: proven, the 9 instructions replaced and the 8 run instead print the same 1 byte and leave the same tape behind, on a tape of 30000 cells",
+				"collapse-runs: proven, the normal form of the program is the same",
+			],
+			validations.iter().map(ToString::to_string).collect::<Vec<_>>()
+		);
+	}
+}
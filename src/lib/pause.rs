@@ -0,0 +1,98 @@
+use std::sync::{
+	atomic::{AtomicBool, Ordering},
+	Arc,
+};
+
+/// A request to pause a running program, which can be made from another thread or a signal
+/// handler, see [`RuntimeSettings::pause`](`crate::engine::RuntimeSettings::pause`).
+///
+/// The engine only looks at the flag at [`SafePoint`]s, where everything about the run is
+/// consistent, and clears it once it paused, so every request pauses the program once.
+///
+/// # Usage
+///
+/// ```
+/// # use std::ops::ControlFlow;
+/// # use brainfuck_rs::{
+/// #   engine::{Engine, RuntimeSettings},
+/// #   hooks::Hooks,
+/// #   host::StdHost,
+/// #   instruction::Instruction,
+/// #   pause::{PauseFlag, SafePoint},
+/// #   token::Token,
+/// #   view::EngineView,
+/// # };
+/// struct Inspector(Vec<(u64, SafePoint, u8)>);
+///
+/// impl Hooks for Inspector {
+///     fn on_pause(&mut self, step: u64, at: SafePoint, bf: EngineView<'_>) -> ControlFlow<()> {
+///         self.0.push((step, at, bf.cell()));
+///         ControlFlow::Continue(())
+///     }
+/// }
+///
+/// let pause = PauseFlag::new();
+/// pause.request();
+///
+/// let instructions = Instruction::parse(Token::tokenize("+++[-].")).unwrap();
+/// let mut inspector = Inspector(vec![]);
+/// let summary = Engine::default()
+///     .run_with(
+///         &instructions,
+///         &mut [].as_slice(),
+///         &mut vec![],
+///         RuntimeSettings {
+///             pause: Some(pause.clone()),
+///             ..Default::default()
+///         },
+///         &mut StdHost::default(),
+///         &mut inspector,
+///     )
+///     .unwrap();
+///
+/// assert_eq!(vec![(3, SafePoint::Loop, 3)], inspector.0);
+/// assert!(!summary.paused);
+/// assert!(!pause.is_requested());
+/// ```
+#[derive(Debug, Clone, Default)]
+pub struct PauseFlag(Arc<AtomicBool>);
+
+impl PauseFlag {
+	/// A flag without a pending request.
+	pub fn new() -> Self {
+		Self::default()
+	}
+
+	/// Ask the program to pause at the next safe point.
+	pub fn request(&self) {
+		self.0.store(true, Ordering::Relaxed);
+	}
+
+	/// Whether a request is pending, that the program didn't pause for yet.
+	pub fn is_requested(&self) -> bool {
+		self.0.load(Ordering::Relaxed)
+	}
+
+	/// Clear a pending request, returning whether there was one.
+	pub(crate) fn take(&self) -> bool {
+		// NOTE: most of the time there's no request, and loading is cheaper than swapping
+		self.is_requested() && self.0.swap(false, Ordering::Relaxed)
+	}
+}
+
+/// Where a program can pause, see [`Hooks::on_pause`](`crate::hooks::Hooks::on_pause`).
+///
+/// Programs pause right before the instruction (or op) at the safe point is executed, so
+/// resuming carries on with it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum SafePoint {
+	/// Checking the condition of a loop, when entering it or jumping back to its start, so
+	/// programs that never do IO can still be paused.
+	Loop,
+	/// Reading input, which may block.
+	Read,
+	/// Printing output.
+	Print,
+	/// Executing an extension instruction.
+	Extension,
+}
@@ -0,0 +1,555 @@
+use std::{
+	collections::VecDeque,
+	io::{self, ErrorKind, Read, Write},
+	mem, panic,
+	sync::{Arc, Condvar, Mutex, MutexGuard, PoisonError},
+	thread,
+};
+
+use crate::engine::{RunError, RunSummary};
+
+/// Bytes a [`channel`] between two stages of a [`run`] holds before the writing stage waits for
+/// the reading one.
+pub const DEFAULT_CAPACITY: usize = 64 * 1024;
+
+/// A program of a pipeline, which runs with the input and output it's given and reports how it
+/// went. It's called on a thread of its own, so it must set up anything that can't be sent
+/// between threads, like [`RuntimeSettings`](`crate::engine::RuntimeSettings`), by itself.
+pub type Stage<'a> =
+	Box<dyn FnOnce(&mut dyn Read, &mut dyn Write) -> Result<RunSummary, RunError> + Send + 'a>;
+
+/// Run programs like a shell pipeline, in a single process: the first stage reads `stdin`, the
+/// output of every stage is the input of the next, and the last stage writes to `stdout`.
+///
+/// Every stage but the last runs on a thread of its own, and all of them run at once, so
+/// interactive programs respond as soon as their input arrives. When a stage ends, the next one
+/// reads EOF, and the previous one fails to write with [`ErrorKind::BrokenPipe`], like in a shell.
+///
+/// Returns how every stage went, in order.
+///
+/// # Usage
+///
+/// ```
+/// # use brainfuck_rs::{
+/// #   engine::{Engine, RuntimeSettings},
+/// #   instruction::Instruction,
+/// #   pipe::{self, Stage},
+/// #   token::Token,
+/// # };
+/// fn stage(instructions: &[Instruction]) -> Stage<'_> {
+///     Box::new(move |mut stdin, mut stdout| {
+///         let settings = RuntimeSettings {
+///             quit_on_eof: true,
+///             ..Default::default()
+///         };
+///         Engine::default().run(instructions, &mut stdin, &mut stdout, settings)
+///     })
+/// }
+///
+/// // NOTE: the first program adds 1 to every byte, the second one doubles it
+/// let increment = Instruction::parse(Token::tokenize(",[+.,]")).unwrap();
+/// let double = Instruction::parse(Token::tokenize(",[[->++<]>.[-]<,]")).unwrap();
+///
+/// let mut output = vec![];
+/// let summaries = pipe::run(
+///     &mut [1, 2, 3].as_slice(),
+///     &mut output,
+///     vec![stage(&increment), stage(&double)],
+/// );
+///
+/// assert_eq!(vec![4, 6, 8], output);
+/// assert!(summaries.iter().all(Result::is_ok));
+/// ```
+pub fn run(
+	stdin: &mut (impl Read + Send),
+	stdout: &mut impl Write,
+	mut stages: Vec<Stage<'_>>,
+) -> Vec<Result<RunSummary, RunError>> {
+	let Some(last) = stages.pop() else {
+		return vec![];
+	};
+	let hub = Hub::new(
+		vec![DEFAULT_CAPACITY; stages.len()],
+		stages.len() + 1,
+		false,
+	);
+
+	let hub = &hub;
+	thread::scope(|scope| {
+		let mut threads = vec![];
+		let mut input: Box<dyn Read + Send> = Box::new(stdin);
+
+		for (index, stage) in stages.into_iter().enumerate() {
+			let (mut writer, reader) = hub.ends(index);
+			let mut previous = mem::replace(&mut input, Box::new(reader));
+
+			threads.push(scope.spawn(move || {
+				let result = stage(&mut previous, &mut writer);
+				hub.finish(previous, writer);
+
+				result
+			}));
+		}
+
+		let last = last(&mut input, stdout);
+		// NOTE: the previous stage may be waiting to write, and would wait forever
+		hub.finish(input, io::sink());
+
+		let mut results: Vec<_> = threads
+			.into_iter()
+			.map(|thread| thread.join().unwrap_or_else(|e| panic::resume_unwind(e)))
+			.collect();
+		results.push(last);
+
+		results
+	})
+}
+
+/// How two programs run with [`link`] went.
+#[derive(Debug)]
+pub struct Linked {
+	/// How each program went, in the order they were given.
+	pub results: [Result<RunSummary, RunError>; 2],
+	/// Everything the programs sent each other, in the order it was sent. Consecutive bytes sent
+	/// by the same program are a single chunk, along with the index of that program.
+	pub transcript: Vec<(usize, Vec<u8>)>,
+	/// If the programs deadlocked, what each of them was waiting for, or `None` for one that had
+	/// already ended.
+	pub blocked: Option<[Option<Blocked>; 2]>,
+}
+
+/// What a program of a deadlocked [`link`] was waiting for.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Blocked {
+	/// Input, with nothing left to read.
+	Reading,
+	/// Room to print, with the other program's input full.
+	Writing,
+}
+
+/// Run two programs as coroutines, each reading what the other prints, until both end or neither
+/// can go on. Each direction goes through a pipe that holds `capacity` bytes, see [`channel`].
+///
+/// If both programs wait for each other, be it to print something or to read what they printed,
+/// both fail with an [`io::Error`] of the kind [`ErrorKind::Deadlock`], and [`Linked::blocked`]
+/// tells what each was waiting for.
+///
+/// # Usage
+///
+/// ```
+/// # use brainfuck_rs::{
+/// #   engine::{Engine, RuntimeSettings},
+/// #   instruction::Instruction,
+/// #   pipe::{self, Stage},
+/// #   token::Token,
+/// # };
+/// fn stage(instructions: &[Instruction]) -> Stage<'_> {
+///     Box::new(move |mut stdin, mut stdout| {
+///         Engine::default().run(instructions, &mut stdin, &mut stdout, RuntimeSettings::default())
+///     })
+/// }
+///
+/// // NOTE: the first program serves 1 and expects it back plus 1, the second one returns what
+/// // it's served plus 1
+/// let serve = Instruction::parse(Token::tokenize("+.,--")).unwrap();
+/// let volley = Instruction::parse(Token::tokenize(",+.")).unwrap();
+///
+/// let linked = pipe::link(stage(&serve), stage(&volley), pipe::DEFAULT_CAPACITY);
+///
+/// assert_eq!(vec![(0, vec![1]), (1, vec![2])], linked.transcript);
+/// assert!(linked.results.iter().all(Result::is_ok));
+/// ```
+///
+/// # Panics
+///
+/// Panics if `capacity` is 0.
+pub fn link(first: Stage<'_>, second: Stage<'_>, capacity: usize) -> Linked {
+	let hub = Hub::new(vec![capacity; 2], 2, true);
+
+	let results = thread::scope(|scope| {
+		let hub = &hub;
+		let (mut first_writer, mut first_reader) = hub.ends(0);
+		let (mut second_writer, mut second_reader) = hub.ends(1);
+
+		let thread = scope.spawn(move || {
+			let result = second(&mut first_reader, &mut second_writer);
+			hub.finish(first_reader, second_writer);
+
+			result
+		});
+
+		let first = first(&mut second_reader, &mut first_writer);
+		hub.finish(second_reader, first_writer);
+
+		[
+			first,
+			thread.join().unwrap_or_else(|e| panic::resume_unwind(e)),
+		]
+	});
+
+	let mut state = hub.lock();
+	let transcript = state.transcript.take().unwrap_or_default();
+	// NOTE: the first program writes to pipe 0 and reads from pipe 1, the second one the other
+	// way around
+	let blocked = state.stalled.take().map(|stalled| {
+		let mut blocked = [None; 2];
+		for (pipe, side) in stalled {
+			match side {
+				Side::Read => blocked[1 - pipe] = Some(Blocked::Reading),
+				Side::Write => blocked[pipe] = Some(Blocked::Writing),
+			}
+		}
+
+		blocked
+	});
+
+	Linked {
+		results,
+		transcript,
+		blocked,
+	}
+}
+
+/// An in-memory pipe of bytes, whose writing end is the [`PipeWriter`] and whose reading end is
+/// the [`PipeReader`]. It holds at most `capacity` bytes, after which writing waits until some
+/// are read.
+///
+/// Dropping the writer makes the reader read EOF once it's read everything, and dropping the
+/// reader makes writing fail with [`ErrorKind::BrokenPipe`].
+///
+/// # Panics
+///
+/// Panics if `capacity` is 0.
+pub fn channel(capacity: usize) -> (PipeWriter, PipeReader) {
+	Hub::new(vec![capacity], 0, false).ends(0)
+}
+
+/// Pipes between the stages of a pipeline, behind a single lock, so it can be told whether every
+/// stage is waiting for another one.
+#[derive(Debug)]
+struct Hub {
+	state: Mutex<HubState>,
+	changed: Condvar,
+}
+
+#[derive(Debug)]
+struct HubState {
+	pipes: Vec<PipeState>,
+	/// Stages that haven't ended yet, or 0 if they aren't kept track of.
+	running: usize,
+	/// Pipes that stages are waiting to read from or to write to.
+	waiting: Vec<(usize, Side)>,
+	/// What the stages were waiting for once they deadlocked.
+	stalled: Option<Vec<(usize, Side)>>,
+	transcript: Option<Vec<(usize, Vec<u8>)>>,
+}
+
+#[derive(Debug)]
+struct PipeState {
+	buffer: VecDeque<u8>,
+	capacity: usize,
+	writer_open: bool,
+	reader_open: bool,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Side {
+	Read,
+	Write,
+}
+
+impl Hub {
+	fn new(capacities: Vec<usize>, stages: usize, record: bool) -> Arc<Self> {
+		assert!(
+			capacities.iter().all(|&capacity| capacity > 0),
+			"a pipe must be able to hold at least a byte"
+		);
+
+		Arc::new(Self {
+			state: Mutex::new(HubState {
+				pipes: capacities
+					.into_iter()
+					.map(|capacity| PipeState {
+						buffer: VecDeque::new(),
+						capacity,
+						writer_open: true,
+						reader_open: true,
+					})
+					.collect(),
+				running: stages,
+				waiting: vec![],
+				stalled: None,
+				transcript: record.then(Vec::new),
+			}),
+			changed: Condvar::new(),
+		})
+	}
+
+	fn ends(self: &Arc<Self>, pipe: usize) -> (PipeWriter, PipeReader) {
+		(
+			PipeWriter {
+				hub: Arc::clone(self),
+				pipe,
+			},
+			PipeReader {
+				hub: Arc::clone(self),
+				pipe,
+			},
+		)
+	}
+
+	fn lock(&self) -> MutexGuard<'_, HubState> {
+		// NOTE: the state is only changed in single steps, so a panic can't leave it half-changed
+		self.state.lock().unwrap_or_else(PoisonError::into_inner)
+	}
+
+	/// Wait until the `side` of `pipe` can be used.
+	///
+	/// # Errors
+	///
+	/// Fails if every running stage is waiting for a pipe that can't be used, since then none of
+	/// them ever will be.
+	fn wait(&self, pipe: usize, side: Side) -> io::Result<MutexGuard<'_, HubState>> {
+		let mut state = self.lock();
+
+		while state.blocked(pipe, side) {
+			state.waiting.push((pipe, side));
+			if state.stalled.is_none() && state.deadlock() {
+				state.stalled = Some(state.waiting.clone());
+				self.changed.notify_all();
+			}
+			if state.stalled.is_none() {
+				state = self
+					.changed
+					.wait(state)
+					.unwrap_or_else(PoisonError::into_inner);
+			}
+
+			let index = state
+				.waiting
+				.iter()
+				.position(|&waiting| waiting == (pipe, side))
+				.expect("waiting stages stay listed until they stop waiting");
+			state.waiting.swap_remove(index);
+
+			// NOTE: every stage that was waiting fails, even if the first one to fail ends and
+			// lets another one go on, since that would only hide the deadlock
+			if state.stalled.is_some() {
+				return Err(io::Error::new(
+					ErrorKind::Deadlock,
+					"every program is waiting for another one",
+				));
+			}
+		}
+
+		Ok(state)
+	}
+
+	/// Close the pipes a stage used, once it ended.
+	fn finish(&self, reader: impl Read, writer: impl Write) {
+		drop((reader, writer));
+
+		let mut state = self.lock();
+		state.running = state.running.saturating_sub(1);
+		// NOTE: the stages that are left may be waiting for each other
+		if state.stalled.is_none() && state.deadlock() {
+			state.stalled = Some(state.waiting.clone());
+			self.changed.notify_all();
+		}
+	}
+
+	/// Close the `side` of `pipe`.
+	fn close(&self, pipe: usize, side: Side) {
+		let mut state = self.lock();
+		match side {
+			Side::Read => state.pipes[pipe].reader_open = false,
+			Side::Write => state.pipes[pipe].writer_open = false,
+		}
+		self.changed.notify_all();
+	}
+}
+
+impl HubState {
+	/// Whether using the `side` of `pipe` would have to wait.
+	fn blocked(&self, pipe: usize, side: Side) -> bool {
+		let pipe = &self.pipes[pipe];
+
+		match side {
+			Side::Read => pipe.writer_open && pipe.buffer.is_empty(),
+			Side::Write => pipe.reader_open && pipe.buffer.len() == pipe.capacity,
+		}
+	}
+
+	/// Whether every running stage is waiting for a pipe that can't be used.
+	fn deadlock(&self) -> bool {
+		self.running != 0
+			&& self.waiting.len() == self.running
+			&& self
+				.waiting
+				.iter()
+				.all(|&(pipe, side)| self.blocked(pipe, side))
+	}
+}
+
+/// Writing end of a [`channel`].
+#[derive(Debug)]
+pub struct PipeWriter {
+	hub: Arc<Hub>,
+	pipe: usize,
+}
+
+impl Write for PipeWriter {
+	fn write(&mut self, bytes: &[u8]) -> io::Result<usize> {
+		if bytes.is_empty() {
+			return Ok(0);
+		}
+
+		let mut state = self.hub.wait(self.pipe, Side::Write)?;
+		if !state.pipes[self.pipe].reader_open {
+			return Err(io::Error::new(
+				ErrorKind::BrokenPipe,
+				"the program reading this ended",
+			));
+		}
+
+		let pipe = &mut state.pipes[self.pipe];
+		let written = bytes.len().min(pipe.capacity - pipe.buffer.len());
+		pipe.buffer.extend(&bytes[..written]);
+
+		let from = self.pipe;
+		if let Some(transcript) = &mut state.transcript {
+			match transcript.last_mut() {
+				Some((last, chunk)) if *last == from => chunk.extend(&bytes[..written]),
+				_ => transcript.push((from, bytes[..written].to_vec())),
+			}
+		}
+		self.hub.changed.notify_all();
+
+		Ok(written)
+	}
+
+	fn flush(&mut self) -> io::Result<()> {
+		Ok(())
+	}
+}
+
+impl Drop for PipeWriter {
+	fn drop(&mut self) {
+		self.hub.close(self.pipe, Side::Write);
+	}
+}
+
+/// Reading end of a [`channel`]. Reading waits until there's something to read, and returns
+/// whatever there is.
+#[derive(Debug)]
+pub struct PipeReader {
+	hub: Arc<Hub>,
+	pipe: usize,
+}
+
+impl Read for PipeReader {
+	fn read(&mut self, buffer: &mut [u8]) -> io::Result<usize> {
+		if buffer.is_empty() {
+			return Ok(0);
+		}
+
+		let mut state = self.hub.wait(self.pipe, Side::Read)?;
+		let pipe = &mut state.pipes[self.pipe];
+		let read = buffer.len().min(pipe.buffer.len());
+		for (byte, buffered) in buffer.iter_mut().zip(pipe.buffer.drain(..read)) {
+			*byte = buffered;
+		}
+		self.hub.changed.notify_all();
+
+		Ok(read)
+	}
+}
+
+impl Drop for PipeReader {
+	fn drop(&mut self) {
+		self.hub.close(self.pipe, Side::Read);
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use crate::{
+		engine::{Engine, RuntimeSettings},
+		instruction::Instruction,
+		token::Token,
+	};
+
+	use super::*;
+
+	fn stage(instructions: &[Instruction]) -> Stage<'_> {
+		Box::new(move |mut stdin, mut stdout| {
+			Engine::default().run(
+				instructions,
+				&mut stdin,
+				&mut stdout,
+				RuntimeSettings::default(),
+			)
+		})
+	}
+
+	#[test]
+	fn later_stages_ending_early() {
+		// NOTE: the first program prints forever, the second one echoes two bytes
+		let forever = Instruction::parse(Token::tokenize("+[.]")).unwrap();
+		let two = Instruction::parse(Token::tokenize(",.,.")).unwrap();
+
+		let mut output = vec![];
+		let results = run(
+			&mut io::empty(),
+			&mut output,
+			vec![stage(&forever), stage(&two)],
+		);
+
+		assert_eq!(vec![1, 1], output);
+		assert!(
+			matches!(&results[0], Err(RunError::Io { error: e, .. }) if e.kind() == ErrorKind::BrokenPipe)
+		);
+		assert_eq!(4, results[1].as_ref().unwrap().steps);
+	}
+
+	#[test]
+	fn deadlocks() {
+		let blocked = |linked: Linked| {
+			assert!(linked.results.iter().all(
+				|result| matches!(result, Err(RunError::Io { error: e, .. }) if e.kind() == ErrorKind::Deadlock),
+			));
+			linked.blocked
+		};
+
+		// NOTE: both wait to read first
+		let listen = Instruction::parse(Token::tokenize(",.")).unwrap();
+		assert_eq!(
+			Some([Some(Blocked::Reading); 2]),
+			blocked(link(stage(&listen), stage(&listen), 1))
+		);
+
+		// NOTE: both talk more than the other can take before listening
+		let talk = Instruction::parse(Token::tokenize("+...,")).unwrap();
+		assert_eq!(
+			Some([Some(Blocked::Writing); 2]),
+			blocked(link(stage(&talk), stage(&talk), 2))
+		);
+
+		// NOTE: one ending doesn't leave the other waiting. It reads everything the other sends
+		// before it ends, or the last write may fail with a broken pipe, depending on scheduling
+		let reply = Instruction::parse(Token::tokenize(",.,,")).unwrap();
+		let linked = link(stage(&talk), stage(&reply), 2);
+		// NOTE: how the chunks interleave depends on scheduling, what each program sent doesn't
+		let sent = |program| {
+			linked
+				.transcript
+				.iter()
+				.filter(|(sender, _)| *sender == program)
+				.flat_map(|(_, chunk)| chunk.clone())
+				.collect::<Vec<u8>>()
+		};
+		assert_eq!((vec![1, 1, 1], vec![1]), (sent(0), sent(1)));
+		assert!(linked.results.iter().all(Result::is_ok));
+		assert_eq!(None, linked.blocked);
+	}
+}
@@ -0,0 +1,461 @@
+use std::{
+	cell::RefCell,
+	ffi::{c_char, c_void, CStr},
+	io::{self, ErrorKind},
+	path::{Path, PathBuf},
+	ptr,
+	rc::Rc,
+};
+
+use libloading::Library;
+use thiserror::Error;
+
+use crate::{
+	extension::{ExtensionConflict, ExtensionSet},
+	host::HostServices,
+	view::EngineMut,
+};
+
+/// Version of the C ABI that plugins are loaded with, passed to `bf_plugin_init`.
+pub const ABI_VERSION: u32 = 1;
+
+/// A dynamic library that handles custom instructions, for extending the interpreter in languages
+/// other than Rust.
+///
+/// The library exports three functions, declared in `include/bf_plugin.h`:
+///
+/// ```c
+/// int32_t bf_plugin_init(uint32_t abi_version, void **state, const char **symbols);
+/// int32_t bf_plugin_handle_instruction(void *state, char symbol, const struct bf_api *api);
+/// void bf_plugin_shutdown(void *state);
+/// ```
+///
+/// - `bf_plugin_init` runs once the library is loaded. It returns 0 if it supports
+///   [`ABI_VERSION`], setting `state` to whatever it wants back in the other functions, and
+///   `symbols` to a NUL-terminated string of the instructions it handles, which must stay valid
+///   until it's shut down. Anything else refuses to load.
+/// - `bf_plugin_handle_instruction` runs whenever the program reaches one of those instructions.
+///   It returns 0 if it succeeded, and anything else ends the program with an error.
+/// - `bf_plugin_shutdown` runs once the plugin isn't needed anymore, and is the last call made
+///   with `state`.
+///
+/// A library may be loaded more than once at a time, so state belongs in `state` rather than in
+/// globals. Handlers reach the engine and the outside world through `struct bf_api`, whose
+/// functions take its `context` first:
+///
+/// | Function                        | What it does                                                     |
+/// |---------------------------------|------------------------------------------------------------------|
+/// | `pointer()`                     | Index of the current cell.                                       |
+/// | `tape_length()`                 | Number of cells.                                                 |
+/// | `get(index)`                    | Value of a cell, or -1 past the end of the tape.                 |
+/// | `set(index, value)`             | Set a cell, returning 0, or -1 past the end of the tape.         |
+/// | `set_pointer(index)`            | Move the pointer, returning 0, or -1 past the end of the tape.   |
+/// | `read()`                        | A byte of the program's input, -1 at its end, or -2 on failure.  |
+/// | `write(byte)`                   | Print a byte, returning 0, or -1 on failure.                     |
+/// | `clock_millis(millis*)`         | [`HostServices::clock_millis`], returning 0, or -1 if refused.   |
+/// | `random_byte(byte*)`            | [`HostServices::random_byte`], returning 0, or -1 if refused.    |
+///
+/// When a handler fails after a call to the API failed, the program ends with that error.
+///
+/// Access to the host goes through the [`HostServices`] a plugin is loaded with, which can refuse
+/// it, but nothing else about the library is checked, see [`Plugin::load`].
+pub struct Plugin {
+	loaded: Rc<RefCell<Loaded>>,
+	symbols: Vec<char>,
+}
+
+type InitFn = unsafe extern "C" fn(u32, *mut *mut c_void, *mut *const c_char) -> i32;
+type HandleFn = unsafe extern "C" fn(*mut c_void, c_char, *const Api) -> i32;
+type ShutdownFn = unsafe extern "C" fn(*mut c_void);
+
+/// The library of a [`Plugin`], shut down once every handler that uses it is dropped.
+struct Loaded {
+	path: PathBuf,
+	state: *mut c_void,
+	handle: HandleFn,
+	shutdown: ShutdownFn,
+	host: Box<dyn HostServices>,
+	// NOTE: dropped last, since the functions above point into it
+	_library: Library,
+}
+
+impl Plugin {
+	/// Load the plugin at `path`, whose handlers use `host` for everything outside the engine.
+	///
+	/// # Errors
+	///
+	/// Fails if the library can't be loaded, doesn't export the functions of the ABI, refuses to
+	/// load, or gives symbols that aren't ASCII.
+	///
+	/// # Safety
+	///
+	/// Loading the library runs its initialization code, and its functions are called whenever the
+	/// program reaches their instructions, with nothing to check what they do. The caller must make
+	/// sure that the library at `path`:
+	///
+	/// - has initialization code, if any, that is sound to run on the current thread,
+	/// - exports the functions of the ABI with exactly the signatures of `include/bf_plugin.h`,
+	/// - only uses the pointers it's given as described above, keeping `symbols` valid until
+	///   `bf_plugin_shutdown`,
+	/// - isn't replaced or unloaded by anything else while the plugin is alive.
+	pub unsafe fn load(
+		path: &Path,
+		host: impl HostServices + 'static,
+	) -> Result<Self, PluginError> {
+		let error = |source| PluginError::Load {
+			path: path.to_owned(),
+			source,
+		};
+
+		// SAFETY: the caller vouches for the library
+		let library = unsafe { Library::new(path) }.map_err(error)?;
+		// SAFETY: the caller promises the signatures of the ABI
+		let (init, handle, shutdown) = unsafe {
+			(
+				*library.get::<InitFn>(b"bf_plugin_init\0").map_err(error)?,
+				*library
+					.get::<HandleFn>(b"bf_plugin_handle_instruction\0")
+					.map_err(error)?,
+				*library
+					.get::<ShutdownFn>(b"bf_plugin_shutdown\0")
+					.map_err(error)?,
+			)
+		};
+
+		let mut state = ptr::null_mut();
+		let mut symbols = ptr::null();
+		// SAFETY: both pointers are valid for writes
+		let status = unsafe { init(ABI_VERSION, &mut state, &mut symbols) };
+		if status != 0 {
+			return Err(PluginError::Refused {
+				path: path.to_owned(),
+				status,
+			});
+		}
+
+		// NOTE: from here on, dropping the plugin shuts it down
+		let loaded = Rc::new(RefCell::new(Loaded {
+			path: path.to_owned(),
+			state,
+			handle,
+			shutdown,
+			host: Box::new(host),
+			_library: library,
+		}));
+
+		let symbols = if symbols.is_null() {
+			""
+		} else {
+			// SAFETY: the plugin promises a NUL-terminated string that outlives its state
+			unsafe { CStr::from_ptr(symbols) }
+				.to_str()
+				.map_err(|_| PluginError::Symbols {
+					path: path.to_owned(),
+				})?
+		};
+		if !symbols.is_ascii() {
+			return Err(PluginError::Symbols {
+				path: path.to_owned(),
+			});
+		}
+
+		Ok(Self {
+			symbols: symbols.chars().collect(),
+			loaded,
+		})
+	}
+
+	/// Instructions the plugin handles.
+	pub fn symbols(&self) -> &[char] {
+		&self.symbols
+	}
+
+	/// Add the instructions of the plugin to `extensions`, handled by the plugin.
+	///
+	/// # Errors
+	///
+	/// Fails if one of them is taken already, see [`ExtensionSet::with_handler`].
+	pub fn register(self, mut extensions: ExtensionSet) -> Result<ExtensionSet, ExtensionConflict> {
+		for symbol in self.symbols {
+			let loaded = Rc::clone(&self.loaded);
+			extensions = extensions
+				.with_handler(symbol, move |bf| loaded.borrow_mut().handle(symbol, bf))?;
+		}
+
+		Ok(extensions)
+	}
+}
+
+impl Loaded {
+	fn handle(&mut self, symbol: char, bf: &mut EngineMut<'_>) -> io::Result<()> {
+		let mut context = Context {
+			bf,
+			host: self.host.as_mut(),
+			error: None,
+		};
+		let api = Api {
+			version: ABI_VERSION,
+			context: ptr::from_mut(&mut context).cast(),
+			pointer,
+			tape_length,
+			get,
+			set,
+			set_pointer,
+			read,
+			write,
+			clock_millis,
+			random_byte,
+		};
+
+		// NOTE: symbols are ASCII, so they fit
+		// SAFETY: `api` and `context` outlive the call
+		let status = unsafe { (self.handle)(self.state, symbol as c_char, &api) };
+		if status == 0 {
+			return Ok(());
+		}
+
+		Err(context.error.unwrap_or_else(|| {
+			io::Error::other(format!(
+				"plugin {} failed to run `{symbol}` with status {status}",
+				self.path.display()
+			))
+		}))
+	}
+}
+
+impl Drop for Loaded {
+	fn drop(&mut self) {
+		// SAFETY: nothing calls into the plugin after this
+		unsafe { (self.shutdown)(self.state) };
+	}
+}
+
+/// What `struct bf_api` points its `context` at.
+struct Context<'a, 'b> {
+	bf: &'a mut EngineMut<'b>,
+	host: &'a mut dyn HostServices,
+	/// The last error of a function of the API.
+	error: Option<io::Error>,
+}
+
+/// `struct bf_api`, which handlers access the engine and the host with.
+#[repr(C)]
+struct Api {
+	version: u32,
+	context: *mut c_void,
+	pointer: extern "C" fn(*mut c_void) -> usize,
+	tape_length: extern "C" fn(*mut c_void) -> usize,
+	get: extern "C" fn(*mut c_void, usize) -> i32,
+	set: extern "C" fn(*mut c_void, usize, u8) -> i32,
+	set_pointer: extern "C" fn(*mut c_void, usize) -> i32,
+	read: extern "C" fn(*mut c_void) -> i32,
+	write: extern "C" fn(*mut c_void, u8) -> i32,
+	clock_millis: extern "C" fn(*mut c_void, *mut u64) -> i32,
+	random_byte: extern "C" fn(*mut c_void, *mut u8) -> i32,
+}
+
+/// The [`Context`] behind the `context` of `struct bf_api`.
+fn context<'a>(context: *mut c_void) -> &'a mut Context<'a, 'a> {
+	// SAFETY: plugins pass back the context they were given, which lives for the whole call
+	unsafe { &mut *context.cast() }
+}
+
+/// Keep `error` for the handler to fail with, returning -1.
+fn fail(context: &mut Context<'_, '_>, error: io::Error) -> i32 {
+	context.error = Some(error);
+	-1
+}
+
+extern "C" fn pointer(cx: *mut c_void) -> usize {
+	context(cx).bf.pointer()
+}
+
+extern "C" fn tape_length(cx: *mut c_void) -> usize {
+	context(cx).bf.view().tape().len()
+}
+
+extern "C" fn get(cx: *mut c_void, index: usize) -> i32 {
+	context(cx).bf.view().get(index).map_or(-1, i32::from)
+}
+
+extern "C" fn set(cx: *mut c_void, index: usize, value: u8) -> i32 {
+	let cx = context(cx);
+	match cx.bf.set(index, value) {
+		Ok(()) => 0,
+		Err(e) => fail(cx, io::Error::new(ErrorKind::InvalidInput, e)),
+	}
+}
+
+extern "C" fn set_pointer(cx: *mut c_void, index: usize) -> i32 {
+	let cx = context(cx);
+	match cx.bf.set_pointer(index) {
+		Ok(()) => 0,
+		Err(e) => fail(cx, io::Error::new(ErrorKind::InvalidInput, e)),
+	}
+}
+
+extern "C" fn read(cx: *mut c_void) -> i32 {
+	let cx = context(cx);
+	match cx.bf.read() {
+		Ok(Some(byte)) => byte.into(),
+		Ok(None) => -1,
+		Err(e) => {
+			fail(cx, e);
+			-2
+		}
+	}
+}
+
+extern "C" fn write(cx: *mut c_void, byte: u8) -> i32 {
+	let cx = context(cx);
+	match cx.bf.write(byte) {
+		Ok(()) => 0,
+		Err(e) => fail(cx, e),
+	}
+}
+
+extern "C" fn clock_millis(cx: *mut c_void, millis: *mut u64) -> i32 {
+	let cx = context(cx);
+	match cx.host.clock_millis() {
+		Ok(value) => {
+			// SAFETY: plugins pass a pointer they can write to
+			unsafe { *millis = value };
+			0
+		}
+		Err(e) => fail(cx, e),
+	}
+}
+
+extern "C" fn random_byte(cx: *mut c_void, byte: *mut u8) -> i32 {
+	let cx = context(cx);
+	match cx.host.random_byte() {
+		Ok(value) => {
+			// SAFETY: plugins pass a pointer they can write to
+			unsafe { *byte = value };
+			0
+		}
+		Err(e) => fail(cx, e),
+	}
+}
+
+/// An error returned when a [`Plugin`] can't be loaded.
+#[derive(Debug, Error)]
+pub enum PluginError {
+	/// The library couldn't be loaded, or doesn't export a function of the ABI
+	#[error("couldn't load plugin {}: {source}", path.display())]
+	Load {
+		/// Path of the library.
+		path: PathBuf,
+		/// Why it couldn't be loaded.
+		source: libloading::Error,
+	},
+	/// `bf_plugin_init` returned something else than 0
+	#[error("plugin {} refused to load with status {status}", path.display())]
+	Refused {
+		/// Path of the library.
+		path: PathBuf,
+		/// What `bf_plugin_init` returned.
+		status: i32,
+	},
+	/// The symbols given by `bf_plugin_init` aren't ASCII
+	#[error("plugin {} handles symbols that aren't ASCII", path.display())]
+	Symbols {
+		/// Path of the library.
+		path: PathBuf,
+	},
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::{
+		capability::Capability,
+		engine::{Engine, RuntimeSettings},
+		host::{Granted, StdHost},
+		program::Program,
+	};
+
+	#[test]
+	#[cfg(unix)]
+	fn plugins_handle_instructions_within_the_sandbox() {
+		use std::process::Command;
+
+		let directory =
+			std::env::temp_dir().join(format!("brainfuck-rs-plugin-{}", std::process::id()));
+		std::fs::create_dir_all(&directory).unwrap();
+		let header = Path::new(env!("CARGO_MANIFEST_DIR")).join("include");
+
+		// NOTE: nothing to check without a C compiler to build the plugin with
+		let Ok(status) = Command::new("cc")
+			.arg("-I")
+			.arg(&header)
+			.args(["-shared", "-fPIC", "-o", "libdouble.so", "-x", "c", "-"])
+			.current_dir(&directory)
+			.stdin(std::process::Stdio::piped())
+			.spawn()
+			.and_then(|mut child| {
+				use std::io::Write;
+
+				child.stdin.take().unwrap().write_all(
+					br#"
+#include <bf_plugin.h>
+
+int32_t bf_plugin_init(uint32_t abi_version, void **state, const char **symbols) {
+	if (abi_version != BF_PLUGIN_ABI_VERSION) return 1;
+	*symbols = "%?";
+	return 0;
+}
+
+int32_t bf_plugin_handle_instruction(void *state, char symbol, const struct bf_api *api) {
+	size_t pointer = api->pointer(api->context);
+	if (symbol == '?') {
+		uint8_t byte;
+		return api->random_byte(api->context, &byte);
+	}
+	return api->set(api->context, pointer, api->get(api->context, pointer) * 2);
+}
+
+void bf_plugin_shutdown(void *state) {}
+"#,
+				)?;
+				child.wait()
+			})
+		else {
+			return;
+		};
+		assert!(status.success());
+
+		let path = directory.join("libdouble.so");
+		let run = |code: &str, capabilities: &[Capability]| {
+			let host = Granted {
+				host: StdHost::default(),
+				capabilities: capabilities.iter().copied().collect(),
+			};
+			// SAFETY: the library was just built from the code above
+			let extensions = unsafe { Plugin::load(&path, host) }
+				.unwrap()
+				.register(ExtensionSet::default())
+				.unwrap();
+			let program = Program::parse_with_extensions(code, &extensions).unwrap();
+
+			let mut output = vec![];
+			Engine::default()
+				.run(
+					&program.instructions,
+					&mut [].as_slice(),
+					&mut output,
+					RuntimeSettings {
+						extensions,
+						..Default::default()
+					},
+				)
+				.map(|_| output)
+		};
+
+		assert_eq!(vec![5 * 8], run("+++++%%%.", &[]).unwrap());
+		assert!(run("?", &[]).is_err());
+		assert!(run("?", &[Capability::Random]).is_ok());
+
+		std::fs::remove_dir_all(&directory).unwrap();
+	}
+}
@@ -0,0 +1,130 @@
+use std::{
+	num::Wrapping,
+	sync::{Mutex, MutexGuard, PoisonError},
+};
+
+use crate::{engine::Engine, labels::CellLabels};
+
+/// Tapes kept around for reuse, so running many short programs, or one program many times,
+/// doesn't allocate a fresh tape for every run.
+///
+/// Engines are taken out of the pool, run, and given back once they're done with. The pool can
+/// be shared between threads. Tapes are cleared with [`Engine::reset_fast`], so only the cells
+/// the previous run got to are set to zero again.
+///
+/// # Usage
+///
+/// ```
+/// # use brainfuck_rs::{
+/// #   engine::{Engine, RuntimeSettings},
+/// #   instruction::Instruction,
+/// #   pool::TapePool,
+/// #   token::Token,
+/// # };
+/// let instructions = Instruction::parse(Token::tokenize("++++++[>++++++++<-]>.")).unwrap();
+/// let pool = TapePool::new();
+///
+/// for _ in 0..3 {
+///     let mut bf = pool.take(30_000);
+///     let mut output = vec![];
+///     bf.run(&instructions, &mut [].as_slice(), &mut output, RuntimeSettings::default())
+///         .unwrap();
+///
+///     assert_eq!(b"0", output.as_slice());
+///     pool.give(bf);
+/// }
+///
+/// assert_eq!(1, pool.len());
+/// ```
+#[derive(Debug, Default)]
+pub struct TapePool {
+	engines: Mutex<Vec<Engine>>,
+}
+
+impl TapePool {
+	/// An empty pool.
+	pub fn new() -> Self {
+		Self::default()
+	}
+
+	/// An engine with `length` empty cells and the pointer on the first one, on a tape from the
+	/// pool if there is one.
+	pub fn take(&self, length: usize) -> Engine {
+		let Some(mut bf) = self.lock().pop() else {
+			return Engine::new(length);
+		};
+		bf.reset_fast();
+		bf.tape.resize(length, Wrapping(0));
+		bf.labels = CellLabels::default();
+
+		bf
+	}
+
+	/// A copy of `bf`, on a tape from the pool if there is one.
+	pub fn copy(&self, bf: &Engine) -> Engine {
+		let Some(mut copy) = self.lock().pop() else {
+			return bf.clone();
+		};
+		copy.tape.clone_from(&bf.tape);
+		copy.pointer = bf.pointer;
+		copy.touched = bf.touched;
+		copy.labels.clone_from(&bf.labels);
+
+		copy
+	}
+
+	/// Put an engine that's done with back into the pool.
+	///
+	/// Its tape must not have been changed by hand since it was taken, see
+	/// [`Engine::reset_fast`].
+	pub fn give(&self, bf: Engine) {
+		self.lock().push(bf);
+	}
+
+	/// Number of tapes in the pool.
+	pub fn len(&self) -> usize {
+		self.lock().len()
+	}
+
+	/// `true` if the pool has no tapes.
+	pub fn is_empty(&self) -> bool {
+		self.len() == 0
+	}
+
+	fn lock(&self) -> MutexGuard<'_, Vec<Engine>> {
+		// NOTE: engines are only pushed and popped, so a panic can't leave the list half-changed
+		self.engines.lock().unwrap_or_else(PoisonError::into_inner)
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use crate::{engine::RuntimeSettings, instruction::Instruction, token::Token};
+
+	use super::*;
+
+	#[test]
+	fn tapes_are_reused() {
+		let pool = TapePool::new();
+
+		let mut bf = pool.take(4);
+		bf.run(
+			&Instruction::parse(Token::tokenize(">>>+++++++")).unwrap(),
+			&mut [].as_slice(),
+			&mut vec![],
+			RuntimeSettings::default(),
+		)
+		.unwrap();
+		let address = bf.tape.as_ptr();
+		pool.give(bf);
+
+		let bf = pool.take(4);
+		assert_eq!(vec![Wrapping(0); 4], bf.tape);
+		assert_eq!(0, bf.pointer);
+		assert_eq!(address, bf.tape.as_ptr());
+
+		let copy = pool.copy(&bf);
+		assert_eq!(bf, copy);
+		assert!(pool.is_empty());
+	}
+}
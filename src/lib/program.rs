@@ -0,0 +1,1271 @@
+use std::{
+	collections::{BTreeMap, BTreeSet, HashMap},
+	fmt::Write,
+	io, mem, slice,
+};
+
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+
+use crate::{
+	analysis,
+	capability::{self, Capability},
+	engine::{Engine, RuntimeSettings},
+	extension::Extension,
+	hooks::{Hooks, Wrap},
+	host::StdHost,
+	instruction::{Instruction, ParseError},
+	token::{Span, Token},
+};
+
+/// Version of the exported AST schema.
+pub const AST_VERSION: u32 = 1;
+
+/// A parsed Brainfuck program along with the source locations of its instructions.
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct Program {
+	/// The instruction tree.
+	pub instructions: Vec<Instruction>,
+	/// Source locations of the instructions in pre-order, that is, in the order their first
+	/// character appears in the source code. A loop's span covers both of its brackets.
+	///
+	/// May be empty if the program wasn't parsed from source code.
+	pub spans: Vec<Span>,
+}
+
+impl Program {
+	/// Tokenize and parse Brainfuck source code, keeping track of where every instruction came from.
+	///
+	/// # Usage
+	///
+	/// ```
+	/// # use brainfuck_rs::program::Program;
+	/// let program = Program::parse("+[-]").unwrap();
+	///
+	/// assert_eq!(program.spans[1].column, 2);
+	/// ```
+	///
+	/// # Errors
+	///
+	/// It may error if there is unmatched loop start or loop end.
+	pub fn parse(code: &str) -> Result<Self, ParseError> {
+		Self::parse_with_extensions(code, &[])
+	}
+
+	/// Parse Brainfuck source code like [`Program::parse`], but also recognize the instructions of
+	/// the given extensions.
+	///
+	/// # Errors
+	///
+	/// It may error if there is unmatched loop start or loop end.
+	pub fn parse_with_extensions(code: &str, extensions: &[Extension]) -> Result<Self, ParseError> {
+		let tokens: Vec<(Token, Span)> = Token::tokenize_spanned(code, extensions).collect();
+
+		let instructions = Instruction::parse(tokens.iter().map(|(token, _)| *token))?;
+
+		let mut spans = Vec::with_capacity(tokens.len());
+		let mut open_loops = vec![];
+		for (token, span) in tokens {
+			match token {
+				Token::LoopStart => {
+					open_loops.push(spans.len());
+					spans.push(span);
+				}
+				Token::LoopEnd => {
+					// NOTE: brackets are already known to be balanced at this point
+					if let Some(index) = open_loops.pop() {
+						spans[index].end = span.end;
+					}
+				}
+				_ => spans.push(span),
+			}
+		}
+
+		Ok(Self {
+			instructions,
+			spans,
+		})
+	}
+
+	/// Capabilities the program's extension instructions need in order to run.
+	///
+	/// [`Engine::run`](`crate::engine::Engine::run`) refuses to run a program unless all of them
+	/// are granted.
+	pub fn required_capabilities(&self) -> BTreeSet<Capability> {
+		capability::required_by(&self.instructions)
+	}
+
+	/// Source location of the instruction with the given pre-order index.
+	pub fn span(&self, index: usize) -> Option<Span> {
+		self.spans.get(index).copied()
+	}
+
+	/// Pre-order index of an instruction of this program, like one handed to [`Hooks`], found by
+	/// its address.
+	///
+	/// [`Hooks`]: crate::hooks::Hooks
+	pub fn index_of(&self, instruction: &Instruction) -> Option<usize> {
+		fn walk(
+			instructions: &[Instruction],
+			target: &Instruction,
+			index: &mut usize,
+		) -> Option<usize> {
+			for instruction in instructions {
+				if std::ptr::eq(instruction, target) {
+					return Some(*index);
+				}
+				*index += 1;
+
+				if let Instruction::Loop(body) = instruction {
+					if let Some(found) = walk(body, target, index) {
+						return Some(found);
+					}
+				}
+			}
+
+			None
+		}
+
+		walk(&self.instructions, instruction, &mut 0)
+	}
+
+	/// Pre-order index of every instruction, keyed by its address, for hooks that look up every
+	/// instruction they're handed.
+	pub(crate) fn indices(&self) -> HashMap<*const Instruction, usize> {
+		fn walk(instructions: &[Instruction], indices: &mut HashMap<*const Instruction, usize>) {
+			for instruction in instructions {
+				indices.insert(instruction, indices.len());
+
+				if let Instruction::Loop(body) = instruction {
+					walk(body, indices);
+				}
+			}
+		}
+
+		let mut indices = HashMap::new();
+		walk(&self.instructions, &mut indices);
+
+		indices
+	}
+
+	/// Remove adjacent instructions that undo each other, `+-`, `-+`, `><` and `<>`, along with
+	/// their spans.
+	///
+	/// Pairs are removed until there are none left, so `+><-` disappears as a whole. Cells and the
+	/// pointer wrap around, so the program prints the same, but it takes fewer steps, which
+	/// budgets and [`RunSummary::steps`](`crate::engine::RunSummary::steps`) see.
+	///
+	/// # Usage
+	///
+	/// ```
+	/// # use brainfuck_rs::program::Program;
+	/// let program = Program::parse("+><-[-+]>").unwrap().cancel_opposites();
+	///
+	/// assert_eq!(Program::parse("[]>").unwrap().instructions, program.instructions);
+	/// ```
+	#[must_use]
+	pub fn cancel_opposites(&self) -> Self {
+		let mut spans = Vec::with_capacity(self.spans.len());
+		let mut index = 0;
+		let mut rest = self.instructions.iter();
+		let mut kept: Vec<Instruction> = Vec::with_capacity(self.instructions.len());
+		// NOTE: the rest of the bodies around the one being walked, and what's kept of them so far,
+		// innermost last, so that deeply nested loops can't overflow the stack
+		let mut open: Vec<(slice::Iter<'_, Instruction>, Vec<Instruction>)> = vec![];
+
+		loop {
+			let Some(instruction) = rest.next() else {
+				let Some((outer_rest, outer_kept)) = open.pop() else {
+					break;
+				};
+
+				rest = outer_rest;
+				let body = mem::replace(&mut kept, outer_kept);
+				kept.push(Instruction::Loop(body));
+				continue;
+			};
+
+			let span = self.spans.get(index).copied();
+			index += 1;
+
+			match (kept.last(), instruction) {
+				(_, Instruction::Loop(body)) => {
+					spans.extend(span);
+					open.push((
+						mem::replace(&mut rest, body.iter()),
+						mem::replace(&mut kept, Vec::with_capacity(body.len())),
+					));
+				}
+				(Some(Instruction::Inc), Instruction::Dec)
+				| (Some(Instruction::Dec), Instruction::Inc)
+				| (Some(Instruction::Next), Instruction::Prev)
+				| (Some(Instruction::Prev), Instruction::Next) => {
+					// NOTE: the last span is the one of the instruction undone, since only loops
+					// push spans after their own
+					kept.pop();
+					if span.is_some() {
+						spans.pop();
+					}
+				}
+				_ => {
+					spans.extend(span);
+					kept.push(instruction.clone());
+				}
+			}
+		}
+
+		Self {
+			instructions: kept,
+			spans,
+		}
+	}
+
+	/// Replace the loops that only count their cell down or up, `[-]` and `[+]`, with
+	/// [`Instruction::Clear`], dropping the spans of their bodies.
+	///
+	/// Cells wrap around, so both loops end once the cell is zero, but a clear gets there in a
+	/// single step however big the cell was, which budgets and
+	/// [`RunSummary::steps`](`crate::engine::RunSummary::steps`) see.
+	///
+	/// # Usage
+	///
+	/// ```
+	/// # use brainfuck_rs::{instruction::Instruction, program::Program};
+	/// let program = Program::parse("+[[-]>[+]]").unwrap().clear_loops();
+	///
+	/// assert_eq!(
+	///     vec![
+	///         Instruction::Inc,
+	///         Instruction::Loop(vec![Instruction::Clear, Instruction::Next, Instruction::Clear])
+	///     ],
+	///     program.instructions
+	/// );
+	/// assert_eq!(5, program.spans.len());
+	/// ```
+	#[must_use]
+	pub fn clear_loops(&self) -> Self {
+		let mut spans = Vec::with_capacity(self.spans.len());
+		let mut index = 0;
+		let mut rest = self.instructions.iter();
+		let mut kept: Vec<Instruction> = Vec::with_capacity(self.instructions.len());
+		// NOTE: like in `cancel_opposites`, innermost last
+		let mut open: Vec<(slice::Iter<'_, Instruction>, Vec<Instruction>)> = vec![];
+
+		loop {
+			let Some(instruction) = rest.next() else {
+				let Some((outer_rest, outer_kept)) = open.pop() else {
+					break;
+				};
+
+				rest = outer_rest;
+				let body = mem::replace(&mut kept, outer_kept);
+				kept.push(Instruction::Loop(body));
+				continue;
+			};
+
+			spans.extend(self.span(index));
+			index += 1;
+
+			match instruction {
+				Instruction::Loop(body)
+					if matches!(body[..], [Instruction::Inc | Instruction::Dec]) =>
+				{
+					// NOTE: the span of the loop covers its body
+					index += 1;
+					kept.push(Instruction::Clear);
+				}
+				Instruction::Loop(body) => open.push((
+					mem::replace(&mut rest, body.iter()),
+					mem::replace(&mut kept, Vec::with_capacity(body.len())),
+				)),
+				_ => kept.push(instruction.clone()),
+			}
+		}
+
+		Self {
+			instructions: kept,
+			spans,
+		}
+	}
+
+	/// Collapse runs of the same instruction, like `+++` or `<<`, into [`Instruction::Add`] and
+	/// [`Instruction::Move`], whose spans cover the whole run.
+	///
+	/// A run takes a single step rather than one per instruction, which budgets and
+	/// [`RunSummary::steps`](`crate::engine::RunSummary::steps`) see. Runs of `+` longer than 127
+	/// and of `-` longer than 128 are split, so that adds fit into an `i8`. Runs of `>` and `<`
+	/// aren't merged with each other, since moving back and forth doesn't end where moving the
+	/// difference does on a tape that grows; [`Program::cancel_opposites`] removes what can be.
+	///
+	/// # Usage
+	///
+	/// ```
+	/// # use brainfuck_rs::{instruction::Instruction, program::Program};
+	/// let program = Program::parse("+++[->>+<<]").unwrap().collapse_runs();
+	///
+	/// assert_eq!(
+	///     vec![
+	///         Instruction::Add(3),
+	///         Instruction::Loop(vec![
+	///             Instruction::Dec,
+	///             Instruction::Move(2),
+	///             Instruction::Inc,
+	///             Instruction::Move(-2)
+	///         ])
+	///     ],
+	///     program.instructions
+	/// );
+	/// assert_eq!(6, program.spans.len());
+	/// ```
+	#[must_use]
+	pub fn collapse_runs(&self) -> Self {
+		let mut spans = Vec::with_capacity(self.spans.len());
+		let mut index = 0;
+		let mut rest: &[Instruction] = &self.instructions;
+		let mut kept: Vec<Instruction> = Vec::with_capacity(self.instructions.len());
+		// NOTE: like in `cancel_opposites`, innermost last
+		let mut open: Vec<(&[Instruction], Vec<Instruction>)> = vec![];
+
+		loop {
+			let Some(instruction) = rest.first() else {
+				let Some((outer_rest, outer_kept)) = open.pop() else {
+					break;
+				};
+
+				rest = outer_rest;
+				let body = mem::replace(&mut kept, outer_kept);
+				kept.push(Instruction::Loop(body));
+				continue;
+			};
+
+			// NOTE: the longest a run can be, and what it collapses into
+			let (limit, collapse): (usize, fn(usize) -> Instruction) = match instruction {
+				Instruction::Inc => (127, |length| Instruction::Add(length as i8)),
+				Instruction::Dec => (128, |length| {
+					Instruction::Add((length as i8).wrapping_neg())
+				}),
+				Instruction::Next => (i32::MAX as usize, |length| Instruction::Move(length as i32)),
+				Instruction::Prev => (i32::MAX as usize, |length| {
+					Instruction::Move(-(length as i32))
+				}),
+				Instruction::Loop(body) => {
+					spans.extend(self.span(index));
+					index += 1;
+					open.push((
+						&rest[1..],
+						mem::replace(&mut kept, Vec::with_capacity(body.len())),
+					));
+					rest = body;
+					continue;
+				}
+				_ => (1, |_| {
+					unreachable!("only runs of one instruction are collapsed")
+				}),
+			};
+
+			let length = rest
+				.iter()
+				.take(limit)
+				.take_while(|next| *next == instruction)
+				.count();
+			if let (Some(first), Some(last)) = (self.span(index), self.span(index + length - 1)) {
+				spans.push(Span {
+					end: last.end,
+					..first
+				});
+			}
+			index += length;
+			kept.push(if length == 1 {
+				instruction.clone()
+			} else {
+				collapse(length)
+			});
+			rest = &rest[length..];
+		}
+
+		Self {
+			instructions: kept,
+			spans,
+		}
+	}
+
+	/// Pre-order indices of the loops that are never entered, whatever the tape: the ones the
+	/// program starts with, when every cell is zero, and the ones right after another loop, which
+	/// only ends once the current cell is zero. Published programs often start with a loop that's
+	/// a comment.
+	pub fn trivially_dead_loops(&self) -> BTreeSet<usize> {
+		let mut dead = BTreeSet::new();
+		let mut index = 0;
+		// NOTE: the rest of every body being walked, and whether the instruction before comes
+		// right after a loop, innermost last, so that deeply nested loops can't overflow the stack
+		let mut open = vec![(self.instructions.iter(), true)];
+
+		while let Some((rest, after_loop)) = open.last_mut() {
+			let Some(instruction) = rest.next() else {
+				open.pop();
+				continue;
+			};
+
+			if *after_loop && matches!(instruction, Instruction::Loop(_)) {
+				dead.insert(index);
+			}
+			index += 1;
+
+			*after_loop = matches!(instruction, Instruction::Loop(_) | Instruction::Clear);
+			if let Instruction::Loop(body) = instruction {
+				open.push((body.iter(), false));
+			}
+		}
+
+		dead
+	}
+
+	/// Remove the loops [`analysis::value_ranges`] finds are never entered, like comment loops at
+	/// the start of a program, along with their spans.
+	///
+	/// The analysis only holds if the tape is longer than the distance between the cells it tells
+	/// apart, see [`ValueRanges::spread`](`crate::analysis::ValueRanges::spread`). On shorter
+	/// tapes, only loops that are never entered on any tape are removed: ones at the start of the
+	/// program, and ones right after another loop, see [`Program::trivially_dead_loops`].
+	///
+	/// # Usage
+	///
+	/// ```
+	/// # use brainfuck_rs::program::Program;
+	/// let program = Program::parse("[comment.]+[-]").unwrap().remove_dead_loops(30_000);
+	///
+	/// assert_eq!(Program::parse("+[-]").unwrap().instructions, program.instructions);
+	/// ```
+	#[must_use]
+	pub fn remove_dead_loops(&self, tape_length: usize) -> Self {
+		let ranges = analysis::value_ranges(self);
+		let dead = if ranges.spread < tape_length {
+			ranges.dead_loops.into_iter().collect()
+		} else {
+			self.trivially_dead_loops()
+		};
+		if dead.is_empty() {
+			return self.clone();
+		}
+
+		let mut spans = Vec::with_capacity(self.spans.len());
+		let mut index = 0;
+		let mut rest = self.instructions.iter();
+		let mut kept: Vec<Instruction> = Vec::with_capacity(self.instructions.len());
+		// NOTE: the rest of the bodies around the one being walked, and what's kept of them so far,
+		// innermost last, so that deeply nested loops can't overflow the stack
+		let mut open: Vec<(slice::Iter<'_, Instruction>, Vec<Instruction>)> = vec![];
+
+		loop {
+			let Some(instruction) = rest.next() else {
+				let Some((outer_rest, outer_kept)) = open.pop() else {
+					break;
+				};
+
+				rest = outer_rest;
+				let body = mem::replace(&mut kept, outer_kept);
+				kept.push(Instruction::Loop(body));
+				continue;
+			};
+
+			if dead.contains(&index) {
+				index += count_instructions(slice::from_ref(instruction));
+				continue;
+			}
+
+			spans.extend(self.spans.get(index).copied());
+			index += 1;
+
+			match instruction {
+				Instruction::Loop(body) => open.push((
+					mem::replace(&mut rest, body.iter()),
+					mem::replace(&mut kept, Vec::with_capacity(body.len())),
+				)),
+				_ => kept.push(instruction.clone()),
+			}
+		}
+
+		Self {
+			instructions: kept,
+			spans,
+		}
+	}
+
+	/// Run the part of the program before it first reads input or uses an extension ahead of time,
+	/// on a tape of `tape_length` cells, and replace it with instructions that print what it
+	/// printed and leave the tape as it left it.
+	///
+	/// Programs that read no input, like a hello world, become nothing but their output. Nothing
+	/// changes if that part doesn't end within `max_steps` instructions, or moves the pointer past
+	/// either end of the tape, since the tape may be different when the program runs. The new
+	/// instructions get the span of the whole part they replace. Like every pass, it expects the
+	/// program to start on a tape of zeros.
+	///
+	/// # Usage
+	///
+	/// ```
+	/// # use brainfuck_rs::program::Program;
+	/// let program = Program::parse("++[>+++<-]>[<+>-]<.,").unwrap().evaluate_prefix(30_000, 1000);
+	///
+	/// assert_eq!(Program::parse("++++++.,").unwrap().instructions, program.instructions);
+	/// ```
+	#[must_use]
+	pub fn evaluate_prefix(&self, tape_length: usize, max_steps: u64) -> Self {
+		/// Hooks that stop the program once the pointer wraps around.
+		#[derive(Default)]
+		struct PointerWrap(bool);
+
+		impl Hooks for PointerWrap {
+			fn on_wrap(&mut self, _step: u64, wrap: Wrap) {
+				self.0 |= matches!(wrap, Wrap::PointerPastEnd | Wrap::PointerPastStart);
+			}
+
+			fn should_break(&mut self) -> bool {
+				self.0
+			}
+		}
+
+		/// Instructions that turn `from` into `to`, with `+` or `-`, whichever is shorter.
+		fn change(output: &mut Vec<Instruction>, from: u8, to: u8) {
+			let delta = to.wrapping_sub(from);
+			if delta <= 128 {
+				output.extend(std::iter::repeat_n(Instruction::Inc, delta.into()));
+			} else {
+				output.extend(std::iter::repeat_n(
+					Instruction::Dec,
+					delta.wrapping_neg().into(),
+				));
+			}
+		}
+
+		let length = self
+			.instructions
+			.iter()
+			.position(needs_outside)
+			.unwrap_or(self.instructions.len());
+		let (prefix, rest) = self.instructions.split_at(length);
+		if prefix.is_empty() {
+			return self.clone();
+		}
+
+		let mut bf = Engine::new(tape_length);
+		let mut output = vec![];
+		let mut wrap = PointerWrap::default();
+		let settings = RuntimeSettings {
+			budget: Some(max_steps),
+			..Default::default()
+		};
+		let result = bf.run_with(
+			prefix,
+			&mut io::empty(),
+			&mut output,
+			settings,
+			&mut StdHost::default(),
+			&mut wrap,
+		);
+		if result.is_err() || wrap.0 {
+			return self.clone();
+		}
+
+		let mut instructions = vec![];
+		let mut current = 0;
+		for &byte in &output {
+			change(&mut instructions, current, byte);
+			instructions.push(Instruction::Print);
+			current = byte;
+		}
+
+		// NOTE: the tape only matters if there's something left to run on it
+		if !rest.is_empty() {
+			let last = bf
+				.tape
+				.iter()
+				.rposition(|cell| cell.0 != 0)
+				.map_or(bf.pointer, |last| last.max(bf.pointer));
+
+			for (index, cell) in bf.tape[..=last].iter().enumerate() {
+				if index > 0 {
+					instructions.push(Instruction::Next);
+					current = 0;
+				}
+				change(&mut instructions, current, cell.0);
+			}
+			instructions.extend(std::iter::repeat_n(Instruction::Prev, last - bf.pointer));
+		}
+
+		let prefix_spans = count_instructions(prefix);
+		let mut spans = vec![];
+		if let (Some(first), Some(last)) = (
+			self.spans.first(),
+			self.span(count_instructions(&prefix[..prefix.len() - 1])),
+		) {
+			let span = Span {
+				end: last.end,
+				..*first
+			};
+			spans.extend(std::iter::repeat_n(span, instructions.len()));
+			spans.extend_from_slice(&self.spans[prefix_spans..]);
+		}
+
+		instructions.extend_from_slice(rest);
+
+		Self {
+			instructions,
+			spans,
+		}
+	}
+
+	/// Rewrite the program into a normal form, so programs that only differ in how they're
+	/// written are the same, see [`Program::structurally_eq`].
+	///
+	/// Arithmetic and pointer movement between other instructions is replaced by what it amounts
+	/// to: the change of every cell it touches, in the order of their offsets, and then the move
+	/// of the pointer to where it ends. Changes are written with `+` or `-`, whichever is shorter,
+	/// and loops that clear the cell become `[-]`. Spans are dropped, since instructions no longer
+	/// come from anywhere in the source code.
+	///
+	/// # Usage
+	///
+	/// ```
+	/// # use brainfuck_rs::program::Program;
+	/// let program = Program::parse(">+<----[+++]").unwrap().canonicalize();
+	///
+	/// assert_eq!(Program::parse("---->+<[-]").unwrap().instructions, program.instructions);
+	/// ```
+	#[must_use]
+	pub fn canonicalize(&self) -> Self {
+		fn shift(output: &mut Vec<Instruction>, offset: isize) {
+			let instruction = if offset < 0 {
+				Instruction::Prev
+			} else {
+				Instruction::Next
+			};
+			output.extend(std::iter::repeat_n(instruction, offset.unsigned_abs()));
+		}
+
+		fn flush(
+			output: &mut Vec<Instruction>,
+			deltas: &mut BTreeMap<isize, u8>,
+			offset: &mut isize,
+		) {
+			let mut position = 0;
+
+			for (&target, &delta) in deltas.iter().filter(|(_, &delta)| delta != 0) {
+				shift(output, target - position);
+				position = target;
+
+				// NOTE: 128 is as long either way, and `+` is picked for it
+				if delta <= 128 {
+					output.extend(std::iter::repeat_n(Instruction::Inc, delta.into()));
+				} else {
+					output.extend(std::iter::repeat_n(
+						Instruction::Dec,
+						(u8::MAX - delta + 1).into(),
+					));
+				}
+			}
+			shift(output, *offset - position);
+
+			deltas.clear();
+			*offset = 0;
+		}
+
+		fn walk(instructions: &[Instruction]) -> Vec<Instruction> {
+			let mut output = vec![];
+			let mut deltas = BTreeMap::new();
+			let mut offset = 0;
+
+			for instruction in instructions {
+				match instruction {
+					Instruction::Inc => {
+						let delta = deltas.entry(offset).or_default();
+						*delta = u8::wrapping_add(*delta, 1);
+					}
+					Instruction::Dec => {
+						let delta = deltas.entry(offset).or_default();
+						*delta = u8::wrapping_sub(*delta, 1);
+					}
+					Instruction::Add(amount) => {
+						let delta = deltas.entry(offset).or_default();
+						*delta = u8::wrapping_add_signed(*delta, *amount);
+					}
+					Instruction::Next => offset += 1,
+					Instruction::Prev => offset -= 1,
+					Instruction::Move(distance) => offset += *distance as isize,
+					Instruction::Loop(body) => {
+						flush(&mut output, &mut deltas, &mut offset);
+
+						// NOTE: adding an odd number visits every value of a cell, so it's bound to
+						// reach zero
+						let clears = analysis::cell_deltas(body).is_some_and(|deltas| {
+							deltas.len() == 1 && deltas.get(&0).is_some_and(|delta| delta % 2 == 1)
+						});
+						output.push(Instruction::Loop(if clears {
+							vec![Instruction::Dec]
+						} else {
+							walk(body)
+						}));
+					}
+					// NOTE: clears are the same as the loops they came from
+					Instruction::Clear => {
+						flush(&mut output, &mut deltas, &mut offset);
+						output.push(Instruction::Loop(vec![Instruction::Dec]));
+					}
+					Instruction::Print | Instruction::Read | Instruction::Extension(_) => {
+						flush(&mut output, &mut deltas, &mut offset);
+						output.push(instruction.clone());
+					}
+				}
+			}
+			flush(&mut output, &mut deltas, &mut offset);
+
+			output
+		}
+
+		Self {
+			instructions: walk(&self.instructions),
+			spans: vec![],
+		}
+	}
+
+	/// Whether both programs have the same [normal form](`Program::canonicalize`), that is, they
+	/// run the same algorithm, even if they're written differently.
+	///
+	/// # Usage
+	///
+	/// ```
+	/// # use brainfuck_rs::program::Program;
+	/// let program = Program::parse("+>+<[+]").unwrap();
+	///
+	/// assert!(program.structurally_eq(&Program::parse(">+<+[---]").unwrap()));
+	/// assert!(!program.structurally_eq(&Program::parse("+>+[+]").unwrap()));
+	/// ```
+	pub fn structurally_eq(&self, other: &Self) -> bool {
+		self.canonicalize().instructions == other.canonicalize().instructions
+	}
+
+	/// Export the instruction tree with spans as JSON.
+	///
+	/// The document has the following shape:
+	///
+	/// ```json
+	/// {
+	///   "version": 1,
+	///   "instructions": [
+	///     { "op": "inc", "span": { "start": 0, "end": 1, "line": 1, "column": 1 } },
+	///     { "op": "loop", "span": { ... }, "body": [ ... ] }
+	///   ]
+	/// }
+	/// ```
+	///
+	/// Adds and moves have an `amount`. Spans are omitted if the program doesn't have them.
+	pub fn to_json(&self) -> String {
+		let document = AstDocument {
+			version: AST_VERSION,
+			instructions: self.to_nodes(),
+		};
+
+		serde_json::to_string_pretty(&document).expect("AST serialization never fails")
+	}
+
+	/// Export the instruction tree with spans as an S-expression, e.g. `(program (inc 1:1) (loop
+	/// 1:2 (dec 1:3)))`.
+	pub fn to_sexpr(&self) -> String {
+		fn write_nodes(output: &mut String, nodes: &[AstNode]) {
+			for node in nodes {
+				let _ = write!(output, " ({}", node.op.name());
+
+				if let Some(amount) = node.amount {
+					let _ = write!(output, " {amount}");
+				}
+
+				if let Some(span) = node.span {
+					let _ = write!(output, " {span}");
+				}
+
+				if let Some(body) = &node.body {
+					write_nodes(output, body);
+				}
+
+				output.push(')');
+			}
+		}
+
+		let mut output = String::from("(program");
+		write_nodes(&mut output, &self.to_nodes());
+		output.push(')');
+
+		output
+	}
+
+	fn to_nodes(&self) -> Vec<AstNode> {
+		fn convert(
+			instructions: &[Instruction],
+			spans: Option<&[Span]>,
+			index: &mut usize,
+		) -> Vec<AstNode> {
+			instructions
+				.iter()
+				.map(|instruction| {
+					let span = spans.map(|spans| spans[*index]);
+					*index += 1;
+
+					let (op, body) = match instruction {
+						Instruction::Inc => (AstOp::Inc, None),
+						Instruction::Dec => (AstOp::Dec, None),
+						Instruction::Next => (AstOp::Next, None),
+						Instruction::Prev => (AstOp::Prev, None),
+						Instruction::Print => (AstOp::Print, None),
+						Instruction::Read => (AstOp::Read, None),
+						Instruction::Clear => (AstOp::Clear, None),
+						Instruction::Add(_) => (AstOp::Add, None),
+						Instruction::Move(_) => (AstOp::Move, None),
+						Instruction::Extension(Extension::Time) => (AstOp::Time, None),
+						Instruction::Extension(Extension::Exit) => (AstOp::Exit, None),
+						Instruction::Extension(Extension::Custom(_)) => (AstOp::Custom, None),
+						Instruction::Loop(inner) => {
+							(AstOp::Loop, Some(convert(inner, spans, index)))
+						}
+					};
+					let symbol = match instruction {
+						Instruction::Extension(Extension::Custom(symbol)) => Some(*symbol),
+						_ => None,
+					};
+					let amount = match instruction {
+						Instruction::Add(amount) => Some((*amount).into()),
+						Instruction::Move(distance) => Some(*distance),
+						_ => None,
+					};
+
+					AstNode {
+						op,
+						symbol,
+						amount,
+						span,
+						body,
+					}
+				})
+				.collect()
+		}
+
+		let spans =
+			(self.spans.len() == count_instructions(&self.instructions)).then_some(&self.spans[..]);
+
+		convert(&self.instructions, spans, &mut 0)
+	}
+
+	/// Import a program exported with [`Program::to_json`], skipping tokenization and parsing.
+	///
+	/// Spans are optional, but if one instruction has a span, all of them must have one.
+	///
+	/// # Usage
+	///
+	/// ```
+	/// # use brainfuck_rs::{instruction::Instruction, program::Program};
+	/// let json = r#"{ "version": 1, "instructions": [{ "op": "loop", "body": [{ "op": "dec" }] }] }"#;
+	///
+	/// let program = Program::from_json(json).unwrap();
+	///
+	/// assert_eq!(vec![Instruction::Loop(vec![Instruction::Dec])], program.instructions);
+	/// ```
+	///
+	/// # Errors
+	///
+	/// It errors if the document isn't valid JSON, uses an unsupported schema version, or doesn't
+	/// describe a valid instruction tree. Errors point at the offending instruction, e.g.
+	/// `instructions[2].body[0]`.
+	pub fn from_json(json: &str) -> Result<Self, ImportError> {
+		fn convert(
+			nodes: Vec<AstNode>,
+			path: &str,
+			spans: &mut Vec<Span>,
+			spanless: &mut usize,
+		) -> Result<Vec<Instruction>, ImportError> {
+			let mut instructions = Vec::with_capacity(nodes.len());
+
+			for (index, node) in nodes.into_iter().enumerate() {
+				let path = format!("{path}[{index}]");
+
+				match node.span {
+					Some(span) => spans.push(span),
+					None => *spanless += 1,
+				}
+
+				let instruction = match (node.op, node.body) {
+					(AstOp::Custom, None) => match node.symbol {
+						Some(symbol) => Instruction::Extension(Extension::Custom(symbol)),
+						None => {
+							return Err(ImportError::Malformed(format!("{path} has no symbol")))
+						}
+					},
+					(AstOp::Add | AstOp::Move, None) => match (node.op, node.amount) {
+						(_, None) => {
+							return Err(ImportError::Malformed(format!("{path} has no amount")))
+						}
+						(AstOp::Add, Some(amount)) => {
+							Instruction::Add(i8::try_from(amount).map_err(|_| {
+								ImportError::Malformed(format!(
+									"{path} adds {amount}, which is out of range"
+								))
+							})?)
+						}
+						(_, Some(distance)) => Instruction::Move(distance),
+					},
+					(AstOp::Loop, Some(body)) => {
+						Instruction::Loop(convert(body, &format!("{path}.body"), spans, spanless)?)
+					}
+					(AstOp::Loop, None) => return Err(ImportError::MissingBody { path }),
+					(_, Some(_)) => return Err(ImportError::UnexpectedBody { path }),
+					(AstOp::Inc, None) => Instruction::Inc,
+					(AstOp::Dec, None) => Instruction::Dec,
+					(AstOp::Next, None) => Instruction::Next,
+					(AstOp::Prev, None) => Instruction::Prev,
+					(AstOp::Print, None) => Instruction::Print,
+					(AstOp::Read, None) => Instruction::Read,
+					(AstOp::Clear, None) => Instruction::Clear,
+					(AstOp::Time, None) => Instruction::Extension(Extension::Time),
+					(AstOp::Exit, None) => Instruction::Extension(Extension::Exit),
+				};
+
+				instructions.push(instruction);
+			}
+
+			Ok(instructions)
+		}
+
+		let document: AstDocument =
+			serde_json::from_str(json).map_err(|e| ImportError::Malformed(e.to_string()))?;
+
+		if document.version != AST_VERSION {
+			return Err(ImportError::UnsupportedVersion {
+				found: document.version,
+				supported: AST_VERSION,
+			});
+		}
+
+		let mut spans = vec![];
+		let mut spanless = 0;
+		let instructions = convert(
+			document.instructions,
+			"instructions",
+			&mut spans,
+			&mut spanless,
+		)?;
+
+		if !spans.is_empty() && spanless > 0 {
+			return Err(ImportError::PartialSpans);
+		}
+
+		Ok(Self {
+			instructions,
+			spans,
+		})
+	}
+}
+
+impl From<Vec<Instruction>> for Program {
+	/// Wraps instructions into a program without spans.
+	fn from(instructions: Vec<Instruction>) -> Self {
+		Self {
+			instructions,
+			spans: vec![],
+		}
+	}
+}
+
+/// An error that could be created while importing a pre-parsed program.
+#[derive(Debug, Clone, PartialEq, Eq, Error)]
+pub enum ImportError {
+	/// The input couldn't be decoded at all
+	#[error("malformed program: {0}")]
+	Malformed(String),
+	/// The input was produced by an incompatible version of the format
+	#[error("unsupported format version {found}, only version {supported} is supported")]
+	UnsupportedVersion {
+		/// Version found in the input.
+		found: u32,
+		/// Version this library understands.
+		supported: u32,
+	},
+	/// A loop has no `body`
+	#[error("`{path}` is a loop, but it has no `body`")]
+	MissingBody {
+		/// Location of the instruction in the document.
+		path: String,
+	},
+	/// A non-loop instruction has a `body`
+	#[error("`{path}` has a `body`, but only loops can have one")]
+	UnexpectedBody {
+		/// Location of the instruction in the document.
+		path: String,
+	},
+	/// Only some of the instructions have spans
+	#[error("either all instructions must have a span or none of them")]
+	PartialSpans,
+}
+
+/// Count instructions in a tree, including the nested ones.
+pub(crate) fn count_instructions(instructions: &[Instruction]) -> usize {
+	let mut count = 0;
+	let mut stack = vec![instructions];
+
+	while let Some(instructions) = stack.pop() {
+		count += instructions.len();
+
+		for instruction in instructions {
+			if let Instruction::Loop(inner) = instruction {
+				stack.push(inner);
+			}
+		}
+	}
+
+	count
+}
+
+/// Whether the instruction, or one in its body, reads input or uses an extension, which
+/// [`Program::evaluate_prefix`] stops at.
+pub(crate) fn needs_outside(instruction: &Instruction) -> bool {
+	let mut stack = vec![instruction];
+
+	while let Some(instruction) = stack.pop() {
+		match instruction {
+			Instruction::Read | Instruction::Extension(_) => return true,
+			Instruction::Loop(body) => stack.extend(body),
+			_ => {}
+		}
+	}
+
+	false
+}
+
+#[derive(Serialize, Deserialize)]
+struct AstDocument {
+	version: u32,
+	instructions: Vec<AstNode>,
+}
+
+#[derive(Serialize, Deserialize)]
+struct AstNode {
+	op: AstOp,
+	/// Symbol of a custom instruction.
+	#[serde(default, skip_serializing_if = "Option::is_none")]
+	symbol: Option<char>,
+	/// Amount of an add, or distance of a move.
+	#[serde(default, skip_serializing_if = "Option::is_none")]
+	amount: Option<i32>,
+	#[serde(default, skip_serializing_if = "Option::is_none")]
+	span: Option<Span>,
+	#[serde(default, skip_serializing_if = "Option::is_none")]
+	body: Option<Vec<Self>>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+enum AstOp {
+	Inc,
+	Dec,
+	Next,
+	Prev,
+	Print,
+	Read,
+	Loop,
+	Clear,
+	Add,
+	Move,
+	Time,
+	Exit,
+	Custom,
+}
+
+impl AstOp {
+	const fn name(self) -> &'static str {
+		match self {
+			Self::Inc => "inc",
+			Self::Dec => "dec",
+			Self::Next => "next",
+			Self::Prev => "prev",
+			Self::Print => "print",
+			Self::Read => "read",
+			Self::Loop => "loop",
+			Self::Clear => "clear",
+			Self::Add => "add",
+			Self::Move => "move",
+			Self::Time => "time",
+			Self::Exit => "exit",
+			Self::Custom => "custom",
+		}
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn loop_spans_cover_both_brackets() {
+		let program = Program::parse("+\n[->]").unwrap();
+
+		assert_eq!(4, program.spans.len());
+		assert_eq!(
+			Span {
+				start: 2,
+				end: 6,
+				line: 2,
+				column: 1
+			},
+			program.spans[1]
+		);
+		assert_eq!(Some(2), program.span(2).map(|span| span.column));
+	}
+
+	#[test]
+	fn cancelled_instructions_lose_their_spans() {
+		let program = Program::parse("+<>>[-+]").unwrap().cancel_opposites();
+
+		assert_eq!(
+			vec![0, 3, 4],
+			program
+				.spans
+				.iter()
+				.map(|span| span.start)
+				.collect::<Vec<_>>()
+		);
+		assert_eq!(Some(8), program.span(2).map(|span| span.end));
+	}
+
+	#[test]
+	fn loops_at_the_start_and_after_loops_are_dead_on_any_tape() {
+		let program = Program::parse("[c]+[>+[a][b]][d]").unwrap();
+
+		assert_eq!(BTreeSet::from([0, 6, 7]), program.trivially_dead_loops());
+		assert_eq!(
+			Program::parse("+[>+[a]]").unwrap().instructions,
+			program.remove_dead_loops(1).instructions
+		);
+	}
+
+	#[test]
+	fn evaluated_prefix_leaves_the_tape_behind() {
+		let program = Program::parse("+++>++<.>,").unwrap();
+
+		let evaluated = program.evaluate_prefix(30_000, 100);
+		assert_eq!(
+			Program::parse("+++.>++,").unwrap().instructions,
+			evaluated.instructions
+		);
+		assert_eq!(
+			vec![
+				Span {
+					start: 0,
+					end: 9,
+					line: 1,
+					column: 1
+				};
+				7
+			],
+			evaluated.spans[..7]
+		);
+		assert_eq!(program.spans.last(), evaluated.spans.last());
+		// NOTE: the pointer wraps around, which may go differently when the program runs
+		assert_eq!(program, program.evaluate_prefix(1, 100));
+		assert_eq!(program, program.evaluate_prefix(30_000, 5));
+	}
+
+	#[test]
+	fn canonical_form_ignores_the_order_of_arithmetic() {
+		let canonical = |code| Program::parse(code).unwrap().canonicalize().instructions;
+
+		assert_eq!(canonical("<<+>>->"), canonical(">-<-<<+>>>+"));
+		assert_eq!(canonical("+.[+++]"), canonical("+.[-]"));
+		assert_ne!(canonical("+.-"), canonical("-.+"));
+		assert_ne!(canonical("[++]"), canonical("[-]"));
+	}
+
+	#[test]
+	fn sexpr_export() {
+		let program = Program::parse("+[-]").unwrap();
+
+		assert_eq!(
+			"(program (inc 1:1) (loop 1:2 (dec 1:3)))",
+			program.to_sexpr()
+		);
+	}
+
+	#[test]
+	fn json_export() {
+		let program = Program::parse("[.]").unwrap();
+
+		let json: serde_json::Value = serde_json::from_str(&program.to_json()).unwrap();
+
+		assert_eq!(AST_VERSION, json["version"]);
+		assert_eq!("loop", json["instructions"][0]["op"]);
+		assert_eq!(3, json["instructions"][0]["span"]["end"]);
+		assert_eq!("print", json["instructions"][0]["body"][0]["op"]);
+	}
+
+	#[test]
+	fn json_round_trip() {
+		let program = Program::parse("+[->[.]<]").unwrap();
+
+		assert_eq!(program, Program::from_json(&program.to_json()).unwrap());
+
+		let optimized = Program::parse("+[--->>[-]<<]")
+			.unwrap()
+			.clear_loops()
+			.collapse_runs();
+
+		assert_eq!(
+			"(program (inc 1:1) (loop 1:2 (add -3 1:3) (move 2 1:6) (clear 1:8) (move -2 1:11)))",
+			optimized.to_sexpr()
+		);
+		assert_eq!(optimized, Program::from_json(&optimized.to_json()).unwrap());
+	}
+
+	#[test]
+	fn json_import_errors() {
+		assert_eq!(
+			ImportError::UnsupportedVersion {
+				found: 99,
+				supported: AST_VERSION
+			},
+			Program::from_json(r#"{ "version": 99, "instructions": [] }"#).unwrap_err()
+		);
+		assert_eq!(
+			ImportError::MissingBody {
+				path: "instructions[1].body[0]".to_owned()
+			},
+			Program::from_json(
+				r#"{ "version": 1, "instructions": [{ "op": "inc" }, { "op": "loop", "body": [{ "op": "loop" }] }] }"#
+			)
+			.unwrap_err()
+		);
+		assert_eq!(
+			ImportError::UnexpectedBody {
+				path: "instructions[0]".to_owned()
+			},
+			Program::from_json(
+				r#"{ "version": 1, "instructions": [{ "op": "inc", "body": [] }] }"#
+			)
+			.unwrap_err()
+		);
+		assert!(matches!(
+			Program::from_json(r#"{ "version": 1, "instructions": [{ "op": "jump" }] }"#),
+			Err(ImportError::Malformed(_))
+		));
+		assert_eq!(
+			ImportError::Malformed("instructions[0] adds 200, which is out of range".to_owned()),
+			Program::from_json(
+				r#"{ "version": 1, "instructions": [{ "op": "add", "amount": 200 }] }"#
+			)
+			.unwrap_err()
+		);
+	}
+
+	#[test]
+	fn export_without_spans() {
+		let program = Program::from(vec![Instruction::Inc]);
+
+		assert_eq!("(program (inc))", program.to_sexpr());
+		assert!(!program.to_json().contains("span"));
+	}
+}
@@ -0,0 +1,247 @@
+use std::{
+	io::{self, BufReader, ErrorKind, Write},
+	net::{SocketAddr, TcpListener, TcpStream, ToSocketAddrs},
+	ops::ControlFlow,
+	sync::mpsc::{self, Receiver},
+	thread,
+};
+
+use crate::{
+	debugger::Debugger,
+	hooks::Hooks,
+	instruction::Instruction,
+	pause::{PauseFlag, SafePoint},
+	program::Program,
+	view::EngineView,
+};
+
+/// Where debuggers connect to a running program over TCP, so programs started normally can be
+/// inspected later.
+///
+/// A thread accepts the connections, and requests a pause through [`DebugListener::pause`] for
+/// each, so a [`RemoteDebugger`] attaches a [`Debugger`] to the program at its next
+/// [`SafePoint`]. The wire protocol is the one of the debugger: lines of commands one way, and
+/// text replies the other, every one of which ends with the `(bf) ` prompt once the program
+/// waits for the next command. Closing the connection detaches the debugger.
+#[derive(Debug)]
+pub struct DebugListener {
+	address: SocketAddr,
+	pause: PauseFlag,
+	connections: Receiver<TcpStream>,
+}
+
+impl DebugListener {
+	/// Listen for debuggers at `address`, which must be a loopback address, since debuggers
+	/// aren't authenticated and can read and change the whole tape.
+	///
+	/// # Errors
+	///
+	/// Fails if the address isn't a loopback one, see [`DebugListener::bind_any`] for others, or
+	/// can't be listened at.
+	pub fn bind(address: impl ToSocketAddrs) -> io::Result<Self> {
+		let addresses: Vec<_> = address.to_socket_addrs()?.collect();
+		if let Some(address) = addresses.iter().find(|address| !address.ip().is_loopback()) {
+			return Err(io::Error::new(
+				ErrorKind::PermissionDenied,
+				format!(
+					"{address} isn't a loopback address, so other machines could debug the program"
+				),
+			));
+		}
+
+		Self::bind_any(addresses.as_slice())
+	}
+
+	/// Listen for debuggers at `address`, even if other machines can reach it. Anyone who can
+	/// connect controls the program, so only use this on networks you trust.
+	///
+	/// # Errors
+	///
+	/// Fails if the address can't be listened at.
+	pub fn bind_any(address: impl ToSocketAddrs) -> io::Result<Self> {
+		let listener = TcpListener::bind(address)?;
+		let address = listener.local_addr()?;
+		let pause = PauseFlag::new();
+		let (sender, connections) = mpsc::channel();
+
+		let requests = pause.clone();
+		thread::spawn(move || {
+			for stream in listener.incoming().flatten() {
+				if sender.send(stream).is_err() {
+					break;
+				}
+				requests.request();
+			}
+		});
+
+		Ok(Self {
+			address,
+			pause,
+			connections,
+		})
+	}
+
+	/// The address debuggers connect to, with the port picked if port 0 was asked for.
+	pub const fn local_addr(&self) -> SocketAddr {
+		self.address
+	}
+
+	/// The flag to pause the program with, which goes into
+	/// [`RuntimeSettings::pause`](`crate::engine::RuntimeSettings::pause`).
+	pub fn pause(&self) -> PauseFlag {
+		self.pause.clone()
+	}
+}
+
+/// Hooks that attach a [`Debugger`] to the program whenever one connects to a [`DebugListener`],
+/// one at a time. Further ones are told so and turned away while one is attached.
+///
+/// Only the tree engine calls the hooks this needs. Ending the program with `quit` sets
+/// [`RunSummary::breakpoint_hit`](`crate::engine::RunSummary::breakpoint_hit`).
+///
+/// # Usage
+///
+/// ```no_run
+/// # use brainfuck_rs::{
+/// #   engine::{Engine, RuntimeSettings},
+/// #   host::StdHost,
+/// #   program::Program,
+/// #   remote::{DebugListener, RemoteDebugger},
+/// # };
+/// let program = Program::parse("+[>+<]").unwrap();
+/// let listener = DebugListener::bind("127.0.0.1:6009").unwrap();
+/// let settings = RuntimeSettings {
+///     pause: Some(listener.pause()),
+///     ..Default::default()
+/// };
+///
+/// // NOTE: runs until a debugger connects and quits it
+/// Engine::default()
+///     .run_with(
+///         &program.instructions,
+///         &mut [].as_slice(),
+///         &mut vec![],
+///         settings,
+///         &mut StdHost::default(),
+///         &mut RemoteDebugger::new(&program, listener),
+///     )
+///     .unwrap();
+/// ```
+pub struct RemoteDebugger<'a> {
+	program: &'a Program,
+	listener: DebugListener,
+	debugger: Option<Debugger<'a, BufReader<TcpStream>, TcpStream>>,
+}
+
+impl<'a> RemoteDebugger<'a> {
+	/// Attach debuggers that connect to `listener` to `program`.
+	pub const fn new(program: &'a Program, listener: DebugListener) -> Self {
+		Self {
+			program,
+			listener,
+			debugger: None,
+		}
+	}
+
+	/// Whether a debugger is attached.
+	pub const fn is_attached(&self) -> bool {
+		self.debugger.is_some()
+	}
+
+	/// Attach the debugger that connected through `stream`, stopping the program after `step`
+	/// instructions.
+	fn attach(&mut self, mut stream: TcpStream, step: u64) -> io::Result<()> {
+		if self.debugger.is_some() {
+			return writeln!(stream, "error: another debugger is attached");
+		}
+
+		let commands = BufReader::new(stream.try_clone()?);
+		self.debugger = Some(Debugger::attach(self.program, commands, stream, step));
+
+		Ok(())
+	}
+}
+
+impl Hooks for RemoteDebugger<'_> {
+	fn before_instruction(&mut self, step: u64, instruction: &Instruction, bf: EngineView<'_>) {
+		let Some(debugger) = &mut self.debugger else {
+			return;
+		};
+
+		debugger.before_instruction(step, instruction, bf);
+		if debugger.detached() {
+			// NOTE: dropping the debugger closes its connection
+			self.debugger = None;
+		}
+	}
+
+	fn should_break(&mut self) -> bool {
+		self.debugger.as_ref().is_some_and(Debugger::quit)
+	}
+
+	fn on_pause(&mut self, step: u64, _at: SafePoint, _bf: EngineView<'_>) -> ControlFlow<()> {
+		while let Ok(stream) = self.listener.connections.try_recv() {
+			// NOTE: a debugger that went away before it was attached is no reason to stop
+			let _ = self.attach(stream, step);
+		}
+
+		ControlFlow::Continue(())
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::{
+		engine::{Engine, RuntimeSettings},
+		host::StdHost,
+	};
+	use std::io::Read;
+
+	#[test]
+	fn only_loopback_addresses_are_listened_at_by_default() {
+		assert!(DebugListener::bind("127.0.0.1:0").is_ok());
+		assert_eq!(
+			ErrorKind::PermissionDenied,
+			DebugListener::bind("0.0.0.0:0").unwrap_err().kind()
+		);
+		assert!(DebugListener::bind_any("0.0.0.0:0").is_ok());
+	}
+
+	#[test]
+	fn debuggers_attach_to_running_programs() {
+		let program = Program::parse("+[>+<]").unwrap();
+		let listener = DebugListener::bind("127.0.0.1:0").unwrap();
+		let address = listener.local_addr();
+
+		let client = thread::spawn(move || {
+			let mut stream = TcpStream::connect(address).unwrap();
+			stream.write_all(b"print\nquit\n").unwrap();
+
+			let mut replies = String::new();
+			stream.read_to_string(&mut replies).unwrap();
+			replies
+		});
+
+		let summary = Engine::default()
+			.run_with(
+				&program.instructions,
+				&mut [].as_slice(),
+				&mut vec![],
+				RuntimeSettings {
+					pause: Some(listener.pause()),
+					..Default::default()
+				},
+				&mut StdHost::default(),
+				&mut RemoteDebugger::new(&program, listener),
+			)
+			.unwrap();
+		assert!(summary.breakpoint_hit);
+
+		let replies = client.join().unwrap();
+		let lines: Vec<_> = replies.split("(bf) ").collect();
+		assert_eq!(3, lines.len());
+		assert_eq!(lines[0], lines[1]);
+		assert!(lines[0].ends_with(" at 1:2 `[`, cell 0 = 1\n"));
+	}
+}
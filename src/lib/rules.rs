@@ -0,0 +1,312 @@
+use std::str::FromStr;
+
+use thiserror::Error;
+
+use crate::{
+	instruction::Instruction,
+	optimize::{Pass, Verdict},
+	program::{count_instructions, Program},
+	token::{Span, Token},
+};
+
+/// Extra peephole rules, one rewrite per line, for trying out optimizations without rebuilding
+/// the crate:
+///
+/// ```text
+/// # comments start with a hash
+/// "[+]" => "[-]"
+/// "[-]+" => Set(1)
+/// ```
+///
+/// - `"CODE" => "CODE"` replaces the code on the left with the code on the right, wherever it
+///   appears in a program, comments aside.
+/// - `"CODE" => Set(N)` replaces it with `[-]` followed by whatever sets the cell to N.
+///
+/// Code is quoted and can't contain quotes. A rule is only accepted if both sides have the same
+/// [normal form](`Program::canonicalize`), so rules can't change what programs do. Rules are tried
+/// in order, and the first one that matches wins. Run them on a program as a [`Pass`].
+///
+/// # Usage
+///
+/// ```
+/// # use brainfuck_rs::{optimize::PassManager, program::Program, rules::Rules};
+/// let rules: Rules = "\"[+]\" => \"[-]\"".parse().unwrap();
+/// let program = PassManager::new().with_pass(rules).run(&Program::parse("+[+]>[+]").unwrap());
+///
+/// assert_eq!(Program::parse("+[-]>[-]").unwrap().instructions, program.instructions);
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct Rules {
+	/// Rules in the order they're tried.
+	pub rules: Vec<Rule>,
+}
+
+/// A line of [`Rules`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Rule {
+	/// Instructions to look for.
+	pub pattern: Vec<Instruction>,
+	/// Instructions to replace them with.
+	pub replacement: Vec<Instruction>,
+}
+
+impl FromStr for Rules {
+	type Err = RuleError;
+
+	fn from_str(text: &str) -> Result<Self, Self::Err> {
+		let mut rules = vec![];
+
+		for (index, line) in text.lines().enumerate() {
+			let number = index + 1;
+			let error = |message: &str| RuleError::Syntax {
+				line: number,
+				message: message.to_owned(),
+			};
+			let code = |code: &str| {
+				Instruction::parse(Token::tokenize(code))
+					.map_err(|e| error(&format!("`{code}` doesn't parse: {e}")))
+			};
+
+			let line = line.trim();
+			if line.is_empty() || line.starts_with('#') {
+				continue;
+			}
+
+			let (pattern, rest) = line
+				.strip_prefix('"')
+				.and_then(|line| line.split_once('"'))
+				.ok_or_else(|| error("expected quoted code to look for"))?;
+			let replacement = rest
+				.trim()
+				.strip_prefix("=>")
+				.ok_or_else(|| error("expected `=>` after the code to look for"))?
+				.trim();
+
+			let pattern = code(pattern)?;
+			if pattern.is_empty() {
+				return Err(error("the code to look for is empty"));
+			}
+
+			let replacement = if let Some(value) = replacement
+				.strip_prefix("Set(")
+				.and_then(|rest| rest.strip_suffix(')'))
+			{
+				let value: u8 = value
+					.parse()
+					.map_err(|_| error("expected a cell value from 0 to 255 in `Set`"))?;
+				set(value)
+			} else {
+				let replacement = replacement
+					.strip_prefix('"')
+					.and_then(|rest| rest.strip_suffix('"'))
+					.filter(|code| !code.contains('"'))
+					.ok_or_else(|| error("expected quoted code or `Set(N)` after `=>`"))?;
+				code(replacement)?
+			};
+
+			// NOTE: the normal form folds arithmetic and pointer movement, and makes every loop
+			// that clears the cell `[-]`, so both sides must do the same
+			let structurally_eq = Program {
+				instructions: pattern.clone(),
+				spans: vec![],
+			}
+			.structurally_eq(&Program {
+				instructions: replacement.clone(),
+				spans: vec![],
+			});
+			if !structurally_eq {
+				return Err(RuleError::Changes { line: number });
+			}
+
+			rules.push(Rule {
+				pattern,
+				replacement,
+			});
+		}
+
+		Ok(Self { rules })
+	}
+}
+
+/// Instructions that set the cell to `value`, with `+` or `-`, whichever is shorter.
+fn set(value: u8) -> Vec<Instruction> {
+	let mut instructions = vec![Instruction::Loop(vec![Instruction::Dec])];
+	if value <= 128 {
+		instructions.extend(std::iter::repeat_n(Instruction::Inc, value.into()));
+	} else {
+		instructions.extend(std::iter::repeat_n(
+			Instruction::Dec,
+			value.wrapping_neg().into(),
+		));
+	}
+	instructions
+}
+
+impl Pass for Rules {
+	fn name(&self) -> &str {
+		"extra-rules"
+	}
+
+	/// Replace every match of a rule, left to right and at every depth, giving the replacements
+	/// the span of the code they replace. Replacements aren't matched again.
+	fn run(&self, program: Program) -> Program {
+		fn walk(
+			rules: &[(Vec<Instruction>, &Rule)],
+			instructions: &[Instruction],
+			old_spans: &[Span],
+			index: &mut usize,
+			spans: &mut Vec<Span>,
+		) -> Vec<Instruction> {
+			let mut output = Vec::with_capacity(instructions.len());
+			let mut position = 0;
+
+			while position < instructions.len() {
+				let rest = &instructions[position..];
+
+				if let Some((pattern, rule)) =
+					rules.iter().find(|(pattern, _)| rest.starts_with(pattern))
+				{
+					let last = *index + count_instructions(&pattern[..pattern.len() - 1]);
+					if let (Some(first), Some(last)) = (old_spans.get(*index), old_spans.get(last))
+					{
+						let span = Span {
+							end: last.end,
+							..*first
+						};
+						spans.extend(std::iter::repeat_n(
+							span,
+							count_instructions(&rule.replacement),
+						));
+					}
+
+					output.extend_from_slice(&rule.replacement);
+					*index += count_instructions(pattern);
+					position += pattern.len();
+					continue;
+				}
+
+				spans.extend(old_spans.get(*index).copied());
+				*index += 1;
+				output.push(match &rest[0] {
+					Instruction::Loop(body) => {
+						Instruction::Loop(walk(rules, body, old_spans, index, spans))
+					}
+					instruction => instruction.clone(),
+				});
+				position += 1;
+			}
+
+			output
+		}
+
+		// NOTE: rules run after the passes of `-O`, which may have turned the loops of a pattern
+		// into clears, see `ClearLoops`
+		let rules: Vec<(Vec<Instruction>, &Rule)> = self
+			.rules
+			.iter()
+			.flat_map(|rule| {
+				let cleared = Program {
+					instructions: rule.pattern.clone(),
+					spans: vec![],
+				}
+				.clear_loops()
+				.instructions;
+
+				let also_cleared = (cleared != rule.pattern).then_some((cleared, rule));
+				[(rule.pattern.clone(), rule)]
+					.into_iter()
+					.chain(also_cleared)
+			})
+			.collect();
+		let mut spans = Vec::with_capacity(program.spans.len());
+		let instructions = walk(
+			&rules,
+			&program.instructions,
+			&program.spans,
+			&mut 0,
+			&mut spans,
+		);
+
+		Program {
+			instructions,
+			spans,
+		}
+	}
+
+	fn validate(&self, before: &Program, after: &Program) -> Verdict {
+		if before.structurally_eq(after) {
+			Verdict::Proven("the normal form of the program is the same".to_owned())
+		} else {
+			Verdict::Refuted("the normal form of the program changed".to_owned())
+		}
+	}
+}
+
+/// An error in [`Rules`].
+#[derive(Debug, Clone, PartialEq, Eq, Error)]
+pub enum RuleError {
+	/// A line couldn't be parsed
+	#[error("line {line}: {message}")]
+	Syntax {
+		/// Line of the rules, starting at 1.
+		line: usize,
+		/// What's wrong with it.
+		message: String,
+	},
+	/// The replacement of a rule may do something else than the code it replaces
+	#[error("line {line}: the replacement doesn't do the same as the code it replaces")]
+	Changes {
+		/// Line of the rule, starting at 1.
+		line: usize,
+	},
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn rules_keep_spans_and_refuse_changes() {
+		let rules: Rules = "# clear and set\n\"[+]+\" => Set(1)\n\"+-\" => \"\""
+			.parse()
+			.unwrap();
+		let program = Program::parse("[[+]+]\n+-.").unwrap();
+		let rewritten = rules.run(program.clone());
+
+		assert_eq!(
+			Program::parse("[[-]+]\n.").unwrap().instructions,
+			rewritten.instructions
+		);
+		let replaced = Span {
+			end: program.spans[3].end,
+			..program.spans[1]
+		};
+		assert_eq!(
+			vec![
+				program.spans[0],
+				replaced,
+				replaced,
+				replaced,
+				program.spans[6]
+			],
+			rewritten.spans
+		);
+		assert!(matches!(
+			rules.validate(&program, &rewritten),
+			Verdict::Proven(_)
+		));
+		assert_eq!(
+			rewritten.instructions,
+			rules.run(program.clear_loops()).instructions
+		);
+
+		assert_eq!(
+			Err(RuleError::Changes { line: 2 }),
+			"\n\"[-]\" => Set(1)".parse::<Rules>()
+		);
+		assert!(matches!(
+			"\"[-]\" => \"[\"".parse::<Rules>(),
+			Err(RuleError::Syntax { line: 1, .. })
+		));
+	}
+}
@@ -0,0 +1,376 @@
+use std::{
+	cell::RefCell,
+	io::{self, Read, Write},
+	rc::Rc,
+	str::FromStr,
+	thread,
+	time::Duration,
+};
+
+use thiserror::Error;
+
+use crate::trace::{escape, unescape};
+
+/// An "expect"-style script that drives an interactive program, one directive per line:
+///
+/// ```text
+/// # comments start with a hash
+/// expect "Name?"
+/// send "Alex\n"
+/// waitms 100
+/// expect "Hello, Alex"
+/// ```
+///
+/// - `expect "TEXT"` waits for the program to print the text, after whatever the previous
+///   `expect` matched.
+/// - `send "TEXT"` makes the text the program's next input.
+/// - `waitms N` pauses for N milliseconds before going on, for programs that read the clock.
+///
+/// Texts are quoted, with `\n`, `\t`, `\r`, `\"`, `\\` and `\xNN` escapes. The input ends after
+/// the last directive. Run a script with [`Session`].
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct Script {
+	/// Directives in the order they run, along with the lines they're on.
+	pub directives: Vec<(usize, Directive)>,
+}
+
+/// A line of a [`Script`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Directive {
+	/// Wait for the program to print this.
+	Expect(Vec<u8>),
+	/// Give this to the program as input.
+	Send(Vec<u8>),
+	/// Pause for this long.
+	Wait(Duration),
+}
+
+impl FromStr for Script {
+	type Err = ScriptError;
+
+	fn from_str(text: &str) -> Result<Self, Self::Err> {
+		let mut directives = vec![];
+
+		for (index, line) in text.lines().enumerate() {
+			let number = index + 1;
+			let error = |message: &str| ScriptError::Syntax {
+				line: number,
+				message: message.to_owned(),
+			};
+
+			let line = line.trim();
+			if line.is_empty() || line.starts_with('#') {
+				continue;
+			}
+
+			let (keyword, rest) = line.split_once(' ').unwrap_or((line, ""));
+			let rest = rest.trim();
+			let text = || unescape(rest).ok_or_else(|| error("expected a quoted text"));
+
+			let directive = match keyword {
+				"expect" => Directive::Expect(text()?),
+				"send" => Directive::Send(text()?),
+				"waitms" => Directive::Wait(Duration::from_millis(
+					rest.parse()
+						.map_err(|_| error("expected a number of milliseconds"))?,
+				)),
+				_ => return Err(error(&format!("unknown directive `{keyword}`"))),
+			};
+			directives.push((number, directive));
+		}
+
+		Ok(Self { directives })
+	}
+}
+
+/// An error in a [`Script`], or a program that didn't do what it expected.
+#[derive(Debug, Clone, PartialEq, Eq, Error)]
+pub enum ScriptError {
+	/// A line couldn't be parsed
+	#[error("line {line}: {message}")]
+	Syntax {
+		/// Line of the script, starting at 1.
+		line: usize,
+		/// What's wrong with it.
+		message: String,
+	},
+	/// The program read input, or ended, without printing what an `expect` waited for
+	#[error(
+		"line {line}: expected \"{}\", but the program {} after printing \"{}\"",
+		escape(.expected),
+		if *.ended { "ended" } else { "read input" },
+		escape(.printed)
+	)]
+	Unmet {
+		/// Line of the `expect`.
+		line: usize,
+		/// The text it waited for.
+		expected: Vec<u8>,
+		/// What the program printed after the previous match.
+		printed: Vec<u8>,
+		/// `false` if the program went on to read input, `true` if it ended.
+		ended: bool,
+	},
+}
+
+/// A run of a [`Script`], handing out the program's input and watching its output.
+///
+/// Since programs only wait for anything when they read input, the script is played when they
+/// do: every `expect` before the next `send` must have been printed by then. The rest of them is
+/// checked by [`Session::finish`] once the program ended.
+///
+/// # Usage
+///
+/// ```
+/// # use brainfuck_rs::{
+/// #   engine::{Engine, RuntimeSettings},
+/// #   instruction::Instruction,
+/// #   script::{Script, Session},
+/// #   token::Token,
+/// # };
+/// // prints `>`, then echoes its input
+/// let instructions = Instruction::parse(Token::tokenize("++++++[->++++++++++<]>++.[-],[.,]"))
+///     .unwrap();
+///
+/// let script: Script = "expect \">\"\nsend \"hi\"\nexpect \"hi\"".parse().unwrap();
+/// let session = Session::new(script);
+///
+/// let mut output = vec![];
+/// Engine::default()
+///     .run(
+///         &instructions,
+///         &mut session.input(),
+///         &mut session.output(&mut output),
+///         RuntimeSettings {
+///             quit_on_eof: true,
+///             ..Default::default()
+///         },
+///     )
+///     .unwrap();
+///
+/// session.finish().unwrap();
+/// assert_eq!(b">hi", output.as_slice());
+/// ```
+#[derive(Debug, Clone)]
+pub struct Session {
+	state: Rc<RefCell<State>>,
+}
+
+#[derive(Debug)]
+struct State {
+	script: Script,
+	/// Index of the next directive.
+	next: usize,
+	/// Input of the current `send` that wasn't read yet.
+	sending: Vec<u8>,
+	/// Output printed after the last match.
+	printed: Vec<u8>,
+	/// The first unmet `expect`, kept since the program may not report errors of its input.
+	failure: Option<ScriptError>,
+}
+
+impl Session {
+	/// Start running the script.
+	pub fn new(script: Script) -> Self {
+		Self {
+			state: Rc::new(RefCell::new(State {
+				script,
+				next: 0,
+				sending: vec![],
+				printed: vec![],
+				failure: None,
+			})),
+		}
+	}
+
+	/// Input of the program, which plays the script whenever it's read.
+	pub fn input(&self) -> ScriptInput {
+		ScriptInput {
+			state: Rc::clone(&self.state),
+		}
+	}
+
+	/// Output of the program, which is watched for what the script expects, and passed on to
+	/// `inner`.
+	pub fn output<W: Write>(&self, inner: W) -> ScriptOutput<W> {
+		ScriptOutput {
+			state: Rc::clone(&self.state),
+			inner,
+		}
+	}
+
+	/// Check the `expect` directives left once the program ended.
+	///
+	/// # Errors
+	///
+	/// Fails with the first `expect` the program didn't meet, whether it was found out while it
+	/// ran or now.
+	pub fn finish(self) -> Result<(), ScriptError> {
+		let mut state = self.state.borrow_mut();
+
+		if let Some(failure) = state.failure.take() {
+			return Err(failure);
+		}
+
+		while let Some((line, directive)) = state.script.directives.get(state.next).cloned() {
+			state.next += 1;
+
+			if let Directive::Expect(expected) = directive {
+				state.expect(line, &expected, true)?;
+			}
+		}
+
+		Ok(())
+	}
+}
+
+impl State {
+	/// Check that the output contains `expected`, consuming it up to the end of the match.
+	fn expect(&mut self, line: usize, expected: &[u8], ended: bool) -> Result<(), ScriptError> {
+		let found = self
+			.printed
+			.windows(expected.len().max(1))
+			.position(|window| window == expected)
+			.or_else(|| expected.is_empty().then_some(0));
+
+		match found {
+			Some(start) => {
+				self.printed.drain(..start + expected.len());
+
+				Ok(())
+			}
+			None => Err(ScriptError::Unmet {
+				line,
+				expected: expected.to_vec(),
+				printed: self.printed.clone(),
+				ended,
+			}),
+		}
+	}
+}
+
+/// Input of a [`Session`].
+pub struct ScriptInput {
+	state: Rc<RefCell<State>>,
+}
+
+impl Read for ScriptInput {
+	fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+		let mut state = self.state.borrow_mut();
+
+		while state.sending.is_empty() {
+			let Some((line, directive)) = state.script.directives.get(state.next).cloned() else {
+				return Ok(0);
+			};
+			state.next += 1;
+
+			match directive {
+				Directive::Expect(expected) => {
+					if let Err(failure) = state.expect(line, &expected, false) {
+						let error = io::Error::other(failure.to_string());
+						state.failure = Some(failure);
+
+						return Err(error);
+					}
+				}
+				Directive::Send(input) => state.sending = input,
+				Directive::Wait(duration) => thread::sleep(duration),
+			}
+		}
+
+		let length = buf.len().min(state.sending.len());
+		buf[..length].copy_from_slice(&state.sending[..length]);
+		state.sending.drain(..length);
+
+		Ok(length)
+	}
+}
+
+/// Output of a [`Session`].
+pub struct ScriptOutput<W> {
+	state: Rc<RefCell<State>>,
+	inner: W,
+}
+
+impl<W: Write> Write for ScriptOutput<W> {
+	fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+		let written = self.inner.write(buf)?;
+		self.state
+			.borrow_mut()
+			.printed
+			.extend_from_slice(&buf[..written]);
+
+		Ok(written)
+	}
+
+	fn flush(&mut self) -> io::Result<()> {
+		self.inner.flush()
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use crate::{
+		engine::{Engine, RuntimeSettings},
+		instruction::Instruction,
+		token::Token,
+	};
+
+	use super::*;
+
+	fn play(code: &str, script: &str) -> Result<Vec<u8>, ScriptError> {
+		let instructions = Instruction::parse(Token::tokenize(code)).unwrap();
+		let session = Session::new(script.parse()?);
+
+		let mut output = vec![];
+		let _ = Engine::default().run(
+			&instructions,
+			&mut session.input(),
+			&mut session.output(&mut output),
+			RuntimeSettings {
+				quit_on_eof: true,
+				..Default::default()
+			},
+		);
+		session.finish()?;
+
+		Ok(output)
+	}
+
+	#[test]
+	fn sessions() {
+		// NOTE: prints `>`, then echoes bytes until EOF
+		let prompt = "++++++[->++++++++++<]>++.[-],[.,]";
+
+		assert_eq!(
+			b">ab".to_vec(),
+			play(
+				prompt,
+				"# a comment\nexpect \">\"\nsend \"a\"\nwaitms 1\nsend \"b\"\nexpect \"ab\""
+			)
+			.unwrap()
+		);
+		assert_eq!(
+			Err(ScriptError::Unmet {
+				line: 1,
+				expected: b"?".to_vec(),
+				printed: b">".to_vec(),
+				ended: false,
+			}),
+			play(prompt, "expect \"?\"\nsend \"a\"")
+		);
+		assert_eq!(
+			"line 2: expected \"?\", but the program ended after printing \"\"",
+			play("++++++[->++++++++++<]>++.", "expect \">\"\nexpect \"?\"")
+				.unwrap_err()
+				.to_string()
+		);
+		assert_eq!(
+			"line 2: unknown directive `sned`",
+			"expect \"\"\nsned \"a\""
+				.parse::<Script>()
+				.unwrap_err()
+				.to_string()
+		);
+	}
+}
@@ -0,0 +1,304 @@
+use std::{
+	collections::VecDeque,
+	io::{self, Read},
+};
+
+use crate::{
+	engine::{unit_cost, Engine, RunError, RuntimeSettings},
+	instruction::Instruction,
+	pool::TapePool,
+};
+
+/// Search for the shortest input that makes the instructions print exactly `target`, running
+/// them on copies of `bf`.
+///
+/// Inputs are tried by length, with the bytes of `target` first, since programs that transform
+/// their input tend to print similar bytes. Inputs whose prefix already makes the program print
+/// something else are never extended: what a program prints before reading a byte can't depend on
+/// that byte. Neither are inputs the program doesn't read to the end.
+///
+/// Settings are the same as in [`Engine::run`], except for [`RuntimeSettings::budget`],
+/// [`RuntimeSettings::cost`] and [`RuntimeSettings::read_ahead`], which are ignored.
+///
+/// # Usage
+///
+/// ```
+/// # use brainfuck_rs::{
+/// #   engine::{Engine, RuntimeSettings},
+/// #   instruction::Instruction,
+/// #   search::{self, SearchSettings},
+/// #   token::Token,
+/// # };
+/// // prints every byte it reads, plus one
+/// let instructions = Instruction::parse(Token::tokenize(",[+.,]")).unwrap();
+///
+/// let search = search::solve(
+///     &Engine::default(),
+///     &instructions,
+///     b"yes",
+///     RuntimeSettings::default(),
+///     &SearchSettings::default(),
+/// )
+/// .unwrap();
+///
+/// assert_eq!(Some(b"xdr".to_vec()), search.found);
+/// ```
+///
+/// # Errors
+///
+/// Same as [`Engine::run`], except that running out of [`SearchSettings::max_steps`] isn't an
+/// error, the input is just not considered.
+pub fn solve(
+	bf: &Engine,
+	instructions: &[Instruction],
+	target: &[u8],
+	settings: RuntimeSettings,
+	search: &SearchSettings,
+) -> Result<Search, RunError> {
+	let settings = RuntimeSettings {
+		budget: Some(search.max_steps),
+		cost: unit_cost,
+		read_ahead: false,
+		..settings
+	};
+
+	let mut alphabet: Vec<u8> = vec![];
+	for &byte in target.iter().chain(&search.alphabet) {
+		if search.alphabet.contains(&byte) && !alphabet.contains(&byte) {
+			alphabet.push(byte);
+		}
+	}
+
+	let pool = TapePool::new();
+	let mut runs = 0;
+	let mut attempt = |input: &[u8], starve: bool| {
+		if runs == search.max_runs {
+			return Ok(None);
+		}
+		runs += 1;
+
+		run(&pool, bf, instructions, input, starve, settings.clone()).map(Some)
+	};
+
+	let mut queue = VecDeque::from([vec![]]);
+	while let Some(input) = queue.pop_front() {
+		// NOTE: reading past the input stops the program, so this tells what it prints no
+		// matter what comes after
+		let Some((output, ending)) = attempt(&input, true)? else {
+			return Ok(Search::given_up(runs));
+		};
+
+		if !target.starts_with(&output) {
+			continue;
+		}
+
+		match ending {
+			Ending::OutOfSteps => continue,
+			Ending::Finished if output == target => return Ok(Search::found(input, runs)),
+			Ending::Finished => continue,
+			Ending::Starved => {}
+		}
+
+		let Some((output, ending)) = attempt(&input, false)? else {
+			return Ok(Search::given_up(runs));
+		};
+
+		if ending == Ending::Finished && output == target {
+			return Ok(Search::found(input, runs));
+		}
+
+		if input.len() < search.max_length {
+			for &byte in &alphabet {
+				let mut longer = input.clone();
+				longer.push(byte);
+				queue.push_back(longer);
+			}
+		}
+	}
+
+	Ok(Search {
+		found: None,
+		runs,
+		complete: true,
+	})
+}
+
+/// Run the instructions on a copy of `bf` from `pool` with `input`, which ends in EOF, or stops
+/// the program if `starve` is set.
+fn run(
+	pool: &TapePool,
+	bf: &Engine,
+	instructions: &[Instruction],
+	input: &[u8],
+	starve: bool,
+	settings: RuntimeSettings,
+) -> Result<(Vec<u8>, Ending), RunError> {
+	let mut stdin = Prefix {
+		input,
+		starve,
+		starved: false,
+	};
+	let mut output = vec![];
+
+	let mut copy = pool.copy(bf);
+	let result = copy.run(instructions, &mut stdin, &mut output, settings);
+	pool.give(copy);
+
+	let ending = match result {
+		Ok(_) => Ending::Finished,
+		Err(RunError::BudgetExhausted { .. }) => Ending::OutOfSteps,
+		Err(RunError::Io { .. }) if stdin.starved => Ending::Starved,
+		Err(e) => return Err(e),
+	};
+
+	Ok((output, ending))
+}
+
+/// Limits of a [`solve`] search.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SearchSettings {
+	/// Bytes inputs are made of.
+	pub alphabet: Vec<u8>,
+	/// Length of the longest input that is tried.
+	pub max_length: usize,
+	/// Number of instructions a single run may execute before its input is given up on.
+	pub max_steps: u64,
+	/// Number of runs the whole search may take.
+	pub max_runs: u64,
+}
+
+impl Default for SearchSettings {
+	/// Creates new `SearchSettings` with default values, where inputs are made of printable ASCII
+	/// and line feeds:
+	///
+	/// ```
+	/// # use brainfuck_rs::search::SearchSettings;
+	/// SearchSettings {
+	///     alphabet: (b' '..=b'~').chain([b'\n']).collect(),
+	///     max_length: 8,
+	///     max_steps: 1_000_000,
+	///     max_runs: 100_000,
+	/// }
+	/// # ;
+	/// ```
+	fn default() -> Self {
+		Self {
+			alphabet: (b' '..=b'~').chain([b'\n']).collect(),
+			max_length: 8,
+			max_steps: 1_000_000,
+			max_runs: 100_000,
+		}
+	}
+}
+
+/// Result of a [`solve`] search.
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct Search {
+	/// The shortest input that makes the program print the target, if one was found.
+	pub found: Option<Vec<u8>>,
+	/// Number of times the program was run.
+	pub runs: u64,
+	/// `false` if the search ran out of [`SearchSettings::max_runs`] before it could rule out
+	/// every input up to [`SearchSettings::max_length`].
+	pub complete: bool,
+}
+
+impl Search {
+	const fn found(input: Vec<u8>, runs: u64) -> Self {
+		Self {
+			found: Some(input),
+			runs,
+			complete: true,
+		}
+	}
+
+	const fn given_up(runs: u64) -> Self {
+		Self {
+			found: None,
+			runs,
+			complete: false,
+		}
+	}
+}
+
+/// How a single run ended.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Ending {
+	Finished,
+	Starved,
+	OutOfSteps,
+}
+
+/// Input that either ends in EOF, or stops the program with an error if it reads past the end.
+struct Prefix<'a> {
+	input: &'a [u8],
+	starve: bool,
+	starved: bool,
+}
+
+impl Read for Prefix<'_> {
+	fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+		if self.input.is_empty() && self.starve {
+			self.starved = true;
+			return Err(io::Error::other("input ran out"));
+		}
+
+		self.input.read(buf)
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use crate::token::Token;
+
+	use super::*;
+
+	fn solve(code: &str, target: &[u8], search: &SearchSettings) -> Search {
+		let instructions = Instruction::parse(Token::tokenize(code)).unwrap();
+
+		super::solve(
+			&Engine::default(),
+			&instructions,
+			target,
+			RuntimeSettings::default(),
+			search,
+		)
+		.unwrap()
+	}
+
+	#[test]
+	fn solving() {
+		let search = SearchSettings {
+			alphabet: (b'a'..=b'z').collect(),
+			..Default::default()
+		};
+
+		// NOTE: prints `y` only if the input is `ok`
+		let check = concat!(
+			",>+<>>+++++++++++[-<<---------->>]<<-[[-]>[-]<]",
+			",>>++++++++++[-<<---------->>]<<-------[[-]>[-]<]",
+			">[>++++++++++++[-<++++++++++>]<.[-]]",
+		);
+		let found = solve(check, b"y", &search);
+		assert_eq!(Some(b"ok".to_vec()), found.found);
+
+		// NOTE: only inputs that are prefixes of the target are extended
+		let echo = solve(",[.,]", b"brain", &search);
+		assert_eq!(Some(b"brain".to_vec()), echo.found);
+		assert!(echo.runs < 200);
+
+		let nothing = solve("+.", b"a", &search);
+		assert_eq!(None, nothing.found);
+		assert!(nothing.complete);
+
+		let limited = solve(
+			",,,,",
+			b"?",
+			&SearchSettings {
+				max_runs: 10,
+				..Default::default()
+			},
+		);
+		assert_eq!(Search::given_up(10), limited);
+	}
+}
@@ -0,0 +1,118 @@
+use crate::engine::{Engine, Eof, RuntimeSettings};
+
+/// Semantics of a well-known interpreter, for running programs written for it exactly like it
+/// does.
+///
+/// Every profile runs programs on 8-bit cells that wrap around between 0 and 255, like every
+/// interpreter emulated here does, since that's the only kind of cell the engine has.
+///
+/// # Usage
+///
+/// ```
+/// # use brainfuck_rs::{instruction::Instruction, semantics::Profile, token::Token};
+/// let profile = Profile::find("bff").unwrap();
+/// let instructions = Instruction::parse(Token::tokenize(",.")).unwrap();
+/// let mut output = vec![];
+///
+/// profile
+///     .engine()
+///     .run(&instructions, &mut std::io::empty(), &mut output, profile.settings())
+///     .unwrap();
+///
+/// assert_eq!(vec![255], output);
+/// ```
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Profile {
+	/// Name the profile is chosen by.
+	pub name: &'static str,
+	/// The interpreter it emulates.
+	pub summary: &'static str,
+	/// What a read leaves in the cell once the input ended.
+	pub eof: Eof,
+	/// Whether the program stops once it reads past the end of the input.
+	pub quit_on_eof: bool,
+	/// Number of cells the tape starts with.
+	pub tape_length: usize,
+	/// Whether the tape grows when the pointer moves past its end, rather than wrapping around.
+	pub grow_tape: bool,
+}
+
+impl Profile {
+	/// Every profile there is.
+	pub const ALL: &'static [Self] = &[
+		Self {
+			name: "bff",
+			summary: "Oleg Mazonka's bff, which stores C's EOF (-1) and grows the tape as needed",
+			eof: Eof::MinusOne,
+			quit_on_eof: false,
+			tape_length: 30_000,
+			grow_tape: true,
+		},
+		Self {
+			name: "beef",
+			summary: "beef, the Debian interpreter, which leaves the cell alone on EOF and grows the tape as needed",
+			eof: Eof::Unchanged,
+			quit_on_eof: false,
+			tape_length: 30_000,
+			grow_tape: true,
+		},
+		Self {
+			name: "js-playground",
+			summary: "JavaScript playgrounds in the browser, which store 0 on EOF in 30000 cells",
+			eof: Eof::Zero,
+			quit_on_eof: false,
+			tape_length: 30_000,
+			grow_tape: false,
+		},
+	];
+
+	/// The profile called `name`.
+	pub fn find(name: &str) -> Option<&'static Self> {
+		Self::ALL.iter().find(|profile| profile.name == name)
+	}
+
+	/// An engine with the tape of the profile.
+	pub fn engine(&self) -> Engine {
+		Engine::new(self.tape_length)
+	}
+
+	/// Settings of the profile, the rest being the default ones.
+	pub fn settings(&self) -> RuntimeSettings {
+		RuntimeSettings {
+			eof: self.eof,
+			quit_on_eof: self.quit_on_eof,
+			grow_tape: self.grow_tape,
+			..Default::default()
+		}
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::{instruction::Instruction, token::Token};
+
+	#[test]
+	fn eof() {
+		// NOTE: reads past the end of the input, then prints what that left in the cell
+		let instructions = Instruction::parse(Token::tokenize("+++,.")).unwrap();
+		let output = |name| {
+			let profile = Profile::find(name).unwrap();
+			let mut output = vec![];
+			profile
+				.engine()
+				.run(
+					&instructions,
+					&mut &b""[..],
+					&mut output,
+					profile.settings(),
+				)
+				.unwrap();
+			output
+		};
+
+		assert_eq!(vec![255], output("bff"));
+		assert_eq!(vec![3], output("beef"));
+		assert_eq!(vec![0], output("js-playground"));
+	}
+}
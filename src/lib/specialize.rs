@@ -0,0 +1,328 @@
+use std::num::Wrapping;
+
+use crate::bytecode::Op;
+#[cfg(feature = "jit")]
+use crate::jit::NativeLoop;
+
+/// A single effect of a specialized loop body on the tape, relative to the cell the iteration
+/// started at.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Effect {
+	Add(isize, u8),
+	Clear(isize),
+	/// Adds the first cell, multiplied by the factor, to the second one.
+	MulAdd(isize, isize, u8),
+}
+
+/// Runs iterations of a loop while the current cell isn't zero and the body stays within the
+/// tape, returning the number of finished iterations.
+type Kernel = Box<dyn Fn(&mut [Wrapping<u8>], &mut usize) -> u64>;
+
+/// An innermost loop compiled into a closure that runs whole iterations over the tape at once.
+pub(crate) struct SpecializedLoop {
+	kernel: Kernel,
+	/// Ops executed per iteration of the loop in the bytecode, not counting the loop's start.
+	ops_per_iteration: u64,
+	/// Whether the loop starts with [`Op::JumpIfZeroAdd`], which runs again on every iteration.
+	reenters_start: bool,
+	/// Offset of the rightmost cell an iteration changes or moves to.
+	reach: isize,
+}
+
+/// Result of running a [`SpecializedLoop`].
+pub(crate) struct Run {
+	/// Bytecode steps the finished iterations stand for.
+	pub steps: u64,
+	/// `false` if the loop was left early, because an iteration would have moved the pointer
+	/// beyond the tape, in which case the interpreter must take it from here. Counted loops are
+	/// always finished.
+	pub finished: bool,
+	/// One past the rightmost cell the iterations may have changed.
+	pub reach: usize,
+}
+
+impl SpecializedLoop {
+	/// Whether the loop whose first op is at `start` would be specialized once it's hot.
+	pub fn specializable(ops: &[Op], start: usize) -> bool {
+		Self::new(ops, start).is_some()
+	}
+
+	/// Specialize the loop whose first op is at `start`, if its body is nothing but arithmetic
+	/// and pointer movement. [`Op::RepeatN`] loops run all their iterations at once.
+	fn new(ops: &[Op], start: usize) -> Option<Self> {
+		let (first_delta, counted, end) = match ops[start] {
+			Op::JumpIfZero(end) => (0, None, end as usize - 1),
+			Op::JumpIfZeroAdd(end, delta) => (delta, None, end as usize - 1),
+			Op::RepeatN(end, factor) => (0, Some(factor), end as usize - 1),
+			_ => return None,
+		};
+
+		fn add(effects: &mut Vec<Effect>, offset: isize, delta: u8) {
+			effects.push(Effect::Add(offset, delta));
+		}
+
+		let mut effects = vec![];
+		let mut offset: isize = 0;
+		let mut min = 0;
+		let mut max = 0;
+
+		let mut shift = |offset: &mut isize, by: i32| {
+			*offset += by as isize;
+			min = min.min(*offset);
+			max = max.max(*offset);
+		};
+
+		if first_delta != 0 {
+			add(&mut effects, 0, first_delta);
+		}
+
+		for &op in &ops[start + 1..end] {
+			match op {
+				Op::Inc => add(&mut effects, offset, 1),
+				Op::Dec => add(&mut effects, offset, u8::MAX),
+				Op::Add(delta) => add(&mut effects, offset, delta),
+				Op::Next => shift(&mut offset, 1),
+				Op::Prev => shift(&mut offset, -1),
+				Op::Move(by) => shift(&mut offset, by),
+				Op::Clear => effects.push(Effect::Clear(offset)),
+				Op::AddMove(delta, by) => {
+					add(&mut effects, offset, delta);
+					shift(&mut offset, by);
+				}
+				Op::ClearMove(by) => {
+					effects.push(Effect::Clear(offset));
+					shift(&mut offset, by);
+				}
+				Op::MoveAdd(by, delta) => {
+					shift(&mut offset, by);
+					add(&mut effects, offset, delta);
+				}
+				Op::AddAt(by, delta) => {
+					let mut target = offset;
+					shift(&mut target, by);
+					add(&mut effects, target, delta);
+				}
+				Op::MulAdd(by, factor) => {
+					let source = offset;
+					let mut target = offset;
+					shift(&mut target, by);
+					effects.push(Effect::MulAdd(source, target, factor));
+				}
+				Op::Set(value) => {
+					effects.push(Effect::Clear(offset));
+					add(&mut effects, offset, value);
+				}
+				Op::Fill(by, value) => {
+					for _ in 0..by.unsigned_abs() {
+						effects.push(Effect::Clear(offset));
+						add(&mut effects, offset, value);
+						shift(&mut offset, by.signum());
+					}
+					effects.push(Effect::Clear(offset));
+					add(&mut effects, offset, value);
+				}
+				Op::Print
+				| Op::AddPrint(_)
+				| Op::Read
+				| Op::Extension(_)
+				| Op::JumpIfZero(_)
+				| Op::JumpIfNonZero(_)
+				| Op::JumpIfZeroAdd(..)
+				| Op::RepeatN(..)
+				| Op::RepeatEnd(_)
+				| Op::ScanRight
+				| Op::ScanLeft => return None,
+			}
+		}
+
+		let shift = offset;
+		let fits = move |tape: &[Wrapping<u8>], pointer: usize| {
+			// NOTE: makes sure that nothing in the body wraps around the tape, which the
+			// interpreter handles instead
+			pointer.checked_add_signed(min).is_some()
+				&& pointer.saturating_add_signed(max) < tape.len()
+		};
+
+		let kernel: Kernel = match counted {
+			None => Box::new(move |tape, pointer| {
+				let mut iterations = 0;
+
+				while tape[*pointer].0 != 0 && fits(tape, *pointer) {
+					// SAFETY: every effect lies between `min` and `max` of the pointer, which was
+					// just checked to be within the tape
+					unsafe { apply(&effects, tape, *pointer) };
+					*pointer = pointer.wrapping_add_signed(shift);
+					iterations += 1;
+				}
+
+				iterations
+			}),
+			// NOTE: counted loops return the pointer to where it started, so checking the bounds
+			// once is enough
+			Some(factor) => Box::new(move |tape, pointer| {
+				let iterations = tape[*pointer].0.wrapping_mul(factor);
+				if !fits(tape, *pointer) {
+					return 0;
+				}
+
+				// NOTE: the effects add to the condition cell, which is zero after the last
+				// iteration
+				for _ in 0..iterations {
+					// SAFETY: the window was checked above, and the pointer doesn't move
+					unsafe { apply(&effects, tape, *pointer) };
+				}
+
+				u64::from(iterations)
+			}),
+		};
+
+		Some(Self {
+			kernel,
+			ops_per_iteration: (end - start) as u64,
+			reenters_start: first_delta != 0,
+			reach: max,
+		})
+	}
+}
+
+/// Apply the effects of an iteration that starts at `pointer`.
+///
+/// # Safety
+///
+/// Every offset of the effects, added to `pointer`, must be a cell of `tape`, which `fits` makes
+/// sure of for the loop the effects were collected from.
+unsafe fn apply(effects: &[Effect], tape: &mut [Wrapping<u8>], pointer: usize) {
+	let length = tape.len();
+	let cell = |offset: isize| {
+		let index = pointer.wrapping_add_signed(offset);
+		debug_assert!(
+			index < length,
+			"cell {index} is outside of the checked window"
+		);
+
+		index
+	};
+
+	for &effect in effects {
+		// SAFETY: guaranteed by the caller
+		unsafe {
+			match effect {
+				Effect::Add(offset, delta) => *tape.get_unchecked_mut(cell(offset)) += delta,
+				Effect::Clear(offset) => *tape.get_unchecked_mut(cell(offset)) = Wrapping(0),
+				Effect::MulAdd(source, target, factor) => {
+					let value = tape.get_unchecked(cell(source)).0;
+					*tape.get_unchecked_mut(cell(target)) += value.wrapping_mul(factor);
+				}
+			}
+		}
+	}
+}
+
+/// Bookkeeping of how hot every loop of a program is.
+enum Entry {
+	/// Entered this many times so far.
+	Cold(u64),
+	Hot(SpecializedLoop),
+	/// The loop can't be specialized, but was compiled to native code.
+	#[cfg(feature = "jit")]
+	Native(Box<NativeLoop>),
+	/// The loop's body can't be specialized.
+	Unspecializable,
+}
+
+/// Innermost loops of a program, specialized into closures once they become hot.
+pub(crate) struct HotLoops {
+	threshold: u64,
+	entries: Vec<Entry>,
+	/// Whether hot loops that can't be specialized are compiled to native code instead.
+	#[cfg(feature = "jit")]
+	native: bool,
+}
+
+impl HotLoops {
+	/// Loops are specialized on their `threshold`-th entry.
+	pub fn new(ops: &[Op], threshold: u64) -> Self {
+		Self {
+			threshold,
+			entries: ops.iter().map(|_| Entry::Cold(0)).collect(),
+			#[cfg(feature = "jit")]
+			native: false,
+		}
+	}
+
+	/// Compile hot loops that can't be specialized, like ones with nested loops, to native code,
+	/// which is only possible as long as the tape doesn't grow.
+	#[cfg(feature = "jit")]
+	pub fn native(mut self, native: bool) -> Self {
+		self.native = native;
+		self
+	}
+
+	/// Enter the loop starting at `start` while the current cell isn't zero, running it in its
+	/// specialized form if it's hot.
+	///
+	/// Returns [`None`] if the loop should be interpreted, or no iteration could be run.
+	pub fn enter(
+		&mut self,
+		ops: &[Op],
+		start: usize,
+		tape: &mut [Wrapping<u8>],
+		pointer: &mut usize,
+	) -> Option<Run> {
+		let entry = &mut self.entries[start];
+
+		if let Entry::Cold(entered) = entry {
+			*entered += 1;
+
+			if *entered < self.threshold {
+				return None;
+			}
+
+			*entry = match SpecializedLoop::new(ops, start) {
+				Some(specialized) => Entry::Hot(specialized),
+				#[cfg(feature = "jit")]
+				None if self.native => NativeLoop::new(ops, start, tape.len())
+					.map_or(Entry::Unspecializable, |native| {
+						Entry::Native(Box::new(native))
+					}),
+				None => Entry::Unspecializable,
+			};
+		}
+
+		#[cfg(feature = "jit")]
+		if let Entry::Native(native) = entry {
+			return Some(Run {
+				steps: native.run(tape, pointer),
+				finished: true,
+				// NOTE: the loop may have touched any cell
+				reach: tape.len(),
+			});
+		}
+
+		let Entry::Hot(specialized) = entry else {
+			return None;
+		};
+
+		let before = *pointer;
+		let iterations = (specialized.kernel)(tape, pointer);
+		if iterations == 0 {
+			return None;
+		}
+
+		let mut steps = iterations * specialized.ops_per_iteration;
+		if specialized.reenters_start {
+			steps += iterations - 1;
+		}
+
+		Some(Run {
+			steps,
+			finished: tape[*pointer].0 == 0,
+			// NOTE: the pointer only moves in one direction, so every iteration happened between
+			// where it was and where it is
+			reach: before
+				.max(*pointer)
+				.saturating_add_signed(specialized.reach)
+				+ 1,
+		})
+	}
+}
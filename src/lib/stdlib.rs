@@ -0,0 +1,162 @@
+//! Every snippet works on cells relative to the one the pointer is at when it starts, and returns
+//! the pointer there when it ends. Layouts are written as the cells from that one rightwards,
+//! before → after the snippet. Cells a snippet expects to be zero must be zero, or the results are
+//! garbage.
+//!
+//! Snippets are plain instructions, so they can be spliced into any program:
+//!
+//! ```
+//! # use brainfuck_rs::{
+//! #   engine::{Engine, RuntimeSettings},
+//! #   instruction::Instruction,
+//! #   stdlib,
+//! # };
+//! // 6 * 7
+//! let mut program = vec![Instruction::Inc; 6];
+//! program.push(Instruction::Next);
+//! program.extend(vec![Instruction::Inc; 7]);
+//! program.push(Instruction::Prev);
+//! program.extend(stdlib::multiply());
+//! program.extend(stdlib::print_number());
+//!
+//! let mut output = vec![];
+//! Engine::default()
+//!     .run(&program, &mut [].as_slice(), &mut output, RuntimeSettings::default())
+//!     .unwrap();
+//!
+//! assert_eq!(b"42", output.as_slice());
+//! ```
+
+use crate::{instruction::Instruction, token::Token};
+
+/// `[a, 0, 0]` → `[a, a, 0]`
+pub fn copy() -> Vec<Instruction> {
+	snippet("[->+>+<<]>>[-<<+>>]<<")
+}
+
+/// `[a, b]` → `[a + b, 0]`, wrapping around.
+pub fn add() -> Vec<Instruction> {
+	snippet(">[-<+>]<")
+}
+
+/// `[a, b]` → `[1, 0]` if `a == b`, `[0, 0]` otherwise.
+pub fn equal() -> Vec<Instruction> {
+	snippet("[->-<]+>[<->[-]]<")
+}
+
+/// `[a, b, 0, 0]` → `[a * b, 0, 0, 0]`, wrapping around.
+pub fn multiply() -> Vec<Instruction> {
+	snippet("[->[->+>+<<]>>[-<<+>>]<<<]>[-]>[-<<+>>]<<")
+}
+
+/// `[n, d, 0, 0, 0, 0, 0]` → `[n / d, n % d, 0, 0, 0, 0, 0]`. Never ends if `d` is zero.
+pub fn divmod() -> Vec<Instruction> {
+	snippet(concat!(
+		// NOTE: counts the remainder up and the divisor down, moving the remainder back into the
+		// divisor when it reaches zero, which leaves `[0, d - n % d, n % d, n / d, 0, 0, 0]`
+		"[->->+>>+<<<[->>>>+>+<<<<<]>>>>>[-<<<<<+>>>>>]<[<->[-]]<[<<[-<+>]>+>-]<<<<]",
+		">[-]>[-<+>]>[-<<<+>>>]<<<",
+	))
+}
+
+/// `[x, 0, 0, 0, 0, 0, 0, 0, 0, 0]` → unchanged, printing `x` in decimal, without leading zeros.
+pub fn print_number() -> Vec<Instruction> {
+	[
+		// NOTE: splits a copy of the number into `[x, 0, ones, hundreds, tens, 0, 0, 0, 0, 0]`
+		copy(),
+		snippet(">>++++++++++<"),
+		divmod(),
+		snippet("[->>+<<]>>>++++++++++<"),
+		divmod(),
+		// NOTE: prints the hundreds if there are any, remembering that in the sixth cell
+		snippet("[>>+<<>>>++++++[-<<<++++++++>>>]<<<.[-]]>"),
+		// NOTE: prints the tens if there are any, or there were hundreds
+		snippet("[->>+>+<<<]>>>[-<<<+>>>]<<<>[->+<]>"),
+		snippet("[<<>>>++++++[-<<<++++++++>>>]<<<.[-]>>[-]]"),
+		snippet("<<<<>++++++[-<++++++++>]<.[-]<<"),
+	]
+	.concat()
+}
+
+/// Instructions of a piece of Brainfuck, for splicing it in between snippets.
+///
+/// # Panics
+///
+/// Panics if the brackets of `code` aren't balanced.
+pub fn snippet(code: &str) -> Vec<Instruction> {
+	Instruction::parse(Token::tokenize(code)).expect("snippets are balanced")
+}
+
+#[cfg(test)]
+mod tests {
+	use std::num::Wrapping;
+
+	use crate::engine::{Engine, RuntimeSettings};
+
+	use super::*;
+
+	/// Run `snippet` on a tape that starts with `cells`, returning the cells it was expected to
+	/// use and the output.
+	fn run(snippet: &[Instruction], cells: &[u8]) -> (Vec<u8>, Vec<u8>) {
+		let mut bf = Engine::default();
+		for (cell, &value) in bf.tape.iter_mut().zip(cells) {
+			*cell = Wrapping(value);
+		}
+
+		let mut output = vec![];
+		bf.run(
+			snippet,
+			&mut [].as_slice(),
+			&mut output,
+			RuntimeSettings::default(),
+		)
+		.unwrap();
+
+		assert_eq!(0, bf.pointer);
+		assert!(bf.tape[cells.len()..].iter().all(|cell| cell.0 == 0));
+
+		(
+			bf.tape[..cells.len()].iter().map(|cell| cell.0).collect(),
+			output,
+		)
+	}
+
+	#[test]
+	fn arithmetic() {
+		for a in [0, 1, 7, 128, 255] {
+			for b in [0, 1, 3, 200, 255] {
+				assert_eq!(vec![a, a, 0], run(&copy(), &[a, 0, 0]).0);
+				assert_eq!(vec![a.wrapping_add(b), 0], run(&add(), &[a, b]).0);
+				assert_eq!(vec![u8::from(a == b), 0], run(&equal(), &[a, b]).0);
+				assert_eq!(
+					vec![a.wrapping_mul(b), 0, 0, 0],
+					run(&multiply(), &[a, b, 0, 0]).0
+				);
+			}
+		}
+
+		assert_eq!(vec![1, 0], run(&equal(), &[200, 200]).0);
+	}
+
+	#[test]
+	fn division() {
+		for n in (0..=u8::MAX).step_by(5) {
+			for d in [1, 2, 3, 10, 17, 128, 255] {
+				assert_eq!(
+					vec![n / d, n % d, 0, 0, 0, 0, 0],
+					run(&divmod(), &[n, d, 0, 0, 0, 0, 0]).0
+				);
+			}
+		}
+	}
+
+	#[test]
+	fn printing_numbers() {
+		for x in 0..=u8::MAX {
+			let (cells, output) = run(&print_number(), &[x, 0, 0, 0, 0, 0, 0, 0, 0, 0]);
+
+			assert_eq!(x.to_string().as_bytes(), output);
+			assert_eq!(vec![x, 0, 0, 0, 0, 0, 0, 0, 0, 0], cells);
+		}
+	}
+}
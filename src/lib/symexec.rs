@@ -0,0 +1,617 @@
+use std::collections::BTreeMap;
+
+use crate::{analysis, extension::Extension, instruction::Instruction};
+
+/// Explore the paths a program can take depending on its input, treating every byte it reads as
+/// an unknown, and recording which conditions on the input lead down each path.
+///
+/// A loop whose condition depends on the input splits the path in two, one where the cell is zero
+/// and one where it isn't, unless the conditions so far rule one of them out. Loops that do
+/// nothing but arithmetic (see [`analysis::cell_deltas`]) and count their cell down or up by one
+/// are taken at once, so copying or clearing an unknown doesn't split the path 256 ways.
+///
+/// Input never runs out, and the [`Extension::Time`] and [`Extension::Custom`] instructions aren't
+/// supported, ending the path they're on.
+///
+/// # Usage
+///
+/// ```
+/// # use brainfuck_rs::{
+/// #   instruction::Instruction,
+/// #   symexec::{self, Limits},
+/// #   token::Token,
+/// # };
+/// // prints `!` if the input is `A`
+/// let code = format!(",>+<{}[[-]>-<]>[{}.[-]]", "-".repeat(65), "+".repeat(32));
+/// let instructions = Instruction::parse(Token::tokenize(&code)).unwrap();
+///
+/// let exploration = symexec::explore(&instructions, &Limits::default());
+///
+/// assert!(exploration.complete);
+/// assert_eq!(2, exploration.paths.len());
+///
+/// let printing = exploration.paths.iter().find(|path| !path.prints.is_empty()).unwrap();
+/// assert_eq!(b"A", printing.witness().unwrap().as_slice());
+/// ```
+pub fn explore(instructions: &[Instruction], limits: &Limits) -> Exploration {
+	let mut ops = vec![];
+	flatten(instructions, &mut ops, &mut 0);
+
+	let mut exploration = Exploration {
+		paths: vec![],
+		complete: true,
+	};
+
+	let mut pending = vec![State::default()];
+	while let Some(mut state) = pending.pop() {
+		let ending = loop {
+			let Some(op) = ops.get(state.pc) else {
+				break Ending::Finished;
+			};
+
+			if state.steps == limits.max_steps {
+				break Ending::OutOfSteps;
+			}
+			state.steps += 1;
+
+			let cell = state.cell(limits);
+			match &op.kind {
+				OpKind::Inc => state.set(limits, cell.plus(Value::constant(1))),
+				OpKind::Dec => state.set(limits, cell.plus(Value::constant(u8::MAX))),
+				OpKind::Clear => state.set(limits, Value::constant(0)),
+				OpKind::Add(delta) => state.set(limits, cell.plus(Value::constant(*delta))),
+				OpKind::Next => state.pointer = (state.pointer + 1) % limits.tape_length,
+				OpKind::Prev => {
+					state.pointer = (state.pointer + limits.tape_length - 1) % limits.tape_length;
+				}
+				OpKind::Move(distance) => {
+					state.pointer = (state.pointer as isize + *distance as isize)
+						.rem_euclid(limits.tape_length as isize) as usize;
+				}
+				OpKind::Print => state.prints.push(Print {
+					instruction: op.instruction,
+					value: cell,
+					constraints: state.constraints.len(),
+				}),
+				OpKind::Read => {
+					state.set(limits, Value::input(state.inputs));
+					state.inputs += 1;
+				}
+				OpKind::Open { end, deltas } => {
+					if let Some(deltas) = deltas {
+						if state.take_simple_loop(limits, deltas) {
+							state.pc = end + 1;
+							continue;
+						}
+					}
+
+					let targets = Targets {
+						zero: end + 1,
+						nonzero: state.pc + 1,
+					};
+					state.branch(cell, targets, &mut pending, &mut exploration, limits);
+					continue;
+				}
+				OpKind::Close { start } => {
+					let targets = Targets {
+						zero: state.pc + 1,
+						nonzero: start + 1,
+					};
+					state.branch(cell, targets, &mut pending, &mut exploration, limits);
+					continue;
+				}
+				OpKind::Extension(Extension::Exit) => break Ending::Finished,
+				OpKind::Extension(Extension::Time | Extension::Custom(_)) => {
+					break Ending::Unsupported
+				}
+			}
+
+			state.pc += 1;
+		};
+
+		exploration.paths.push(Path {
+			constraints: state.constraints,
+			prints: state.prints,
+			inputs: state.inputs,
+			ending,
+		});
+	}
+
+	exploration
+}
+
+/// Bounds of an [`explore`] run.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Limits {
+	/// Number of instructions a single path may execute before it's cut short, counted like
+	/// [`RunSummary::steps`](`crate::engine::RunSummary::steps`), except that a loop taken at once
+	/// counts as one.
+	pub max_steps: u64,
+	/// Number of paths explored. Once there are this many, a path that would split only takes the
+	/// branch where the cell is zero.
+	pub max_paths: usize,
+	/// Length of the tape, which the pointer wraps around.
+	pub tape_length: usize,
+}
+
+impl Default for Limits {
+	/// Creates new `Limits` with default values:
+	///
+	/// ```
+	/// # use brainfuck_rs::symexec::Limits;
+	/// Limits {
+	///     max_steps: 100_000,
+	///     max_paths: 1_000,
+	///     tape_length: 30_000,
+	/// }
+	/// # ;
+	/// ```
+	fn default() -> Self {
+		Self {
+			max_steps: 100_000,
+			max_paths: 1_000,
+			tape_length: 30_000,
+		}
+	}
+}
+
+/// Result of [`explore`].
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct Exploration {
+	/// Every path that was explored.
+	pub paths: Vec<Path>,
+	/// `false` if paths were left out because of [`Limits::max_paths`].
+	pub complete: bool,
+}
+
+impl Exploration {
+	/// Conditions under which the `.` instruction with the given pre-order index (see
+	/// [`Program::span`](`crate::program::Program::span`)) prints, one set for every time it does
+	/// on every path.
+	pub fn reaching(&self, instruction: usize) -> impl Iterator<Item = &[Constraint]> {
+		self.paths.iter().flat_map(move |path| {
+			path.prints
+				.iter()
+				.filter(move |print| print.instruction == instruction)
+				.map(|print| &path.constraints[..print.constraints])
+		})
+	}
+}
+
+/// A single way through the program.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Path {
+	/// Conditions the input must meet for the program to go this way, in the order the program
+	/// checked them.
+	pub constraints: Vec<Constraint>,
+	/// Everything printed along the way.
+	pub prints: Vec<Print>,
+	/// Number of bytes read along the way.
+	pub inputs: usize,
+	/// How the path ended.
+	pub ending: Ending,
+}
+
+impl Path {
+	/// The smallest input that takes the program down this path, or [`None`] if the conditions
+	/// contradict each other.
+	pub fn witness(&self) -> Option<Vec<u8>> {
+		(0..self.inputs)
+			.map(|input| solve(&self.constraints, input))
+			.collect()
+	}
+}
+
+/// How a [`Path`] ended.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Ending {
+	/// The program ran to its end, or exited.
+	Finished,
+	/// The path ran out of [`Limits::max_steps`].
+	OutOfSteps,
+	/// The program executed an instruction whose result can't be told.
+	Unsupported,
+}
+
+/// A `.` instruction executed on a [`Path`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Print {
+	/// Pre-order index of the instruction.
+	pub instruction: usize,
+	/// The printed value.
+	pub value: Value,
+	/// Number of the path's [`Path::constraints`] that were checked before printing.
+	pub constraints: usize,
+}
+
+/// A cell value of the form `scale * input + offset`, wrapping around, where `input` is the value
+/// of the byte read with that index. Values that don't depend on the input have no `input` and a
+/// `scale` of zero.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct Value {
+	/// Index of the byte of input the value depends on.
+	pub input: Option<usize>,
+	/// Factor of the input byte.
+	pub scale: u8,
+	/// Constant added to it.
+	pub offset: u8,
+}
+
+impl Value {
+	/// A value that doesn't depend on the input.
+	pub const fn constant(offset: u8) -> Self {
+		Self {
+			input: None,
+			scale: 0,
+			offset,
+		}
+	}
+
+	/// The byte of input with the given index.
+	pub const fn input(input: usize) -> Self {
+		Self {
+			input: Some(input),
+			scale: 1,
+			offset: 0,
+		}
+	}
+
+	/// The value, given the input byte it depends on.
+	pub const fn eval(self, input: u8) -> u8 {
+		self.scale.wrapping_mul(input).wrapping_add(self.offset)
+	}
+
+	/// `self + other`, if it still depends on a single input byte.
+	fn plus(self, other: Self) -> Self {
+		self.checked_plus(other)
+			.expect("adding a constant keeps the value representable")
+	}
+
+	fn checked_plus(self, other: Self) -> Option<Self> {
+		let input = match (self.input, other.input) {
+			(Some(a), Some(b)) if a != b => return None,
+			(a, b) => a.or(b),
+		};
+		let scale = self.scale.wrapping_add(other.scale);
+
+		Some(Self {
+			input: input.filter(|_| scale != 0),
+			scale,
+			offset: self.offset.wrapping_add(other.offset),
+		})
+	}
+
+	fn times(self, factor: u8) -> Self {
+		let scale = self.scale.wrapping_mul(factor);
+
+		Self {
+			input: self.input.filter(|_| scale != 0),
+			scale,
+			offset: self.offset.wrapping_mul(factor),
+		}
+	}
+}
+
+/// A condition on the input: `value` is zero, or it isn't.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Constraint {
+	/// The checked value, which always depends on the input.
+	pub value: Value,
+	/// Whether the value must be zero.
+	pub zero: bool,
+}
+
+impl Constraint {
+	/// Whether the condition holds for the given input byte.
+	pub const fn holds(self, input: u8) -> bool {
+		(self.value.eval(input) == 0) == self.zero
+	}
+}
+
+/// The smallest value of the input byte that meets every constraint on it.
+fn solve(constraints: &[Constraint], input: usize) -> Option<u8> {
+	(0..=u8::MAX).find(|&byte| {
+		constraints
+			.iter()
+			.filter(|constraint| constraint.value.input == Some(input))
+			.all(|constraint| constraint.holds(byte))
+	})
+}
+
+/// State of a path being explored.
+#[derive(Debug, Clone, Default)]
+struct State {
+	pc: usize,
+	pointer: usize,
+	/// Cells that were changed, others are zero.
+	tape: BTreeMap<usize, Value>,
+	inputs: usize,
+	steps: u64,
+	constraints: Vec<Constraint>,
+	prints: Vec<Print>,
+}
+
+impl State {
+	fn cell(&self, limits: &Limits) -> Value {
+		self.at(0, limits)
+	}
+
+	fn at(&self, offset: isize, limits: &Limits) -> Value {
+		self.tape
+			.get(&self.index(offset, limits))
+			.copied()
+			.unwrap_or_default()
+	}
+
+	fn set(&mut self, limits: &Limits, value: Value) {
+		self.set_at(0, limits, value);
+	}
+
+	fn set_at(&mut self, offset: isize, limits: &Limits, value: Value) {
+		self.tape.insert(self.index(offset, limits), value);
+	}
+
+	fn index(&self, offset: isize, limits: &Limits) -> usize {
+		(self.pointer as isize + offset).rem_euclid(limits.tape_length as isize) as usize
+	}
+
+	/// Run a loop that only does arithmetic at once, if it counts its cell down or up by one,
+	/// returning whether it did.
+	fn take_simple_loop(&mut self, limits: &Limits, deltas: &BTreeMap<isize, u8>) -> bool {
+		let counter = self.cell(limits);
+		let iterations = match deltas.get(&0) {
+			Some(&u8::MAX) => counter,
+			Some(1) => counter.times(u8::MAX),
+			_ => return false,
+		};
+
+		let mut updates = vec![];
+		for (&offset, &delta) in deltas {
+			if offset == 0 {
+				continue;
+			}
+
+			match self
+				.at(offset, limits)
+				.checked_plus(iterations.times(delta))
+			{
+				Some(value) => updates.push((offset, value)),
+				None => return false,
+			}
+		}
+
+		for (offset, value) in updates {
+			self.set_at(offset, limits, value);
+		}
+		self.set(limits, Value::constant(0));
+
+		true
+	}
+
+	/// Jump depending on whether `value` is zero, splitting off a path for the other answer if
+	/// both are possible and there's room for it.
+	fn branch(
+		&mut self,
+		value: Value,
+		targets: Targets,
+		pending: &mut Vec<Self>,
+		exploration: &mut Exploration,
+		limits: &Limits,
+	) {
+		let Some(input) = value.input else {
+			self.pc = if value.offset == 0 {
+				targets.zero
+			} else {
+				targets.nonzero
+			};
+			return;
+		};
+
+		let possible = |zero| {
+			let mut constraints = self.constraints.clone();
+			constraints.push(Constraint { value, zero });
+
+			solve(&constraints, input).is_some()
+		};
+
+		match (possible(true), possible(false)) {
+			(true, true) => {
+				if exploration.paths.len() + pending.len() + 1 < limits.max_paths {
+					let mut other = self.clone();
+					other.constraints.push(Constraint { value, zero: false });
+					other.pc = targets.nonzero;
+					pending.push(other);
+				} else {
+					exploration.complete = false;
+				}
+
+				self.constraints.push(Constraint { value, zero: true });
+				self.pc = targets.zero;
+			}
+			(true, false) => self.pc = targets.zero,
+			(false, _) => self.pc = targets.nonzero,
+		}
+	}
+}
+
+/// Where a path continues after checking a loop's condition.
+#[derive(Debug, Clone, Copy)]
+struct Targets {
+	zero: usize,
+	nonzero: usize,
+}
+
+/// An instruction of the flattened program.
+#[derive(Debug)]
+struct Op {
+	kind: OpKind,
+	/// Pre-order index of the instruction it came from.
+	instruction: usize,
+}
+
+#[derive(Debug)]
+enum OpKind {
+	Inc,
+	Dec,
+	Next,
+	Prev,
+	Print,
+	Read,
+	Clear,
+	Add(u8),
+	Move(i32),
+	/// Start of a loop, with the changes an iteration makes if it only does arithmetic.
+	Open {
+		end: usize,
+		deltas: Option<BTreeMap<isize, u8>>,
+	},
+	Close {
+		start: usize,
+	},
+	Extension(Extension),
+}
+
+/// Flatten the instruction tree into ops that jump between the brackets of loops.
+fn flatten(instructions: &[Instruction], ops: &mut Vec<Op>, index: &mut usize) {
+	for instruction in instructions {
+		let current = *index;
+		*index += 1;
+
+		let kind = match instruction {
+			Instruction::Inc => OpKind::Inc,
+			Instruction::Dec => OpKind::Dec,
+			Instruction::Next => OpKind::Next,
+			Instruction::Prev => OpKind::Prev,
+			Instruction::Print => OpKind::Print,
+			Instruction::Read => OpKind::Read,
+			Instruction::Clear => OpKind::Clear,
+			Instruction::Add(amount) => OpKind::Add(*amount as u8),
+			Instruction::Move(distance) => OpKind::Move(*distance),
+			Instruction::Extension(extension) => OpKind::Extension(*extension),
+			Instruction::Loop(body) => {
+				let start = ops.len();
+				ops.push(Op {
+					kind: OpKind::Open {
+						end: 0,
+						deltas: analysis::cell_deltas(body),
+					},
+					instruction: current,
+				});
+
+				flatten(body, ops, index);
+
+				let end = ops.len();
+				if let OpKind::Open { end: open_end, .. } = &mut ops[start].kind {
+					*open_end = end;
+				}
+
+				OpKind::Close { start }
+			}
+		};
+
+		ops.push(Op {
+			kind,
+			instruction: current,
+		});
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use crate::{
+		engine::{Engine, RuntimeSettings},
+		token::Token,
+	};
+
+	use super::*;
+
+	fn explore(code: &str) -> (Vec<Instruction>, Exploration) {
+		let instructions = Instruction::parse(Token::tokenize(code)).unwrap();
+		let exploration = super::explore(&instructions, &Limits::default());
+
+		(instructions, exploration)
+	}
+
+	#[test]
+	fn paths() {
+		// NOTE: prints the first byte doubled, then whether the second one isn't 5
+		let (instructions, exploration) = explore(",[->++<]>.<,-----[>>+<<[-]]>>[<<+>>-]<<.");
+
+		assert!(exploration.complete);
+		assert_eq!(2, exploration.paths.len());
+
+		for path in &exploration.paths {
+			assert_eq!(Ending::Finished, path.ending);
+			assert_eq!(2, path.inputs);
+			assert_eq!(
+				Value {
+					input: Some(0),
+					scale: 2,
+					offset: 0
+				},
+				path.prints[0].value
+			);
+
+			let input = path.witness().unwrap();
+			let mut output = vec![];
+			Engine::default()
+				.run(
+					&instructions,
+					&mut input.as_slice(),
+					&mut output,
+					RuntimeSettings::default(),
+				)
+				.unwrap();
+
+			let printed: Vec<u8> = path
+				.prints
+				.iter()
+				.map(|print| {
+					print
+						.value
+						.eval(print.value.input.map_or(0, |index| input[index]))
+				})
+				.collect();
+			assert_eq!(output, printed);
+		}
+
+		// NOTE: the last `.` is the 36th instruction
+		let mut reaching: Vec<_> = exploration.reaching(35).collect();
+		reaching.sort_by_key(|constraints| constraints[0].zero);
+		assert_eq!(
+			vec![
+				[Constraint {
+					value: Value {
+						input: Some(1),
+						scale: 1,
+						offset: 251
+					},
+					zero: false,
+				}]
+				.as_slice(),
+				[Constraint {
+					value: Value {
+						input: Some(1),
+						scale: 1,
+						offset: 251
+					},
+					zero: true,
+				}]
+				.as_slice(),
+			],
+			reaching
+		);
+	}
+
+	#[test]
+	fn paths_are_bounded() {
+		// NOTE: reads until a zero byte, which may never come
+		let (_, exploration) = explore("+[,]");
+
+		assert!(!exploration.complete);
+		assert_eq!(Limits::default().max_paths, exploration.paths.len());
+
+		let (_, endless) = explore("+[]");
+		assert_eq!(Ending::OutOfSteps, endless.paths[0].ending);
+	}
+}
@@ -0,0 +1,399 @@
+use std::{
+	collections::BTreeSet,
+	fmt,
+	io::{Read, Write},
+	mem,
+	ops::ControlFlow,
+	slice,
+};
+
+use crate::{
+	analysis,
+	engine::{Engine, Eof, RunError, RunSummary, RuntimeSettings},
+	extension::Extension,
+	hooks::Hooks,
+	host::StdHost,
+	instruction::Instruction,
+};
+
+/// Run instructions like [`Engine::run`], tracking which bytes of input every byte of output
+/// depends on into `dependencies`.
+///
+/// Every cell has a shadow set of the input bytes its value was derived from. Reading puts the
+/// byte's index there, replacing whatever was there before, and arithmetic keeps it. Since
+/// Brainfuck moves values around with loops, an iteration also adds whatever the cell its
+/// condition checked depends on to every cell it changes with `+` and `-`, which is how `[->+<]`
+/// carries the dependencies of one cell over to the other. Cells read during the same iteration
+/// are exempt, since how often the loop runs doesn't change them.
+///
+/// Once a loop that doesn't return the pointer to where it started depends on input, so does
+/// every cell read afterwards, since which cell that is depends on it. That lasts until the end
+/// of the iteration of the loop around it, by which point programs usually know where the pointer
+/// is again, like after `[>>>]<[...]`.
+///
+/// Whether an iteration happens at all isn't counted as a dependency, so a loop that processes
+/// input byte by byte doesn't make every output depend on all the input before it.
+///
+/// # Usage
+///
+/// ```
+/// # use std::collections::BTreeSet;
+/// # use brainfuck_rs::{
+/// #   engine::{Engine, RuntimeSettings},
+/// #   instruction::Instruction,
+/// #   taint::{self, Dependencies},
+/// #   token::Token,
+/// # };
+/// // prints the sum of two bytes, then the first one
+/// let instructions = Instruction::parse(Token::tokenize(",>,[-<+>]<.>,.")).unwrap();
+///
+/// let mut dependencies = Dependencies::default();
+/// taint::track(
+///     &mut Engine::default(),
+///     &instructions,
+///     &mut b"abc".as_slice(),
+///     &mut vec![],
+///     RuntimeSettings::default(),
+///     &mut dependencies,
+/// )
+/// .unwrap();
+///
+/// assert_eq!(
+///     vec![BTreeSet::from([0, 1]), BTreeSet::from([2])],
+///     dependencies.outputs
+/// );
+/// ```
+///
+/// # Errors
+///
+/// Same as [`Engine::run`]. [`RuntimeSettings::budget`] counts instructions like
+/// [`RunSummary::steps`], [`RuntimeSettings::cost`], [`RuntimeSettings::read_ahead`] and
+/// [`RuntimeSettings::break_on_output`] are ignored.
+pub fn track(
+	bf: &mut Engine,
+	instructions: &[Instruction],
+	stdin: &mut impl Read,
+	stdout: &mut impl Write,
+	settings: RuntimeSettings,
+	dependencies: &mut Dependencies,
+) -> Result<RunSummary, RunError> {
+	let mut tracker = Tracker {
+		shadow: vec![BTreeSet::new(); bf.tape.len()],
+		pointer_taint: BTreeSet::new(),
+		fresh: BTreeSet::new(),
+		bf,
+		stdin,
+		stdout,
+		settings,
+		dependencies,
+		steps: 0,
+		exit_code: None,
+	};
+	let _ = tracker.block(instructions, &BTreeSet::new())?;
+
+	Ok(RunSummary {
+		steps: tracker.steps,
+		unread_input: vec![],
+		exit_code: tracker.exit_code,
+		output_matched: false,
+		breakpoint_hit: false,
+		paused: false,
+		location: None,
+	})
+}
+
+/// Input bytes every output byte depends on, see [`track`].
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct Dependencies {
+	/// Indices of the input bytes every output byte depends on, in the order they were printed.
+	pub outputs: Vec<BTreeSet<usize>>,
+	/// Number of bytes read.
+	pub inputs: usize,
+}
+
+impl fmt::Display for Dependencies {
+	/// A matrix with a row for every output byte and a column for every input byte, marking
+	/// dependencies with `x`. The header holds the last digit of every input byte's index.
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		let width = self.outputs.len().saturating_sub(1).to_string().len();
+
+		let header: String = (0..self.inputs)
+			.map(|input| char::from(b'0' + (input % 10) as u8))
+			.collect();
+		writeln!(f, "{:width$} {header}", "")?;
+
+		for (output, inputs) in self.outputs.iter().enumerate() {
+			let row: String = (0..self.inputs)
+				.map(|input| if inputs.contains(&input) { 'x' } else { '.' })
+				.collect();
+			writeln!(f, "{output:>width$} {row}")?;
+		}
+
+		Ok(())
+	}
+}
+
+type Taint = BTreeSet<usize>;
+
+struct Tracker<'a, R, W> {
+	bf: &'a mut Engine,
+	stdin: &'a mut R,
+	stdout: &'a mut W,
+	settings: RuntimeSettings,
+	dependencies: &'a mut Dependencies,
+	/// Input bytes the value of every cell depends on.
+	shadow: Vec<Taint>,
+	/// Input bytes the position of the pointer depends on.
+	pointer_taint: Taint,
+	/// Cells read during the current iteration of the innermost loop.
+	fresh: BTreeSet<usize>,
+	steps: u64,
+	exit_code: Option<u8>,
+}
+
+impl<R: Read, W: Write> Tracker<'_, R, W> {
+	/// Run `instructions`, adding `context` to every cell they change with `+` and `-`.
+	fn block(
+		&mut self,
+		instructions: &[Instruction],
+		context: &Taint,
+	) -> Result<ControlFlow<()>, RunError> {
+		for instruction in instructions {
+			let flow = match instruction {
+				Instruction::Loop(body) => self.run_loop(instruction, body, context)?,
+				Instruction::Inc | Instruction::Dec | Instruction::Add(_) => {
+					self.charge()?;
+
+					let cell = &mut self.bf.tape[self.bf.pointer];
+					*cell += match *instruction {
+						Instruction::Inc => 1,
+						Instruction::Add(amount) => amount as u8,
+						_ => u8::MAX,
+					};
+					// NOTE: a cell read in this iteration changes by the same amount no matter how
+					// many iterations there are, e.g. in `[,+]`
+					if !self.fresh.contains(&self.bf.pointer) {
+						self.shadow[self.bf.pointer].extend(context);
+					}
+
+					ControlFlow::Continue(())
+				}
+				_ => self.execute(instruction, context)?,
+			};
+
+			if flow.is_break() {
+				return Ok(flow);
+			}
+		}
+
+		Ok(ControlFlow::Continue(()))
+	}
+
+	fn run_loop(
+		&mut self,
+		instruction: &Instruction,
+		body: &[Instruction],
+		context: &Taint,
+	) -> Result<ControlFlow<()>, RunError> {
+		let balanced = analysis::pointer_bounds(slice::from_ref(instruction)).is_some();
+
+		let mut conditions = Taint::new();
+		let mut moved = false;
+		let mut skipped = true;
+
+		loop {
+			self.charge()?;
+
+			let condition = self.current_taint();
+			conditions.extend(condition.iter().copied());
+
+			if self.bf.tape[self.bf.pointer].0 == 0 {
+				// NOTE: the cell is zero no matter what the input was, which is how `[-]` clears
+				// dependencies along with the value
+				self.shadow[self.bf.pointer].clear();
+
+				// NOTE: where a loop like `[>]` leaves the pointer depends on its conditions,
+				// even if it didn't run at all
+				if !balanced && (moved || skipped) {
+					self.pointer_taint.extend(conditions);
+				}
+
+				return Ok(ControlFlow::Continue(()));
+			}
+			skipped = false;
+
+			let context = context.union(&condition).copied().collect();
+			let pointer = self.bf.pointer;
+			let pointer_taint = self.pointer_taint.clone();
+			let fresh = mem::take(&mut self.fresh);
+
+			if self.block(body, &context)?.is_break() {
+				return Ok(ControlFlow::Break(()));
+			}
+
+			self.pointer_taint = pointer_taint;
+			self.fresh = fresh;
+			if self.bf.pointer != pointer {
+				moved = true;
+				self.pointer_taint.extend(conditions.iter().copied());
+			}
+		}
+	}
+
+	/// Run an instruction that isn't a loop or arithmetic on the engine.
+	fn execute(
+		&mut self,
+		instruction: &Instruction,
+		context: &Taint,
+	) -> Result<ControlFlow<()>, RunError> {
+		self.charge()?;
+
+		let settings = RuntimeSettings {
+			budget: None,
+			read_ahead: false,
+			break_on_output: None,
+			..self.settings.clone()
+		};
+		let mut io = Io::default();
+		let summary = self.bf.run_with(
+			slice::from_ref(instruction),
+			self.stdin,
+			self.stdout,
+			settings,
+			&mut StdHost::default(),
+			&mut io,
+		)?;
+
+		// NOTE: the tape may have grown
+		self.shadow.resize(self.bf.tape.len(), BTreeSet::new());
+		let pointer = self.bf.pointer;
+
+		match instruction {
+			Instruction::Print => {
+				let mut taint = self.current_taint();
+				taint.extend(context);
+				self.dependencies.outputs.push(taint);
+			}
+			Instruction::Read => match io.input {
+				Some(_) => {
+					self.shadow[pointer] = BTreeSet::from([self.dependencies.inputs]);
+					self.fresh.insert(pointer);
+					self.dependencies.inputs += 1;
+				}
+				None if self.settings.quit_on_eof => return Ok(ControlFlow::Break(())),
+				// NOTE: a cell the read leaves alone keeps depending on what it did
+				None if self.settings.eof == Eof::Unchanged => {}
+				None => self.shadow[pointer].clear(),
+			},
+			// NOTE: what custom instructions do is up to the embedder, there's no telling what
+			// they depend on
+			Instruction::Extension(Extension::Time | Extension::Custom(_)) => {
+				self.shadow[pointer].clear();
+			}
+			// NOTE: the cell is zero whatever it was, so it only depends on whether it got cleared
+			Instruction::Clear => self.shadow[pointer] = context.clone(),
+			_ => {}
+		}
+
+		if summary.exit_code.is_some() {
+			self.exit_code = summary.exit_code;
+
+			return Ok(ControlFlow::Break(()));
+		}
+
+		Ok(ControlFlow::Continue(()))
+	}
+
+	/// Input bytes the value of the current cell depends on, including through the position of
+	/// the pointer.
+	fn current_taint(&self) -> Taint {
+		self.shadow[self.bf.pointer]
+			.union(&self.pointer_taint)
+			.copied()
+			.collect()
+	}
+
+	/// Count a step, failing if it goes over the budget.
+	fn charge(&mut self) -> Result<(), RunError> {
+		if let Some(budget) = self.settings.budget {
+			if self.steps == budget {
+				return Err(RunError::BudgetExhausted {
+					spent: self.steps,
+					budget,
+					location: None,
+				});
+			}
+		}
+
+		self.steps += 1;
+
+		Ok(())
+	}
+}
+
+/// Hooks that catch whether a byte was read.
+#[derive(Default)]
+struct Io {
+	input: Option<u8>,
+}
+
+impl Hooks for Io {
+	fn on_input(&mut self, _step: u64, byte: u8) {
+		self.input = Some(byte);
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use crate::{token::Token, utils::StripShebang};
+
+	use super::*;
+
+	fn track(code: &str, input: &[u8]) -> Dependencies {
+		let instructions = Instruction::parse(Token::tokenize(code)).unwrap();
+
+		let mut dependencies = Dependencies::default();
+		super::track(
+			&mut Engine::default(),
+			&instructions,
+			&mut &input[..],
+			&mut vec![],
+			RuntimeSettings {
+				quit_on_eof: true,
+				..Default::default()
+			},
+			&mut dependencies,
+		)
+		.unwrap();
+
+		dependencies
+	}
+
+	#[test]
+	fn dependencies() {
+		let rot13 = track(
+			include_str!("../../examples/brainfuck-programs/rot13.b").strip_shebang(),
+			b"abc",
+		);
+		assert_eq!(
+			vec![
+				BTreeSet::from([0]),
+				BTreeSet::from([1]),
+				BTreeSet::from([2])
+			],
+			rot13.outputs
+		);
+		assert_eq!("  012\n0 x..\n1 .x.\n2 ..x\n", rot13.to_string());
+
+		// NOTE: where `[>]` stops depends on the first byte, so the second is read from a cell
+		// that depends on it
+		let scan = track(",>>,<<[>]>.", b"\x01\x02");
+		assert_eq!(vec![BTreeSet::from([0, 1])], scan.outputs);
+
+		let constant = track(",+++.", b"a");
+		assert_eq!(vec![BTreeSet::from([0])], constant.outputs);
+
+		let independent = track(",[-]+++.", b"a");
+		assert_eq!(vec![BTreeSet::new()], independent.outputs);
+	}
+}
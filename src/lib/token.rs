@@ -1,3 +1,9 @@
+use std::fmt;
+
+use serde::{Deserialize, Serialize};
+
+use crate::extension::Extension;
+
 /// Tokens that could be encountered in a Brainfuck program.
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum Token {
@@ -17,12 +23,61 @@ pub enum Token {
 	LoopStart,
 	/// `]`
 	LoopEnd,
+	/// Symbol of an enabled [`Extension`]
+	Extension(Extension),
 }
 
 impl Token {
 	/// Tokenizes an input string, returning an iterator of tokens.
 	pub fn tokenize(code: &str) -> impl Iterator<Item = Self> + '_ {
-		code.chars().filter_map(|ch| match ch {
+		code.chars().filter_map(Self::from_char)
+	}
+
+	/// Tokenizes an input string like [`Token::tokenize`], but also recognizes the symbols of the
+	/// given extensions.
+	pub fn tokenize_with_extensions<'a>(
+		code: &'a str,
+		extensions: &'a [Extension],
+	) -> impl Iterator<Item = Self> + 'a {
+		code.chars()
+			.filter_map(|ch| Self::from_char_with_extensions(ch, extensions))
+	}
+
+	/// Tokenizes an input string like [`Token::tokenize_with_extensions`], but also yields the
+	/// [`Span`] of every token.
+	pub fn tokenize_spanned<'a>(
+		code: &'a str,
+		extensions: &'a [Extension],
+	) -> impl Iterator<Item = (Self, Span)> + 'a {
+		let mut line = 1;
+		let mut column = 0;
+
+		code.char_indices().filter_map(move |(offset, ch)| {
+			if ch == '\n' {
+				line += 1;
+				column = 0;
+				return None;
+			}
+
+			column += 1;
+
+			let token = Self::from_char_with_extensions(ch, extensions)?;
+
+			Some((
+				token,
+				Span {
+					start: offset,
+					end: offset + ch.len_utf8(),
+					line,
+					column,
+				},
+			))
+		})
+	}
+
+	/// Converts a character into a token, if it's one of the eight Brainfuck commands.
+	pub fn from_char(ch: char) -> Option<Self> {
+		match ch {
 			'+' => Some(Self::Inc),
 			'-' => Some(Self::Dec),
 			'>' => Some(Self::Next),
@@ -32,6 +87,103 @@ impl Token {
 			'[' => Some(Self::LoopStart),
 			']' => Some(Self::LoopEnd),
 			_ => None,
+		}
+	}
+
+	/// The character the token is written as, the opposite of [`Token::from_char_with_extensions`].
+	pub const fn symbol(self) -> char {
+		match self {
+			Self::Inc => '+',
+			Self::Dec => '-',
+			Self::Next => '>',
+			Self::Prev => '<',
+			Self::Print => '.',
+			Self::Read => ',',
+			Self::LoopStart => '[',
+			Self::LoopEnd => ']',
+			Self::Extension(extension) => extension.symbol(),
+		}
+	}
+
+	/// Converts a character into a token like [`Token::from_char`], but also recognizes the
+	/// symbols of the given extensions.
+	pub fn from_char_with_extensions(ch: char, extensions: &[Extension]) -> Option<Self> {
+		Self::from_char(ch).or_else(|| {
+			extensions
+				.iter()
+				.copied()
+				.find(|extension| extension.symbol() == ch)
+				.map(Self::Extension)
 		})
 	}
 }
+
+/// Location of a piece of Brainfuck source code.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Default, Serialize, Deserialize)]
+pub struct Span {
+	/// Byte offset of the first character.
+	pub start: usize,
+	/// Byte offset right after the last character.
+	pub end: usize,
+	/// Line of the first character, starting from 1.
+	pub line: usize,
+	/// Column of the first character, starting from 1.
+	pub column: usize,
+}
+
+impl fmt::Display for Span {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		write!(f, "{}:{}", self.line, self.column)
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn extensions_are_opt_in() {
+		assert_eq!(0, Token::tokenize("~").count());
+		assert_eq!(
+			vec![Token::Inc, Token::Extension(Extension::Time)],
+			Token::tokenize_with_extensions("+~", &[Extension::Time]).collect::<Vec<_>>()
+		);
+	}
+
+	#[test]
+	fn spans_track_lines_and_columns() {
+		let spans: Vec<Span> = Token::tokenize_spanned("+ comment\n  [-]", &[])
+			.map(|(_, span)| span)
+			.collect();
+
+		assert_eq!(
+			vec![
+				Span {
+					start: 0,
+					end: 1,
+					line: 1,
+					column: 1
+				},
+				Span {
+					start: 12,
+					end: 13,
+					line: 2,
+					column: 3
+				},
+				Span {
+					start: 13,
+					end: 14,
+					line: 2,
+					column: 4
+				},
+				Span {
+					start: 14,
+					end: 15,
+					line: 2,
+					column: 5
+				},
+			],
+			spans
+		);
+	}
+}
@@ -0,0 +1,638 @@
+use std::{fmt, str::FromStr};
+
+use thiserror::Error;
+
+use crate::{bytecode::Op, hooks::Hooks, instruction::Instruction, view::EngineView};
+
+/// Version of the trace format.
+pub const TRACE_VERSION: u32 = 1;
+
+/// How IO payloads are stored in a trace, so traces can be shared in bug reports without leaking
+/// what went through the program.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Redaction {
+	/// Keep payloads as they are.
+	#[default]
+	None,
+	/// Keep at most the given number of leading bytes of every payload.
+	Truncate(usize),
+	/// Replace payloads with their hash, which still tells whether two payloads were equal.
+	///
+	/// The hash isn't cryptographic and short payloads can be guessed from it.
+	Hash,
+}
+
+/// Whether data went into or out of the program.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Direction {
+	/// Read by `,`.
+	Input,
+	/// Written by `.`.
+	Output,
+}
+
+impl Direction {
+	const fn name(self) -> &'static str {
+		match self {
+			Self::Input => "input",
+			Self::Output => "output",
+		}
+	}
+}
+
+/// Data of a trace event, possibly redacted.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Payload {
+	/// All the bytes.
+	Bytes(Vec<u8>),
+	/// Only the leading bytes are kept.
+	Truncated {
+		/// The kept bytes.
+		kept: Vec<u8>,
+		/// Length of the original payload.
+		length: usize,
+	},
+	/// Only the hash is kept.
+	Hashed {
+		/// Length of the original payload.
+		length: usize,
+		/// 64-bit FNV-1a hash of the original payload.
+		hash: u64,
+	},
+}
+
+impl Payload {
+	/// Store bytes according to the redaction mode.
+	pub fn redact(bytes: Vec<u8>, redaction: Redaction) -> Self {
+		match redaction {
+			Redaction::Truncate(keep) if bytes.len() > keep => Self::Truncated {
+				kept: bytes[..keep].to_vec(),
+				length: bytes.len(),
+			},
+			Redaction::Hash => Self::Hashed {
+				length: bytes.len(),
+				hash: fnv1a(&bytes),
+			},
+			_ => Self::Bytes(bytes),
+		}
+	}
+
+	/// Length of the original payload.
+	pub fn len(&self) -> usize {
+		match self {
+			Self::Bytes(bytes) => bytes.len(),
+			Self::Truncated { length, .. } | Self::Hashed { length, .. } => *length,
+		}
+	}
+
+	/// Whether the original payload was empty.
+	pub fn is_empty(&self) -> bool {
+		self.len() == 0
+	}
+
+	/// Whether some of the data was left out.
+	pub const fn is_redacted(&self) -> bool {
+		!matches!(self, Self::Bytes(_))
+	}
+}
+
+/// A contiguous run of input or output.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TraceEvent {
+	/// Number of instructions executed before the first byte of the run.
+	pub step: u64,
+	/// Whether the bytes were read or written.
+	pub direction: Direction,
+	/// The bytes.
+	pub payload: Payload,
+}
+
+/// Recorded IO of a program run.
+///
+/// Even with redacted payloads, the trace keeps the order, position and length of every run of
+/// input and output, so the structure of the execution is preserved.
+///
+/// Its text form, produced by [`Display`](`fmt::Display`) and read back by
+/// [`FromStr`], looks like this:
+///
+/// ```text
+/// brainfuck-rs trace 1
+/// input 12 bytes "Hello\n"
+/// output 40 truncated 6 "Ur"
+/// input 58 hashed 3 af63bd4c8601b7be
+/// end 200
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct Trace {
+	/// Runs of input and output, in the order they happened.
+	pub events: Vec<TraceEvent>,
+	/// Total number of executed instructions.
+	pub steps: u64,
+}
+
+impl Trace {
+	/// All the input the program read, or [`None`] if some of it was redacted.
+	pub fn input(&self) -> Option<Vec<u8>> {
+		let mut input = vec![];
+
+		for event in &self.events {
+			match (&event.direction, &event.payload) {
+				(Direction::Input, Payload::Bytes(bytes)) => input.extend(bytes),
+				(Direction::Input, _) => return None,
+				(Direction::Output, _) => {}
+			}
+		}
+
+		Some(input)
+	}
+
+	/// Render the trace as a standalone HTML page with the given title, for sharing runs in bug
+	/// reports or teaching materials.
+	///
+	/// The page shows the transcript the way a terminal would, with input highlighted and the step
+	/// of every run of input or output shown on hover, followed by a table of all the runs.
+	/// Redacted payloads show what's left of them.
+	pub fn to_html(&self, title: &str) -> String {
+		let mut transcript = String::new();
+		let mut rows = String::new();
+
+		for event in &self.events {
+			let (class, direction) = match event.direction {
+				Direction::Input => ("input", "input"),
+				Direction::Output => ("output", "output"),
+			};
+			let payload = html_payload(&event.payload);
+
+			transcript.push_str(&format!(
+				"<span class=\"{class}\" title=\"step {}\">{payload}</span>",
+				event.step
+			));
+			rows.push_str(&format!(
+				"<tr><td>{}</td><td>{direction}</td><td>{}</td><td class=\"{class}\">{payload}</td></tr>\n",
+				event.step,
+				event.payload.len()
+			));
+		}
+
+		format!(
+			r#"<!DOCTYPE html>
+<html lang="en">
+<head>
+<meta charset="utf-8">
+<title>{title}</title>
+<style>
+body {{ font-family: sans-serif; margin: 2em; }}
+pre, td {{ font-family: monospace; }}
+pre {{ background: #1e1e1e; color: #d4d4d4; padding: 1em; white-space: pre-wrap; }}
+.input {{ color: #4ec94e; font-weight: bold; }}
+.byte, .redacted {{ color: #c586c0; }}
+table {{ border-collapse: collapse; }}
+td, th {{ border: 1px solid #ccc; padding: 0.2em 0.6em; text-align: left; vertical-align: top; white-space: pre-wrap; }}
+</style>
+</head>
+<body>
+<h1>{title}</h1>
+<p>{steps} instructions executed, input is <span class="input">highlighted</span>.</p>
+<pre>{transcript}</pre>
+<table>
+<tr><th>step</th><th>direction</th><th>bytes</th><th>payload</th></tr>
+{rows}</table>
+</body>
+</html>
+"#,
+			title = html_escape(title),
+			steps = self.steps,
+		)
+	}
+}
+
+/// A payload as HTML, with bytes that aren't printable shown like `\x00`.
+fn html_payload(payload: &Payload) -> String {
+	let bytes = |bytes: &[u8]| -> String {
+		String::from_utf8_lossy(bytes)
+			.chars()
+			.map(|ch| match ch {
+				'\n' | '\t' => ch.to_string(),
+				_ if ch.is_control() => {
+					format!("<span class=\"byte\">\\x{:02x}</span>", u32::from(ch))
+				}
+				_ => html_escape(&ch.to_string()),
+			})
+			.collect()
+	};
+
+	match payload {
+		Payload::Bytes(all) => bytes(all),
+		Payload::Truncated { kept, length } => format!(
+			"{}<span class=\"redacted\">… ({length} bytes in total)</span>",
+			bytes(kept)
+		),
+		Payload::Hashed { length, hash } => {
+			format!("<span class=\"redacted\">[{length} bytes, hash {hash:016x}]</span>")
+		}
+	}
+}
+
+pub(crate) fn html_escape(text: &str) -> String {
+	text.replace('&', "&amp;")
+		.replace('<', "&lt;")
+		.replace('>', "&gt;")
+		.replace('"', "&quot;")
+}
+
+impl fmt::Display for Trace {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		writeln!(f, "brainfuck-rs trace {TRACE_VERSION}")?;
+
+		for event in &self.events {
+			write!(f, "{} {} ", event.direction.name(), event.step)?;
+
+			match &event.payload {
+				Payload::Bytes(bytes) => writeln!(f, "bytes \"{}\"", escape(bytes))?,
+				Payload::Truncated { kept, length } => {
+					writeln!(f, "truncated {length} \"{}\"", escape(kept))?;
+				}
+				Payload::Hashed { length, hash } => writeln!(f, "hashed {length} {hash:016x}")?,
+			}
+		}
+
+		writeln!(f, "end {}", self.steps)
+	}
+}
+
+impl FromStr for Trace {
+	type Err = TraceError;
+
+	fn from_str(text: &str) -> Result<Self, Self::Err> {
+		let mut lines = text
+			.lines()
+			.enumerate()
+			.map(|(index, line)| (index + 1, line));
+
+		let header = lines.next().map(|(_, line)| line).unwrap_or_default();
+		let version = header
+			.strip_prefix("brainfuck-rs trace ")
+			.ok_or_else(|| TraceError::new(1, "missing `brainfuck-rs trace` header"))?;
+		if version != TRACE_VERSION.to_string() {
+			return Err(TraceError::new(
+				1,
+				format!("unsupported trace version {version}, only version {TRACE_VERSION} is supported"),
+			));
+		}
+
+		let mut events = vec![];
+		for (line_number, line) in lines {
+			let error = |message: &str| TraceError::new(line_number, message);
+
+			let mut parts = line.splitn(3, ' ');
+			let kind = parts.next().unwrap_or_default();
+			let step: u64 = parts
+				.next()
+				.and_then(|step| step.parse().ok())
+				.ok_or_else(|| error("expected a step number"))?;
+
+			let direction = match kind {
+				"end" => {
+					return Ok(Self {
+						events,
+						steps: step,
+					})
+				}
+				"input" => Direction::Input,
+				"output" => Direction::Output,
+				_ => return Err(error("expected `input`, `output` or `end`")),
+			};
+
+			let rest = parts.next().unwrap_or_default();
+			let (form, rest) = rest.split_once(' ').unwrap_or((rest, ""));
+			let payload = match form {
+				"bytes" => Payload::Bytes(unescape(rest).ok_or_else(|| error("malformed bytes"))?),
+				"truncated" | "hashed" => {
+					let (length, rest) = rest.split_once(' ').unwrap_or((rest, ""));
+					let length: usize = length.parse().map_err(|_| error("expected a length"))?;
+
+					if form == "truncated" {
+						Payload::Truncated {
+							kept: unescape(rest).ok_or_else(|| error("malformed bytes"))?,
+							length,
+						}
+					} else {
+						Payload::Hashed {
+							length,
+							hash: u64::from_str_radix(rest, 16)
+								.map_err(|_| error("expected a hexadecimal hash"))?,
+						}
+					}
+				}
+				_ => return Err(error("expected `bytes`, `truncated` or `hashed`")),
+			};
+
+			events.push(TraceEvent {
+				step,
+				direction,
+				payload,
+			});
+		}
+
+		Err(TraceError::new(text.lines().count(), "missing `end` line"))
+	}
+}
+
+/// An error that could be created while reading a trace.
+#[derive(Debug, Clone, PartialEq, Eq, Error)]
+#[error("line {line}: {message}")]
+pub struct TraceError {
+	/// Line with the error, starting from 1.
+	pub line: usize,
+	/// What's wrong with it.
+	pub message: String,
+}
+
+impl TraceError {
+	fn new(line: usize, message: impl Into<String>) -> Self {
+		Self {
+			line,
+			message: message.into(),
+		}
+	}
+}
+
+/// [`Hooks`] that record a [`Trace`] of a run.
+///
+/// # Usage
+///
+/// ```
+/// # use std::io::{self, BufReader};
+/// # use brainfuck_rs::{
+/// #   engine::{Engine, RuntimeSettings},
+/// #   host::StdHost,
+/// #   instruction::Instruction,
+/// #   token::Token,
+/// #   trace::{Recorder, Redaction},
+/// # };
+/// let instructions = Instruction::parse(Token::tokenize(",.")).unwrap();
+///
+/// let mut recorder = Recorder::new(Redaction::Hash);
+/// Engine::default()
+///     .run_with(
+///         &instructions,
+///         &mut BufReader::new(b"x".as_slice()),
+///         &mut io::sink(),
+///         RuntimeSettings::default(),
+///         &mut StdHost::default(),
+///         &mut recorder,
+///     )
+///     .unwrap();
+///
+/// let trace = recorder.finish();
+/// assert_eq!(2, trace.events.len());
+/// assert_eq!(2, trace.steps);
+/// ```
+#[derive(Debug, Clone, Default)]
+pub struct Recorder {
+	redaction: Redaction,
+	events: Vec<TraceEvent>,
+	pending: Option<(u64, Direction, Vec<u8>)>,
+	steps: u64,
+}
+
+impl Recorder {
+	/// Creates a new recorder that redacts payloads according to `redaction`.
+	pub fn new(redaction: Redaction) -> Self {
+		Self {
+			redaction,
+			..Default::default()
+		}
+	}
+
+	/// Finish recording. Works even if the run ended with an error.
+	pub fn finish(mut self) -> Trace {
+		self.flush();
+
+		Trace {
+			events: self.events,
+			steps: self.steps,
+		}
+	}
+
+	fn record(&mut self, step: u64, direction: Direction, byte: u8) {
+		match &mut self.pending {
+			Some((_, pending_direction, bytes)) if *pending_direction == direction => {
+				bytes.push(byte)
+			}
+			_ => {
+				self.flush();
+				self.pending = Some((step, direction, vec![byte]));
+			}
+		}
+	}
+
+	fn flush(&mut self) {
+		if let Some((step, direction, bytes)) = self.pending.take() {
+			self.events.push(TraceEvent {
+				step,
+				direction,
+				payload: Payload::redact(bytes, self.redaction),
+			});
+		}
+	}
+}
+
+impl Hooks for Recorder {
+	const WATCHES_WRAPS: bool = false;
+
+	fn before_instruction(&mut self, step: u64, _instruction: &Instruction, _bf: EngineView<'_>) {
+		self.steps = step + 1;
+	}
+
+	fn before_op(&mut self, step: u64, _index: usize, _op: Op, _bf: EngineView<'_>) {
+		self.steps = step + 1;
+	}
+
+	fn on_input(&mut self, step: u64, byte: u8) {
+		self.record(step, Direction::Input, byte);
+	}
+
+	fn on_output(&mut self, step: u64, byte: u8) {
+		self.record(step, Direction::Output, byte);
+	}
+}
+
+/// FNV-1a hash of some bytes.
+pub(crate) fn fnv1a(bytes: &[u8]) -> u64 {
+	bytes.iter().fold(0xcbf2_9ce4_8422_2325, |hash, &byte| {
+		(hash ^ u64::from(byte)).wrapping_mul(0x0100_0000_01b3)
+	})
+}
+
+/// Escape bytes for the inside of a double-quoted string.
+pub(crate) fn escape(bytes: &[u8]) -> String {
+	bytes
+		.iter()
+		.map(|&byte| match byte {
+			b'\n' => "\\n".to_owned(),
+			b'\t' => "\\t".to_owned(),
+			b'\r' => "\\r".to_owned(),
+			b'"' => "\\\"".to_owned(),
+			b'\\' => "\\\\".to_owned(),
+			0x20..=0x7e => char::from(byte).to_string(),
+			_ => format!("\\x{byte:02x}"),
+		})
+		.collect()
+}
+
+/// Decode a double-quoted string written by [`escape`], or [`None`] if it's malformed.
+pub(crate) fn unescape(quoted: &str) -> Option<Vec<u8>> {
+	let inner = quoted.strip_prefix('"')?.strip_suffix('"')?;
+
+	let mut bytes = vec![];
+	let mut chars = inner.chars();
+	while let Some(ch) = chars.next() {
+		if ch != '\\' {
+			bytes.push(u8::try_from(ch).ok()?);
+			continue;
+		}
+
+		let byte = match chars.next()? {
+			'n' => b'\n',
+			't' => b'\t',
+			'r' => b'\r',
+			'"' => b'"',
+			'\\' => b'\\',
+			'x' => {
+				let hex: String = chars.by_ref().take(2).collect();
+				u8::from_str_radix(&hex, 16).ok()?
+			}
+			_ => return None,
+		};
+		bytes.push(byte);
+	}
+
+	Some(bytes)
+}
+
+#[cfg(test)]
+mod tests {
+	use crate::engine::Engine;
+
+	use super::*;
+
+	fn record(redaction: Redaction) -> Trace {
+		let mut recorder = Recorder::new(redaction);
+
+		for (step, byte) in (3..).zip(b"Hi\n") {
+			recorder.on_input(step, *byte);
+		}
+		recorder.on_output(10, 0);
+		recorder.on_output(11, b'"');
+		recorder.on_input(12, b'!');
+		recorder.before_instruction(19, &Instruction::Read, EngineView::new(&Engine::default()));
+
+		recorder.finish()
+	}
+
+	#[test]
+	fn consecutive_bytes_form_one_event() {
+		let trace = record(Redaction::None);
+
+		assert_eq!(
+			vec![
+				TraceEvent {
+					step: 3,
+					direction: Direction::Input,
+					payload: Payload::Bytes(b"Hi\n".to_vec())
+				},
+				TraceEvent {
+					step: 10,
+					direction: Direction::Output,
+					payload: Payload::Bytes(b"\0\"".to_vec())
+				},
+				TraceEvent {
+					step: 12,
+					direction: Direction::Input,
+					payload: Payload::Bytes(b"!".to_vec())
+				},
+			],
+			trace.events
+		);
+	}
+
+	#[test]
+	fn redaction_keeps_structure() {
+		let truncated = record(Redaction::Truncate(1));
+
+		assert_eq!(
+			Payload::Truncated {
+				kept: b"H".to_vec(),
+				length: 3
+			},
+			truncated.events[0].payload
+		);
+		assert_eq!(Payload::Bytes(b"!".to_vec()), truncated.events[2].payload);
+
+		let hashed = record(Redaction::Hash);
+
+		assert!(hashed
+			.events
+			.iter()
+			.all(|event| event.payload.is_redacted()));
+		assert_eq!(
+			vec![3, 2, 1],
+			hashed
+				.events
+				.iter()
+				.map(|event| event.payload.len())
+				.collect::<Vec<_>>()
+		);
+	}
+
+	#[test]
+	fn text_round_trip() {
+		for redaction in [Redaction::None, Redaction::Truncate(1), Redaction::Hash] {
+			let trace = record(redaction);
+
+			assert_eq!(trace, trace.to_string().parse().unwrap());
+		}
+
+		assert_eq!(
+			"brainfuck-rs trace 1\ninput 3 bytes \"Hi\\n\"\noutput 10 bytes \"\\x00\\\"\"\ninput 12 bytes \"!\"\nend 20\n",
+			record(Redaction::None).to_string()
+		);
+	}
+
+	#[test]
+	fn html_transcript() {
+		let html = record(Redaction::None).to_html("<a & b>");
+
+		assert!(html.starts_with("<!DOCTYPE html>"));
+		assert!(html.contains("<title>&lt;a &amp; b&gt;</title>"));
+		assert!(html.contains(
+			"<pre><span class=\"input\" title=\"step 3\">Hi\n</span>\
+			 <span class=\"output\" title=\"step 10\"><span class=\"byte\">\\x00</span>&quot;</span>"
+		));
+		assert!(
+			html.contains("<tr><td>12</td><td>input</td><td>1</td><td class=\"input\">!</td></tr>")
+		);
+
+		let hashed = record(Redaction::Hash).to_html("hashed");
+		assert!(hashed.contains("[3 bytes, hash "));
+	}
+
+	#[test]
+	fn malformed_traces() {
+		assert_eq!(
+			TraceError::new(
+				1,
+				"unsupported trace version 7, only version 1 is supported"
+			),
+			"brainfuck-rs trace 7\nend 0".parse::<Trace>().unwrap_err()
+		);
+		assert_eq!(
+			2,
+			"brainfuck-rs trace 1\ninput x bytes \"\"\nend 0"
+				.parse::<Trace>()
+				.unwrap_err()
+				.line
+		);
+	}
+}
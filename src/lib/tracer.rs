@@ -0,0 +1,345 @@
+use std::{
+	collections::HashMap,
+	fmt,
+	io::{self, Write},
+	str::FromStr,
+};
+
+use thiserror::Error;
+
+use crate::{
+	hooks::Hooks, instruction::Instruction, program::Program, token::Span, view::EngineView,
+};
+
+/// Which instructions a [`Tracer`] prints, as terms separated by spaces, all of which must hold,
+/// like `only=.,, loop-depth>=3`. Without any terms, every instruction is printed.
+///
+/// - `only=SYMBOLS` keeps instructions written with one of the symbols, `[` being loops, which
+///   are printed whenever their condition is checked.
+/// - `span=LINE:COLUMN-LINE:COLUMN` keeps instructions that start within that part of the source
+///   code, both ends included.
+/// - `loop-depth` and `step`, compared to a number with `==`, `!=`, `<=`, `>=`, `<` or `>`, keep
+///   instructions nested in that many loops, or run after that many instructions.
+///
+/// # Usage
+///
+/// ```
+/// # use brainfuck_rs::tracer::TraceFilter;
+/// let filter: TraceFilter = " only=.,,  span=12:1-20:80 loop-depth>=3".parse().unwrap();
+///
+/// assert_eq!("only=., span=12:1-20:80 loop-depth >= 3", filter.to_string());
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct TraceFilter(Vec<Term>);
+
+impl TraceFilter {
+	/// Whether the instruction written with `symbol` at `span`, nested in `depth` loops, is printed
+	/// when it runs after `step` instructions.
+	pub fn matches(&self, symbol: char, span: Option<Span>, depth: usize, step: u64) -> bool {
+		self.0.iter().all(|term| match term {
+			Term::Only(symbols) => symbols.contains(&symbol),
+			Term::Span { from, to } => {
+				span.is_some_and(|span| (*from..=*to).contains(&(span.line, span.column)))
+			}
+			Term::Compare {
+				subject,
+				operator,
+				value,
+			} => {
+				let actual = match subject {
+					Subject::LoopDepth => depth as u64,
+					Subject::Step => step,
+				};
+
+				match *operator {
+					"==" => actual == *value,
+					"!=" => actual != *value,
+					"<=" => actual <= *value,
+					">=" => actual >= *value,
+					"<" => actual < *value,
+					_ => actual > *value,
+				}
+			}
+		})
+	}
+}
+
+impl FromStr for TraceFilter {
+	type Err = TraceFilterError;
+
+	fn from_str(text: &str) -> Result<Self, Self::Err> {
+		text.split_whitespace()
+			.map(str::parse)
+			.collect::<Result<_, _>>()
+			.map(Self)
+	}
+}
+
+impl fmt::Display for TraceFilter {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		for (index, term) in self.0.iter().enumerate() {
+			if index > 0 {
+				write!(f, " ")?;
+			}
+			write!(f, "{term}")?;
+		}
+
+		Ok(())
+	}
+}
+
+/// A term of a [`TraceFilter`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Term {
+	/// Symbols of the instructions to keep, without duplicates.
+	Only(Vec<char>),
+	/// Lines and columns of the first and last place instructions may start at.
+	Span {
+		from: (usize, usize),
+		to: (usize, usize),
+	},
+	Compare {
+		subject: Subject,
+		operator: &'static str,
+		value: u64,
+	},
+}
+
+/// What a [`Term::Compare`] compares.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Subject {
+	LoopDepth,
+	Step,
+}
+
+impl Term {
+	/// Comparison operators, longest first, so `<=` isn't taken for `<`.
+	const OPERATORS: &'static [&'static str] = &["==", "!=", "<=", ">=", "<", ">"];
+}
+
+impl FromStr for Term {
+	type Err = TraceFilterError;
+
+	fn from_str(text: &str) -> Result<Self, Self::Err> {
+		if let Some(symbols) = text.strip_prefix("only=") {
+			let mut only = vec![];
+			for symbol in symbols.chars() {
+				if !only.contains(&symbol) {
+					only.push(symbol);
+				}
+			}
+
+			return Ok(Self::Only(only));
+		}
+
+		if let Some(range) = text.strip_prefix("span=") {
+			let place = |place: &str| {
+				place
+					.split_once(':')
+					.and_then(|(line, column)| Some((line.parse().ok()?, column.parse().ok()?)))
+			};
+
+			return range
+				.split_once('-')
+				.and_then(|(from, to)| Some((place(from)?, place(to)?)))
+				.map(|(from, to)| Self::Span { from, to })
+				.ok_or_else(|| {
+					TraceFilterError(format!(
+						"expected `span=LINE:COLUMN-LINE:COLUMN`, found `{text}`"
+					))
+				});
+		}
+
+		let (operator, (subject, value)) = Self::OPERATORS
+			.iter()
+			.find_map(|&operator| Some((operator, text.split_once(operator)?)))
+			.ok_or_else(|| TraceFilterError(format!("unknown filter `{text}`")))?;
+		let subject = match subject {
+			"loop-depth" => Subject::LoopDepth,
+			"step" => Subject::Step,
+			_ => {
+				return Err(TraceFilterError(format!(
+					"expected `loop-depth` or `step` to compare, found `{subject}`"
+				)))
+			}
+		};
+		let value = value
+			.parse()
+			.map_err(|_| TraceFilterError(format!("expected a number, found `{value}`")))?;
+
+		Ok(Self::Compare {
+			subject,
+			operator,
+			value,
+		})
+	}
+}
+
+impl fmt::Display for Term {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		match self {
+			Self::Only(symbols) => write!(f, "only={}", symbols.iter().collect::<String>()),
+			Self::Span { from, to } => write!(f, "span={}:{}-{}:{}", from.0, from.1, to.0, to.1),
+			Self::Compare {
+				subject,
+				operator,
+				value,
+			} => {
+				let subject = match subject {
+					Subject::LoopDepth => "loop-depth",
+					Subject::Step => "step",
+				};
+
+				write!(f, "{subject} {operator} {value}")
+			}
+		}
+	}
+}
+
+/// Hooks that print every instruction a program runs that a [`TraceFilter`] keeps, one line each,
+/// like `42 3:7 + cell 5 = 12`: the step, where the instruction is in the source code, its symbol,
+/// and the current cell before it runs.
+///
+/// Writing errors don't stop the program, but end the trace, and are returned by
+/// [`Tracer::finish`].
+///
+/// # Usage
+///
+/// ```
+/// # use brainfuck_rs::{
+/// #   engine::{Engine, RuntimeSettings},
+/// #   host::StdHost,
+/// #   program::Program,
+/// #   tracer::Tracer,
+/// # };
+/// let program = Program::parse("++[>+<-]").unwrap();
+/// let mut tracer = Tracer::new(&program, "only=+ loop-depth>=1".parse().unwrap(), vec![]);
+///
+/// Engine::default()
+///     .run_with(
+///         &program.instructions,
+///         &mut [].as_slice(),
+///         &mut vec![],
+///         RuntimeSettings::default(),
+///         &mut StdHost::default(),
+///         &mut tracer,
+///     )
+///     .unwrap();
+///
+/// assert_eq!(
+///     "4 1:5 + cell 1 = 0\n9 1:5 + cell 1 = 1\n",
+///     String::from_utf8(tracer.finish().unwrap()).unwrap()
+/// );
+/// ```
+#[derive(Debug)]
+pub struct Tracer<W> {
+	filter: TraceFilter,
+	/// Span and loop depth of every instruction, keyed by its address.
+	at: HashMap<*const Instruction, (Option<Span>, usize)>,
+	output: W,
+	error: Option<io::Error>,
+}
+
+impl<W: Write> Tracer<W> {
+	/// Trace the instructions of `program` that `filter` keeps into `output`.
+	pub fn new(program: &Program, filter: TraceFilter, output: W) -> Self {
+		fn walk(
+			program: &Program,
+			instructions: &[Instruction],
+			depth: usize,
+			at: &mut HashMap<*const Instruction, (Option<Span>, usize)>,
+		) {
+			for instruction in instructions {
+				at.insert(instruction, (program.span(at.len()), depth));
+
+				if let Instruction::Loop(body) = instruction {
+					walk(program, body, depth + 1, at);
+				}
+			}
+		}
+
+		let mut at = HashMap::new();
+		walk(program, &program.instructions, 0, &mut at);
+
+		Self {
+			filter,
+			at,
+			output,
+			error: None,
+		}
+	}
+
+	/// Flush the trace and hand back where it went.
+	///
+	/// # Errors
+	///
+	/// Fails if writing the trace failed at some point.
+	pub fn finish(mut self) -> io::Result<W> {
+		if let Some(error) = self.error {
+			return Err(error);
+		}
+		self.output.flush()?;
+
+		Ok(self.output)
+	}
+}
+
+impl<W: Write> Hooks for Tracer<W> {
+	fn before_instruction(&mut self, step: u64, instruction: &Instruction, bf: EngineView<'_>) {
+		if self.error.is_some() {
+			return;
+		}
+		let Some(&(span, depth)) = self.at.get(&std::ptr::from_ref(instruction)) else {
+			return;
+		};
+		if !self.filter.matches(instruction.symbol(), span, depth, step) {
+			return;
+		}
+
+		let place = span.map_or_else(|| "?".to_owned(), |span| span.to_string());
+		let result = writeln!(
+			self.output,
+			"{step} {place} {} {} = {}",
+			instruction.symbol(),
+			bf.labels().name(bf.pointer()),
+			bf.cell()
+		);
+		self.error = result.err();
+	}
+}
+
+/// An error returned when a [`TraceFilter`] can't be parsed.
+#[derive(Debug, Clone, PartialEq, Eq, Error)]
+#[error("{0}")]
+pub struct TraceFilterError(pub String);
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn filters_pick_instructions() {
+		let filter: TraceFilter = "span=1:2-2:1 loop-depth<2".parse().unwrap();
+		let span = |line, column| {
+			Some(Span {
+				start: 0,
+				end: 1,
+				line,
+				column,
+			})
+		};
+
+		assert!(filter.matches('+', span(1, 80), 1, 0));
+		assert!(filter.matches('[', span(2, 1), 0, 0));
+		assert!(!filter.matches('+', span(2, 2), 0, 0));
+		assert!(!filter.matches('+', span(1, 5), 2, 0));
+		assert!(!filter.matches('+', None, 0, 0));
+
+		assert!(TraceFilter::default().matches('+', None, 9, 9));
+		assert_eq!(
+			Err(TraceFilterError("unknown filter `depth`".to_owned())),
+			"depth".parse::<TraceFilter>()
+		);
+		assert!("span=1:1".parse::<TraceFilter>().is_err());
+	}
+}
@@ -0,0 +1,227 @@
+use std::{io, num::Wrapping};
+
+use thiserror::Error;
+
+use crate::{engine::Engine, labels::CellLabels, location::LoopFrame};
+
+/// Read-only access to a running [`Engine`], given to [`Hooks`](`crate::hooks::Hooks`).
+#[derive(Debug, Clone, Copy)]
+pub struct EngineView<'a> {
+	bf: &'a Engine,
+}
+
+impl<'a> EngineView<'a> {
+	pub(crate) const fn new(bf: &'a Engine) -> Self {
+		Self { bf }
+	}
+
+	/// Index of the current cell.
+	pub const fn pointer(self) -> usize {
+		self.bf.pointer
+	}
+
+	/// Value of the current cell.
+	pub fn cell(self) -> u8 {
+		self.bf.tape[self.bf.pointer].0
+	}
+
+	/// Value of the cell at `index`, or [`None`] if it's past the end of the tape.
+	pub fn get(self, index: usize) -> Option<u8> {
+		self.bf.tape.get(index).map(|cell| cell.0)
+	}
+
+	/// All the cells.
+	pub fn tape(self) -> &'a [Wrapping<u8>] {
+		&self.bf.tape
+	}
+
+	/// Names of the cells, see [`Engine::labels`].
+	pub fn labels(self) -> &'a CellLabels {
+		&self.bf.labels
+	}
+
+	/// Loops the program is running, the outermost first, like frames of a call stack. Only the
+	/// tree engine keeps track of them, while the bytecode engines leave this empty.
+	pub fn loops(self) -> &'a [LoopFrame] {
+		&self.bf.loops
+	}
+}
+
+/// Access to a running [`Engine`] for the handlers of custom instructions, see
+/// [`ExtensionSet::with_handler`](`crate::extension::ExtensionSet::with_handler`).
+///
+/// Cells and the pointer can only be changed within the tape, so the engine can carry on safely
+/// once the handler returns. Input and output go through the same channels as the program's own
+/// `,` and `.`, after whatever the program printed so far, but aren't reported to hooks.
+pub struct EngineMut<'a> {
+	bf: &'a mut Engine,
+	io: &'a mut dyn ProgramIo,
+}
+
+impl<'a> EngineMut<'a> {
+	pub(crate) fn new(bf: &'a mut Engine, io: &'a mut dyn ProgramIo) -> Self {
+		Self { bf, io }
+	}
+
+	/// Read-only access to the engine.
+	pub fn view(&self) -> EngineView<'_> {
+		EngineView::new(self.bf)
+	}
+
+	/// Index of the current cell.
+	pub fn pointer(&self) -> usize {
+		self.bf.pointer
+	}
+
+	/// Value of the current cell.
+	pub fn cell(&self) -> u8 {
+		self.view().cell()
+	}
+
+	/// Set the current cell.
+	pub fn set_cell(&mut self, value: u8) {
+		self.bf.tape[self.bf.pointer] = Wrapping(value);
+	}
+
+	/// Set the cell at `index`.
+	///
+	/// # Errors
+	///
+	/// Fails if the index is past the end of the tape, leaving the tape as it was.
+	pub fn set(&mut self, index: usize, value: u8) -> Result<(), OutOfTape> {
+		let length = self.bf.tape.len();
+		let cell = self
+			.bf
+			.tape
+			.get_mut(index)
+			.ok_or(OutOfTape { index, length })?;
+		*cell = Wrapping(value);
+		self.bf.touched = self.bf.touched.max(index + 1);
+
+		Ok(())
+	}
+
+	/// Move the pointer to the cell at `index`.
+	///
+	/// # Errors
+	///
+	/// Fails if the index is past the end of the tape, leaving the pointer where it was.
+	pub fn set_pointer(&mut self, index: usize) -> Result<(), OutOfTape> {
+		let length = self.bf.tape.len();
+		if index >= length {
+			return Err(OutOfTape { index, length });
+		}
+		self.bf.pointer = index;
+		self.bf.touched = self.bf.touched.max(index + 1);
+
+		Ok(())
+	}
+
+	/// Read a byte of the program's input, or [`None`] at its end.
+	///
+	/// # Errors
+	///
+	/// Fails if reading fails.
+	pub fn read(&mut self) -> io::Result<Option<u8>> {
+		self.io.read()
+	}
+
+	/// Print a byte to the program's output.
+	///
+	/// # Errors
+	///
+	/// Fails if writing fails.
+	pub fn write(&mut self, byte: u8) -> io::Result<()> {
+		self.io.write(byte)
+	}
+}
+
+/// Input and output of a running program, as handed to [`EngineMut`].
+pub(crate) trait ProgramIo {
+	fn read(&mut self) -> io::Result<Option<u8>>;
+
+	fn write(&mut self, byte: u8) -> io::Result<()>;
+}
+
+/// An error returned when accessing a cell that isn't on the tape.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Error)]
+#[error("cell {index} is past the end of the tape, which has {length} cells")]
+pub struct OutOfTape {
+	/// Index of the cell.
+	pub index: usize,
+	/// Length of the tape.
+	pub length: usize,
+}
+
+#[cfg(test)]
+mod tests {
+	use crate::{
+		engine::{Engine, RuntimeSettings},
+		extension::{Extension, ExtensionSet},
+		hooks::Hooks,
+		host::StdHost,
+		instruction::Instruction,
+	};
+
+	use super::*;
+
+	#[test]
+	fn bounded_access() {
+		// NOTE: `%` echoes a byte into the last cell, then tries to move past it
+		let extensions = ExtensionSet::default()
+			.with_handler('%', |bf| {
+				let last = bf.view().tape().len() - 1;
+				let input = bf.read()?.unwrap_or_default();
+
+				bf.set(last, input).unwrap();
+				bf.set_pointer(last).unwrap();
+				assert_eq!(
+					Err(OutOfTape {
+						index: last + 1,
+						length: last + 1
+					}),
+					bf.set_pointer(last + 1)
+				);
+				bf.write(bf.cell())
+			})
+			.unwrap();
+
+		#[derive(Default)]
+		struct Pointers(Vec<usize>);
+
+		impl Hooks for Pointers {
+			fn before_instruction(
+				&mut self,
+				_step: u64,
+				_instruction: &Instruction,
+				bf: EngineView<'_>,
+			) {
+				self.0.push(bf.pointer());
+			}
+		}
+
+		let mut bf = Engine::new(3);
+		let mut output = vec![];
+		let mut pointers = Pointers::default();
+		bf.run_with(
+			&[
+				Instruction::Extension(Extension::Custom('%')),
+				Instruction::Print,
+			],
+			&mut b"a".as_slice(),
+			&mut output,
+			RuntimeSettings {
+				extensions,
+				..Default::default()
+			},
+			&mut StdHost::default(),
+			&mut pointers,
+		)
+		.unwrap();
+
+		assert_eq!(b"aa".as_slice(), output);
+		assert_eq!(vec![0, 2], pointers.0);
+		assert_eq!(Some(b'a'), EngineView::new(&bf).get(2));
+		assert_eq!(None, EngineView::new(&bf).get(3));
+	}
+}
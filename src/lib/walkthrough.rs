@@ -0,0 +1,298 @@
+use std::{
+	collections::HashMap,
+	io::{Read, Write},
+};
+
+use serde_json::json;
+
+use crate::{
+	bytecode::Op,
+	engine::{Engine, RunError, RuntimeSettings},
+	hooks::Hooks,
+	host::StdHost,
+	instruction::Instruction,
+	program::Program,
+	token::Span,
+	trace::{html_escape, Direction, Payload, Recorder, Redaction, Trace},
+	view::EngineView,
+};
+
+/// The page [`Walkthrough::to_html`] fills in.
+const TEMPLATE: &str = include_str!("walkthrough.html");
+
+/// An instruction executed during a [`Walkthrough`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Step {
+	/// Its symbol, `[` for loops.
+	pub symbol: char,
+	/// Where it is in the source code, if that's known.
+	pub location: Option<Span>,
+	/// Index of the current cell right before it.
+	pub pointer: usize,
+	/// Value of the current cell right before it.
+	pub cell: u8,
+}
+
+/// A run of a program recorded instruction by instruction, for clicking through it on the page
+/// made by [`Walkthrough::to_html`].
+///
+/// Only the current cell is recorded at every step, which is enough to tell the whole tape, since
+/// instructions only change the current cell.
+///
+/// # Usage
+///
+/// ```
+/// # use brainfuck_rs::{
+/// #   engine::{Engine, RuntimeSettings},
+/// #   program::Program,
+/// #   walkthrough::Walkthrough,
+/// # };
+/// let program = Program::parse("++[>+<-]").unwrap();
+/// let walkthrough = Walkthrough::record(
+///     &mut Engine::default(),
+///     &program,
+///     &mut std::io::empty(),
+///     &mut std::io::sink(),
+///     RuntimeSettings::default(),
+///     10,
+/// )
+/// .unwrap();
+///
+/// assert_eq!(10, walkthrough.steps.len());
+/// assert!(walkthrough.truncated);
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Walkthrough {
+	/// Every executed instruction, in order.
+	pub steps: Vec<Step>,
+	/// Input and output of the run.
+	pub trace: Trace,
+	/// Index of the current cell once the run ended.
+	pub pointer: usize,
+	/// The tape once the run ended, up to the last cell that was used.
+	pub tape: Vec<u8>,
+	/// Whether the run was cut short, because it didn't end within the maximum number of steps.
+	pub truncated: bool,
+}
+
+/// Hooks that record a [`Walkthrough`].
+struct Recording<'a> {
+	program: &'a Program,
+	indices: HashMap<*const Instruction, usize>,
+	steps: Vec<Step>,
+	recorder: Recorder,
+}
+
+impl Hooks for Recording<'_> {
+	fn before_instruction(&mut self, step: u64, instruction: &Instruction, bf: EngineView<'_>) {
+		self.recorder.before_instruction(step, instruction, bf);
+		self.steps.push(Step {
+			symbol: instruction.symbol(),
+			location: self
+				.indices
+				.get(&std::ptr::from_ref(instruction))
+				.and_then(|&index| self.program.span(index)),
+			pointer: bf.pointer(),
+			cell: bf.cell(),
+		});
+	}
+
+	fn before_op(&mut self, step: u64, index: usize, op: Op, bf: EngineView<'_>) {
+		self.recorder.before_op(step, index, op, bf);
+	}
+
+	fn on_input(&mut self, step: u64, byte: u8) {
+		self.recorder.on_input(step, byte);
+	}
+
+	fn on_output(&mut self, step: u64, byte: u8) {
+		self.recorder.on_output(step, byte);
+	}
+}
+
+impl Walkthrough {
+	/// Run `program` on `bf` with the tree engine, recording at most `max_steps` instructions.
+	///
+	/// Once the program executed that many, it's stopped, and the walkthrough is
+	/// [truncated](`Walkthrough::truncated`). The budget of `settings` is replaced by that.
+	///
+	/// # Errors
+	///
+	/// Fails if the run does, other than by running out of steps.
+	pub fn record(
+		bf: &mut Engine,
+		program: &Program,
+		stdin: &mut impl Read,
+		stdout: &mut impl Write,
+		settings: RuntimeSettings,
+		max_steps: u64,
+	) -> Result<Self, RunError> {
+		let mut recording = Recording {
+			program,
+			indices: program.indices(),
+			steps: vec![],
+			recorder: Recorder::new(Redaction::None),
+		};
+
+		let result = bf.run_with(
+			&program.instructions,
+			stdin,
+			stdout,
+			RuntimeSettings {
+				budget: Some(max_steps),
+				..settings
+			},
+			&mut StdHost::default(),
+			&mut recording,
+		);
+		let truncated = match result {
+			Ok(_) => false,
+			Err(RunError::BudgetExhausted { .. }) => true,
+			Err(e) => return Err(e),
+		};
+
+		let used = bf.tape.iter().rposition(|cell| cell.0 != 0).unwrap_or(0);
+		Ok(Self {
+			steps: recording.steps,
+			trace: recording.recorder.finish(),
+			pointer: bf.pointer,
+			tape: bf.tape[..=used.max(bf.pointer)]
+				.iter()
+				.map(|cell| cell.0)
+				.collect(),
+			truncated,
+		})
+	}
+
+	/// Render the walkthrough of a run of `source` as a standalone HTML page with the given title,
+	/// for embedding in course materials.
+	///
+	/// The page steps through the run with buttons, a slider or the arrow keys, highlighting the
+	/// current instruction in the source code, and showing the tape around the pointer, the input
+	/// read and the output printed so far.
+	pub fn to_html(&self, title: &str, source: &str) -> String {
+		let mut previous: Option<Span> = None;
+		let steps: Vec<_> = self
+			.steps
+			.iter()
+			.map(|step| {
+				// NOTE: a loop spans its whole body, but only the bracket that checks the
+				// condition is highlighted, `]` if the body just ran
+				let location = match (step.symbol, step.location) {
+					('[', Some(span)) => Some(
+						if previous.is_some_and(|previous| {
+							span.start < previous.start && previous.end <= span.end
+						}) {
+							(span.end - 1, span.end)
+						} else {
+							(span.start, span.start + 1)
+						},
+					),
+					(_, span) => span.map(|span| (span.start, span.end)),
+				};
+				previous = step.location;
+
+				json!([
+					location.map(|(start, _)| start),
+					location.map(|(_, end)| end),
+					step.pointer,
+					step.cell
+				])
+			})
+			.collect();
+
+		let data = json!({
+			"source": source,
+			"steps": steps,
+			"io": self.io(),
+			"pointer": self.pointer,
+			"tape": self.tape,
+			"truncated": self.truncated,
+		});
+		// NOTE: the data lives in a script element, which would end at the first `</`
+		let data = data.to_string().replace("</", "<\\/");
+
+		TEMPLATE
+			.replace("{{title}}", &html_escape(title))
+			.replace("{{data}}", &data)
+	}
+
+	/// Every byte of input and output, with the step that read or printed it and whether it was
+	/// input.
+	///
+	/// The trace only tells the step of the first byte of every run of input or output, the others
+	/// belong to the following reads or prints.
+	fn io(&self) -> Vec<(usize, bool, u8)> {
+		let mut io = vec![];
+
+		for event in &self.trace.events {
+			let Payload::Bytes(bytes) = &event.payload else {
+				continue;
+			};
+			let (symbol, input) = match event.direction {
+				Direction::Input => (',', true),
+				Direction::Output => ('.', false),
+			};
+			let first = usize::try_from(event.step).unwrap_or(usize::MAX);
+			// NOTE: extension instructions may read or print as well, whose bytes are all shown
+			// at the start of the run
+			let mut steps = (first..self.steps.len())
+				.filter(|&index| self.steps[index].symbol == symbol)
+				.chain(std::iter::repeat(first));
+
+			for &byte in bytes {
+				io.push((steps.next().unwrap_or(first), input, byte));
+			}
+		}
+
+		io
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn records_every_step() {
+		let program = Program::parse(",[.,]").unwrap();
+		let walkthrough = Walkthrough::record(
+			&mut Engine::default(),
+			&program,
+			&mut &b"ab"[..],
+			&mut vec![],
+			RuntimeSettings::default(),
+			100,
+		)
+		.unwrap();
+
+		assert!(!walkthrough.truncated);
+		assert_eq!(
+			",[.,[.,[",
+			walkthrough
+				.steps
+				.iter()
+				.map(|step| step.symbol)
+				.collect::<String>()
+		);
+		assert_eq!(
+			Some(2),
+			walkthrough.steps[2].location.map(|span| span.start)
+		);
+		assert_eq!(
+			vec![
+				(0, true, b'a'),
+				(2, false, b'a'),
+				(3, true, b'b'),
+				(5, false, b'b')
+			],
+			walkthrough.io()
+		);
+
+		let html = walkthrough.to_html("</script>", ",[.,]");
+		assert!(html.contains("<title>&lt;/script&gt;</title>"));
+		assert!(html.contains("\"source\":\",[.,]\""));
+		// NOTE: the loop is checked at its start once, then at its end
+		assert!(html.contains("\"steps\":[[0,1,0,0],[1,2,0,97],[2,3,0,97],[3,4,0,97],[4,5,0,98]"));
+	}
+}
@@ -0,0 +1,158 @@
+//! Running a program in a child process held to OS-level resource limits, for `run --isolate`.
+//!
+//! The child is this executable again, with the same arguments, and it talks to the parent over
+//! pipes only: the parent feeds it its own input, and passes on what it prints. That way, a
+//! program that gets past every check of the engine still can't take more memory or CPU time than
+//! it was given, write files, or leave a core dump behind.
+//!
+//! Limits are set with `setrlimit`, so isolation is only supported on Unix.
+
+use std::{
+	env,
+	io::{self, Read, Write},
+	process::{Command, ExitCode, Stdio},
+	thread,
+};
+
+use color_eyre::eyre::{bail, Result, WrapErr};
+
+/// Whether isolation is supported on this platform.
+pub const SUPPORTED: bool = cfg!(unix);
+
+/// Environment variable that tells the child of an isolated run that it's the one to run the
+/// program.
+const CHILD_VARIABLE: &str = "BRAINFUCK_RS_ISOLATED";
+
+/// Number of files the child may have open at once, which is enough for its standard streams and
+/// the program file.
+const OPEN_FILES: u64 = 16;
+
+/// Resources the child of an isolated run may use.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Limits {
+	/// Bytes of address space.
+	pub memory: u64,
+	/// Seconds of CPU time, or `None` for no limit.
+	pub cpu_seconds: Option<u64>,
+}
+
+/// Whether this process is the child of an isolated run.
+pub fn is_child() -> bool {
+	env::var_os(CHILD_VARIABLE).is_some()
+}
+
+/// Run this command again in a child process held to `limits`, forwarding standard input to it
+/// and its output back, and exit like it did.
+pub fn run(limits: Limits) -> Result<ExitCode> {
+	let mut command = Command::new(env::current_exe().wrap_err("can't find this executable")?);
+	command
+		.args(env::args_os().skip(1))
+		.env(CHILD_VARIABLE, "1")
+		.stdin(Stdio::piped())
+		.stdout(Stdio::piped())
+		.stderr(Stdio::piped());
+	limit(&mut command, limits)?;
+
+	let mut child = command
+		.spawn()
+		.wrap_err("can't start the isolated process")?;
+	let mut stdin = child.stdin.take().expect("stdin is piped");
+	let mut stdout = child.stdout.take().expect("stdout is piped");
+	let mut stderr = child.stderr.take().expect("stderr is piped");
+
+	// NOTE: not joined, since reading our input may never end. Once the child exits, writing to
+	// it fails, and the thread is gone with the process
+	thread::spawn(move || forward(&mut io::stdin(), &mut stdin));
+	let errors = thread::spawn(move || forward(&mut stderr, &mut io::stderr()));
+	forward(&mut stdout, &mut io::stdout());
+	let _ = errors.join();
+
+	let status = child.wait()?;
+	if let Some(code) = status.code() {
+		// NOTE: exit codes are a byte wide on Unix
+		return Ok(ExitCode::from(u8::try_from(code).unwrap_or(u8::MAX)));
+	}
+
+	#[cfg(unix)]
+	{
+		use std::os::unix::process::ExitStatusExt;
+
+		match status.signal() {
+			Some(libc::SIGXCPU) => bail!("the isolated program ran out of CPU time"),
+			Some(libc::SIGXFSZ) => bail!("the isolated program tried to write a file"),
+			Some(signal) => bail!(
+				"the isolated program was killed by signal {signal}, possibly for running out of memory"
+			),
+			None => {}
+		}
+	}
+
+	bail!("the isolated program ended abnormally: {status}")
+}
+
+/// Copy everything from `reader` to `writer` as it arrives, until either end is closed.
+fn forward(reader: &mut impl Read, writer: &mut impl Write) {
+	let mut buffer = [0; 8 * 1024];
+
+	loop {
+		let read = match reader.read(&mut buffer) {
+			Ok(0) => return,
+			Ok(read) => read,
+			Err(e) if e.kind() == io::ErrorKind::Interrupted => continue,
+			Err(_) => return,
+		};
+		// NOTE: flushed right away, so interactive programs work like they do unisolated
+		if writer.write_all(&buffer[..read]).is_err() || writer.flush().is_err() {
+			return;
+		}
+	}
+}
+
+/// Make `command` start with `limits` in place.
+#[cfg(unix)]
+fn limit(command: &mut Command, limits: Limits) -> Result<()> {
+	use std::os::unix::process::CommandExt;
+
+	// NOTE: reaching the hard limit of CPU time kills the process outright, the soft one a second
+	// earlier sends it SIGXCPU, which tells what happened
+	let limits = [
+		(libc::RLIMIT_AS, Some((limits.memory, limits.memory))),
+		(
+			libc::RLIMIT_CPU,
+			limits
+				.cpu_seconds
+				.map(|seconds| (seconds, seconds.saturating_add(1))),
+		),
+		(libc::RLIMIT_FSIZE, Some((0, 0))),
+		(libc::RLIMIT_CORE, Some((0, 0))),
+		(libc::RLIMIT_NOFILE, Some((OPEN_FILES, OPEN_FILES))),
+	];
+
+	// SAFETY: the closure runs between `fork` and `exec`, where only async-signal-safe functions
+	// may be called, and `setrlimit` is one. It neither allocates nor takes locks
+	unsafe {
+		command.pre_exec(move || {
+			for (resource, limit) in limits {
+				let Some((soft, hard)) = limit else {
+					continue;
+				};
+				let limit = libc::rlimit {
+					rlim_cur: soft as libc::rlim_t,
+					rlim_max: hard as libc::rlim_t,
+				};
+				if libc::setrlimit(resource, &limit) != 0 {
+					return Err(io::Error::last_os_error());
+				}
+			}
+
+			Ok(())
+		});
+	}
+
+	Ok(())
+}
+
+#[cfg(not(unix))]
+fn limit(_command: &mut Command, _limits: Limits) -> Result<()> {
+	bail!("isolation is only supported on Unix")
+}
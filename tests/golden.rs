@@ -0,0 +1,176 @@
+//! Runs the example programs on every engine, comparing what they print against the golden files
+//! in `tests/golden`. Run with `BLESS=1` to write the current output as the golden one instead.
+
+use std::{env, fs};
+
+use brainfuck_rs::{
+	bytecode::{Bytecode, Fusion},
+	engine::{Engine, RuntimeSettings},
+	program::Program,
+	utils::StripShebang,
+};
+
+/// Programs from `examples/brainfuck-programs` that have golden output, and whether they need
+/// [`RuntimeSettings::quit_on_eof`] to stop. Their input is read from `tests/golden/<name>.in`,
+/// if there is one.
+const PROGRAMS: &[(&str, bool)] = &[
+	("hello-world", false),
+	("rot13", true),
+	("head", true),
+	("qsort", false),
+	("fibonacci", false),
+	("primes", false),
+	("quine", false),
+	("bf2c", false),
+];
+
+fn load(name: &str) -> String {
+	fs::read_to_string(format!("examples/brainfuck-programs/{name}.b")).unwrap()
+}
+
+/// Output of the program on every engine, along with the engine's name.
+fn outputs(source: &str, input: &[u8], quit_on_eof: bool) -> Vec<(&'static str, Vec<u8>)> {
+	let program = Program::parse(source.strip_shebang()).unwrap();
+	let settings = RuntimeSettings {
+		quit_on_eof,
+		..Default::default()
+	};
+
+	let bytecode = Bytecode::compile(&program.instructions);
+	let optimized = bytecode.collapse().count_loops().fuse(Fusion::ALL);
+
+	["tree", "bytecode", "fused", "tiered"]
+		.into_iter()
+		.map(|engine| {
+			let mut bf = Engine::default();
+			let mut input = input;
+			let mut output = vec![];
+
+			match engine {
+				"tree" => bf.run(
+					&program.instructions,
+					&mut input,
+					&mut output,
+					settings.clone(),
+				),
+				"bytecode" => bf.run_bytecode(&bytecode, &mut input, &mut output, settings.clone()),
+				"fused" => bf.run_bytecode(&optimized, &mut input, &mut output, settings.clone()),
+				_ => bf.run_bytecode(
+					&optimized,
+					&mut input,
+					&mut output,
+					RuntimeSettings {
+						specialize_after: Some(1),
+						..settings.clone()
+					},
+				),
+			}
+			.unwrap();
+
+			(engine, output)
+		})
+		.collect()
+}
+
+#[test]
+fn golden_outputs() {
+	let bless = env::var_os("BLESS").is_some();
+
+	for &(name, quit_on_eof) in PROGRAMS {
+		let input = fs::read(format!("tests/golden/{name}.in")).unwrap_or_default();
+		let golden_path = format!("tests/golden/{name}.out");
+
+		let outputs = outputs(&load(name), &input, quit_on_eof);
+		if bless {
+			fs::write(&golden_path, &outputs[0].1).unwrap();
+		}
+
+		let golden = fs::read(&golden_path).unwrap();
+		for (engine, output) in outputs {
+			assert!(
+				golden == output,
+				"{name} on the {engine} engine printed:\n{}",
+				String::from_utf8_lossy(&output)
+			);
+		}
+	}
+}
+
+#[test]
+fn fixtures_are_distinct() {
+	let programs: Vec<(&str, Program)> = PROGRAMS
+		.iter()
+		.map(|&(name, _)| (name, Program::parse(load(name).strip_shebang()).unwrap()))
+		.collect();
+
+	for (index, (name, program)) in programs.iter().enumerate() {
+		for (other_name, other) in &programs[index + 1..] {
+			assert!(
+				!program.structurally_eq(other),
+				"{name} and {other_name} are the same program, written differently"
+			);
+		}
+	}
+}
+
+#[test]
+fn superinstructions_save_dispatches() {
+	// NOTE: fused engines count a step per op they dispatch
+	let steps = |source: &str, input: &[u8], quit_on_eof: bool, fusions: &[Fusion]| {
+		let program = Program::parse(source.strip_shebang()).unwrap();
+		let bytecode = Bytecode::compile(&program.instructions)
+			.collapse()
+			.count_loops()
+			.fuse(fusions);
+
+		Engine::default()
+			.run_bytecode(
+				&bytecode,
+				&mut { input },
+				&mut vec![],
+				RuntimeSettings {
+					quit_on_eof,
+					..Default::default()
+				},
+			)
+			.unwrap()
+			.steps
+	};
+
+	// NOTE: what `Set` and `AddPrint` save on top of the other superinstructions
+	let older: Vec<Fusion> = Fusion::ALL
+		.iter()
+		.copied()
+		.filter(|fusion| !matches!(fusion, Fusion::Set | Fusion::AddPrint))
+		.collect();
+
+	let mut saved = 0;
+	for &(name, quit_on_eof) in PROGRAMS {
+		let input = fs::read(format!("tests/golden/{name}.in")).unwrap_or_default();
+		let source = load(name);
+
+		let before = steps(&source, &input, quit_on_eof, &older);
+		let after = steps(&source, &input, quit_on_eof, Fusion::ALL);
+		assert!(
+			after <= before,
+			"{name} dispatches {} more ops",
+			after - before
+		);
+		saved += before - after;
+	}
+
+	assert!(saved > 0);
+	assert!(
+		steps(&load("hello-world"), &[], false, Fusion::ALL)
+			< steps(&load("hello-world"), &[], false, &older)
+	);
+}
+
+#[test]
+fn quine_prints_itself() {
+	let source = load("quine");
+
+	for (engine, output) in outputs(&source, &[], false) {
+		assert_eq!(source.as_bytes(), output, "on the {engine} engine");
+	}
+}
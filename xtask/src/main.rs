@@ -0,0 +1,297 @@
+//! Maintenance tasks for brainfuck-rs, run with `cargo xtask <TASK>`:
+//!
+//! - `features` checks every combination of the crate's features with clippy, so minimal builds
+//!   don't break unnoticed.
+//! - `msrv` builds the crate with the toolchain of its `rust-version`, which must be installed
+//!   with rustup.
+//! - `release [TARGET...]` runs the tests, then builds release binaries for the given targets, or
+//!   the major ones, and packages each with shell completions, the man page and the example
+//!   programs into `target/dist`. Targets must be installed with rustup, along with whatever
+//!   linker they need.
+
+use std::{
+	env,
+	error::Error,
+	fs,
+	path::{Path, PathBuf},
+	process::{self, Command},
+};
+
+use serde_json::Value;
+
+/// Name of the package the tasks are about.
+const PACKAGE: &str = "brainfuck-rs";
+
+/// Targets released when none are given.
+const RELEASE_TARGETS: &[&str] = &[
+	"x86_64-unknown-linux-gnu",
+	"aarch64-unknown-linux-gnu",
+	"x86_64-pc-windows-msvc",
+	"x86_64-apple-darwin",
+	"aarch64-apple-darwin",
+];
+
+/// Shells to include completions for, as named by the `completions` subcommand.
+const SHELLS: &[&str] = &["bash", "elvish", "fish", "powershell", "zsh"];
+
+type Result<T> = std::result::Result<T, Box<dyn Error>>;
+
+fn main() {
+	let task = env::args().nth(1);
+
+	let result = match task.as_deref() {
+		Some("features") => features(),
+		Some("msrv") => msrv(),
+		Some("release") => release(&env::args().skip(2).collect::<Vec<_>>()),
+		_ => {
+			eprintln!("usage: cargo xtask <features|msrv|release [TARGET...]>");
+			process::exit(2);
+		}
+	};
+
+	if let Err(e) = result {
+		eprintln!("error: {e}");
+		process::exit(1);
+	}
+}
+
+/// Run clippy on every combination of features, without the default ones unless they're part of
+/// it.
+fn features() -> Result<()> {
+	let package = package()?;
+	let features: Vec<&str> = package["features"]
+		.as_object()
+		.ok_or("the package has no features")?
+		.keys()
+		.map(String::as_str)
+		.filter(|&feature| feature != "default")
+		.collect();
+
+	let mut failed = vec![];
+	for mask in 0..1_u32 << features.len() {
+		let combination: Vec<&str> = features
+			.iter()
+			.enumerate()
+			.filter(|&(index, _)| mask & 1 << index != 0)
+			.map(|(_, &feature)| feature)
+			.collect();
+		let combination = combination.join(",");
+
+		eprintln!("checking features [{combination}]");
+		let passed = cargo(&[
+			"clippy",
+			"--package",
+			PACKAGE,
+			"--all-targets",
+			"--no-default-features",
+			"--features",
+			&combination,
+			"--",
+			"-D",
+			"warnings",
+		])?;
+
+		if !passed {
+			failed.push(combination);
+		}
+	}
+
+	if !failed.is_empty() {
+		return Err(format!("these feature combinations fail: [{}]", failed.join("], [")).into());
+	}
+
+	Ok(())
+}
+
+/// Build and test the package with the toolchain of its `rust-version`.
+fn msrv() -> Result<()> {
+	let package = package()?;
+	let version = package["rust_version"]
+		.as_str()
+		.ok_or("the package has no `rust-version`")?;
+	let toolchain = format!("+{version}");
+
+	eprintln!("checking Rust {version}");
+	for arguments in [
+		["build", "--package", PACKAGE],
+		["test", "--package", PACKAGE],
+	] {
+		if !cargo(&[&[toolchain.as_str()], &arguments[..]].concat())? {
+			return Err(format!(
+				"the package doesn't build with Rust {version}, which may need to be installed with \
+				 `rustup toolchain install {version}`"
+			)
+			.into());
+		}
+	}
+
+	Ok(())
+}
+
+/// Test the package, then build and package release binaries for `targets`.
+fn release(targets: &[String]) -> Result<()> {
+	let targets: Vec<&str> = if targets.is_empty() {
+		RELEASE_TARGETS.to_vec()
+	} else {
+		targets.iter().map(String::as_str).collect()
+	};
+	let package = package()?;
+	let version = package["version"]
+		.as_str()
+		.ok_or("the package has no version")?;
+	let root = Path::new(
+		package["manifest_path"]
+			.as_str()
+			.ok_or("the package has no manifest")?,
+	)
+	.parent()
+	.ok_or("the manifest isn't in a directory")?
+	.to_owned();
+	let dist = root.join("target").join("dist");
+
+	// NOTE: this includes the golden tests, which run every example program
+	eprintln!("testing");
+	if !cargo(&["test", "--package", PACKAGE, "--release"])? {
+		return Err("the tests fail".into());
+	}
+
+	// NOTE: completions and the man page are the same on every target, so they're generated once
+	// by a binary that runs here
+	eprintln!("generating documentation");
+	let docs = dist.join("docs");
+	fs::create_dir_all(docs.join("completions"))?;
+	for &shell in SHELLS {
+		let script = run_binary(&["completions", shell])?;
+		fs::write(docs.join("completions").join(shell), script)?;
+	}
+	fs::write(docs.join(format!("{PACKAGE}.1")), run_binary(&["man"])?)?;
+
+	let mut failed = vec![];
+	for target in targets {
+		eprintln!("building {target}");
+		if !cargo(&[
+			"build",
+			"--package",
+			PACKAGE,
+			"--release",
+			"--target",
+			target,
+		])? {
+			failed.push(target);
+			continue;
+		}
+
+		let name = format!("{PACKAGE}-{version}-{target}");
+		let staging = dist.join(&name);
+		if staging.exists() {
+			fs::remove_dir_all(&staging)?;
+		}
+		fs::create_dir_all(&staging)?;
+
+		let binary = if target.contains("windows") {
+			format!("{PACKAGE}.exe")
+		} else {
+			PACKAGE.to_owned()
+		};
+		fs::copy(
+			root.join("target")
+				.join(target)
+				.join("release")
+				.join(&binary),
+			staging.join(&binary),
+		)?;
+		for file in ["README.md", "LICENSE"] {
+			fs::copy(root.join(file), staging.join(file))?;
+		}
+		copy_dir(&docs, &staging)?;
+		copy_dir(
+			&root.join("examples").join("brainfuck-programs"),
+			&staging.join("examples"),
+		)?;
+
+		let archive = format!("{name}.tar.gz");
+		let archived = Command::new("tar")
+			.current_dir(&dist)
+			.args(["-czf", &archive, &name])
+			.status()?
+			.success();
+		if !archived {
+			return Err(format!("couldn't archive {name}").into());
+		}
+		eprintln!("packaged {}", dist.join(archive).display());
+	}
+
+	if !failed.is_empty() {
+		return Err(format!("these targets fail to build: {}", failed.join(", ")).into());
+	}
+
+	Ok(())
+}
+
+/// Run the release binary for this machine, returning what it printed.
+fn run_binary(arguments: &[&str]) -> Result<Vec<u8>> {
+	let output = Command::new(cargo_path())
+		.args(["run", "--quiet", "--release", "--package", PACKAGE, "--"])
+		.args(arguments)
+		.output()?;
+	if !output.status.success() {
+		return Err(String::from_utf8_lossy(&output.stderr).into_owned().into());
+	}
+
+	Ok(output.stdout)
+}
+
+/// Copy the contents of the `from` directory into `to`, creating it if needed.
+fn copy_dir(from: &Path, to: &Path) -> Result<()> {
+	fs::create_dir_all(to)?;
+
+	for entry in fs::read_dir(from)? {
+		let entry = entry?;
+		let destination: PathBuf = to.join(entry.file_name());
+
+		if entry.file_type()?.is_dir() {
+			copy_dir(&entry.path(), &destination)?;
+		} else {
+			fs::copy(entry.path(), destination)?;
+		}
+	}
+
+	Ok(())
+}
+
+/// Metadata of the package, as reported by `cargo metadata`.
+fn package() -> Result<Value> {
+	let output = Command::new(cargo_path())
+		.args(["metadata", "--no-deps", "--format-version", "1"])
+		.output()?;
+	if !output.status.success() {
+		return Err(String::from_utf8_lossy(&output.stderr).into_owned().into());
+	}
+
+	let metadata: Value = serde_json::from_slice(&output.stdout)?;
+	metadata["packages"]
+		.as_array()
+		.and_then(|packages| packages.iter().find(|package| package["name"] == PACKAGE))
+		.cloned()
+		.ok_or_else(|| format!("there's no `{PACKAGE}` package").into())
+}
+
+/// Run cargo, returning whether it succeeded.
+fn cargo(arguments: &[&str]) -> Result<bool> {
+	// NOTE: a toolchain override only works through rustup's proxy, not the cargo running us
+	let program = if arguments
+		.first()
+		.is_some_and(|argument| argument.starts_with('+'))
+	{
+		"cargo".to_owned()
+	} else {
+		cargo_path()
+	};
+
+	Ok(Command::new(program).args(arguments).status()?.success())
+}
+
+/// The cargo that runs this task.
+fn cargo_path() -> String {
+	env::var("CARGO").unwrap_or_else(|_| "cargo".to_owned())
+}